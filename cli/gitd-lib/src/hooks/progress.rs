@@ -172,6 +172,26 @@ impl<'a> From<Namespaced<'a, replication::Success>> for Progress {
             }
         }
 
+        let failed = s.failed_batches();
+        if !failed.is_empty() {
+            progress.push('\n');
+            progress.push_str("failed to fetch tracked peers:\n");
+            for batch in failed {
+                for peer in &batch.peers {
+                    let _ = writeln!(progress, "{}: {}", peer, batch.source);
+                }
+            }
+        }
+
+        let quarantined = s.quarantined();
+        if !quarantined.is_empty() {
+            progress.push('\n');
+            progress.push_str("quarantined refs:\n");
+            for q in quarantined {
+                let _ = writeln!(progress, "{}", q.reason);
+            }
+        }
+
         let urns = s.urns_created().collect::<Vec<_>>();
         if !urns.is_empty() {
             progress.push('\n');
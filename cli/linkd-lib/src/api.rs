@@ -13,6 +13,8 @@ use librad::{
 };
 use link_async::Spawner;
 
+use crate::journal::Journal;
+
 pub use sockets::Sockets;
 
 pub mod announce;
@@ -24,11 +26,12 @@ mod rpc;
 pub mod sockets;
 pub mod wire_types;
 
-#[instrument(name = "api subroutine", skip(spawner, peer, sockets))]
+#[instrument(name = "api subroutine", skip(spawner, peer, sockets, journal))]
 pub async fn routine<'a, S, G>(
     spawner: Arc<Spawner>,
     peer: Peer<S, G>,
     sockets: &'a Sockets,
+    journal: Journal,
     linger_timeout: Option<Duration>,
     announce_wait_time: Duration,
 ) -> ()
@@ -36,7 +39,13 @@ where
     S: Signer + Clone,
     G: RequestPullGuard,
 {
-    let tasks = Box::pin(rpc::tasks(spawner, peer, sockets.rpc(), announce_wait_time));
+    let tasks = Box::pin(rpc::tasks(
+        spawner,
+        peer,
+        sockets.rpc(),
+        journal,
+        announce_wait_time,
+    ));
     if let Some(timeout) = linger_timeout {
         link_async::tasks::run_until_idle(tasks, timeout).await
     } else {
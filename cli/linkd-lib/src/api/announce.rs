@@ -15,6 +15,8 @@ impl Request {
             urn: self.urn,
             rev: Some(self.rev.into()),
             origin: Some(peer),
+            cob: None,
+            batch: None,
         }
     }
 }
@@ -18,6 +18,8 @@ use librad::{
 };
 use link_async::{incoming::UnixListenerExt, Spawner};
 
+use crate::journal::{self, Journal};
+
 use super::{
     announce,
     io::{self, SocketTransportError, Transport},
@@ -29,6 +31,7 @@ pub fn tasks<S, G>(
     spawner: Arc<Spawner>,
     peer: Peer<S, G>,
     socket: &UnixListener,
+    journal: Journal,
     announce_wait_time: Duration,
 ) -> impl futures::stream::Stream<Item = link_async::Task<()>> + Send + '_
 where
@@ -44,6 +47,7 @@ where
                     spawner.clone(),
                     peer.clone(),
                     stream,
+                    journal.clone(),
                     announce_wait_time,
                 )))
             },
@@ -62,6 +66,7 @@ async fn rpc<S, G>(
     spawner: Arc<Spawner>,
     peer: Peer<S, G>,
     stream: UnixStream,
+    journal: Journal,
     announce_wait_time: Duration,
 ) where
     S: Signer + Clone,
@@ -91,7 +96,7 @@ async fn rpc<S, G>(
                                         Listener::announce(next.mode, sx.clone());
                                     tracing::info!(?p, "dispatching request");
                                     listener.ack().await;
-                                    listener.handle(peer, announce_wait_time, p).boxed()
+                                    listener.handle(peer, journal.clone(), announce_wait_time, p).boxed()
                                 },
                                 messages::RequestPayload::RequestPull(p) => {
                                     let mut listener = Listener::request_pull(next.mode, sx.clone());
@@ -242,10 +247,11 @@ impl Listener<announce::Response> {
         }
     }
 
-    #[tracing::instrument(skip(self, peer))]
+    #[tracing::instrument(skip(self, peer, journal))]
     async fn handle<S, G>(
         mut self,
         peer: Peer<S, G>,
+        journal: Journal,
         announce_wait_time: Duration,
         announce: announce::Request,
     ) where
@@ -253,6 +259,22 @@ impl Listener<announce::Response> {
         G: RequestPullGuard,
     {
         tracing::info!(rev = ?announce.rev, urn = %announce.urn, "received announce request");
+        let task = journal::Task::Announce {
+            urn: announce.urn.clone(),
+            rev: announce.rev,
+        };
+        let id = match journal.schedule(task) {
+            Ok(Some(id)) => Some(id),
+            Ok(None) => {
+                tracing::debug!("announcement already pending");
+                self.success(announce::Response.into()).await;
+                return;
+            },
+            Err(e) => {
+                tracing::warn!(err = ?e, "failed to journal announcement");
+                None
+            },
+        };
         let gossip_announce = announce.into_gossip(peer.peer_id());
         if peer.connected_peers().await.is_empty() {
             tracing::debug!(wait_time=?announce_wait_time, "No connected peers, waiting a bit");
@@ -274,6 +296,11 @@ impl Listener<announce::Response> {
         } else {
             self.success(announce::Response.into()).await;
         }
+        if let Some(id) = id {
+            if let Err(e) = journal.complete(id) {
+                tracing::warn!(err = ?e, "failed to journal announcement completion");
+            }
+        }
     }
 }
 
@@ -319,7 +346,7 @@ impl Listener<request_pull::Response> {
             Ok(mut rp) => {
                 while let Some(resp) = rp.next().await {
                     match resp {
-                        Ok(Response::Progress(Progress { message })) => {
+                        Ok(Response::Progress(Progress { message, .. })) => {
                             self.progress(message).await
                         },
                         Ok(Response::Success(success)) => {
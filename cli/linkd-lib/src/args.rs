@@ -17,7 +17,10 @@ use librad::{
     profile::{LnkHome, ProfileId},
     PeerId,
 };
-use lnk_clib::{keys::ssh::SshAuthSock, seed::Seed};
+use lnk_clib::{
+    keys::{ssh::SshAuthSock, store},
+    seed::Seed,
+};
 
 use crate::tracking;
 
@@ -34,6 +37,15 @@ pub struct Args {
     #[clap(long = "bootstrap", name = "bootstrap")]
     pub bootstraps: Vec<Seed<String>>,
 
+    /// Usage: `--bootstrap-domain <domain1> --bootstrap-domain <domain2>`
+    ///
+    /// Domains publishing bootstrap nodes as `SRV` records of
+    /// `_radicle._udp.<domain>`, whose targets carry the peer id in a `TXT`
+    /// record of the form `id=<peer>`. The records are looked up again
+    /// periodically. Complements the `--bootstrap` and configured nodes.
+    #[clap(long = "bootstrap-domain", name = "bootstrap-domain")]
+    pub bootstrap_domains: Vec<String>,
+
     /// Identifier of the profile the daemon will run for. This value determines
     /// which monorepo (if existing) on disk will be the backing storage.
     #[clap(long)]
@@ -50,6 +62,12 @@ pub struct Args {
     #[clap(long, default_value_t)]
     pub ssh_auth_sock: SshAuthSock,
 
+    /// Peer id of the key to use from the ssh-agent. If given, the key is
+    /// not required to be stored in the profile, eg. because it lives on a
+    /// hardware token.
+    #[clap(long)]
+    pub ssh_key: Option<PeerId>,
+
     /// Configures the type of signer used to get access to the storage.
     #[clap(long, default_value_t)]
     pub signer: Signer,
@@ -74,6 +92,15 @@ pub struct Args {
     #[clap(flatten)]
     pub request_pull: RequestPullStorage,
 
+    #[clap(flatten)]
+    pub webhooks: WebhookArgs,
+
+    #[clap(flatten)]
+    pub mirrors: MirrorArgs,
+
+    #[clap(flatten)]
+    pub resync: ResyncArgs,
+
     /// The number of milliseconds to wait after losing all connections before
     /// shutting down the node. If not specified the node will never
     /// shutdown.
@@ -87,6 +114,10 @@ pub enum Signer {
     Key,
     /// Connect to ssh-agent for delegated signing.
     SshAgent,
+    /// Load the secret key of the profile from the key storage given by
+    /// `--key-storage`. A passphrase, if the storage requires one, is read
+    /// from stdin.
+    KeyStore,
 }
 
 impl Default for Signer {
@@ -100,6 +131,7 @@ impl fmt::Display for Signer {
         let ty = match self {
             Self::Key => "key",
             Self::SshAgent => "ssh-agent",
+            Self::KeyStore => "key-store",
         };
 
         write!(f, "{}", ty)
@@ -113,6 +145,7 @@ impl FromStr for Signer {
         match input {
             "key" => Ok(Self::Key),
             "ssh-agent" => Ok(Self::SshAgent),
+            "key-store" => Ok(Self::KeyStore),
             _ => Err(format!("unsupported signer `{}`", input)),
         }
     }
@@ -144,6 +177,10 @@ pub struct KeyArgs {
         required_if_eq("signer", "key")
     )]
     pub source: KeySource,
+    /// Where the secret key is stored when using the `key-store` signer, one
+    /// of `file`, `encrypted` or `keychain`.
+    #[clap(long = "key-storage", name = "key-storage", default_value_t)]
+    pub storage: store::Backend,
 }
 
 #[derive(Debug, Eq, PartialEq, Parser)]
@@ -237,6 +274,14 @@ pub struct MetricsArgs {
         required_if_eq("metrics-provider", "graphite")
     )]
     pub graphite_addr: String,
+
+    /// Address to serve Prometheus metrics on, via HTTP under `/metrics`.
+    #[clap(
+        long,
+        default_value = "127.0.0.1:9102",
+        required_if_eq("metrics-provider", "prometheus")
+    )]
+    pub prometheus_listen: String,
 }
 
 impl Default for MetricsArgs {
@@ -244,6 +289,7 @@ impl Default for MetricsArgs {
         Self {
             provider: None,
             graphite_addr: "localhost:2003".to_string(),
+            prometheus_listen: "127.0.0.1:9102".to_string(),
         }
     }
 }
@@ -251,6 +297,7 @@ impl Default for MetricsArgs {
 #[derive(Debug, Eq, PartialEq, Parser)]
 pub enum MetricsProvider {
     Graphite,
+    Prometheus,
 }
 
 impl FromStr for MetricsProvider {
@@ -259,6 +306,7 @@ impl FromStr for MetricsProvider {
     fn from_str(input: &str) -> Result<Self, Self::Err> {
         match input {
             "graphite" => Ok(Self::Graphite),
+            "prometheus" => Ok(Self::Prometheus),
             _ => Err(format!("unsupported key source `{}`", input)),
         }
     }
@@ -281,6 +329,26 @@ pub struct ProtocolArgs {
         parse(try_from_str = parse_protocol_network))
     ]
     pub network: Network,
+
+    /// Discover peers on the local network via multicast DNS, and announce
+    /// this peer to them.
+    #[clap(long = "protocol-mdns")]
+    pub mdns: bool,
+
+    /// Address to additionally accept connections on over TCP, for clients
+    /// whose network blocks UDP.
+    #[clap(long = "protocol-tcp-listen")]
+    pub tcp_listen: Option<SocketAddr>,
+
+    /// Address to serve namespaces to vanilla git clients on, over git's
+    /// smart HTTP protocol. Fetch only.
+    #[clap(long = "git-http-listen")]
+    pub git_http_listen: Option<SocketAddr>,
+
+    /// Do not compress gossip and membership messages sent to peers which
+    /// support it. Compressed messages are still accepted.
+    #[clap(long = "protocol-no-compression")]
+    pub no_compression: bool,
     // TODO(xla): Expose protocol args (membership, replication, etc.).
 }
 
@@ -322,9 +390,10 @@ fn parse_protocol_network(src: &str) -> Result<Network, String> {
 
 #[derive(Debug, Default, Eq, PartialEq, Parser)]
 pub struct TrackingArgs {
-    /// Instruct the node to automatically track either everything it observes
-    /// or a selected set of peer ids and urns which need to be provided
-    /// through extra arguments to take effect.
+    /// Instruct the node to automatically track either everything it observes,
+    /// a selected set of peer ids and urns which need to be provided
+    /// through extra arguments to take effect, or according to the tracking
+    /// policy file of the profile (`policy`).
     #[clap(long = "track", name = "track")]
     pub mode: Option<TrackingMode>,
 
@@ -347,10 +416,60 @@ pub struct TrackingArgs {
     pub pairs: Vec<tracking::Pair>,
 }
 
+#[derive(Debug, Default, Eq, PartialEq, Parser)]
+pub struct WebhookArgs {
+    /// Notify the webhooks declared by projects about replication and
+    /// request-pull events.
+    #[clap(long = "webhooks")]
+    pub enabled: bool,
+
+    /// Directory containing the shared secrets referenced by webhooks, in a
+    /// subdirectory per project named after the URN id, one file per secret
+    /// named after the reference. Webhooks referencing a secret which can not
+    /// be found are skipped.
+    #[clap(long = "webhook-secrets", name = "webhook-secrets")]
+    pub secrets: Option<PathBuf>,
+
+    /// Host webhooks may be delivered to. Argument can be repeated. Webhooks
+    /// to any other host, or resolving to a loopback or private address, are
+    /// skipped.
+    #[clap(long = "webhook-allow-host", name = "webhook-allow-host")]
+    pub allowed_hosts: Vec<String>,
+}
+
+#[derive(Debug, Default, Eq, PartialEq, Parser)]
+pub struct MirrorArgs {
+    /// Push replicated namespaces to the external remotes configured in the
+    /// mirrors file of the profile.
+    #[clap(long = "mirrors")]
+    pub enabled: bool,
+
+    /// Directory containing the credentials referenced by mirrors, one file
+    /// per credential named after the reference. The credential is used as
+    /// the password when pushing over HTTPS, SSH remotes authenticate via
+    /// ssh-agent.
+    #[clap(long = "mirror-secrets", name = "mirror-secrets")]
+    pub secrets: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Eq, PartialEq, Parser)]
+pub struct ResyncArgs {
+    /// Periodically compare the tracked namespaces with the tracked peers we
+    /// are connected to, and fetch those which fell behind, eg. because
+    /// announcements were missed.
+    #[clap(long = "resync")]
+    pub enabled: bool,
+
+    /// The average number of seconds between re-synchronisation rounds.
+    #[clap(long = "resync-interval", name = "resync-interval")]
+    pub interval: Option<u64>,
+}
+
 #[derive(Debug, Eq, PartialEq, Parser)]
 pub enum TrackingMode {
     Everything,
     Selected,
+    Policy,
 }
 
 impl FromStr for TrackingMode {
@@ -360,6 +479,7 @@ impl FromStr for TrackingMode {
         match input {
             "everything" => Ok(Self::Everything),
             "selected" => Ok(Self::Selected),
+            "policy" => Ok(Self::Policy),
             _ => Err(format!("unsupported tracking mode `{}`", input)),
         }
     }
@@ -392,12 +512,18 @@ pub struct RequestPullStorage {
     /// Number of [`librad::git::storage::Storage`] instances to reserve.
     #[clap(long = "request-pull-pool-size", default_value_t = num_cpus::get_physical())]
     pub pool_size: usize,
+
+    /// Reject request-pulls which update the default branch of a project
+    /// without the approvals required by its review policy.
+    #[clap(long = "request-pull-enforce-review")]
+    pub enforce_review: bool,
 }
 
 impl Default for RequestPullStorage {
     fn default() -> Self {
         Self {
             pool_size: num_cpus::get_physical(),
+            enforce_review: false,
         }
     }
 }
@@ -7,6 +7,7 @@ use std::{
     convert::TryFrom,
     io,
     net::{Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs as _},
+    sync::Arc,
     time::Duration,
 };
 
@@ -19,19 +20,30 @@ use tokio::{
 use tracing::warn;
 
 use librad::{
-    crypto::{BoxedSigner, IntoSecretKeyError},
-    git::storage,
+    crypto::{keystore::pinentry::SecUtf8, BoxedSigner, IntoSecretKeyError},
+    git::{storage, tracking::auto},
     keystore::SecretKeyExt as _,
     net,
-    net::{discovery, peer::Config as PeerConfig, protocol::membership},
+    net::{discovery, peer::Config as PeerConfig, protocol::membership, resolve},
     profile::{LnkHome, Profile},
     SecretKey,
 };
 use lnk_clib::keys;
 
-use crate::{args, request_pull, tracking::Tracker};
+use crate::{
+    args,
+    expiry,
+    mirrors,
+    request_pull,
+    resync,
+    tracking::{Provisional, Tracker},
+    webhooks,
+};
 
-use lnk_clib::seed::{self, store::FileStore, Seeds};
+use lnk_clib::seed::{
+    self,
+    store::{FileStore, Store as _},
+};
 
 lazy_static::lazy_static! {
     /// General binding to any available port, i.e. `0.0.0.0:0`.
@@ -57,10 +69,10 @@ pub enum Error {
     #[error(transparent)]
     Keys(#[from] keys::ssh::Error),
 
-    #[error("no bootstrap nodes could be resolved")]
+    #[error("no valid bootstrap nodes")]
     NoBootstrap,
 
-    #[error("no seed nodes could be resolved")]
+    #[error("no valid seed nodes")]
     NoSeeds,
 
     #[error(transparent)]
@@ -72,6 +84,9 @@ pub enum Error {
     #[error(transparent)]
     SecretKey(#[from] IntoSecretKeyError),
 
+    #[error(transparent)]
+    TrackingPolicy(#[from] auto::Error),
+
     #[error(transparent)]
     Seed(#[from] seed::error::Load),
 
@@ -90,46 +105,82 @@ impl Default for RunMode {
     }
 }
 
+/// The discovery of bootstrap nodes: those given statically, and those
+/// published under the bootstrap domains.
+pub type Bootstrap = discovery::Merge<discovery::Resolving, discovery::Dns>;
+
 pub struct Cfg<Disco, Signer, Auth> {
     pub disco: Disco,
+    /// Discovery of peers on the local network, if enabled.
+    pub mdns: Option<discovery::mdns::Config>,
     pub metrics: Option<Metrics>,
+    /// Address to serve git over HTTP on, see [`librad::git::http`].
+    pub git_http: Option<SocketAddr>,
     pub peer: PeerConfig<Signer, Auth>,
     pub tracker: Option<Tracker>,
+    /// Entries tracked provisionally by the request-pull guard, to be revised
+    /// by the tracking routine.
+    pub provisional: Provisional,
+    pub webhooks: Option<webhooks::Config>,
+    pub mirrors: Option<mirrors::Config>,
+    pub resync: Option<resync::Config>,
+    pub expiry: expiry::Config,
     pub run_mode: RunMode,
     pub profile: Profile,
 }
 
-impl Cfg<discovery::Static, BoxedSigner, request_pull::State> {
+impl Cfg<Bootstrap, BoxedSigner, request_pull::State> {
     pub async fn from_args(args: &args::Args) -> Result<Self, Error> {
         let membership = membership::Params::default();
         let profile = Profile::try_from(args)?;
 
-        let seeds = if !args.bootstraps.is_empty() {
-            let (seeds, failures) = Seeds::resolve(args.bootstraps.iter()).await;
-            for fail in failures {
-                tracing::warn!("failed to load bootstrap seed: {}", fail);
-            }
-
-            if seeds.is_empty() {
-                return Err(Error::NoBootstrap);
-            }
-
-            seeds
+        // Seeds are resolved lazily, and re-resolved periodically by the
+        // discovery, so failing to resolve them now is not fatal.
+        let (seeds, no_seeds) = if !args.bootstraps.is_empty() {
+            (args.bootstraps.clone(), Error::NoBootstrap)
         } else {
             let store = FileStore::<String>::new(profile.paths().seeds_file())?;
-            let (seeds, failures) = Seeds::load(&store, membership.max_active).await?;
-
-            for fail in &failures {
-                tracing::warn!("failed to load configured seed: {}", fail)
-            }
-
-            if seeds.is_empty() && !failures.is_empty() {
-                return Err(Error::NoSeeds);
+            let mut seeds = Vec::new();
+            for seed in store.scan()? {
+                match seed {
+                    Ok(seed) => seeds.push(seed),
+                    Err(err) => tracing::warn!("failed to load configured seed: {}", err),
+                }
+                if seeds.len() == membership.max_active {
+                    break;
+                }
             }
-
-            seeds
+            (seeds, Error::NoSeeds)
+        };
+        let hosts = seeds
+            .iter()
+            .filter_map(|seed| match seed.addrs.parse::<resolve::Host>() {
+                Ok(host) => Some((seed.peer, host)),
+                Err(err) => {
+                    tracing::warn!("invalid address for seed {}: {}", seed, err);
+                    None
+                },
+            })
+            .collect::<Vec<_>>();
+        if hosts.is_empty() && !seeds.is_empty() {
+            return Err(no_seeds);
+        }
+        // Seeds are worth keeping a connection to. Their addresses are filled
+        // in as they are resolved by the discovery.
+        let health = net::protocol::health::Config::default();
+        for (peer, _) in &hosts {
+            health.watch.watch(*peer, None);
+        }
+        let resolver = resolve::Resolver::default();
+        let dns = if args.bootstrap_domains.is_empty() {
+            discovery::dns::Config::default()
+        } else {
+            discovery::dns::Config::system()
         };
-        let disco = discovery::Static::try_from(seeds)?;
+        let disco = discovery::Merge(
+            discovery::Resolving::new(resolver.clone(), hosts),
+            discovery::Dns::new(dns, resolver, args.bootstrap_domains.clone()),
+        );
         let signer = construct_signer(args, &profile).await?;
 
         // Ensure the storage is accessible for the created profile and signer.
@@ -150,23 +201,81 @@ impl Cfg<discovery::Static, BoxedSigner, request_pull::State> {
                     .next()
                     .unwrap(),
             )),
+            Some(args::MetricsProvider::Prometheus) => Some(Metrics::Prometheus {
+                addr: args
+                    .metrics
+                    .prometheus_listen
+                    .to_socket_addrs()?
+                    .next()
+                    .unwrap(),
+                registry: Arc::new(net::metrics::Registry::new()),
+            }),
             None => None,
         };
+        let runtime = net::peer::config::Runtime {
+            metrics: match &metrics {
+                Some(Metrics::Prometheus { registry, .. }) => {
+                    Some(registry.clone() as Arc<dyn net::metrics::Metrics>)
+                },
+                _ => None,
+            },
+            ..Default::default()
+        };
 
         let run_mode = match &args.linger_timeout {
             Some(t) => RunMode::Mortal(t.into()),
             None => RunMode::Immortal,
         };
 
-        let tracker = args.tracking.mode.as_ref().map(|arg| match arg {
-            args::TrackingMode::Everything => Tracker::Everything,
-            args::TrackingMode::Selected => Tracker::selected(
+        let tracker = match &args.tracking.mode {
+            None => None,
+            Some(args::TrackingMode::Everything) => Some(Tracker::Everything),
+            Some(args::TrackingMode::Selected) => Some(Tracker::selected(
                 args.tracking.peer_ids.clone(),
                 args.tracking.urns.clone(),
                 args.tracking.pairs.clone(),
-            ),
+            )),
+            Some(args::TrackingMode::Policy) => {
+                let policy = auto::load(profile.paths())?.unwrap_or_else(|| {
+                    warn!(
+                        path = %profile.paths().tracking_policy_file().display(),
+                        "no tracking policy found, tracking nothing"
+                    );
+                    auto::Policy::default()
+                });
+                Some(Tracker::Policy(policy))
+            },
+        };
+
+        let webhooks = args.webhooks.enabled.then(|| webhooks::Config {
+            secrets: args.webhooks.secrets.clone(),
+            allowed_hosts: args
+                .webhooks
+                .allowed_hosts
+                .iter()
+                .map(|host| host.to_ascii_lowercase())
+                .collect(),
+            ..webhooks::Config::default()
         });
 
+        let mirrors = args.mirrors.enabled.then(|| mirrors::Config {
+            secrets: args.mirrors.secrets.clone(),
+            ..mirrors::Config::default()
+        });
+
+        let resync = args.resync.enabled.then(|| {
+            let default = resync::Config::default();
+            resync::Config {
+                interval: args
+                    .resync
+                    .interval
+                    .map(Duration::from_secs)
+                    .unwrap_or(default.interval),
+                ..default
+            }
+        });
+
+        let provisional = Provisional::default();
         let storage_lock = storage::pool::Initialised::no();
         let request_pull = request_pull::State::new(
             storage::Pool::new(
@@ -178,11 +287,14 @@ impl Cfg<discovery::Static, BoxedSigner, request_pull::State> {
                 args.request_pull.pool_size,
             ),
             tracker.clone(),
+            provisional.clone(),
         );
 
         Ok(Self {
             disco,
+            mdns: args.protocol.mdns.then(discovery::mdns::Config::default),
             metrics,
+            git_http: args.protocol.git_http_listen,
             peer: PeerConfig {
                 signer,
                 protocol: net::protocol::Config {
@@ -194,10 +306,29 @@ impl Cfg<discovery::Static, BoxedSigner, request_pull::State> {
                     replication: Default::default(),
                     rate_limits: Default::default(),
                     request_pull,
+                    request_pull_policy: Default::default(),
+                    read_access: Default::default(),
+                    announcements: Default::default(),
+                    review: net::protocol::request_pull::review::Config {
+                        enforce: args.request_pull.enforce_review,
+                    },
+                    tcp_listen_addr: args.protocol.tcp_listen,
+                    health,
+                    compression: net::protocol::compression::Config {
+                        enabled: !args.protocol.no_compression,
+                        ..Default::default()
+                    },
+                    router: None,
                 },
                 storage: Default::default(),
+                runtime,
             },
             tracker,
+            provisional,
+            webhooks,
+            mirrors,
+            resync,
+            expiry: expiry::Config::default(),
             profile,
             run_mode,
         })
@@ -206,6 +337,10 @@ impl Cfg<discovery::Static, BoxedSigner, request_pull::State> {
 
 pub enum Metrics {
     Graphite(SocketAddr),
+    Prometheus {
+        addr: SocketAddr,
+        registry: Arc<net::metrics::Registry>,
+    },
 }
 
 impl TryFrom<&args::Args> for Profile {
@@ -229,7 +364,18 @@ async fn construct_signer(args: &args::Args, profile: &Profile) -> anyhow::Resul
             tokio::task::spawn_blocking({
                 let profile = profile.clone();
                 let sock = args.ssh_auth_sock.clone();
-                move || keys::ssh::signer(&profile, sock).map_err(anyhow::Error::from)
+                let peer_id = args.ssh_key;
+                move || {
+                    match peer_id {
+                        Some(peer_id) => keys::ssh::agent_signer(
+                            sock,
+                            Some(peer_id),
+                            Some(keys::ssh::SIGN_TIMEOUT),
+                        ),
+                        None => keys::ssh::signer(&profile, sock),
+                    }
+                    .map_err(anyhow::Error::from)
+                }
             })
             .await?
         },
@@ -275,5 +421,33 @@ async fn construct_signer(args: &args::Args, profile: &Profile) -> anyhow::Resul
 
             Ok(BoxedSigner::from(key))
         },
+        args::Signer::KeyStore => {
+            let backend = args.key.storage;
+            let passphrase = match backend {
+                keys::store::Backend::Keychain => String::new(),
+                _ => {
+                    let mut passphrase = String::new();
+                    timeout(
+                        Duration::from_secs(5),
+                        stdin().read_to_string(&mut passphrase),
+                    )
+                    .await?
+                    .context("reading passphrase from stdin")?;
+                    passphrase.trim_end_matches(&['\r', '\n'][..]).to_owned()
+                },
+            };
+            tokio::task::spawn_blocking({
+                let profile = profile.clone();
+                move || {
+                    let store = keys::store::open(
+                        &profile,
+                        backend,
+                        keys::store::Passphrase::new(SecUtf8::from(passphrase)),
+                    )?;
+                    keys::store::signer(&*store).map_err(anyhow::Error::from)
+                }
+            })
+            .await?
+        },
     }
 }
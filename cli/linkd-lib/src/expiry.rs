@@ -0,0 +1,88 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Periodic removal of expired tracking entries.
+//!
+//! A peer may be tracked with an expiry, cf.
+//! [`librad::git::tracking::Config::expires`]. Nothing happens when the time
+//! comes, so every [`Config::interval`] the routine untracks the peers whose
+//! entry expired, and removes their remotes from the affected namespaces.
+
+use std::{
+    collections::BTreeSet,
+    time::{Duration, SystemTime},
+};
+
+use tokio::time::sleep;
+use tracing::{error, info, instrument, warn};
+
+use librad::{
+    git::{
+        storage::{gc, Storage},
+        Urn,
+    },
+    net::{peer::Peer, protocol::RequestPullGuard},
+    PeerId,
+    Signer,
+};
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Interval between checks for expired entries.
+    ///
+    /// Default: 1h
+    pub interval: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+#[instrument(name = "expiry subroutine", skip(peer, config))]
+pub async fn routine<S, G>(peer: Peer<S, G>, config: Config) -> anyhow::Result<()>
+where
+    S: Signer + Clone,
+    G: RequestPullGuard,
+{
+    info!("starting expiry routine");
+
+    loop {
+        sleep(config.interval).await;
+
+        match peer
+            .using_storage(|storage| expire(storage, SystemTime::now()))
+            .await
+        {
+            Ok(Ok(untracked)) => {
+                for (urn, peer) in untracked {
+                    info!(%urn, %peer, "untracked expired peer");
+                }
+            },
+            Ok(Err(err)) => warn!(err = %err, "failed to untrack expired peers"),
+            Err(err) => error!(err = %err, "failed to access storage"),
+        }
+    }
+}
+
+/// Untrack the peers whose tracking entry expired as of `now`, and remove
+/// their remotes.
+///
+/// Returns the untracked `(urn, peer)` pairs.
+pub fn expire(storage: &Storage, now: SystemTime) -> Result<Vec<(Urn, PeerId)>, gc::Error> {
+    let untracked = gc::untrack_expired(storage, now)?;
+    let urns = untracked
+        .iter()
+        .map(|(urn, _)| urn.clone())
+        .collect::<BTreeSet<_>>();
+    for urn in urns {
+        // The peer may still be a delegate, or tracked by `urn`'s default
+        // entry, in which case its remote is kept.
+        gc::expire_remotes(storage, &urn)?;
+    }
+
+    Ok(untracked)
+}
@@ -0,0 +1,343 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Write-ahead journal of the tasks scheduled by the node.
+//!
+//! Before a task is run, it is appended to the journal, and once it finished
+//! (successfully or not), its completion is recorded. Tasks which were
+//! scheduled but never completed -- because the process crashed or was killed
+//! -- are replayed by [`routine`] when the node starts up again.
+//!
+//! The journal is a sequence of CBOR-encoded records, which is compacted
+//! whenever it is opened. A partially written trailing record, as left behind
+//! by a crash, is discarded.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write as _},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use radicle_git_ext::Oid;
+use thiserror::Error;
+use tracing::{error, info, instrument, warn};
+
+use librad::{
+    git::Urn,
+    net::{peer::Peer, protocol::RequestPullGuard},
+    PeerId,
+    Signer,
+};
+
+use crate::api::announce;
+
+/// Compact the journal once this many records were appended since it was
+/// opened, and no tasks are pending.
+const COMPACT_AFTER: usize = 1024;
+
+#[derive(Debug, Error)]
+#[error("failed to access the task journal at `{path}`")]
+pub struct Error {
+    path: PathBuf,
+    #[source]
+    source: io::Error,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, minicbor::Encode, minicbor::Decode,
+)]
+#[cbor(transparent)]
+pub struct Id(#[n(0)] u64);
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, minicbor::Encode, minicbor::Decode)]
+pub enum Task {
+    /// Replicate `urn` from `peer`.
+    #[n(0)]
+    Replicate {
+        #[n(0)]
+        urn: Urn,
+        #[n(1)]
+        peer: PeerId,
+        #[n(2)]
+        addrs: Vec<SocketAddr>,
+    },
+    /// Announce `rev` of `urn` to the network.
+    #[n(1)]
+    Announce {
+        #[n(0)]
+        urn: Urn,
+        #[n(1)]
+        rev: Oid,
+    },
+}
+
+impl Task {
+    /// Whether `self` and `other` have the same effect when run.
+    ///
+    /// Address hints are not considered when replicating, as the peer is
+    /// looked up anyways.
+    fn is_duplicate(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Self::Replicate { urn, peer, .. },
+                Self::Replicate {
+                    urn: urn2,
+                    peer: peer2,
+                    ..
+                },
+            ) => urn == urn2 && peer == peer2,
+            (a @ Self::Announce { .. }, b @ Self::Announce { .. }) => a == b,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, minicbor::Encode, minicbor::Decode)]
+enum Record {
+    #[n(0)]
+    Scheduled {
+        #[n(0)]
+        id: Id,
+        #[n(1)]
+        task: Task,
+    },
+    #[n(1)]
+    Completed {
+        #[n(0)]
+        id: Id,
+    },
+}
+
+/// Handle to the journal, which may be shared between tasks.
+#[derive(Clone)]
+pub struct Journal {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    path: PathBuf,
+    file: File,
+    next: u64,
+    appended: usize,
+    pending: BTreeMap<Id, Task>,
+}
+
+impl Journal {
+    /// Open the journal at `path`, creating it if it doesn't exist.
+    ///
+    /// The tasks left pending by the previous process are retained, except for
+    /// duplicates, and can be obtained via [`Journal::pending`].
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let io_err = |source| Error {
+            path: path.clone(),
+            source,
+        };
+
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(io_err(e)),
+        };
+        let (next, pending) = replay(&bytes);
+        let file = compact(&path, &pending).map_err(io_err)?;
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(Inner {
+                path,
+                file,
+                next,
+                appended: 0,
+                pending,
+            })),
+        })
+    }
+
+    /// The tasks which were scheduled, but not completed yet.
+    pub fn pending(&self) -> Vec<(Id, Task)> {
+        self.lock()
+            .pending
+            .iter()
+            .map(|(id, task)| (*id, task.clone()))
+            .collect()
+    }
+
+    /// Record that `task` is about to be run.
+    ///
+    /// Returns `None` if an equivalent task is already pending, in which case
+    /// `task` should not be run.
+    pub fn schedule(&self, task: Task) -> Result<Option<Id>, Error> {
+        let mut inner = self.lock();
+        if inner
+            .pending
+            .values()
+            .any(|other| other.is_duplicate(&task))
+        {
+            return Ok(None);
+        }
+
+        let id = Id(inner.next);
+        inner.append(&Record::Scheduled {
+            id,
+            task: task.clone(),
+        })?;
+        inner.next += 1;
+        inner.pending.insert(id, task);
+
+        Ok(Some(id))
+    }
+
+    /// Record that the task `id` has finished.
+    pub fn complete(&self, id: Id) -> Result<(), Error> {
+        let mut inner = self.lock();
+        if inner.pending.remove(&id).is_none() {
+            return Ok(());
+        }
+        inner.append(&Record::Completed { id })?;
+        if inner.pending.is_empty() && inner.appended >= COMPACT_AFTER {
+            inner.file = compact(&inner.path, &inner.pending).map_err(|source| Error {
+                path: inner.path.clone(),
+                source,
+            })?;
+            inner.appended = 0;
+        }
+
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Inner {
+    fn append(&mut self, record: &Record) -> Result<(), Error> {
+        let buf = encode(record);
+        self.file
+            .write_all(&buf)
+            .and_then(|()| self.file.sync_data())
+            .map_err(|source| Error {
+                path: self.path.clone(),
+                source,
+            })?;
+        self.appended += 1;
+
+        Ok(())
+    }
+}
+
+/// Fold the records in `bytes` into the next free id and the pending tasks.
+fn replay(bytes: &[u8]) -> (u64, BTreeMap<Id, Task>) {
+    let mut next = 0;
+    let mut pending = BTreeMap::<Id, Task>::new();
+    let mut decoder = minicbor::Decoder::new(bytes);
+    while decoder.position() < bytes.len() {
+        match decoder.decode::<Record>() {
+            Ok(Record::Scheduled { id, task }) => {
+                next = next.max(id.0 + 1);
+                if pending.values().any(|other| other.is_duplicate(&task)) {
+                    info!(%id, ?task, "dropping duplicate task");
+                } else {
+                    pending.insert(id, task);
+                }
+            },
+            Ok(Record::Completed { id }) => {
+                pending.remove(&id);
+            },
+            Err(e) => {
+                warn!(err = %e, "discarding malformed tail of the task journal");
+                break;
+            },
+        }
+    }
+
+    (next, pending)
+}
+
+/// Atomically replace the journal at `path` with one containing only
+/// `pending`, and open it for appending.
+fn compact(path: &Path, pending: &BTreeMap<Id, Task>) -> io::Result<File> {
+    let tmp = path.with_extension("journal.tmp");
+    {
+        let mut file = File::create(&tmp)?;
+        for (id, task) in pending {
+            let record = Record::Scheduled {
+                id: *id,
+                task: task.clone(),
+            };
+            file.write_all(&encode(&record))?;
+        }
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)?;
+
+    OpenOptions::new().append(true).open(path)
+}
+
+fn encode(record: &Record) -> Vec<u8> {
+    let mut buf = Vec::new();
+    minicbor::encode(record, &mut buf).expect("encoding to a `Vec` is infallible");
+    buf
+}
+
+/// Replay the tasks left pending by a previous run of the node, after waiting
+/// for `wait_time` to allow for connections to be established.
+#[instrument(name = "journal subroutine", skip(peer, journal))]
+pub async fn routine<S, G>(peer: Peer<S, G>, journal: Journal, wait_time: Duration)
+where
+    S: Signer + Clone,
+    G: RequestPullGuard,
+{
+    let pending = journal.pending();
+    if pending.is_empty() {
+        return;
+    }
+    link_async::sleep(wait_time).await;
+    info!(tasks = pending.len(), "replaying pending tasks");
+
+    for (id, task) in pending {
+        let res = match &task {
+            Task::Replicate {
+                urn,
+                peer: from,
+                addrs,
+            } => match peer.client() {
+                // The task may well have completed before the restart, in
+                // which case there is nothing to fetch.
+                Ok(client) => client
+                    .replicate_diverged((*from, addrs.clone()), urn.clone(), None)
+                    .await
+                    .map(|_| ())
+                    .map_err(anyhow::Error::from),
+                Err(e) => Err(e.into()),
+            },
+            Task::Announce { urn, rev } => {
+                let request = announce::Request {
+                    urn: urn.clone(),
+                    rev: *rev,
+                };
+                peer.announce(request.into_gossip(peer.peer_id()))
+                    .map_err(|_| anyhow::anyhow!("announcement subroutine is gone"))
+            },
+        };
+        if let Err(err) = res {
+            error!(?err, %id, ?task, "replayed task failed");
+        }
+        if let Err(err) = journal.complete(id) {
+            error!(?err, "failed to record task completion");
+        }
+    }
+}
@@ -8,10 +8,15 @@ pub mod args;
 mod cfg;
 
 pub mod api;
+pub mod expiry;
+pub mod journal;
 mod logging;
 mod metrics;
+pub mod mirrors;
 pub mod node;
 mod protocol;
 pub mod request_pull;
+pub mod resync;
 mod signals;
 pub mod tracking;
+pub mod webhooks;
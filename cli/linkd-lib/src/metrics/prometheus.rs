@@ -0,0 +1,63 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{debug, info, instrument, warn};
+
+use librad::net::metrics::Registry;
+
+/// Serve the metrics recorded in `registry` on `listen_addr`.
+///
+/// This is a minimal HTTP/1.0 responder: it answers `GET /metrics` with the
+/// [`Registry::render`]ed metrics, and any other request with a 404.
+#[instrument(name = "prometheus subroutine", skip(registry))]
+pub async fn routine(registry: Arc<Registry>, listen_addr: SocketAddr) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(listen_addr).await?;
+    info!("serving prometheus metrics on {}", listener.local_addr()?);
+
+    loop {
+        let (stream, remote) = listener.accept().await?;
+        let registry = registry.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(&registry, stream).await {
+                warn!(err = %e, %remote, "error serving metrics");
+            }
+        });
+    }
+}
+
+async fn respond(registry: &Registry, mut stream: TcpStream) -> anyhow::Result<()> {
+    let mut buf = vec![0; 1024];
+    let n = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..n]);
+    let path = request.lines().next().and_then(|line| {
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["GET", path, ..] => Some(path),
+            _ => None,
+        }
+    });
+    debug!(?path, "metrics request");
+
+    let response = match path {
+        Some("/metrics") => {
+            let body = registry.render();
+            format!(
+                "HTTP/1.0 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        },
+        _ => "HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_owned(),
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
@@ -0,0 +1,217 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Pushes replicated namespaces to the external remotes they are mirrored to,
+//! cf. [`librad::git::mirror`].
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use futures::{pin_mut, select, FutureExt as _, StreamExt as _};
+use tokio::time::sleep;
+use tracing::{debug, error, info, instrument, warn};
+
+use librad::{
+    git::{
+        mirror::{self, Mirror},
+        Urn,
+    },
+    net::{
+        peer::{
+            event::upstream::{Gossip, RequestPull},
+            Peer,
+            ProtocolEvent,
+        },
+        protocol::{
+            broadcast::PutResult::Applied,
+            gossip::{Payload, Rev},
+            RequestPullGuard,
+        },
+    },
+    std_ext::time::unix_now,
+    Signer,
+};
+
+use crate::webhooks::secret;
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Directory containing the credentials referenced by mirrors, one file
+    /// per credential.
+    pub secrets: Option<PathBuf>,
+    /// Interval at which updated namespaces are pushed, and failed pushes
+    /// retried if due.
+    ///
+    /// Default: 10s
+    pub interval: Duration,
+    pub backoff: mirror::Backoff,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            secrets: None,
+            interval: Duration::from_secs(10),
+            backoff: mirror::Backoff::default(),
+        }
+    }
+}
+
+#[instrument(name = "mirrors subroutine", skip(peer, config))]
+pub async fn routine<S, G>(peer: Peer<S, G>, config: Config) -> anyhow::Result<()>
+where
+    S: Signer + Clone,
+    G: RequestPullGuard,
+{
+    info!("starting mirrors routine");
+
+    let paths = peer.protocol_config().paths.clone();
+    let mut state = mirror::State::load(&paths)?;
+    // Updates may have been missed while the node was not running, so all
+    // mirrors are synced initially.
+    let mut pending = mirror::load(&paths)?
+        .into_iter()
+        .map(|m| m.urn.id)
+        .collect::<BTreeSet<_>>();
+
+    let events = peer.subscribe().fuse();
+    pin_mut!(events);
+
+    loop {
+        let tick = sleep(config.interval).fuse();
+        pin_mut!(tick);
+        loop {
+            select! {
+                res = events.next() => match res {
+                    Some(Ok(event)) => {
+                        if let Some(urn) = updated(event) {
+                            pending.insert(urn.id);
+                        }
+                    },
+                    Some(Err(err)) => error!(?err, "event error"),
+                    None => return Ok(()),
+                },
+                _ = tick => break,
+            }
+        }
+
+        let mirrors = match mirror::load(&paths) {
+            Ok(mirrors) => mirrors,
+            Err(err) => {
+                warn!(err = %err, "failed to load mirrors");
+                continue;
+            },
+        };
+        let now = unix_now();
+        let due = mirrors
+            .iter()
+            .filter(
+                |m| match state.get(m).and_then(|s| s.retry_at(&config.backoff)) {
+                    // Backing off, the update is pushed once the retry is due
+                    Some(at) => at <= now,
+                    None => pending.contains(&m.urn.id),
+                },
+            )
+            .cloned()
+            .collect::<Vec<_>>();
+        pending.clear();
+        if due.is_empty() {
+            continue;
+        }
+
+        let synced = peer
+            .using_read_only({
+                let mut state = state.clone();
+                let secrets = config.secrets.clone();
+                move |storage| {
+                    let creds = |mirror: &Mirror,
+                                 url: &str,
+                                 username: Option<&str>,
+                                 allowed: git2::CredentialType| {
+                        credentials(secrets.as_deref(), mirror, url, username, allowed)
+                    };
+                    for mirror in &due {
+                        match mirror::sync(storage, &mut state, mirror, &creds) {
+                            Ok(synced) => debug!(
+                                urn = %mirror.urn,
+                                mirror = %mirror.name,
+                                updated = synced.updated.len(),
+                                deleted = synced.deleted.len(),
+                                "synced mirror"
+                            ),
+                            Err(err) => warn!(
+                                err = %err,
+                                urn = %mirror.urn,
+                                mirror = %mirror.name,
+                                "failed to sync mirror"
+                            ),
+                        }
+                    }
+                    state.prune(&mirrors);
+                    if let Err(err) = state.save() {
+                        warn!(err = %err, "failed to save mirrors state");
+                    }
+                    state
+                }
+            })
+            .await;
+        match synced {
+            Ok(synced) => state = synced,
+            Err(err) => error!(err = %err, "failed to access storage"),
+        }
+    }
+}
+
+/// The namespace updated by `event`, if any.
+fn updated(event: ProtocolEvent) -> Option<Urn> {
+    match event {
+        ProtocolEvent::Gossip(gossip) => match *gossip {
+            Gossip::Put {
+                payload:
+                    Payload {
+                        urn,
+                        rev: Some(Rev::Git(_)),
+                        ..
+                    },
+                result: Applied(_),
+                ..
+            } => Some(urn),
+            _ => None,
+        },
+        ProtocolEvent::RequestPull(RequestPull { urn, .. }) => Some(urn),
+        _ => None,
+    }
+}
+
+/// Credentials for pushing to `mirror`.
+///
+/// SSH remotes authenticate via `ssh-agent`. Otherwise, the secret named by
+/// [`Mirror::credential`] is used as the password, eg. an access token.
+fn credentials(
+    secrets: Option<&Path>,
+    mirror: &Mirror,
+    _url: &str,
+    username: Option<&str>,
+    allowed: git2::CredentialType,
+) -> Result<git2::Cred, git2::Error> {
+    let username = username.unwrap_or("git");
+    if allowed.contains(git2::CredentialType::SSH_KEY) {
+        return git2::Cred::ssh_key_from_agent(username);
+    }
+    if allowed.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
+        if let (Some(dir), Some(name)) = (secrets, &mirror.credential) {
+            let secret = secret(dir, name).map_err(|e| git2::Error::from_str(&e.to_string()))?;
+            let password = String::from_utf8(secret)
+                .map_err(|_| git2::Error::from_str("credential is not valid UTF-8"))?;
+            return git2::Cred::userpass_plaintext(username, &password);
+        }
+    }
+
+    Err(git2::Error::from_str(&format!(
+        "no credentials for mirror `{}`",
+        mirror.name
+    )))
+}
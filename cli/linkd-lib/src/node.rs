@@ -10,21 +10,23 @@ use futures::{future::FutureExt as _, stream::FuturesUnordered, StreamExt};
 use tokio::sync::mpsc;
 use tracing::info;
 
-use librad::{
-    crypto::BoxedSigner,
-    net::{discovery, peer::Peer},
-};
+use librad::{crypto::BoxedSigner, net::peer::Peer};
 
 use crate::{
     api,
     args::Args,
-    cfg::{self, Cfg, RunMode},
+    cfg::{self, Bootstrap, Cfg, RunMode},
+    expiry,
+    journal::{self, Journal},
     logging,
-    metrics::graphite,
+    metrics::{graphite, prometheus},
+    mirrors,
     protocol,
     request_pull,
+    resync,
     signals,
     tracking,
+    webhooks,
 };
 
 /// The amount of time to wait for connections before making any announcements
@@ -36,30 +38,96 @@ pub async fn run() -> anyhow::Result<()> {
     let spawner = Arc::new(link_async::Spawner::from_current().unwrap());
 
     let args = Args::parse();
-    let cfg: Cfg<discovery::Static, BoxedSigner, request_pull::State> = cfg(&args).await?;
+    let cfg: Cfg<Bootstrap, BoxedSigner, request_pull::State> = cfg(&args).await?;
 
     let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
     let mut signals_task = spawner.spawn(signals::routine(shutdown_tx)).fuse();
 
+    let journal = Journal::open(cfg.profile.paths().tasks_journal_file())?;
+
     let mut coalesced = FuturesUnordered::new();
+    let read_access = cfg.peer.protocol.read_access.clone();
     let peer = Peer::new(cfg.peer)?;
     let peer_task = spawner
-        .spawn(protocol::routine(peer.clone(), cfg.disco, shutdown_rx))
+        .spawn(protocol::routine(
+            peer.clone(),
+            cfg.disco,
+            cfg.mdns,
+            shutdown_rx,
+        ))
         .fuse();
     coalesced.push(peer_task);
 
-    if let Some(cfg::Metrics::Graphite(addr)) = cfg.metrics {
-        let graphite_task = spawner.spawn(graphite::routine(peer.clone(), addr)).fuse();
-        coalesced.push(graphite_task);
+    match cfg.metrics {
+        Some(cfg::Metrics::Graphite(addr)) => {
+            let graphite_task = spawner.spawn(graphite::routine(peer.clone(), addr)).fuse();
+            coalesced.push(graphite_task);
+        },
+        Some(cfg::Metrics::Prometheus { addr, registry }) => {
+            let prometheus_task = spawner.spawn(prometheus::routine(registry, addr)).fuse();
+            coalesced.push(prometheus_task);
+        },
+        None => {},
     }
 
+    if let Some(addr) = cfg.git_http {
+        let paths = cfg.profile.paths().clone();
+        let config = librad::git::http::Config {
+            read_access,
+            ..Default::default()
+        };
+        let git_http_task = spawner
+            .spawn(async move {
+                let listener = tokio::net::TcpListener::bind(addr).await?;
+                librad::git::http::serve(listener, paths, config).await?;
+                Ok::<_, anyhow::Error>(())
+            })
+            .fuse();
+        coalesced.push(git_http_task);
+    }
+
+    spawner
+        .spawn(journal::routine(
+            peer.clone(),
+            journal.clone(),
+            ANNOUNCE_WAIT_TIME,
+        ))
+        .detach();
+
     if let Some(tracker) = cfg.tracker {
         let tracking_task = spawner
-            .spawn(tracking::routine(peer.clone(), tracker))
+            .spawn(tracking::routine(
+                peer.clone(),
+                tracker,
+                cfg.provisional,
+                journal.clone(),
+            ))
             .fuse();
         coalesced.push(tracking_task);
     }
 
+    if let Some(config) = cfg.webhooks {
+        let webhooks_task = spawner
+            .spawn(webhooks::routine(peer.clone(), config))
+            .fuse();
+        coalesced.push(webhooks_task);
+    }
+
+    if let Some(config) = cfg.mirrors {
+        let mirrors_task = spawner.spawn(mirrors::routine(peer.clone(), config)).fuse();
+        coalesced.push(mirrors_task);
+    }
+
+    if let Some(config) = cfg.resync {
+        let resync_task = spawner.spawn(resync::routine(peer.clone(), config)).fuse();
+        coalesced.push(resync_task);
+    }
+
+    let expiry_task = spawner
+        .spawn(expiry::routine(peer.clone(), cfg.expiry))
+        .fuse();
+    coalesced.push(expiry_task);
+
     let timeout = match cfg.run_mode {
         RunMode::Mortal(t) => Some(t),
         RunMode::Immortal => None,
@@ -69,6 +137,7 @@ pub async fn run() -> anyhow::Result<()> {
         spawner.clone(),
         peer.clone(),
         &sockets,
+        journal,
         timeout,
         ANNOUNCE_WAIT_TIME,
     )
@@ -100,15 +169,11 @@ pub async fn run() -> anyhow::Result<()> {
 }
 
 #[cfg(unix)]
-async fn cfg(
-    args: &Args,
-) -> anyhow::Result<Cfg<discovery::Static, BoxedSigner, request_pull::State>> {
+async fn cfg(args: &Args) -> anyhow::Result<Cfg<Bootstrap, BoxedSigner, request_pull::State>> {
     Ok(Cfg::from_args(args).await?)
 }
 
 #[cfg(windows)]
-async fn cfg(
-    args: &Args,
-) -> anyhow::Result<Cfg<discovery::Static, BoxedSigner, request_pull::State>> {
+async fn cfg(args: &Args) -> anyhow::Result<Cfg<Bootstrap, BoxedSigner, request_pull::State>> {
     unimplemented!("Windows is not supported, contributions are welcome :)")
 }
@@ -5,19 +5,25 @@
 
 use std::{net::SocketAddr, panic, time::Duration};
 
-use futures::{future::FutureExt as _, pin_mut, select};
+use futures::{future::FutureExt as _, pin_mut, select, stream::StreamExt as _};
 use tokio::{sync::mpsc, time::sleep};
 use tracing::{error, info, instrument};
 
 use librad::{
-    net::{self, discovery::Discovery, peer::Peer, protocol::RequestPullGuard},
+    net::{
+        self,
+        discovery::{self, Discovery},
+        peer::Peer,
+        protocol::RequestPullGuard,
+    },
     Signer,
 };
 
-#[instrument(name = "protocol subroutine", skip(disco, peer, shutdown_rx))]
+#[instrument(name = "protocol subroutine", skip(disco, mdns, peer, shutdown_rx))]
 pub async fn routine<D, S, G>(
     peer: Peer<S, G>,
     disco: D,
+    mdns: Option<discovery::mdns::Config>,
     mut shutdown_rx: mpsc::Receiver<()>,
 ) -> anyhow::Result<()>
 where
@@ -31,7 +37,17 @@ where
     loop {
         match peer.bind().await {
             Ok(bound) => {
-                let (stop, run) = bound.accept(disco.clone().discover());
+                // The listen addrs are only known once bound, so mDNS discovery
+                // is started anew on every bind.
+                let disco = match mdns {
+                    Some(config) => {
+                        let mdns =
+                            discovery::Mdns::new(config, bound.peer_id(), bound.listen_addrs());
+                        futures::stream::select(disco.clone().discover(), mdns.discover()).boxed()
+                    },
+                    None => disco.clone().discover().boxed(),
+                };
+                let (stop, run) = bound.accept(disco);
                 let run = run.fuse();
                 pin_mut!(run);
 
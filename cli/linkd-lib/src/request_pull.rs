@@ -6,27 +6,34 @@ use std::fmt;
 use thiserror::Error;
 
 use librad::{
-    git::{storage, tracking, Urn},
+    git::{
+        storage,
+        tracking::{self, auto},
+        Urn,
+    },
     net::protocol::request_pull::Guard,
     PeerId,
 };
 
-use crate::tracking::Tracker;
+use crate::tracking::{Provisional, Tracker};
 
 #[derive(Clone)]
 pub struct State {
     storage: storage::Pool<storage::Storage>,
     tracker: Option<Tracker>,
+    provisional: Provisional,
 }
 
 impl State {
     pub fn new(
         storage: storage::Pool<storage::Storage>,
         tracker: impl Into<Option<Tracker>>,
+        provisional: Provisional,
     ) -> Self {
         State {
             storage,
             tracker: tracker.into(),
+            provisional,
         }
     }
 }
@@ -39,6 +46,8 @@ pub enum Error {
     IsTracked(#[from] tracking::error::IsTracked),
     #[error(transparent)]
     Track(#[from] tracking::error::Track),
+    #[error(transparent)]
+    Policy(#[from] auto::Error),
     #[error("`{0}` was rejected")]
     Rejected(Urn),
 }
@@ -66,16 +75,22 @@ impl Guard for State {
     fn guard(&self, peer: &PeerId, urn: &Urn) -> Result<Self::Output, Self::Error> {
         match &self.tracker {
             Some(tracker) => {
-                if tracker.guard(peer, urn).unwrap() {
-                    let storage = futures::executor::block_on(self.storage.get())?;
-                    if !tracking::is_tracked(storage.as_ref(), urn, Some(*peer))? {
+                let storage = futures::executor::block_on(self.storage.get())?;
+                let decision = tracker.decide(&*storage, peer, urn)?;
+                if decision != auto::Decision::Ignore {
+                    if !tracking::is_tracked(&*storage, urn, Some(*peer))? {
                         let tracked = tracking::track(
-                            storage.as_ref(),
+                            &*storage,
                             urn,
                             Some(*peer),
                             tracking::Config::default(),
                             tracking::policy::Track::MustNotExist,
                         )?;
+                        // A pending decision is revised by the tracking routine
+                        // once the identity was pulled.
+                        if decision == auto::Decision::Pending && tracked.is_ok() {
+                            self.provisional.insert(urn, *peer);
+                        }
                         Ok(Tracked {
                             tracked: Some(tracked),
                             urn: urn.clone(),
@@ -0,0 +1,142 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Periodic re-synchronisation of tracked namespaces.
+//!
+//! Peers only fetch in response to gossip, so an announcement missed eg.
+//! while the node was offline leaves the local view of a namespace behind
+//! until the next update. Every [`Config::interval`] (give or take
+//! [`Config::jitter`]), the routine compares the signed refs of each tracked
+//! namespace with those of a sample of the tracked peers we are connected to,
+//! and fetches the namespaces found to be stale.
+//!
+//! Fetches are scheduled with [`Priority::Resync`], so they yield to
+//! interactive requests and gossip.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+use rand::{seq::SliceRandom as _, Rng as _};
+use tokio::time::sleep;
+use tracing::{debug, info, instrument, warn};
+
+use librad::{
+    git::{tracking, Urn},
+    net::{peer::Peer, protocol::RequestPullGuard, replication::Priority},
+    PeerId,
+    Signer,
+};
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Average interval between re-synchronisation rounds.
+    ///
+    /// Default: 10min
+    pub interval: Duration,
+    /// Maximum deviation from `interval`, chosen at random for each round so
+    /// that nodes started together don't fetch from their seeds in lockstep.
+    ///
+    /// Default: 2min
+    pub jitter: Duration,
+    /// Number of connected tracked peers to compare each namespace with.
+    ///
+    /// Default: 3
+    pub sample: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10 * 60),
+            jitter: Duration::from_secs(2 * 60),
+            sample: 3,
+        }
+    }
+}
+
+#[instrument(name = "resync subroutine", skip(peer, config))]
+pub async fn routine<S, G>(peer: Peer<S, G>, config: Config) -> anyhow::Result<()>
+where
+    S: Signer + Clone,
+    G: RequestPullGuard,
+{
+    info!("starting resync routine");
+
+    let client = peer.client()?.prioritise(Priority::Resync);
+    loop {
+        sleep(jittered(config.interval, config.jitter)).await;
+
+        let candidates = match candidates(&peer, &config).await {
+            Ok(candidates) => candidates,
+            Err(err) => {
+                warn!(err = %err, "failed to determine namespaces to resync");
+                continue;
+            },
+        };
+        debug!(namespaces = candidates.len(), "resync round");
+        for (urn, peers) in candidates {
+            for remote_peer in peers {
+                match client
+                    .replicate_diverged((remote_peer, vec![]), urn.clone(), None)
+                    .await
+                {
+                    Ok(Some(_)) => info!(%urn, %remote_peer, "resynced stale namespace"),
+                    Ok(None) => {},
+                    Err(err) => warn!(err = %err, %urn, %remote_peer, "failed to resync"),
+                }
+            }
+        }
+    }
+}
+
+/// The tracked namespaces, each with a random sample of the tracked peers we
+/// are connected to.
+async fn candidates<S, G>(
+    peer: &Peer<S, G>,
+    config: &Config,
+) -> anyhow::Result<BTreeMap<Urn, Vec<PeerId>>>
+where
+    S: Signer + Clone,
+    G: RequestPullGuard,
+{
+    let connected = peer
+        .connected_peers()
+        .await
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+    if connected.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    let tracked = peer
+        .using_read_only(move |storage| {
+            let mut tracked = BTreeMap::<Urn, Vec<PeerId>>::new();
+            for entry in tracking::tracked(storage, None)? {
+                if let tracking::Tracked::Peer { urn, peer, .. } = entry? {
+                    if connected.contains(&peer) {
+                        tracked.entry(urn).or_default().push(peer);
+                    }
+                }
+            }
+            Ok::<_, anyhow::Error>(tracked)
+        })
+        .await??;
+
+    let mut rng = rand::thread_rng();
+    Ok(tracked
+        .into_iter()
+        .map(|(urn, mut peers)| {
+            peers.shuffle(&mut rng);
+            peers.truncate(config.sample);
+            (urn, peers)
+        })
+        .collect())
+}
+
+fn jittered(interval: Duration, jitter: Duration) -> Duration {
+    let jitter = jitter.min(interval);
+    let offset = rand::thread_rng().gen_range(Duration::ZERO..=jitter * 2);
+    interval - jitter + offset
+}
@@ -3,17 +3,34 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{collections::BTreeSet, convert::Infallible, str::FromStr};
+use std::{
+    collections::BTreeSet,
+    convert::Infallible,
+    str::FromStr,
+    sync::{Arc, Mutex, PoisonError},
+};
 
 use futures::{pin_mut, StreamExt as _};
 use radicle_git_ext::FromMultihashError;
 use thiserror::Error;
-use tracing::{error, info, instrument, trace};
+use tracing::{error, info, instrument, trace, warn};
 
 use librad::{
-    git::{tracking, Urn},
+    git::{
+        storage::{gc, ReadOnly, Storage},
+        tracking::{
+            self,
+            auto::{self, Decision},
+        },
+        Urn,
+    },
     net::{
-        peer::{event::upstream::Gossip, Peer, PeerInfo, ProtocolEvent},
+        peer::{
+            event::upstream::{Gossip, RequestPull},
+            Peer,
+            PeerInfo,
+            ProtocolEvent,
+        },
         protocol::{
             broadcast::PutResult::Uninteresting,
             gossip::Payload,
@@ -25,6 +42,8 @@ use librad::{
     Signer,
 };
 
+use crate::journal::{self, Journal};
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Tracker {
     /// Track any `Urn` or `PeerId`, regardless of a tracking entry being
@@ -34,6 +53,8 @@ pub enum Tracker {
     ///
     /// Use [`Tracker::selected`] for constructing this variant.
     Selected(Selected),
+    /// Track according to an [`auto::Policy`].
+    Policy(auto::Policy),
 }
 
 impl request_pull::Guard for Tracker {
@@ -45,6 +66,12 @@ impl request_pull::Guard for Tracker {
         match self {
             Self::Everything => Ok(true),
             Self::Selected(selected) => selected.guard(peer, urn),
+            // Without storage access, the identity is not available. Rules
+            // depending on it are evaluated by the `State` guard.
+            Self::Policy(policy) => Ok(matches!(
+                policy.evaluate(peer, urn, None),
+                Ok(Decision::Track | Decision::Pending)
+            )),
         }
     }
 }
@@ -207,16 +234,95 @@ impl Tracker {
         Self::Selected(Selected::new(peers, urns, pairs))
     }
 
-    fn is_tracked(&self, peer_id: &PeerId, urn: &Urn) -> bool {
+    /// Decide whether to track `urn` as announced by `peer_id`.
+    pub fn decide<S>(
+        &self,
+        storage: &S,
+        peer_id: &PeerId,
+        urn: &Urn,
+    ) -> Result<Decision, auto::Error>
+    where
+        S: AsRef<ReadOnly>,
+    {
         match self {
-            Self::Everything => true,
-            Self::Selected(Selected(s)) => s.iter().any(|s| s.is_tracked(peer_id, urn)),
+            Self::Everything => Ok(Decision::Track),
+            Self::Selected(Selected(s)) => {
+                if s.iter().any(|s| s.is_tracked(peer_id, urn)) {
+                    Ok(Decision::Track)
+                } else {
+                    Ok(Decision::Ignore)
+                }
+            },
+            Self::Policy(policy) => policy.evaluate_in(storage, peer_id, urn),
         }
     }
 }
 
-#[instrument(name = "tracking subroutine", skip(peer, tracker))]
-pub async fn routine<S, G>(peer: Peer<S, G>, tracker: Tracker) -> anyhow::Result<()>
+/// The `(urn, peer)` pairs tracked provisionally by the request-pull guard,
+/// because the decision depends on the identity which was not yet available.
+///
+/// Shared between the guard and the [`routine`], which revises them once the
+/// request-pull completed.
+#[derive(Clone, Debug, Default)]
+pub struct Provisional(Arc<Mutex<BTreeSet<(Urn, PeerId)>>>);
+
+impl Provisional {
+    pub fn insert(&self, urn: &Urn, peer: PeerId) {
+        self.lock().insert((urn.clone().with_path(None), peer));
+    }
+
+    /// Remove the pair, returning whether it was tracked provisionally.
+    pub fn remove(&self, urn: &Urn, peer: PeerId) -> bool {
+        self.lock().remove(&(urn.clone().with_path(None), peer))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeSet<(Urn, PeerId)>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RejectError {
+    #[error(transparent)]
+    UntrackAll(#[from] tracking::error::UntrackAll),
+    #[error(transparent)]
+    Gc(#[from] gc::Error),
+}
+
+/// Undo the replication of a provisionally tracked `urn` which was rejected:
+/// untrack all peers, including the delegates tracked while replicating, and
+/// remove the namespace.
+pub fn reject(storage: &Storage, urn: &Urn) -> Result<(), RejectError> {
+    let urn = urn.clone().with_path(None);
+    tracking::untrack_all(
+        storage,
+        &urn,
+        tracking::UntrackAllArgs::new(tracking::policy::UntrackAll::Any),
+    )?;
+    gc::remove_namespace(storage, &urn)?;
+
+    Ok(())
+}
+
+/// The outcome of handling an announcement in the [`routine`].
+enum Outcome {
+    Tracked,
+    AlreadyTracked,
+    Ignored,
+    /// Tracked provisionally, but rejected once the identity was available.
+    Rejected,
+}
+
+#[instrument(
+    name = "tracking subroutine",
+    skip(peer, tracker, provisional, journal)
+)]
+pub async fn routine<S, G>(
+    peer: Peer<S, G>,
+    tracker: Tracker,
+    provisional: Provisional,
+    journal: Journal,
+) -> anyhow::Result<()>
 where
     S: Signer + Clone,
     G: RequestPullGuard,
@@ -238,11 +344,16 @@ where
                     result,
                 } = *gossip;
 
-                if result != Uninteresting || !tracker.is_tracked(&peer_id, &urn) {
+                if result != Uninteresting {
                     continue;
                 }
 
                 let go = async {
+                    let decision = decide(&peer, &tracker, peer_id, &urn).await?;
+                    if decision == Decision::Ignore {
+                        return Ok(Outcome::Ignored);
+                    }
+
                     let updated = peer
                     .using_storage({
                         let urn = urn.clone();
@@ -268,19 +379,59 @@ where
                     .await??;
 
                     // Skip explicit replication if the peer is already tracked.
-                    if updated {
-                        let addr_hints = seen_addrs.iter().copied().collect::<Vec<_>>();
-                        peer.client()?
+                    if !updated {
+                        return Ok(Outcome::AlreadyTracked);
+                    }
+
+                    let addr_hints = seen_addrs.iter().copied().collect::<Vec<_>>();
+                    let task = journal::Task::Replicate {
+                        urn: urn.clone(),
+                        peer: peer_id,
+                        addrs: addr_hints.clone(),
+                    };
+                    if let Some(id) = journal.schedule(task)? {
+                        let res = peer
+                            .client()?
                             .replicate((peer_id, addr_hints), urn.clone(), None)
-                            .await?;
+                            .await;
+                        journal.complete(id)?;
+                        res?;
                     }
 
-                    Ok::<_, anyhow::Error>(updated)
+                    if decision == Decision::Pending {
+                        return revise(&peer, &tracker, peer_id, &urn).await;
+                    }
+
+                    Ok::<_, anyhow::Error>(Outcome::Tracked)
                 };
 
                 match go.await {
-                    Ok(true) => info!("tracked project {} from {}", urn, peer_id),
-                    Ok(false) => info!("already tracked {} from {}", urn, peer_id),
+                    Ok(Outcome::Tracked) => info!("tracked project {} from {}", urn, peer_id),
+                    Ok(Outcome::AlreadyTracked) => {
+                        info!("already tracked {} from {}", urn, peer_id)
+                    },
+                    Ok(Outcome::Ignored) => trace!("ignored {} from {}", urn, peer_id),
+                    Ok(Outcome::Rejected) => {
+                        info!("rejected {} from {} as per policy", urn, peer_id)
+                    },
+                    Err(err) => error!(?err, "tracking failed for {} from {}", urn, peer_id),
+                }
+            },
+
+            // Request-pulls are admitted before the identity may be known, so
+            // policies depending on it are evaluated once it was pulled. Only
+            // the entries the guard created provisionally are revised.
+            Ok(ProtocolEvent::RequestPull(RequestPull {
+                peer: peer_id, urn, ..
+            })) => {
+                if !provisional.remove(&urn, peer_id) {
+                    continue;
+                }
+                match revise(&peer, &tracker, peer_id, &urn).await {
+                    Ok(Outcome::Rejected) => {
+                        info!("rejected {} from {} as per policy", urn, peer_id)
+                    },
+                    Ok(_) => {},
                     Err(err) => error!(?err, "tracking failed for {} from {}", urn, peer_id),
                 }
             },
@@ -294,3 +445,49 @@ where
 
     Ok(())
 }
+
+async fn decide<S, G>(
+    peer: &Peer<S, G>,
+    tracker: &Tracker,
+    peer_id: PeerId,
+    urn: &Urn,
+) -> anyhow::Result<Decision>
+where
+    S: Signer + Clone,
+    G: RequestPullGuard,
+{
+    let tracker = tracker.clone();
+    let urn = urn.clone();
+    Ok(peer
+        .using_storage(move |storage| tracker.decide(storage, &peer_id, &urn))
+        .await??)
+}
+
+/// Evaluate `tracker` again for a provisionally tracked `urn`, and [`reject`]
+/// it if it is rejected.
+async fn revise<S, G>(
+    peer: &Peer<S, G>,
+    tracker: &Tracker,
+    peer_id: PeerId,
+    urn: &Urn,
+) -> anyhow::Result<Outcome>
+where
+    S: Signer + Clone,
+    G: RequestPullGuard,
+{
+    match decide(peer, tracker, peer_id, urn).await? {
+        Decision::Ignore => {
+            peer.using_storage({
+                let urn = urn.clone();
+                move |storage| reject(storage, &urn)
+            })
+            .await??;
+            Ok(Outcome::Rejected)
+        },
+        Decision::Pending => {
+            warn!(%urn, %peer_id, "identity still not available, keeping provisional tracking entry");
+            Ok(Outcome::Tracked)
+        },
+        Decision::Track => Ok(Outcome::Tracked),
+    }
+}
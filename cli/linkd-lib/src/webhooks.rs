@@ -0,0 +1,372 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Seed-side dispatcher for the webhooks declared by projects, cf.
+//! [`librad::git::identities::webhooks`].
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs as _},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use futures::{pin_mut, StreamExt as _};
+use radicle_git_ext as ext;
+use thiserror::Error;
+use tokio::sync::Semaphore;
+use tracing::{debug, error, info, instrument, warn};
+use url::Url;
+
+use librad::{
+    git::{hooks::webhook, identities::webhooks, Urn},
+    net::{
+        peer::{
+            event::upstream::{Gossip, RequestPull},
+            Peer,
+            PeerInfo,
+            ProtocolEvent,
+        },
+        protocol::{
+            broadcast::PutResult::Applied,
+            gossip::{Payload, Rev},
+            RequestPullGuard,
+        },
+    },
+    Signer,
+};
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Directory containing the shared secrets referenced by webhooks, in a
+    /// subdirectory per project named after the URN id, one file per secret.
+    ///
+    /// Scoping the secrets by project prevents a project from having events
+    /// signed with a secret meant for another.
+    pub secrets: Option<PathBuf>,
+    /// Hosts webhooks may be delivered to.
+    ///
+    /// Webhooks are declared by project maintainers, not the operator, so
+    /// nothing is delivered unless the operator allows the host. Addresses
+    /// which are not [`is_public`] are refused regardless.
+    pub allowed_hosts: BTreeSet<String>,
+    pub dispatch: webhook::Config,
+    /// The number of events delivered concurrently. Further events wait for a
+    /// delivery to finish, so a slow endpoint can not pile up tasks.
+    ///
+    /// Default: 16
+    pub max_deliveries: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            secrets: None,
+            allowed_hosts: BTreeSet::new(),
+            dispatch: webhook::Config::default(),
+            max_deliveries: 16,
+        }
+    }
+}
+
+#[instrument(name = "webhooks subroutine", skip(peer, config))]
+pub async fn routine<S, G>(peer: Peer<S, G>, config: Config) -> anyhow::Result<()>
+where
+    S: Signer + Clone,
+    G: RequestPullGuard,
+{
+    info!("starting webhooks routine");
+    if config.allowed_hosts.is_empty() {
+        warn!("no hosts allowed, webhooks will not be delivered");
+    }
+
+    let dispatcher = Arc::new(webhook::Dispatcher::new(
+        config.dispatch,
+        Http::new(&config.dispatch),
+    ));
+    let deliveries = Arc::new(Semaphore::new(config.max_deliveries.max(1)));
+    let events = peer.subscribe();
+    pin_mut!(events);
+
+    while let Some(res) = events.next().await {
+        let event = match res {
+            Ok(ProtocolEvent::Gossip(gossip)) => match *gossip {
+                Gossip::Put {
+                    provider: PeerInfo { peer_id, .. },
+                    payload:
+                        Payload {
+                            urn,
+                            rev: Some(Rev::Git(rev)),
+                            ..
+                        },
+                    result: Applied(_),
+                } => webhook::Event::Replicated {
+                    urn,
+                    peer: peer_id,
+                    rev: ext::Oid::from(rev),
+                },
+                _ => continue,
+            },
+            Ok(ProtocolEvent::RequestPull(RequestPull { peer, urn, refs })) => {
+                webhook::Event::RequestPull {
+                    urn,
+                    peer,
+                    refs: refs
+                        .into_iter()
+                        .map(|r| (r.name.to_string(), r.oid))
+                        .collect(),
+                }
+            },
+            Ok(_) => continue,
+            Err(err) => {
+                error!(?err, "event error");
+                continue;
+            },
+        };
+
+        let urn = event.urn().clone();
+        let hooks = match peer
+            .using_storage({
+                let urn = urn.clone();
+                move |storage| webhooks::get(storage, &urn)
+            })
+            .await
+        {
+            Ok(Ok(Some(hooks))) if !hooks.0.is_empty() => hooks,
+            Ok(Ok(_)) => continue,
+            Ok(Err(err)) => {
+                warn!(err = %err, urn = %urn, "failed to read webhooks");
+                continue;
+            },
+            Err(err) => {
+                error!(err = %err, "failed to access storage");
+                continue;
+            },
+        };
+
+        let endpoints = endpoints(&config, &urn, hooks);
+        if endpoints.is_empty() {
+            continue;
+        }
+        let permit = deliveries.clone().acquire_owned().await?;
+        tokio::spawn({
+            let dispatcher = dispatcher.clone();
+            async move {
+                for (endpoint, err) in dispatcher.dispatch(&endpoints, &event).await {
+                    warn!(err = %err, url = %endpoint.url, urn = %urn, "webhook delivery failed");
+                }
+                drop(permit)
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Resolve the secrets of `hooks`.
+///
+/// Webhooks to hosts which are not allowed are skipped. So are webhooks
+/// referencing a secret which can not be resolved, as the receiving end
+/// presumably expects requests to be signed.
+fn endpoints(config: &Config, urn: &Urn, hooks: webhooks::Webhooks) -> Vec<webhook::Endpoint> {
+    let secrets = config.secrets.as_ref().map(|dir| dir.join(urn.encode_id()));
+    hooks
+        .0
+        .into_iter()
+        .filter_map(|hook| {
+            if !allowed(&config.allowed_hosts, &hook.url) {
+                debug!(urn = %urn, url = %hook.url, "host not allowed, skipping webhook");
+                return None;
+            }
+            let secret = match &hook.secret {
+                None => None,
+                Some(name) => match secrets.as_deref().map(|dir| secret(dir, name)) {
+                    Some(Ok(secret)) => Some(secret),
+                    Some(Err(err)) => {
+                        warn!(err = %err, urn = %urn, url = %hook.url, "skipping webhook");
+                        return None;
+                    },
+                    None => {
+                        debug!(urn = %urn, url = %hook.url, "no secrets configured, skipping webhook");
+                        return None;
+                    },
+                },
+            };
+            Some(webhook::Endpoint {
+                url: hook.url.to_string(),
+                secret,
+            })
+        })
+        .collect()
+}
+
+/// Whether webhooks may be delivered to `url`, given the `allowed` hosts.
+pub fn allowed(allowed: &BTreeSet<String>, url: &Url) -> bool {
+    matches!(url.scheme(), "http" | "https")
+        && url
+            .host_str()
+            .map(|host| allowed.contains(host))
+            .unwrap_or(false)
+}
+
+/// Whether `ip` is a globally routable address, ie. not loopback, private,
+/// link-local or otherwise reserved.
+///
+/// IPv6 addresses embedding an IPv4 address are judged by the latter, except
+/// for NAT64 addresses, which are refused outright as the translator may reach
+/// hosts on its private network.
+pub fn is_public(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => is_public_v6(ip),
+    }
+}
+
+fn is_public_v4(ip: &Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // Shared address space, RFC 6598
+        || (a == 100 && (b & 0xc0) == 64)
+        // "This network", RFC 1122
+        || a == 0)
+}
+
+fn is_public_v6(ip: &Ipv6Addr) -> bool {
+    if ip.is_unspecified() || ip.is_loopback() {
+        return false;
+    }
+    let segments = ip.segments();
+    match segments {
+        // IPv4-mapped and IPv4-compatible, RFC 4291
+        [0, 0, 0, 0, 0, 0xffff, hi, lo] | [0, 0, 0, 0, 0, 0, hi, lo] => {
+            return is_public_v4(&v4(hi, lo))
+        },
+        // 6to4, RFC 3056
+        [0x2002, hi, lo, ..] => return is_public_v4(&v4(hi, lo)),
+        _ => {},
+    }
+    let first = segments[0];
+    !(ip.is_multicast()
+        // NAT64 well-known prefix, RFC 6052
+        || segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0]
+        // NAT64 local-use prefix, RFC 8215
+        || segments[..3] == [0x64, 0xff9b, 1]
+        // Documentation, RFC 3849
+        || segments[..2] == [0x2001, 0xdb8]
+        // Unique local, RFC 4193
+        || (first & 0xfe00) == 0xfc00
+        // Link-local
+        || (first & 0xffc0) == 0xfe80
+        // Site-local, deprecated by RFC 3879
+        || (first & 0xffc0) == 0xfec0)
+}
+
+fn v4(hi: u16, lo: u16) -> Ipv4Addr {
+    Ipv4Addr::from(u32::from(hi) << 16 | u32::from(lo))
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum SecretError {
+    #[error("invalid secret name `{0}`")]
+    Name(String),
+    #[error("failed to read secret `{name}`")]
+    Io {
+        name: String,
+        #[source]
+        source: io::Error,
+    },
+}
+
+pub(crate) fn secret(dir: &Path, name: &str) -> Result<Vec<u8>, SecretError> {
+    if name.is_empty() || name.starts_with('.') || name.contains(std::path::is_separator) {
+        return Err(SecretError::Name(name.to_owned()));
+    }
+    let mut secret = fs::read(dir.join(name)).map_err(|source| SecretError::Io {
+        name: name.to_owned(),
+        source,
+    })?;
+    while secret.last() == Some(&b'\n') {
+        secret.pop();
+    }
+
+    Ok(secret)
+}
+
+#[derive(Debug, Error)]
+pub enum HttpError {
+    #[error(transparent)]
+    Request(#[from] Box<ureq::Error>),
+    #[error(transparent)]
+    Join(#[from] tokio::task::JoinError),
+}
+
+/// [`webhook::Client`] backed by [`ureq`].
+///
+/// The timeouts of the [`webhook::Config`] are applied to the connection, so
+/// requests are aborted rather than left running on the blocking thread pool
+/// when the dispatcher gives up on them. Redirects are not followed, and
+/// hosts which resolve to non-[`is_public`] addresses are refused.
+pub struct Http {
+    agent: ureq::Agent,
+}
+
+impl Http {
+    pub fn new(config: &webhook::Config) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(config.timeout)
+            .timeout(config.timeout)
+            .redirects(0)
+            .resolver(resolve_public)
+            .build();
+        Self { agent }
+    }
+}
+
+fn resolve_public(netloc: &str) -> io::Result<Vec<SocketAddr>> {
+    let addrs = netloc
+        .to_socket_addrs()?
+        .filter(|addr| is_public(&addr.ip()))
+        .collect::<Vec<_>>();
+    if addrs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} does not resolve to a public address", netloc),
+        ));
+    }
+
+    Ok(addrs)
+}
+
+#[async_trait::async_trait]
+impl webhook::Client for Http {
+    type Error = HttpError;
+
+    async fn post(
+        &self,
+        url: &str,
+        headers: Vec<(&'static str, String)>,
+        body: Vec<u8>,
+    ) -> Result<(), Self::Error> {
+        let agent = self.agent.clone();
+        let url = url.to_owned();
+        tokio::task::spawn_blocking(move || {
+            let mut req = agent.post(&url);
+            for (name, value) in &headers {
+                req = req.set(name, value);
+            }
+            // Non-2xx responses are errors
+            req.send_bytes(&body).map(|_| ()).map_err(Box::new)
+        })
+        .await??;
+
+        Ok(())
+    }
+}
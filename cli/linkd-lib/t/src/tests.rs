@@ -5,4 +5,7 @@
 
 mod api;
 mod args;
+mod expiry;
+mod journal;
 mod tracking;
+mod webhooks;
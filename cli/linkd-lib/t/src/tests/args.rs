@@ -23,13 +23,16 @@ use linkd_lib::args::{
     KeyArgs,
     MetricsArgs,
     MetricsProvider,
+    MirrorArgs,
     ProtocolArgs,
     ProtocolListen,
+    ResyncArgs,
     Signer,
     TrackingArgs,
     TrackingMode,
+    WebhookArgs,
 };
-use lnk_clib::seed::Seed;
+use lnk_clib::{keys::store, seed::Seed};
 
 #[test]
 fn defaults() -> Result<()> {
@@ -92,6 +95,28 @@ fn bootstraps() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn bootstrap_domains() -> Result<()> {
+    #[rustfmt::skip]
+    let iter = vec![
+        "linkd",
+            "--protocol-listen", "localhost",
+            "--bootstrap-domain", "radicle.xyz",
+            "--bootstrap-domain", "seeds.example.com",
+    ];
+    let parsed = Args::try_parse_from(iter)?;
+
+    assert_eq!(
+        parsed,
+        Args {
+            bootstrap_domains: vec!["radicle.xyz".to_string(), "seeds.example.com".to_string()],
+            ..Default::default()
+        }
+    );
+
+    Ok(())
+}
+
 #[test]
 fn metrics_graphite() -> Result<()> {
     #[rustfmt::skip]
@@ -109,6 +134,33 @@ fn metrics_graphite() -> Result<()> {
             metrics: MetricsArgs {
                 provider: Some(MetricsProvider::Graphite),
                 graphite_addr: "graphite:9108".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn metrics_prometheus() -> Result<()> {
+    #[rustfmt::skip]
+    let iter = vec![
+        "linkd",
+            "--protocol-listen", "localhost",
+            "--metrics-provider", "prometheus",
+            "--prometheus-listen", "0.0.0.0:9102",
+    ];
+    let parsed = Args::try_parse_from(iter)?;
+
+    assert_eq!(
+        parsed,
+        Args {
+            metrics: MetricsArgs {
+                provider: Some(MetricsProvider::Prometheus),
+                prometheus_listen: "0.0.0.0:9102".to_string(),
+                ..Default::default()
             },
             ..Default::default()
         }
@@ -189,6 +241,54 @@ fn protocol_network() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn protocol_mdns() -> Result<()> {
+    #[rustfmt::skip]
+    let iter = vec![
+        "linkd",
+            "--protocol-listen", "localhost",
+            "--protocol-mdns",
+    ];
+    let parsed = Args::try_parse_from(iter)?;
+
+    assert_eq!(
+        parsed,
+        Args {
+            protocol: ProtocolArgs {
+                mdns: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn protocol_no_compression() -> Result<()> {
+    #[rustfmt::skip]
+    let iter = vec![
+        "linkd",
+            "--protocol-listen", "localhost",
+            "--protocol-no-compression",
+    ];
+    let parsed = Args::try_parse_from(iter)?;
+
+    assert_eq!(
+        parsed,
+        Args {
+            protocol: ProtocolArgs {
+                no_compression: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    );
+
+    Ok(())
+}
+
 #[test]
 fn lnk_home() -> Result<()> {
     #[rustfmt::skip]
@@ -355,6 +455,24 @@ fn signer_ssh_agent() -> Result<()> {
         }
     );
 
+    #[rustfmt::skip]
+    let iter = vec![
+        "linkd",
+            "--protocol-listen", "localhost",
+            "--signer", "ssh-agent",
+            "--ssh-key", "hybz9gfgtd9d4pd14a6r66j5hz6f77fed4jdu7pana4fxaxbt369kg",
+    ];
+    let parsed = Args::try_parse_from(iter)?;
+
+    assert_eq!(
+        parsed,
+        Args {
+            signer: Signer::SshAgent,
+            ssh_key: Some("hybz9gfgtd9d4pd14a6r66j5hz6f77fed4jdu7pana4fxaxbt369kg".parse()?),
+            ..Default::default()
+        }
+    );
+
     Ok(())
 }
 
@@ -422,3 +540,121 @@ fn tracking() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn tracking_policy() -> Result<()> {
+    #[rustfmt::skip]
+    let parsed = Args::try_parse_from(vec![
+        "linkd",
+            "--protocol-listen", "localhost",
+            "--track", "policy",
+    ])?;
+    assert_eq!(
+        parsed,
+        Args {
+            tracking: TrackingArgs {
+                mode: Some(TrackingMode::Policy),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn webhooks() -> Result<()> {
+    #[rustfmt::skip]
+    let parsed = Args::try_parse_from(vec![
+        "linkd",
+            "--protocol-listen", "localhost",
+            "--webhooks",
+            "--webhook-secrets", "/run/secrets/linkd",
+            "--webhook-allow-host", "ci.example.com",
+            "--webhook-allow-host", "chat.example.com",
+    ])?;
+    assert_eq!(
+        parsed,
+        Args {
+            webhooks: WebhookArgs {
+                enabled: true,
+                secrets: Some(PathBuf::from("/run/secrets/linkd")),
+                allowed_hosts: vec!["ci.example.com".to_owned(), "chat.example.com".to_owned()],
+            },
+            ..Default::default()
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn signer_key_store() -> Result<()> {
+    #[rustfmt::skip]
+    let parsed = Args::try_parse_from(vec![
+        "linkd",
+            "--protocol-listen", "localhost",
+            "--signer", "key-store",
+            "--key-storage", "keychain",
+    ])?;
+    assert_eq!(
+        parsed,
+        Args {
+            signer: args::Signer::KeyStore,
+            key: KeyArgs {
+                storage: store::Backend::Keychain,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn mirrors() -> Result<()> {
+    #[rustfmt::skip]
+    let parsed = Args::try_parse_from(vec![
+        "linkd",
+            "--protocol-listen", "localhost",
+            "--mirrors",
+            "--mirror-secrets", "/run/secrets/mirrors",
+    ])?;
+    assert_eq!(
+        parsed,
+        Args {
+            mirrors: MirrorArgs {
+                enabled: true,
+                secrets: Some(PathBuf::from("/run/secrets/mirrors")),
+            },
+            ..Default::default()
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn resync() -> Result<()> {
+    #[rustfmt::skip]
+    let parsed = Args::try_parse_from(vec![
+        "linkd",
+            "--protocol-listen", "localhost",
+            "--resync",
+            "--resync-interval", "300",
+    ])?;
+    assert_eq!(
+        parsed,
+        Args {
+            resync: ResyncArgs {
+                enabled: true,
+                interval: Some(300),
+            },
+            ..Default::default()
+        }
+    );
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{git::tracking, PeerId, SecretKey};
+use linkd_lib::expiry;
+
+#[test]
+fn expiry_untracks() {
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+    let expired = PeerId::from(SecretKey::new());
+    let current = PeerId::from(SecretKey::new());
+
+    let now = SystemTime::now();
+    let secs = now.duration_since(UNIX_EPOCH).unwrap().as_secs();
+    for (peer, expires) in [(expired, secs - 1), (current, secs + 60)] {
+        tracking::track(
+            &store,
+            &urn,
+            Some(peer),
+            tracking::Config {
+                expires: Some(expires),
+                ..tracking::Config::default()
+            },
+            tracking::policy::Track::Any,
+        )
+        .unwrap()
+        .unwrap();
+    }
+
+    assert_eq!(
+        expiry::expire(&store, now).unwrap(),
+        vec![(urn.clone(), expired)]
+    );
+    assert!(!tracking::is_tracked(&store, &urn, Some(expired)).unwrap());
+    assert!(tracking::is_tracked(&store, &urn, Some(current)).unwrap());
+
+    // Nothing left to expire
+    assert!(expiry::expire(&store, now).unwrap().is_empty());
+}
@@ -0,0 +1,65 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{fs::OpenOptions, io::Write as _};
+
+use linkd_lib::journal::{Journal, Task};
+
+fn replicate(addrs: Vec<std::net::SocketAddr>) -> Task {
+    Task::Replicate {
+        urn: "rad:git:hnrkb39fr6f4jj59nfiq7tfd9aznirdu7b59o"
+            .parse()
+            .unwrap(),
+        peer: "hynkyndc6w3p8urucakobzna7sxwgcqny7xxtw88dtx3pkf7m3nrzc"
+            .parse()
+            .unwrap(),
+        addrs,
+    }
+}
+
+#[test]
+fn pending_survives_reopen() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("tasks.journal");
+
+    let journal = Journal::open(&path).unwrap();
+    let id = journal.schedule(replicate(vec![])).unwrap().unwrap();
+    // Equivalent task is deduplicated
+    assert!(journal
+        .schedule(replicate(vec!["127.0.0.1:8776".parse().unwrap()]))
+        .unwrap()
+        .is_none());
+    drop(journal);
+
+    let journal = Journal::open(&path).unwrap();
+    assert_eq!(journal.pending(), vec![(id, replicate(vec![]))]);
+    journal.complete(id).unwrap();
+    drop(journal);
+
+    let journal = Journal::open(&path).unwrap();
+    assert!(journal.pending().is_empty());
+    // Ids are not reused
+    assert_ne!(journal.schedule(replicate(vec![])).unwrap(), Some(id));
+}
+
+#[test]
+fn truncated_tail_is_discarded() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("tasks.journal");
+
+    let id = {
+        let journal = Journal::open(&path).unwrap();
+        journal.schedule(replicate(vec![])).unwrap().unwrap()
+    };
+    OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(&[0x82, 0x00])
+        .unwrap();
+
+    let journal = Journal::open(&path).unwrap();
+    assert_eq!(journal.pending(), vec![(id, replicate(vec![]))]);
+}
@@ -1,7 +1,17 @@
 // Copyright © 2022 The Radicle Link Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use linkd_lib::tracking::{Pair, Selected};
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{
+    git::{
+        storage::ReadOnlyStorage as _,
+        tracking::{self, auto},
+        Urn,
+    },
+    PeerId,
+    SecretKey,
+};
+use linkd_lib::tracking::{reject, Pair, Provisional, Selected, Tracker};
 
 #[test]
 pub fn selected_dedups() {
@@ -17,3 +27,56 @@ pub fn selected_dedups() {
     assert!(selected.urns().next().is_none());
     assert_eq!(selected.pairs().next(), Some(&pair));
 }
+
+#[test]
+fn rejects_name_denied_project() {
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, owner, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+    let announcer = PeerId::from(SecretKey::new());
+    for peer in [announcer, PeerId::from(SecretKey::new())] {
+        tracking::track(
+            &store,
+            &urn,
+            Some(peer),
+            tracking::Config::default(),
+            tracking::policy::Track::Any,
+        )
+        .unwrap()
+        .unwrap();
+    }
+
+    let tracker = Tracker::Policy(auto::Policy {
+        deny: auto::Rules {
+            names: vec![project.subject().name.as_str().to_owned()],
+            ..auto::Rules::default()
+        },
+        ..auto::Policy::everything()
+    });
+    assert_eq!(
+        tracker.decide(&store, &announcer, &urn).unwrap(),
+        auto::Decision::Ignore
+    );
+
+    reject(&store, &urn).unwrap();
+    assert!(tracking::tracked_peers(&store, Some(&urn))
+        .unwrap()
+        .next()
+        .is_none());
+    assert!(!store.has_urn(&urn).unwrap());
+    assert!(store.has_urn(&owner.urn()).unwrap());
+}
+
+#[test]
+fn provisional() {
+    let provisional = Provisional::default();
+    let peer = PeerId::from(SecretKey::new());
+    let urn = "rad:git:hnrkb39fr6f4jj59nfiq7tfd9aznirdu7b59o"
+        .parse::<Urn>()
+        .unwrap();
+
+    provisional.insert(&urn, peer);
+    assert!(!provisional.remove(&urn, PeerId::from(SecretKey::new())));
+    assert!(provisional.remove(&urn, peer));
+    assert!(!provisional.remove(&urn, peer));
+}
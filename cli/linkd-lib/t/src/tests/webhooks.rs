@@ -0,0 +1,92 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    collections::BTreeSet,
+    io,
+    net::{IpAddr, TcpListener},
+    time::Duration,
+};
+
+use librad::git::hooks::webhook::{self, Client as _};
+use linkd_lib::webhooks::{allowed, is_public, Http};
+use url::Url;
+
+#[test]
+fn only_allowed_hosts() {
+    let hosts = ["ci.example.com".to_owned()]
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+    let url = |s: &str| Url::parse(s).unwrap();
+
+    assert!(allowed(&hosts, &url("https://ci.example.com/hook")));
+    assert!(allowed(&hosts, &url("http://CI.example.com:8080/hook")));
+    assert!(!allowed(&hosts, &url("https://example.com/hook")));
+    assert!(!allowed(&hosts, &url("https://evil.ci.example.com/hook")));
+    assert!(!allowed(&hosts, &url("ftp://ci.example.com/hook")));
+    assert!(!allowed(
+        &BTreeSet::new(),
+        &url("https://ci.example.com/hook")
+    ));
+}
+
+#[test]
+fn public_addresses() {
+    let public = |s: &str| is_public(&s.parse::<IpAddr>().unwrap());
+
+    assert!(public("93.184.216.34"));
+    assert!(public("2606:2800:220:1:248:1893:25c8:1946"));
+    assert!(public("::ffff:93.184.216.34"));
+    assert!(public("2002:5db8:d822::1"));
+
+    for addr in [
+        "0.0.0.0",
+        "127.0.0.1",
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.64.0.1",
+        "255.255.255.255",
+        "::",
+        "::1",
+        "::ffff:127.0.0.1",
+        "::ffff:10.0.0.1",
+        "fd00::1",
+        "fe80::1",
+        "::10.0.0.1",
+        "fec0::1",
+        "2001:db8::1",
+        "64:ff9b::93.184.216.34",
+        "64:ff9b:1::a00:1",
+        "2002:a00:1::1",
+    ] {
+        assert!(!public(addr), "{} should not be public", addr)
+    }
+}
+
+#[test]
+fn refuses_loopback() {
+    let http = Http::new(&webhook::Config {
+        timeout: Duration::from_secs(1),
+        ..webhook::Config::default()
+    });
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    for host in ["127.0.0.1", "localhost"] {
+        let url = format!("http://{}:{}/hook", host, port);
+        let res = rt.block_on(http.post(&url, vec![], b"{}".to_vec()));
+        assert!(res.is_err(), "{} should be refused", url);
+        assert_eq!(
+            listener.accept().unwrap_err().kind(),
+            io::ErrorKind::WouldBlock,
+            "{} should not have been connected to",
+            url
+        );
+    }
+}
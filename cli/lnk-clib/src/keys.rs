@@ -12,6 +12,7 @@ use librad::{
 
 pub mod prompt;
 pub mod ssh;
+pub mod store;
 
 /// The filename for storing the secret key.
 pub const LIBRAD_KEY_FILE: &str = "librad.key";
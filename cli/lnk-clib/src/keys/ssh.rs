@@ -8,6 +8,7 @@ use std::{
     fmt,
     path::{Path, PathBuf},
     str::FromStr,
+    time::Duration,
 };
 
 use thiserror::Error;
@@ -18,6 +19,12 @@ use librad::{
     PeerId,
 };
 
+/// Default for how long `agent_signer` waits for a signature.
+///
+/// Hardware tokens may require the user to confirm each signature, so this is
+/// rather generous.
+pub const SIGN_TIMEOUT: Duration = Duration::from_secs(60);
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error(transparent)]
@@ -30,6 +37,12 @@ pub enum Error {
         "the key for {0} is not in the ssh-agent, consider adding it via `lnk profile ssh add`"
     )]
     NoSuchKey(PeerId),
+    #[error("the ssh-agent holds no ed25519 keys")]
+    NoKeys,
+    #[error("the ssh-agent holds {0} ed25519 keys, the key to use must be specified")]
+    AmbiguousKey(usize),
+    #[error("the ssh-agent did not respond to the signing request within {0:?}")]
+    Timeout(Duration),
     #[error(transparent)]
     RemoveKey(#[from] ssh::error::RemoveKey),
     #[error(transparent)]
@@ -3,7 +3,7 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{fmt, sync::Arc};
+use std::{fmt, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
 use lnk_thrussh_agent::{client::tokio::UnixStream, Constraint};
@@ -26,6 +26,8 @@ use librad::{
     git::storage::ReadOnly,
     keystore::sign::Signer,
     profile::Profile,
+    PeerId,
+    PublicKey,
     Signature,
 };
 
@@ -33,9 +35,27 @@ use crate::{keys, runtime};
 
 use super::{with_socket, SshAuthSock};
 
+/// A [`Signer`] delegating to the `ssh-agent`.
+///
+/// The public key is retrieved once when connecting, so only signing requests
+/// go to the agent. They are awaited asynchronously, optionally bounded by a
+/// timeout.
 #[derive(Clone)]
 pub struct SshSigner {
+    public_key: sign::ed25519::PublicKey,
     signer: Arc<dyn sign::ed25519::Signer<Error = ssh::error::Sign> + Send + Sync>,
+    timeout: Option<Duration>,
+}
+
+impl SshSigner {
+    async fn connect(agent: SshAgent, timeout: Option<Duration>) -> Result<Self, super::Error> {
+        let signer = agent.connect::<UnixStream>().await?;
+        Ok(Self {
+            public_key: signer.public_key(),
+            signer: Arc::new(signer),
+            timeout,
+        })
+    }
 }
 
 #[async_trait]
@@ -43,14 +63,18 @@ impl Signer for SshSigner {
     type Error = BoxedSignError;
 
     fn public_key(&self) -> sign::ed25519::PublicKey {
-        self.signer.public_key()
+        self.public_key
     }
 
     async fn sign(&self, data: &[u8]) -> Result<sign::ed25519::Signature, BoxedSignError> {
-        self.signer
-            .sign(data)
-            .await
-            .map_err(BoxedSignError::from_std_error)
+        let sign = self.signer.sign(data);
+        match self.timeout {
+            None => sign.await.map_err(BoxedSignError::from_std_error),
+            Some(timeout) => tokio::time::timeout(timeout, sign)
+                .await
+                .map_err(|_| BoxedSignError::from_std_error(super::Error::Timeout(timeout)))?
+                .map_err(BoxedSignError::from_std_error),
+        }
     }
 }
 
@@ -73,17 +97,59 @@ pub fn signer(profile: &Profile, sock: SshAuthSock) -> Result<BoxedSigner, super
     runtime::block_on(async move {
         let keys = ssh::list_keys::<UnixStream>(&agent).await?;
         if keys.contains(&pk) {
-            let signer = agent.connect::<UnixStream>().await?;
-            let signer = SshSigner {
-                signer: Arc::new(signer),
-            };
-            Ok(BoxedSigner::new(signer))
+            Ok(BoxedSigner::new(SshSigner::connect(agent, None).await?))
         } else {
             Err(super::Error::NoSuchKey(peer_id))
         }
     })
 }
 
+/// Get a signer for a key which only lives in the `ssh-agent`, eg. on a
+/// hardware token.
+///
+/// Unlike [`signer`], this does not require the storage of a profile to be
+/// initialised, so it can be used to initialise it. If `peer_id` is not
+/// given, the agent must hold exactly one ed25519 key.
+///
+/// Signing fails if the agent does not respond within `timeout`.
+///
+/// See [`SshAuthSock`] for how the `ssh-agent` will be connected to. Use
+/// `SshAuthSock::default` to connect via `SSH_AUTH_SOCK`.
+pub fn agent_signer(
+    sock: SshAuthSock,
+    peer_id: Option<PeerId>,
+    timeout: Option<Duration>,
+) -> Result<BoxedSigner, super::Error> {
+    runtime::block_on(async move {
+        // Listing keys does not depend on the key the agent is created for
+        let any = with_socket(
+            SshAgent::new(sign::ed25519::PublicKey([0; 32])),
+            sock.clone(),
+        );
+        let keys = ssh::list_keys::<UnixStream>(&any).await?;
+        let pk = match peer_id {
+            Some(peer_id) => {
+                let pk = (*peer_id.as_public_key()).into();
+                if !keys.contains(&pk) {
+                    return Err(super::Error::NoSuchKey(peer_id));
+                }
+                pk
+            },
+            None => match keys.as_slice() {
+                [] => return Err(super::Error::NoKeys),
+                [pk] => *pk,
+                _ => return Err(super::Error::AmbiguousKey(keys.len())),
+            },
+        };
+        tracing::debug!(
+            peer_id = %PeerId::from(PublicKey::from(pk)),
+            "using ssh-agent key"
+        );
+        let agent = with_socket(SshAgent::new(pk), sock);
+        Ok(BoxedSigner::new(SshSigner::connect(agent, timeout).await?))
+    })
+}
+
 /// Add the signing key associated with this `profile` to the `ssh-agent`.
 ///
 /// See [`SshAuthSock`] for how the agent will be connected to. Use
@@ -3,7 +3,7 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::fmt;
+use std::{fmt, time::Duration};
 
 use serde::{de::DeserializeOwned, Serialize};
 use thrussh_agent::Constraint;
@@ -11,6 +11,7 @@ use thrussh_agent::Constraint;
 use librad::{
     crypto::{keystore::crypto::Crypto, BoxedSigner},
     profile::Profile,
+    PeerId,
 };
 
 /// Get the signing key associated with this `profile`.
@@ -21,6 +22,18 @@ pub fn signer(_profile: &Profile, _sock: SshAuthSock) -> Result<BoxedSigner, sup
     unimplemented!("Windows is not supported, contributions are welcome :)")
 }
 
+/// Get a signer for a key which only lives in the `ssh-agent`.
+///
+/// See [`SshAuthSock`] for how the `ssh-agent` will be connected to. Use
+/// `SshAuthSock::default` to connect via `SSH_AUTH_SOCK`.
+pub fn agent_signer(
+    _sock: SshAuthSock,
+    _peer_id: Option<PeerId>,
+    _timeout: Option<Duration>,
+) -> Result<BoxedSigner, super::Error> {
+    unimplemented!("Windows is not supported, contributions are welcome :)")
+}
+
 /// Add the signing key associated with this `profile` to the `ssh-agent`.
 ///
 /// See [`SshAuthSock`] for how the agent will be connected to. Use
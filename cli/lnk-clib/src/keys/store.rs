@@ -0,0 +1,585 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Storage backends for the [`SecretKey`] of a profile.
+//!
+//! The [`KeyStore`] trait abstracts over where the key is kept, and how it is
+//! protected at rest:
+//!
+//! * [`Backend::File`] is the [`FileStorage`] created by
+//!   [`super::file_storage`], encrypted using a passphrase-derived key
+//! * [`Backend::Encrypted`] is an [`EncryptedFile`], which derives the
+//!   encryption key from a passphrase using Argon2id
+//! * [`Backend::Keychain`] stores the key in the keychain of the operating
+//!   system, ie. the macOS Keychain or the freedesktop secret service on Linux.
+//!   This requires the `keychain` feature.
+//!
+//! [`open`] selects a backend at runtime, [`signer`] constructs the signer of
+//! a peer from it.
+
+use std::{
+    fmt,
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use chacha20poly1305::{
+    aead::{Aead as _, NewAead as _, Payload},
+    Key,
+    XChaCha20Poly1305,
+    XNonce,
+};
+use rand::RngCore as _;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use librad::{
+    crypto::{
+        keystore::{
+            crypto::{KdfParams, Pwhash, SecretBoxError},
+            file,
+            pinentry::{Pinentry, SecUtf8},
+            Keystore as _,
+            SecretKeyExt as _,
+        },
+        BoxedSigner,
+        IntoSecretKeyError,
+        SecStr,
+    },
+    profile::Profile,
+    PeerId,
+    PublicKey,
+    SecretKey,
+};
+
+use super::{file_storage, LIBRAD_KEY_FILE};
+
+/// The filename for storing the secret key encrypted by [`EncryptedFile`].
+pub const ENCRYPTED_KEY_FILE: &str = "librad.key.enc";
+
+/// The service name under which keys are stored in the OS keychain.
+pub const KEYCHAIN_SERVICE: &str = "radicle-link";
+
+const VERSION: u32 = 1;
+const SALT_BYTES: usize = 16;
+
+/// Upper bounds of the [`Argon2Params`] a key file may specify, so a tampered
+/// file can't make unlocking it exhaust memory or time.
+const MAX_M_COST: u32 = 1024 * 1024;
+const MAX_T_COST: u32 = 64;
+const MAX_P_COST: u32 = 16;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("no key found in {0}")]
+    NotFound(String),
+
+    #[error("key already exists in {0}")]
+    Exists(String),
+
+    #[error("failed to encrypt key")]
+    Encrypt,
+
+    #[error("failed to decrypt key: wrong passphrase or corrupted key file")]
+    Decrypt,
+
+    #[error("unsupported key file version {0}")]
+    Version(u32),
+
+    #[error("the key is stored for {stored}, but belongs to {actual}")]
+    Mismatch { stored: PeerId, actual: PeerId },
+
+    #[error("key derivation failed: {0}")]
+    Kdf(String),
+
+    #[error("error reading passphrase")]
+    Passphrase(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error("the `{0}` key storage is not supported by this build")]
+    Unsupported(Backend),
+
+    #[error(transparent)]
+    File(#[from] file::Error<SecretBoxError<io::Error>, IntoSecretKeyError>),
+
+    #[error(transparent)]
+    Key(#[from] IntoSecretKeyError),
+
+    #[cfg(feature = "keychain")]
+    #[error(transparent)]
+    Keychain(#[from] keyring::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Storage of a single [`SecretKey`].
+pub trait KeyStore {
+    /// A human-readable description of where the key is stored.
+    fn location(&self) -> String;
+
+    /// Store `key`, failing if a key is already stored.
+    fn put_key(&mut self, key: SecretKey) -> Result<(), Error>;
+
+    /// Retrieve the stored key, unlocking the storage if necessary.
+    fn get_key(&self) -> Result<SecretKey, Error>;
+}
+
+/// The available [`KeyStore`] backends, see the [module
+/// documentation](self).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Backend {
+    File,
+    Encrypted,
+    Keychain,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Self::File
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let backend = match self {
+            Self::File => "file",
+            Self::Encrypted => "encrypted",
+            Self::Keychain => "keychain",
+        };
+        write!(f, "{}", backend)
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "file" => Ok(Self::File),
+            "encrypted" => Ok(Self::Encrypted),
+            "keychain" => Ok(Self::Keychain),
+            _ => Err(format!("unsupported key storage `{}`", input)),
+        }
+    }
+}
+
+/// Open the [`KeyStore`] of `profile` using `backend`.
+///
+/// The passphrase is obtained from `pinentry` if and when the backend needs
+/// it. The [`Backend::File`] storage always uses the recommended KDF
+/// parameters.
+pub fn open<P>(
+    profile: &Profile,
+    backend: Backend,
+    pinentry: P,
+) -> Result<Box<dyn KeyStore + Send>, Error>
+where
+    P: Pinentry<Error = io::Error> + Send + 'static,
+{
+    let keys_dir = profile.paths().keys_dir();
+    match backend {
+        Backend::File => Ok(Box::new(Legacy {
+            path: keys_dir.join(LIBRAD_KEY_FILE),
+            storage: file_storage(profile, Pwhash::new(pinentry, KdfParams::recommended())),
+        })),
+        Backend::Encrypted => Ok(Box::new(EncryptedFile::new(
+            keys_dir.join(ENCRYPTED_KEY_FILE),
+            pinentry,
+            Argon2Params::recommended(),
+        ))),
+        #[cfg(feature = "keychain")]
+        Backend::Keychain => Ok(Box::new(Keychain::new(profile.id().to_string()))),
+        #[cfg(not(feature = "keychain"))]
+        Backend::Keychain => Err(Error::Unsupported(backend)),
+    }
+}
+
+/// Construct a signer from the key stored in `store`.
+pub fn signer<S>(store: &S) -> Result<BoxedSigner, Error>
+where
+    S: KeyStore + ?Sized,
+{
+    Ok(store.get_key()?.into())
+}
+
+/// A [`Pinentry`] returning a fixed passphrase, eg. one read from `stdin`.
+#[derive(Clone)]
+pub struct Passphrase(SecUtf8);
+
+impl Passphrase {
+    pub fn new(passphrase: SecUtf8) -> Self {
+        Self(passphrase)
+    }
+}
+
+impl Pinentry for Passphrase {
+    type Error = io::Error;
+
+    fn get_passphrase(&self) -> Result<SecUtf8, Self::Error> {
+        Ok(self.0.clone())
+    }
+}
+
+/// The [`FileStorage`] used by [`super::file_storage`].
+///
+/// [`FileStorage`]: librad::crypto::keystore::FileStorage
+struct Legacy<P: Pinentry> {
+    path: PathBuf,
+    storage: librad::crypto::keystore::FileStorage<Pwhash<P>, PublicKey, SecretKey, ()>,
+}
+
+impl<P> KeyStore for Legacy<P>
+where
+    P: Pinentry<Error = io::Error>,
+{
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn put_key(&mut self, key: SecretKey) -> Result<(), Error> {
+        Ok(self.storage.put_key(key)?)
+    }
+
+    fn get_key(&self) -> Result<SecretKey, Error> {
+        Ok(self.storage.get_key()?.secret_key)
+    }
+}
+
+/// Parameters of the Argon2id key derivation.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Argon2Params {
+    /// Memory size, in KiB.
+    pub m_cost: u32,
+    /// Number of iterations.
+    pub t_cost: u32,
+    /// Degree of parallelism.
+    pub p_cost: u32,
+}
+
+impl Argon2Params {
+    /// Parameters suitable for protecting long-lived keys.
+    pub fn recommended() -> Self {
+        Self {
+            m_cost: 64 * 1024,
+            t_cost: 3,
+            p_cost: 1,
+        }
+    }
+
+    /// Parameters which make key derivation fast, and **weak**.
+    ///
+    /// # Warning
+    ///
+    /// Only intended for testing.
+    pub fn insecure() -> Self {
+        Self {
+            m_cost: 8,
+            t_cost: 1,
+            p_cost: 1,
+        }
+    }
+
+    /// Ensure the parameters don't exceed [`MAX_M_COST`], [`MAX_T_COST`] and
+    /// [`MAX_P_COST`].
+    fn check(&self) -> Result<(), Error> {
+        if self.m_cost > MAX_M_COST || self.t_cost > MAX_T_COST || self.p_cost > MAX_P_COST {
+            return Err(Error::Kdf(format!(
+                "parameters exceed the limits m_cost={}, t_cost={}, p_cost={}",
+                MAX_M_COST, MAX_T_COST, MAX_P_COST
+            )));
+        }
+        Ok(())
+    }
+
+    fn derive(&self, passphrase: &[u8], salt: &[u8]) -> Result<Zeroizing<[u8; 32]>, Error> {
+        let params = argon2::Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(|e| Error::Kdf(e.to_string()))?;
+        let mut key = Zeroizing::new([0; 32]);
+        argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+            .hash_password_into(passphrase, salt, key.as_mut())
+            .map_err(|e| Error::Kdf(e.to_string()))?;
+        Ok(key)
+    }
+}
+
+/// The on-disk format of an [`EncryptedFile`].
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Sealed {
+    version: u32,
+    /// Stored in the clear, so the key can be identified without unlocking it.
+    /// Also authenticated as associated data.
+    peer_id: PeerId,
+    kdf: Argon2Params,
+    #[serde(with = "b64")]
+    salt: Vec<u8>,
+    #[serde(with = "b64")]
+    nonce: Vec<u8>,
+    #[serde(with = "b64")]
+    ciphertext: Vec<u8>,
+}
+
+/// A key file encrypted with XChaCha20-Poly1305, using a key derived from a
+/// passphrase via Argon2id.
+pub struct EncryptedFile<P> {
+    path: PathBuf,
+    pinentry: P,
+    params: Argon2Params,
+}
+
+impl<P> EncryptedFile<P> {
+    /// Create an [`EncryptedFile`] at `path`.
+    ///
+    /// `params` only apply when storing a key, reading a key uses the
+    /// parameters it was stored with.
+    pub fn new(path: impl Into<PathBuf>, pinentry: P, params: Argon2Params) -> Self {
+        Self {
+            path: path.into(),
+            pinentry,
+            params,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The [`PeerId`] of the stored key, which is readable without the
+    /// passphrase.
+    pub fn peer_id(&self) -> Result<PeerId, Error> {
+        Ok(self.read()?.peer_id)
+    }
+
+    fn read(&self) -> Result<Sealed, Error> {
+        let json = match fs::read(&self.path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(Error::NotFound(self.location()))
+            },
+            Err(e) => return Err(e.into()),
+        };
+        let sealed: Sealed = serde_json::from_slice(&json)?;
+        if sealed.version != VERSION {
+            return Err(Error::Version(sealed.version));
+        }
+        sealed.kdf.check()?;
+        Ok(sealed)
+    }
+
+    fn location(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+impl<P> KeyStore for EncryptedFile<P>
+where
+    P: Pinentry,
+    P::Error: std::error::Error + Send + Sync + 'static,
+{
+    fn location(&self) -> String {
+        EncryptedFile::location(self)
+    }
+
+    fn put_key(&mut self, key: SecretKey) -> Result<(), Error> {
+        let passphrase = self
+            .pinentry
+            .get_passphrase()
+            .map_err(|e| Error::Passphrase(Box::new(e)))?;
+        let mut salt = vec![0; SALT_BYTES];
+        let mut nonce = XNonce::default();
+        rand::thread_rng().fill_bytes(&mut salt);
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let peer_id = PeerId::from(key.clone());
+        let aad = peer_id.to_string();
+        let derived = self
+            .params
+            .derive(passphrase.unsecure().as_bytes(), &salt)?;
+        let ciphertext = XChaCha20Poly1305::new(Key::from_slice(derived.as_ref()))
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: key.as_ref(),
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| Error::Encrypt)?;
+
+        let sealed = Sealed {
+            version: VERSION,
+            peer_id,
+            kdf: self.params,
+            salt,
+            nonce: nonce.to_vec(),
+            ciphertext,
+        };
+        let json = serde_json::to_vec(&sealed)?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = match create_new(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(Error::Exists(self.location()))
+            },
+            Err(e) => return Err(e.into()),
+        };
+        file.write_all(&json)
+            .and_then(|()| file.sync_all())
+            .map_err(|e| {
+                fs::remove_file(&self.path).ok();
+                e.into()
+            })
+    }
+
+    fn get_key(&self) -> Result<SecretKey, Error> {
+        let sealed = self.read()?;
+        if sealed.nonce.len() != XNonce::default().len() {
+            return Err(Error::Decrypt);
+        }
+
+        let passphrase = self
+            .pinentry
+            .get_passphrase()
+            .map_err(|e| Error::Passphrase(Box::new(e)))?;
+        let aad = sealed.peer_id.to_string();
+        let derived = sealed
+            .kdf
+            .derive(passphrase.unsecure().as_bytes(), &sealed.salt)?;
+        let plain = XChaCha20Poly1305::new(Key::from_slice(derived.as_ref()))
+            .decrypt(
+                XNonce::from_slice(&sealed.nonce),
+                Payload {
+                    msg: &sealed.ciphertext,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| Error::Decrypt)?;
+        let key = SecretKey::from_bytes_and_meta(SecStr::new(plain), &())?;
+        let actual = PeerId::from(key.clone());
+        if actual != sealed.peer_id {
+            return Err(Error::Mismatch {
+                stored: sealed.peer_id,
+                actual,
+            });
+        }
+
+        Ok(key)
+    }
+}
+
+/// Create the file at `path`, readable and writable only by the current user,
+/// failing if it already exists.
+fn create_new(path: &Path) -> io::Result<fs::File> {
+    let mut opts = fs::OpenOptions::new();
+    opts.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt as _;
+        opts.mode(0o600);
+    }
+    opts.open(path)
+}
+
+/// A key stored in the keychain of the operating system, under the service
+/// [`KEYCHAIN_SERVICE`].
+#[cfg(feature = "keychain")]
+pub struct Keychain {
+    account: String,
+}
+
+#[cfg(feature = "keychain")]
+impl Keychain {
+    /// Access the key stored for `account`, typically the [`Profile`] id.
+    pub fn new(account: impl Into<String>) -> Self {
+        Self {
+            account: account.into(),
+        }
+    }
+
+    fn entry(&self) -> keyring::Entry {
+        keyring::Entry::new(KEYCHAIN_SERVICE, &self.account)
+    }
+}
+
+/// The content of a [`Keychain`] entry.
+#[cfg(feature = "keychain")]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeychainEntry {
+    /// The [`PeerId`] of `key`, checked when the key is retrieved.
+    peer_id: PeerId,
+    #[serde(with = "b64")]
+    key: Vec<u8>,
+}
+
+#[cfg(feature = "keychain")]
+impl KeyStore for Keychain {
+    fn location(&self) -> String {
+        format!("keychain {}/{}", KEYCHAIN_SERVICE, self.account)
+    }
+
+    fn put_key(&mut self, key: SecretKey) -> Result<(), Error> {
+        let entry = self.entry();
+        match entry.get_password() {
+            Ok(_) => return Err(Error::Exists(self.location())),
+            Err(keyring::Error::NoEntry) => {},
+            Err(e) => return Err(e.into()),
+        }
+        let mut stored = KeychainEntry {
+            peer_id: PeerId::from(key.clone()),
+            key: key.as_ref().to_vec(),
+        };
+        let encoded = serde_json::to_string(&stored).map(Zeroizing::new);
+        zeroize::Zeroize::zeroize(&mut stored.key);
+        Ok(entry.set_password(&encoded?)?)
+    }
+
+    fn get_key(&self) -> Result<SecretKey, Error> {
+        let encoded = match self.entry().get_password() {
+            Ok(encoded) => Zeroizing::new(encoded),
+            Err(keyring::Error::NoEntry) => return Err(Error::NotFound(self.location())),
+            Err(e) => return Err(e.into()),
+        };
+        let stored: KeychainEntry = serde_json::from_str(&encoded).map_err(|_| Error::Decrypt)?;
+        let key = SecretKey::from_bytes_and_meta(SecStr::new(stored.key), &())?;
+        let actual = PeerId::from(key.clone());
+        if actual != stored.peer_id {
+            return Err(Error::Mismatch {
+                stored: stored.peer_id,
+                actual,
+            });
+        }
+
+        Ok(key)
+    }
+}
+
+mod b64 {
+    use serde::{de::Error as _, Deserialize as _, Deserializer, Serializer};
+
+    pub fn serialize<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&base64::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        base64::decode(s).map_err(D::Error::custom)
+    }
+}
@@ -19,7 +19,11 @@ use librad::{
     profile::{LnkHome, Profile, ProfileId},
     Signer as _,
 };
-use lnk_clib::keys::{file_storage, ssh};
+use lnk_clib::keys::{
+    file_storage,
+    ssh,
+    store::{self, Argon2Params, EncryptedFile, KeyStore as _, Passphrase},
+};
 use test_helpers::logging;
 
 #[test]
@@ -49,6 +53,37 @@ fn agent_signature() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn agent_only_signature() -> anyhow::Result<()> {
+    logging::init();
+
+    let temp = tempdir()?;
+    let pass = Pwhash::new(SecUtf8::from(b"42".to_vec()), *KDF_PARAMS_TEST);
+    let home = LnkHome::Root(temp.path().to_path_buf());
+    let profile = Profile::from_home(&home, Some(ProfileId::new()))?;
+    let key = SecretKey::new();
+    let peer_id = librad::PeerId::from(key.clone());
+    let mut key_store = file_storage(&profile, pass.clone());
+    key_store.put_key(key)?;
+
+    let (sig, signer) = with_ssh_agent(|sock| {
+        // Once in the agent, the key is not needed anywhere else
+        ssh::add_signer(&profile, sock.clone(), pass, Vec::new())?;
+        drop(temp);
+        let signer = ssh::agent_signer(sock, None, Some(ssh::SIGN_TIMEOUT))?;
+        let sig = signer.sign_blocking(b"secret message")?;
+        Ok((sig, signer))
+    })?;
+
+    // The public key is cached, the agent is gone by now
+    assert_eq!(signer.peer_id(), peer_id);
+    assert!(peer_id
+        .as_public_key()
+        .verify(&sig.into(), b"secret message"));
+
+    Ok(())
+}
+
 #[test]
 fn async_agent_signature() -> anyhow::Result<()> {
     // This test reproduces an issue which caused the SshSigner to block the tokio
@@ -117,3 +152,44 @@ fn async_agent_signature() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn encrypted_file() -> anyhow::Result<()> {
+    let temp = tempdir()?;
+    let path = temp.path().join(store::ENCRYPTED_KEY_FILE);
+    let pass = |p: &str| Passphrase::new(SecUtf8::from(p));
+    let key = SecretKey::new();
+
+    let mut key_store = EncryptedFile::new(&path, pass("42"), Argon2Params::insecure());
+    key_store.put_key(key.clone())?;
+    assert!(matches!(
+        key_store.put_key(SecretKey::new()),
+        Err(store::Error::Exists(_))
+    ));
+    assert_eq!(key_store.get_key()?.public(), key.public());
+
+    let locked = EncryptedFile::new(&path, pass("43"), Argon2Params::insecure());
+    assert_eq!(locked.peer_id()?, librad::PeerId::from(key));
+    assert!(matches!(locked.get_key(), Err(store::Error::Decrypt)));
+
+    Ok(())
+}
+
+#[test]
+fn encrypted_file_bounds_kdf() -> anyhow::Result<()> {
+    let temp = tempdir()?;
+    let path = temp.path().join(store::ENCRYPTED_KEY_FILE);
+    let pass = Passphrase::new(SecUtf8::from("42"));
+
+    let mut key_store = EncryptedFile::new(&path, pass, Argon2Params::insecure());
+    key_store.put_key(SecretKey::new())?;
+
+    let sealed = std::fs::read_to_string(&path)?;
+    std::fs::write(
+        &path,
+        sealed.replace("\"mCost\":8,", "\"mCost\":4294967295,"),
+    )?;
+    assert!(matches!(key_store.get_key(), Err(store::Error::Kdf(_))));
+
+    Ok(())
+}
@@ -22,6 +22,7 @@ pub mod rad_refs;
 pub mod refs;
 pub mod tracking;
 pub mod working_copy_dir;
+pub mod workspace;
 
 pub mod display;
 mod field;
@@ -28,6 +28,7 @@ use crate::{
     display,
     git::{self, checkout, include},
     working_copy_dir::WorkingCopyDir,
+    workspace,
 };
 
 pub type Display = display::Display<PersonPayload>;
@@ -54,6 +55,9 @@ pub enum Error {
 
     #[error(transparent)]
     Relations(#[from] relations::Error),
+
+    #[error(transparent)]
+    Workspace(#[from] workspace::Error),
 }
 
 pub enum Creation {
@@ -89,21 +93,26 @@ where
         signer,
     };
 
-    match creation {
+    let maybe_repo = match creation {
         Creation::New { path } => {
             if let Some(path) = path {
                 let valid = git::new::New::new(payload.clone(), path).validate()?;
-                valid.init(url, settings)?;
-            };
+                Some(valid.init(url, settings)?)
+            } else {
+                None
+            }
         },
         Creation::Existing { path } => {
             let valid = git::existing::Existing::new(payload.clone(), path).validate()?;
-            valid.init(url, settings)?;
+            Some(valid.init(url, settings)?)
         },
-    }
+    };
 
     let person = person::create(storage, payload, direct)?;
     include::update(&storage, &paths, &person)?;
+    if let Some(repo) = maybe_repo {
+        workspace::register(&paths, person.urn(), &repo)?;
+    }
 
     Ok(person)
 }
@@ -202,6 +211,7 @@ where
     };
     let repo = git::checkout::checkout(&paths, settings, storage, &person, from)?;
     include::update(&storage, &paths, &person)?;
+    workspace::register(&paths, person.urn(), &repo)?;
     Ok(repo)
 }
 
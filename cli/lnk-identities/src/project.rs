@@ -32,6 +32,7 @@ use crate::{
     display,
     git::{self, checkout, include},
     working_copy_dir::WorkingCopyDir,
+    workspace,
     MissingDefaultIdentity,
 };
 
@@ -62,6 +63,9 @@ pub enum Error {
 
     #[error(transparent)]
     Relations(Box<relations::Error>),
+
+    #[error(transparent)]
+    Workspace(#[from] workspace::Error),
 }
 
 impl From<relations::Error> for Error {
@@ -157,6 +161,7 @@ where
     let include_path = include::update(storage, &paths, &project)?;
     if let Some(repo) = maybe_repo {
         librad::git::include::set_include_path(&repo, include_path)?;
+        workspace::register(&paths, project.urn(), &repo)?;
     }
 
     Ok(project)
@@ -272,6 +277,7 @@ where
         signer,
     };
     let repo = git::checkout::checkout(&paths, settings, storage, &project, from)?;
+    workspace::register(&paths, project.urn(), &repo)?;
     Ok(repo)
 }
 
@@ -0,0 +1,285 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Registry of the working copies of identities in storage.
+//!
+//! Whenever a working copy is created or checked out, the association between
+//! the identity's [`Urn`] and the path of the working copy is recorded in the
+//! file found at [`Paths::working_copies_file`]. As working copies may be
+//! moved, deleted, or reconfigured without us noticing, associations can be
+//! [`Registry::validate`]d and [`Registry::repair`]ed.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use librad::{
+    git::{
+        local::url::LocalUrl,
+        storage::{self, ReadOnly, ReadOnlyStorage as _},
+        types::{remote::Remote, Force, Refspec},
+        Urn,
+    },
+    git_ext,
+    paths::Paths,
+    refspec_pattern,
+};
+
+use crate::git::validation;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to read the working copy registry at `{path}`")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("failed to write the working copy registry at `{path}`")]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("malformed working copy registry at `{path}`")]
+    Malformed {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Storage(#[from] storage::Error),
+
+    #[error(transparent)]
+    Validation(#[from] validation::Error),
+}
+
+/// The state of an association between an identity and a working copy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// The working copy exists, and its `rad` remote points to the identity.
+    Intact,
+    /// Nothing exists at the path of the working copy.
+    Missing,
+    /// The path exists, but is not a git repository.
+    NotARepository,
+    /// The identity no longer exists in storage.
+    UnknownUrn,
+    /// The working copy does not have a `rad` remote.
+    MissingRemote,
+    /// The `rad` remote of the working copy points to a different identity.
+    RemoteMismatch { found: LocalUrl },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Association {
+    pub urn: Urn,
+    pub path: PathBuf,
+    pub status: Status,
+}
+
+/// The outcome of [`Registry::repair`].
+#[derive(Clone, Debug, Default)]
+pub struct Repaired {
+    /// Associations whose `rad` remote was re-created.
+    pub restored: Vec<Association>,
+    /// Associations which were dropped, as they can't be repaired.
+    pub removed: Vec<Association>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Registry {
+    file: PathBuf,
+    entries: BTreeMap<Urn, BTreeSet<PathBuf>>,
+}
+
+impl Registry {
+    /// Load the registry from [`Paths::working_copies_file`].
+    ///
+    /// If the file does not exist yet, the registry is empty.
+    pub fn load(paths: &Paths) -> Result<Self, Error> {
+        let file = paths.working_copies_file().to_path_buf();
+        let entries = match fs::read(&file) {
+            Ok(json) => serde_json::from_slice(&json).map_err(|source| Error::Malformed {
+                path: file.clone(),
+                source,
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(source) => return Err(Error::Read { path: file, source }),
+        };
+
+        Ok(Self { file, entries })
+    }
+
+    /// Persist the registry.
+    ///
+    /// The file is replaced atomically, so concurrent readers never observe a
+    /// partially written registry. Concurrent writers may, however, overwrite
+    /// each other's changes.
+    pub fn save(&self) -> Result<(), Error> {
+        let write_err = |source| Error::Write {
+            path: self.file.clone(),
+            source,
+        };
+        let json = serde_json::to_vec_pretty(&self.entries).map_err(|source| Error::Malformed {
+            path: self.file.clone(),
+            source,
+        })?;
+        let tmp = self.file.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(write_err)?;
+        fs::rename(&tmp, &self.file).map_err(write_err)
+    }
+
+    /// Associate `path` with `urn`.
+    ///
+    /// Returns `false` if the association already existed.
+    pub fn register(&mut self, urn: Urn, path: impl AsRef<Path>) -> bool {
+        self.entries
+            .entry(urn)
+            .or_default()
+            .insert(normalise(path.as_ref()))
+    }
+
+    /// Remove the association of `path` with `urn`.
+    ///
+    /// Returns `false` if there was no such association.
+    pub fn unregister(&mut self, urn: &Urn, path: impl AsRef<Path>) -> bool {
+        let path = normalise(path.as_ref());
+        match self.entries.get_mut(urn) {
+            None => false,
+            Some(paths) => {
+                let removed = paths.remove(&path);
+                if paths.is_empty() {
+                    self.entries.remove(urn);
+                }
+                removed
+            },
+        }
+    }
+
+    /// All associations, ordered by [`Urn`].
+    pub fn list(&self) -> impl Iterator<Item = (&Urn, &Path)> + '_ {
+        self.entries
+            .iter()
+            .flat_map(|(urn, paths)| paths.iter().map(move |path| (urn, path.as_path())))
+    }
+
+    /// The working copies of `urn`.
+    pub fn working_copies(&self, urn: &Urn) -> impl Iterator<Item = &Path> + '_ {
+        self.entries
+            .get(urn)
+            .into_iter()
+            .flat_map(|paths| paths.iter().map(PathBuf::as_path))
+    }
+
+    /// Determine the [`Status`] of all associations.
+    pub fn validate<S>(&self, storage: &S) -> Result<Vec<Association>, Error>
+    where
+        S: AsRef<ReadOnly>,
+    {
+        self.list()
+            .map(|(urn, path)| {
+                Ok(Association {
+                    urn: urn.clone(),
+                    path: path.to_path_buf(),
+                    status: status(storage.as_ref(), urn, path)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Repair all associations which are not [`Status::Intact`].
+    ///
+    /// A missing `rad` remote is re-created, all other defects can't be
+    /// repaired without user intervention, so the respective associations are
+    /// removed. The registry is [`Registry::save`]d afterwards.
+    pub fn repair<S>(&mut self, storage: &S) -> Result<Repaired, Error>
+    where
+        S: AsRef<ReadOnly>,
+    {
+        let mut repaired = Repaired::default();
+        for assoc in self.validate(storage)? {
+            match assoc.status {
+                Status::Intact => {},
+                Status::MissingRemote => {
+                    let repo = git2::Repository::open(&assoc.path)?;
+                    let mut rad = Remote::rad_remote(
+                        LocalUrl::from(assoc.urn.clone()),
+                        Refspec {
+                            src: refspec_pattern!("refs/heads/*"),
+                            dst: refspec_pattern!("refs/remotes/rad/*"),
+                            force: Force::True,
+                        },
+                    );
+                    rad.save(&repo)?;
+                    tracing::info!(
+                        urn = %assoc.urn,
+                        path = %assoc.path.display(),
+                        "restored rad remote"
+                    );
+                    repaired.restored.push(assoc);
+                },
+                _ => {
+                    self.unregister(&assoc.urn, &assoc.path);
+                    tracing::info!(
+                        urn = %assoc.urn,
+                        path = %assoc.path.display(),
+                        status = ?assoc.status,
+                        "removed working copy"
+                    );
+                    repaired.removed.push(assoc);
+                },
+            }
+        }
+        self.save()?;
+
+        Ok(repaired)
+    }
+}
+
+/// Record `repo` as a working copy of `urn`.
+pub fn register(paths: &Paths, urn: Urn, repo: &git2::Repository) -> Result<(), Error> {
+    let mut registry = Registry::load(paths)?;
+    if registry.register(urn, repo.workdir().unwrap_or_else(|| repo.path())) {
+        registry.save()?;
+    }
+    Ok(())
+}
+
+fn status(storage: &ReadOnly, urn: &Urn, path: &Path) -> Result<Status, Error> {
+    if !path.exists() {
+        return Ok(Status::Missing);
+    }
+    let repo = match git2::Repository::open(path) {
+        Ok(repo) => repo,
+        Err(e) if git_ext::is_not_found_err(&e) => return Ok(Status::NotARepository),
+        Err(e) => return Err(e.into()),
+    };
+    if !storage.has_urn(urn)? {
+        return Ok(Status::UnknownUrn);
+    }
+
+    match validation::remote(&repo, &LocalUrl::from(urn.clone())) {
+        Ok(Some(_)) => Ok(Status::Intact),
+        Ok(None) => Ok(Status::MissingRemote),
+        Err(validation::Error::UrlMismatch { found, .. }) => Ok(Status::RemoteMismatch { found }),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn normalise(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
@@ -0,0 +1,75 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::fs;
+
+use either::Either;
+use tempfile::tempdir;
+
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{
+    crypto::SecretKey,
+    git::{local::transport, Storage},
+};
+use lnk_identities::{
+    git::checkout::{checkout, Local},
+    workspace::{self, Registry, Status},
+};
+
+#[test]
+fn validate_and_repair() -> anyhow::Result<()> {
+    let temp = tempdir()?;
+    let paths = tmp::paths();
+    let signer = SecretKey::new();
+    let storage = Storage::open(&*paths, signer.clone())?;
+    let proj = TestProject::create(&storage)?;
+    let urn = proj.project.urn();
+    let settings = transport::Settings {
+        paths: paths.clone(),
+        signer: signer.into(),
+    };
+
+    let local = Local::new(&proj.project, temp.path().to_path_buf());
+    let repo = checkout(
+        &paths,
+        settings,
+        &storage,
+        &proj.project,
+        Either::Left(local),
+    )?;
+    workspace::register(&paths, urn.clone(), &repo)?;
+
+    let path = temp.path().canonicalize()?;
+    let mut registry = Registry::load(&paths)?;
+    assert_eq!(
+        registry.list().collect::<Vec<_>>(),
+        vec![(&urn, path.as_path())]
+    );
+    assert_eq!(registry.validate(&storage)?[0].status, Status::Intact);
+
+    // A missing remote is restored
+    repo.remote_delete("rad")?;
+    assert_eq!(
+        registry.validate(&storage)?[0].status,
+        Status::MissingRemote
+    );
+    let repaired = registry.repair(&storage)?;
+    assert_eq!(repaired.restored.len(), 1);
+    assert!(repaired.removed.is_empty());
+    assert_eq!(
+        Registry::load(&paths)?.validate(&storage)?[0].status,
+        Status::Intact
+    );
+
+    // A deleted working copy is dropped
+    drop(repo);
+    fs::remove_dir_all(&path)?;
+    assert_eq!(registry.validate(&storage)?[0].status, Status::Missing);
+    let repaired = registry.repair(&storage)?;
+    assert_eq!(repaired.removed.len(), 1);
+    assert_eq!(Registry::load(&paths)?.list().count(), 0);
+
+    Ok(())
+}
@@ -4,9 +4,11 @@
 // Linking Exception. For full terms see the included LICENSE file.
 
 use super::{
+    history::EntryId,
     AuthorizingIdentity,
     Change,
     CollaborativeObject,
+    History,
     IdentityStorage,
     ObjectId,
     TypeName,
@@ -38,6 +40,9 @@ pub(super) struct ChangeGraph<'a> {
     object_id: ObjectId,
     authorizing_identity: &'a dyn AuthorizingIdentity,
     graph: petgraph::Graph<Change, ()>,
+    /// Edges from changes in the graph to known parents which were not loaded,
+    /// see [`ChangeGraph::load_since`].
+    anchors: HashMap<git2::Oid, Vec<git2::Oid>>,
 }
 
 impl<'a> ChangeGraph<'a> {
@@ -50,6 +55,30 @@ impl<'a> ChangeGraph<'a> {
         authorizing_identity: &'c dyn AuthorizingIdentity,
         typename: &TypeName,
         oid: &ObjectId,
+    ) -> Result<Option<ChangeGraph<'c>>, Error> {
+        Self::load_since(
+            tip_refs,
+            repo,
+            authorizing_identity,
+            typename,
+            oid,
+            &BTreeSet::new(),
+        )
+    }
+
+    /// Like [`ChangeGraph::load`], but stop walking at the commits in `known`.
+    ///
+    /// The resulting graph contains only the changes which are not in `known`,
+    /// and records which of them are children of changes in `known`. If there
+    /// are no such changes, `None` is returned.
+    #[tracing::instrument(skip(repo, tip_refs, authorizing_identity, known))]
+    pub(super) fn load_since<'b, 'c>(
+        tip_refs: impl Iterator<Item = &'b git2::Reference<'b>>,
+        repo: &'c git2::Repository,
+        authorizing_identity: &'c dyn AuthorizingIdentity,
+        typename: &TypeName,
+        oid: &ObjectId,
+        known: &BTreeSet<git2::Oid>,
     ) -> Result<Option<ChangeGraph<'c>>, Error> {
         let mut builder = GraphBuilder::default();
         let mut edges_to_process: Vec<(git2::Commit, git2::Oid)> = Vec::new();
@@ -60,6 +89,9 @@ impl<'a> ChangeGraph<'a> {
         // Populate the initial set of edges_to_process from the refs we have
         for reference in tip_refs {
             let commit = reference.peel_to_commit()?;
+            if known.contains(&commit.id()) {
+                continue;
+            }
             match Change::load(repo, &commit) {
                 Ok(change) => {
                     let new_edges = builder.add_change(commit, change);
@@ -73,6 +105,10 @@ impl<'a> ChangeGraph<'a> {
 
         // Process edges until we have no more to process
         while let Some((parent_commit, child_commit_id)) = edges_to_process.pop() {
+            if known.contains(&parent_commit.id()) {
+                builder.add_anchor(child_commit_id, parent_commit.id());
+                continue;
+            }
             tracing::trace!(?parent_commit, ?child_commit_id, "loading change");
             match Change::load(repo, &parent_commit) {
                 Ok(change) => {
@@ -129,6 +165,40 @@ impl<'a> ChangeGraph<'a> {
         }
     }
 
+    /// Evaluate the changes in this graph on top of `base`, the already
+    /// verified history of the changes this graph was loaded since.
+    ///
+    /// See [`ChangeGraph::load_since`].
+    pub(super) fn evaluate_onto<I: IdentityStorage>(
+        &self,
+        identities: &I,
+        base: &History,
+    ) -> History {
+        let evaluating =
+            evaluation::Evaluating::new(identities, self.authorizing_identity, self.repo);
+        let topo = Topo::new(&self.graph);
+        let items = topo.iter(&self.graph).map(|idx| {
+            let node = &self.graph[idx];
+            let outgoing_edges = self.graph.edges_directed(idx, EdgeDirection::Outgoing);
+            let child_commits: Vec<git2::Oid> = outgoing_edges
+                .map(|e| *self.graph[e.target()].commit())
+                .collect();
+            (node, child_commits)
+        });
+        let entries = evaluating.entries(items);
+        let anchors = self
+            .anchors
+            .iter()
+            .map(|(child, parents)| {
+                (
+                    EntryId::from(*child),
+                    parents.iter().copied().map(EntryId::from).collect(),
+                )
+            })
+            .collect();
+        base.graft(entries, &anchors)
+    }
+
     /// Get the tips of the collaborative object
     pub(super) fn tips(&self) -> BTreeSet<git2::Oid> {
         self.graph
@@ -153,6 +223,7 @@ impl<'a> ChangeGraph<'a> {
 struct GraphBuilder {
     node_indices: HashMap<git2::Oid, petgraph::graph::NodeIndex<u32>>,
     graph: petgraph::Graph<Change, ()>,
+    anchors: HashMap<git2::Oid, Vec<git2::Oid>>,
 }
 
 impl Default for GraphBuilder {
@@ -160,6 +231,7 @@ impl Default for GraphBuilder {
         GraphBuilder {
             node_indices: HashMap::new(),
             graph: petgraph::graph::Graph::new(),
+            anchors: HashMap::new(),
         }
     }
 }
@@ -211,6 +283,14 @@ impl GraphBuilder {
         self.graph.update_edge(*parent_id, *child_id, ());
     }
 
+    /// Record that `child` is a child of the known change `parent`
+    fn add_anchor(&mut self, child: git2::Oid, parent: git2::Oid) {
+        let parents = self.anchors.entry(child).or_default();
+        if !parents.contains(&parent) {
+            parents.push(parent);
+        }
+    }
+
     fn build<'b>(
         self,
         repo: &'b git2::Repository,
@@ -228,6 +308,7 @@ impl GraphBuilder {
                 object_id,
                 authorizing_identity,
                 graph: self.graph,
+                anchors: self.anchors,
             }))
         } else {
             Ok(None)
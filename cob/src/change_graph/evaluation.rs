@@ -38,11 +38,22 @@ impl<'a, I: IdentityStorage> Evaluating<'a, I> {
     ///
     /// If the change corresponding to the root OID is not in `items`
     pub fn evaluate<'b, It: Iterator<Item = (&'b Change, Vec<git2::Oid>)>>(
-        mut self,
+        self,
         root: git2::Oid,
         items: It,
     ) -> history::History {
-        let entries = pruning_fold::pruning_fold(
+        let entries = self.entries(items);
+        // SAFETY: The caller must guarantee that `root` is in `items`
+        history::History::new(root, entries).unwrap()
+    }
+
+    /// Evaluate `items`, which must be in topological order, returning the
+    /// entries for the accepted changes.
+    pub fn entries<'b, It: Iterator<Item = (&'b Change, Vec<git2::Oid>)>>(
+        mut self,
+        items: It,
+    ) -> HashMap<history::EntryId, history::HistoryEntry> {
+        pruning_fold::pruning_fold(
             HashMap::new(),
             items.map(|(change, children)| ChangeWithChildren {
                 change,
@@ -59,9 +70,7 @@ impl<'a, I: IdentityStorage> Evaluating<'a, I> {
                     ControlFlow::Continue(entries)
                 },
             },
-        );
-        // SAFETY: The caller must guarantee that `root` is in `items`
-        history::History::new(root, entries).unwrap()
+        )
     }
 
     fn evaluate_change(
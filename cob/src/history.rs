@@ -124,6 +124,53 @@ impl History {
         }
     }
 
+    /// The number of entries in this history.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The ids of all entries in this history.
+    pub(crate) fn entry_ids(&self) -> impl Iterator<Item = &EntryId> {
+        self.entries.keys()
+    }
+
+    /// Extend this history with `entries`, which were evaluated on top of it.
+    ///
+    /// `anchors` maps the ids of new entries to the ids of the entries of this
+    /// history they are children of. New entries which are not reachable from
+    /// the root via `anchors` or each other are dropped.
+    pub(crate) fn graft(
+        &self,
+        entries: HashMap<EntryId, HistoryEntry>,
+        anchors: &HashMap<EntryId, Vec<EntryId>>,
+    ) -> Self {
+        let mut all = self.entries.clone();
+        for (child, parents) in anchors {
+            if !entries.contains_key(child) {
+                continue;
+            }
+            for parent in parents {
+                if let Some(parent) = all.get_mut(parent) {
+                    if !parent.children.contains(child) {
+                        parent.children.push(child.clone());
+                    }
+                }
+            }
+        }
+        all.extend(entries);
+        let NewGraph { graph, indices } = create_petgraph(&self.root, &all);
+        Self {
+            root: self.root.clone(),
+            entries: all,
+            graph,
+            indices,
+        }
+    }
+
     pub(crate) fn tips(&self) -> BTreeSet<EntryId> {
         self.graph
             .externals(petgraph::Direction::Outgoing)
@@ -205,6 +252,12 @@ impl From<git2::Oid> for EntryId {
     }
 }
 
+impl From<&EntryId> for git2::Oid {
+    fn from(id: &EntryId) -> Self {
+        id.0.into()
+    }
+}
+
 /// One entry in the dependency graph for a change
 #[derive(Clone, Debug, PartialEq, Eq, Hash, minicbor::Encode, minicbor::Decode)]
 pub struct HistoryEntry {
@@ -50,6 +50,15 @@
 //! the reference to the tips returned by the `RefsStorage` is different to
 //! those that were used to generate the cache) then we fall back to evaluating
 //! the full change graph of the object.
+//!
+//! ## Snapshots
+//!
+//! The cache is only valid for the exact tips it was computed from. For
+//! long-lived objects evaluation can additionally start from a signed
+//! snapshot of a previously evaluated history: only the changes on top of it
+//! are loaded and verified. Snapshots are written explicitly, see
+//! [`snapshot()`], and only used when signed by the key passed to the
+//! retrieval functions as trusted.
 
 use std::{cell::RefCell, collections::BTreeSet, convert::TryFrom, fmt, rc::Rc, str::FromStr};
 
@@ -84,6 +93,8 @@ pub use history::{EntryContents, History, HistoryEntry, HistoryType};
 
 mod pruning_fold;
 
+mod snapshot;
+
 pub mod internals {
     //! This module exposes implementation details of the collaborative object
     //! crate for use in testing
@@ -191,6 +202,12 @@ impl From<&git2::Oid> for ObjectId {
     }
 }
 
+impl From<ObjectId> for git2::Oid {
+    fn from(ObjectId(oid): ObjectId) -> Self {
+        oid
+    }
+}
+
 /// A collaborative object
 #[derive(Debug, Clone)]
 pub struct CollaborativeObject {
@@ -305,6 +322,14 @@ pub mod error {
         SignerIsNotAuthor,
     }
 
+    #[derive(Debug, Error)]
+    pub enum Snapshot<RefsError: std::error::Error> {
+        #[error(transparent)]
+        Retrieve(#[from] Retrieve<RefsError>),
+        #[error(transparent)]
+        Store(#[from] super::snapshot::Error),
+    }
+
     #[derive(Debug, Error)]
     pub enum ParseObjectId {
         #[error(transparent)]
@@ -417,6 +442,7 @@ pub fn retrieve<R: RefsStorage, I: IdentityStorage, P: AsRef<std::path::Path>>(
     typename: &TypeName,
     oid: &ObjectId,
     cache_dir: Option<P>,
+    trusted_snapshots: Option<&PublicKey>,
 ) -> Result<Option<CollaborativeObject>, error::Retrieve<R::Error>> {
     let tip_refs = refs_storage
         .object_references(&authorizing_identity.urn(), typename, oid)
@@ -429,7 +455,12 @@ pub fn retrieve<R: RefsStorage, I: IdentityStorage, P: AsRef<std::path::Path>>(
         typename,
         tip_refs,
     }
-    .load_or_materialize::<error::Retrieve<R::Error>, _>(identity_storage, cache.as_mut(), repo)?
+    .load_or_materialize::<error::Retrieve<R::Error>, _>(
+        identity_storage,
+        cache.as_mut(),
+        repo,
+        trusted_snapshots,
+    )?
     .map(|tg| tg.into()))
 }
 
@@ -441,6 +472,7 @@ pub fn list<R: RefsStorage, P: AsRef<std::path::Path>, I: IdentityStorage>(
     authorizing_identity: &dyn AuthorizingIdentity,
     typename: &TypeName,
     cache_dir: Option<P>,
+    trusted_snapshots: Option<&PublicKey>,
 ) -> Result<Vec<CollaborativeObject>, error::Retrieve<R::Error>> {
     let references = refs_storage
         .type_references(&authorizing_identity.urn(), typename)
@@ -460,6 +492,7 @@ pub fn list<R: RefsStorage, P: AsRef<std::path::Path>, I: IdentityStorage>(
             identity_storage,
             cache.as_mut(),
             repo,
+            trusted_snapshots,
         )?;
         match loaded {
             Some(obj) => {
@@ -540,7 +573,12 @@ pub fn update<R: RefsStorage, I: IdentityStorage, P: AsRef<std::path::Path>>(
         oid: object_id,
         tip_refs: existing_refs,
     }
-    .load_or_materialize::<error::Update<R::Error>, _>(identity_storage, cache.as_mut(), repo)?
+    .load_or_materialize::<error::Update<R::Error>, _>(
+        identity_storage,
+        cache.as_mut(),
+        repo,
+        Some(&signer.public_key().into()),
+    )?
     .ok_or(error::Update::NoSuchObject)?;
 
     let change = change::Change::create(
@@ -574,6 +612,44 @@ pub fn update<R: RefsStorage, I: IdentityStorage, P: AsRef<std::path::Path>>(
     Ok(cached.into())
 }
 
+/// Persist a snapshot of the current history of an object, signed by `signer`.
+///
+/// Subsequent retrievals trusting the key of `signer` start evaluating the
+/// object from this snapshot. Returns the snapshot commit, or `None` if there
+/// is no such object.
+#[allow(clippy::too_many_arguments)]
+pub fn snapshot<R: RefsStorage, I: IdentityStorage, P: AsRef<std::path::Path>>(
+    refs_storage: &R,
+    identity_storage: &I,
+    repo: &git2::Repository,
+    signer: &BoxedSigner,
+    authorizing_identity: &dyn AuthorizingIdentity,
+    typename: &TypeName,
+    oid: &ObjectId,
+    cache_dir: Option<P>,
+) -> Result<Option<git2::Oid>, error::Snapshot<R::Error>> {
+    let tip_refs = refs_storage
+        .object_references(&authorizing_identity.urn(), typename, oid)
+        .map_err(error::Retrieve::Refs)?;
+    let mut cache = open_cache(cache_dir).map_err(error::Retrieve::from)?;
+    let graph = CobRefs {
+        oid: *oid,
+        authorizing_identity,
+        typename,
+        tip_refs,
+    }
+    .load_or_materialize::<error::Retrieve<R::Error>, _>(
+        identity_storage,
+        cache.as_mut(),
+        repo,
+        Some(&signer.public_key().into()),
+    )?;
+    match graph {
+        Some(graph) => Ok(Some(snapshot::store(repo, signer, graph)?)),
+        None => Ok(None),
+    }
+}
+
 /// Retrieve additional information about the change graph of an object. This
 /// is mostly useful for debugging and testing
 pub fn changegraph_info_for_object<R: RefsStorage>(
@@ -631,6 +707,7 @@ impl<'a> CobRefs<'a> {
         identity_storage: &I,
         cache: &mut dyn Cache,
         repo: &git2::Repository,
+        trusted_snapshots: Option<&PublicKey>,
     ) -> Result<Option<Rc<RefCell<CachedChangeGraph>>>, E>
     where
         E: From<cache::Error>,
@@ -649,6 +726,43 @@ impl<'a> CobRefs<'a> {
             },
             None => {
                 tracing::trace!(object_id=?self.oid, ?tip_oids, "object not found in cache");
+                let snapshot = trusted_snapshots.and_then(|key| {
+                    snapshot::load(
+                        repo,
+                        &self.authorizing_identity.urn(),
+                        self.typename,
+                        &self.oid,
+                        key,
+                    )
+                });
+                if let Some(snapshot) = snapshot {
+                    tracing::trace!(object_id=?self.oid, "evaluating object from snapshot");
+                    let known = snapshot
+                        .history()
+                        .entry_ids()
+                        .map(git2::Oid::from)
+                        .collect::<BTreeSet<_>>();
+                    let history = match ChangeGraph::load_since(
+                        self.tip_refs.iter(),
+                        repo,
+                        self.authorizing_identity,
+                        self.typename,
+                        &self.oid,
+                        &known,
+                    )? {
+                        Some(graph) => graph.evaluate_onto(identity_storage, snapshot.history()),
+                        None => snapshot.history().clone(),
+                    };
+                    let cached = cache::CachedChangeGraph::new(
+                        tip_oids,
+                        history,
+                        self.typename.clone(),
+                        self.oid,
+                        self.authorizing_identity.urn(),
+                    );
+                    cache.put(self.oid, cached.clone())?;
+                    return Ok(Some(cached));
+                }
                 if let Some(graph) = ChangeGraph::load(
                     self.tip_refs.iter(),
                     repo,
@@ -0,0 +1,144 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Signed snapshots of the evaluated history of an object.
+//!
+//! Evaluating an object requires loading and verifying every change in its
+//! change graph. For long-lived objects this becomes slow, and unlike the
+//! [`crate::cache`] -- which is only valid for exactly the tips it was
+//! computed from -- a snapshot remains useful as the object grows: evaluation
+//! only walks the change graph back to the changes covered by the snapshot,
+//! verifies the changes on top of it, and grafts them onto the snapshotted
+//! history.
+//!
+//! A snapshot is a commit without parents whose tree contains a CBOR encoded
+//! [`CachedChangeGraph`], and whose message carries a signature over the tree
+//! as in change commits. It is stored at
+//! `refs/rad/cache/cob-snapshots/<identity>/<typename>/<object id>`, outside of
+//! any namespace, so it is never served to other peers. Snapshots are only
+//! used if they are signed by a trusted key, typically that of the local
+//! peer, and otherwise ignored in favour of the full history.
+
+use std::{cell::RefCell, convert::TryFrom, rc::Rc};
+
+use link_crypto::{BoxedSigner, PublicKey};
+use link_identities::{git::Urn, sign::Signatures};
+use minicbor::Decode as _;
+use thiserror::Error;
+
+use super::{cache::CachedChangeGraph, ObjectId, TypeName};
+
+const SNAPSHOT_BLOB_NAME: &str = "snapshot";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Encode(#[from] minicbor::encode::Error<std::io::Error>),
+    #[error(transparent)]
+    Sign(#[from] link_crypto::BoxedSignError),
+}
+
+fn refname(urn: &Urn, typename: &TypeName, oid: &ObjectId) -> String {
+    format!(
+        "refs/rad/cache/cob-snapshots/{}/{}/{}",
+        urn.encode_id(),
+        typename,
+        oid
+    )
+}
+
+/// Load the snapshot of `oid`, if there is one signed by `trusted`.
+///
+/// Snapshots which can't be read or verified are treated as absent.
+pub(crate) fn load(
+    repo: &git2::Repository,
+    urn: &Urn,
+    typename: &TypeName,
+    oid: &ObjectId,
+    trusted: &PublicKey,
+) -> Option<CachedChangeGraph> {
+    let commit = repo
+        .find_reference(&refname(urn, typename, oid))
+        .and_then(|r| r.peel_to_commit())
+        .ok()?;
+    let signed = match Signatures::try_from(&commit) {
+        Ok(sigs) => sigs
+            .iter()
+            .any(|(key, sig)| key == trusted && key.verify(sig, commit.tree_id().as_bytes())),
+        Err(e) => {
+            tracing::warn!(object_id=?oid, err=?e, "ignoring unsigned snapshot");
+            return None;
+        },
+    };
+    if !signed {
+        tracing::warn!(object_id=?oid, "ignoring snapshot not signed by a trusted key");
+        return None;
+    }
+
+    let blob = commit
+        .tree()
+        .ok()?
+        .get_name(SNAPSHOT_BLOB_NAME)?
+        .to_object(repo)
+        .and_then(|o| o.peel_to_blob())
+        .ok()?;
+    match CachedChangeGraph::decode(&mut minicbor::Decoder::new(blob.content())) {
+        Ok(graph)
+            if graph.object_id() == *oid
+                && graph.typename() == typename
+                && graph.authorizing_identity_urn() == urn =>
+        {
+            Some(graph)
+        },
+        Ok(_) => {
+            tracing::warn!(object_id=?oid, "ignoring snapshot of a different object");
+            None
+        },
+        Err(e) => {
+            tracing::warn!(object_id=?oid, err=?e, "ignoring undecodable snapshot");
+            None
+        },
+    }
+}
+
+/// Persist `graph` as the snapshot of its object, signed by `signer`.
+pub(crate) fn store(
+    repo: &git2::Repository,
+    signer: &BoxedSigner,
+    graph: Rc<RefCell<CachedChangeGraph>>,
+) -> Result<git2::Oid, Error> {
+    let graph = graph.borrow();
+    let bytes = minicbor::to_vec(&*graph)?;
+
+    let mut tb = repo.treebuilder(None)?;
+    let blob = repo.blob(&bytes)?;
+    tb.insert(SNAPSHOT_BLOB_NAME, blob, git2::FileMode::Blob.into())?;
+    let revision = tb.write()?;
+    let tree = repo.find_tree(revision)?;
+
+    let signatures: Signatures = link_identities::git::sign(signer, revision.into())?.into();
+    let author = repo.signature()?;
+    let message = link_identities::git::sign::CommitMessage::new(
+        &format!("snapshot of {} changes", graph.history().len()),
+        &signatures,
+        std::iter::empty(),
+    )
+    .to_string();
+    let commit = repo.commit(None, &author, &author, &message, &tree, &[])?;
+
+    repo.reference(
+        &refname(
+            graph.authorizing_identity_urn(),
+            graph.typename(),
+            &graph.object_id(),
+        ),
+        commit,
+        true,
+        "snapshot",
+    )?;
+    Ok(commit)
+}
@@ -82,7 +82,7 @@ impl request_pull::Guard for AllowAll {
     fn guard(&self, peer: &PeerId, urn: &git::Urn) -> Result<Self::Output, Self::Error> {
         let storage = futures::executor::block_on(self.storage.get())?;
         let _res = tracking::track(
-            storage.as_ref(),
+            &*storage,
             urn,
             Some(*peer),
             tracking::Config::default(),
@@ -151,8 +151,17 @@ async fn main() {
                 replication: Default::default(),
                 rate_limits: Default::default(),
                 request_pull,
+                request_pull_policy: Default::default(),
+                read_access: Default::default(),
+                announcements: Default::default(),
+                review: Default::default(),
+                tcp_listen_addr: None,
+                health: Default::default(),
+                compression: Default::default(),
+                router: None,
             },
             storage: Default::default(),
+            runtime: Default::default(),
         })
         .unwrap();
         let bound = peer.bind().await.unwrap();
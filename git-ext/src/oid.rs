@@ -96,6 +96,19 @@ mod minicbor_impls {
     }
 }
 
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for Oid {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let bytes = u.bytes(20)?;
+        // SAFETY: checks the length of the slice, which we know is correct
+        Ok(Self(git2::Oid::from_bytes(bytes).unwrap()))
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (20, Some(20))
+    }
+}
+
 impl Deref for Oid {
     type Target = git2::Oid;
 
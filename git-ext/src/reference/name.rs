@@ -240,6 +240,27 @@ impl FromIterator<Self> for RefLike {
     }
 }
 
+/// Generates names of one to four path components, consisting of alphanumeric
+/// characters, `-` and `_`.
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for RefLike {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-_";
+
+        let depth = u.int_in_range(1..=4)?;
+        let mut components = Vec::with_capacity(depth);
+        for _ in 0..depth {
+            let len = u.int_in_range(1..=16)?;
+            let component = (0..len)
+                .map(|_| u.choose(ALPHABET).map(|c| char::from(*c)))
+                .collect::<arbitrary::Result<String>>()?;
+            components.push(component);
+        }
+
+        Self::try_from(components.join("/")).map_err(|_| arbitrary::Error::IncorrectFormat)
+    }
+}
+
 impl Display for RefLike {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.write_str(self.as_str())
@@ -0,0 +1,11 @@
+#![no_main]
+use bytes::BytesMut;
+use futures_codec::Decoder as _;
+use libfuzzer_sys::fuzz_target;
+use librad::net::protocol::{gossip, io::codec};
+
+fuzz_target!(|data: &[u8]| {
+    let mut codec = codec::Gossip::<gossip::Payload>::new();
+    let mut buf = BytesMut::from(data);
+    while let Ok(Some(_)) = codec.decode_eof(&mut buf) {}
+});
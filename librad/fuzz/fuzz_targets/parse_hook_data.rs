@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use link_hooks::{Data, Track};
+use radicle_git_ext::Oid;
+use std::str::FromStr;
+
+fuzz_target!(|data: &str| {
+    _ = Data::<Oid>::from_str(data);
+    _ = Track::<Oid>::from_str(data);
+});
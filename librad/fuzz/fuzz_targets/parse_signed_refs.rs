@@ -0,0 +1,13 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use librad::{
+    git::refs::{Refs, Signed},
+    PeerId,
+    SecretKey,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let signer = PeerId::from(SecretKey::from_seed([42; 32]));
+    _ = serde_json::from_slice::<Refs>(data);
+    _ = Signed::from_json(data, &signer);
+});
@@ -0,0 +1,22 @@
+#![no_main]
+use std::net::SocketAddr;
+
+use bytes::BytesMut;
+use futures_codec::{Decoder as _, Encoder as _};
+use libfuzzer_sys::fuzz_target;
+use librad::net::protocol::{broadcast, compression, gossip, io::codec};
+
+type Message = broadcast::Message<SocketAddr, gossip::Payload>;
+
+fuzz_target!(|input: (Message, bool)| {
+    let (msg, compress) = input;
+    let compression = compress.then(|| compression::Config {
+        threshold: 0,
+        ..Default::default()
+    });
+    let mut codec = codec::Gossip::with_compression(compression);
+    let mut buf = BytesMut::new();
+    codec.encode(msg.clone(), &mut buf).unwrap();
+    assert_eq!(Some(msg), codec.decode_eof(&mut buf).unwrap());
+    assert!(buf.is_empty());
+});
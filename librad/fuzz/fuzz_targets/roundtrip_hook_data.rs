@@ -0,0 +1,11 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use link_hooks::{Data, Track};
+use radicle_git_ext::Oid;
+use std::str::FromStr;
+
+fuzz_target!(|input: (Data<Oid>, Track<Oid>)| {
+    let (data, track) = input;
+    assert_eq!(data, Data::from_str(&data.to_string()).unwrap());
+    assert_eq!(track, Track::from_str(&track.to_string()).unwrap());
+});
@@ -0,0 +1,33 @@
+#![no_main]
+use std::net::SocketAddr;
+
+use bytes::BytesMut;
+use futures_codec::{Decoder as _, Encoder as _};
+use libfuzzer_sys::fuzz_target;
+use librad::net::protocol::{
+    compression,
+    io::codec,
+    membership::{Message, Priority},
+};
+
+fuzz_target!(|input: (Message<SocketAddr>, bool)| {
+    let (msg, compress) = input;
+    // `Priority::High` is encoded as nothing at all, and so can only be decoded
+    // when followed by more input. See `membership::rpc`.
+    let msg = match msg {
+        Message::Neighbour { info, .. } => Message::Neighbour {
+            info,
+            prio: Priority::Normal,
+        },
+        msg => msg,
+    };
+    let compression = compress.then(|| compression::Config {
+        threshold: 0,
+        ..Default::default()
+    });
+    let mut codec = codec::Membership::with_compression(compression);
+    let mut buf = BytesMut::new();
+    codec.encode(msg.clone(), &mut buf).unwrap();
+    assert_eq!(Some(msg), codec.decode_eof(&mut buf).unwrap());
+    assert!(buf.is_empty());
+});
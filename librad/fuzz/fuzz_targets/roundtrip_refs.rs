@@ -0,0 +1,8 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use librad::git::refs::Refs;
+
+fuzz_target!(|refs: Refs| {
+    let json = serde_json::to_vec(&refs).unwrap();
+    assert_eq!(refs, serde_json::from_slice::<Refs>(&json).unwrap());
+});
@@ -0,0 +1,468 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Backup and restore of the state of a peer.
+//!
+//! [`create`] writes the monorepo -- all namespaces, as well as the tracking
+//! configuration stored alongside them -- and the address book of the profile
+//! into a single archive. Secret keys are **not** included, and have to be
+//! transferred separately.
+//!
+//! [`restore`] re-imports an archive, eg. when migrating a seed to another
+//! machine. The identity of every namespace is verified before its refs are
+//! written, namespaces failing verification are skipped. Namespaces which
+//! already exist in the target storage are left alone. An archive can only be
+//! restored into a storage of the peer it was created by, ie. using the same
+//! secret key, as the refs owned by that peer are signed by it.
+//!
+//! # Format
+//!
+//! The archive starts with the line `# radicle-link backup`, followed by a
+//! sequence of sections, each introduced by a line `<name> <length>` and
+//! followed by `length` bytes of content:
+//!
+//! * `manifest.json`, the [`Manifest`], which always comes first
+//! * `monorepo.bundle`, a `git bundle` of all refs of the monorepo
+//! * `addresses.json`, the address book, if there is one
+//!
+//! Creating and restoring archives requires the `git` executable.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead as _, Read as _, Write as _},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+};
+
+use git_ext as ext;
+use serde::{Deserialize, Serialize};
+use std_ext::time::unix_now;
+use thiserror::Error;
+
+use crate::{
+    git::{storage::Storage, Urn},
+    identities::git::{Person, Project, SomeIdentity},
+    paths::Paths,
+    PeerId,
+};
+
+/// The version of the archive format written by [`create`].
+pub const VERSION: u32 = 1;
+
+const MAGIC: &str = "# radicle-link backup";
+const MANIFEST: &str = "manifest.json";
+const MONOREPO: &str = "monorepo.bundle";
+const ADDRESS_BOOK: &str = "addresses.json";
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("`{0}` is not a backup archive")]
+    NotAnArchive(PathBuf),
+
+    #[error("unsupported archive version {0}")]
+    Version(u32),
+
+    #[error("malformed archive: {0}")]
+    Malformed(String),
+
+    #[error("archive was created by {archive}, but the storage belongs to {storage}")]
+    PeerMismatch { archive: PeerId, storage: PeerId },
+
+    #[error("`git {command}` exited with {status}: {stderr}")]
+    Command {
+        command: &'static str,
+        status: ExitStatus,
+        stderr: String,
+    },
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// The table of contents of an archive.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub version: u32,
+    /// When the archive was created, in seconds since the epoch.
+    pub created_at: u64,
+    /// The peer whose state the archive contains.
+    pub peer_id: PeerId,
+    /// The namespaces, along with the tip of their `rad/id`.
+    pub namespaces: BTreeMap<Urn, ext::Oid>,
+    /// The symbolic refs of the monorepo, by name, and their targets.
+    ///
+    /// The bundle records them as direct refs.
+    pub symrefs: BTreeMap<String, String>,
+}
+
+/// The outcome of [`restore`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Restored {
+    /// The namespaces which were restored.
+    pub restored: Vec<Urn>,
+    /// The namespaces which already existed, and were left alone.
+    pub existing: Vec<Urn>,
+    /// The namespaces which failed verification, and why.
+    pub rejected: Vec<(Urn, String)>,
+}
+
+/// Write the monorepo of `storage` and the address book found at `paths` to
+/// an archive at `out`.
+#[tracing::instrument(level = "debug", skip(storage, paths))]
+pub fn create(storage: &Storage, paths: &Paths, out: &Path) -> Result<Manifest, Error> {
+    let repo = git2::Repository::open_bare(storage.path())?;
+    let mut namespaces = BTreeMap::new();
+    let mut symrefs = BTreeMap::new();
+    let mut has_refs = false;
+    for r in repo.references()? {
+        let r = r?;
+        let name = match r.name() {
+            Some(name) => name,
+            None => continue,
+        };
+        has_refs = true;
+        if let Some(target) = r.symbolic_target() {
+            symrefs.insert(name.to_owned(), target.to_owned());
+        }
+        if let (Some(id), Some(oid)) = (
+            name.strip_prefix("refs/namespaces/")
+                .and_then(|rest| rest.strip_suffix("/refs/rad/id")),
+            r.target(),
+        ) {
+            if let Ok(urn) = Urn::try_from_id(id) {
+                namespaces.insert(urn, ext::Oid::from(oid));
+            }
+        }
+    }
+
+    let manifest = Manifest {
+        version: VERSION,
+        created_at: unix_now(),
+        peer_id: *storage.peer_id(),
+        namespaces,
+        symrefs,
+    };
+
+    let tmp = out.with_extension("tmp");
+    let mut archive = io::BufWriter::new(fs::File::create(&tmp)?);
+    writeln!(archive, "{}", MAGIC)?;
+    write_section(
+        &mut archive,
+        MANIFEST,
+        &mut io::Cursor::new(serde_json::to_vec(&manifest)?),
+    )?;
+
+    // `git bundle` refuses to create empty bundles
+    if has_refs {
+        let bundle = tempfile::NamedTempFile::new()?;
+        git(
+            storage.path(),
+            "bundle",
+            &[
+                "bundle".as_ref(),
+                "create".as_ref(),
+                bundle.path().as_os_str(),
+                "--all".as_ref(),
+            ],
+        )?;
+        write_section(&mut archive, MONOREPO, &mut fs::File::open(bundle.path())?)?;
+    }
+
+    match fs::File::open(paths.address_book_file()) {
+        Ok(mut file) => write_section(&mut archive, ADDRESS_BOOK, &mut file)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+        Err(e) => return Err(e.into()),
+    }
+
+    archive
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    fs::rename(&tmp, out)?;
+
+    Ok(manifest)
+}
+
+/// Read the [`Manifest`] of the archive at `path`.
+pub fn manifest(path: &Path) -> Result<Manifest, Error> {
+    let mut archive = open(path)?;
+    read_manifest(&mut archive)
+}
+
+/// Restore the archive at `path` into `storage`, and the address book found at
+/// `paths`.
+///
+/// The address book is only restored if `paths` doesn't have one yet.
+///
+/// # Errors
+///
+/// [`Error::PeerMismatch`] if the archive was created by a peer other than the
+/// one of `storage`. Nothing is restored in this case.
+#[tracing::instrument(level = "debug", skip(storage, paths))]
+pub fn restore(storage: &Storage, paths: &Paths, path: &Path) -> Result<Restored, Error> {
+    let mut archive = open(path)?;
+    let manifest = read_manifest(&mut archive)?;
+    if &manifest.peer_id != storage.peer_id() {
+        return Err(Error::PeerMismatch {
+            archive: manifest.peer_id,
+            storage: *storage.peer_id(),
+        });
+    }
+
+    let mut refs = BTreeMap::new();
+    while let Some((name, len)) = read_section_header(&mut archive)? {
+        let mut content = (&mut archive).take(len);
+        match name.as_str() {
+            MONOREPO => {
+                let mut bundle = tempfile::NamedTempFile::new()?;
+                io::copy(&mut content, &mut bundle)?;
+                bundle.as_file().sync_all()?;
+                refs = unbundle(storage.path(), bundle.path())?;
+            },
+            ADDRESS_BOOK => {
+                let target = paths.address_book_file();
+                if !target.exists() {
+                    let tmp = target.with_extension("json.tmp");
+                    io::copy(&mut content, &mut fs::File::create(&tmp)?)?;
+                    fs::rename(&tmp, target)?;
+                }
+            },
+            other => {
+                tracing::warn!(section = %other, "skipping unknown section");
+            },
+        }
+        // Skip whatever wasn't consumed
+        io::copy(&mut content, &mut io::sink())?;
+    }
+
+    write_refs(storage, &manifest, refs)
+}
+
+/// Verify the namespaces found in `refs`, and write the refs of the verified
+/// ones, as well as the refs outside of any namespace.
+fn write_refs(
+    storage: &Storage,
+    manifest: &Manifest,
+    refs: BTreeMap<String, git2::Oid>,
+) -> Result<Restored, Error> {
+    let repo = git2::Repository::open_bare(storage.path())?;
+    let ids = storage.read_only().identities::<SomeIdentity>();
+
+    let mut namespaces: BTreeMap<String, Vec<(&str, git2::Oid)>> = BTreeMap::new();
+    let mut other = Vec::new();
+    for (name, oid) in &refs {
+        match name
+            .strip_prefix("refs/namespaces/")
+            .and_then(|rest| rest.split_once('/'))
+        {
+            Some((ns, _)) => namespaces
+                .entry(ns.to_owned())
+                .or_default()
+                .push((name, *oid)),
+            None if name == "HEAD" => {},
+            None => other.push((name.as_str(), *oid)),
+        }
+    }
+
+    // Namespaces may refer to delegates in the archive
+    let rad_id = |urn: &Urn| refs.get(&format!("refs/namespaces/{}/refs/rad/id", urn.encode_id()));
+
+    let mut restored = Restored::default();
+    for (ns, ns_refs) in namespaces {
+        let urn = match Urn::try_from_id(&ns) {
+            Ok(urn) => urn,
+            Err(e) => {
+                tracing::warn!(namespace = %ns, err = %e, "skipping invalid namespace");
+                continue;
+            },
+        };
+        if repo
+            .find_reference(&format!("refs/namespaces/{}/refs/rad/id", ns))
+            .is_ok()
+        {
+            restored.existing.push(urn);
+            continue;
+        }
+
+        let verified = match rad_id(&urn) {
+            None => Err("missing `rad/id`".to_owned()),
+            Some(head) => match ids.some_identity(*head) {
+                Err(e) => Err(e.to_string()),
+                Ok(identity) if identity.urn().id != urn.id => {
+                    Err(format!("`rad/id` points to {}", identity.urn()))
+                },
+                Ok(SomeIdentity::Person(_)) => ids
+                    .coerce::<Person>()
+                    .verify(*head)
+                    .map(|_| ())
+                    .map_err(|e| e.to_string()),
+                Ok(SomeIdentity::Project(_)) => ids
+                    .coerce::<Project>()
+                    .verify(*head, |delegate| {
+                        rad_id(&delegate).copied().ok_or(MissingDelegate(delegate))
+                    })
+                    .map(|_| ())
+                    .map_err(|e| e.to_string()),
+                Ok(_) => Err("unknown identity kind".to_owned()),
+            },
+        };
+        if let Err(reason) = verified {
+            tracing::warn!(%urn, %reason, "not restoring namespace");
+            restored.rejected.push((urn, reason));
+            continue;
+        }
+
+        for (name, oid) in ns_refs {
+            write_ref(&repo, manifest, name, oid)?;
+        }
+        restored.restored.push(urn);
+    }
+
+    for (name, oid) in other {
+        write_ref(&repo, manifest, name, oid)?;
+    }
+
+    Ok(restored)
+}
+
+#[derive(Debug, Error)]
+#[error("delegate {0} not found")]
+struct MissingDelegate(Urn);
+
+fn write_ref(
+    repo: &git2::Repository,
+    manifest: &Manifest,
+    name: &str,
+    oid: git2::Oid,
+) -> Result<(), Error> {
+    let res = match manifest.symrefs.get(name) {
+        Some(target) => repo.reference_symbolic(name, target, false, "restored from backup"),
+        None => repo.reference(name, oid, false, "restored from backup"),
+    };
+    match res {
+        Ok(_) => Ok(()),
+        Err(e) if e.code() == git2::ErrorCode::Exists => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn open(path: &Path) -> Result<io::BufReader<fs::File>, Error> {
+    let mut archive = io::BufReader::new(fs::File::open(path)?);
+    let mut magic = String::new();
+    archive.read_line(&mut magic)?;
+    if magic.trim_end() != MAGIC {
+        return Err(Error::NotAnArchive(path.to_path_buf()));
+    }
+
+    Ok(archive)
+}
+
+fn read_manifest<R>(archive: &mut R) -> Result<Manifest, Error>
+where
+    R: io::BufRead,
+{
+    let len = match read_section_header(archive)? {
+        Some((name, len)) if name == MANIFEST => len,
+        _ => return Err(Error::Malformed("missing manifest".to_owned())),
+    };
+    let mut json = Vec::new();
+    archive.take(len).read_to_end(&mut json)?;
+    // Check the version before anything else, newer versions may change the
+    // manifest in incompatible ways
+    #[derive(Deserialize)]
+    struct Versioned {
+        version: u32,
+    }
+    let Versioned { version } = serde_json::from_slice(&json)?;
+    if version > VERSION {
+        return Err(Error::Version(version));
+    }
+
+    Ok(serde_json::from_slice(&json)?)
+}
+
+fn write_section<W, R>(archive: &mut W, name: &str, content: &mut R) -> Result<(), Error>
+where
+    W: io::Write,
+    R: io::Read + io::Seek,
+{
+    let len = content.seek(io::SeekFrom::End(0))?;
+    content.seek(io::SeekFrom::Start(0))?;
+    writeln!(archive, "{} {}", name, len)?;
+    let copied = io::copy(content, archive)?;
+    if copied != len {
+        return Err(Error::Malformed(format!(
+            "`{}` changed while archiving",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+fn read_section_header<R>(archive: &mut R) -> Result<Option<(String, u64)>, Error>
+where
+    R: io::BufRead,
+{
+    let mut line = String::new();
+    if archive.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    line.trim_end()
+        .split_once(' ')
+        .and_then(|(name, len)| Some((name.to_owned(), len.parse().ok()?)))
+        .map(Some)
+        .ok_or_else(|| Error::Malformed(format!("invalid section header `{}`", line.trim_end())))
+}
+
+/// Index the pack of `bundle` into the monorepo at `git_dir`, and return the
+/// refs it contains.
+fn unbundle(git_dir: &Path, bundle: &Path) -> Result<BTreeMap<String, git2::Oid>, Error> {
+    let stdout = git(
+        git_dir,
+        "bundle",
+        &["bundle".as_ref(), "unbundle".as_ref(), bundle.as_os_str()],
+    )?;
+    String::from_utf8_lossy(&stdout)
+        .lines()
+        .map(|line| {
+            let (oid, name) = line
+                .split_once(' ')
+                .ok_or_else(|| Error::Malformed(format!("invalid bundle ref `{}`", line)))?;
+            let oid = git2::Oid::from_str(oid)
+                .map_err(|_| Error::Malformed(format!("invalid bundle ref `{}`", line)))?;
+            Ok((name.to_owned(), oid))
+        })
+        .collect()
+}
+
+fn git(git_dir: &Path, command: &'static str, args: &[&std::ffi::OsStr]) -> Result<Vec<u8>, Error> {
+    let out = Command::new("git")
+        .arg("--git-dir")
+        .arg(git_dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()?;
+    if !out.status.success() {
+        return Err(Error::Command {
+            command,
+            status: out.status,
+            stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+        });
+    }
+
+    Ok(out.stdout)
+}
@@ -0,0 +1,306 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Persistent caches, stored under [`Paths::caches_dir`].
+//!
+//! A [`Cache`] maps opaque keys to CBOR-encoded values, one file per entry.
+//! The layout on disk looks like this:
+//!
+//! ```ignore
+//! <caches dir>
+//! |- <name>
+//! |  |- v<version>
+//! |  |  |- <hash of key 1>
+//! |  |  |- <hash of key 2>
+//! |  |  ...
+//! ```
+//!
+//! Bumping the `version` passed to [`Cache::open`] invalidates all entries
+//! written by other versions, which are removed when the cache is opened.
+//!
+//! Each entry is prefixed with a header containing the version and a checksum
+//! of the payload. Entries which fail to validate, eg. because they were
+//! truncated by a crash, are treated as absent and removed. Entries are
+//! written to a temporary file first and then renamed, so concurrent readers
+//! (including other processes) never observe partial writes.
+//!
+//! The total size of a cache is bounded: when it grows beyond the configured
+//! limit, the entries written least recently are evicted. The size is tracked
+//! per [`Cache`] instance, so the limit is only approximate if multiple
+//! processes write to the same cache concurrently.
+
+use std::{
+    fs,
+    io,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use minicbor::{Decode, Encode};
+use thiserror::Error;
+
+use crate::paths::Paths;
+
+/// Magic bytes identifying a cache entry.
+const MAGIC: &[u8; 4] = b"RLC\0";
+/// Length of the entry header: magic, version, checksum.
+const HEADER_LEN: usize = 4 + 4 + 20;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("invalid cache name `{0}`")]
+    Name(String),
+
+    #[error(transparent)]
+    Encode(#[from] minicbor::encode::Error<io::Error>),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// A persistent, size-bounded key-value cache.
+///
+/// Cloning a [`Cache`] is cheap, and clones share the size accounting.
+pub struct Cache<V> {
+    dir: PathBuf,
+    version: u32,
+    max_bytes: u64,
+    size: Arc<AtomicU64>,
+    _marker: PhantomData<fn() -> V>,
+}
+
+impl<V> Clone for Cache<V> {
+    fn clone(&self) -> Self {
+        Self {
+            dir: self.dir.clone(),
+            version: self.version,
+            max_bytes: self.max_bytes,
+            size: Arc::clone(&self.size),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<V> Cache<V>
+where
+    V: Encode + for<'b> Decode<'b>,
+{
+    /// Open the cache `name` at schema `version`, creating it if necessary.
+    ///
+    /// `name` must be a single, non-empty path component. Entries of other
+    /// versions of the cache are removed.
+    pub fn open(paths: &Paths, name: &str, version: u32, max_bytes: u64) -> Result<Self, Error> {
+        Self::open_in(paths.caches_dir(), name, version, max_bytes)
+    }
+
+    /// Like [`Cache::open`], but rooted at `root` instead of
+    /// [`Paths::caches_dir`].
+    pub fn open_in(
+        root: impl AsRef<Path>,
+        name: &str,
+        version: u32,
+        max_bytes: u64,
+    ) -> Result<Self, Error> {
+        if name.is_empty() || name.starts_with('.') || name.contains(std::path::is_separator) {
+            return Err(Error::Name(name.to_owned()));
+        }
+        let base = root.as_ref().join(name);
+        let current = format!("v{}", version);
+        fs::create_dir_all(&base)?;
+        for entry in fs::read_dir(&base)? {
+            let entry = entry?;
+            if entry.file_name() != current.as_str() {
+                tracing::debug!(path = ?entry.path(), "removing stale cache version");
+                match entry.file_type()? {
+                    ty if ty.is_dir() => fs::remove_dir_all(entry.path())?,
+                    _ => fs::remove_file(entry.path())?,
+                }
+            }
+        }
+
+        let dir = base.join(current);
+        fs::create_dir_all(&dir)?;
+        let size = entries(&dir)?.map(|(_, meta)| meta.len()).sum();
+
+        Ok(Self {
+            dir,
+            version,
+            max_bytes,
+            size: Arc::new(AtomicU64::new(size)),
+            _marker: PhantomData,
+        })
+    }
+
+    /// The approximate size of all entries, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    /// Look up the value for `key`.
+    ///
+    /// Corrupt entries, and entries whose value fails to decode, are removed
+    /// and yield `None`.
+    #[tracing::instrument(level = "trace", skip(self, key))]
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Result<Option<V>, Error> {
+        let path = self.entry_path(key.as_ref())?;
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let value = self
+            .validate(&bytes)
+            .and_then(|payload| minicbor::decode(payload).ok());
+        if value.is_none() {
+            tracing::warn!(path = %path.display(), "removing corrupt cache entry");
+            self.remove_path(&path)?;
+        }
+
+        Ok(value)
+    }
+
+    /// Insert or replace the value for `key`.
+    ///
+    /// May evict other entries if the cache exceeds its size limit.
+    #[tracing::instrument(level = "trace", skip(self, key, value))]
+    pub fn put(&self, key: impl AsRef<[u8]>, value: &V) -> Result<(), Error> {
+        let path = self.entry_path(key.as_ref())?;
+        let payload = minicbor::to_vec(value)?;
+        let checksum = git2::Oid::hash_object(git2::ObjectType::Blob, &payload)?;
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.version.to_be_bytes());
+        bytes.extend_from_slice(checksum.as_bytes());
+        bytes.extend_from_slice(&payload);
+
+        let replaced = fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+        {
+            use std::io::Write as _;
+
+            let mut tmp = tempfile::NamedTempFile::new_in(&self.dir)?;
+            tmp.write_all(&bytes)?;
+            tmp.as_file().sync_all()?;
+            tmp.persist(&path).map_err(|e| e.error)?;
+        }
+        self.size.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        self.sub_size(replaced);
+
+        if self.size() > self.max_bytes {
+            self.evict(&path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove the entry for `key`, if any.
+    pub fn remove(&self, key: impl AsRef<[u8]>) -> Result<(), Error> {
+        let path = self.entry_path(key.as_ref())?;
+        self.remove_path(&path)
+    }
+
+    /// Remove all entries.
+    pub fn clear(&self) -> Result<(), Error> {
+        for (path, _) in entries(&self.dir)? {
+            self.remove_path(&path)?;
+        }
+        Ok(())
+    }
+
+    fn entry_path(&self, key: &[u8]) -> Result<PathBuf, Error> {
+        let hash = git2::Oid::hash_object(git2::ObjectType::Blob, key)?;
+        Ok(self.dir.join(hash.to_string()))
+    }
+
+    /// Check the header of `bytes`, returning the payload if it is valid.
+    fn validate<'a>(&self, bytes: &'a [u8]) -> Option<&'a [u8]> {
+        if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+            return None;
+        }
+        let (header, payload) = bytes.split_at(HEADER_LEN);
+        if header[4..8] != self.version.to_be_bytes() {
+            return None;
+        }
+        let checksum = git2::Oid::hash_object(git2::ObjectType::Blob, payload).ok()?;
+        (&header[8..] == checksum.as_bytes()).then(|| payload)
+    }
+
+    fn remove_path(&self, path: &Path) -> Result<(), Error> {
+        let len = match fs::metadata(path) {
+            Ok(meta) => meta.len(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        match fs::remove_file(path) {
+            Ok(()) => {
+                self.sub_size(len);
+                Ok(())
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Evict the oldest entries until the cache is within 90% of its limit.
+    ///
+    /// Leaving some headroom avoids evicting on every subsequent `put`. The
+    /// entry at `keep` (ie. the one just written) is never evicted.
+    fn evict(&self, keep: &Path) -> Result<(), Error> {
+        let mut entries = entries(&self.dir)?
+            .map(|(path, meta)| {
+                let mtime = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                (mtime, path, meta.len())
+            })
+            .collect::<Vec<_>>();
+        // Resync with what's actually on disk
+        self.size.store(
+            entries.iter().map(|(_, _, len)| len).sum(),
+            Ordering::Relaxed,
+        );
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        let target = self.max_bytes / 10 * 9;
+        for (_, path, _) in entries {
+            if self.size() <= target {
+                break;
+            }
+            if path == keep {
+                continue;
+            }
+            tracing::trace!(path = %path.display(), "evicting cache entry");
+            self.remove_path(&path)?;
+        }
+
+        Ok(())
+    }
+
+    fn sub_size(&self, len: u64) {
+        self.size
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |size| {
+                Some(size.saturating_sub(len))
+            })
+            .ok();
+    }
+}
+
+/// Regular files in `dir`, excluding in-flight temporary files.
+fn entries(dir: &Path) -> io::Result<impl Iterator<Item = (PathBuf, fs::Metadata)>> {
+    Ok(fs::read_dir(dir)?.filter_map(|entry| {
+        let entry = entry.ok()?;
+        let meta = entry.metadata().ok()?;
+        let is_tmp = entry.file_name().to_string_lossy().starts_with('.');
+        (meta.is_file() && !is_tmp).then(|| (entry.path(), meta))
+    }))
+}
@@ -10,7 +10,7 @@ use crate::git::{
     types::{Namespace, Reference, RefsCategory},
 };
 
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, num::NonZeroUsize, str::FromStr};
 
 pub use cob::{
     AuthorizingIdentity,
@@ -25,9 +25,17 @@ pub use cob::{
     RefsStorage,
     TypeName,
 };
-use link_crypto::BoxedSigner;
+use link_crypto::{keystore::sign::Signer as _, BoxedSigner, PublicKey};
 use link_identities::git::{SomeIdentity, Urn};
 
+#[cfg(feature = "cobs")]
+mod doc;
+pub mod index;
+#[cfg(feature = "cobs")]
+pub mod issue;
+#[cfg(feature = "cobs")]
+pub mod patch;
+
 pub mod error {
     use super::RefsError;
     use crate::git::identities::Error as IdentitiesError;
@@ -61,6 +69,15 @@ pub mod error {
         ResolveAuth(#[from] ResolveAuthorizer),
     }
 
+    #[allow(clippy::large_enum_variant)]
+    #[derive(Debug, Error)]
+    pub enum Snapshot {
+        #[error(transparent)]
+        Cob(#[from] cob::error::Snapshot<RefsError>),
+        #[error(transparent)]
+        ResolveAuth(#[from] ResolveAuthorizer),
+    }
+
     #[allow(clippy::large_enum_variant)]
     #[derive(Debug, Error)]
     pub enum ResolveAuthorizer {
@@ -102,6 +119,7 @@ pub struct CollaborativeObjects<'a> {
     signer: BoxedSigner,
     store: &'a Storage,
     cache_dir: Option<std::path::PathBuf>,
+    snapshot_every: Option<NonZeroUsize>,
 }
 
 impl<'a> CollaborativeObjects<'a> {
@@ -114,16 +132,34 @@ impl<'a> CollaborativeObjects<'a> {
             signer,
             store,
             cache_dir,
+            snapshot_every: None,
         }
     }
 
+    /// Snapshot an object whenever an update brings the number of changes in
+    /// its history to a multiple of `every`.
+    ///
+    /// Snapshots are signed by the local peer, and evaluation of objects
+    /// starts from the latest one, see [`CollaborativeObjects::snapshot`].
+    pub fn with_snapshots(self, every: NonZeroUsize) -> Self {
+        Self {
+            snapshot_every: Some(every),
+            ..self
+        }
+    }
+
+    /// The key snapshots must be signed with to be used for evaluation.
+    fn trusted_snapshots(&self) -> PublicKey {
+        self.signer.public_key().into()
+    }
+
     pub fn create(
         &self,
         whoami: &LocalIdentity,
         within_identity: &Urn,
         spec: NewObjectSpec,
     ) -> Result<cob::CollaborativeObject, error::Create> {
-        cob::create_object(cob::CreateObjectArgs {
+        let object = cob::create_object(cob::CreateObjectArgs {
             refs_storage: self,
             repo: self.store.as_raw(),
             signer: &self.signer,
@@ -135,7 +171,9 @@ impl<'a> CollaborativeObjects<'a> {
             message: spec.message,
             cache_dir: self.cache_dir.clone(),
         })
-        .map_err(error::Create::from)
+        .map_err(error::Create::from)?;
+        self.reindex(within_identity);
+        Ok(object)
     }
 
     pub fn retrieve(
@@ -152,6 +190,7 @@ impl<'a> CollaborativeObjects<'a> {
             typename,
             oid,
             self.cache_dir.clone(),
+            Some(&self.trusted_snapshots()),
         )
         .map_err(error::Retrieve::from)
     }
@@ -168,6 +207,7 @@ impl<'a> CollaborativeObjects<'a> {
             resolve_authorizing_identity(self.store, identity_urn)?.as_ref(),
             typename,
             self.cache_dir.clone(),
+            Some(&self.trusted_snapshots()),
         )
         .map_err(error::Retrieve::from)
     }
@@ -178,7 +218,7 @@ impl<'a> CollaborativeObjects<'a> {
         within_identity: &Urn,
         spec: UpdateObjectSpec,
     ) -> Result<cob::CollaborativeObject, error::Update> {
-        cob::update(cob::UpdateObjectArgs {
+        let object = cob::update(cob::UpdateObjectArgs {
             refs_storage: self,
             identity_storage: &self,
             signer: &self.signer,
@@ -192,7 +232,64 @@ impl<'a> CollaborativeObjects<'a> {
             changes: spec.changes,
             cache_dir: self.cache_dir.clone(),
         })
-        .map_err(error::Update::from)
+        .map_err(error::Update::from)?;
+        if let Some(every) = self.snapshot_every {
+            if object.history().len() % every.get() == 0 {
+                if let Err(e) = self.snapshot(within_identity, object.typename(), object.id()) {
+                    tracing::warn!(err = %e, object = %object.id(), "failed to snapshot object")
+                }
+            }
+        }
+        self.reindex(within_identity);
+        Ok(object)
+    }
+
+    /// Persist a snapshot of the current history of an object, signed by the
+    /// local peer.
+    ///
+    /// Subsequent evaluations of the object only need to verify the changes
+    /// made since. Returns `None` if there is no such object.
+    pub fn snapshot(
+        &self,
+        identity_urn: &Urn,
+        typename: &cob::TypeName,
+        oid: &cob::ObjectId,
+    ) -> Result<Option<git2::Oid>, error::Snapshot> {
+        cob::snapshot(
+            self,
+            &self,
+            self.store.as_raw(),
+            &self.signer,
+            resolve_authorizing_identity(self.store, identity_urn)?.as_ref(),
+            typename,
+            oid,
+            self.cache_dir.clone(),
+        )
+        .map_err(error::Snapshot::from)
+    }
+
+    /// The entries of the [`index::Index`] of `identity_urn` matching
+    /// `query`, most recently updated first.
+    ///
+    /// The index is brought up to date first, see [`index::refresh`].
+    pub fn query(
+        &self,
+        identity_urn: &Urn,
+        query: &index::Query,
+    ) -> Result<Vec<(ObjectId, index::Entry)>, index::Error> {
+        Ok(index::refresh(self, identity_urn)?
+            .query(query)
+            .into_iter()
+            .map(|(id, entry)| (id, entry.clone()))
+            .collect())
+    }
+
+    /// Update the index after a write. Failure to do so is not fatal, as the
+    /// index is refreshed before answering queries.
+    fn reindex(&self, urn: &Urn) {
+        if let Err(e) = index::refresh(self, urn) {
+            tracing::warn!(err = %e, %urn, "failed to update cob index")
+        }
     }
 
     pub fn changegraph_info_for_object(
@@ -0,0 +1,105 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Evaluation and modification of automerge-backed object histories.
+
+use std::ops::ControlFlow;
+
+use thiserror::Error;
+
+use super::{EntryContents, History};
+
+#[derive(Debug, Error)]
+#[error("invalid automerge document: {0}")]
+pub struct Error(String);
+
+impl Error {
+    fn new(e: impl std::fmt::Display) -> Self {
+        Self(e.to_string())
+    }
+}
+
+/// The automerge document described by a [`History`].
+pub(crate) struct Doc {
+    frontend: automerge::Frontend,
+    backend: automerge::Backend,
+}
+
+impl Doc {
+    /// An empty document, for creating a new object.
+    pub fn new() -> Self {
+        Self {
+            frontend: automerge::Frontend::new(),
+            backend: automerge::Backend::new(),
+        }
+    }
+
+    /// Apply all changes of `history`, parents before children.
+    pub fn load(history: &History) -> Result<Self, Error> {
+        let backend = history.traverse(Ok(automerge::Backend::new()), |backend, entry| {
+            let mut backend = match backend {
+                Ok(backend) => backend,
+                Err(e) => return ControlFlow::Break(Err(e)),
+            };
+            match entry.contents() {
+                EntryContents::Automerge(bytes) => {
+                    match automerge::Change::from_bytes(bytes.clone())
+                        .map_err(Error::new)
+                        .and_then(|change| backend.apply_changes(vec![change]).map_err(Error::new))
+                    {
+                        Ok(_) => ControlFlow::Continue(Ok(backend)),
+                        Err(e) => ControlFlow::Break(Err(e)),
+                    }
+                },
+            }
+        })?;
+        let mut frontend = automerge::Frontend::new();
+        frontend
+            .apply_patch(backend.get_patch().map_err(Error::new)?)
+            .map_err(Error::new)?;
+
+        Ok(Self { frontend, backend })
+    }
+
+    /// Make a change to the document, returning the contents of a history
+    /// entry recording it.
+    pub fn change<F>(&mut self, f: F) -> Result<EntryContents, Error>
+    where
+        F: FnOnce(
+            &mut dyn automerge::MutableDocument,
+        ) -> Result<(), automerge::InvalidChangeRequest>,
+    {
+        let (_, change) = self
+            .frontend
+            .change::<_, _, automerge::InvalidChangeRequest>(None, f)
+            .map_err(Error::new)?;
+        let change = change.ok_or_else(|| Error::new("empty change"))?;
+        let (patch, change) = self
+            .backend
+            .apply_local_change(change)
+            .map_err(Error::new)?;
+        self.frontend.apply_patch(patch).map_err(Error::new)?;
+
+        Ok(EntryContents::Automerge(change.raw_bytes().to_vec()))
+    }
+
+    /// The current state of the document as JSON.
+    pub fn state(&mut self) -> serde_json::Value {
+        self.frontend.state().to_json()
+    }
+}
+
+/// A string value, for use in [`automerge::LocalChange`]s.
+pub(crate) fn string(s: &str) -> automerge::Value {
+    automerge::Value::Primitive(automerge::Primitive::Str(s.into()))
+}
+
+/// The length of the list at `path`, or zero if there is none.
+pub(crate) fn list_len(doc: &dyn automerge::MutableDocument, path: &automerge::Path) -> u32 {
+    match doc.value_at_path(path) {
+        Some(automerge::Value::List(items)) => items.len() as u32,
+        _ => 0,
+    }
+}
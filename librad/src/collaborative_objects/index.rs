@@ -0,0 +1,257 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! A queryable index of the collaborative objects of a namespace.
+//!
+//! Listing collaborative objects requires to load and verify the change graph
+//! of every object. The [`Index`] records the heads of each object along with
+//! a summary which can be queried without doing so: its type, author, and
+//! when it was created and last updated.
+//!
+//! The index is kept up to date incrementally: [`refresh`] only evaluates
+//! objects whose heads changed since the index was last written. This happens
+//! whenever an object is created or updated through
+//! [`super::CollaborativeObjects`], before answering a [`Query`], and --
+//! if configured -- after replicating a namespace.
+//!
+//! The index of a namespace is stored as a blob the ref
+//! `refs/rad/cache/cobs/<id>` points to, outside of any namespace, so it is
+//! never served to other peers.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::ControlFlow,
+    time::SystemTime,
+};
+
+use git_ext as ext;
+use serde::{Deserialize, Serialize};
+use std_ext::time::unix_secs;
+use thiserror::Error;
+
+use super::{CollaborativeObjects, ObjectId, TypeName};
+use crate::git::{types::RefsCategory, Urn};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Retrieve(#[from] super::error::Retrieve),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// The summary of a collaborative object.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub typename: TypeName,
+    /// The author of the change which created the object.
+    pub author: Urn,
+    /// The commit time of the change which created the object, in seconds
+    /// since the epoch.
+    pub created: i64,
+    /// The most recent commit time of the heads of the object, in seconds
+    /// since the epoch.
+    pub updated: i64,
+    /// The heads of the object this entry was computed from.
+    pub tips: BTreeSet<ext::Oid>,
+}
+
+/// The materialised index of the collaborative objects of a namespace.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Index {
+    pub objects: BTreeMap<ObjectId, Entry>,
+}
+
+impl Index {
+    /// Load the stored index of `urn`.
+    ///
+    /// A missing or unreadable index yields an empty one.
+    pub fn load(repo: &git2::Repository, urn: &Urn) -> Self {
+        repo.find_reference(&refname(urn))
+            .and_then(|r| r.peel_to_blob())
+            .ok()
+            .and_then(|blob| serde_json::from_slice(blob.content()).ok())
+            .unwrap_or_default()
+    }
+
+    /// The entries matching `query`, most recently updated first.
+    pub fn query(&self, query: &Query) -> Vec<(ObjectId, &Entry)> {
+        let mut matches = self
+            .objects
+            .iter()
+            .filter(|(_, entry)| query.is_match(entry))
+            .map(|(id, entry)| (*id, entry))
+            .collect::<Vec<_>>();
+        matches.sort_by(|(a_id, a), (b_id, b)| b.updated.cmp(&a.updated).then(a_id.cmp(b_id)));
+        if let Some(limit) = query.limit {
+            matches.truncate(limit);
+        }
+        matches
+    }
+
+    fn store(&self, repo: &git2::Repository, urn: &Urn) -> Result<(), Error> {
+        let blob = repo.blob(&serde_json::to_vec(self)?)?;
+        repo.reference(&refname(urn), blob, true, "update cob index")?;
+        Ok(())
+    }
+}
+
+/// A query against an [`Index`].
+///
+/// The default query matches all objects.
+#[derive(Clone, Debug, Default)]
+pub struct Query {
+    typename: Option<TypeName>,
+    author: Option<Urn>,
+    since: Option<i64>,
+    limit: Option<usize>,
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match objects of type `typename`.
+    pub fn typename(self, typename: TypeName) -> Self {
+        Self {
+            typename: Some(typename),
+            ..self
+        }
+    }
+
+    /// Only match objects created by `author`.
+    pub fn author(self, author: Urn) -> Self {
+        Self {
+            author: Some(author),
+            ..self
+        }
+    }
+
+    /// Only match objects updated at or after `time`.
+    pub fn since(self, time: SystemTime) -> Self {
+        let secs = unix_secs(time);
+        Self {
+            since: Some(secs as i64),
+            ..self
+        }
+    }
+
+    /// Return at most `limit` objects.
+    pub fn limit(self, limit: usize) -> Self {
+        Self {
+            limit: Some(limit),
+            ..self
+        }
+    }
+
+    fn is_match(&self, entry: &Entry) -> bool {
+        self.typename
+            .as_ref()
+            .map_or(true, |typename| &entry.typename == typename)
+            && self
+                .author
+                .as_ref()
+                .map_or(true, |author| &entry.author == author)
+            && self.since.map_or(true, |since| entry.updated >= since)
+    }
+}
+
+fn refname(urn: &Urn) -> String {
+    format!("refs/rad/cache/cobs/{}", urn.encode_id())
+}
+
+/// Bring the index of `urn` up to date, and return it.
+///
+/// Objects which fail to load are left out of the index.
+pub fn refresh(cobs: &CollaborativeObjects, urn: &Urn) -> Result<Index, Error> {
+    let repo = cobs.store.as_raw();
+    let stored = Index::load(repo, urn);
+    let mut index = Index::default();
+    for ((typename, id), tips) in heads(repo, urn)? {
+        match stored.objects.get(&id) {
+            Some(entry) if entry.tips == tips && entry.typename == typename => {
+                index.objects.insert(id, entry.clone());
+            },
+            _ => {
+                tracing::trace!(object = %id, %typename, "indexing collaborative object");
+                let object = match cobs.retrieve(urn, &typename, &id) {
+                    Ok(Some(object)) => object,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        tracing::warn!(object = %id, err = %e, "skipping invalid collaborative object");
+                        continue;
+                    },
+                };
+                let author = object.history().traverse(None, |_, entry| {
+                    ControlFlow::Break(Some(entry.author().clone()))
+                });
+                let author = match author {
+                    Some(author) => author,
+                    None => continue,
+                };
+                let created = repo.find_commit(id.into())?.time().seconds();
+                let updated = tips
+                    .iter()
+                    .map(|tip| repo.find_commit((*tip).into()).map(|c| c.time().seconds()))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter()
+                    .max()
+                    .unwrap_or(created);
+                index.objects.insert(
+                    id,
+                    Entry {
+                        typename,
+                        author,
+                        created,
+                        updated,
+                        tips,
+                    },
+                );
+            },
+        }
+    }
+
+    if index != stored {
+        index.store(repo, urn)?;
+    }
+    Ok(index)
+}
+
+/// The heads of all collaborative objects in the namespace `urn`, local and
+/// remote.
+fn heads(
+    repo: &git2::Repository,
+    urn: &Urn,
+) -> Result<BTreeMap<(TypeName, ObjectId), BTreeSet<ext::Oid>>, git2::Error> {
+    let prefix = format!("refs/namespaces/{}/refs/", urn.encode_id());
+    let cobs = format!("{}/", RefsCategory::Cobs);
+    let mut heads = BTreeMap::<_, BTreeSet<_>>::new();
+    for reference in repo.references_glob(&format!("{}*", prefix))? {
+        let reference = reference?;
+        let name = match reference.name() {
+            Some(name) => &name[prefix.len()..],
+            None => continue,
+        };
+        let path = match name.strip_prefix("remotes/") {
+            Some(remote) => remote.split_once('/').map(|(_, path)| path),
+            None => Some(name),
+        };
+        let object = path
+            .and_then(|path| path.strip_prefix(cobs.as_str()))
+            .and_then(|path| path.split_once('/'))
+            .and_then(|(typename, id)| Some((typename.parse().ok()?, id.parse().ok()?)));
+        if let Some(object) = object {
+            let tip = reference.peel_to_commit()?.id();
+            heads.entry(object).or_default().insert(tip.into());
+        }
+    }
+    Ok(heads)
+}
@@ -0,0 +1,466 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Issues, as collaborative objects of type [`struct@ISSUE`].
+//!
+//! An issue is an automerge document of the shape:
+//!
+//! ```json
+//! {
+//!   "author": "<urn of the person who opened the issue>",
+//!   "title": "<string>",
+//!   "description": "<string>",
+//!   "state": "open" | "closed",
+//!   "labels": { "<label>": true | false, ... },
+//!   "comments": [ { "author": "<urn>", "body": "<string>" }, ... ]
+//! }
+//! ```
+//!
+//! Removing a label sets it to `false` rather than deleting the key, so that
+//! concurrent label changes merge predictably.
+//!
+//! Listing issues requires to evaluate the history of every issue, so a
+//! per-project [`Index`] is materialised in the monorepo. It records the
+//! summary of each issue along with the tips it was computed from, and is
+//! brought up to date incrementally by [`Issues::index`]: only issues whose
+//! tips changed are evaluated again. The index is stored as a blob the ref
+//! `refs/rad/cache/issues/<id>` points to, outside of any namespace, so it is
+//! never served to other peers.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    str::FromStr as _,
+};
+
+use git_ext as ext;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{
+    doc::{self, Doc},
+    CollaborativeObject,
+    CollaborativeObjects,
+    NewObjectSpec,
+    ObjectId,
+    RefsStorage as _,
+    TypeName,
+    UpdateObjectSpec,
+};
+use crate::git::{identities::local::LocalIdentity, storage::Storage, Urn};
+
+lazy_static! {
+    /// The type of collaborative objects representing issues.
+    pub static ref ISSUE: TypeName = "xyz.radicle.issue".parse().unwrap();
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("no issue {0}")]
+    NoSuchIssue(ObjectId),
+
+    #[error("malformed issue {id}: {reason}")]
+    Malformed { id: ObjectId, reason: String },
+
+    #[error(transparent)]
+    Doc(#[from] doc::Error),
+
+    #[error(transparent)]
+    Create(#[from] super::error::Create),
+
+    #[error(transparent)]
+    Retrieve(#[from] super::error::Retrieve),
+
+    #[error(transparent)]
+    Update(#[from] super::error::Update),
+
+    #[error(transparent)]
+    Refs(#[from] super::RefsError),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Open,
+    Closed,
+}
+
+impl State {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Closed => "closed",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comment {
+    pub author: Urn,
+    pub body: String,
+}
+
+/// The state of an issue.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Issue {
+    pub author: Urn,
+    pub title: String,
+    pub description: String,
+    pub state: State,
+    pub labels: BTreeSet<String>,
+    pub comments: Vec<Comment>,
+}
+
+impl Issue {
+    fn from_object(object: &CollaborativeObject) -> Result<Self, Error> {
+        let id = *object.id();
+        let malformed = |reason: String| Error::Malformed { id, reason };
+
+        let raw: raw::Issue = serde_json::from_value(Doc::load(object.history())?.state())
+            .map_err(|e| malformed(e.to_string()))?;
+        let urn = |s: &str| Urn::from_str(s).map_err(|e| malformed(e.to_string()));
+        Ok(Self {
+            author: urn(&raw.author)?,
+            title: raw.title,
+            description: raw.description,
+            state: raw.state,
+            labels: raw
+                .labels
+                .into_iter()
+                .filter_map(|(label, set)| set.then(|| label))
+                .collect(),
+            comments: raw
+                .comments
+                .into_iter()
+                .map(|c| {
+                    Ok(Comment {
+                        author: urn(&c.author)?,
+                        body: c.body,
+                    })
+                })
+                .collect::<Result<_, Error>>()?,
+        })
+    }
+}
+
+mod raw {
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct Issue {
+        pub author: String,
+        pub title: String,
+        #[serde(default)]
+        pub description: String,
+        pub state: super::State,
+        #[serde(default)]
+        pub labels: BTreeMap<String, bool>,
+        #[serde(default)]
+        pub comments: Vec<Comment>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Comment {
+        pub author: String,
+        pub body: String,
+    }
+}
+
+/// The summary of an issue recorded in the [`Index`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub title: String,
+    pub state: State,
+    pub labels: BTreeSet<String>,
+    /// The tips of the issue this entry was computed from.
+    pub tips: BTreeSet<ext::Oid>,
+}
+
+/// The materialised index of all issues of a project.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Index {
+    pub issues: BTreeMap<ObjectId, Entry>,
+}
+
+impl Index {
+    /// The issues in `state`.
+    pub fn with_state(&self, state: State) -> impl Iterator<Item = (&ObjectId, &Entry)> {
+        self.issues
+            .iter()
+            .filter(move |(_, entry)| entry.state == state)
+    }
+
+    /// The open issues.
+    pub fn open(&self) -> impl Iterator<Item = (&ObjectId, &Entry)> {
+        self.with_state(State::Open)
+    }
+
+    /// The closed issues.
+    pub fn closed(&self) -> impl Iterator<Item = (&ObjectId, &Entry)> {
+        self.with_state(State::Closed)
+    }
+
+    /// The issues labelled with `label`.
+    pub fn labelled<'a>(
+        &'a self,
+        label: &'a str,
+    ) -> impl Iterator<Item = (&'a ObjectId, &'a Entry)> + 'a {
+        self.issues
+            .iter()
+            .filter(move |(_, entry)| entry.labels.contains(label))
+    }
+}
+
+fn index_refname(project: &Urn) -> String {
+    format!("refs/rad/cache/issues/{}", project.encode_id())
+}
+
+/// Typed access to the issues of a project.
+pub struct Issues<'a> {
+    store: &'a Storage,
+    cobs: CollaborativeObjects<'a>,
+}
+
+impl<'a> Issues<'a> {
+    pub fn new(store: &'a Storage, cache_dir: Option<PathBuf>) -> Self {
+        Self {
+            store,
+            cobs: store.collaborative_objects(cache_dir),
+        }
+    }
+
+    /// Open a new issue in `project`.
+    pub fn create(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        title: &str,
+        description: &str,
+    ) -> Result<(ObjectId, Issue), Error> {
+        let author = whoami.urn().to_string();
+        let history = Doc::new().change(|d| {
+            let root = automerge::Path::root();
+            d.add_change(automerge::LocalChange::set(
+                root.clone().key("author"),
+                doc::string(&author),
+            ))?;
+            d.add_change(automerge::LocalChange::set(
+                root.clone().key("title"),
+                doc::string(title),
+            ))?;
+            d.add_change(automerge::LocalChange::set(
+                root.clone().key("description"),
+                doc::string(description),
+            ))?;
+            d.add_change(automerge::LocalChange::set(
+                root.clone().key("state"),
+                doc::string(State::Open.as_str()),
+            ))?;
+            d.add_change(automerge::LocalChange::set(
+                root.clone().key("labels"),
+                automerge::Value::Map(Default::default()),
+            ))?;
+            d.add_change(automerge::LocalChange::set(
+                root.key("comments"),
+                automerge::Value::List(Vec::new()),
+            ))
+        })?;
+        let object = self.cobs.create(
+            whoami,
+            project,
+            NewObjectSpec {
+                history,
+                typename: ISSUE.clone(),
+                message: Some(format!("open issue: {}", title)),
+            },
+        )?;
+        Ok((*object.id(), Issue::from_object(&object)?))
+    }
+
+    /// Retrieve the issue `id` of `project`.
+    pub fn get(&self, project: &Urn, id: &ObjectId) -> Result<Option<Issue>, Error> {
+        self.cobs
+            .retrieve(project, &ISSUE, id)?
+            .map(|object| Issue::from_object(&object))
+            .transpose()
+    }
+
+    /// Add a comment to an issue.
+    pub fn comment(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        id: &ObjectId,
+        body: &str,
+    ) -> Result<Issue, Error> {
+        let author = whoami.urn().to_string();
+        self.update(whoami, project, id, "comment", |d| {
+            let comments = automerge::Path::root().key("comments");
+            let len = doc::list_len(d, &comments);
+            let mut comment = std::collections::HashMap::new();
+            comment.insert("author".into(), doc::string(&author));
+            comment.insert("body".into(), doc::string(body));
+            d.add_change(automerge::LocalChange::insert(
+                comments.index(len),
+                automerge::Value::Map(comment),
+            ))
+        })
+    }
+
+    /// Add `label` to an issue.
+    pub fn label(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        id: &ObjectId,
+        label: &str,
+    ) -> Result<Issue, Error> {
+        self.update(whoami, project, id, "label", |d| {
+            d.add_change(automerge::LocalChange::set(
+                automerge::Path::root().key("labels").key(label),
+                automerge::Value::Primitive(automerge::Primitive::Boolean(true)),
+            ))
+        })
+    }
+
+    /// Remove `label` from an issue.
+    pub fn unlabel(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        id: &ObjectId,
+        label: &str,
+    ) -> Result<Issue, Error> {
+        self.update(whoami, project, id, "unlabel", |d| {
+            d.add_change(automerge::LocalChange::set(
+                automerge::Path::root().key("labels").key(label),
+                automerge::Value::Primitive(automerge::Primitive::Boolean(false)),
+            ))
+        })
+    }
+
+    /// Close an issue.
+    pub fn close(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        id: &ObjectId,
+    ) -> Result<Issue, Error> {
+        self.set_state(whoami, project, id, State::Closed)
+    }
+
+    /// Reopen a closed issue.
+    pub fn reopen(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        id: &ObjectId,
+    ) -> Result<Issue, Error> {
+        self.set_state(whoami, project, id, State::Open)
+    }
+
+    /// The up-to-date [`Index`] of the issues of `project`.
+    ///
+    /// Issues whose tips changed since the index was last materialised are
+    /// evaluated again, and the index is written back if anything changed.
+    pub fn index(&self, project: &Urn) -> Result<Index, Error> {
+        let repo = self.store.as_raw();
+        let refname = index_refname(project);
+        let stored = repo
+            .find_reference(&refname)
+            .and_then(|r| r.peel_to_blob())
+            .ok()
+            .and_then(|blob| serde_json::from_slice::<Index>(blob.content()).ok())
+            .unwrap_or_default();
+
+        let mut index = Index::default();
+        for (id, refs) in self.cobs.type_references(project, &ISSUE)? {
+            let tips = refs
+                .iter()
+                .map(|r| r.peel_to_commit().map(|c| ext::Oid::from(c.id())))
+                .collect::<Result<BTreeSet<_>, _>>()?;
+            match stored.issues.get(&id) {
+                Some(entry) if entry.tips == tips => {
+                    index.issues.insert(id, entry.clone());
+                },
+                _ => {
+                    tracing::trace!(issue = %id, "re-indexing issue");
+                    if let Some(issue) = self.get(project, &id)? {
+                        index.issues.insert(
+                            id,
+                            Entry {
+                                title: issue.title,
+                                state: issue.state,
+                                labels: issue.labels,
+                                tips,
+                            },
+                        );
+                    }
+                },
+            }
+        }
+
+        if index != stored {
+            let blob = repo.blob(&serde_json::to_vec(&index)?)?;
+            repo.reference(&refname, blob, true, "update issue index")?;
+        }
+        Ok(index)
+    }
+
+    fn set_state(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        id: &ObjectId,
+        state: State,
+    ) -> Result<Issue, Error> {
+        self.update(whoami, project, id, state.as_str(), |d| {
+            d.add_change(automerge::LocalChange::set(
+                automerge::Path::root().key("state"),
+                doc::string(state.as_str()),
+            ))
+        })
+    }
+
+    fn update<F>(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        id: &ObjectId,
+        message: &str,
+        f: F,
+    ) -> Result<Issue, Error>
+    where
+        F: FnOnce(
+            &mut dyn automerge::MutableDocument,
+        ) -> Result<(), automerge::InvalidChangeRequest>,
+    {
+        let object = self
+            .cobs
+            .retrieve(project, &ISSUE, id)?
+            .ok_or(Error::NoSuchIssue(*id))?;
+        let changes = Doc::load(object.history())?.change(f)?;
+        let object = self.cobs.update(
+            whoami,
+            project,
+            UpdateObjectSpec {
+                object_id: *id,
+                typename: ISSUE.clone(),
+                message: Some(message.to_owned()),
+                changes,
+            },
+        )?;
+        Issue::from_object(&object)
+    }
+}
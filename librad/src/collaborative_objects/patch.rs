@@ -0,0 +1,588 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Patches, as collaborative objects of type [`struct@PATCH`].
+//!
+//! A patch proposes to change a project from some base commit to some head
+//! commit. Whenever the proposed branch is rewritten, a new [`Revision`] is
+//! appended, so that review comments stay anchored to the revision they were
+//! made on. A patch is an automerge document of the shape:
+//!
+//! ```json
+//! {
+//!   "author": "<urn of the person who proposed the patch>",
+//!   "title": "<string>",
+//!   "description": "<string>",
+//!   "state": "open" | "merged" | "closed",
+//!   "revisions": [ { "author": "<urn>", "base": "<oid>", "head": "<oid>" }, ... ],
+//!   "reviews": [
+//!     {
+//!       "author": "<urn>",
+//!       "revision": <index into revisions>,
+//!       "path": "<path>" | null,
+//!       "line": <line> | null,
+//!       "body": "<string>"
+//!     },
+//!     ...
+//!   ],
+//!   "merge": { "revision": <index into revisions>, "commit": "<oid>" } | null
+//! }
+//! ```
+//!
+//! The commits a patch refers to are expected to be present in the monorepo,
+//! ie. replicated along with the branch they are on.
+
+use std::{collections::HashMap, path::PathBuf, str::FromStr as _};
+
+use git_ext as ext;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{
+    doc::{self, Doc},
+    CollaborativeObject,
+    CollaborativeObjects,
+    NewObjectSpec,
+    ObjectId,
+    TypeName,
+    UpdateObjectSpec,
+};
+use crate::git::{identities::local::LocalIdentity, storage::Storage, Urn};
+
+lazy_static! {
+    /// The type of collaborative objects representing patches.
+    pub static ref PATCH: TypeName = "xyz.radicle.patch".parse().unwrap();
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("no patch {0}")]
+    NoSuchPatch(ObjectId),
+
+    #[error("malformed patch {id}: {reason}")]
+    Malformed { id: ObjectId, reason: String },
+
+    #[error("patch {id} has no revision {revision}")]
+    NoSuchRevision { id: ObjectId, revision: usize },
+
+    #[error("patch {0} is not open")]
+    NotOpen(ObjectId),
+
+    #[error("commit {0} not found")]
+    MissingCommit(ext::Oid),
+
+    #[error(transparent)]
+    Doc(#[from] doc::Error),
+
+    #[error(transparent)]
+    Create(#[from] super::error::Create),
+
+    #[error(transparent)]
+    Retrieve(#[from] super::error::Retrieve),
+
+    #[error(transparent)]
+    Update(#[from] super::error::Update),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Open,
+    Merged,
+    Closed,
+}
+
+impl State {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Merged => "merged",
+            Self::Closed => "closed",
+        }
+    }
+}
+
+/// A version of the proposed change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Revision {
+    pub author: Urn,
+    pub base: ext::Oid,
+    pub head: ext::Oid,
+}
+
+/// A location in the tree of a revision a review comment refers to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Anchor {
+    pub path: String,
+    pub line: Option<u32>,
+}
+
+/// A review comment on a revision.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Review {
+    pub author: Urn,
+    /// The index of the revision in [`Patch::revisions`].
+    pub revision: usize,
+    /// The location the comment refers to, if it is not about the revision
+    /// as a whole.
+    pub anchor: Option<Anchor>,
+    pub body: String,
+}
+
+/// The record of a patch being merged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Merge {
+    /// The index of the merged revision in [`Patch::revisions`].
+    pub revision: usize,
+    /// The commit the revision was merged as.
+    pub commit: ext::Oid,
+}
+
+/// The state of a patch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Patch {
+    pub author: Urn,
+    pub title: String,
+    pub description: String,
+    pub state: State,
+    /// The revisions of the patch, oldest first. There is always at least
+    /// one.
+    pub revisions: Vec<Revision>,
+    pub reviews: Vec<Review>,
+    pub merge: Option<Merge>,
+}
+
+impl Patch {
+    /// The most recent revision.
+    pub fn latest(&self) -> &Revision {
+        self.revisions
+            .last()
+            .expect("patches have at least one revision")
+    }
+
+    /// The reviews of the revision at `index`.
+    pub fn reviews_of(&self, index: usize) -> impl Iterator<Item = &Review> {
+        self.reviews.iter().filter(move |r| r.revision == index)
+    }
+
+    fn from_object(object: &CollaborativeObject) -> Result<Self, Error> {
+        let id = *object.id();
+        let malformed = |reason: String| Error::Malformed { id, reason };
+
+        let raw: raw::Patch = serde_json::from_value(Doc::load(object.history())?.state())
+            .map_err(|e| malformed(e.to_string()))?;
+        let urn = |s: &str| Urn::from_str(s).map_err(|e| malformed(e.to_string()));
+        let oid = |s: &str| {
+            git2::Oid::from_str(s)
+                .map(ext::Oid::from)
+                .map_err(|e| malformed(e.to_string()))
+        };
+
+        let revisions = raw
+            .revisions
+            .iter()
+            .map(|r| {
+                Ok(Revision {
+                    author: urn(&r.author)?,
+                    base: oid(&r.base)?,
+                    head: oid(&r.head)?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        if revisions.is_empty() {
+            return Err(malformed("no revisions".to_owned()));
+        }
+        let reviews = raw
+            .reviews
+            .into_iter()
+            .map(|r| {
+                Ok(Review {
+                    author: urn(&r.author)?,
+                    revision: r.revision,
+                    anchor: r.path.map(|path| Anchor { path, line: r.line }),
+                    body: r.body,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        let merge = raw
+            .merge
+            .map(|m| {
+                Ok::<_, Error>(Merge {
+                    revision: m.revision,
+                    commit: oid(&m.commit)?,
+                })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            author: urn(&raw.author)?,
+            title: raw.title,
+            description: raw.description,
+            state: raw.state,
+            revisions,
+            reviews,
+            merge,
+        })
+    }
+}
+
+mod raw {
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct Patch {
+        pub author: String,
+        pub title: String,
+        #[serde(default)]
+        pub description: String,
+        pub state: super::State,
+        pub revisions: Vec<Revision>,
+        #[serde(default)]
+        pub reviews: Vec<Review>,
+        #[serde(default)]
+        pub merge: Option<Merge>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Revision {
+        pub author: String,
+        pub base: String,
+        pub head: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Review {
+        pub author: String,
+        pub revision: usize,
+        #[serde(default)]
+        pub path: Option<String>,
+        #[serde(default)]
+        pub line: Option<u32>,
+        pub body: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Merge {
+        pub revision: usize,
+        pub commit: String,
+    }
+}
+
+fn revision(author: &str, base: ext::Oid, head: ext::Oid) -> automerge::Value {
+    let mut revision = HashMap::new();
+    revision.insert("author".into(), doc::string(author));
+    revision.insert("base".into(), doc::string(&base.to_string()));
+    revision.insert("head".into(), doc::string(&head.to_string()));
+    automerge::Value::Map(revision)
+}
+
+fn uint(n: u64) -> automerge::Value {
+    automerge::Value::Primitive(automerge::Primitive::Uint(n))
+}
+
+/// Typed access to the patches of a project.
+pub struct Patches<'a> {
+    store: &'a Storage,
+    cobs: CollaborativeObjects<'a>,
+}
+
+impl<'a> Patches<'a> {
+    pub fn new(store: &'a Storage, cache_dir: Option<PathBuf>) -> Self {
+        Self {
+            store,
+            cobs: store.collaborative_objects(cache_dir),
+        }
+    }
+
+    /// Propose to change `project` from `base` to `head`.
+    pub fn create(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        title: &str,
+        description: &str,
+        base: ext::Oid,
+        head: ext::Oid,
+    ) -> Result<(ObjectId, Patch), Error> {
+        self.ensure_commits(&[base, head])?;
+        let author = whoami.urn().to_string();
+        let history = Doc::new().change(|d| {
+            let root = automerge::Path::root();
+            d.add_change(automerge::LocalChange::set(
+                root.clone().key("author"),
+                doc::string(&author),
+            ))?;
+            d.add_change(automerge::LocalChange::set(
+                root.clone().key("title"),
+                doc::string(title),
+            ))?;
+            d.add_change(automerge::LocalChange::set(
+                root.clone().key("description"),
+                doc::string(description),
+            ))?;
+            d.add_change(automerge::LocalChange::set(
+                root.clone().key("state"),
+                doc::string(State::Open.as_str()),
+            ))?;
+            d.add_change(automerge::LocalChange::set(
+                root.clone().key("revisions"),
+                automerge::Value::List(vec![revision(&author, base, head)]),
+            ))?;
+            d.add_change(automerge::LocalChange::set(
+                root.key("reviews"),
+                automerge::Value::List(Vec::new()),
+            ))
+        })?;
+        let object = self.cobs.create(
+            whoami,
+            project,
+            NewObjectSpec {
+                history,
+                typename: PATCH.clone(),
+                message: Some(format!("propose patch: {}", title)),
+            },
+        )?;
+        Ok((*object.id(), Patch::from_object(&object)?))
+    }
+
+    /// Retrieve the patch `id` of `project`.
+    pub fn get(&self, project: &Urn, id: &ObjectId) -> Result<Option<Patch>, Error> {
+        self.cobs
+            .retrieve(project, &PATCH, id)?
+            .map(|object| Patch::from_object(&object))
+            .transpose()
+    }
+
+    /// All patches of `project`.
+    pub fn list(&self, project: &Urn) -> Result<Vec<(ObjectId, Patch)>, Error> {
+        self.cobs
+            .list(project, &PATCH)?
+            .iter()
+            .map(|object| Ok((*object.id(), Patch::from_object(object)?)))
+            .collect()
+    }
+
+    /// Append a new revision, eg. after the proposed branch was rebased or
+    /// otherwise rewritten.
+    ///
+    /// If `head` and `base` are those of the latest revision already, the
+    /// patch is returned unchanged.
+    pub fn revise(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        id: &ObjectId,
+        base: ext::Oid,
+        head: ext::Oid,
+    ) -> Result<Patch, Error> {
+        self.ensure_commits(&[base, head])?;
+        let (object, patch) = self.load(project, id)?;
+        if patch.state != State::Open {
+            return Err(Error::NotOpen(*id));
+        }
+        let latest = patch.latest();
+        if latest.base == base && latest.head == head {
+            return Ok(patch);
+        }
+
+        let author = whoami.urn().to_string();
+        self.update(whoami, project, &object, "revise", |d| {
+            let revisions = automerge::Path::root().key("revisions");
+            let len = doc::list_len(d, &revisions);
+            d.add_change(automerge::LocalChange::insert(
+                revisions.index(len),
+                revision(&author, base, head),
+            ))
+        })
+    }
+
+    /// Append a new revision if the ref `branch` no longer points to the head
+    /// of the latest revision.
+    ///
+    /// This is the way to keep a patch up to date with a branch which may be
+    /// force-pushed. The base of the new revision is the merge base of the
+    /// new head and the previous base.
+    pub fn sync(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        id: &ObjectId,
+        branch: &str,
+    ) -> Result<Patch, Error> {
+        let repo = self.store.as_raw();
+        let (_, patch) = self.load(project, id)?;
+        let head = repo.find_reference(branch)?.peel_to_commit()?.id();
+        let latest = patch.latest();
+        if ext::Oid::from(head) == latest.head {
+            return Ok(patch);
+        }
+        let base = repo.merge_base(head, latest.base.into())?;
+        self.revise(whoami, project, id, base.into(), head.into())
+    }
+
+    /// Comment on the revision at `revision`, optionally anchored to a
+    /// location in its tree.
+    pub fn review(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        id: &ObjectId,
+        revision: usize,
+        anchor: Option<Anchor>,
+        body: &str,
+    ) -> Result<Patch, Error> {
+        let (object, patch) = self.load(project, id)?;
+        if revision >= patch.revisions.len() {
+            return Err(Error::NoSuchRevision { id: *id, revision });
+        }
+
+        let author = whoami.urn().to_string();
+        self.update(whoami, project, &object, "review", |d| {
+            let reviews = automerge::Path::root().key("reviews");
+            let len = doc::list_len(d, &reviews);
+            let mut review = HashMap::new();
+            review.insert("author".into(), doc::string(&author));
+            review.insert("revision".into(), uint(revision as u64));
+            review.insert("body".into(), doc::string(body));
+            if let Some(anchor) = anchor {
+                review.insert("path".into(), doc::string(&anchor.path));
+                if let Some(line) = anchor.line {
+                    review.insert("line".into(), uint(line.into()));
+                }
+            }
+            d.add_change(automerge::LocalChange::insert(
+                reviews.index(len),
+                automerge::Value::Map(review),
+            ))
+        })
+    }
+
+    /// Record that the revision at `revision` was merged as `commit`.
+    pub fn merge(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        id: &ObjectId,
+        revision: usize,
+        commit: ext::Oid,
+    ) -> Result<Patch, Error> {
+        self.ensure_commits(&[commit])?;
+        let (object, patch) = self.load(project, id)?;
+        if patch.state != State::Open {
+            return Err(Error::NotOpen(*id));
+        }
+        if revision >= patch.revisions.len() {
+            return Err(Error::NoSuchRevision { id: *id, revision });
+        }
+
+        self.update(whoami, project, &object, "merge", |d| {
+            let root = automerge::Path::root();
+            let mut merge = HashMap::new();
+            merge.insert("revision".into(), uint(revision as u64));
+            merge.insert("commit".into(), doc::string(&commit.to_string()));
+            d.add_change(automerge::LocalChange::set(
+                root.clone().key("merge"),
+                automerge::Value::Map(merge),
+            ))?;
+            d.add_change(automerge::LocalChange::set(
+                root.key("state"),
+                doc::string(State::Merged.as_str()),
+            ))
+        })
+    }
+
+    /// Close a patch without merging it.
+    pub fn close(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        id: &ObjectId,
+    ) -> Result<Patch, Error> {
+        self.set_state(whoami, project, id, State::Closed)
+    }
+
+    /// Reopen a closed patch.
+    pub fn reopen(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        id: &ObjectId,
+    ) -> Result<Patch, Error> {
+        self.set_state(whoami, project, id, State::Open)
+    }
+
+    fn set_state(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        id: &ObjectId,
+        state: State,
+    ) -> Result<Patch, Error> {
+        let (object, patch) = self.load(project, id)?;
+        if patch.state == State::Merged {
+            return Err(Error::NotOpen(*id));
+        }
+        self.update(whoami, project, &object, state.as_str(), |d| {
+            d.add_change(automerge::LocalChange::set(
+                automerge::Path::root().key("state"),
+                doc::string(state.as_str()),
+            ))
+        })
+    }
+
+    fn ensure_commits(&self, oids: &[ext::Oid]) -> Result<(), Error> {
+        let repo = self.store.as_raw();
+        for oid in oids {
+            match repo.find_commit((*oid).into()) {
+                Ok(_) => {},
+                Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                    return Err(Error::MissingCommit(*oid))
+                },
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    fn load(&self, project: &Urn, id: &ObjectId) -> Result<(CollaborativeObject, Patch), Error> {
+        let object = self
+            .cobs
+            .retrieve(project, &PATCH, id)?
+            .ok_or(Error::NoSuchPatch(*id))?;
+        let patch = Patch::from_object(&object)?;
+        Ok((object, patch))
+    }
+
+    fn update<F>(
+        &self,
+        whoami: &LocalIdentity,
+        project: &Urn,
+        object: &CollaborativeObject,
+        message: &str,
+        f: F,
+    ) -> Result<Patch, Error>
+    where
+        F: FnOnce(
+            &mut dyn automerge::MutableDocument,
+        ) -> Result<(), automerge::InvalidChangeRequest>,
+    {
+        let changes = Doc::load(object.history())?.change(f)?;
+        let object = self.cobs.update(
+            whoami,
+            project,
+            UpdateObjectSpec {
+                object_id: *object.id(),
+                typename: PATCH.clone(),
+                message: Some(message.to_owned()),
+                changes,
+            },
+        )?;
+        Patch::from_object(&object)
+    }
+}
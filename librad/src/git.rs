@@ -3,10 +3,14 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+#[cfg(feature = "hooks")]
 pub mod hooks;
+#[cfg(feature = "git-http")]
+pub mod http;
 pub mod identities;
 pub mod include;
 pub mod local;
+pub mod mirror;
 pub mod p2p;
 pub mod refs;
 
@@ -4,7 +4,10 @@
 use std::{fs, io, path::Path};
 
 pub use link_hooks::{
+    dispatch::{self, DeadLetter, Dispatcher},
+    exec::{self, Executor, Failure, Failures},
     hook::{self, Hook, Hooks, Notification, Process as _},
+    webhook,
     Data,
     Track,
 };
@@ -40,6 +43,22 @@ pub async fn hooks(paths: &Paths, config: hook::Config) -> io::Result<Hooks<Chil
     Ok(Hooks::new(config, data_hooks, track_hooks))
 }
 
+/// Create an [`Executor`] for the hooks located under [`Paths::hooks_dir`].
+///
+/// The hooks are discovered in the same `hooks/urn_changed` and
+/// `hooks/tracking_changed` directories as for [`hooks`], but only executable
+/// files are considered. Unlike [`hooks`], no processes are started upfront:
+/// each hook is spawned once per [`Notification`] passed to
+/// [`Executor::notify`], which returns immediately.
+///
+/// The returned receiver yields the [`Failure`]s of hooks.
+pub fn executor(paths: &Paths, config: exec::Config) -> io::Result<(Executor, Failures)> {
+    let hooks_dir = paths.hooks_dir();
+    let data_hooks = exec::discover(hooks_dir.join(DATA))?;
+    let track_hooks = exec::discover(hooks_dir.join(TRACK))?;
+    Ok(Executor::new(config, data_hooks, track_hooks))
+}
+
 async fn load(dir: impl AsRef<Path>) -> io::Result<Vec<Hook<Child>>> {
     let dir = dir.as_ref();
     let mut hooks = Vec::new();
@@ -0,0 +1,632 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Serving namespaces over git's "smart" HTTP protocol.
+//!
+//! This allows vanilla `git` clients to clone from a seed without speaking the
+//! p2p protocol:
+//!
+//! ```text
+//! git clone http://seed.example.com:8080/rad:git:hnrk...
+//! ```
+//!
+//! The refs of the namespace are advertised in the conventional layout
+//! computed by [`export::layout`], ie. as `refs/heads/*`, `refs/tags/*` and
+//! `refs/remotes/<peer>/*`, with `HEAD` pointing to the default branch.
+//! Packfiles are then generated by `git upload-pack`, confined to the
+//! namespace via `GIT_NAMESPACE`.
+//!
+//! Only fetching is supported, using the stateless version 0 of the protocol.
+//! Clients requesting version 2 fall back to it. Clients are not
+//! authenticated, so access is decided by
+//! [`ReadAccess::decide_anonymous`]: denied namespaces are not found, and
+//! hidden refs are neither advertised nor fetchable. TLS is expected to be
+//! terminated by a reverse proxy.
+
+use std::{future::Future, io, str::FromStr as _, sync::Arc, time::Duration};
+
+use flate2::read::GzDecoder;
+use futures::try_join;
+use std_ext::Void;
+use thiserror::Error;
+use tokio::{
+    io::{
+        AsyncBufRead,
+        AsyncBufReadExt as _,
+        AsyncRead,
+        AsyncReadExt as _,
+        AsyncWrite,
+        AsyncWriteExt as _,
+        BufReader,
+    },
+    net::{TcpListener, TcpStream},
+    process::Command,
+    sync::Semaphore,
+    task::{spawn_blocking, JoinError},
+};
+use tracing::{debug, info, instrument, warn};
+
+use crate::{
+    git::{
+        storage::{
+            self,
+            export::{self, Layout},
+            ReadOnly,
+            ReadOnlyStorage as _,
+        },
+        Urn,
+    },
+    net::protocol::read_access::{Decision, ReadAccess},
+    paths::Paths,
+};
+
+/// Default of [`Config::max_requests`].
+pub const DEFAULT_MAX_REQUESTS: usize = 32;
+/// Time a client has to send the request line and headers.
+pub const HEAD_TIMEOUT: Duration = Duration::from_secs(30);
+/// Time a client has to send the body of a request.
+pub const BODY_TIMEOUT: Duration = Duration::from_secs(60);
+/// Time after which the response is aborted if neither `git upload-pack` nor
+/// the client make any progress.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Size of the buffer used to copy the output of `git upload-pack`.
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+/// Maximum size of the (decompressed) body of a request.
+pub const MAX_REQUEST_SIZE: u64 = 16 * 1024 * 1024;
+/// Maximum size of the request line and of each header line.
+const MAX_LINE_SIZE: u64 = 8 * 1024;
+/// Maximum number of request headers.
+const MAX_HEADERS: usize = 64;
+
+/// Capabilities of `git upload-pack --stateless-rpc` we advertise.
+const CAPABILITIES: &str = "multi_ack_detailed no-done side-band-64k thin-pack ofs-delta \
+                            shallow no-progress include-tag allow-reachable-sha1-in-want";
+
+#[derive(Debug, Error)]
+enum Error {
+    #[error("bad request: {0}")]
+    BadRequest(&'static str),
+
+    #[error("not found")]
+    NotFound,
+
+    #[error("only fetching via the smart protocol is supported")]
+    Forbidden,
+
+    #[error("request body too large")]
+    TooLarge,
+
+    #[error("timed out waiting for the request")]
+    Timeout,
+
+    #[error("timed out sending the response")]
+    Stalled,
+
+    #[error("upload-pack exited with {0}")]
+    UploadPack(std::process::ExitStatus),
+
+    #[error("failed to spawn upload-pack")]
+    Spawn(#[source] io::Error),
+
+    #[error(transparent)]
+    Init(#[from] storage::read::error::Init),
+
+    #[error(transparent)]
+    Storage(#[from] storage::Error),
+
+    #[error(transparent)]
+    Export(#[from] export::Error),
+
+    #[error(transparent)]
+    Join(#[from] JoinError),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl Error {
+    fn status(&self) -> &'static str {
+        match self {
+            Self::BadRequest(_) => "400 Bad Request",
+            Self::NotFound => "404 Not Found",
+            Self::Forbidden => "403 Forbidden",
+            Self::TooLarge => "413 Payload Too Large",
+            Self::Timeout => "408 Request Timeout",
+            _ => "500 Internal Server Error",
+        }
+    }
+
+    fn is_client_error(&self) -> bool {
+        matches!(
+            self,
+            Self::BadRequest(_) | Self::NotFound | Self::Forbidden | Self::TooLarge | Self::Timeout
+        )
+    }
+}
+
+/// Configuration of [`serve`].
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Access control, see [`ReadAccess::decide_anonymous`].
+    pub read_access: ReadAccess,
+    /// Maximum number of requests served concurrently. No more connections
+    /// are accepted while this many are being served.
+    pub max_requests: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            read_access: ReadAccess::default(),
+            max_requests: DEFAULT_MAX_REQUESTS,
+        }
+    }
+}
+
+/// Serve the namespaces in the storage at `paths` to connections accepted on
+/// `listener`.
+#[instrument(name = "git http", skip(listener, paths))]
+pub async fn serve(listener: TcpListener, paths: Paths, config: Config) -> io::Result<Void> {
+    info!("serving git over http on {}", listener.local_addr()?);
+    let requests = Arc::new(Semaphore::new(config.max_requests.max(1)));
+    loop {
+        let permit = requests
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("semaphore is never closed");
+        let (stream, remote) = listener.accept().await?;
+        let paths = paths.clone();
+        let access = config.read_access.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(&paths, &access, stream).await {
+                warn!(err = %e, %remote, "error serving git over http");
+            }
+            drop(permit)
+        });
+    }
+}
+
+/// A request routed to a namespace.
+#[derive(Debug)]
+enum Route {
+    /// `GET /<urn>/info/refs?service=git-upload-pack`
+    InfoRefs(Urn),
+    /// `POST /<urn>/git-upload-pack`
+    UploadPack(Urn),
+}
+
+struct Head {
+    method: String,
+    target: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Head {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+async fn respond(paths: &Paths, access: &ReadAccess, stream: TcpStream) -> Result<(), Error> {
+    let (recv, mut send) = stream.into_split();
+    let mut recv = BufReader::new(recv);
+
+    let head = tokio::time::timeout(HEAD_TIMEOUT, read_head(&mut recv))
+        .await
+        .unwrap_or(Err(Error::Timeout));
+    let res = match head {
+        Ok(head) => {
+            debug!(method = %head.method, target = %head.target, "git http request");
+            match route(&head) {
+                Ok(Route::InfoRefs(urn)) => match hidden(access, &urn) {
+                    Ok(hidden) => info_refs(paths, urn, &hidden, &mut send).await,
+                    Err(e) => Err(e),
+                },
+                Ok(Route::UploadPack(urn)) => match hidden(access, &urn) {
+                    Ok(hidden) => {
+                        upload_pack(paths, urn, &hidden, &head, &mut recv, &mut send).await
+                    },
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            }
+        },
+        Err(e) => Err(e),
+    };
+    match res {
+        Ok(()) => idle(send.shutdown()).await?,
+        // Either the response is already underway, or the connection is broken
+        Err(e @ Error::Io(_)) | Err(e @ Error::UploadPack(_)) | Err(e @ Error::Stalled) => {
+            return Err(e)
+        },
+        Err(e) => {
+            idle(write_head(&mut send, e.status(), "text/plain")).await?;
+            idle(send.write_all(e.to_string().as_bytes())).await?;
+            idle(send.shutdown()).await?;
+            if e.is_client_error() {
+                debug!(err = %e, "git http request refused");
+            } else {
+                return Err(e);
+            }
+        },
+    }
+
+    Ok(())
+}
+
+fn route(head: &Head) -> Result<Route, Error> {
+    let (path, query) = match head.target.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (head.target.as_str(), None),
+    };
+    let (repo, route): (_, fn(Urn) -> Route) = if let Some(repo) = path.strip_suffix("/info/refs") {
+        let service = query
+            .into_iter()
+            .flat_map(|q| q.split('&'))
+            .find_map(|kv| kv.strip_prefix("service="));
+        match (head.method.as_str(), service) {
+            ("GET", Some("git-upload-pack")) => (repo, Route::InfoRefs),
+            ("GET", _) => return Err(Error::Forbidden),
+            _ => return Err(Error::BadRequest("unsupported method")),
+        }
+    } else if let Some(repo) = path.strip_suffix("/git-upload-pack") {
+        match head.method.as_str() {
+            "POST" => (repo, Route::UploadPack),
+            _ => return Err(Error::BadRequest("unsupported method")),
+        }
+    } else if path.ends_with("/git-receive-pack") {
+        return Err(Error::Forbidden);
+    } else {
+        return Err(Error::NotFound);
+    };
+
+    let id = repo.trim_start_matches('/');
+    let id = id.strip_suffix(".git").unwrap_or(id);
+    let id = id.strip_prefix("rad:git:").unwrap_or(id);
+    let urn = Urn::try_from_id(id).map_err(|_| Error::NotFound)?;
+    Ok(route(urn))
+}
+
+/// The `uploadpack.hideRefs` patterns of the refs of `urn` to hide from the
+/// client, or [`Error::NotFound`] if it may not fetch `urn` at all.
+///
+/// Denied namespaces are indistinguishable from non-existent ones.
+fn hidden(access: &ReadAccess, urn: &Urn) -> Result<Vec<String>, Error> {
+    match access.decide_anonymous(urn) {
+        Decision::Allow => Ok(vec![]),
+        Decision::Hide(categories) => Ok(categories.iter().map(|c| c.pattern()).collect()),
+        Decision::Deny => {
+            debug!(%urn, "git http request denied");
+            Err(Error::NotFound)
+        },
+    }
+}
+
+/// Whether the ref `name`, relative to the namespace, matches any of the
+/// `hidden` patterns.
+fn is_hidden(hidden: &[String], name: &str) -> bool {
+    hidden.iter().any(|pattern| {
+        name.strip_prefix(pattern.as_str())
+            .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+async fn info_refs<W>(paths: &Paths, urn: Urn, hidden: &[String], send: &mut W) -> Result<(), Error>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    let mut layout = {
+        let paths = paths.clone();
+        let urn = urn.clone();
+        spawn_blocking(move || -> Result<Layout, Error> {
+            let storage = ReadOnly::open(&paths)?;
+            if !storage.has_urn(&urn)? {
+                return Err(Error::NotFound);
+            }
+            Ok(storage.layout(&urn, export::Options::default())?)
+        })
+        .await??
+    };
+    if !hidden.is_empty() {
+        let namespace = format!("refs/namespaces/{}/", urn.encode_id());
+        let sources = &layout.sources;
+        layout.refs.retain(|name, _| {
+            sources
+                .get(name)
+                .and_then(|source| source.strip_prefix(&namespace))
+                .map_or(false, |source| !is_hidden(hidden, source))
+        });
+    }
+
+    idle(write_head(
+        send,
+        "200 OK",
+        "application/x-git-upload-pack-advertisement",
+    ))
+    .await?;
+    idle(send.write_all(&advertisement(&layout))).await?;
+
+    Ok(())
+}
+
+/// The ref advertisement of `layout`, including the service announcement
+/// required by the smart HTTP protocol.
+fn advertisement(layout: &Layout) -> Vec<u8> {
+    let mut buf = Vec::new();
+    pkt_line(&mut buf, "# service=git-upload-pack\n");
+    buf.extend_from_slice(b"0000");
+
+    let head = layout.head.as_ref().and_then(|branch| {
+        let name = format!("refs/heads/{}", branch);
+        layout.refs.get(&name).map(|oid| (name, *oid))
+    });
+    let mut caps = format!("{} agent=radicle-link", CAPABILITIES);
+    if let Some((target, _)) = &head {
+        caps.push_str(&format!(" symref=HEAD:{}", target));
+    }
+
+    let mut refs = head
+        .iter()
+        .map(|(_, oid)| ("HEAD", *oid))
+        .chain(layout.refs.iter().map(|(name, oid)| (name.as_str(), *oid)));
+    match refs.next() {
+        None => pkt_line(
+            &mut buf,
+            &format!("{} capabilities^{{}}\0{}\n", git2::Oid::zero(), caps),
+        ),
+        Some((name, oid)) => {
+            pkt_line(&mut buf, &format!("{} {}\0{}\n", oid, name, caps));
+            for (name, oid) in refs {
+                pkt_line(&mut buf, &format!("{} {}\n", oid, name));
+            }
+        },
+    }
+    buf.extend_from_slice(b"0000");
+
+    buf
+}
+
+fn pkt_line(buf: &mut Vec<u8>, line: &str) {
+    buf.extend_from_slice(format!("{:04x}", line.len() + 4).as_bytes());
+    buf.extend_from_slice(line.as_bytes());
+}
+
+async fn upload_pack<R, W>(
+    paths: &Paths,
+    urn: Urn,
+    hidden: &[String],
+    head: &Head,
+    recv: &mut R,
+    send: &mut W,
+) -> Result<(), Error>
+where
+    R: AsyncBufRead + Unpin,
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    let has_urn = {
+        let paths = paths.clone();
+        let urn = urn.clone();
+        spawn_blocking(move || -> Result<bool, Error> {
+            Ok(ReadOnly::open(&paths)?.has_urn(&urn)?)
+        })
+        .await??
+    };
+    if !has_urn {
+        return Err(Error::NotFound);
+    }
+
+    if head
+        .header("expect")
+        .map_or(false, |v| v.eq_ignore_ascii_case("100-continue"))
+    {
+        idle(send.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")).await?;
+    }
+    let body = tokio::time::timeout(BODY_TIMEOUT, read_body(head, recv))
+        .await
+        .unwrap_or(Err(Error::Timeout))?;
+    let body = match head.header("content-encoding") {
+        Some("gzip") | Some("x-gzip") => gunzip(&body)?,
+        None | Some("identity") => body,
+        Some(_) => return Err(Error::BadRequest("unsupported content encoding")),
+    };
+
+    let mut child = Command::new("git")
+        .current_dir(paths.git_dir())
+        .env_clear()
+        .envs(std::env::vars().filter(|(key, _)| key == "PATH" || key.starts_with("GIT_TRACE")))
+        .env("GIT_NAMESPACE", urn.encode_id())
+        .args(
+            hidden
+                .iter()
+                .flat_map(|pattern| ["-c".to_owned(), format!("uploadpack.hideRefs={}", pattern)]),
+        )
+        .args(&[
+            "-c",
+            "uploadpack.allowreachablesha1inwant=true",
+            "upload-pack",
+            "--strict",
+            "--stateless-rpc",
+            ".",
+        ])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(Error::Spawn)?;
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+
+    info!(%urn, "upload-pack");
+    idle(write_head(
+        send,
+        "200 OK",
+        "application/x-git-upload-pack-result",
+    ))
+    .await?;
+    let (_, _, status) = try_join!(
+        async move {
+            stdin.write_all(&body).await?;
+            stdin.shutdown().await.map_err(Error::from)
+        },
+        copy(&mut stdout, send),
+        async { child.wait().await.map_err(Error::from) },
+    )?;
+    if !status.success() {
+        return Err(Error::UploadPack(status));
+    }
+
+    Ok(())
+}
+
+/// Copy `src` to `dst`, failing with [`Error::Stalled`] if either side makes
+/// no progress within [`IDLE_TIMEOUT`].
+async fn copy<R, W>(src: &mut R, dst: &mut W) -> Result<(), Error>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0; COPY_BUFFER_SIZE];
+    loop {
+        let n = idle(src.read(&mut buf)).await?;
+        if n == 0 {
+            break;
+        }
+        idle(dst.write_all(&buf[..n])).await?;
+    }
+    idle(dst.flush()).await
+}
+
+/// Run the I/O operation `f`, failing with [`Error::Stalled`] if it doesn't
+/// complete within [`IDLE_TIMEOUT`].
+async fn idle<F, T>(f: F) -> Result<T, Error>
+where
+    F: Future<Output = io::Result<T>>,
+{
+    match tokio::time::timeout(IDLE_TIMEOUT, f).await {
+        Ok(res) => Ok(res?),
+        Err(_) => Err(Error::Stalled),
+    }
+}
+
+async fn read_head<R>(recv: &mut R) -> Result<Head, Error>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let line = read_line(recv).await?;
+    let (method, target) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        [method, target, version] if version.starts_with("HTTP/1.") => {
+            (method.to_owned(), target.to_owned())
+        },
+        _ => return Err(Error::BadRequest("malformed request line")),
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(recv).await?;
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(Error::BadRequest("too many headers"));
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(Error::BadRequest("malformed header"))?;
+        headers.push((name.trim().to_owned(), value.trim().to_owned()));
+    }
+
+    Ok(Head {
+        method,
+        target,
+        headers,
+    })
+}
+
+/// Read the body of the request, which is either sent with a
+/// `Content-Length`, or chunked.
+async fn read_body<R>(head: &Head, recv: &mut R) -> Result<Vec<u8>, Error>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut body = Vec::new();
+    if head
+        .header("transfer-encoding")
+        .map_or(false, |v| v.eq_ignore_ascii_case("chunked"))
+    {
+        loop {
+            let line = read_line(recv).await?;
+            let size = line.split(';').next().unwrap_or_default().trim();
+            let size = u64::from_str_radix(size, 16)
+                .map_err(|_| Error::BadRequest("malformed chunk size"))?;
+            if size == 0 {
+                // Skip trailers
+                while !read_line(recv).await?.is_empty() {}
+                break;
+            }
+            if body.len() as u64 + size > MAX_REQUEST_SIZE {
+                return Err(Error::TooLarge);
+            }
+            (&mut *recv).take(size).read_to_end(&mut body).await?;
+            if !read_line(recv).await?.is_empty() {
+                return Err(Error::BadRequest("malformed chunk"));
+            }
+        }
+    } else if let Some(len) = head.header("content-length") {
+        let len = u64::from_str(len).map_err(|_| Error::BadRequest("malformed content length"))?;
+        if len > MAX_REQUEST_SIZE {
+            return Err(Error::TooLarge);
+        }
+        (&mut *recv).take(len).read_to_end(&mut body).await?;
+    }
+
+    Ok(body)
+}
+
+async fn read_line<R>(recv: &mut R) -> Result<String, Error>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = String::new();
+    (&mut *recv)
+        .take(MAX_LINE_SIZE)
+        .read_line(&mut line)
+        .await?;
+    match line.strip_suffix('\n') {
+        Some(line) => Ok(line.trim_end_matches('\r').to_owned()),
+        None => Err(Error::BadRequest("line too long or truncated")),
+    }
+}
+
+fn gunzip(body: &[u8]) -> Result<Vec<u8>, Error> {
+    use std::io::Read as _;
+
+    let mut out = Vec::new();
+    GzDecoder::new(body)
+        .take(MAX_REQUEST_SIZE + 1)
+        .read_to_end(&mut out)?;
+    if out.len() as u64 > MAX_REQUEST_SIZE {
+        return Err(Error::TooLarge);
+    }
+    Ok(out)
+}
+
+async fn write_head<W>(send: &mut W, status: &str, content_type: &str) -> io::Result<()>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    send.write_all(
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+            status, content_type
+        )
+        .as_bytes(),
+    )
+    .await
+}
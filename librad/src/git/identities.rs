@@ -3,12 +3,17 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+pub mod aliases;
 pub mod any;
 pub mod error;
+pub mod links;
 pub mod local;
 pub mod person;
 pub mod project;
 pub mod relations;
+pub mod review;
+pub mod supersede;
+pub mod webhooks;
 
 pub(super) mod common;
 
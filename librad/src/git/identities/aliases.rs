@@ -0,0 +1,273 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Human-readable names for URNs, published by persons.
+//!
+//! A person may declare [`Aliases`] in the payload of their identity,
+//! mapping names of their choosing to URNs. Being part of the identity
+//! document, the mappings are signed by the person's delegations and
+//! replicated along with the person.
+//!
+//! An [`Alias`] of the form `rad:alias:<person>/<name>` names the URN the
+//! person(s) `<person>` map `<name>` to, where `<person>` is either the
+//! encoded id of a person URN, or the name of a person. Aliases are
+//! [`resolve`]d against the verified persons found in local storage, ie. the
+//! ones reachable via the tracking graph. Since person names are not unique,
+//! several persons may map the same alias to different URNs -- the
+//! [`Resolution`] reports such conflicts instead of choosing a winner.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    str::FromStr,
+};
+
+use thiserror::Error;
+use url::Url;
+
+use super::{
+    super::storage::{self, Storage},
+    any,
+    person,
+};
+use crate::identities::{
+    git::{Person, SomeIdentity, Urn},
+    payload::{ExtError, HasNamespace},
+};
+
+lazy_static! {
+    static ref ALIASES_NAMESPACE: Url =
+        Url::parse("https://radicle.xyz/link/identities/aliases/v1").unwrap();
+}
+
+/// Payload extension declaring the names a person gives to URNs.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct Aliases(pub BTreeMap<String, Urn>);
+
+impl HasNamespace for Aliases {
+    fn namespace() -> &'static Url {
+        &ALIASES_NAMESPACE
+    }
+}
+
+/// A reference to a URN by a [`Aliases`] entry, of the form
+/// `rad:alias:<person>/<name>`.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Alias {
+    pub person: String,
+    pub name: String,
+}
+
+impl Alias {
+    const PREFIX: &'static str = "rad:alias:";
+}
+
+impl fmt::Display for Alias {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}/{}", Self::PREFIX, self.person, self.name)
+    }
+}
+
+impl FromStr for Alias {
+    type Err = error::Parse;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s
+            .strip_prefix(Self::PREFIX)
+            .ok_or_else(|| error::Parse::Prefix(s.to_owned()))?;
+        let (person, name) = rest
+            .split_once('/')
+            .ok_or_else(|| error::Parse::Malformed(s.to_owned()))?;
+        if person.is_empty() {
+            return Err(error::Parse::Malformed(s.to_owned()));
+        }
+        validate_name(name)?;
+
+        Ok(Self {
+            person: person.to_owned(),
+            name: name.to_owned(),
+        })
+    }
+}
+
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    #[non_exhaustive]
+    pub enum Parse {
+        #[error("alias `{0}` does not start with `rad:alias:`")]
+        Prefix(String),
+
+        #[error("alias `{0}` is not of the form `rad:alias:<person>/<name>`")]
+        Malformed(String),
+
+        #[error("invalid alias name `{0}`")]
+        Name(String),
+    }
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("the person {0} does not exist")]
+    NotFound(Urn),
+
+    #[error("invalid aliases in payload of {urn}")]
+    Ext {
+        urn: Urn,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error(transparent)]
+    Parse(#[from] error::Parse),
+
+    #[error(transparent)]
+    Payload(#[from] ExtError),
+
+    #[error(transparent)]
+    Identities(#[from] super::Error),
+}
+
+/// A URN an [`Alias`] may refer to.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Candidate {
+    /// The person declaring the alias.
+    pub person: Urn,
+    pub urn: Urn,
+}
+
+/// The outcome of [`resolve`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// No verified person matching the alias declares its name.
+    NotFound,
+    /// All persons declaring the alias agree on `urn`.
+    Unique { urn: Urn, persons: Vec<Urn> },
+    /// The alias refers to different URNs, depending on the person.
+    Conflict(Vec<Candidate>),
+}
+
+impl Resolution {
+    /// The URN the alias refers to, if it is unambiguous.
+    pub fn unique(&self) -> Option<&Urn> {
+        match self {
+            Self::Unique { urn, .. } => Some(urn),
+            _ => None,
+        }
+    }
+}
+
+/// Read the [`Aliases`] declared by the person `urn`.
+///
+/// Returns `None` if `urn` is not found, or is not a person. A person without
+/// aliases yields an empty [`Aliases`].
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn get<S>(storage: &S, urn: &Urn) -> Result<Option<Aliases>, Error>
+where
+    S: AsRef<storage::ReadOnly>,
+{
+    match any::get(storage, &urn.clone().with_path(None))? {
+        Some(SomeIdentity::Person(person)) => aliases_of(&person).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Map `name` to `target` in the [`Aliases`] of the person `urn`, replacing
+/// any previous mapping.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn set(storage: &Storage, urn: &Urn, name: &str, target: Urn) -> Result<Person, Error> {
+    validate_name(name)?;
+    modify(storage, urn, |aliases| {
+        aliases.0.insert(name.to_owned(), target.with_path(None));
+    })
+}
+
+/// Remove `name` from the [`Aliases`] of the person `urn`.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn remove(storage: &Storage, urn: &Urn, name: &str) -> Result<Person, Error> {
+    modify(storage, urn, |aliases| {
+        aliases.0.remove(name);
+    })
+}
+
+/// Resolve `alias` against the verified persons in `storage`.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn resolve<S>(storage: &S, alias: &Alias) -> Result<Resolution, Error>
+where
+    S: AsRef<storage::ReadOnly>,
+{
+    let by_id = Urn::try_from_id(&alias.person).ok();
+    let mut candidates = BTreeSet::new();
+    for urn in any::list_urns(storage)? {
+        let urn = urn?;
+        if by_id.as_ref().map_or(false, |id| id != &urn) {
+            continue;
+        }
+        let person = match person::verify(storage, &urn) {
+            Ok(Some(person)) => person,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::trace!(%urn, err = %e, "skipping unverified identity");
+                continue;
+            },
+        };
+        if by_id.is_none() && person.subject().name.as_str() != alias.person {
+            continue;
+        }
+        if let Some(target) = aliases_of(&person)?.0.remove(&alias.name) {
+            candidates.insert(Candidate {
+                person: urn,
+                urn: target,
+            });
+        }
+    }
+
+    let targets = candidates.iter().map(|c| &c.urn).collect::<BTreeSet<_>>();
+    Ok(match targets.len() {
+        0 => Resolution::NotFound,
+        1 => {
+            let urn = (*targets.iter().next().unwrap()).clone();
+            Resolution::Unique {
+                urn,
+                persons: candidates.into_iter().map(|c| c.person).collect(),
+            }
+        },
+        _ => Resolution::Conflict(candidates.into_iter().collect()),
+    })
+}
+
+fn aliases_of(person: &Person) -> Result<Aliases, Error> {
+    person
+        .payload()
+        .get_ext::<Aliases>()
+        .map(Option::unwrap_or_default)
+        .map_err(|source| Error::Ext {
+            urn: person.urn(),
+            source,
+        })
+}
+
+fn modify<F>(storage: &Storage, urn: &Urn, f: F) -> Result<Person, Error>
+where
+    F: FnOnce(&mut Aliases),
+{
+    let urn = urn.clone().with_path(None);
+    let person = person::get(storage, &urn)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+    let mut aliases = aliases_of(&person)?;
+    f(&mut aliases);
+    let mut payload = person.payload().clone();
+    payload.set_ext(aliases)?;
+
+    Ok(person::update(storage, &urn, None, payload, None)?)
+}
+
+fn validate_name(name: &str) -> Result<(), error::Parse> {
+    if name.is_empty() || name.contains(|c: char| c == '/' || c.is_whitespace() || c.is_control()) {
+        Err(error::Parse::Name(name.to_owned()))
+    } else {
+        Ok(())
+    }
+}
@@ -7,7 +7,7 @@ use git_ext::{self as ext, is_exists_err};
 use std_ext::result::ResultExt as _;
 
 use super::super::{
-    storage::{self, ReadOnlyStorage as _, Storage},
+    storage::{self, transaction::Previous, ReadOnlyStorage as _, Storage, Transaction},
     types::{Force, Namespace, Reference},
 };
 use crate::identities::git::Urn;
@@ -57,4 +57,56 @@ impl<'a> IdRef<'a> {
             .create(storage.as_raw(), *target.as_ref(), Force::True, msg)
             .and(Ok(()))
     }
+
+    /// Like [`IdRef::create`], but staged in `tx`.
+    pub fn stage_create(
+        &self,
+        storage: &Storage,
+        tx: &mut Transaction,
+        target: impl AsRef<git2::Oid>,
+    ) -> Result<(), storage::Error> {
+        let name = Reference::rad_id(Namespace::from(self.0));
+        if !storage.has_ref(&name)? {
+            tx.update(
+                name,
+                *target.as_ref(),
+                Previous::Absent,
+                &format!("Initial rad/id for {}", self.0),
+            );
+        }
+        Ok(())
+    }
+
+    /// Like [`IdRef::update`] or [`IdRef::update_matching`], but staged in
+    /// `tx`.
+    pub fn stage_update(
+        &self,
+        tx: &mut Transaction,
+        target: impl AsRef<git2::Oid>,
+        previous: Previous,
+        msg: &str,
+    ) {
+        tx.update(
+            Reference::rad_id(Namespace::from(self.0)),
+            *target.as_ref(),
+            previous,
+            msg,
+        );
+    }
+
+    /// Like [`IdRef::update`], but only if the ref currently points to
+    /// `previous`.
+    pub fn update_matching(
+        &self,
+        storage: &Storage,
+        target: impl AsRef<git2::Oid>,
+        previous: impl AsRef<git2::Oid>,
+        msg: &str,
+    ) -> Result<(), git2::Error> {
+        let name = Reference::rad_id(Namespace::from(self.0)).to_string();
+        let repo = storage.as_raw();
+        repo.reference_ensure_log(&name)?;
+        repo.reference_matching(&name, *target.as_ref(), true, *previous.as_ref(), msg)
+            .and(Ok(()))
+    }
 }
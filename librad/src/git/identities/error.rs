@@ -49,18 +49,27 @@ pub enum Error {
     #[error(transparent)]
     Merge(#[from] identities::git::error::Merge),
 
+    #[error(transparent)]
+    Rotate(#[from] identities::git::error::Rotate),
+
     #[error(transparent)]
     Load(#[from] identities::git::error::Load),
 
     #[error(transparent)]
     Store(#[from] identities::git::error::Store),
 
+    #[error(transparent)]
+    Update(#[from] identities::git::error::Update),
+
     #[error(transparent)]
     PersHist(#[from] identities::git::error::History<identities::git::PersonDoc>),
 
     #[error(transparent)]
     ProjHist(#[from] identities::git::error::History<identities::git::ProjectDoc>),
 
+    #[error(transparent)]
+    Transaction(#[from] storage::transaction::Error),
+
     #[error(transparent)]
     Git(#[from] git2::Error),
 }
@@ -0,0 +1,213 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Dependencies of an identity on other identities, pinned at specific
+//! revisions.
+//!
+//! Similar to git submodules, a project (or person) may declare [`Links`] to
+//! other URNs in its payload. Each [`Link`] records the commit of the linked
+//! identity's history it depends on. The [`closure`] of links can be resolved
+//! transitively up to a bounded depth, and [`verify`] checks that all pinned
+//! revisions are present in local storage, eg. after replicating the closure.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use git_ext as ext;
+use thiserror::Error;
+use url::Url;
+
+use super::{
+    super::storage::{self, ReadOnlyStorage as _},
+    any,
+};
+use crate::identities::{
+    git::{SomeIdentity, Urn},
+    payload::HasNamespace,
+};
+
+lazy_static! {
+    static ref LINKS_NAMESPACE: Url =
+        Url::parse("https://radicle.xyz/link/identities/links/v1").unwrap();
+}
+
+/// Payload extension declaring the [`Link`]s of an identity, keyed by a
+/// human-readable name (eg. the path a dependency is checked out at).
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct Links(pub BTreeMap<String, Link>);
+
+impl HasNamespace for Links {
+    fn namespace() -> &'static Url {
+        &LINKS_NAMESPACE
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Link {
+    /// The linked identity.
+    ///
+    /// If the [`Urn`] has a path, [`verify`] checks that `rev` is reachable
+    /// from the branch it resolves to. Otherwise, `rev` merely has to exist.
+    pub urn: Urn,
+    /// The commit the link is pinned at.
+    pub rev: ext::Oid,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("invalid links in payload of {urn}")]
+    Ext {
+        urn: Urn,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error(transparent)]
+    Identities(#[from] super::Error),
+
+    #[error(transparent)]
+    Store(#[from] storage::Error),
+}
+
+/// Read the [`Links`] declared by the identity `urn`.
+///
+/// Returns `None` if the identity is not found. An identity without links
+/// yields an empty [`Links`].
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn get<S>(storage: &S, urn: &Urn) -> Result<Option<Links>, Error>
+where
+    S: AsRef<storage::ReadOnly>,
+{
+    let links = match any::get(storage, urn)? {
+        None => return Ok(None),
+        Some(SomeIdentity::Project(project)) => project.payload().get_ext::<Links>(),
+        Some(SomeIdentity::Person(person)) => person.payload().get_ext::<Links>(),
+        Some(_) => Ok(None),
+    }
+    .map_err(|source| Error::Ext {
+        urn: urn.clone(),
+        source,
+    })?;
+
+    Ok(Some(links.unwrap_or_default()))
+}
+
+/// A [`Link`] found while resolving a [`Closure`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edge {
+    /// The identity declaring the link.
+    pub from: Urn,
+    /// The name of the link in the [`Links`] of `from`.
+    pub name: String,
+    pub link: Link,
+    /// The distance from the root of the [`Closure`], starting at `1` for
+    /// links declared by the root itself.
+    pub depth: usize,
+}
+
+/// The transitive closure of [`Links`] reachable from a root identity.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Closure {
+    pub edges: Vec<Edge>,
+    /// Linked identities which are not present in local storage, and whose
+    /// links could thus not be resolved.
+    pub missing: BTreeSet<Urn>,
+}
+
+impl Closure {
+    /// The distinct identities linked to, excluding the root.
+    pub fn urns(&self) -> BTreeSet<Urn> {
+        self.edges.iter().map(|edge| id(&edge.link.urn)).collect()
+    }
+}
+
+/// Resolve the [`Closure`] of [`Links`] starting at `urn`.
+///
+/// Links are followed breadth-first, up to `max_depth` hops from `urn`. A
+/// `max_depth` of `0` yields an empty closure. Cycles are followed only once.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn closure<S>(storage: &S, urn: &Urn, max_depth: usize) -> Result<Closure, Error>
+where
+    S: AsRef<storage::ReadOnly>,
+{
+    let mut closure = Closure::default();
+    let mut seen = BTreeSet::from([id(urn)]);
+    let mut queue = VecDeque::from([(id(urn), 0)]);
+
+    while let Some((next, depth)) = queue.pop_front() {
+        if depth >= max_depth {
+            continue;
+        }
+        let links = match get(storage, &next)? {
+            Some(links) => links,
+            None => {
+                closure.missing.insert(next);
+                continue;
+            },
+        };
+        for (name, link) in links.0 {
+            let linked = id(&link.urn);
+            if seen.insert(linked.clone()) {
+                queue.push_back((linked, depth + 1));
+            }
+            closure.edges.push(Edge {
+                from: next.clone(),
+                name,
+                link,
+                depth: depth + 1,
+            });
+        }
+    }
+
+    Ok(closure)
+}
+
+/// The reason an [`Edge`] of a [`Closure`] could not be verified.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Unverified {
+    /// The linked identity is not present in local storage.
+    MissingIdentity(Edge),
+    /// The pinned revision is not present in local storage, or not reachable
+    /// from the linked branch.
+    MissingRevision(Edge),
+}
+
+/// Verify that all pinned revisions in the [`Closure`] of `urn` are present
+/// locally.
+///
+/// Returns the [`Unverified`] edges, ie. an empty result means the closure is
+/// complete.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn verify<S>(storage: &S, urn: &Urn, max_depth: usize) -> Result<Vec<Unverified>, Error>
+where
+    S: AsRef<storage::ReadOnly>,
+{
+    let storage = storage.as_ref();
+    let closure = closure(storage, urn, max_depth)?;
+    let mut unverified = Vec::new();
+    for edge in closure.edges {
+        if !storage.has_urn(&id(&edge.link.urn))? {
+            unverified.push(Unverified::MissingIdentity(edge));
+            continue;
+        }
+
+        let present = match edge.link.urn.path {
+            Some(_) => storage.has_commit(&edge.link.urn, edge.link.rev)?,
+            None => storage
+                .find_object(edge.link.rev)?
+                .map(|obj| obj.kind() == Some(git2::ObjectType::Commit))
+                .unwrap_or(false),
+        };
+        if !present {
+            unverified.push(Unverified::MissingRevision(edge));
+        }
+    }
+
+    Ok(unverified)
+}
+
+/// The identity part of `urn`, ie. without a path.
+fn id(urn: &Urn) -> Urn {
+    urn.clone().with_path(None)
+}
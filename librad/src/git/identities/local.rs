@@ -3,13 +3,14 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::ops::Deref;
+use std::{convert::TryFrom as _, ops::Deref};
 
 use thiserror::Error;
 
 use super::{
     super::{
-        storage::{self, config, Storage},
+        refs::{stored as refs, Refs},
+        storage::{self, config, ReadOnlyStorage as _, Storage, Transaction},
         types::{Force, Namespace, Reference},
     },
     person,
@@ -22,9 +23,18 @@ use crate::{
 
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error("no verified person identity found at {0}")]
+    NotFound(Urn),
+
+    #[error("namespace {0} does not exist")]
+    NoSuchNamespace(Urn),
+
     #[error(transparent)]
     Validation(#[from] ValidationError),
 
+    #[error(transparent)]
+    Refs(#[from] refs::Error),
+
     #[error(transparent)]
     Identities(#[from] Box<super::Error>),
 
@@ -101,6 +111,14 @@ impl LocalIdentity {
             .map_err(storage::Error::from)
     }
 
+    /// Like [`LocalIdentity::link`], but staged in `tx`.
+    pub fn stage_link(&self, tx: &mut Transaction, from: &Urn) {
+        let source = Reference::rad_self(Namespace::from(from), None);
+        let target = Reference::rad_id(Namespace::from(self.urn()));
+        let msg = format!("creating symbolic ref {} -> {}", source, target);
+        tx.symbolic(source, target, &msg);
+    }
+
     pub fn into_inner(self) -> VerifiedPerson {
         self.0
     }
@@ -137,3 +155,146 @@ pub fn default(storage: &Storage) -> Result<Option<LocalIdentity>, Error> {
         None => Ok(None),
     }
 }
+
+/// Resolve the [`LocalIdentity`] in effect for `urn`.
+///
+/// That is, the one [`link`]ed from the namespace of `urn`, if any, or the
+/// [`default`] otherwise.
+///
+/// [`link`]: LocalIdentity::link
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn resolve(storage: &Storage, urn: &Urn) -> Result<Option<LocalIdentity>, Error> {
+    match load(storage, urn.clone())? {
+        Some(local) => Ok(Some(local)),
+        None => default(storage),
+    }
+}
+
+/// A change of a `rad/self` association, as returned by [`set_default`],
+/// [`clear_default`], [`set`], and [`clear`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    /// The namespace whose `rad/self` changed, or `None` if the default
+    /// changed.
+    pub namespace: Option<Urn>,
+    /// The [`Urn`] of the person previously associated, if any.
+    pub old: Option<Urn>,
+    /// The [`Urn`] of the person now associated, if any.
+    pub new: Option<Urn>,
+}
+
+impl Change {
+    pub fn is_changed(&self) -> bool {
+        self.old != self.new
+    }
+}
+
+/// A [`Change`] as the payload of an `urn_changed` hook.
+///
+/// The [`Urn`] is the one of the namespace, or of the new (or old) person for
+/// a change of the default, with the path set to `rad/self`. The revisions are
+/// the ids of the persons, or zero if there is none.
+#[cfg(feature = "hooks")]
+impl From<Change> for crate::git::hooks::Data<git_ext::Oid> {
+    fn from(change: Change) -> Self {
+        let id = |urn: Option<Urn>| {
+            urn.map(|urn| urn.id)
+                .unwrap_or_else(|| git2::Oid::zero().into())
+        };
+        let urn = change
+            .namespace
+            .clone()
+            .or_else(|| change.new.clone())
+            .or_else(|| change.old.clone())
+            .expect("a change of the default has an old or new person")
+            .with_path(reflike!("refs/rad/self"));
+        Self {
+            urn,
+            old: id(change.old),
+            new: id(change.new),
+        }
+    }
+}
+
+/// Set the default [`LocalIdentity`] to the person at `person`.
+///
+/// The person must be present in `storage`, pass verification, and be a valid
+/// [`LocalIdentity`].
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn set_default(storage: &Storage, person: &Urn) -> Result<Change, Error> {
+    let local = load(storage, person.clone())?.ok_or_else(|| Error::NotFound(person.clone()))?;
+    let mut config = storage.config()?;
+    let old = config.user()?;
+    let new = Some(local.urn());
+    config.set_user(local)?;
+
+    Ok(Change {
+        namespace: None,
+        old,
+        new,
+    })
+}
+
+/// Remove the default [`LocalIdentity`].
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn clear_default(storage: &Storage) -> Result<Change, Error> {
+    let mut config = storage.config()?;
+    let old = config.user()?;
+    config.set_user(None)?;
+
+    Ok(Change {
+        namespace: None,
+        old,
+        new: None,
+    })
+}
+
+/// Associate the person at `person` with the namespace of `urn`, overriding
+/// the default [`LocalIdentity`] for it.
+///
+/// The person must be a valid [`LocalIdentity`], as for [`set_default`]. The
+/// signed refs of `urn` are updated if the association changed.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn set(storage: &Storage, urn: &Urn, person: &Urn) -> Result<Change, Error> {
+    if !storage.has_urn(urn)? {
+        return Err(Error::NoSuchNamespace(urn.clone()));
+    }
+    let local = load(storage, person.clone())?.ok_or_else(|| Error::NotFound(person.clone()))?;
+    let change = Change {
+        namespace: Some(urn.clone()),
+        old: current(storage, urn)?,
+        new: Some(local.urn()),
+    };
+    if change.is_changed() {
+        local.link(storage, urn)?;
+        Refs::update(storage, urn)?;
+    }
+
+    Ok(change)
+}
+
+/// Remove the association of the namespace of `urn` with a person, such that
+/// the default [`LocalIdentity`] is in effect for it.
+///
+/// The signed refs of `urn` are updated if there was an association.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn clear(storage: &Storage, urn: &Urn) -> Result<Change, Error> {
+    let change = Change {
+        namespace: Some(urn.clone()),
+        old: current(storage, urn)?,
+        new: None,
+    };
+    if let Some(mut r) = storage.reference(&Reference::rad_self(Namespace::from(urn), None))? {
+        r.delete().map_err(storage::Error::from)?;
+        Refs::update(storage, urn)?;
+    }
+
+    Ok(change)
+}
+
+/// The [`Urn`] of the person `rad/self` of `urn` points to, if any.
+fn current(storage: &Storage, urn: &Urn) -> Result<Option<Urn>, Error> {
+    let rad_self =
+        Urn::try_from(Reference::rad_self(Namespace::from(urn), None)).expect("namespace is set");
+    Ok(person::get(storage, &rad_self)?.map(|person| person.urn()))
+}
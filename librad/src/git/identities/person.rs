@@ -10,7 +10,7 @@ use radicle_git_ext::{self as ext, is_not_found_err, OneLevel};
 use super::{
     super::{
         refs::Refs,
-        storage::{self, ReadOnlyStorage as _, Storage},
+        storage::{self, transaction::Previous, ReadOnlyStorage as _, Storage},
         types::Reference,
     },
     common,
@@ -121,8 +121,10 @@ where
     }?;
 
     let urn = person.urn();
-    common::IdRef::from(&urn).create(storage, person.content_id)?;
-    person.link(storage, &urn)?;
+    let mut tx = storage.transaction();
+    common::IdRef::from(&urn).stage_create(storage, &mut tx, person.content_id)?;
+    person.stage_link(&mut tx, &urn);
+    tx.commit()?;
     Refs::update(storage, &urn)?;
 
     Ok(person.into_inner().into_inner())
@@ -146,10 +148,12 @@ where
     let prev = Verifying::from(prev).signed()?;
     let next = identities(storage).update(prev, payload, delegations, storage.signer())?;
 
-    common::IdRef::from(urn).update(storage, next.content_id, "update")?;
+    let mut tx = storage.transaction();
+    common::IdRef::from(urn).stage_update(&mut tx, next.content_id, Previous::Any, "update");
     if let Some(local_id) = whoami.into() {
-        local_id.link(storage, urn)?;
+        local_id.stage_link(&mut tx, urn);
     }
+    tx.commit()?;
     Refs::update(storage, urn)?;
 
     Ok(next)
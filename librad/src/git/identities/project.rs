@@ -8,12 +8,16 @@ use std::{convert::TryFrom, fmt::Debug};
 use either::Either;
 use git_ext::{is_not_found_err, OneLevel};
 
+pub mod delegations;
+pub use delegations::resolve as resolve_delegations;
 pub mod heads;
+pub mod import;
+pub use import::{import_repo, init_from_repo};
 
 use super::{
     super::{
         refs::Refs as Sigrefs,
-        storage::{self, ReadOnlyStorage as _, Storage},
+        storage::{self, transaction::Previous, ReadOnlyStorage as _, Storage},
         types::{namespace, reference, Force, Reference, Single, SymbolicRef},
     },
     common,
@@ -23,7 +27,15 @@ use super::{
 use crate::{
     identities::{
         self,
-        git::{Identities, IndirectDelegation, Project, Revision, VerifiedProject, Verifying},
+        git::{
+            Identities,
+            IndirectDelegation,
+            Project,
+            Revision,
+            Rotation,
+            VerifiedProject,
+            Verifying,
+        },
         urn,
     },
     PeerId,
@@ -201,6 +213,23 @@ pub fn merge(storage: &Storage, urn: &Urn, from: PeerId) -> Result<Project, Erro
     Ok(next)
 }
 
+/// Apply the delegate [`Rotation`] to the [`Project`] at `urn`.
+///
+/// `rad/id` is updated only if the rotated identity verifies, ie. the
+/// signatures collected in `rotation` reach a quorum of both the current and
+/// the new delegations, and only if `rad/id` was not modified concurrently.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn rotate(storage: &Storage, urn: &Urn, rotation: Rotation) -> Result<VerifiedProject, Error> {
+    let prev = verify(storage, urn)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+    let prev_id = prev.content_id;
+    let next = identities(storage).rotate(prev, rotation)?;
+
+    ProjectRefs::Rotate(&next, prev_id).apply(storage)?;
+    Sigrefs::update(storage, urn)?;
+
+    Ok(next)
+}
+
 /// Return the newer of `a` and `b`, or an error if their histories are
 /// unrelated.
 pub fn newer<S>(
@@ -217,21 +246,37 @@ where
 enum ProjectRefs<'a> {
     Create(&'a Project),
     Update(&'a Project, &'a str),
+    Rotate(&'a Project, git_ext::Oid),
 }
 
 impl<'a> ProjectRefs<'a> {
+    /// Apply the ref updates in a single transaction.
     pub fn apply(&self, storage: &Storage) -> Result<(), Error> {
-        for symref in self.delegates() {
-            symref.create(storage.as_raw())?;
+        let mut tx = storage.transaction();
+        for SymbolicRef { source, target, .. } in self.delegates() {
+            let msg = format!("creating symbolic ref {} -> {}", source, target);
+            tx.symbolic(source, target, &msg);
         }
         match self {
-            Self::Create(project) => {
-                common::IdRef::from(&project.urn()).create(storage, project.content_id)
-            },
-            Self::Update(project, msg) => {
-                common::IdRef::from(&project.urn()).update(storage, project.content_id, msg)
-            },
-        }?;
+            Self::Create(project) => common::IdRef::from(&project.urn()).stage_create(
+                storage,
+                &mut tx,
+                project.content_id,
+            )?,
+            Self::Update(project, msg) => common::IdRef::from(&project.urn()).stage_update(
+                &mut tx,
+                project.content_id,
+                Previous::Any,
+                msg,
+            ),
+            Self::Rotate(project, previous) => common::IdRef::from(&project.urn()).stage_update(
+                &mut tx,
+                project.content_id,
+                Previous::Is(*previous),
+                "rotate",
+            ),
+        }
+        tx.commit()?;
 
         Ok(())
     }
@@ -240,6 +285,7 @@ impl<'a> ProjectRefs<'a> {
         match self {
             Self::Create(project) => project,
             Self::Update(project, _) => project,
+            Self::Rotate(project, _) => project,
         }
     }
 
@@ -0,0 +1,185 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Resolution of the keys which may sign on behalf of a project.
+//!
+//! A project delegates either directly to keys, or indirectly to persons,
+//! which in turn delegate to the keys of their devices. [`resolve`] flattens
+//! this into the set of [`PeerId`]s authorised to act for the project, and
+//! records which identity authorised each of them. As resolving requires
+//! verifying the project and all its delegate persons, the result can be kept
+//! in a [`Cache`], which must be [`Cache::invalidate`]d whenever one of these
+//! identities changes.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use either::Either::{Left, Right};
+use parking_lot::Mutex;
+
+use super::{
+    super::{error::Error, person},
+    storage,
+    Revision,
+    Urn,
+};
+use crate::{identities::delegation::Delegations as _, PeerId};
+
+/// How a [`PeerId`] is authorised to act for a project.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Authority {
+    /// The identity delegating to the key: the project itself for direct
+    /// delegations, or the delegate person otherwise.
+    pub via: Urn,
+    /// The revision of `via` at which the key was resolved.
+    pub revision: Revision,
+}
+
+/// The [`PeerId`]s authorised to act for a project.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resolved {
+    pub urn: Urn,
+    /// The revision of the project the delegations were resolved at.
+    pub revision: Revision,
+    pub peers: BTreeMap<PeerId, Authority>,
+}
+
+impl Resolved {
+    pub fn is_authorised(&self, peer: &PeerId) -> bool {
+        self.peers.contains_key(peer)
+    }
+
+    /// The identities this resolution was derived from.
+    pub fn depends_on(&self) -> BTreeSet<&Urn> {
+        self.peers
+            .values()
+            .map(|auth| &auth.via)
+            .chain(Some(&self.urn))
+            .collect()
+    }
+}
+
+/// Resolve the [`PeerId`]s authorised to act for the project `urn`.
+///
+/// Delegate persons are resolved at the latest revision found in `storage`,
+/// falling back to the revision the project refers to if the person's
+/// namespace is absent. Revoked keys are not authorised. A key which is both
+/// delegated to directly and via a person is attributed to the project.
+///
+/// If the project is not found, `None` is returned.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn resolve<S>(storage: &S, urn: &Urn) -> Result<Option<Resolved>, Error>
+where
+    S: AsRef<storage::ReadOnly>,
+{
+    let storage = storage.as_ref();
+    let project = match super::verify(storage, urn)? {
+        None => return Ok(None),
+        Some(project) => project,
+    };
+
+    let mut peers = BTreeMap::new();
+    let mut persons = Vec::new();
+    for delegation in project.delegations() {
+        match delegation {
+            Left(key) => {
+                if project.permits(key, None) {
+                    peers.insert(
+                        PeerId::from(*key),
+                        Authority {
+                            via: project.urn(),
+                            revision: project.revision,
+                        },
+                    );
+                }
+            },
+            Right(person) => persons.push(person),
+        }
+    }
+
+    for embedded in persons {
+        let latest = person::verify(storage, &embedded.urn())?;
+        let (keys, revision) = match &latest {
+            Some(person) => (
+                person
+                    .delegations()
+                    .iter()
+                    .filter(|key| person.permits(key, None))
+                    .copied()
+                    .collect::<Vec<_>>(),
+                person.revision,
+            ),
+            None => (
+                embedded
+                    .delegations()
+                    .iter()
+                    .filter(|key| embedded.permits(key, None))
+                    .copied()
+                    .collect(),
+                embedded.revision,
+            ),
+        };
+        for key in keys {
+            peers.entry(PeerId::from(key)).or_insert_with(|| Authority {
+                via: embedded.urn(),
+                revision,
+            });
+        }
+    }
+
+    Ok(Some(Resolved {
+        urn: project.urn(),
+        revision: project.revision,
+        peers,
+    }))
+}
+
+/// A cache of [`resolve`]d delegations.
+///
+/// Cloning yields a handle to the same cache.
+#[derive(Clone, Default)]
+pub struct Cache {
+    entries: Arc<Mutex<HashMap<Urn, Arc<Resolved>>>>,
+}
+
+impl Cache {
+    /// [`resolve`] the delegations of `urn`, or return the cached result.
+    ///
+    /// Projects which are not found are not cached.
+    pub fn resolve<S>(&self, storage: &S, urn: &Urn) -> Result<Option<Arc<Resolved>>, Error>
+    where
+        S: AsRef<storage::ReadOnly>,
+    {
+        let urn = urn.clone().with_path(None);
+        if let Some(resolved) = self.entries.lock().get(&urn) {
+            return Ok(Some(Arc::clone(resolved)));
+        }
+        let resolved = resolve(storage, &urn)?.map(Arc::new);
+        if let Some(resolved) = &resolved {
+            self.entries.lock().insert(urn, Arc::clone(resolved));
+        }
+
+        Ok(resolved)
+    }
+
+    /// Drop all cached results which depend on the identity `urn`.
+    ///
+    /// Must be called whenever a project or person identity is updated, or
+    /// has been replicated. Returns the number of entries dropped.
+    pub fn invalidate(&self, urn: &Urn) -> usize {
+        let urn = urn.clone().with_path(None);
+        let mut entries = self.entries.lock();
+        let before = entries.len();
+        entries.retain(|_, resolved| !resolved.depends_on().contains(&urn));
+        before - entries.len()
+    }
+
+    /// Drop all cached results.
+    pub fn clear(&self) {
+        self.entries.lock().clear()
+    }
+}
@@ -0,0 +1,333 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Publishing an existing git repository as a [`Project`].
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    fmt::Debug,
+    path::{Path, PathBuf},
+};
+
+use git_ext::is_not_found_err;
+use git_ref_format::RefString;
+use thiserror::Error;
+
+use super::{
+    super::{super::refs::Refs as Sigrefs, local::LocalIdentity},
+    create,
+    ProjectPayload,
+};
+use crate::{
+    git::{
+        local::url::LocalUrl,
+        storage::{gc, Storage},
+        Urn,
+    },
+    identities::git::{IndirectDelegation, Project},
+};
+
+/// The name of the remote [`Remote::configure`] adds to the working copy.
+pub const REMOTE_NAME: &str = "rad";
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("`{0}` is not a git repository")]
+    NotARepo(PathBuf),
+
+    #[error("no branches to import")]
+    NoBranches,
+
+    #[error("the default branch `{0}` is not among the imported branches")]
+    MissingDefaultBranch(String),
+
+    #[error("invalid branch name `{0}`")]
+    BranchName(String),
+
+    #[error("branch `{0}` not found")]
+    MissingBranch(RefString),
+
+    #[error("more than one branch would be imported as `{0}`")]
+    DuplicateBranch(RefString),
+
+    #[error(transparent)]
+    Identities(#[from] super::Error),
+
+    #[error(transparent)]
+    Sigrefs(#[from] crate::git::refs::stored::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// Which branches of the repository to import.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Branches {
+    /// All local branches.
+    All,
+    /// Only the branch which becomes the default branch of the project.
+    Default,
+    /// Only the named local branches.
+    Only(BTreeSet<RefString>),
+}
+
+impl Default for Branches {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+/// Options for [`init_from_repo`].
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Default: [`Branches::All`]
+    pub branches: Branches,
+    /// Rename branches when importing, eg. `master` to `main`. Branches not
+    /// mentioned keep their name.
+    pub rename: BTreeMap<RefString, RefString>,
+    /// Import tags, too.
+    ///
+    /// Default: `true`
+    pub tags: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            branches: Branches::default(),
+            rename: BTreeMap::new(),
+            tags: true,
+        }
+    }
+}
+
+/// The result of [`init_from_repo`].
+#[derive(Clone, Debug)]
+pub struct Imported {
+    pub project: Project,
+    /// The branches imported, mapping the name in the repository to the name
+    /// in the project.
+    pub branches: BTreeMap<RefString, RefString>,
+    /// The remote to configure in the working copy.
+    pub remote: Remote,
+}
+
+/// Configuration of the remote pointing to the project in the working copy
+/// the project was imported from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Remote {
+    pub name: String,
+    pub url: LocalUrl,
+    /// Push refspecs mapping the branches of the working copy to their names in
+    /// the project.
+    pub push: Vec<String>,
+    /// The branch in the working copy which corresponds to the default branch
+    /// of the project, and its name in the project.
+    pub upstream: Option<(RefString, RefString)>,
+}
+
+impl Remote {
+    /// Add the remote to `repo`, and set it as the upstream of the default
+    /// branch.
+    ///
+    /// Fails if a remote of the same name already exists.
+    pub fn configure(&self, repo: &git2::Repository) -> Result<(), git2::Error> {
+        repo.remote(&self.name, &self.url.to_string())?;
+        for spec in &self.push {
+            repo.remote_add_push(&self.name, spec)?;
+        }
+        if let Some((local, remote)) = &self.upstream {
+            let mut config = repo.config()?;
+            config.set_str(&format!("branch.{}.remote", local), &self.name)?;
+            config.set_str(
+                &format!("branch.{}.merge", local),
+                &format!("refs/heads/{}", remote),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Create a [`Project`] from the existing git repository at `path`.
+///
+/// The branches selected by [`Options`] (and optionally the tags) are imported
+/// into the project's namespace, and the `rad/signed_refs` are updated. If the
+/// `payload` does not specify a default branch, the branch `HEAD` of the
+/// repository points to is used.
+///
+/// The repository itself is not modified. To make it a working copy of the
+/// project, use [`Remote::configure`] on the returned [`Imported::remote`].
+///
+/// The import is all-or-nothing: if importing the branches fails after the
+/// project was created, its namespace is removed again.
+#[tracing::instrument(level = "debug", skip(storage, whoami, path), fields(path = %path.as_ref().display()))]
+pub fn init_from_repo<P>(
+    storage: &Storage,
+    whoami: LocalIdentity,
+    payload: P,
+    delegations: IndirectDelegation,
+    path: impl AsRef<Path>,
+    opts: Options,
+) -> Result<Imported, Error>
+where
+    P: Into<ProjectPayload> + Debug,
+{
+    let path = path.as_ref();
+    let repo = git2::Repository::open(path).map_err(|e| {
+        if is_not_found_err(&e) {
+            Error::NotARepo(path.to_path_buf())
+        } else {
+            e.into()
+        }
+    })?;
+
+    let mut branches = select_branches(&repo, &opts)?;
+    if branches.is_empty() {
+        return Err(Error::NoBranches);
+    }
+
+    let mut payload = payload.into();
+    let upstream = match &payload.subject.default_branch {
+        Some(default) => branches
+            .iter()
+            .find(|(_, to)| to.as_str() == default.as_str())
+            .map(|(from, to)| (from.clone(), to.clone()))
+            .ok_or_else(|| Error::MissingDefaultBranch(default.to_string()))?,
+        None => {
+            let head = repo.head()?;
+            let head = head
+                .shorthand()
+                .filter(|_| head.is_branch())
+                .ok_or_else(|| Error::MissingDefaultBranch("HEAD".to_owned()))?;
+            let (from, to) = branches
+                .iter()
+                .find(|(from, _)| from.as_str() == head)
+                .ok_or_else(|| Error::MissingDefaultBranch(head.to_owned()))?;
+            payload.subject.default_branch = Some(to.as_str().into());
+            (from.clone(), to.clone())
+        },
+    };
+
+    if opts.branches == Branches::Default {
+        branches.retain(|from, _| from == &upstream.0);
+    }
+
+    let project = create(storage, whoami, payload, delegations)?;
+    let urn = project.urn();
+    if let Err(e) = import_refs(storage, &urn, &repo, &branches, opts.tags) {
+        if let Err(err) = gc::remove_namespace(storage, &urn) {
+            tracing::warn!(urn = %urn, err = %err, "failed to remove partially imported project");
+        }
+        return Err(e);
+    }
+
+    let remote = Remote {
+        name: REMOTE_NAME.to_owned(),
+        url: LocalUrl::from(urn),
+        push: branches
+            .iter()
+            .map(|(from, to)| format!("refs/heads/{}:refs/heads/{}", from, to))
+            .collect(),
+        upstream: Some(upstream),
+    };
+
+    Ok(Imported {
+        project,
+        branches,
+        remote,
+    })
+}
+
+/// Create a [`Project`] from the default branch of the existing git repository
+/// at `path`, and return its [`Urn`].
+///
+/// This is a shorthand for [`init_from_repo`], importing neither other branches
+/// nor tags.
+pub fn import_repo<P>(
+    storage: &Storage,
+    whoami: LocalIdentity,
+    payload: P,
+    delegations: IndirectDelegation,
+    path: impl AsRef<Path>,
+) -> Result<Urn, Error>
+where
+    P: Into<ProjectPayload> + Debug,
+{
+    let opts = Options {
+        branches: Branches::Default,
+        tags: false,
+        ..Options::default()
+    };
+    init_from_repo(storage, whoami, payload, delegations, path, opts).map(|i| i.project.urn())
+}
+
+fn import_refs(
+    storage: &Storage,
+    urn: &Urn,
+    repo: &git2::Repository,
+    branches: &BTreeMap<RefString, RefString>,
+    tags: bool,
+) -> Result<(), Error> {
+    let namespace = format!("refs/namespaces/{}", urn.encode_id());
+    let mut specs = branches
+        .iter()
+        .map(|(from, to)| format!("+refs/heads/{}:{}/refs/heads/{}", from, namespace, to))
+        .collect::<Vec<_>>();
+    if tags {
+        specs.push(format!("+refs/tags/*:{}/refs/tags/*", namespace));
+    }
+    {
+        let mut fetch_opts = git2::FetchOptions::new();
+        fetch_opts.download_tags(git2::AutotagOption::None);
+        let mut remote = storage
+            .as_raw()
+            .remote_anonymous(&repo.path().to_string_lossy())?;
+        remote.fetch(&specs, Some(&mut fetch_opts), None)?;
+    }
+    Sigrefs::update(storage, urn)?;
+
+    Ok(())
+}
+
+fn select_branches(
+    repo: &git2::Repository,
+    opts: &Options,
+) -> Result<BTreeMap<RefString, RefString>, Error> {
+    let mut branches = BTreeMap::new();
+    for r in repo.references_glob("refs/heads/*")? {
+        let r = r?;
+        let name = match r.name().and_then(|name| name.strip_prefix("refs/heads/")) {
+            Some(name) => name,
+            None => continue,
+        };
+        let name = RefString::try_from(name).map_err(|_| Error::BranchName(name.to_owned()))?;
+        if let Branches::Only(only) = &opts.branches {
+            if !only.contains(&name) {
+                continue;
+            }
+        }
+        let to = opts
+            .rename
+            .get(&name)
+            .cloned()
+            .unwrap_or_else(|| name.clone());
+        branches.insert(name, to);
+    }
+
+    if let Branches::Only(only) = &opts.branches {
+        if let Some(missing) = only.iter().find(|name| !branches.contains_key(*name)) {
+            return Err(Error::MissingBranch(missing.clone()));
+        }
+    }
+    let mut targets = BTreeSet::new();
+    for to in branches.values() {
+        if !targets.insert(to) {
+            return Err(Error::DuplicateBranch(to.clone()));
+        }
+    }
+
+    Ok(branches)
+}
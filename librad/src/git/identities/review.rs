@@ -0,0 +1,142 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Review policy declared by a project.
+//!
+//! A project may declare a [`Review`] policy in its payload, mapping path
+//! prefixes of its source tree to the keys which must approve changes to
+//! them -- similar to a `CODEOWNERS` file, except that it is versioned and
+//! signed along with the project identity. Seeds may enforce the policy on
+//! updates of the default branch, see
+//! [`crate::net::protocol::request_pull::review`].
+//!
+//! The policy of the example below requires one approval from either of two
+//! keys for all paths, and two approvals from a set of three keys for paths
+//! under `librad/src/net`:
+//!
+//! ```json
+//! "https://radicle.xyz/link/identities/review/v1": {
+//!   "rules": [
+//!     { "path": "", "approvers": ["hyn...", "hyb..."] },
+//!     { "path": "librad/src/net", "approvers": ["hyd...", "hyo...", "hyy..."], "required": 2 }
+//!   ]
+//! }
+//! ```
+
+use std::collections::BTreeSet;
+
+use thiserror::Error;
+use url::Url;
+
+use super::{super::storage, any};
+use crate::{
+    identities::{
+        git::{SomeIdentity, Urn},
+        payload::HasNamespace,
+    },
+    PublicKey,
+};
+
+lazy_static! {
+    static ref REVIEW_NAMESPACE: Url =
+        Url::parse("https://radicle.xyz/link/identities/review/v1").unwrap();
+}
+
+/// Payload extension declaring the review [`Rule`]s of a project.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Review {
+    pub rules: Vec<Rule>,
+}
+
+impl HasNamespace for Review {
+    fn namespace() -> &'static Url {
+        &REVIEW_NAMESPACE
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Rule {
+    /// The path prefix the rule applies to, relative to the root of the
+    /// source tree. Prefixes match whole path components, the empty prefix
+    /// matches all paths.
+    pub path: String,
+    /// The keys which may approve changes to paths under [`Rule::path`].
+    pub approvers: BTreeSet<PublicKey>,
+    /// The number of distinct [`Rule::approvers`] required to approve a
+    /// change.
+    #[serde(default = "Rule::default_required")]
+    pub required: usize,
+}
+
+impl Rule {
+    fn default_required() -> usize {
+        1
+    }
+
+    fn prefix(&self) -> &str {
+        self.path.trim_matches('/')
+    }
+
+    /// Whether the rule applies to `path`.
+    pub fn matches(&self, path: &str) -> bool {
+        let prefix = self.prefix();
+        prefix.is_empty()
+            || path
+                .strip_prefix(prefix)
+                .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+    }
+}
+
+impl Review {
+    /// The rule applying to `path`, if any.
+    ///
+    /// If several rules match, the one with the longest [`Rule::path`] takes
+    /// precedence, and among those the last one declared.
+    pub fn rule(&self, path: &str) -> Option<&Rule> {
+        let mut best: Option<&Rule> = None;
+        for rule in self.rules.iter().filter(|rule| rule.matches(path)) {
+            match best {
+                Some(b) if b.prefix().len() > rule.prefix().len() => {},
+                _ => best = Some(rule),
+            }
+        }
+        best
+    }
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("invalid review policy in payload of {urn}")]
+    Ext {
+        urn: Urn,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error(transparent)]
+    Identities(#[from] super::Error),
+}
+
+/// Read the [`Review`] policy declared by the project `urn`.
+///
+/// Returns `None` if `urn` is not found, is not a project, or does not
+/// declare a review policy.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn get<S>(storage: &S, urn: &Urn) -> Result<Option<Review>, Error>
+where
+    S: AsRef<storage::ReadOnly>,
+{
+    match any::get(storage, &urn.clone().with_path(None))? {
+        Some(SomeIdentity::Project(project)) => {
+            project
+                .payload()
+                .get_ext::<Review>()
+                .map_err(|source| Error::Ext {
+                    urn: urn.clone(),
+                    source,
+                })
+        },
+        _ => Ok(None),
+    }
+}
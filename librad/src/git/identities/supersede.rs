@@ -0,0 +1,214 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Projects superseded by other projects.
+//!
+//! A project which can no longer be maintained under its URN -- eg. because
+//! delegates lost their keys, or the project was forked for good -- may
+//! declare that it is superseded by another URN. The declaration is a
+//! [`Supersede`] extension of the project payload, and as such part of the
+//! signed identity document stored under the old namespace. It only takes
+//! effect once the identity update carrying it is signed by a quorum of
+//! delegates, ie. it is only honoured on a verified project.
+//!
+//! [`resolve`] follows declarations transitively, up to a bounded depth.
+//! [`inherit_tracking`] lets trackers of a superseded project converge on its
+//! successor.
+
+use std::collections::BTreeSet;
+
+use thiserror::Error;
+use url::Url;
+
+use super::{
+    super::{
+        storage::{self, Storage},
+        tracking,
+    },
+    project,
+};
+use crate::identities::{
+    git::{Project, Urn},
+    payload::{ExtError, HasNamespace},
+};
+
+lazy_static! {
+    static ref SUPERSEDE_NAMESPACE: Url =
+        Url::parse("https://radicle.xyz/link/identities/supersede/v1").unwrap();
+}
+
+/// Payload extension declaring that a project is superseded by the project
+/// `urn`.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Supersede {
+    pub urn: Urn,
+    /// Human-readable explanation, eg. for display by clients.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl HasNamespace for Supersede {
+    fn namespace() -> &'static Url {
+        &SUPERSEDE_NAMESPACE
+    }
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("the project {0} does not exist")]
+    NotFound(Urn),
+
+    #[error("{0} can't supersede itself")]
+    SelfReference(Urn),
+
+    #[error("supersede declarations of {0} form a cycle")]
+    Cycle(Urn),
+
+    #[error("invalid supersede declaration in payload of {urn}")]
+    Ext {
+        urn: Urn,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error(transparent)]
+    Payload(#[from] ExtError),
+
+    #[error(transparent)]
+    Identities(#[from] super::Error),
+
+    #[error(transparent)]
+    Tracked(#[from] tracking::error::Tracked),
+
+    #[error(transparent)]
+    Track(#[from] tracking::error::Track),
+}
+
+/// Declare that the project `urn` is superseded by `by`.
+///
+/// This updates the project identity with the local signature. As with any
+/// identity update, the declaration only takes effect once a quorum of
+/// delegates signed it.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn supersede(
+    storage: &Storage,
+    urn: &Urn,
+    by: Urn,
+    reason: Option<String>,
+) -> Result<Project, Error> {
+    let urn = urn.clone().with_path(None);
+    let by = by.with_path(None);
+    if by == urn {
+        return Err(Error::SelfReference(urn));
+    }
+    let project = project::get(storage, &urn)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+    let mut payload = project.payload().clone();
+    payload.set_ext(Supersede { urn: by, reason })?;
+
+    Ok(project::update(storage, &urn, None, payload, None)?)
+}
+
+/// Read the [`Supersede`] declaration of the project `urn`.
+///
+/// Returns `None` if `urn` is not found, does not verify, or is not
+/// superseded.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn get<S>(storage: &S, urn: &Urn) -> Result<Option<Supersede>, Error>
+where
+    S: AsRef<storage::ReadOnly>,
+{
+    let urn = urn.clone().with_path(None);
+    match project::verify(storage, &urn)? {
+        None => Ok(None),
+        Some(project) => project
+            .payload()
+            .get_ext::<Supersede>()
+            .map_err(|source| Error::Ext { urn, source }),
+    }
+}
+
+/// A [`Supersede`] declaration followed by [`resolve`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hop {
+    /// The superseded project.
+    pub from: Urn,
+    pub supersede: Supersede,
+}
+
+/// The outcome of [`resolve`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resolved {
+    /// The project declarations lead to.
+    ///
+    /// Note that it may not (yet) be present in local storage.
+    pub urn: Urn,
+    /// The declarations followed, in order.
+    pub hops: Vec<Hop>,
+}
+
+impl Resolved {
+    pub fn is_superseded(&self) -> bool {
+        !self.hops.is_empty()
+    }
+}
+
+/// Follow the [`Supersede`] declarations starting at `urn`, up to
+/// `max_depth` hops.
+///
+/// # Errors
+///
+/// If the declarations form a cycle.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn resolve<S>(storage: &S, urn: &Urn, max_depth: usize) -> Result<Resolved, Error>
+where
+    S: AsRef<storage::ReadOnly>,
+{
+    let mut current = urn.clone().with_path(None);
+    let mut seen = BTreeSet::from([current.clone()]);
+    let mut hops = Vec::new();
+    while hops.len() < max_depth {
+        let supersede = match get(storage, &current)? {
+            None => break,
+            Some(supersede) => supersede,
+        };
+        let next = supersede.urn.clone().with_path(None);
+        if !seen.insert(next.clone()) {
+            return Err(Error::Cycle(urn.clone()));
+        }
+        hops.push(Hop {
+            from: current,
+            supersede,
+        });
+        current = next;
+    }
+
+    Ok(Resolved { urn: current, hops })
+}
+
+/// Track the peers tracked for `from` also for `to`, retaining their
+/// tracking configuration.
+///
+/// Existing tracking entries of `to` are left as they are. Returns the number
+/// of entries created.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn inherit_tracking(storage: &Storage, from: &Urn, to: &Urn) -> Result<usize, Error> {
+    let to = to.clone().with_path(None);
+    let mut created = 0;
+    for tracked in tracking::tracked(storage, Some(&from.clone().with_path(None)))? {
+        let tracked = tracked?;
+        if tracking::track(
+            storage,
+            &to,
+            tracked.peer_id(),
+            tracked.config().clone(),
+            tracking::policy::Track::MustNotExist,
+        )?
+        .is_ok()
+        {
+            created += 1;
+        }
+    }
+
+    Ok(created)
+}
@@ -0,0 +1,85 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Webhook endpoints declared by a project.
+//!
+//! A project may declare [`Webhooks`] in its payload, asking seeds which
+//! replicate it to notify the endpoints about events concerning the project,
+//! such as replication or request-pulls. Seeds are free to ignore them.
+//!
+//! Since the payload is public, a [`Webhook`] does not carry the shared secret
+//! used to sign requests, but only a reference to it. It is up to the operator
+//! of a seed to resolve the reference, eg. by provisioning a file of that
+//! name.
+
+use thiserror::Error;
+use url::Url;
+
+use super::{super::storage, any};
+use crate::identities::{
+    git::{SomeIdentity, Urn},
+    payload::HasNamespace,
+};
+
+lazy_static! {
+    static ref WEBHOOKS_NAMESPACE: Url =
+        Url::parse("https://radicle.xyz/link/identities/webhooks/v1").unwrap();
+}
+
+/// Payload extension declaring the [`Webhook`]s of a project.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct Webhooks(pub Vec<Webhook>);
+
+impl HasNamespace for Webhooks {
+    fn namespace() -> &'static Url {
+        &WEBHOOKS_NAMESPACE
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Webhook {
+    /// The endpoint to `POST` events to.
+    pub url: Url,
+    /// The name of the shared secret to sign requests with.
+    ///
+    /// If absent, requests are not signed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("invalid webhooks in payload of {urn}")]
+    Ext {
+        urn: Urn,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error(transparent)]
+    Identities(#[from] super::Error),
+}
+
+/// Read the [`Webhooks`] declared by the project `urn`.
+///
+/// Returns `None` if `urn` is not found, or is not a project. A project
+/// without webhooks yields an empty [`Webhooks`].
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn get<S>(storage: &S, urn: &Urn) -> Result<Option<Webhooks>, Error>
+where
+    S: AsRef<storage::ReadOnly>,
+{
+    match any::get(storage, &urn.clone().with_path(None))? {
+        Some(SomeIdentity::Project(project)) => project
+            .payload()
+            .get_ext::<Webhooks>()
+            .map(|hooks| Some(hooks.unwrap_or_default()))
+            .map_err(|source| Error::Ext {
+                urn: urn.clone(),
+                source,
+            }),
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,485 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Mirroring namespaces to external git remotes.
+//!
+//! A [`Mirror`] pushes selected refs of a namespace to a regular git remote,
+//! eg. a read-only mirror of a project hosted on a code forge. The refs are
+//! taken from the [`export::layout`] of the namespace, so the mirror looks
+//! like a conventional repository: `refs/heads/*`, `refs/tags/*`, and -- if
+//! selected -- `refs/remotes/<peer>/*`.
+//!
+//! Mirrors are configured in [`Paths::mirrors_file`], a JSON array of
+//! [`Mirror`]s. What was last pushed to each of them is recorded in the
+//! [`State`], stored at [`Paths::mirrors_state_file`]. [`sync`] only pushes
+//! refs which changed since, and deletes refs from the remote which were
+//! pushed before, but no longer exist or are no longer selected. Refs on the
+//! remote which were not pushed by the mirror are left alone.
+//!
+//! Failed syncs are recorded in the [`Status`] of the mirror, and should be
+//! retried no earlier than [`Status::retry_at`].
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    fs,
+    io,
+    path::PathBuf,
+    time::Duration,
+};
+
+use git_ext as ext;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std_ext::time::unix_now;
+use thiserror::Error;
+
+use crate::{
+    git::{
+        storage::{export, ReadOnly},
+        Urn,
+    },
+    paths::Paths,
+};
+
+/// The refs a [`Mirror`] pushes if none are configured.
+pub const DEFAULT_REFS: [&str; 2] = ["refs/heads/*", "refs/tags/*"];
+
+/// Maximum number of times credentials are requested per [`sync`].
+const MAX_CREDENTIAL_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("failed to read `{path}`")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("failed to write `{path}`")]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("malformed `{path}`")]
+    Malformed {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("invalid pattern `{pattern}`")]
+    Pattern {
+        pattern: String,
+        #[source]
+        source: globset::Error,
+    },
+
+    #[error("remote rejected {}: {}", .refname, .reason)]
+    Rejected { refname: String, reason: String },
+
+    #[error(transparent)]
+    Export(#[from] export::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// An external remote the namespace `urn` is mirrored to.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mirror {
+    pub urn: Urn,
+    /// Identifies the mirror among the mirrors of `urn`.
+    pub name: String,
+    /// The URL of the remote, in any form understood by `git`.
+    pub url: String,
+    /// Globs over the names of the refs to push, eg. `refs/heads/main`. `*`
+    /// does not match across `/`, use `**` for that.
+    ///
+    /// Default: [`DEFAULT_REFS`]
+    #[serde(default = "default_refs")]
+    pub refs: Vec<String>,
+    /// The name of the credential to authenticate with, passed on to
+    /// [`Credentials`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+}
+
+fn default_refs() -> Vec<String> {
+    DEFAULT_REFS.iter().map(|s| s.to_string()).collect()
+}
+
+impl Mirror {
+    fn patterns(&self) -> Result<GlobSet, Error> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &self.refs {
+            let glob = GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(|source| Error::Pattern {
+                    pattern: pattern.clone(),
+                    source,
+                })?;
+            builder.add(glob);
+        }
+        builder.build().map_err(|source| Error::Pattern {
+            pattern: self.refs.join(", "),
+            source,
+        })
+    }
+}
+
+/// Load the mirrors configured in [`Paths::mirrors_file`].
+///
+/// If the file does not exist, no mirrors are configured.
+pub fn load(paths: &Paths) -> Result<Vec<Mirror>, Error> {
+    let path = paths.mirrors_file();
+    match fs::read(path) {
+        Ok(json) => serde_json::from_slice(&json).map_err(|source| Error::Malformed {
+            path: path.to_path_buf(),
+            source,
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(source) => Err(Error::Read {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+/// Supplies credentials for pushing to a [`Mirror`].
+///
+/// The arguments are those of [`git2::RemoteCallbacks::credentials`]. Any
+/// `Fn(&Mirror, &str, Option<&str>, git2::CredentialType) -> Result<git2::Cred,
+/// git2::Error>` is a [`Credentials`].
+pub trait Credentials {
+    fn credentials(
+        &self,
+        mirror: &Mirror,
+        url: &str,
+        username: Option<&str>,
+        allowed: git2::CredentialType,
+    ) -> Result<git2::Cred, git2::Error>;
+}
+
+impl<F> Credentials for F
+where
+    F: Fn(&Mirror, &str, Option<&str>, git2::CredentialType) -> Result<git2::Cred, git2::Error>,
+{
+    fn credentials(
+        &self,
+        mirror: &Mirror,
+        url: &str,
+        username: Option<&str>,
+        allowed: git2::CredentialType,
+    ) -> Result<git2::Cred, git2::Error> {
+        self(mirror, url, username, allowed)
+    }
+}
+
+/// [`Credentials`] for remotes which don't require authentication.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Anonymous;
+
+impl Credentials for Anonymous {
+    fn credentials(
+        &self,
+        mirror: &Mirror,
+        _: &str,
+        _: Option<&str>,
+        _: git2::CredentialType,
+    ) -> Result<git2::Cred, git2::Error> {
+        Err(git2::Error::from_str(&format!(
+            "no credentials for mirror `{}`",
+            mirror.name
+        )))
+    }
+}
+
+/// Exponential backoff of retries after failed syncs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Backoff {
+    /// The delay after the first failure, doubled with every subsequent one.
+    ///
+    /// Default: 30s
+    pub initial: Duration,
+    /// The maximum delay.
+    ///
+    /// Default: 1h
+    pub max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(30),
+            max: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl Backoff {
+    /// The delay before retrying after `failures` consecutive failures.
+    pub fn delay(&self, failures: u32) -> Duration {
+        match failures {
+            0 => Duration::ZERO,
+            n => self
+                .initial
+                .checked_mul(1 << (n - 1).min(31))
+                .map_or(self.max, |delay| delay.min(self.max)),
+        }
+    }
+}
+
+/// The sync state of a [`Mirror`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    /// The refs last pushed to the mirror, by name.
+    pub refs: BTreeMap<String, ext::Oid>,
+    /// When the mirror was last synced successfully, in seconds since the
+    /// epoch.
+    pub last_success: Option<u64>,
+    /// When a sync was last attempted, in seconds since the epoch.
+    pub last_attempt: Option<u64>,
+    /// The number of failed syncs since the last successful one.
+    pub failures: u32,
+    /// The error the last sync failed with, if it failed.
+    pub last_error: Option<String>,
+}
+
+impl Status {
+    /// When the mirror should be retried, in seconds since the epoch, if the
+    /// last sync failed.
+    pub fn retry_at(&self, backoff: &Backoff) -> Option<u64> {
+        if self.failures == 0 {
+            return None;
+        }
+        let last = self.last_attempt.unwrap_or_default();
+        Some(last.saturating_add(backoff.delay(self.failures).as_secs()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    urn: Urn,
+    name: String,
+    status: Status,
+}
+
+/// The [`Status`] of all mirrors.
+#[derive(Clone, Debug, Default)]
+pub struct State {
+    file: Option<PathBuf>,
+    mirrors: BTreeMap<(Urn, String), Status>,
+}
+
+impl State {
+    /// Load the state from [`Paths::mirrors_state_file`].
+    ///
+    /// If the file does not exist yet, no mirror has been synced.
+    pub fn load(paths: &Paths) -> Result<Self, Error> {
+        let file = paths.mirrors_state_file().to_path_buf();
+        let entries: Vec<Entry> = match fs::read(&file) {
+            Ok(json) => serde_json::from_slice(&json).map_err(|source| Error::Malformed {
+                path: file.clone(),
+                source,
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(source) => return Err(Error::Read { path: file, source }),
+        };
+
+        Ok(Self {
+            file: Some(file),
+            mirrors: entries
+                .into_iter()
+                .map(|Entry { urn, name, status }| ((urn, name), status))
+                .collect(),
+        })
+    }
+
+    /// A state which is never persisted.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Write the state back to disk.
+    pub fn save(&self) -> Result<(), Error> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let entries = self
+            .mirrors
+            .iter()
+            .map(|((urn, name), status)| Entry {
+                urn: urn.clone(),
+                name: name.clone(),
+                status: status.clone(),
+            })
+            .collect::<Vec<_>>();
+        let json = serde_json::to_vec(&entries).map_err(|source| Error::Malformed {
+            path: file.clone(),
+            source,
+        })?;
+        let tmp = file.with_extension("json.tmp");
+        fs::write(&tmp, json)
+            .and_then(|()| fs::rename(&tmp, file))
+            .map_err(|source| Error::Write {
+                path: file.clone(),
+                source,
+            })
+    }
+
+    pub fn get(&self, mirror: &Mirror) -> Option<&Status> {
+        self.mirrors.get(&key(mirror))
+    }
+
+    /// The `mirrors` whose last sync failed, and which are due to be retried
+    /// as of `now`, in seconds since the epoch.
+    pub fn due<'a>(
+        &'a self,
+        mirrors: &'a [Mirror],
+        backoff: &'a Backoff,
+        now: u64,
+    ) -> impl Iterator<Item = &'a Mirror> + 'a {
+        mirrors.iter().filter(move |mirror| {
+            self.get(mirror)
+                .and_then(|status| status.retry_at(backoff))
+                .map_or(false, |at| at <= now)
+        })
+    }
+
+    /// Forget the state of mirrors which are not among `mirrors`.
+    pub fn prune(&mut self, mirrors: &[Mirror]) {
+        self.mirrors
+            .retain(|(urn, name), _| mirrors.iter().any(|m| &m.urn == urn && &m.name == name))
+    }
+}
+
+fn key(mirror: &Mirror) -> (Urn, String) {
+    (mirror.urn.clone(), mirror.name.clone())
+}
+
+/// The outcome of a successful [`sync`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Synced {
+    /// The refs which were created or updated on the remote.
+    pub updated: Vec<String>,
+    /// The refs which were deleted from the remote.
+    pub deleted: Vec<String>,
+}
+
+/// Push the changes to the refs selected by `mirror` since the last sync.
+///
+/// The outcome is recorded in `state`, which the caller is responsible for
+/// [`State::save`]ing. Refs are force-pushed, as the history of the branches
+/// of a namespace can be rewritten by their owners.
+#[tracing::instrument(level = "debug", skip(storage, state, mirror, creds), fields(urn = %mirror.urn, mirror = %mirror.name))]
+pub fn sync<S, C>(
+    storage: &S,
+    state: &mut State,
+    mirror: &Mirror,
+    creds: &C,
+) -> Result<Synced, Error>
+where
+    S: AsRef<ReadOnly>,
+    C: Credentials,
+{
+    let status = state.mirrors.entry(key(mirror)).or_default();
+    let now = unix_now();
+    status.last_attempt = Some(now);
+    match push(storage.as_ref(), &status.refs, mirror, creds) {
+        Ok((refs, synced)) => {
+            status.refs = refs;
+            status.last_success = Some(now);
+            status.failures = 0;
+            status.last_error = None;
+            Ok(synced)
+        },
+        Err(e) => {
+            status.failures = status.failures.saturating_add(1);
+            status.last_error = Some(e.to_string());
+            Err(e)
+        },
+    }
+}
+
+fn push<C>(
+    storage: &ReadOnly,
+    pushed: &BTreeMap<String, ext::Oid>,
+    mirror: &Mirror,
+    creds: &C,
+) -> Result<(BTreeMap<String, ext::Oid>, Synced), Error>
+where
+    C: Credentials,
+{
+    let patterns = mirror.patterns()?;
+    let export::Layout { refs, sources, .. } =
+        export::layout(storage, &mirror.urn, export::Options::default())?;
+
+    let mut synced = Synced::default();
+    let mut specs = Vec::new();
+    let mut wanted = BTreeMap::new();
+    for (name, oid) in refs {
+        if !patterns.is_match(&name) {
+            continue;
+        }
+        if pushed.get(&name) != Some(&oid) {
+            if let Some(source) = sources.get(&name) {
+                specs.push(format!("+{}:{}", source, name));
+                synced.updated.push(name.clone());
+            }
+        }
+        wanted.insert(name, oid);
+    }
+    for name in pushed.keys() {
+        if !wanted.contains_key(name) {
+            specs.push(format!(":{}", name));
+            synced.deleted.push(name.clone());
+        }
+    }
+
+    if specs.is_empty() {
+        return Ok((wanted, synced));
+    }
+
+    let repo = git2::Repository::open_bare(storage.path())?;
+    let mut remote = repo.remote_anonymous(&mirror.url)?;
+    let rejected = RefCell::new(None);
+    let attempts = Cell::new(0);
+    let mut callbacks = git2::RemoteCallbacks::new();
+    callbacks.credentials(|url, username, allowed| {
+        attempts.set(attempts.get() + 1);
+        if attempts.get() > MAX_CREDENTIAL_ATTEMPTS {
+            return Err(git2::Error::from_str("authentication failed"));
+        }
+        creds.credentials(mirror, url, username, allowed)
+    });
+    callbacks.push_update_reference(|refname, status| {
+        if let Some(reason) = status {
+            tracing::warn!(refname, reason, "remote rejected ref");
+            rejected
+                .borrow_mut()
+                .get_or_insert_with(|| (refname.to_owned(), reason.to_owned()));
+        }
+        Ok(())
+    });
+    remote.push(
+        &specs,
+        Some(git2::PushOptions::new().remote_callbacks(callbacks)),
+    )?;
+
+    match rejected.into_inner() {
+        Some((refname, reason)) => Err(Error::Rejected { refname, reason }),
+        None => Ok((wanted, synced)),
+    }
+}
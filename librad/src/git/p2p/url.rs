@@ -7,6 +7,7 @@ use std::{
     convert::TryFrom,
     fmt::{self, Display},
     net::{AddrParseError, SocketAddr},
+    num::ParseIntError,
     str::FromStr,
 };
 
@@ -21,6 +22,13 @@ pub struct GitUrl<R> {
     pub local_peer: PeerId,
     pub remote_peer: PeerId,
     pub addr_hints: Vec<SocketAddr>,
+    /// Address hints given as hostnames, which need to be resolved before
+    /// connecting. See [`crate::net::resolve`].
+    pub host_hints: Vec<Host>,
+    /// Addresses the remote peer accepts connections over the TCP fallback
+    /// transport on, if they differ from the [`Self::addr_hints`]. See
+    /// [`crate::net::tcp`].
+    pub tcp_hints: Vec<SocketAddr>,
     pub repo: R,
 }
 
@@ -30,6 +38,8 @@ impl<R> GitUrl<R> {
             local_peer: &self.local_peer,
             remote_peer: &self.remote_peer,
             addr_hints: &self.addr_hints,
+            host_hints: &self.host_hints,
+            tcp_hints: &self.tcp_hints,
             repo: &self.repo,
         }
     }
@@ -108,18 +118,29 @@ where
                 let mhash = Multihash::from_bytes(bytes)?;
                 R::try_from(mhash).map_err(|e| Self::Err::Repo(Box::new(e)))
             })?;
-        let addr_hints = url
-            .query_pairs()
-            .filter_map(|(k, v)| match k.as_ref() {
-                "addr" => v.parse().ok(),
-                _ => None,
-            })
-            .collect();
+        let mut addr_hints = Vec::new();
+        let mut host_hints = Vec::new();
+        let mut tcp_hints = Vec::new();
+        for (k, v) in url.query_pairs() {
+            if k == "addr" {
+                if let Ok(addr) = v.parse() {
+                    addr_hints.push(addr)
+                } else if let Ok(host) = v.parse() {
+                    host_hints.push(host)
+                }
+            } else if k == "tcp" {
+                if let Ok(addr) = v.parse() {
+                    tcp_hints.push(addr)
+                }
+            }
+        }
 
         Ok(Self {
             local_peer,
             remote_peer,
             addr_hints,
+            host_hints,
+            tcp_hints,
             repo,
         })
     }
@@ -130,6 +151,8 @@ pub struct GitUrlRef<'a, R> {
     pub local_peer: &'a PeerId,
     pub remote_peer: &'a PeerId,
     pub addr_hints: &'a [SocketAddr],
+    pub host_hints: &'a [Host],
+    pub tcp_hints: &'a [SocketAddr],
     pub repo: &'a R,
 }
 
@@ -150,6 +173,8 @@ where
             local_peer,
             remote_peer,
             addr_hints: addr_hints.as_ref(),
+            host_hints: &[],
+            tcp_hints: &[],
             repo: &urn.id,
         }
     }
@@ -164,6 +189,8 @@ impl<R> GitUrlRef<'_, R> {
             local_peer: *self.local_peer,
             remote_peer: *self.remote_peer,
             addr_hints: self.addr_hints.to_vec(),
+            host_hints: self.host_hints.to_vec(),
+            tcp_hints: self.tcp_hints.to_vec(),
             repo: self.repo.clone(),
         }
     }
@@ -176,6 +203,8 @@ impl<'a, R> Clone for GitUrlRef<'a, R> {
             local_peer: self.local_peer,
             remote_peer: self.remote_peer,
             addr_hints: self.addr_hints,
+            host_hints: self.host_hints,
+            tcp_hints: self.tcp_hints,
             repo: self.repo,
         }
     }
@@ -208,6 +237,8 @@ where
         {
             let mut query = url.query_pairs_mut();
             query.extend_pairs(git.addr_hints.iter().map(|addr| ("addr", addr.to_string())));
+            query.extend_pairs(git.host_hints.iter().map(|host| ("addr", host.to_string())));
+            query.extend_pairs(git.tcp_hints.iter().map(|addr| ("tcp", addr.to_string())));
         }
         let repo: Multihash = git.repo.into();
         url.set_path(&format!(
@@ -218,3 +249,66 @@ where
         url
     }
 }
+
+/// A hostname and port.
+///
+/// Unlike [`SocketAddr`], the host needs to be resolved before connecting,
+/// which allows to reach peers whose address changes, eg. behind dynamic DNS.
+/// IP address literals are accepted as hosts, too.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Host {
+    pub name: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum HostError {
+    #[error("missing port")]
+    MissingPort,
+
+    #[error("empty hostname")]
+    EmptyName,
+
+    #[error("invalid port")]
+    Port(#[from] ParseIntError),
+}
+
+impl FromStr for Host {
+    type Err = HostError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, port) = s.rsplit_once(':').ok_or(HostError::MissingPort)?;
+        let name = name
+            .strip_prefix('[')
+            .and_then(|name| name.strip_suffix(']'))
+            .unwrap_or(name);
+        if name.is_empty() {
+            return Err(HostError::EmptyName);
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            port: port.parse()?,
+        })
+    }
+}
+
+impl Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.name.contains(':') {
+            write!(f, "[{}]:{}", self.name, self.port)
+        } else {
+            write!(f, "{}:{}", self.name, self.port)
+        }
+    }
+}
+
+impl From<SocketAddr> for Host {
+    fn from(addr: SocketAddr) -> Self {
+        Self {
+            name: addr.ip().to_string(),
+            port: addr.port(),
+        }
+    }
+}
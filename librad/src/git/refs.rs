@@ -12,7 +12,9 @@ use std::{
     path::Path,
 };
 
+pub mod attestation;
 mod serde_impls;
+pub mod v2;
 
 use git_ext::{is_not_found_err, reference};
 use link_canonical::{Cjson, CjsonError};
@@ -86,6 +88,35 @@ impl<A: Ord> FromIterator<A> for Remotes<A> {
     }
 }
 
+/// Generates graphs of at most [`TRACKING_GRAPH_DEPTH`] levels.
+#[cfg(feature = "fuzzing")]
+impl<'a, A> arbitrary::Arbitrary<'a> for Remotes<A>
+where
+    A: arbitrary::Arbitrary<'a> + Ord,
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        fn go<'a, A>(
+            u: &mut arbitrary::Unstructured<'a>,
+            depth: usize,
+        ) -> arbitrary::Result<Remotes<A>>
+        where
+            A: arbitrary::Arbitrary<'a> + Ord,
+        {
+            let mut remotes = Remotes::new();
+            if depth > 0 {
+                for _ in 0..u.arbitrary_len::<A>()? {
+                    let k = u.arbitrary()?;
+                    let v = go(u, depth - 1)?;
+                    remotes.insert(k, Box::new(v));
+                }
+            }
+            Ok(remotes)
+        }
+
+        go(u, TRACKING_GRAPH_DEPTH)
+    }
+}
+
 impl<A: Ord> Remotes<A> {
     pub fn new() -> Self {
         Self(BTreeMap::new())
@@ -197,11 +228,21 @@ pub mod stored {
         #[error(transparent)]
         Store(#[from] storage::Error),
 
+        #[error(transparent)]
+        Config(#[from] storage::config::Error),
+
         #[error(transparent)]
         Git(#[from] git2::Error),
 
         #[error(transparent)]
         Tracked(#[from] tracking::error::TrackedPeers),
+
+        #[error(transparent)]
+        V2(#[from] v2::Error),
+
+        #[cfg(feature = "fault-injection")]
+        #[error(transparent)]
+        Fault(#[from] storage::faults::Fault),
     }
 }
 
@@ -235,6 +276,16 @@ pub struct Refs {
     pub remotes: Remotes<PeerId>,
 }
 
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for Refs {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self {
+            categorised_refs: u.arbitrary()?,
+            remotes: u.arbitrary()?,
+        })
+    }
+}
+
 impl Refs {
     /// Compute the [`Refs`] from the current storage state at [`Urn`].
     #[tracing::instrument(level = "debug", skip(storage, urn), fields(urn = %urn))]
@@ -329,17 +380,22 @@ impl Refs {
         P: Into<Option<PeerId>> + Debug,
     {
         let peer = peer.into();
-        load(storage, urn, peer.as_ref()).map(|may| may.map(|Loaded { refs, .. }| Self::from(refs)))
+        load(storage, urn, peer.as_ref()).map(|may| may.map(|Loaded { refs, .. }| refs))
     }
 
     /// Compute the current [`Refs`], sign them, and store them at the
     /// `rad/signed_refs` branch of [`Urn`].
+    ///
+    /// The refs are stored in the version 1 format, unless
+    /// [`storage::config::Config::set_sigrefs_v2`] is enabled, in which case
+    /// they are stored in the sharded [`v2`] format. Only the latter honours
+    /// [`storage::config::Config::set_sigrefs_compression`].
     #[tracing::instrument(skip(storage, urn), fields(urn = %urn, local_peer = %storage.peer_id()))]
     pub fn update(storage: &Storage, urn: &Urn) -> Result<Updated, stored::Error> {
         let branch = Reference::rad_signed_refs(Namespace::from(urn), None);
         tracing::debug!("updating signed refs for {}", branch);
 
-        let signed_refs = Self::compute(storage, urn)?.sign(storage.signer())?;
+        let refs = Self::compute(storage, urn)?;
 
         let raw_git = storage.as_raw();
 
@@ -347,28 +403,42 @@ impl Refs {
             .reference(&branch)?
             .map(|r| r.peel_to_commit())
             .transpose()?;
+        let config = storage.config_readonly()?;
+        #[cfg(feature = "fault-injection")]
+        storage.inject(storage::faults::Op::ObjectWrite)?;
         let tree = {
-            let blob_oid = {
-                let json = serde_json::to_vec(&signed_refs)?;
-                raw_git.blob(&json)?
+            let compress = config.sigrefs_compression()?;
+            let oid = if config.sigrefs_v2()? {
+                v2::write(raw_git, storage.signer(), &refs, parent.as_ref(), compress)?
+            } else {
+                if compress {
+                    tracing::warn!(
+                        "signed refs compression requires the version 2 format, writing uncompressed"
+                    );
+                }
+                let signed_refs = refs.clone().sign(storage.signer())?;
+                let blob_oid = {
+                    let json = serde_json::to_vec(&signed_refs)?;
+                    raw_git.blob(&json)?
+                };
+                let mut builder = raw_git.treebuilder(None)?;
+                builder.insert(stored::BLOB_PATH, blob_oid, 0o100_644)?;
+                builder.write()?
             };
-
-            let mut builder = raw_git.treebuilder(None)?;
-            builder.insert(stored::BLOB_PATH, blob_oid, 0o100_644)?;
-            let oid = builder.write()?;
-
             raw_git.find_tree(oid)
         }?;
 
         if let Some(ref parent) = parent {
             if parent.tree()?.id() == tree.id() {
                 return Ok(Updated::Unchanged {
-                    refs: signed_refs.refs,
+                    refs,
                     at: parent.id(),
                 });
             }
         }
 
+        #[cfg(feature = "fault-injection")]
+        storage.inject(storage::faults::Op::RefUpdate)?;
         let author = raw_git.signature()?;
         let commit = raw_git.commit(
             Some(reference::RefLike::from(&branch).as_str()),
@@ -381,15 +451,16 @@ impl Refs {
         match commit {
             Ok(commit_id) => {
                 tracing::trace!(
-                    ?signed_refs.refs,
+                    ?refs,
                     %branch,
                     head = %commit_id,
                     parent = ?parent.as_ref().map(|commit| commit.id()),
                     "updated signed refs for {}", urn
                 );
+                storage.touch(urn, commit_id);
 
                 Ok(Updated::Updated {
-                    refs: signed_refs.refs,
+                    refs,
                     at: commit_id,
                 })
             },
@@ -659,7 +730,7 @@ impl Serialize for Signed<Verified> {
 pub(crate) struct Loaded {
     #[allow(unused)]
     pub at: git_ext::Oid,
-    pub refs: Signed<Verified>,
+    pub refs: Refs,
 }
 
 pub(crate) fn load<S>(
@@ -688,6 +759,9 @@ where
     }
 }
 
+/// Load and verify the signed refs stored in the commit `at`.
+///
+/// Both [`v2`] and version 1 documents are supported.
 pub(crate) fn load_at<S>(
     storage: S,
     at: git_ext::Oid,
@@ -697,13 +771,20 @@ where
     S: AsRef<storage::ReadOnly>,
 {
     let signer = peer.unwrap_or_else(|| storage.as_ref().peer_id());
+    if let Some(refs) = v2::read(&storage, at, signer)? {
+        return Ok(Some(Loaded { at, refs }));
+    }
+
     let loaded = storage
         .as_ref()
-        .blob_at(at, Path::new(stored::BLOB_PATH))?
-        .map(|blob| Signed::from_json(blob.content(), signer))
+        .read_blob_at(at, Path::new(stored::BLOB_PATH))?
+        .map(|blob| Signed::from_json(&blob.content, signer))
         .transpose()
         .map_err(stored::Error::from)?
-        .map(|refs| Loaded { at, refs });
+        .map(|refs| Loaded {
+            at,
+            refs: refs.into(),
+        });
 
     Ok(loaded)
 }
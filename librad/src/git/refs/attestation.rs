@@ -0,0 +1,254 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Attestations of the signed refs of other peers.
+//!
+//! A peer may [`attest`] the `rad/signed_refs` tips of the remotes it has
+//! observed for a [`Urn`], ie. sign and publish a statement that it has seen
+//! the remote in a particular state, and when. The attestations are stored in
+//! the tree of the `rad/attestations` branch, one blob per attested peer,
+//! named after its [`PeerId`].
+//!
+//! As `rad/attestations` is covered by the attesting peer's own signed refs,
+//! it is replicated like any other ref, and ends up at
+//! `refs/remotes/<witness>/rad/attestations` of the peers tracking it. The
+//! [`witnesses`] of a given state of a peer can thus be queried locally. A
+//! peer which presents different states to different parts of the network
+//! (ie. equivocates) will have fewer independent witnesses for each of them
+//! than an honest one, and the conflicting attestations serve as evidence.
+//!
+//! Attesting is optional, and never happens implicitly.
+
+use std::{collections::BTreeMap, path::Path};
+
+use git_ext::{reference, Oid};
+use link_canonical::CjsonError;
+use serde::{Deserialize, Serialize};
+use std_ext::time::unix_now;
+use thiserror::Error;
+
+use super::{signing, stored, v2::Sealed, Refs, Urn};
+use crate::{
+    git::{
+        storage::{self, ReadOnlyStorage as _, Storage},
+        types::{Namespace, Reference},
+    },
+    PeerId,
+};
+
+/// The maximum size of an attestation blob, in bytes. Larger ones are
+/// ignored.
+pub const MAX_SIZE: usize = 4 * 1024;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Signing(#[from] signing::Error),
+
+    #[error(transparent)]
+    Refs(#[from] stored::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Cjson(#[from] CjsonError),
+
+    #[error(transparent)]
+    Store(#[from] storage::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// The statement that `peer` was observed with its `rad/signed_refs` at
+/// `signed_refs`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    pub peer: PeerId,
+    pub signed_refs: Oid,
+    /// Seconds since the UNIX epoch.
+    pub timestamp: u64,
+}
+
+/// A peer attesting a state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Witness {
+    pub peer: PeerId,
+    /// Seconds since the UNIX epoch, as claimed by the witness.
+    pub timestamp: u64,
+}
+
+/// Attest the current `rad/signed_refs` tips of all remotes of `urn`, and
+/// update the signed refs of the local peer to publish the attestations.
+///
+/// Attestations of tips which did not change since the last call are retained
+/// as-is, so their timestamps denote when a state was first observed. Remotes
+/// which are no longer present are dropped.
+///
+/// Returns the attestations which were created.
+#[tracing::instrument(skip(storage, urn), fields(urn = %urn))]
+pub fn attest(storage: &Storage, urn: &Urn) -> Result<Vec<Attestation>, Error> {
+    let branch = Reference::rad_attestations(Namespace::from(urn), None);
+    let repo = storage.as_raw();
+
+    let parent = storage
+        .reference(&branch)?
+        .map(|r| r.peel_to_commit())
+        .transpose()?;
+    let parent_tree = parent.as_ref().map(|commit| commit.tree()).transpose()?;
+    let previous = |peer: &PeerId| -> Option<(git2::Oid, Attestation)> {
+        let entry = parent_tree.as_ref()?.get_name(&peer.to_string())?;
+        let blob = repo.find_blob(entry.id()).ok()?;
+        let sealed = serde_json::from_slice::<Sealed<Attestation>>(blob.content()).ok()?;
+        Some((blob.id(), sealed.body))
+    };
+
+    let timestamp = unix_now();
+    let mut created = Vec::new();
+    let mut builder = repo.treebuilder(None)?;
+    for (peer, tip) in remote_tips(storage, urn, "signed_refs")? {
+        let oid = match previous(&peer) {
+            Some((oid, prev)) if prev.peer == peer && prev.signed_refs == tip => oid,
+            _ => {
+                let attestation = Attestation {
+                    peer,
+                    signed_refs: tip,
+                    timestamp,
+                };
+                let sealed = Sealed::sign(attestation.clone(), storage.signer())?;
+                created.push(attestation);
+                repo.blob(&serde_json::to_vec(&sealed)?)?
+            },
+        };
+        builder.insert(peer.to_string(), oid, 0o100_644)?;
+    }
+    let tree = repo.find_tree(builder.write()?)?;
+
+    if let Some(ref parent) = parent {
+        if parent.tree_id() == tree.id() {
+            return Ok(created);
+        }
+    }
+
+    let author = repo.signature()?;
+    repo.commit(
+        Some(reference::RefLike::from(&branch).as_str()),
+        &author,
+        &author,
+        &format!("Update rad/attestations for {}", urn),
+        &tree,
+        &parent.iter().collect::<Vec<&git2::Commit>>(),
+    )?;
+    Refs::update(storage, urn)?;
+
+    Ok(created)
+}
+
+/// The peers which attested `peer` with its `rad/signed_refs` at
+/// `signed_refs`, including the local peer.
+///
+/// Each witness is counted once, and `peer` never witnesses itself.
+/// Attestations which fail to verify are ignored.
+pub fn witnesses<S>(
+    storage: &S,
+    urn: &Urn,
+    peer: &PeerId,
+    signed_refs: Oid,
+) -> Result<Vec<Witness>, Error>
+where
+    S: AsRef<storage::ReadOnly>,
+{
+    let storage = storage.as_ref();
+    let local = {
+        let branch = Reference::rad_attestations(Namespace::from(urn), None);
+        storage
+            .reference(&branch)?
+            .and_then(|r| r.target())
+            .map(|tip| (*storage.peer_id(), Oid::from(tip)))
+    };
+
+    let name = peer.to_string();
+    let mut witnesses = BTreeMap::new();
+    for (witness, tip) in local
+        .into_iter()
+        .chain(remote_tips(storage, urn, "attestations")?)
+    {
+        if witness == *peer {
+            continue;
+        }
+        let blob = match storage.blob_at(tip, Path::new(&name))? {
+            None => continue,
+            Some(blob) if blob.size() > MAX_SIZE => {
+                tracing::warn!(%witness, size = blob.size(), "attestation too large");
+                continue;
+            },
+            Some(blob) => blob,
+        };
+        let sealed = match serde_json::from_slice::<Sealed<Attestation>>(blob.content()) {
+            Ok(sealed) => sealed,
+            Err(e) => {
+                tracing::warn!(%witness, err = %e, "invalid attestation");
+                continue;
+            },
+        };
+        if !sealed.verify(&witness)? {
+            tracing::warn!(%witness, "invalid attestation signature");
+            continue;
+        }
+        let Attestation {
+            peer: attested,
+            signed_refs: attested_tip,
+            timestamp,
+        } = sealed.body;
+        if attested == *peer && attested_tip == signed_refs {
+            witnesses.insert(
+                witness,
+                Witness {
+                    peer: witness,
+                    timestamp,
+                },
+            );
+        }
+    }
+
+    Ok(witnesses.into_values().collect())
+}
+
+/// The number of independent [`witnesses`] of `peer` with its
+/// `rad/signed_refs` at `signed_refs`.
+pub fn count<S>(storage: &S, urn: &Urn, peer: &PeerId, signed_refs: Oid) -> Result<usize, Error>
+where
+    S: AsRef<storage::ReadOnly>,
+{
+    witnesses(storage, urn, peer, signed_refs).map(|ws| ws.len())
+}
+
+/// The tips of `refs/remotes/*/rad/<name>` in the namespace of `urn`.
+fn remote_tips<S>(storage: &S, urn: &Urn, name: &str) -> Result<Vec<(PeerId, Oid)>, Error>
+where
+    S: AsRef<storage::ReadOnly>,
+{
+    let prefix = format!("refs/namespaces/{}/refs/remotes/", Namespace::from(urn));
+    let suffix = format!("/rad/{}", name);
+    let glob = globset::Glob::new(&format!("{}*{}", prefix, suffix))
+        .unwrap()
+        .compile_matcher();
+
+    let mut tips = Vec::new();
+    for r in storage.as_ref().references_glob(glob)? {
+        let r = r?;
+        let peer = r
+            .name()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .and_then(|name| name.strip_suffix(&suffix))
+            .and_then(|peer| peer.parse::<PeerId>().ok());
+        if let (Some(peer), Some(tip)) = (peer, r.target()) {
+            tips.push((peer, tip.into()))
+        }
+    }
+    Ok(tips)
+}
@@ -0,0 +1,322 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Signed refs, version 2.
+//!
+//! Version 1 stores all signed refs in a single blob, which grows with the
+//! number of refs and has to be re-signed whenever any of them changes.
+//! Version 2 shards the refs by category instead. The tree of a
+//! `rad/signed_refs` commit contains:
+//!
+//! * `categories/<category>`: a signed [`Shard`] per category. A shard is only
+//!   re-signed if its refs changed since the parent commit, otherwise the
+//!   existing blob is reused.
+//! * `manifest`: the signed [`Manifest`], which lists the blob oid of each
+//!   shard, as well as the [`Remotes`].
+//!
+//! Version 2 is only written if
+//! [`crate::git::storage::config::Config::set_sigrefs_v2`] is enabled.
+//!
+//! Blobs are subject to the size limits [`MAX_MANIFEST_SIZE`] and
+//! [`MAX_SHARD_SIZE`], which are enforced both when writing and when reading.
+//!
+//! If [`crate::git::storage::config::Config::set_sigrefs_compression`] is
+//! enabled, blobs are compressed, and shards delta-encoded against their
+//! previous version where that is smaller, cf. [`encoding`]. Size limits apply
+//! to both the stored and the decoded blobs.
+//!
+//! [`super::load_at`] falls back to version 1 if the tree contains no
+//! `manifest`. Note, however, that peers which only understand version 1 are
+//! not able to verify version 2 documents, and peers which do not support
+//! [`encoding`] are not able to verify compressed ones.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use git_ext::Oid;
+use link_canonical::{Cjson, CjsonError};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{signing, Refs, Remotes};
+use crate::{git::storage, PeerId, Signature, Signer};
+
+pub mod encoding;
+use encoding::Format;
+
+/// The version of the [`Manifest`] format.
+pub const VERSION: u32 = 2;
+
+/// The maximum size of the `manifest` blob, in bytes.
+pub const MAX_MANIFEST_SIZE: usize = 1024 * 1024;
+
+/// The maximum size of a `categories/<category>` blob, in bytes.
+pub const MAX_SHARD_SIZE: usize = 4 * 1024 * 1024;
+
+pub(super) const MANIFEST_PATH: &str = "manifest";
+pub(super) const CATEGORIES_PATH: &str = "categories";
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("unsupported signed refs version {0}")]
+    Version(u32),
+
+    #[error("`{path}` exceeds the size limit of {limit} bytes ({size} bytes)")]
+    TooLarge {
+        path: String,
+        size: usize,
+        limit: usize,
+    },
+
+    #[error("invalid signature on `{0}`")]
+    InvalidSignature(String),
+
+    #[error("shard `{0}` is missing")]
+    MissingShard(String),
+
+    #[error("shard `{0}` does not match the manifest")]
+    Mismatch(String),
+
+    #[error("unknown blob format {0:?}")]
+    Format(Option<u8>),
+
+    #[error("delta chain exceeds the maximum depth of {0}")]
+    DeltaDepth(usize),
+
+    #[error("base blob {0} of delta is missing")]
+    MissingBase(Oid),
+
+    #[error(transparent)]
+    Signing(#[from] signing::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Cjson(#[from] CjsonError),
+
+    #[error(transparent)]
+    Store(#[from] storage::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// The refs of a single category, eg. `heads`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Shard {
+    /// The category, included so that a shard can not be passed off as
+    /// another category's.
+    pub category: String,
+    pub refs: BTreeMap<String, Oid>,
+}
+
+/// The entry point of a version 2 document.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// The blob oid of the [`Shard`] of each category.
+    pub categories: BTreeMap<String, Oid>,
+    pub remotes: Remotes<PeerId>,
+}
+
+/// A `T` and a signature over its canonical form.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Sealed<T> {
+    pub body: T,
+    pub signature: Signature,
+}
+
+impl<T: Serialize> Sealed<T> {
+    pub(super) fn sign<S>(body: T, signer: &S) -> Result<Self, signing::Error>
+    where
+        S: Signer,
+    {
+        let canonical = Cjson(&body).canonical_form()?;
+        let signature = futures::executor::block_on(signer.sign(&canonical))
+            .map_err(|err| signing::Error::Sign(Box::new(err)))?;
+        Ok(Self {
+            body,
+            signature: signature.into(),
+        })
+    }
+
+    pub(super) fn verify(&self, signer: &PeerId) -> Result<bool, CjsonError> {
+        let canonical = Cjson(&self.body).canonical_form()?;
+        Ok(self.signature.verify(&canonical, &**signer))
+    }
+}
+
+fn shard_path(category: &str) -> PathBuf {
+    Path::new(CATEGORIES_PATH).join(category)
+}
+
+fn ensure_size(path: &Path, size: usize, limit: usize) -> Result<(), Error> {
+    if size > limit {
+        return Err(Error::TooLarge {
+            path: path.display().to_string(),
+            size,
+            limit,
+        });
+    }
+    Ok(())
+}
+
+/// Write the tree of a version 2 document for `refs`, and return its oid.
+///
+/// Shards which are unchanged in the tree of `parent` are reused as-is. If
+/// `compress` is `true`, blobs are written in an [`encoding`] other than plain
+/// JSON.
+pub(super) fn write<S>(
+    repo: &git2::Repository,
+    signer: &S,
+    refs: &Refs,
+    parent: Option<&git2::Commit>,
+    compress: bool,
+) -> Result<git2::Oid, Error>
+where
+    S: Signer,
+{
+    let parent = parent.map(|commit| commit.tree()).transpose()?;
+    let find_blob = |oid: Oid| -> Result<Option<Vec<u8>>, Error> {
+        match repo.find_blob(oid.into()) {
+            Ok(blob) => Ok(Some(blob.content().to_vec())),
+            Err(e) if git_ext::is_not_found_err(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    };
+    let previous = |path: &Path| -> Option<(git2::Oid, Shard, usize)> {
+        let entry = parent.as_ref()?.get_path(path).ok()?;
+        let blob = repo.find_blob(entry.id()).ok()?;
+        let (sealed, depth) = encoding::shard(blob.content(), MAX_SHARD_SIZE, find_blob).ok()?;
+        Some((blob.id(), sealed.body, depth))
+    };
+
+    let mut categories = BTreeMap::new();
+    let mut shards = repo.treebuilder(None)?;
+    for (category, refs) in &refs.categorised_refs {
+        let path = shard_path(category);
+        let shard = Shard {
+            category: category.clone(),
+            refs: refs.clone(),
+        };
+        let oid = match previous(&path) {
+            Some((oid, prev, _)) if prev == shard => oid,
+            prev => {
+                let sealed = Sealed::sign(shard, signer)?;
+                let json = encoding::plain(&sealed)?;
+                ensure_size(&path, json.len(), MAX_SHARD_SIZE)?;
+                let blob = if compress {
+                    let deflated = encoding::deflate(Format::Deflate, &sealed)?;
+                    match prev {
+                        Some((base, prev, depth)) if depth < encoding::MAX_DELTA_DEPTH => {
+                            let delta = encoding::Delta::new((base.into(), &prev), &sealed);
+                            let delta = encoding::deflate(Format::Delta, &delta)?;
+                            if delta.len() < deflated.len() {
+                                delta
+                            } else {
+                                deflated
+                            }
+                        },
+                        _ => deflated,
+                    }
+                } else {
+                    json
+                };
+                repo.blob(&blob)?
+            },
+        };
+        shards.insert(category.as_str(), oid, 0o100_644)?;
+        categories.insert(category.clone(), oid.into());
+    }
+
+    let manifest = Manifest {
+        version: VERSION,
+        categories,
+        remotes: refs.remotes.clone(),
+    };
+    let sealed = Sealed::sign(manifest, signer)?;
+    let json = encoding::plain(&sealed)?;
+    ensure_size(Path::new(MANIFEST_PATH), json.len(), MAX_MANIFEST_SIZE)?;
+    let blob = if compress {
+        encoding::deflate(Format::Deflate, &sealed)?
+    } else {
+        json
+    };
+
+    let mut builder = repo.treebuilder(None)?;
+    builder.insert(MANIFEST_PATH, repo.blob(&blob)?, 0o100_644)?;
+    builder.insert(CATEGORIES_PATH, shards.write()?, 0o040_000)?;
+    Ok(builder.write()?)
+}
+
+/// Load and verify the version 2 document at `at`.
+///
+/// Returns `None` if there is no `manifest`, ie. the document is not a version
+/// 2 document.
+pub(super) fn read<S>(storage: &S, at: Oid, signer: &PeerId) -> Result<Option<Refs>, Error>
+where
+    S: AsRef<storage::ReadOnly>,
+{
+    let storage = storage.as_ref();
+    let find_blob = |oid: Oid| -> Result<Option<Vec<u8>>, Error> { Ok(storage.read_blob(oid)?) };
+
+    let manifest = match storage.read_blob_at(at, Path::new(MANIFEST_PATH))? {
+        None => return Ok(None),
+        Some(blob) => {
+            ensure_size(
+                Path::new(MANIFEST_PATH),
+                blob.content.len(),
+                MAX_MANIFEST_SIZE,
+            )?;
+            encoding::sealed::<Manifest>(&blob.content, MAX_MANIFEST_SIZE)?
+        },
+    };
+    if !manifest.verify(signer)? {
+        return Err(Error::InvalidSignature(MANIFEST_PATH.to_owned()));
+    }
+    let Manifest {
+        version,
+        categories,
+        remotes,
+    } = manifest.body;
+    if version != VERSION {
+        return Err(Error::Version(version));
+    }
+
+    let mut categorised_refs = BTreeMap::new();
+    for (category, oid) in categories {
+        let path = shard_path(&category);
+        let shard = {
+            let blob = storage
+                .read_blob_at(at, &path)?
+                .ok_or_else(|| Error::MissingShard(path.display().to_string()))?;
+            if blob.id != oid {
+                return Err(Error::Mismatch(path.display().to_string()));
+            }
+            ensure_size(&path, blob.content.len(), MAX_SHARD_SIZE)?;
+            encoding::shard(&blob.content, MAX_SHARD_SIZE, find_blob)?.0
+        };
+        if !shard.verify(signer)? {
+            return Err(Error::InvalidSignature(path.display().to_string()));
+        }
+        if shard.body.category != category {
+            return Err(Error::Mismatch(path.display().to_string()));
+        }
+        categorised_refs.insert(category, shard.body.refs);
+    }
+
+    Ok(Some(Refs {
+        categorised_refs,
+        remotes,
+    }))
+}
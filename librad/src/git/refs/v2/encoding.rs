@@ -0,0 +1,177 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Encoding of the blobs of a version 2 document at rest.
+//!
+//! A blob is either plain JSON, or starts with [`MAGIC`] followed by a
+//! [`Format`] tag:
+//!
+//! * [`Format::Deflate`]: the deflated JSON of the plain blob.
+//! * [`Format::Delta`]: the deflated JSON of a [`Delta`] against the blob of
+//!   the same [`Shard`] in a previous document.
+//!
+//! In either case, the signature is over the canonical form of the decoded
+//! body, so the encoding does not affect verification.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{Read as _, Write as _},
+};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use git_ext::Oid;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{Error, Sealed, Shard};
+use crate::Signature;
+
+/// Prefix of encoded blobs. Plain blobs are JSON, and thus never start with a
+/// NUL byte.
+pub const MAGIC: &[u8] = b"\0rad";
+
+/// The maximum number of [`Delta`]s to follow to decode a [`Shard`].
+pub const MAX_DELTA_DEPTH: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Format {
+    Deflate = 1,
+    Delta = 2,
+}
+
+/// The changes to the refs of a [`Shard`] relative to the one stored in the
+/// blob `base`, and the signature over the resulting [`Shard`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Delta {
+    pub base: Oid,
+    pub signature: Signature,
+    pub upserts: BTreeMap<String, Oid>,
+    pub removals: BTreeSet<String>,
+}
+
+impl Delta {
+    /// The [`Delta`] turning `base` into `sealed`.
+    pub fn new(base: (Oid, &Shard), sealed: &Sealed<Shard>) -> Self {
+        let (base, prev) = base;
+        let upserts = sealed
+            .body
+            .refs
+            .iter()
+            .filter(|(name, oid)| prev.refs.get(*name) != Some(oid))
+            .map(|(name, oid)| (name.clone(), *oid))
+            .collect();
+        let removals = prev
+            .refs
+            .keys()
+            .filter(|name| !sealed.body.refs.contains_key(*name))
+            .cloned()
+            .collect();
+        Self {
+            base,
+            signature: sealed.signature.clone(),
+            upserts,
+            removals,
+        }
+    }
+}
+
+/// Encode `value` as plain JSON.
+pub fn plain<T: Serialize>(value: &T) -> Result<Vec<u8>, Error> {
+    Ok(serde_json::to_vec(value)?)
+}
+
+/// Encode `value` as deflated JSON, tagged with `format`.
+pub fn deflate<T: Serialize>(format: Format, value: &T) -> Result<Vec<u8>, Error> {
+    let mut out = MAGIC.to_vec();
+    out.push(format as u8);
+    let mut encoder = DeflateEncoder::new(out, Compression::default());
+    serde_json::to_writer(&mut encoder, value)?;
+    Ok(encoder.finish()?)
+}
+
+/// A blob with its [`Format`] tag stripped, and deflated content inflated.
+enum Decoded {
+    Plain(Vec<u8>),
+    Delta(Vec<u8>),
+}
+
+fn decode(content: &[u8], limit: usize) -> Result<Decoded, Error> {
+    let (format, deflated) = match content.strip_prefix(MAGIC) {
+        None => return Ok(Decoded::Plain(content.to_vec())),
+        Some([format, rest @ ..]) => (*format, rest),
+        Some([]) => return Err(Error::Format(None)),
+    };
+    let mut inflated = Vec::new();
+    DeflateDecoder::new(deflated)
+        .take(limit as u64 + 1)
+        .read_to_end(&mut inflated)?;
+    if inflated.len() > limit {
+        return Err(Error::TooLarge {
+            path: "<inflated>".to_owned(),
+            size: inflated.len(),
+            limit,
+        });
+    }
+    match format {
+        f if f == Format::Deflate as u8 => Ok(Decoded::Plain(inflated)),
+        f if f == Format::Delta as u8 => Ok(Decoded::Delta(inflated)),
+        f => Err(Error::Format(Some(f))),
+    }
+}
+
+/// Decode a `Sealed<T>` which can not be delta-encoded, ie. the
+/// [`super::Manifest`].
+pub fn sealed<T: DeserializeOwned>(content: &[u8], limit: usize) -> Result<Sealed<T>, Error> {
+    match decode(content, limit)? {
+        Decoded::Plain(json) => Ok(serde_json::from_slice(&json)?),
+        Decoded::Delta(_) => Err(Error::Format(Some(Format::Delta as u8))),
+    }
+}
+
+/// Decode a `Sealed<Shard>`, resolving [`Delta`]s via `find_blob`.
+///
+/// Also returns the number of [`Delta`]s which were followed.
+pub fn shard<F>(content: &[u8], limit: usize, find_blob: F) -> Result<(Sealed<Shard>, usize), Error>
+where
+    F: Fn(Oid) -> Result<Option<Vec<u8>>, Error>,
+{
+    let mut deltas = Vec::new();
+    let mut content = content.to_vec();
+    let base = loop {
+        match decode(&content, limit)? {
+            Decoded::Plain(json) => break serde_json::from_slice::<Sealed<Shard>>(&json)?,
+            Decoded::Delta(json) => {
+                if deltas.len() == MAX_DELTA_DEPTH {
+                    return Err(Error::DeltaDepth(MAX_DELTA_DEPTH));
+                }
+                let delta = serde_json::from_slice::<Delta>(&json)?;
+                content = find_blob(delta.base)?.ok_or(Error::MissingBase(delta.base))?;
+                deltas.push(delta);
+            },
+        }
+    };
+
+    // Each delta is within `limit`, but a chain of them could still grow the
+    // shard past it
+    let depth = deltas.len();
+    let mut sealed = base;
+    for delta in deltas.into_iter().rev() {
+        for name in delta.removals {
+            sealed.body.refs.remove(&name);
+        }
+        sealed.body.refs.extend(delta.upserts);
+        sealed.signature = delta.signature;
+        let size = serde_json::to_vec(&sealed)?.len();
+        if size > limit {
+            return Err(Error::TooLarge {
+                path: "<resolved>".to_owned(),
+                size,
+                limit,
+            });
+        }
+    }
+
+    Ok((sealed, depth))
+}
@@ -5,6 +5,7 @@
 // Linking Exception. For full terms see the included LICENSE file.
 
 use std::{
+    collections::BTreeMap,
     convert::TryFrom,
     fmt::Debug,
     marker::PhantomData,
@@ -14,6 +15,7 @@ use std::{
 use crypto::{BoxedSigner, SomeSigner};
 use git2::string_array::StringArray;
 use git_ext::{self as ext, is_not_found_err};
+use parking_lot::Mutex;
 use std_ext::Void;
 
 use crate::{
@@ -24,14 +26,27 @@ use crate::{
     Signer,
 };
 
+pub mod bundle;
 pub mod config;
+pub mod digest;
+pub mod eviction;
+pub mod export;
+#[cfg(feature = "fault-injection")]
+pub mod faults;
+pub mod gc;
 pub mod glob;
+pub mod migrations;
+pub mod pinned;
 pub mod pool;
+pub mod quota;
 pub mod read;
+pub mod reader;
+pub mod transaction;
 pub mod watch;
 
 pub use config::Config;
 pub use glob::Pattern;
+pub use pinned::Pinned;
 pub use pool::{Pool, PoolError, Pooled, PooledRef};
 pub use read::{
     Error,
@@ -42,12 +57,14 @@ pub use read::{
     References,
     ReferencesGlob,
 };
+pub use reader::Reader;
+pub use transaction::Transaction;
 pub use watch::{NamespaceEvent, Watcher};
 
 pub mod error {
     use thiserror::Error;
 
-    use super::config;
+    use super::{config, migrations, transaction};
 
     #[derive(Debug, Error)]
     #[non_exhaustive]
@@ -62,19 +79,10 @@ pub mod error {
         SignerKeyMismatch,
 
         #[error(transparent)]
-        TrackingMigration(#[from] Box<dyn std::error::Error + Send + Sync + 'static>),
-    }
-
-    impl From<crate::git::identities::error::Error> for Init {
-        fn from(err: crate::git::identities::error::Error) -> Self {
-            Self::TrackingMigration(Box::new(err))
-        }
-    }
+        Migration(#[from] migrations::Error),
 
-    impl From<crate::git::tracking::migration::Error> for Init {
-        fn from(err: crate::git::tracking::migration::Error) -> Self {
-            Self::TrackingMigration(Box::new(err))
-        }
+        #[error(transparent)]
+        Transaction(#[from] transaction::Error),
     }
 }
 
@@ -82,11 +90,18 @@ pub mod error {
 pub struct Storage {
     inner: ReadOnly,
     signer: BoxedSigner,
+    /// URNs whose signed refs were updated through this [`Storage`], cf.
+    /// [`Storage::take_touched`].
+    touched: Mutex<BTreeMap<Urn, ext::Oid>>,
 }
 
 impl Storage {
     /// Open the [`Storage`], initialising it if it doesn't exist.
     ///
+    /// Pending [`migrations`] are applied. Storage which was migrated by a
+    /// newer version of this library is refused. [`Transaction`]s which were
+    /// interrupted while committing are rolled back.
+    ///
     /// Note that a [`Storage`] is tied to the [`Signer`] with which it was
     /// initialised, attempting to open it with a different one (that is, a
     /// different key) will return an error.
@@ -115,6 +130,7 @@ impl Storage {
                         .external_template(false),
                 )?;
                 Config::init(&mut backend, &signer)?;
+                migrations::set_version(&backend, migrations::SCHEMA_VERSION)?;
 
                 Ok(backend)
             },
@@ -128,25 +144,18 @@ impl Storage {
         }
 
         let storage = Self {
-            inner: ReadOnly { backend, peer_id },
+            inner: ReadOnly {
+                backend,
+                peer_id,
+                reader: read::open_reader(paths),
+                writable: true,
+            },
             signer: BoxedSigner::from(SomeSigner { signer }),
+            touched: Mutex::new(BTreeMap::new()),
         };
 
-        // NOTE: this is temporary migration code, converting v1 tracking entries into
-        // v2 tracking entries. It should eventually be phased out as upstream
-        // dependencies migrate to the latest version.
-        {
-            let urns = crate::git::identities::any::list(&storage)?
-                .map(|i| i.map(|i| i.urn()))
-                .collect::<Result<std::collections::BTreeSet<_>, _>>()?;
-
-            // The failures will eventually converge as `Storage::open` is called on the
-            // next start up, and we report them via trace in the meantime.
-            let results = crate::git::tracking::migration::migrate(&storage, urns)?;
-            results.failures.into_iter().for_each(|(err, urn, peer)| {
-                tracing::warn!(urn = %urn, peer = %peer, reason = %err, "failed to migrate");
-            });
-        }
+        migrations::migrate(&storage)?;
+        transaction::recover(storage.as_raw())?;
 
         Ok(storage)
     }
@@ -190,6 +199,7 @@ impl Storage {
         Ok(Self {
             inner: ro,
             signer: BoxedSigner::from(SomeSigner { signer }),
+            touched: Mutex::new(BTreeMap::new()),
         })
     }
 
@@ -213,14 +223,62 @@ impl Storage {
         Ok(Config::try_from(self.as_raw())?)
     }
 
+    /// Drain the [`Urn`]s whose `rad/signed_refs` were updated through this
+    /// [`Storage`] since the last call, along with the new tip.
+    ///
+    /// This is used by [`crate::net::peer::Peer`] to announce local writes.
+    /// Storages handed out by a [`Pool`] are drained when they are recycled,
+    /// so the URNs touched by one borrower are not seen by the next.
+    pub fn take_touched(&self) -> BTreeMap<Urn, ext::Oid> {
+        std::mem::take(&mut self.touched.lock())
+    }
+
+    pub(crate) fn touch(&self, urn: &Urn, at: git2::Oid) {
+        self.touched
+            .lock()
+            .insert(urn.clone().with_path(None), at.into());
+    }
+
     pub fn config_path(&self) -> PathBuf {
         config::path(self.as_raw())
     }
 
+    /// See [`ReadOnly::at_sigref`].
+    pub fn at_sigref<P>(&self, urn: &Urn, peer: P, at: ext::Oid) -> Result<Pinned, pinned::Error>
+    where
+        P: Into<Option<PeerId>> + Debug,
+    {
+        self.inner.at_sigref(urn, peer, at)
+    }
+
+    /// See [`ReadOnly::export`].
+    pub fn export(
+        &self,
+        urn: &Urn,
+        target: &export::Target,
+        opts: export::Options,
+    ) -> Result<export::Exported, export::Error> {
+        self.inner.export(urn, target, opts)
+    }
+
     pub fn watch(&self) -> watch::Watch {
         watch::Watch { storage: self }
     }
 
+    /// Start a [`Transaction`] to update several refs under a common set of
+    /// locks.
+    pub fn transaction(&self) -> Transaction<'_> {
+        Transaction::new(self)
+    }
+
+    /// Apply the [`faults::Faults`] installed for this storage to `op`.
+    #[cfg(feature = "fault-injection")]
+    pub(crate) fn inject(&self, op: faults::Op) -> Result<(), faults::Fault> {
+        faults::lookup(self.path())
+            .map(|faults| faults.inject(op))
+            .unwrap_or(Ok(()))
+    }
+
     pub(super) fn signer(&self) -> &BoxedSigner {
         &self.signer
     }
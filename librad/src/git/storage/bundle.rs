@@ -0,0 +1,293 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Offline replication via `git bundle` files.
+//!
+//! [`create`] writes the refs of a namespace -- including the `rad/` refs and
+//! the signed refs, as well as the refs of tracked peers -- to a bundle, along
+//! with the objects they point to. If given the refs of a previously created
+//! bundle, only the objects added since are included, and the bundle can only
+//! be imported by someone who imported the previous one.
+//!
+//! The bundle is a regular v2 `git bundle`. Refs are named as in the
+//! monorepo, ie. `refs/namespaces/<urn>/refs/..`. An additional ref
+//! `refs/rad/bundle/<peer>` records the peer which created the bundle, and
+//! points to its `rad/id`.
+//!
+//! A bundle is imported by replicating from it like from its creator, see
+//! [`crate::net::replication::bundle`]. This requires the `git` executable.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{self, BufRead as _, Write as _},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+};
+
+use git_ext as ext;
+use thiserror::Error;
+
+use super::ReadOnly;
+use crate::{
+    git::{identities, Urn},
+    PeerId,
+};
+
+/// The first line of a bundle.
+const SIGNATURE: &str = "# v2 git bundle";
+
+/// Prefix of the ref naming the creator of a bundle.
+pub const AUTHOR_REF_PREFIX: &str = "refs/rad/bundle/";
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("no identity found for {0}")]
+    NotFound(Urn),
+
+    #[error("`{0}` is not a git bundle")]
+    NotABundle(PathBuf),
+
+    #[error("malformed bundle header: {0}")]
+    Malformed(String),
+
+    #[error("`git pack-objects` exited with {status}: {stderr}")]
+    PackObjects { status: ExitStatus, stderr: String },
+
+    #[error(transparent)]
+    Identities(#[from] identities::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// The header of a bundle created by [`create`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// The peer which created the bundle.
+    pub author: PeerId,
+    /// The namespace contained in the bundle.
+    pub urn: Urn,
+    /// The refs of the namespace, relative to it, eg. `refs/rad/id`.
+    pub refs: BTreeMap<String, ext::Oid>,
+    /// The commits the bundle requires to be present when importing it.
+    pub prerequisites: BTreeSet<ext::Oid>,
+    /// The offset of the packfile within the bundle.
+    pub pack_offset: u64,
+}
+
+impl Header {
+    /// Read the header of the bundle at `path`.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let mut file = io::BufReader::new(fs::File::open(path)?);
+        let mut offset = 0;
+        let mut buf = Vec::new();
+        let mut next_line = || -> Result<Option<String>, Error> {
+            buf.clear();
+            let n = file.read_until(b'\n', &mut buf)?;
+            if n == 0 || buf.last() != Some(&b'\n') {
+                return Ok(None);
+            }
+            offset += n as u64;
+            buf.pop();
+            String::from_utf8(buf.clone())
+                .map(Some)
+                .map_err(|_| Error::Malformed("invalid UTF-8".to_owned()))
+        };
+
+        if next_line()?.as_deref() != Some(SIGNATURE) {
+            return Err(Error::NotABundle(path.to_path_buf()));
+        }
+
+        let mut author = None;
+        let mut namespace = None;
+        let mut refs = BTreeMap::new();
+        let mut prerequisites = BTreeSet::new();
+        loop {
+            let line = next_line()?
+                .ok_or_else(|| Error::Malformed("unexpected end of header".to_owned()))?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(prereq) = line.strip_prefix('-') {
+                let oid = prereq.split(' ').next().unwrap_or_default();
+                prerequisites.insert(parse_oid(oid)?);
+                continue;
+            }
+
+            let (oid, name) = line
+                .split_once(' ')
+                .ok_or_else(|| Error::Malformed(format!("invalid ref line `{}`", line)))?;
+            let oid = parse_oid(oid)?;
+            if let Some(peer) = name.strip_prefix(AUTHOR_REF_PREFIX) {
+                let peer = peer
+                    .parse()
+                    .map_err(|_| Error::Malformed(format!("invalid author `{}`", peer)))?;
+                author = Some(peer);
+                continue;
+            }
+
+            let (ns, name) = name
+                .strip_prefix("refs/namespaces/")
+                .and_then(|rest| rest.split_once('/'))
+                .ok_or_else(|| Error::Malformed(format!("unexpected ref `{}`", name)))?;
+            match &namespace {
+                None => namespace = Some(ns.to_owned()),
+                Some(expected) if expected == ns => {},
+                Some(_) => return Err(Error::Malformed("multiple namespaces".to_owned())),
+            }
+            refs.insert(name.to_owned(), oid);
+        }
+
+        let author = author.ok_or_else(|| Error::Malformed("missing author".to_owned()))?;
+        let namespace = namespace.ok_or_else(|| Error::Malformed("missing refs".to_owned()))?;
+        let urn = Urn::try_from_id(&namespace)
+            .map_err(|_| Error::Malformed(format!("invalid namespace `{}`", namespace)))?;
+
+        Ok(Self {
+            author,
+            urn,
+            refs,
+            prerequisites,
+            pack_offset: offset,
+        })
+    }
+}
+
+fn parse_oid(s: &str) -> Result<ext::Oid, Error> {
+    git2::Oid::from_str(s)
+        .map(ext::Oid::from)
+        .map_err(|_| Error::Malformed(format!("invalid object id `{}`", s)))
+}
+
+/// Write the namespace `urn` to a bundle at `path`.
+///
+/// If `since` is given, objects reachable from its commits are omitted from
+/// the bundle, and become its prerequisites. `since` is typically the
+/// [`Header::refs`] of the bundle previously created for the same recipient.
+/// All refs of the namespace are recorded in the bundle regardless.
+#[tracing::instrument(level = "debug", skip(storage, since))]
+pub fn create<S>(
+    storage: &S,
+    urn: &Urn,
+    path: &Path,
+    since: Option<&BTreeMap<String, ext::Oid>>,
+) -> Result<Header, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    let repo = &storage.backend;
+    if identities::any::get(storage, urn)?.is_none() {
+        return Err(Error::NotFound(urn.clone()));
+    }
+    let namespace = format!("refs/namespaces/{}/", urn.encode_id());
+
+    let mut refs = BTreeMap::new();
+    for r in repo.references_glob(&format!("{}refs/*", namespace))? {
+        let r = r?;
+        // `rad/ids/*` are symbolic refs to other namespaces
+        let (name, oid) = match (r.name(), r.resolve().ok().and_then(|r| r.target())) {
+            (Some(name), Some(oid)) => (name.to_owned(), oid),
+            _ => continue,
+        };
+        if let Some(name) = name.strip_prefix(&namespace) {
+            refs.insert(name.to_owned(), ext::Oid::from(oid));
+        }
+    }
+    let rad_id = refs
+        .get("refs/rad/id")
+        .copied()
+        .ok_or_else(|| Error::NotFound(urn.clone()))?;
+
+    let mut prerequisites = BTreeSet::new();
+    for oid in since.into_iter().flat_map(|since| since.values()) {
+        match repo
+            .find_object(**oid, None)
+            .and_then(|obj| obj.peel_to_commit())
+        {
+            Ok(commit) => {
+                prerequisites.insert(ext::Oid::from(commit.id()));
+            },
+            // Gone or not a commit, nothing to omit
+            Err(_) => continue,
+        }
+    }
+
+    let tmp = path.with_extension("bundle.tmp");
+    let mut out = io::BufWriter::new(fs::File::create(&tmp)?);
+    writeln!(out, "{}", SIGNATURE)?;
+    for oid in &prerequisites {
+        writeln!(out, "-{}", oid)?;
+    }
+    for (name, oid) in &refs {
+        writeln!(out, "{} {}{}", oid, namespace, name)?;
+    }
+    writeln!(out, "{} {}{}", rad_id, AUTHOR_REF_PREFIX, storage.peer_id())?;
+    writeln!(out)?;
+    out.flush()?;
+    let pack_offset = out.get_ref().metadata()?.len();
+
+    let mut child = Command::new("git")
+        .arg("--git-dir")
+        .arg(storage.path())
+        .args(&[
+            "pack-objects",
+            "--stdout",
+            "--thin",
+            "--delta-base-offset",
+            "--revs",
+            "-q",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    {
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let tips = refs.values().collect::<BTreeSet<_>>();
+        for oid in tips {
+            writeln!(stdin, "{}", oid)?;
+        }
+        for oid in &prerequisites {
+            writeln!(stdin, "^{}", oid)?;
+        }
+    }
+    io::copy(child.stdout.as_mut().expect("stdout is piped"), &mut out)?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let _ = fs::remove_file(&tmp);
+        return Err(Error::PackObjects {
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&tmp, path)?;
+
+    Ok(Header {
+        author: *storage.peer_id(),
+        urn: urn.clone(),
+        refs,
+        prerequisites,
+        pack_offset,
+    })
+}
+
+impl ReadOnly {
+    /// Write the namespace `urn` to a bundle at `path`, see [`create`].
+    pub fn bundle(
+        &self,
+        urn: &Urn,
+        path: &Path,
+        since: Option<&BTreeMap<String, ext::Oid>>,
+    ) -> Result<Header, Error> {
+        create(self, urn, path, since)
+    }
+}
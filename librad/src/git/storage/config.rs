@@ -27,6 +27,8 @@ const CONFIG_USER_NAME: &str = "user.name";
 const CONFIG_USER_EMAIL: &str = "user.email";
 const CONFIG_RAD_SELF: &str = "rad.self";
 const CONFIG_RAD_PEER_ID: &str = "rad.peerid";
+const CONFIG_RAD_SIGREFS_COMPRESSION: &str = "rad.signedrefs.compression";
+const CONFIG_RAD_SIGREFS_V2: &str = "rad.signedrefs.v2";
 
 #[derive(Debug, Error)]
 #[non_exhaustive]
@@ -174,6 +176,33 @@ where
         }
     }
 
+    /// Enable or disable compression of the signed refs written subsequently.
+    ///
+    /// Only the version 2 format supports compression, so this has no effect
+    /// unless [`Config::set_sigrefs_v2`] is enabled, too. Version 1 signed refs
+    /// are written uncompressed, so that peers which only understand version 1
+    /// can still read them.
+    ///
+    /// Cf. [`crate::git::refs::v2`]
+    pub fn set_sigrefs_compression(&mut self, enabled: bool) -> Result<(), Error> {
+        self.inner
+            .set_bool(CONFIG_RAD_SIGREFS_COMPRESSION, enabled)
+            .map_err(Error::from)
+    }
+
+    /// Enable or disable writing signed refs in the version 2 format.
+    ///
+    /// Peers which only understand version 1 are not able to verify version 2
+    /// signed refs, so this should only be enabled once all peers replicating
+    /// from this storage support it.
+    ///
+    /// Cf. [`crate::git::refs::v2`]
+    pub fn set_sigrefs_v2(&mut self, enabled: bool) -> Result<(), Error> {
+        self.inner
+            .set_bool(CONFIG_RAD_SIGREFS_V2, enabled)
+            .map_err(Error::from)
+    }
+
     pub(crate) fn as_raw(&self) -> &git2::Config {
         &self.inner
     }
@@ -200,6 +229,21 @@ impl<S> Config<'_, S> {
             .and_then(|peer_id| peer_id.parse().map_err(Error::from))
     }
 
+    /// Whether signed refs are written compressed. Default: `false`.
+    pub fn sigrefs_compression(&self) -> Result<bool, Error> {
+        self.inner
+            .get_bool(CONFIG_RAD_SIGREFS_COMPRESSION)
+            .or_matches::<Error, _, _>(is_not_found_err, || Ok(false))
+    }
+
+    /// Whether signed refs are written in the version 2 format. Default:
+    /// `false`.
+    pub fn sigrefs_v2(&self) -> Result<bool, Error> {
+        self.inner
+            .get_bool(CONFIG_RAD_SIGREFS_V2)
+            .or_matches::<Error, _, _>(is_not_found_err, || Ok(false))
+    }
+
     pub fn user(&self) -> Result<Option<Urn>, Error> {
         self.inner
             .get_string(CONFIG_RAD_SELF)
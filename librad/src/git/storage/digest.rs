@@ -0,0 +1,168 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Stable digests of the state of a namespace.
+//!
+//! A [`Summary`] condenses the refs of a namespace into a two-level hash
+//! tree: the refs of each peer -- the local peer's own refs, and the view of
+//! every remote peer under `refs/remotes/<peer>` -- are hashed into a digest
+//! per peer, and those are hashed into a single [`Summary::root`].
+//!
+//! Ref names are taken relative to the peer they belong to, so two peers which
+//! have the same view of a project arrive at the same root, regardless of
+//! which of those views is their own. Comparing the roots detects divergence
+//! with a single hash exchange, comparing the per-peer digests narrows it down
+//! to the peers whose views differ, see [`Summary::diverged`].
+//!
+//! The digests are computed by hashing a canonical textual listing of the
+//! refs as git blobs, and are thus stable across implementations.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use git_ext as ext;
+use thiserror::Error;
+
+use super::{ReadOnly, ReadOnlyStorage as _};
+use crate::{git::Urn, PeerId};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Storage(#[from] super::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// Which refs to include in a [`Summary`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    /// Include the `rad/signed_refs` of every peer.
+    ///
+    /// The signed refs are redundant if all refs they list are included, but
+    /// also capture whether the peer (re-)signed them.
+    pub sigrefs: bool,
+    /// Ref prefixes to exclude, relative to the namespace, eg. `refs/tags`.
+    pub hide: Vec<String>,
+}
+
+/// Digest of the refs of a namespace, see the [module
+/// documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq, minicbor::Encode, minicbor::Decode)]
+#[cbor(array)]
+pub struct Summary {
+    /// The digest of the refs of each peer.
+    #[n(0)]
+    pub peers: BTreeMap<PeerId, ext::Oid>,
+}
+
+impl Summary {
+    /// The root of the hash tree.
+    pub fn root(&self) -> ext::Oid {
+        let mut listing = String::new();
+        for (peer, digest) in &self.peers {
+            listing.push_str(&format!("{} {}\n", digest, peer));
+        }
+        hash(&listing)
+    }
+
+    /// The peers whose views differ between `self` and `other`, including
+    /// those present in only one of them.
+    pub fn diverged(&self, other: &Self) -> BTreeSet<PeerId> {
+        self.peers
+            .keys()
+            .chain(other.peers.keys())
+            .filter(|peer| self.peers.get(peer) != other.peers.get(peer))
+            .copied()
+            .collect()
+    }
+
+    /// Whether `self` has the same view as `other` of every peer in `other`.
+    ///
+    /// Fetching from a peer whose summary is `other` would not yield
+    /// anything new in this case.
+    pub fn covers(&self, other: &Self) -> bool {
+        other
+            .peers
+            .iter()
+            .all(|(peer, digest)| self.peers.get(peer) == Some(digest))
+    }
+}
+
+/// Compute the [`Summary`] of `urn`.
+///
+/// Returns `None` if the namespace doesn't exist.
+pub fn summary<S>(storage: &S, urn: &Urn, opts: &Options) -> Result<Option<Summary>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    let urn = urn.clone().with_path(None);
+    if !storage.has_urn(&urn)? {
+        return Ok(None);
+    }
+
+    let prefix = format!("refs/namespaces/{}/", urn.encode_id());
+    let mut refs: BTreeMap<PeerId, Vec<(String, git2::Oid)>> = BTreeMap::new();
+    for reference in storage.backend.references_glob(&format!("{}*", prefix))? {
+        let reference = reference?;
+        let (name, oid) = match (reference.name(), reference.target()) {
+            (Some(name), Some(oid)) => (name, oid),
+            // Symbolic, or not valid UTF-8
+            _ => continue,
+        };
+        let name = match name.strip_prefix(&prefix) {
+            Some(name) if !is_hidden(name, &opts.hide) => name,
+            _ => continue,
+        };
+        if let Some((peer, name)) = split(storage.peer_id(), name) {
+            if !opts.sigrefs && name == "rad/signed_refs" {
+                continue;
+            }
+            refs.entry(peer).or_default().push((name.to_owned(), oid));
+        }
+    }
+
+    let peers = refs
+        .into_iter()
+        .map(|(peer, mut refs)| {
+            refs.sort();
+            let mut listing = String::new();
+            for (name, oid) in refs {
+                listing.push_str(&format!("{} {}\n", oid, name));
+            }
+            (peer, hash(&listing))
+        })
+        .collect();
+
+    Ok(Some(Summary { peers }))
+}
+
+/// Split a namespace-relative ref name into the peer it belongs to, and the
+/// name relative to that peer (without the leading `refs/`).
+fn split<'a>(local: &PeerId, name: &'a str) -> Option<(PeerId, &'a str)> {
+    let name = name.strip_prefix("refs/")?;
+    match name.strip_prefix("remotes/") {
+        None => Some((*local, name)),
+        Some(remote) => {
+            let (peer, name) = remote.split_once('/')?;
+            Some((peer.parse().ok()?, name))
+        },
+    }
+}
+
+fn is_hidden(name: &str, hide: &[String]) -> bool {
+    hide.iter().any(|prefix| {
+        name.strip_prefix(prefix.as_str())
+            .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+fn hash(listing: &str) -> ext::Oid {
+    git2::Oid::hash_object(git2::ObjectType::Blob, listing.as_bytes())
+        .expect("hashing in-memory data is infallible")
+        .into()
+}
@@ -0,0 +1,378 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Bounding the number of namespaces in the monorepo.
+//!
+//! A [`Limit`] caps the number of namespaces. It is enforced after a new
+//! namespace was replicated: if the limit is exceeded, [`make_room`] evicts
+//! namespaces in the order determined by the [`Policy`]. [`plan`] tells
+//! beforehand whether this is possible, so replicating can be refused without
+//! fetching anything. Eviction removes the namespace using
+//! [`super::gc::remove_namespace`], tracking relationships are left intact.
+//!
+//! Besides the [`Limit::pinned`] namespaces, namespaces the local peer has a
+//! stake in are never evicted: identities it is a delegate of (including its
+//! own person identity), and namespaces with local branches (`refs/heads`).
+//! Neither are the namespaces a namespace which is kept refers to via its
+//! `rad/self` or `rad/ids/*`, such as the identities of the delegates of a
+//! project, as the project could no longer be verified without them.
+//!
+//! The default policy, [`Lru`], evicts the namespaces which were least
+//! recently fetched from us first. To support it, the time a namespace was
+//! last served is [`record_served`] when serving a fetch. As this happens on
+//! every fetch, the times are buffered in memory, and written to a file in
+//! the monorepo at most every [`FLUSH_INTERVAL`] -- or when [`flush`]ed.
+//! Times recorded within the interval before the process exits may thus be
+//! lost, which merely makes them look less recently served.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Debug,
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::Mutex;
+use std_ext::time::unix_secs;
+use thiserror::Error;
+
+use super::{gc, ReadOnly, Storage};
+use crate::{
+    git::{identities, Urn},
+    identities::{delegation::Delegations as _, git::SomeIdentity},
+};
+
+/// Name of the file in the monorepo the served times are kept in.
+const SERVED: &str = "link-served";
+
+/// How long [`record_served`] buffers the served times in memory before
+/// writing them out.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    /// Served times not yet written, per monorepo.
+    static ref PENDING: Mutex<HashMap<PathBuf, Pending>> = Mutex::new(HashMap::new());
+}
+
+struct Pending {
+    flushed: Instant,
+    served: BTreeMap<String, u64>,
+}
+
+impl Pending {
+    fn new() -> Self {
+        Self {
+            flushed: Instant::now(),
+            served: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("namespace limit of {max} reached, and no namespace can be evicted")]
+    Exhausted { max: usize },
+
+    #[error(transparent)]
+    Gc(#[from] Box<gc::Error>),
+
+    #[error(transparent)]
+    Identities(#[from] identities::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// A namespace which may be evicted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    pub urn: Urn,
+    /// When the namespace was last fetched from us, if ever.
+    pub last_served: Option<SystemTime>,
+}
+
+/// Determines the order in which namespaces are evicted.
+pub trait Policy: Debug + Send + Sync {
+    /// Sort `candidates` such that the one to evict first comes first.
+    fn rank(&self, candidates: &mut [Candidate]);
+}
+
+/// Evict the least recently served namespaces first.
+///
+/// Namespaces which were never served are evicted before all others.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lru;
+
+impl Policy for Lru {
+    fn rank(&self, candidates: &mut [Candidate]) {
+        candidates.sort_by(|a, b| a.last_served.cmp(&b.last_served))
+    }
+}
+
+/// Limit on the number of namespaces.
+#[derive(Clone, Debug)]
+pub struct Limit {
+    /// Maximum number of namespaces.
+    pub max: usize,
+    /// Namespaces which are never evicted.
+    ///
+    /// Note that pinned namespaces count towards `max`.
+    pub pinned: BTreeSet<Urn>,
+    pub policy: Arc<dyn Policy>,
+}
+
+impl Limit {
+    /// Limit to `max` namespaces, using the [`Lru`] policy.
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            pinned: BTreeSet::new(),
+            policy: Arc::new(Lru),
+        }
+    }
+}
+
+/// The namespaces [`make_room`] would evict to accommodate `urn`, in the
+/// order they would be evicted.
+///
+/// `urn` is counted whether or not it is already present, and is never
+/// evicted.
+///
+/// # Errors
+///
+/// [`Error::Exhausted`] if not enough namespaces can be evicted, because they
+/// are pinned, the local peer has a stake in them, or they are referred to by
+/// namespaces which are kept.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn plan(storage: &Storage, limit: &Limit, urn: &Urn) -> Result<Vec<Urn>, Error> {
+    let urn = urn.clone().with_path(None);
+    let mut urns = identities::any::list_urns(storage)?.collect::<Result<BTreeSet<_>, _>>()?;
+    urns.insert(urn.clone());
+    if urns.len() <= limit.max {
+        return Ok(vec![]);
+    }
+    let excess = urns.len() - limit.max;
+
+    let mut roots = vec![urn.clone()];
+    for candidate in &urns {
+        if limit.pinned.contains(candidate) || is_staked(storage, candidate)? {
+            roots.push(candidate.clone())
+        }
+    }
+    let retained = gc::closure(storage, roots).map_err(Box::new)?;
+
+    let served = served(storage.path())?;
+    let mut candidates = Vec::new();
+    let mut references = BTreeMap::new();
+    for candidate in urns.into_iter().filter(|c| !retained.contains(c)) {
+        let last_served = served
+            .get(&candidate.encode_id())
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(*secs));
+        let refs = gc::references(storage.as_ref(), &candidate).map_err(Box::new)?;
+        references.insert(candidate.clone(), refs);
+        candidates.push(Candidate {
+            urn: candidate,
+            last_served,
+        });
+    }
+    if candidates.len() < excess {
+        return Err(Error::Exhausted { max: limit.max });
+    }
+    limit.policy.rank(&mut candidates);
+
+    // A namespace can only be evicted once no namespace which is kept refers
+    // to it, so pick the first eligible candidate until we have enough. Evicting
+    // one may make an earlier one eligible, eg. the delegate of a project.
+    let mut evict = Vec::with_capacity(excess);
+    while evict.len() < excess {
+        let next = candidates.iter().map(|c| &c.urn).find(|candidate| {
+            !evict.contains(*candidate)
+                && !references.iter().any(|(other, refs)| {
+                    other != *candidate && !evict.contains(other) && refs.contains(*candidate)
+                })
+        });
+        match next {
+            Some(candidate) => evict.push(candidate.clone()),
+            None => return Err(Error::Exhausted { max: limit.max }),
+        }
+    }
+
+    Ok(evict)
+}
+
+/// Evict namespaces until `limit` is no longer exceeded, sparing `urn`.
+///
+/// This is meant to be called after `urn` was successfully replicated, see
+/// [`plan`]. Returns the evicted namespaces, in the order they were evicted.
+///
+/// # Errors
+///
+/// [`Error::Exhausted`] if not enough namespaces can be evicted. Nothing is
+/// evicted in this case.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn make_room(storage: &Storage, limit: &Limit, urn: &Urn) -> Result<Vec<Urn>, Error> {
+    let evict = plan(storage, limit, urn)?;
+    let mut evicted = Vec::with_capacity(evict.len());
+    for urn in evict {
+        gc::remove_namespace(storage, &urn).map_err(Box::new)?;
+        forget(storage, &urn)?;
+        tracing::info!(urn = %urn, "evicted namespace");
+        evicted.push(urn);
+    }
+
+    Ok(evicted)
+}
+
+/// Whether the local peer has a stake in `urn`: it is a delegate of the
+/// identity, or it has local branches in the namespace.
+///
+/// Identities which can't be read are considered staked, so they are left
+/// alone rather than evicted on a guess.
+fn is_staked(storage: &Storage, urn: &Urn) -> Result<bool, Error> {
+    let heads = format!("refs/namespaces/{}/refs/heads/*", urn.encode_id());
+    if storage.as_raw().references_glob(&heads)?.next().is_some() {
+        return Ok(true);
+    }
+
+    let key = storage.peer_id().as_public_key();
+    let votes = Some(key).into_iter().collect::<BTreeSet<_>>();
+    let delegate = match identities::any::get(storage, urn) {
+        Ok(None) => false,
+        Ok(Some(SomeIdentity::Person(person))) => person
+            .eligible(votes)
+            .map(|eligible| !eligible.is_empty())
+            .unwrap_or(true),
+        Ok(Some(SomeIdentity::Project(project))) => project
+            .eligible(votes)
+            .map(|eligible| !eligible.is_empty())
+            .unwrap_or(true),
+        Err(e) => {
+            tracing::warn!(err = %e, urn = %urn, "failed to read identity, not evicting");
+            true
+        },
+    };
+
+    Ok(delegate)
+}
+
+/// When `urn` was last fetched from us, if ever.
+pub fn last_served<S>(storage: &S, urn: &Urn) -> Result<Option<SystemTime>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    Ok(served(storage.as_ref().path())?
+        .get(&urn.encode_id())
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(*secs)))
+}
+
+/// Record that the namespace `id` of the monorepo at `git_dir` was fetched
+/// from us `at` the given time.
+///
+/// Takes the path of the monorepo, as serving fetches doesn't require to open
+/// the [`Storage`]. The time is written out at most every [`FLUSH_INTERVAL`],
+/// see the [module documentation](self).
+pub fn record_served(git_dir: &Path, id: &str, at: SystemTime) -> Result<(), Error> {
+    let secs = unix_secs(at);
+    let mut pending = PENDING.lock();
+    let entry = pending
+        .entry(git_dir.to_path_buf())
+        .or_insert_with(Pending::new);
+    let served = entry.served.entry(id.to_owned()).or_default();
+    *served = (*served).max(secs);
+    if entry.flushed.elapsed() >= FLUSH_INTERVAL {
+        write(git_dir, &mut entry.served, &BTreeSet::new())?;
+        entry.flushed = Instant::now();
+    }
+
+    Ok(())
+}
+
+/// Write out the served times buffered by [`record_served`] for the monorepo
+/// at `git_dir`.
+pub fn flush(git_dir: &Path) -> Result<(), Error> {
+    let mut pending = PENDING.lock();
+    if let Some(entry) = pending.get_mut(git_dir) {
+        write(git_dir, &mut entry.served, &BTreeSet::new())?;
+        entry.flushed = Instant::now();
+    }
+
+    Ok(())
+}
+
+/// Forget when `urn` was last served.
+pub fn forget(storage: &Storage, urn: &Urn) -> Result<(), Error> {
+    let mut pending = PENDING.lock();
+    let mut buffered = BTreeMap::new();
+    let entry = pending.get_mut(storage.path());
+    let served = entry
+        .map(|entry| &mut entry.served)
+        .unwrap_or(&mut buffered);
+    let forgotten = Some(urn.encode_id()).into_iter().collect();
+
+    write(storage.path(), served, &forgotten)
+}
+
+/// The served times of the monorepo at `git_dir`, including the buffered
+/// ones.
+fn served(git_dir: &Path) -> Result<BTreeMap<String, u64>, Error> {
+    let mut served = read(git_dir)?;
+    if let Some(entry) = PENDING.lock().get(git_dir) {
+        for (id, secs) in &entry.served {
+            let at = served.entry(id.clone()).or_default();
+            *at = (*at).max(*secs);
+        }
+    }
+
+    Ok(served)
+}
+
+fn read(git_dir: &Path) -> Result<BTreeMap<String, u64>, Error> {
+    let contents = match fs::read_to_string(git_dir.join(SERVED)) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(contents
+        .lines()
+        .filter_map(|line| {
+            let (id, secs) = line.split_once(' ')?;
+            Some((id.to_owned(), secs.parse().ok()?))
+        })
+        .collect())
+}
+
+/// Merge `pending` into the file of served times, dropping the `forgotten`
+/// ids. `pending` is drained on success.
+///
+/// Must be called with [`PENDING`] locked.
+fn write(
+    git_dir: &Path,
+    pending: &mut BTreeMap<String, u64>,
+    forgotten: &BTreeSet<String>,
+) -> Result<(), Error> {
+    let mut served = read(git_dir)?;
+    for (id, secs) in pending.iter() {
+        let at = served.entry(id.clone()).or_default();
+        *at = (*at).max(*secs);
+    }
+    served.retain(|id, _| !forgotten.contains(id));
+
+    let mut tmp = tempfile::NamedTempFile::new_in(git_dir)?;
+    for (id, secs) in &served {
+        writeln!(tmp, "{} {}", id, secs)?;
+    }
+    tmp.persist(git_dir.join(SERVED)).map_err(|e| e.error)?;
+    pending.clear();
+
+    Ok(())
+}
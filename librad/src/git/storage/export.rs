@@ -0,0 +1,469 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Materialising a namespace as a standalone git repository.
+//!
+//! This is the reverse of replication: the refs of a namespace are copied into
+//! a regular repository, laid out the way `git` expects them.
+//!
+//! * `refs/namespaces/<urn>/refs/heads/*` becomes `refs/heads/*`
+//! * `refs/namespaces/<urn>/refs/tags/*` becomes `refs/tags/*`
+//! * `refs/namespaces/<urn>/refs/remotes/<peer>/heads/*` becomes
+//!   `refs/remotes/<handle>@<peer>/*`
+//!
+//! The generated config contains a remote for every exported remote peer, as
+//! well as one named [`REMOTE_NAME`] for the local peer, all pointing back to
+//! the monorepo via [`LocalUrl`]s (cf. [`crate::git::include`]). The
+//! repository is thus usable on its own, but can still be synced with the
+//! monorepo if the `rad` transport is available.
+//!
+//! Alternatively, the repository can be written as a [`Target::Bundle`]. This
+//! requires the `git` executable.
+//!
+//! [`layout`] computes the same mapping without materialising it, eg. for
+//! serving a namespace to vanilla `git` clients.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom as _,
+    fs,
+    io,
+    iter,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+};
+
+use either::Either;
+use git_ext as ext;
+use thiserror::Error;
+
+use super::ReadOnly;
+use crate::{
+    git::{
+        identities,
+        local::url::LocalUrl,
+        types::{Namespace, Reference},
+        Urn,
+    },
+    identities::git::{Project, SomeIdentity},
+    PeerId,
+};
+
+/// The name of the remote pointing to the local peer's view of the project.
+pub const REMOTE_NAME: &str = "rad";
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("no identity found for {0}")]
+    NotFound(Urn),
+
+    #[error("`{0}` already exists and is not empty")]
+    Exists(PathBuf),
+
+    #[error("`git bundle` exited with {status}: {stderr}")]
+    Bundle { status: ExitStatus, stderr: String },
+
+    #[error(transparent)]
+    Identities(#[from] identities::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Where to [`export`] to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// A repository at the given path, which must not exist or be an empty
+    /// directory.
+    Repository { path: PathBuf, bare: bool },
+    /// A `git bundle` file at the given path.
+    Bundle(PathBuf),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Options {
+    /// Also export the branches of remote peers.
+    ///
+    /// Default: `true`
+    pub remotes: bool,
+    /// Also export tags.
+    ///
+    /// Default: `true`
+    pub tags: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            remotes: true,
+            tags: true,
+        }
+    }
+}
+
+/// The result of [`export`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Exported {
+    /// The exported remotes, by the name of the remote in the exported
+    /// repository.
+    pub remotes: BTreeMap<String, PeerId>,
+    /// The branch `HEAD` points to, if any.
+    pub head: Option<String>,
+}
+
+/// The refs of a namespace laid out the way `git` expects them, see [`layout`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Layout {
+    /// The target of each ref, by its name in the conventional layout.
+    pub refs: BTreeMap<String, ext::Oid>,
+    /// The name of the ref in the monorepo each ref was taken from.
+    pub sources: BTreeMap<String, String>,
+    /// The branch `HEAD` points to, if any.
+    pub head: Option<String>,
+}
+
+/// Export the namespace `urn` to `target`.
+///
+/// For projects, `HEAD` is set to the default branch. Otherwise, or if the
+/// default branch does not exist, `HEAD` points to the first branch in
+/// lexicographic order. Non-bare repositories have `HEAD` checked out.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn export<S>(storage: &S, urn: &Urn, target: &Target, opts: Options) -> Result<Exported, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    match target {
+        Target::Repository { path, bare } => {
+            ensure_empty(path)?;
+            let repo = git2::Repository::init_opts(
+                path,
+                git2::RepositoryInitOptions::new()
+                    .bare(*bare)
+                    .no_reinit(true)
+                    .external_template(false),
+            )?;
+            let exported = materialise(storage, urn, &repo, opts)?;
+            if !bare && exported.head.is_some() {
+                repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))?;
+            }
+
+            Ok(exported)
+        },
+
+        Target::Bundle(path) => {
+            let tmp = tempfile::tempdir()?;
+            let repo = git2::Repository::init_opts(
+                tmp.path(),
+                git2::RepositoryInitOptions::new()
+                    .bare(true)
+                    .external_template(false),
+            )?;
+            let exported = materialise(storage, urn, &repo, opts)?;
+            bundle(tmp.path(), path)?;
+
+            Ok(exported)
+        },
+    }
+}
+
+impl ReadOnly {
+    /// Export the namespace `urn` to `target`, see [`export`].
+    pub fn export(&self, urn: &Urn, target: &Target, opts: Options) -> Result<Exported, Error> {
+        export(self, urn, target, opts)
+    }
+}
+
+/// Compute the refs of the namespace `urn` in the conventional layout.
+///
+/// The mapping is the same as for [`export`], except that remote branches are
+/// named `refs/remotes/<peer>/*`. If the local peer does not have the default
+/// branch of a project -- as is typically the case on seeds -- it is taken from
+/// the delegates, provided their tips lie on a single line of history. `HEAD`
+/// is chosen like for [`export`].
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn layout<S>(storage: &S, urn: &Urn, opts: Options) -> Result<Layout, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    let identity =
+        identities::any::get(storage, urn)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+    let namespace = format!("refs/namespaces/{}/", urn.encode_id());
+
+    let mut refs = BTreeMap::new();
+    let mut sources = BTreeMap::new();
+    for r in storage
+        .backend
+        .references_glob(&format!("{}refs/*", namespace))?
+    {
+        let r = r?;
+        let (source, name, oid) = match (r.name(), r.target()) {
+            (Some(source), Some(oid)) => match source.strip_prefix(&namespace) {
+                Some(name) => (source, name, oid),
+                None => continue,
+            },
+            _ => continue,
+        };
+        let local =
+            name.starts_with("refs/heads/") || (opts.tags && name.starts_with("refs/tags/"));
+        let name = if local {
+            Some(name.to_owned())
+        } else if opts.remotes {
+            name.strip_prefix("refs/remotes/")
+                .and_then(|rest| rest.split_once("/heads/"))
+                .filter(|(peer, _)| peer.parse::<PeerId>().is_ok())
+                .map(|(peer, branch)| format!("refs/remotes/{}/{}", peer, branch))
+        } else {
+            None
+        };
+        if let Some(name) = name {
+            refs.insert(name.clone(), oid.into());
+            sources.insert(name, source.to_owned());
+        }
+    }
+
+    let default_branch = match identity {
+        SomeIdentity::Project(project) => match &project.subject().default_branch {
+            Some(branch) => {
+                let branch = branch.to_string();
+                let head = format!("refs/heads/{}", branch);
+                if !refs.contains_key(&head) {
+                    if let Some((source, tip)) =
+                        delegates_tip(storage, &namespace, &project, &branch)?
+                    {
+                        refs.insert(head.clone(), tip.into());
+                        sources.insert(head, source);
+                    }
+                }
+                Some(branch)
+            },
+            None => None,
+        },
+        _ => None,
+    };
+    let head = match default_branch.filter(|b| refs.contains_key(&format!("refs/heads/{}", b))) {
+        Some(branch) => Some(branch),
+        None => refs
+            .keys()
+            .find_map(|name| name.strip_prefix("refs/heads/"))
+            .map(ToOwned::to_owned),
+    };
+
+    Ok(Layout {
+        refs,
+        sources,
+        head,
+    })
+}
+
+impl ReadOnly {
+    /// Compute the refs of the namespace `urn` in the conventional layout, see
+    /// [`layout`].
+    pub fn layout(&self, urn: &Urn, opts: Options) -> Result<Layout, Error> {
+        layout(self, urn, opts)
+    }
+}
+
+/// The most recent tip of `branch` among the delegates of `project`, along
+/// with the name of its ref, if all other tips are its ancestors.
+fn delegates_tip(
+    storage: &ReadOnly,
+    namespace: &str,
+    project: &Project,
+    branch: &str,
+) -> Result<Option<(String, git2::Oid)>, Error> {
+    let repo = &storage.backend;
+    let peers = project
+        .delegations()
+        .into_iter()
+        .flat_map(|d| -> Box<dyn Iterator<Item = PeerId>> {
+            match d {
+                Either::Left(key) => Box::new(iter::once(PeerId::from(*key))),
+                Either::Right(person) => Box::new(
+                    person
+                        .delegations()
+                        .into_iter()
+                        .map(|key| PeerId::from(*key)),
+                ),
+            }
+        })
+        .collect::<BTreeSet<_>>();
+
+    let mut tips = BTreeMap::new();
+    for peer in peers {
+        let name = format!("{}refs/remotes/{}/heads/{}", namespace, peer, branch);
+        match repo.refname_to_id(&name) {
+            Ok(oid) => {
+                tips.entry(oid).or_insert(name);
+            },
+            Err(e) if ext::is_not_found_err(&e) => {},
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    for (tip, name) in &tips {
+        let mut descends = true;
+        for other in tips.keys() {
+            if other != tip && !repo.graph_descendant_of(*tip, *other)? {
+                descends = false;
+                break;
+            }
+        }
+        if descends {
+            return Ok(Some((name.clone(), *tip)));
+        }
+    }
+
+    Ok(None)
+}
+
+fn materialise(
+    storage: &ReadOnly,
+    urn: &Urn,
+    repo: &git2::Repository,
+    opts: Options,
+) -> Result<Exported, Error> {
+    let identity =
+        identities::any::get(storage, urn)?.ok_or_else(|| Error::NotFound(urn.clone()))?;
+    let namespace = format!("refs/namespaces/{}", urn.encode_id());
+
+    let mut specs = vec![format!("+{}/refs/heads/*:refs/heads/*", namespace)];
+    if opts.tags {
+        specs.push(format!("+{}/refs/tags/*:refs/tags/*", namespace));
+    }
+    let mut remotes = BTreeMap::new();
+    if opts.remotes {
+        for peer in remote_peers(storage, &namespace)? {
+            let name = remote_name(storage, urn, peer);
+            specs.push(format!(
+                "+{}/refs/remotes/{}/heads/*:refs/remotes/{}/*",
+                namespace, peer, name
+            ));
+            remotes.insert(name, peer);
+        }
+    }
+
+    {
+        let mut fetch_opts = git2::FetchOptions::new();
+        fetch_opts.download_tags(git2::AutotagOption::None);
+        let mut remote = repo.remote_anonymous(&storage.path().to_string_lossy())?;
+        remote.fetch(&specs, Some(&mut fetch_opts), None)?;
+    }
+
+    let url = LocalUrl::from(urn.clone()).to_string();
+    repo.remote_with_fetch(
+        REMOTE_NAME,
+        &url,
+        &format!("+refs/heads/*:refs/remotes/{}/*", REMOTE_NAME),
+    )?;
+    for (name, peer) in &remotes {
+        repo.remote_with_fetch(
+            name,
+            &url,
+            &format!("+refs/remotes/{}/heads/*:refs/remotes/{}/*", peer, name),
+        )?;
+    }
+
+    let default_branch = match identity {
+        SomeIdentity::Project(project) => project
+            .subject()
+            .default_branch
+            .as_ref()
+            .map(|b| b.to_string()),
+        _ => None,
+    };
+    let head = match default_branch
+        .filter(|b| repo.find_reference(&format!("refs/heads/{}", b)).is_ok())
+    {
+        Some(branch) => Some(branch),
+        None => repo
+            .references_glob("refs/heads/*")?
+            .filter_map(|r| r.ok())
+            .filter_map(|r| r.shorthand().map(ToOwned::to_owned))
+            .min(),
+    };
+    if let Some(branch) = &head {
+        repo.set_head(&format!("refs/heads/{}", branch))?;
+        let mut config = repo.config()?;
+        config.set_str(&format!("branch.{}.remote", branch), REMOTE_NAME)?;
+        config.set_str(
+            &format!("branch.{}.merge", branch),
+            &format!("refs/heads/{}", branch),
+        )?;
+    }
+
+    Ok(Exported { remotes, head })
+}
+
+/// The peers which have refs under `refs/remotes` of `namespace`.
+fn remote_peers(storage: &ReadOnly, namespace: &str) -> Result<BTreeSet<PeerId>, Error> {
+    let prefix = format!("{}/refs/remotes/", namespace);
+    let mut peers = BTreeSet::new();
+    for r in storage.backend.references_glob(&format!("{}*", prefix))? {
+        let r = r?;
+        let peer = r
+            .name()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .and_then(|rest| rest.split('/').next())
+            .and_then(|peer| peer.parse::<PeerId>().ok());
+        peers.extend(peer);
+    }
+
+    Ok(peers)
+}
+
+/// `<handle>@<peer>` if the person identity of `peer` is available, `<peer>`
+/// otherwise.
+fn remote_name(storage: &ReadOnly, urn: &Urn, peer: PeerId) -> String {
+    let handle = Urn::try_from(Reference::rad_self(Namespace::from(urn), peer))
+        .ok()
+        .and_then(|urn| identities::person::get(storage, &urn).ok().flatten())
+        .and_then(|person| {
+            ext::RefLike::try_from(format!("{}@{}", person.subject().name, peer)).ok()
+        });
+    match handle {
+        Some(name) => name.to_string(),
+        None => peer.to_string(),
+    }
+}
+
+fn ensure_empty(path: &Path) -> Result<(), Error> {
+    match fs::read_dir(path) {
+        Ok(mut entries) => {
+            if entries.next().is_some() {
+                return Err(Error::Exists(path.to_path_buf()));
+            }
+            Ok(())
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn bundle(repo: &Path, out: &Path) -> Result<(), Error> {
+    let out = Command::new("git")
+        .arg("--git-dir")
+        .arg(repo)
+        .args(&["bundle", "create"])
+        .arg(out)
+        .arg("--all")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()?;
+    if !out.status.success() {
+        return Err(Error::Bundle {
+            status: out.status,
+            stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,253 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Fault injection, for testing.
+//!
+//! A [`Faults`] schedule, once [`install`]ed for a [`Paths`], is consulted by
+//! every [`super::Storage`] on those paths (including the ones held by a
+//! [`super::Pool`]) before performing one of the [`Op`]s it covers. Each
+//! [`Rule`] may fail or delay a range of occurrences of an [`Op`], which
+//! allows to simulate a crash or a slow disk at a precise point of, say, a
+//! replication run, and to assert that the storage is left in a consistent
+//! state afterwards.
+//!
+//! Only available with the `fault-injection` feature.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use thiserror::Error;
+
+use crate::paths::Paths;
+
+lazy_static! {
+    static ref INSTALLED: Mutex<HashMap<PathBuf, Faults>> = Mutex::new(HashMap::new());
+}
+
+/// The operations faults can be injected into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Op {
+    /// Updating refs, either through a [`super::Transaction`], when updating
+    /// `rad/signed_refs`, or when applying the ref updates of a replication
+    /// run.
+    RefUpdate,
+    /// Writing objects, ie. the commit of a `rad/signed_refs` update or the
+    /// packfile received during replication.
+    ObjectWrite,
+    /// Checking out a storage from a [`super::Pool`].
+    Checkout,
+    /// Removing the journal of a [`super::Transaction`] once its edits are
+    /// applied. Failing it leaves the journal behind, as if the process died
+    /// right before, so the edits are rolled back when the storage is next
+    /// opened.
+    JournalRemove,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Fail the operation with a [`Fault`].
+    Fail,
+    /// Block for the given duration before proceeding with the operation.
+    Delay(Duration),
+}
+
+/// Apply an [`Action`] to some occurrences of an [`Op`].
+///
+/// The occurrences of every [`Op`] are counted, starting from zero. A rule
+/// applies to occurrences `skip..skip + times`, or to all from `skip` onwards
+/// if `times` is `None`.
+#[derive(Clone, Copy, Debug)]
+pub struct Rule {
+    pub op: Op,
+    pub action: Action,
+    pub skip: usize,
+    pub times: Option<usize>,
+}
+
+impl Rule {
+    pub fn fail(op: Op) -> Self {
+        Self {
+            op,
+            action: Action::Fail,
+            skip: 0,
+            times: None,
+        }
+    }
+
+    pub fn delay(op: Op, duration: Duration) -> Self {
+        Self {
+            op,
+            action: Action::Delay(duration),
+            skip: 0,
+            times: None,
+        }
+    }
+
+    /// Let the first `n` occurrences pass.
+    pub fn skip(self, n: usize) -> Self {
+        Self { skip: n, ..self }
+    }
+
+    /// Apply to `n` occurrences only.
+    pub fn times(self, n: usize) -> Self {
+        Self {
+            times: Some(n),
+            ..self
+        }
+    }
+
+    /// Apply to a single occurrence only.
+    pub fn once(self) -> Self {
+        self.times(1)
+    }
+
+    fn applies(&self, op: Op, nth: usize) -> bool {
+        self.op == op
+            && nth >= self.skip
+            && self
+                .times
+                .map(|times| nth < self.skip + times)
+                .unwrap_or(true)
+    }
+}
+
+/// The error returned by an operation failed by a [`Rule`].
+#[derive(Clone, Copy, Debug, Error, PartialEq, Eq)]
+#[error("injected fault: {op:?} #{nth}")]
+pub struct Fault {
+    pub op: Op,
+    /// The occurrence of `op` which was failed.
+    pub nth: usize,
+}
+
+/// A schedule of faults, see the [module documentation](self).
+///
+/// Cloning yields a handle to the same schedule, so rules can be added and
+/// occurrences inspected while it is installed.
+#[derive(Clone, Default)]
+pub struct Faults(Arc<Mutex<Inner>>);
+
+#[derive(Default)]
+struct Inner {
+    rules: Vec<Rule>,
+    seen: BTreeMap<Op, usize>,
+    injected: Vec<Fault>,
+}
+
+impl Faults {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(self, rule: Rule) -> Self {
+        self.push(rule);
+        self
+    }
+
+    /// Add a rule. If several rules apply to an occurrence, the first one
+    /// added wins.
+    pub fn push(&self, rule: Rule) {
+        self.0.lock().rules.push(rule)
+    }
+
+    /// Remove all rules, but keep counting occurrences.
+    pub fn clear(&self) {
+        self.0.lock().rules.clear()
+    }
+
+    /// The number of times `op` was attempted so far.
+    pub fn occurrences(&self, op: Op) -> usize {
+        self.0.lock().seen.get(&op).copied().unwrap_or(0)
+    }
+
+    /// The [`Fault`]s injected so far.
+    pub fn injected(&self) -> Vec<Fault> {
+        self.0.lock().injected.clone()
+    }
+
+    fn next(&self, op: Op) -> (usize, Option<Action>) {
+        let mut inner = self.0.lock();
+        let nth = {
+            let seen = inner.seen.entry(op).or_insert(0);
+            let nth = *seen;
+            *seen += 1;
+            nth
+        };
+        let action = inner
+            .rules
+            .iter()
+            .find(|rule| rule.applies(op, nth))
+            .map(|rule| rule.action);
+        if let Some(Action::Fail) = action {
+            inner.injected.push(Fault { op, nth });
+        }
+        (nth, action)
+    }
+
+    /// Record an occurrence of `op`, and apply the scheduled [`Action`], if
+    /// any.
+    pub(crate) fn inject(&self, op: Op) -> Result<(), Fault> {
+        match self.next(op) {
+            (nth, Some(Action::Fail)) => {
+                tracing::warn!(?op, nth, "injecting failure");
+                Err(Fault { op, nth })
+            },
+            (nth, Some(Action::Delay(duration))) => {
+                tracing::warn!(?op, nth, ?duration, "injecting delay");
+                thread::sleep(duration);
+                Ok(())
+            },
+            (_, None) => Ok(()),
+        }
+    }
+
+    /// Like [`Faults::inject`], but without blocking the executor on delays.
+    pub(crate) async fn inject_async(&self, op: Op) -> Result<(), Fault> {
+        match self.next(op) {
+            (nth, Some(Action::Fail)) => {
+                tracing::warn!(?op, nth, "injecting failure");
+                Err(Fault { op, nth })
+            },
+            (nth, Some(Action::Delay(duration))) => {
+                tracing::warn!(?op, nth, ?duration, "injecting delay");
+                link_async::sleep(duration).await;
+                Ok(())
+            },
+            (_, None) => Ok(()),
+        }
+    }
+}
+
+/// Install `faults` for all storages on `paths`, replacing the ones installed
+/// previously.
+///
+/// This takes effect immediately, also for storages which are already open.
+pub fn install(paths: &Paths, faults: Faults) {
+    INSTALLED.lock().insert(key(paths.git_dir()), faults);
+}
+
+/// Remove the [`Faults`] installed for `paths`, if any.
+pub fn uninstall(paths: &Paths) -> Option<Faults> {
+    INSTALLED.lock().remove(&key(paths.git_dir()))
+}
+
+pub(crate) fn lookup(git_dir: &Path) -> Option<Faults> {
+    let installed = INSTALLED.lock();
+    if installed.is_empty() {
+        return None;
+    }
+    installed.get(&key(git_dir)).cloned()
+}
+
+fn key(git_dir: &Path) -> PathBuf {
+    fs::canonicalize(git_dir).unwrap_or_else(|_| git_dir.to_path_buf())
+}
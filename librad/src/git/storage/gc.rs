@@ -0,0 +1,404 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Reclaiming space from the monorepo.
+//!
+//! Namespaces are never removed, and remotes are kept around after the peers
+//! they belong to are untracked. Over time, this accumulates refs (and the
+//! objects only reachable from them) which are of no use to anyone. This
+//! module provides the building blocks to clean up:
+//!
+//! * [`unreferenced`] lists namespaces which nobody is interested in anymore,
+//!   which can then be removed using [`remove_namespace`].
+//! * [`untrack_expired`] removes tracking entries whose
+//!   [`tracking::Config::expires`] has passed.
+//! * [`expire_remotes`] removes the remotes of peers which are no longer
+//!   tracked.
+//! * [`pack`] repacks the repository and prunes unreachable objects, using `git
+//!   gc`.
+//!
+//! Packing must not run concurrently with fetches into the same repository,
+//! as objects which were just received, but are not yet referenced, could be
+//! pruned. Fetches thus hold a [`fetch_lock`] for their duration, while
+//! [`pack`] waits for all of them to finish, and prevents new ones from
+//! starting until it is done. Note that this only coordinates within the same
+//! process: `git gc` only prunes objects older than the `prune` expiry, which
+//! is assumed to be longer than any fetch from another process takes.
+
+use std::{
+    collections::BTreeSet,
+    convert::TryFrom,
+    io,
+    process::{Command, ExitStatus, Stdio},
+    time::SystemTime,
+};
+
+use either::Either::{Left, Right};
+use parking_lot::{RwLock, RwLockReadGuard};
+use std_ext::time::unix_secs;
+use thiserror::Error;
+
+use super::{quota, ReadOnly, Storage};
+use crate::{
+    git::{
+        identities::{self, SomeIdentity},
+        refs,
+        tracking,
+        types::{Namespace, Reference},
+        Urn,
+    },
+    PeerId,
+};
+
+lazy_static! {
+    static ref LOCK: RwLock<()> = RwLock::new(());
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("`git gc` exited with {status}: {stderr}")]
+    Gc { status: ExitStatus, stderr: String },
+
+    #[error(transparent)]
+    Identities(#[from] identities::Error),
+
+    #[error(transparent)]
+    IsTracked(#[from] tracking::error::IsTracked),
+
+    #[error(transparent)]
+    TrackedPeers(#[from] tracking::error::TrackedPeers),
+
+    #[error(transparent)]
+    Tracked(#[from] tracking::error::Tracked),
+
+    #[error(transparent)]
+    Untrack(#[from] tracking::error::Untrack),
+
+    #[error(transparent)]
+    Quota(#[from] quota::Error),
+
+    #[error(transparent)]
+    Read(#[from] super::read::Error),
+
+    #[error(transparent)]
+    Config(#[from] super::config::Error),
+
+    #[error(transparent)]
+    Refs(#[from] refs::stored::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Acquire the lock preventing [`pack`] from running while fetching.
+///
+/// Many fetches can hold the lock at the same time. The lock is released when
+/// the returned guard is dropped.
+pub fn fetch_lock() -> RwLockReadGuard<'static, ()> {
+    LOCK.read()
+}
+
+/// List the namespaces which nobody is interested in anymore.
+///
+/// A namespace is referenced, and thus not listed, if:
+///
+/// * it is tracked, or any peers are tracked in it
+/// * it contains local branches
+/// * it is the local identity (`rad.self`), or the local peer is a delegate
+/// * the `rad/self` or `rad/ids/*` of a referenced namespace point to it, eg.
+///   because it is the identity of a delegate of a referenced project
+///
+/// Unreferenced namespaces are typically left behind after untracking a
+/// project, or when replication of an identity was triggered by a peer we are
+/// not interested in.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn unreferenced<S>(storage: &S) -> Result<Vec<Urn>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    let urns = identities::any::list_urns(storage)?.collect::<Result<BTreeSet<_>, _>>()?;
+
+    let mut roots = storage.config()?.user()?.into_iter().collect::<Vec<_>>();
+    for urn in &urns {
+        if is_referenced(storage, urn)? {
+            roots.push(urn.clone())
+        }
+    }
+    let referenced = closure(storage, roots)?;
+
+    Ok(urns
+        .into_iter()
+        .filter(|urn| !referenced.contains(urn))
+        .collect())
+}
+
+/// Whether `urn` is referenced in its own right, ie. not via another namespace.
+fn is_referenced(storage: &ReadOnly, urn: &Urn) -> Result<bool, Error> {
+    if tracking::is_tracked(storage, urn, None)?
+        || tracking::tracked_peers(storage, Some(urn))?
+            .next()
+            .is_some()
+    {
+        return Ok(true);
+    }
+
+    let heads = format!("refs/namespaces/{}/refs/heads/*", urn.encode_id());
+    if storage.backend.references_glob(&heads)?.next().is_some() {
+        return Ok(true);
+    }
+
+    Ok(delegates(storage, urn)?.contains(storage.peer_id()))
+}
+
+/// The namespaces `roots` and everything reachable from them through
+/// [`references`].
+pub(super) fn closure<S, I>(storage: &S, roots: I) -> Result<BTreeSet<Urn>, Error>
+where
+    S: AsRef<ReadOnly>,
+    I: IntoIterator<Item = Urn>,
+{
+    let storage = storage.as_ref();
+    let mut pending = roots.into_iter().collect::<Vec<_>>();
+    let mut reachable = BTreeSet::new();
+    while let Some(urn) = pending.pop() {
+        if reachable.insert(urn.clone()) {
+            pending.extend(references(storage, &urn)?)
+        }
+    }
+
+    Ok(reachable)
+}
+
+/// The identities the `rad/self` and `rad/ids/*` refs of `urn` point to,
+/// including those of its remotes.
+pub(super) fn references(storage: &ReadOnly, urn: &Urn) -> Result<Vec<Urn>, Error> {
+    let namespace = format!("refs/namespaces/{}/refs/", urn.encode_id());
+    let mut refs = Vec::new();
+
+    for glob in &["rad/ids/*", "remotes/*/rad/ids/*"] {
+        for r in storage
+            .backend
+            .references_glob(&format!("{}{}", namespace, glob))?
+        {
+            let r = r?;
+            if let Some(urn) = r
+                .name()
+                .and_then(|name| name.rsplit('/').next())
+                .and_then(|id| Urn::try_from_id(id).ok())
+            {
+                refs.push(urn)
+            }
+        }
+    }
+
+    let remotes = format!("{}remotes/", namespace);
+    let mut peers = vec![None];
+    for r in storage
+        .backend
+        .references_glob(&format!("{}*/rad/self", remotes))?
+    {
+        let r = r?;
+        if let Some(peer) = r
+            .name()
+            .and_then(|name| name.strip_prefix(&remotes)?.split('/').next())
+            .and_then(|peer| peer.parse::<PeerId>().ok())
+        {
+            peers.push(Some(peer))
+        }
+    }
+    for peer in peers {
+        let rad_self = Urn::try_from(Reference::rad_self(Namespace::from(urn), peer))
+            .expect("namespace is set");
+        if let Some(person) = identities::person::get(storage, &rad_self)? {
+            refs.push(person.urn())
+        }
+    }
+
+    Ok(refs)
+}
+
+/// Remove all refs of the namespace `urn`.
+///
+/// Returns the number of refs removed. The objects they pointed to are only
+/// removed by a subsequent [`pack`]. The storage usage recorded for `urn` is
+/// [`quota::reset`].
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn remove_namespace(storage: &Storage, urn: &Urn) -> Result<usize, Error> {
+    let prefix = format!("refs/namespaces/{}/*", urn.encode_id());
+    let n = delete_refs(storage, &prefix)?;
+    quota::reset(storage, urn)?;
+
+    Ok(n)
+}
+
+/// Untrack all peers whose tracking entry expired as of `now`.
+///
+/// Returns the untracked `(urn, peer)` pairs. Their remotes are only removed
+/// by a subsequent [`expire_remotes`].
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn untrack_expired(storage: &Storage, now: SystemTime) -> Result<Vec<(Urn, PeerId)>, Error> {
+    let now = unix_secs(now);
+    // Don't untrack while iterating the refdb
+    let mut expired = Vec::new();
+    for tracked in tracking::tracked(storage, None)? {
+        if let tracking::Tracked::Peer { urn, peer, config } = tracked? {
+            if config.is_expired(now) {
+                expired.push((urn, peer));
+            }
+        }
+    }
+
+    let mut untracked = Vec::with_capacity(expired.len());
+    for (urn, peer) in expired {
+        let args = tracking::UntrackArgs::new(tracking::policy::Untrack::MustExist);
+        // The entry may have been removed concurrently, in which case there
+        // is nothing left to do.
+        if tracking::untrack(storage, &urn, peer, args)?.is_ok() {
+            tracing::debug!(urn = %urn, peer = %peer, "untracked expired peer");
+            untracked.push((urn, peer));
+        }
+    }
+
+    Ok(untracked)
+}
+
+/// Remove the remotes of all peers in the namespace `urn` which are neither
+/// tracked, nor delegates of the identity.
+///
+/// The `rad/signed_refs` of the local peer are updated if any remotes were
+/// removed. Returns the peers whose remotes were removed.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn expire_remotes(storage: &Storage, urn: &Urn) -> Result<BTreeSet<PeerId>, Error> {
+    let keep = {
+        let mut keep = delegates(storage, urn)?;
+        for peer in tracking::tracked_peers(storage, Some(urn))? {
+            keep.insert(peer?);
+        }
+        keep
+    };
+
+    let remotes = format!("refs/namespaces/{}/refs/remotes/", urn.encode_id());
+    let peers = storage
+        .as_raw()
+        .references_glob(&format!("{}*", remotes))?
+        .filter_map(|r| {
+            let r = r.ok()?;
+            let peer = r.name()?.strip_prefix(&remotes)?.split('/').next()?;
+            peer.parse::<PeerId>().ok()
+        })
+        .filter(|peer| !keep.contains(peer))
+        .collect::<BTreeSet<_>>();
+
+    for peer in &peers {
+        tracing::debug!(peer = %peer, "expiring remote");
+        delete_refs(storage, &format!("{}{}/*", remotes, peer))?;
+    }
+    if !peers.is_empty() {
+        refs::Refs::update(storage, urn)?;
+    }
+
+    Ok(peers)
+}
+
+/// Options for [`pack`].
+#[derive(Clone, Debug)]
+pub struct Pack {
+    /// Only prune unreachable objects older than this, in any format accepted
+    /// by `git gc --prune`.
+    pub prune: String,
+    /// Pass `--aggressive` to `git gc`.
+    pub aggressive: bool,
+}
+
+impl Default for Pack {
+    fn default() -> Self {
+        Self {
+            prune: "2.weeks.ago".to_owned(),
+            aggressive: false,
+        }
+    }
+}
+
+/// Run `git gc` on the monorepo.
+///
+/// Blocks until all fetches holding a [`fetch_lock`] have finished, and
+/// prevents new ones from starting while running.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn pack<S>(storage: &S, opts: Pack) -> Result<(), Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    let _lock = LOCK.write();
+
+    let mut cmd = Command::new("git");
+    cmd.current_dir(storage.path())
+        .args(&["gc", "--quiet"])
+        .arg(format!("--prune={}", opts.prune))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    if opts.aggressive {
+        cmd.arg("--aggressive");
+    }
+    let out = cmd.output()?;
+    if !out.status.success() {
+        return Err(Error::Gc {
+            status: out.status,
+            stderr: String::from_utf8_lossy(&out.stderr).into_owned(),
+        });
+    }
+
+    Ok(())
+}
+
+fn delegates<S>(storage: &S, urn: &Urn) -> Result<BTreeSet<PeerId>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let delegates = match identities::any::get(storage, urn)? {
+        Some(SomeIdentity::Person(person)) => person
+            .delegations()
+            .into_iter()
+            .copied()
+            .map(PeerId::from)
+            .collect(),
+        Some(SomeIdentity::Project(project)) => project
+            .delegations()
+            .into_iter()
+            .flat_map(|d| match d {
+                Left(pk) => vec![PeerId::from(*pk)],
+                Right(indirect) => indirect
+                    .delegations()
+                    .into_iter()
+                    .copied()
+                    .map(PeerId::from)
+                    .collect(),
+            })
+            .collect(),
+        _ => BTreeSet::new(),
+    };
+
+    Ok(delegates)
+}
+
+fn delete_refs(storage: &Storage, glob: &str) -> Result<usize, Error> {
+    // Don't delete while iterating the refdb
+    let refs = storage
+        .as_raw()
+        .references_glob(glob)?
+        .collect::<Result<Vec<_>, _>>()?;
+    let n = refs.len();
+    for mut r in refs {
+        r.delete()?;
+    }
+
+    Ok(n)
+}
@@ -0,0 +1,241 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Versioned migrations of the storage layout.
+//!
+//! The schema version of the monorepo is recorded in its git config under
+//! [`CONFIG_KEY`]. A monorepo without a recorded version predates this module,
+//! and is considered to be at version `0`. [`migrate`] applies all
+//! [`MIGRATIONS`] newer than the recorded version, in order, and records each
+//! version after it was applied successfully.
+//!
+//! Migrations must be idempotent: a migration may be interrupted (eg. by a
+//! crash) after it was partially applied, in which case it runs again the next
+//! time. A migration may also report that it is [`Outcome::Incomplete`], eg.
+//! because some entries could not be converted. The version is not recorded
+//! then, and the migration is retried on the next run, while subsequent
+//! migrations are skipped.
+//!
+//! Recording the version does not prevent data in the old format from being
+//! written afterwards, eg. by an older version of this library sharing the
+//! storage. A migration can thus provide a [`Migration::recheck`], which
+//! [`migrate`] runs on every invocation to detect such data, and applies the
+//! migration again if it finds any.
+//!
+//! Storage which was migrated to a version newer than [`SCHEMA_VERSION`] can
+//! not be opened, as it may contain data this version does not understand.
+
+use std::{collections::BTreeSet, convert::TryFrom as _, str::FromStr as _};
+
+use git_ext::is_not_found_err;
+use thiserror::Error;
+
+use super::{ReadOnlyStorage as _, Storage};
+use crate::{identities::git::Urn, PeerId};
+
+/// The git config key holding the schema version.
+pub const CONFIG_KEY: &str = "rad.schema";
+
+/// The schema version this version of the code understands.
+pub const SCHEMA_VERSION: u32 = 1;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(
+        "storage schema version {found} is newer than the supported version {supported}, \
+         please upgrade"
+    )]
+    TooNew { found: u32, supported: u32 },
+
+    #[error("invalid schema version {0}")]
+    Invalid(i64),
+
+    #[error("migration to version {version} failed")]
+    Migration {
+        version: u32,
+        #[source]
+        source: BoxError,
+    },
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// The result of running a single [`Migration`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Done,
+    /// The migration should be retried on the next run.
+    Incomplete,
+}
+
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub run: fn(&Storage) -> Result<Outcome, BoxError>,
+    /// Whether there is data the migration applies to, even though the
+    /// recorded version is not older than [`Migration::version`].
+    ///
+    /// This should be cheap, as it is evaluated every time [`migrate`] runs.
+    pub recheck: Option<fn(&Storage) -> Result<bool, BoxError>>,
+}
+
+/// All migrations, ordered by version.
+pub static MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    description: "convert v1 tracking entries to v2",
+    run: tracking_v2,
+    recheck: Some(has_tracking_v1),
+}];
+
+/// The migrations [`migrate`] ran.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// The version before migrating.
+    pub from: u32,
+    /// The version after migrating.
+    pub to: u32,
+    /// The versions of migrations which were [`Outcome::Incomplete`].
+    pub incomplete: BTreeSet<u32>,
+    /// The versions of already applied migrations which were run again, as
+    /// their [`Migration::recheck`] found data to migrate.
+    pub rerun: BTreeSet<u32>,
+}
+
+/// Read the schema version of `repo`.
+pub fn version(repo: &git2::Repository) -> Result<u32, Error> {
+    match repo.config()?.get_i64(CONFIG_KEY) {
+        Ok(v) => u32::try_from(v).map_err(|_| Error::Invalid(v)),
+        Err(e) if is_not_found_err(&e) => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Ensure that the schema version of `repo` is not newer than
+/// [`SCHEMA_VERSION`].
+pub fn check(repo: &git2::Repository) -> Result<u32, Error> {
+    let found = version(repo)?;
+    if found > SCHEMA_VERSION {
+        return Err(Error::TooNew {
+            found,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    Ok(found)
+}
+
+/// Record the schema version of `repo`, without running any migrations.
+///
+/// Used when initialising a new monorepo, which is always at the latest
+/// version.
+pub(super) fn set_version(repo: &git2::Repository, version: u32) -> Result<(), Error> {
+    repo.config()?.set_i64(CONFIG_KEY, i64::from(version))?;
+    Ok(())
+}
+
+/// Run all pending migrations.
+#[tracing::instrument(level = "debug", skip(storage))]
+pub fn migrate(storage: &Storage) -> Result<Report, Error> {
+    let repo = storage.as_raw();
+    let from = check(repo)?;
+    let mut report = Report {
+        from,
+        to: from,
+        incomplete: BTreeSet::new(),
+        rerun: BTreeSet::new(),
+    };
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version <= from) {
+        let recheck = match migration.recheck {
+            None => continue,
+            Some(recheck) => recheck,
+        };
+        let error = |source| Error::Migration {
+            version: migration.version,
+            source,
+        };
+        if !recheck(storage).map_err(error)? {
+            continue;
+        }
+        tracing::info!(
+            version = migration.version,
+            description = migration.description,
+            "re-running storage migration"
+        );
+        report.rerun.insert(migration.version);
+        if (migration.run)(storage).map_err(error)? == Outcome::Incomplete {
+            tracing::warn!(
+                version = migration.version,
+                "storage migration incomplete, will retry"
+            );
+            report.incomplete.insert(migration.version);
+        }
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > from) {
+        tracing::info!(
+            version = migration.version,
+            description = migration.description,
+            "running storage migration"
+        );
+        let outcome = (migration.run)(storage).map_err(|source| Error::Migration {
+            version: migration.version,
+            source,
+        })?;
+        match outcome {
+            Outcome::Done => {
+                set_version(repo, migration.version)?;
+                report.to = migration.version;
+            },
+            Outcome::Incomplete => {
+                tracing::warn!(
+                    version = migration.version,
+                    "storage migration incomplete, will retry"
+                );
+                report.incomplete.insert(migration.version);
+                break;
+            },
+        }
+    }
+
+    Ok(report)
+}
+
+/// Convert v1 tracking entries into v2 tracking entries.
+fn tracking_v2(storage: &Storage) -> Result<Outcome, BoxError> {
+    let urns = tracking_v1(storage)?
+        .into_iter()
+        .map(|(urn, _)| urn)
+        .collect::<BTreeSet<_>>();
+
+    let results = crate::git::tracking::migration::migrate(storage, urns)?;
+    if results.failures.is_empty() {
+        return Ok(Outcome::Done);
+    }
+    for (err, urn, peer) in results.failures {
+        tracing::warn!(urn = %urn, peer = %peer, reason = %err, "failed to migrate");
+    }
+    Ok(Outcome::Incomplete)
+}
+
+fn has_tracking_v1(storage: &Storage) -> Result<bool, BoxError> {
+    Ok(!tracking_v1(storage)?.is_empty())
+}
+
+/// The v1 tracking entries, ie. remotes named `<urn id>/<peer>`.
+fn tracking_v1(storage: &Storage) -> Result<Vec<(Urn, PeerId)>, BoxError> {
+    Ok(storage
+        .remotes()?
+        .iter()
+        .flatten()
+        .filter_map(|name| {
+            let (id, peer) = name.split_once('/')?;
+            Some((Urn::try_from_id(id).ok()?, PeerId::from_str(peer).ok()?))
+        })
+        .collect())
+}
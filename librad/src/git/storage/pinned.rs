@@ -0,0 +1,177 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Read access to a namespace as it was attested by a past `rad/signed_refs`
+//! commit.
+//!
+//! A [`Pinned`] view resolves references using the oids recorded in the
+//! signed refs blob at a given commit, instead of the current state of the
+//! ref database. This allows to reproduce (or audit) a state which was
+//! published by a peer at some point in time, regardless of whether the
+//! references have moved since.
+
+use std::{fmt::Debug, path::Path};
+
+use git_ext::{self as ext, is_not_found_err, reference::OneLevel};
+use std_ext::result::ResultExt as _;
+use thiserror::Error;
+
+use crate::{
+    git::{
+        refs::{self, Refs},
+        types::{Namespace, Reference, RefsCategory},
+    },
+    identities::git::Urn,
+    PeerId,
+};
+
+use super::{ReadOnly, ReadOnlyStorage as _};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("{at} is not in the history of {sigrefs}")]
+    NotInHistory { at: ext::Oid, sigrefs: ext::RefLike },
+
+    #[error("no signed refs found at {0}")]
+    Missing(ext::Oid),
+
+    #[error(transparent)]
+    Refs(#[from] refs::stored::Error),
+
+    #[error(transparent)]
+    Store(#[from] super::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// A read-only view of a namespace, pinned to a `rad/signed_refs` commit.
+///
+/// Constructed via [`ReadOnly::at_sigref`] or [`super::Storage::at_sigref`].
+pub struct Pinned<'a> {
+    storage: &'a ReadOnly,
+    urn: Urn,
+    peer: Option<PeerId>,
+    at: ext::Oid,
+    refs: Refs,
+}
+
+impl<'a> Pinned<'a> {
+    pub(super) fn new(
+        storage: &'a ReadOnly,
+        urn: &Urn,
+        peer: Option<PeerId>,
+        at: ext::Oid,
+    ) -> Result<Self, Error> {
+        let sigrefs = Reference::rad_signed_refs(Namespace::from(urn), peer);
+        let tip = storage.reference_oid(&sigrefs).map(Some).or_matches(
+            |e| matches!(e, super::Error::Git(e) if is_not_found_err(e)),
+            || Ok::<_, super::Error>(None),
+        )?;
+        let in_history = match tip {
+            None => false,
+            Some(tip) => tip == at || storage.backend.graph_descendant_of(tip.into(), at.into())?,
+        };
+        if !in_history {
+            return Err(Error::NotInHistory {
+                at,
+                sigrefs: ext::RefLike::from(&sigrefs),
+            });
+        }
+
+        let refs = refs::load_at(storage, at, peer.as_ref())?
+            .map(|refs::Loaded { refs, .. }| refs)
+            .ok_or(Error::Missing(at))?;
+
+        Ok(Self {
+            storage,
+            urn: urn.clone(),
+            peer,
+            at,
+            refs,
+        })
+    }
+
+    pub fn urn(&self) -> &Urn {
+        &self.urn
+    }
+
+    /// The remote peer whose signed refs this view is pinned to, or `None` if
+    /// it is the local peer.
+    pub fn peer(&self) -> Option<&PeerId> {
+        self.peer.as_ref()
+    }
+
+    /// The `rad/signed_refs` commit this view is pinned to.
+    pub fn at(&self) -> ext::Oid {
+        self.at
+    }
+
+    /// The (verified) [`Refs`] recorded at [`Pinned::at`].
+    pub fn refs(&self) -> &Refs {
+        &self.refs
+    }
+
+    /// Resolve `name` within `category` to the oid recorded in the signed
+    /// refs.
+    pub fn reference_oid(&self, category: &RefsCategory, name: &OneLevel) -> Option<ext::Oid> {
+        self.refs
+            .categorised_refs
+            .get(&category.to_string())
+            .and_then(|refs| refs.get(name.as_str()))
+            .copied()
+    }
+
+    /// Resolve the branch `name` (ie. `refs/heads/<name>`).
+    pub fn head(&self, name: &OneLevel) -> Option<ext::Oid> {
+        self.reference_oid(&RefsCategory::Heads, name)
+    }
+
+    /// Resolve the tag `name` (ie. `refs/tags/<name>`).
+    pub fn tag(&self, name: &OneLevel) -> Option<ext::Oid> {
+        self.reference_oid(&RefsCategory::Tags, name)
+    }
+
+    /// Find the object `name` within `category` pointed to at the time.
+    ///
+    /// Returns `None` if either the reference was not recorded, or the object
+    /// is not (or no longer) present in the storage.
+    pub fn find_object(
+        &self,
+        category: &RefsCategory,
+        name: &OneLevel,
+    ) -> Result<Option<git2::Object<'a>>, Error> {
+        match self.reference_oid(category, name) {
+            None => Ok(None),
+            Some(oid) => Ok(self.storage.find_object(oid)?),
+        }
+    }
+
+    /// Read the blob at `path` from the tree of the branch `name`, as it was
+    /// at the time.
+    pub fn blob(&self, name: &OneLevel, path: &'a Path) -> Result<Option<git2::Blob<'a>>, Error> {
+        match self.head(name) {
+            None => Ok(None),
+            Some(oid) => Ok(self.storage.blob_at(oid, path)?),
+        }
+    }
+}
+
+impl ReadOnly {
+    /// Obtain a [`Pinned`] view of `urn`, resolving references according to
+    /// the signed refs recorded at the `rad/signed_refs` commit `at`.
+    ///
+    /// If `peer` is `None`, the signed refs of the local peer are consulted.
+    /// `at` must be in the history of the respective `rad/signed_refs`, and
+    /// the signature must verify against `peer`.
+    #[tracing::instrument(level = "debug", skip(self, urn), fields(urn = %urn))]
+    pub fn at_sigref<P>(&self, urn: &Urn, peer: P, at: ext::Oid) -> Result<Pinned, Error>
+    where
+        P: Into<Option<PeerId>> + Debug,
+    {
+        Pinned::new(self, urn, peer.into(), at)
+    }
+}
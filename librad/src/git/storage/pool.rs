@@ -3,14 +3,26 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+//! A pool of [`Storage`] or [`ReadOnly`] instances.
+//!
+//! Checkouts are served in FIFO order: once the pool is exhausted, tasks
+//! queue up, and a returned storage is handed to the task which has been
+//! waiting the longest. A checkout may be given a timeout, after which the
+//! task gives up its place in the queue. Latency-sensitive paths can use
+//! [`Pool::try_checkout`] instead, which never waits. Gossip handling, for
+//! example, answers most queries via a [`super::Reader`] without a checkout,
+//! and only resorts to [`Pool::try_checkout`] if the reader fails.
+
 use std::{
+    collections::VecDeque,
     marker::PhantomData,
     ops::{Deref, DerefMut},
     sync::Arc,
+    time::{Duration, Instant},
 };
 
-use deadpool::managed::{self, Manager, Object, RecycleResult};
-use parking_lot::RwLock;
+use futures::channel::oneshot;
+use parking_lot::{Mutex, RwLock};
 use std_ext::Void;
 use thiserror::Error;
 
@@ -25,10 +37,35 @@ pub enum InitError {
 
     #[error(transparent)]
     Write(#[from] error::Init),
+
+    #[cfg(feature = "fault-injection")]
+    #[error(transparent)]
+    Fault(#[from] super::faults::Fault),
 }
 
-pub type Pool<S> = deadpool::managed::Pool<S, InitError>;
-pub type PoolError = managed::PoolError<InitError>;
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum PoolError {
+    #[error("timed out after {waited:?} waiting for a storage from the pool")]
+    Timeout { waited: Duration },
+
+    #[error(transparent)]
+    Backend(#[from] InitError),
+}
+
+/// Creates and recycles the storages of a [`Pool`].
+#[async_trait]
+pub trait Manager<S>: Send + Sync {
+    /// Open a new storage.
+    async fn create(&self) -> Result<S, InitError>;
+
+    /// Prepare an idle storage for reuse. If this fails, the storage is
+    /// discarded, and a new one is created instead.
+    async fn recycle(&self, storage: &mut S) -> Result<(), InitError>;
+
+    /// The [`Paths`] the storages are opened on.
+    fn paths(&self) -> &Paths;
+}
 
 #[async_trait]
 pub trait Pooled<S: Send> {
@@ -36,29 +73,364 @@ pub trait Pooled<S: Send> {
 }
 
 #[async_trait]
-impl<S: Send> Pooled<S> for Pool<S> {
+impl<S: Send + 'static> Pooled<S> for Pool<S> {
     async fn get(&self) -> Result<PooledRef<S>, PoolError> {
-        self.get().await.map(PooledRef::from)
+        self.get().await
     }
 }
 
-/// A reference to a pooled storage.
+/// A snapshot of the utilisation of a [`Pool`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Status {
+    /// The maximum number of storages the pool holds.
+    pub max_size: usize,
+    /// The number of storages currently held, whether idle or checked out.
+    pub size: usize,
+    /// The number of idle storages.
+    pub idle: usize,
+    /// The number of tasks waiting for a storage.
+    pub waiting: usize,
+    /// The number of checkouts which timed out so far.
+    pub timeouts: u64,
+}
+
+/// A pool of storages, see the [module documentation](self).
+///
+/// Cloning yields a handle to the same pool.
+pub struct Pool<S> {
+    inner: Arc<Shared<S>>,
+}
+
+impl<S> Clone for Pool<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+struct Shared<S> {
+    manager: Box<dyn Manager<S>>,
+    max_size: usize,
+    timeout: Option<Duration>,
+    state: Mutex<State<S>>,
+}
+
+struct State<S> {
+    idle: VecDeque<S>,
+    /// Storages held, including the ones checked out and the ones being
+    /// created.
+    size: usize,
+    waiters: VecDeque<Waiter<S>>,
+    next_waiter: u64,
+    timeouts: u64,
+}
+
+struct Waiter<S> {
+    id: u64,
+    tx: oneshot::Sender<Grant<S>>,
+}
+
+/// The right to use one unit of a pool's capacity.
+enum Slot<S> {
+    /// An idle storage to be reused.
+    Idle(S),
+    /// Capacity to create a new storage.
+    Vacant,
+}
+
+/// A [`Slot`] which is released back to the pool when dropped.
+struct Grant<S> {
+    pool: Arc<Shared<S>>,
+    slot: Option<Slot<S>>,
+}
+
+impl<S> Drop for Grant<S> {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot.take() {
+            self.pool.release(slot)
+        }
+    }
+}
+
+impl<S> Shared<S> {
+    /// Hand `slot` to the longest waiting task, if any. Otherwise, keep the
+    /// storage around as idle, or give up the capacity.
+    fn release(self: &Arc<Self>, mut slot: Slot<S>) {
+        loop {
+            let waiter = {
+                let mut state = self.state.lock();
+                match state.waiters.pop_front() {
+                    Some(waiter) => waiter,
+                    None => {
+                        match slot {
+                            Slot::Idle(storage) => state.idle.push_back(storage),
+                            Slot::Vacant => state.size -= 1,
+                        }
+                        return;
+                    },
+                }
+            };
+            // Not holding the lock: if the waiter is dropped concurrently, the
+            // grant may be dropped (and thus released) right here.
+            let grant = Grant {
+                pool: Arc::clone(self),
+                slot: Some(slot),
+            };
+            match waiter.tx.send(grant) {
+                Ok(()) => return,
+                // The waiter is gone, try the next one
+                Err(mut grant) => slot = grant.slot.take().unwrap(),
+            }
+        }
+    }
+
+    /// Take a [`Slot`] if one is available without waiting. Tasks which are
+    /// already waiting take precedence.
+    fn try_acquire(self: &Arc<Self>, state: &mut State<S>) -> Option<Grant<S>> {
+        if !state.waiters.is_empty() {
+            return None;
+        }
+        let slot = match state.idle.pop_front() {
+            Some(storage) => Slot::Idle(storage),
+            None if state.size < self.max_size => {
+                state.size += 1;
+                Slot::Vacant
+            },
+            None => return None,
+        };
+        Some(Grant {
+            pool: Arc::clone(self),
+            slot: Some(slot),
+        })
+    }
+}
+
+impl<S: Send + 'static> Pool<S> {
+    /// Create a pool of at most `max_size` storages, whose checkouts via
+    /// [`Pool::get`] wait indefinitely.
+    pub fn new<M>(manager: M, max_size: usize) -> Self
+    where
+        M: Manager<S> + 'static,
+    {
+        Self::with_timeout(manager, max_size, None)
+    }
+
+    /// Create a pool of at most `max_size` storages, whose checkouts via
+    /// [`Pool::get`] time out after `timeout`.
+    pub fn with_timeout<M>(
+        manager: M,
+        max_size: usize,
+        timeout: impl Into<Option<Duration>>,
+    ) -> Self
+    where
+        M: Manager<S> + 'static,
+    {
+        Self {
+            inner: Arc::new(Shared {
+                manager: Box::new(manager),
+                max_size,
+                timeout: timeout.into(),
+                state: Mutex::new(State {
+                    idle: VecDeque::with_capacity(max_size),
+                    size: 0,
+                    waiters: VecDeque::new(),
+                    next_waiter: 0,
+                    timeouts: 0,
+                }),
+            }),
+        }
+    }
+
+    /// Check out a storage, waiting at most for the pool's default timeout.
+    pub async fn get(&self) -> Result<PooledRef<S>, PoolError> {
+        self.checkout(self.inner.timeout).await
+    }
+
+    /// Check out a storage, waiting at most for `timeout`, or indefinitely if
+    /// `None`.
+    ///
+    /// If the pool is exhausted, the caller is queued behind the ones already
+    /// waiting.
+    pub async fn checkout(
+        &self,
+        timeout: impl Into<Option<Duration>>,
+    ) -> Result<PooledRef<S>, PoolError> {
+        let started = Instant::now();
+        let acquired = {
+            let mut state = self.inner.state.lock();
+            match self.inner.try_acquire(&mut state) {
+                Some(grant) => Ok(grant),
+                None => {
+                    let id = state.next_waiter;
+                    state.next_waiter += 1;
+                    let (tx, rx) = oneshot::channel();
+                    state.waiters.push_back(Waiter { id, tx });
+                    Err(Queued {
+                        pool: Arc::clone(&self.inner),
+                        id,
+                        rx,
+                    })
+                },
+            }
+        };
+        let grant = match acquired {
+            Ok(grant) => grant,
+            Err(queued) => queued.wait(timeout.into(), started).await?,
+        };
+        self.open(grant, started).await
+    }
+
+    /// Check out a storage only if one is available right away, ie. the pool
+    /// is not exhausted and nobody is waiting.
+    pub async fn try_checkout(&self) -> Result<Option<PooledRef<S>>, PoolError> {
+        let started = Instant::now();
+        let grant = {
+            let mut state = self.inner.state.lock();
+            self.inner.try_acquire(&mut state)
+        };
+        match grant {
+            None => Ok(None),
+            Some(grant) => self.open(grant, started).await.map(Some),
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        let state = self.inner.state.lock();
+        Status {
+            max_size: self.inner.max_size,
+            size: state.size,
+            idle: state.idle.len(),
+            waiting: state.waiters.len(),
+            timeouts: state.timeouts,
+        }
+    }
+
+    /// Turn `grant` into a usable storage, recycling the idle one it holds or
+    /// creating a new one.
+    async fn open(&self, mut grant: Grant<S>, started: Instant) -> Result<PooledRef<S>, PoolError> {
+        let manager = &self.inner.manager;
+
+        #[cfg(feature = "fault-injection")]
+        if let Some(faults) = super::faults::lookup(manager.paths().git_dir()) {
+            faults
+                .inject_async(super::faults::Op::Checkout)
+                .await
+                .map_err(InitError::from)?;
+        }
+
+        // Should we be cancelled while recycling, the capacity is given back
+        let storage = match grant.slot.replace(Slot::Vacant) {
+            Some(Slot::Idle(mut storage)) => match manager.recycle(&mut storage).await {
+                Ok(()) => Some(storage),
+                Err(e) => {
+                    tracing::warn!(err = %e, "failed to recycle storage, creating a new one");
+                    None
+                },
+            },
+            _ => None,
+        };
+        let storage = match storage {
+            Some(storage) => storage,
+            None => manager.create().await?,
+        };
+        grant.slot = None;
+
+        Ok(PooledRef {
+            storage: Some(storage),
+            pool: Arc::clone(&self.inner),
+            waited: started.elapsed(),
+        })
+    }
+}
+
+/// A place in the queue of a [`Pool`], which is given up when dropped.
+struct Queued<S> {
+    pool: Arc<Shared<S>>,
+    id: u64,
+    rx: oneshot::Receiver<Grant<S>>,
+}
+
+impl<S> Queued<S> {
+    async fn wait(
+        mut self,
+        timeout: Option<Duration>,
+        started: Instant,
+    ) -> Result<Grant<S>, PoolError> {
+        let received = match timeout {
+            None => Ok((&mut self.rx).await),
+            Some(after) => link_async::timeout(after, &mut self.rx).await,
+        };
+        if let Ok(Ok(grant)) = received {
+            return Ok(grant);
+        }
+
+        self.dequeue();
+        // We may have been served just before leaving the queue
+        if let Ok(Some(grant)) = self.rx.try_recv() {
+            return Ok(grant);
+        }
+        self.pool.state.lock().timeouts += 1;
+        Err(PoolError::Timeout {
+            waited: started.elapsed(),
+        })
+    }
+
+    fn dequeue(&self) {
+        let id = self.id;
+        self.pool
+            .state
+            .lock()
+            .waiters
+            .retain(|waiter| waiter.id != id)
+    }
+}
+
+impl<S> Drop for Queued<S> {
+    fn drop(&mut self) {
+        self.dequeue()
+    }
+}
+
+/// A reference to a pooled storage, which is returned to the pool when
+/// dropped.
 ///
 /// The `S` parameter can be filled by [`Storage`] for read-write access or
 /// [`ReadOnly`] for read-only access.
-pub struct PooledRef<S>(Object<S, InitError>);
+pub struct PooledRef<S> {
+    // Only `None` after `drop`
+    storage: Option<S>,
+    pool: Arc<Shared<S>>,
+    waited: Duration,
+}
+
+impl<S> PooledRef<S> {
+    /// The time it took to check out this storage, including the time spent
+    /// waiting in the queue.
+    pub fn waited(&self) -> Duration {
+        self.waited
+    }
+}
+
+impl<S> Drop for PooledRef<S> {
+    fn drop(&mut self) {
+        if let Some(storage) = self.storage.take() {
+            self.pool.release(Slot::Idle(storage))
+        }
+    }
+}
 
 impl<S> Deref for PooledRef<S> {
     type Target = S;
 
     fn deref(&self) -> &Self::Target {
-        self.0.deref()
+        self.storage.as_ref().unwrap()
     }
 }
 
 impl<S> DerefMut for PooledRef<S> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        self.0.deref_mut()
+        self.storage.as_mut().unwrap()
     }
 }
 
@@ -76,13 +448,7 @@ impl<S> AsMut<S> for PooledRef<S> {
 
 impl AsRef<ReadOnly> for PooledRef<Storage> {
     fn as_ref(&self) -> &ReadOnly {
-        self.0.read_only()
-    }
-}
-
-impl<S> From<Object<S, InitError>> for PooledRef<S> {
-    fn from(obj: Object<S, InitError>) -> Self {
-        Self(obj)
+        self.deref().read_only()
     }
 }
 
@@ -126,14 +492,18 @@ impl ReadConfig {
 }
 
 #[async_trait]
-impl Manager<ReadOnly, InitError> for ReadConfig {
+impl Manager<ReadOnly> for ReadConfig {
     async fn create(&self) -> Result<ReadOnly, InitError> {
         ReadOnly::open(&self.paths).map_err(InitError::from)
     }
 
-    async fn recycle(&self, _: &mut ReadOnly) -> RecycleResult<InitError> {
+    async fn recycle(&self, _: &mut ReadOnly) -> Result<(), InitError> {
         Ok(())
     }
+
+    fn paths(&self) -> &Paths {
+        &self.paths
+    }
 }
 
 impl<S> ReadWriteConfig<S> {
@@ -154,7 +524,7 @@ impl<S> ReadWriteConfig<S> {
 }
 
 #[async_trait]
-impl<S> Manager<Storage, InitError> for ReadWriteConfig<S>
+impl<S> Manager<Storage> for ReadWriteConfig<S>
 where
     S: Signer + Clone,
     S::Error: std::error::Error + Send + Sync + 'static,
@@ -175,7 +545,14 @@ where
         }
     }
 
-    async fn recycle(&self, _: &mut Storage) -> RecycleResult<InitError> {
+    async fn recycle(&self, storage: &mut Storage) -> Result<(), InitError> {
+        // Only drained by borrowers which announce writes, don't let the
+        // touched URNs pile up in idle storages.
+        storage.take_touched();
         Ok(())
     }
+
+    fn paths(&self) -> &Paths {
+        &self.paths
+    }
 }
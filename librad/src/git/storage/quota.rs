@@ -0,0 +1,219 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Disk usage accounting and quotas.
+//!
+//! All namespaces share the object database of the monorepo, so there is no
+//! precise notion of how much space a single namespace occupies. Instead, the
+//! size of every packfile received when fetching a namespace is [`record`]ed
+//! against it, which is an upper bound of the space it is responsible for.
+//! The global usage is the size of the object database on disk.
+//!
+//! A [`Quota`] caps either. It is enforced when fetching, by limiting the size
+//! of the packfile the remote end may send to what is [`remaining`] -- the
+//! transfer is aborted if it would exceed the limit.
+//!
+//! The per-namespace ledger is kept in the git config of the monorepo. Use
+//! [`reset`] after removing a namespace, eg. using
+//! [`super::gc::remove_namespace`]. Updates to the ledger are serialised
+//! within the process, so concurrent fetches don't lose each other's counts.
+//!
+//! Measuring the object database means walking the `objects` directory, so the
+//! result is cached for [`GLOBAL_TTL`], and bytes [`record`]ed in the meantime
+//! are added to it.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom as _,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use thiserror::Error;
+
+use super::{ReadOnly, Storage};
+use crate::git::Urn;
+
+const SECTION: &str = "link-usage";
+
+/// How long a measurement of the size of the object database is reused by
+/// [`global_usage`].
+pub const GLOBAL_TTL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    /// Serialises updates of the ledger.
+    static ref LEDGER: Mutex<()> = Mutex::new(());
+    /// Size of the object database per `objects` directory, and when it was
+    /// measured. Expired entries are evicted on access.
+    static ref GLOBAL: Mutex<HashMap<PathBuf, (Instant, u64)>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Size limits, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// Maximum number of bytes to receive for a single namespace.
+    pub namespace: Option<u64>,
+    /// Maximum size of the object database.
+    pub global: Option<u64>,
+}
+
+/// Disk usage report, in bytes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Bytes received per namespace.
+    ///
+    /// Namespaces which were never fetched into are absent.
+    pub namespaces: BTreeMap<Urn, u64>,
+    /// Size of the object database.
+    pub global: u64,
+}
+
+/// Report the current disk usage.
+pub fn usage<S>(storage: &S) -> Result<Usage, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let storage = storage.as_ref();
+    let mut namespaces = BTreeMap::new();
+    let config = storage.backend.config()?;
+    let pattern = format!("{}\\..*\\.bytes", SECTION);
+    let entries = config.entries(Some(&pattern))?;
+    for entry in &entries {
+        let entry = entry?;
+        let urn = entry
+            .name()
+            .and_then(|name| name.strip_prefix(SECTION))
+            .and_then(|name| name.strip_prefix('.'))
+            .and_then(|name| name.strip_suffix(".bytes"))
+            .and_then(|id| Urn::try_from_id(id).ok());
+        match urn {
+            Some(urn) => {
+                namespaces.insert(urn, u64::try_from(entry.value_i64()).unwrap_or(0));
+            },
+            None => tracing::warn!(name = ?entry.name(), "invalid usage entry"),
+        }
+    }
+
+    Ok(Usage {
+        namespaces,
+        global: global_usage(storage)?,
+    })
+}
+
+/// The number of bytes received for `urn`.
+pub fn namespace_usage<S>(storage: &S, urn: &Urn) -> Result<u64, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let config = storage.as_ref().backend.config()?;
+    match config.get_i64(&key(urn)) {
+        Ok(bytes) => Ok(u64::try_from(bytes).unwrap_or(0)),
+        Err(e) if git_ext::is_not_found_err(&e) => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The size of the object database.
+///
+/// Note that measuring it walks the `objects` directory, and is thus
+/// proportional to the number of loose objects. The result is cached for
+/// [`GLOBAL_TTL`].
+pub fn global_usage<S>(storage: &S) -> Result<u64, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let objects = storage.as_ref().path().join("objects");
+    let mut global = GLOBAL.lock();
+    global.retain(|_, (at, _)| at.elapsed() < GLOBAL_TTL);
+    if let Some((_, size)) = global.get(&objects) {
+        return Ok(*size);
+    }
+    let size = dir_size(&objects)?;
+    global.insert(objects, (Instant::now(), size));
+
+    Ok(size)
+}
+
+/// The number of bytes which may still be received for `urn` under `quota`.
+///
+/// `None` means unlimited.
+pub fn remaining<S>(storage: &S, urn: &Urn, quota: Quota) -> Result<Option<u64>, Error>
+where
+    S: AsRef<ReadOnly>,
+{
+    let namespace = quota
+        .namespace
+        .map(|max| Ok::<_, Error>(max.saturating_sub(namespace_usage(storage, urn)?)))
+        .transpose()?;
+    let global = quota
+        .global
+        .map(|max| Ok::<_, Error>(max.saturating_sub(global_usage(storage)?)))
+        .transpose()?;
+
+    Ok(match (namespace, global) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    })
+}
+
+/// Record that `bytes` were received for `urn`.
+pub fn record(storage: &Storage, urn: &Urn, bytes: u64) -> Result<(), Error> {
+    if bytes == 0 {
+        return Ok(());
+    }
+    {
+        let _ledger = LEDGER.lock();
+        let total = namespace_usage(storage, urn)?.saturating_add(bytes);
+        let mut config = storage.as_raw().config()?;
+        config.set_i64(&key(urn), i64::try_from(total).unwrap_or(i64::MAX))?;
+    }
+    if let Some((_, size)) = GLOBAL.lock().get_mut(&storage.path().join("objects")) {
+        *size = size.saturating_add(bytes);
+    }
+
+    Ok(())
+}
+
+/// Forget the usage recorded for `urn`.
+pub fn reset(storage: &Storage, urn: &Urn) -> Result<(), Error> {
+    let _ledger = LEDGER.lock();
+    let mut config = storage.as_raw().config()?;
+    match config.remove(&key(urn)) {
+        Err(e) if !git_ext::is_not_found_err(&e) => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn key(urn: &Urn) -> String {
+    format!("{}.{}.bytes", SECTION, urn.encode_id())
+}
+
+fn dir_size(dir: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += meta.len();
+        }
+    }
+
+    Ok(size)
+}
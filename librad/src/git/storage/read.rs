@@ -22,6 +22,8 @@ use crate::{
 use super::{
     config::{self, Config},
     glob::{self, Pattern},
+    migrations,
+    reader::{self, Reader},
 };
 
 #[derive(Debug, Error)]
@@ -40,7 +42,7 @@ pub enum Error {
 pub mod error {
     use thiserror::Error;
 
-    use super::config;
+    use super::{config, migrations};
 
     #[derive(Debug, Error)]
     #[non_exhaustive]
@@ -50,6 +52,9 @@ pub mod error {
 
         #[error(transparent)]
         Git(#[from] git2::Error),
+
+        #[error(transparent)]
+        Migration(#[from] migrations::Error),
     }
 }
 
@@ -157,6 +162,11 @@ pub trait ReadOnlyStorage {
 pub struct ReadOnly {
     pub(super) backend: git2::Repository,
     pub(super) peer_id: PeerId,
+    /// Answers the hot queries if available, cf. [`super::reader`].
+    pub(super) reader: Option<Reader>,
+    /// Whether this is the [`ReadOnly`] part of a read-write
+    /// [`super::Storage`], ie. may write to the repository.
+    pub(super) writable: bool,
 }
 
 impl ReadOnly {
@@ -175,8 +185,14 @@ impl ReadOnly {
     pub fn open(paths: &Paths) -> Result<Self, error::Init> {
         crate::git::init();
         let backend = git2::Repository::open(paths.git_dir())?;
+        migrations::check(&backend)?;
         let peer_id = Config::try_from(&backend)?.peer_id()?;
-        Ok(Self { backend, peer_id })
+        Ok(Self {
+            backend,
+            peer_id,
+            reader: open_reader(paths),
+            writable: false,
+        })
     }
 
     pub fn peer_id(&self) -> &PeerId {
@@ -211,11 +227,67 @@ impl ReadOnly {
         Ok(Config::try_from(&self.backend)?)
     }
 
+    /// Access the identities in the repository.
+    ///
+    /// The verification cache is only used if this is [`super::Storage`], as
+    /// it writes to the repository.
     pub fn identities<'a, T: 'a>(&'a self) -> Identities<'a, T> {
-        Identities::from(&self.backend)
+        let identities = Identities::from(&self.backend);
+        if self.writable {
+            identities.with_verification_cache()
+        } else {
+            identities
+        }
+    }
+
+    /// Read the blob at `path` in the tree of the commit `at`.
+    ///
+    /// Like [`ReadOnlyStorage::blob_at`], but tries the [`Reader`] first, so
+    /// the blob is owned.
+    pub fn read_blob_at(&self, at: ext::Oid, path: &Path) -> Result<Option<reader::Blob>, Error> {
+        if let Some(blob) = self.try_reader(|reader| reader.blob_at(at, path)) {
+            return Ok(blob);
+        }
+        Ok(self.blob_at(at, path)?.map(|blob| reader::Blob {
+            id: blob.id().into(),
+            content: blob.content().to_vec(),
+        }))
+    }
+
+    /// Read the content of the blob `oid`, trying the [`Reader`] first.
+    ///
+    /// `None` if `oid` doesn't exist or is not a blob.
+    pub fn read_blob(&self, oid: ext::Oid) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(blob) = self.try_reader(|reader| reader.blob(oid)) {
+            return Ok(blob);
+        }
+        Ok(self
+            .find_object(oid)?
+            .and_then(|obj| obj.into_blob().ok())
+            .map(|blob| blob.content().to_vec()))
+    }
+
+    /// Answer a query using the [`Reader`], if there is one.
+    ///
+    /// `None` if the query should be answered by libgit2 instead.
+    fn try_reader<F, T>(&self, f: F) -> Option<T>
+    where
+        F: FnOnce(&Reader) -> Result<T, super::reader::error::Read>,
+    {
+        let reader = self.reader.as_ref()?;
+        f(reader)
+            .map_err(|e| tracing::debug!(err = %e, "falling back to libgit2"))
+            .ok()
     }
 }
 
+/// Open the [`Reader`] for `paths`, or `None` if that fails.
+pub(super) fn open_reader(paths: &Paths) -> Option<Reader> {
+    Reader::open(paths)
+        .map_err(|e| tracing::warn!(err = %e, "failed to open reader, using libgit2 only"))
+        .ok()
+}
+
 impl ReadOnlyStorage for ReadOnly {
     #[tracing::instrument(level = "debug", skip(self))]
     fn has_urn(&self, urn: &Urn) -> Result<bool, Error> {
@@ -228,8 +300,12 @@ impl ReadOnlyStorage for ReadOnly {
         RefLike: From<&'b Ref>,
         Ref: Debug,
     {
+        let name = RefLike::from(reference);
+        if let Some(has) = self.try_reader(|reader| reader.has_ref(&name)) {
+            return Ok(has);
+        }
         self.backend
-            .find_reference(RefLike::from(reference).as_str())
+            .find_reference(name.as_str())
             .and(Ok(true))
             .or_matches(is_not_found_err, || Ok(false))
     }
@@ -239,6 +315,11 @@ impl ReadOnlyStorage for ReadOnly {
     where
         Oid: AsRef<git2::Oid> + Debug,
     {
+        let id = ext::Oid::from(*oid.as_ref());
+        if let Some(has) = self.try_reader(|reader| reader.has_commit(urn, id)) {
+            return Ok(has);
+        }
+
         let (oid, kind) = match self.find_object(oid)? {
             None => return Ok(false),
             Some(object) => match object.kind() {
@@ -263,6 +344,11 @@ impl ReadOnlyStorage for ReadOnly {
     where
         Oid: AsRef<git2::Oid> + Debug,
     {
+        let id = ext::Oid::from(*oid.as_ref());
+        if let Some(has) = self.try_reader(|reader| reader.has_tag(urn, id)) {
+            return Ok(has);
+        }
+
         let (oid, kind) = match self.find_object(oid)? {
             None => return Ok(false),
             Some(object) => match object.kind() {
@@ -286,6 +372,12 @@ impl ReadOnlyStorage for ReadOnly {
             tracing::warn!("zero oid");
             return Ok(false);
         }
+        // Only trust hits, so libgit2 gets to double check misses
+        if let Some(reader) = &self.reader {
+            if reader.has_object((*oid).into()) {
+                return Ok(true);
+            }
+        }
 
         Ok(self.backend.odb()?.exists(*oid))
     }
@@ -387,10 +479,20 @@ impl ReadOnlyStorage for ReadOnly {
     where
         RefLike: From<&'b Ref>,
     {
-        self.backend
-            .refname_to_id(&RefLike::from(reference))
-            .map(ext::Oid::from)
-            .map_err(Error::from)
+        let name = RefLike::from(reference);
+        match self.try_reader(|reader| reader.reference_oid(&name)) {
+            Some(Some(oid)) => Ok(oid),
+            Some(None) => Err(Error::Git(git2::Error::new(
+                git2::ErrorCode::NotFound,
+                git2::ErrorClass::Reference,
+                format!("reference '{}' not found", name.as_str()),
+            ))),
+            None => self
+                .backend
+                .refname_to_id(&name)
+                .map(ext::Oid::from)
+                .map_err(Error::from),
+        }
     }
 
     #[tracing::instrument(level = "trace", skip(self))]
@@ -0,0 +1,344 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! A read-only backend for hot queries, which bypasses libgit2.
+//!
+//! A `git2::Repository` can't be shared between threads, which is why
+//! concurrent access to the storage goes through a [`super::Pool`]. Some
+//! queries are however frequent and cheap enough for the checkout to dominate
+//! their cost: whether a [`Urn`] is known, whether it has a commit, where its
+//! `rad/signed_refs` point to, or reading the blobs of the signed refs. A
+//! [`Reader`] answers those using
+//! gitoxide's refdb and object database, which can be shared freely --
+//! lookups only take a lock when the `packed-refs` or the set of packfiles
+//! changed on disk.
+//!
+//! [`super::ReadOnly`] (and thus [`super::Storage`]) use a [`Reader`] for the
+//! corresponding [`super::ReadOnlyStorage`] methods if one could be opened,
+//! falling back to libgit2 if it fails. A [`Reader`] only answers queries it
+//! can answer with certainty: if an object is missing from its view of the
+//! object database, it returns an error rather than a negative answer, so the
+//! query is retried using libgit2. Readers are cached per storage
+//! location, so all storages opened on the same [`Paths`] share the in-memory
+//! indices. The cache only holds weak references: once the last handle to a
+//! [`Reader`] is dropped, its indices are released.
+
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fs,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Weak},
+};
+
+use bstr::BStr;
+use git_ext::{self as ext, RefLike};
+use link_git::{
+    hash::ObjectId,
+    object::{tree::EntryMode, Kind, TagRef},
+    odb::{self, index, window},
+    refdb::{self, Refdb},
+    refs::{file, Target},
+    traverse::commit::{ancestors, Ancestors},
+};
+use parking_lot::Mutex;
+
+use crate::{
+    git::types::{reference, Reference},
+    identities::git::Urn,
+    paths::Paths,
+};
+
+lazy_static! {
+    static ref READERS: Mutex<HashMap<PathBuf, Weak<Inner>>> = Mutex::new(HashMap::new());
+}
+
+/// Maximum number of tags to peel before giving up.
+const MAX_PEEL: usize = 5;
+
+pub mod error {
+    use thiserror::Error;
+
+    use super::*;
+
+    #[derive(Debug, Error)]
+    #[non_exhaustive]
+    pub enum Open {
+        #[error(transparent)]
+        Refdb(#[from] refdb::error::Open),
+
+        #[error(transparent)]
+        Odb(#[from] index::error::Discover),
+    }
+
+    #[derive(Debug, Error)]
+    #[non_exhaustive]
+    pub enum Read {
+        #[error("malformed URN")]
+        Ref(#[from] reference::FromUrnError),
+
+        #[error(transparent)]
+        Snapshot(#[from] refdb::error::Snapshot),
+
+        #[error(transparent)]
+        Find(#[from] file::find::Error),
+
+        #[error(transparent)]
+        Follow(#[from] refdb::error::Follow),
+
+        #[error(transparent)]
+        Odb(#[from] odb::Error),
+
+        #[error(transparent)]
+        Revwalk(#[from] ancestors::Error),
+
+        #[error("malformed tag {0}")]
+        Tag(ext::Oid),
+
+        #[error("object {0} not found")]
+        Missing(ext::Oid),
+
+        #[error("expected object {0} to be a {1}")]
+        Kind(ext::Oid, Kind),
+
+        #[error("unsupported path {}", .0.display())]
+        Path(PathBuf),
+    }
+}
+
+type Odb = odb::Odb<index::Shared<()>, window::Small<()>>;
+
+/// A blob read through a [`Reader`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Blob {
+    pub id: ext::Oid,
+    pub content: Vec<u8>,
+}
+
+/// A read-only view of the storage, see the [module documentation](self).
+///
+/// Cloning yields a handle to the same in-memory state.
+#[derive(Clone)]
+pub struct Reader {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    refdb: Refdb,
+    odb: Odb,
+}
+
+impl Reader {
+    /// Get the [`Reader`] for the storage at `paths`, opening it unless it
+    /// is already in use.
+    pub fn open(paths: &Paths) -> Result<Self, error::Open> {
+        let git_dir = paths.git_dir();
+        let key = fs::canonicalize(git_dir).unwrap_or_else(|_| git_dir.to_path_buf());
+        let mut readers = READERS.lock();
+        if let Some(inner) = readers.get(&key).and_then(Weak::upgrade) {
+            return Ok(Self { inner });
+        }
+        readers.retain(|_, inner| inner.strong_count() > 0);
+        let reader = Self::open_uncached(git_dir)?;
+        readers.insert(key, Arc::downgrade(&reader.inner));
+        Ok(reader)
+    }
+
+    fn open_uncached(git_dir: &Path) -> Result<Self, error::Open> {
+        let refdb = Refdb::open(git_dir)?;
+        let odb = {
+            let loose = odb::backend::Loose::at(git_dir.join("objects"));
+            let packed = odb::backend::Packed {
+                index: index::Shared::open(git_dir)?,
+                data: window::Fixed::default(),
+            };
+            Odb { loose, packed }
+        };
+
+        Ok(Self {
+            inner: Arc::new(Inner { refdb, odb }),
+        })
+    }
+
+    /// Whether the identity `urn` refers to exists, cf.
+    /// [`super::ReadOnlyStorage::has_urn`].
+    pub fn has_urn(&self, urn: &Urn) -> Result<bool, error::Read> {
+        self.has_ref(&RefLike::from(&Reference::try_from(urn)?))
+    }
+
+    /// Whether the reference `name` exists. Symbolic references are not
+    /// followed.
+    pub fn has_ref(&self, name: &RefLike) -> Result<bool, error::Read> {
+        Ok(self.inner.refdb.snapshot()?.find(as_bstr(name))?.is_some())
+    }
+
+    /// The object the reference `name` points to, after following symbolic
+    /// references.
+    pub fn reference_oid(&self, name: &RefLike) -> Result<Option<ext::Oid>, error::Read> {
+        let snapshot = self.inner.refdb.snapshot()?;
+        let reference = match snapshot.find(as_bstr(name))? {
+            None => return Ok(None),
+            Some(reference) => reference,
+        };
+        match snapshot.follow(&reference) {
+            Ok(reference) => Ok(Some(reference.target.into_id().into())),
+            // Dangling symref
+            Err(refdb::error::Follow::NotFound(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn has_object(&self, oid: ext::Oid) -> bool {
+        self.inner.odb.contains(ObjectId::from(oid))
+    }
+
+    /// Whether `oid` is a commit in the history of the tip of `urn`, cf.
+    /// [`super::ReadOnlyStorage::has_commit`].
+    pub fn has_commit(&self, urn: &Urn, oid: ext::Oid) -> Result<bool, error::Read> {
+        let oid = ObjectId::from(oid);
+        if self.kind(oid)? != Kind::Commit {
+            return Ok(false);
+        }
+        let tip = match self.tip(urn, Kind::Commit)? {
+            None => return Ok(false),
+            Some(tip) => tip,
+        };
+        if tip == oid {
+            return Ok(true);
+        }
+
+        let odb = &self.inner.odb;
+        let mut cache = odb::cache::lru::StaticLinkedList::<64>::default();
+        let walk = Ancestors::new(Some(tip), ancestors::State::default(), move |oid, buf| {
+            let obj = odb.find(oid, buf, &mut cache).ok().flatten()?;
+            obj.try_into_commit_iter()
+        });
+        for parent in walk {
+            if parent? == oid {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Whether `oid` is the tag the tip of `urn` points to, cf.
+    /// [`super::ReadOnlyStorage::has_tag`].
+    pub fn has_tag(&self, urn: &Urn, oid: ext::Oid) -> Result<bool, error::Read> {
+        let oid = ObjectId::from(oid);
+        if self.kind(oid)? != Kind::Tag {
+            return Ok(false);
+        }
+        Ok(self.tip(urn, Kind::Tag)? == Some(oid))
+    }
+
+    /// The content of the blob `oid`, or `None` if `oid` is not a blob.
+    pub fn blob(&self, oid: ext::Oid) -> Result<Option<Vec<u8>>, error::Read> {
+        let mut buf = Vec::new();
+        let obj = self.find(oid.into(), &mut buf)?;
+        Ok((obj.kind == Kind::Blob).then(|| obj.data.to_vec()))
+    }
+
+    /// The blob at `path` in the tree of the commit `at`, cf.
+    /// [`super::ReadOnlyStorage::blob_at`].
+    ///
+    /// `None` if there is no entry at `path`.
+    pub fn blob_at(&self, at: ext::Oid, path: &Path) -> Result<Option<Blob>, error::Read> {
+        let mut buf = Vec::new();
+        let mut oid = {
+            let commit = self.find(at.into(), &mut buf)?;
+            commit
+                .try_into_commit_iter()
+                .and_then(|mut commit| commit.tree_id())
+                .ok_or(error::Read::Kind(at, Kind::Commit))?
+        };
+        let mut mode = EntryMode::Tree;
+        for component in path.components() {
+            let name = match component {
+                Component::Normal(name) => name.to_str(),
+                _ => None,
+            }
+            .ok_or_else(|| error::Read::Path(path.to_path_buf()))?;
+            if mode != EntryMode::Tree {
+                return Ok(None);
+            }
+            let tree = self
+                .find(oid, &mut buf)?
+                .try_into_tree_iter()
+                .ok_or_else(|| error::Read::Kind(oid.into(), Kind::Tree))?;
+            let mut found = None;
+            for entry in tree {
+                let entry = entry.map_err(|_| error::Read::Kind(oid.into(), Kind::Tree))?;
+                if entry.filename == name.as_bytes() {
+                    found = Some((entry.oid.to_owned(), entry.mode));
+                    break;
+                }
+            }
+            match found {
+                None => return Ok(None),
+                Some((next, next_mode)) => {
+                    oid = next;
+                    mode = next_mode;
+                },
+            }
+        }
+
+        let obj = self.find(oid, &mut buf)?;
+        if obj.kind != Kind::Blob {
+            return Err(error::Read::Kind(oid.into(), Kind::Blob));
+        }
+        Ok(Some(Blob {
+            id: oid.into(),
+            content: obj.data.to_vec(),
+        }))
+    }
+
+    /// Find the object `oid`, which is expected to exist.
+    fn find<'a>(
+        &self,
+        oid: ObjectId,
+        buf: &'a mut Vec<u8>,
+    ) -> Result<odb::Object<'a>, error::Read> {
+        self.inner
+            .odb
+            .find(oid, buf, &mut odb::cache::Never)?
+            .ok_or_else(|| error::Read::Missing(oid.into()))
+    }
+
+    fn kind(&self, oid: ObjectId) -> Result<Kind, error::Read> {
+        let mut buf = Vec::new();
+        Ok(self.find(oid, &mut buf)?.kind)
+    }
+
+    /// The tip of `urn`, peeled to `kind`.
+    ///
+    /// `None` if either the reference doesn't exist, or it can't be peeled to
+    /// `kind`.
+    fn tip(&self, urn: &Urn, kind: Kind) -> Result<Option<ObjectId>, error::Read> {
+        let name = RefLike::from(&Reference::try_from(urn)?);
+        let mut oid = match self.reference_oid(&name)? {
+            None => return Ok(None),
+            Some(oid) => ObjectId::from(oid),
+        };
+
+        let mut buf = Vec::new();
+        for _ in 0..MAX_PEEL {
+            let obj = self.find(oid, &mut buf)?;
+            if obj.kind == kind {
+                return Ok(Some(oid));
+            }
+            if obj.kind != Kind::Tag {
+                return Ok(None);
+            }
+            oid = TagRef::from_bytes(obj.data)
+                .map_err(|_| error::Read::Tag(oid.into()))?
+                .target();
+        }
+        Ok(None)
+    }
+}
+
+fn as_bstr(name: &RefLike) -> &BStr {
+    BStr::new(name.as_str())
+}
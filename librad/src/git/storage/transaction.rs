@@ -0,0 +1,330 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Atomic updates of several refs.
+//!
+//! A [`Transaction`] collects ref edits. [`Transaction::prepare`] locks all
+//! affected refs (using git's lockfile-based transaction mechanism) and checks
+//! the expected previous states, so either all edits are validated or none is
+//! applied. Dropping a [`Prepared`] transaction without committing it releases
+//! the locks, leaving the refs untouched.
+//!
+//! libgit2 commits by moving the lockfiles into place one ref after the other.
+//! To keep [`Prepared::commit`] all-or-nothing, the previous state of every
+//! edited ref is recorded in a journal beforehand. If committing fails
+//! half-way, the edits which were applied are rolled back before the error is
+//! returned. If the process dies half-way, they are rolled back the next time a
+//! [`Storage`] is opened on the same monorepo. Concurrent readers may still
+//! observe some of the edits before others while a commit is in progress.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use git_ext::{self as ext, is_not_found_err};
+use thiserror::Error;
+
+use super::Storage;
+
+mod journal;
+pub(super) use journal::recover;
+use journal::{Entry, Journal, State};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("{name}: expected {expected}, found {actual:?}")]
+    Conflict {
+        name: String,
+        expected: Previous,
+        actual: Option<ext::Oid>,
+    },
+
+    #[error("symbolic ref {name} would point to non-existent {target}")]
+    Dangling { name: String, target: String },
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[cfg(feature = "fault-injection")]
+    #[error(transparent)]
+    Fault(#[from] super::faults::Fault),
+}
+
+/// The state a ref is expected to be in before it is edited.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Previous {
+    /// Don't care.
+    Any,
+    /// The ref must not exist.
+    Absent,
+    /// The ref must be a direct ref pointing to the given oid.
+    Is(ext::Oid),
+}
+
+impl std::fmt::Display for Previous {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Any => f.write_str("any ref"),
+            Self::Absent => f.write_str("no ref"),
+            Self::Is(oid) => write!(f, "{}", oid),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Edit {
+    Direct {
+        target: ext::Oid,
+        previous: Previous,
+        msg: String,
+    },
+    Symbolic {
+        target: String,
+        msg: String,
+    },
+    Delete {
+        previous: Previous,
+    },
+}
+
+/// A set of ref edits to validate and apply together, see the [module
+/// documentation](self).
+///
+/// Constructed via [`Storage::transaction`]. Ref names are fully qualified,
+/// ie. include the `refs/namespaces/<urn>/` prefix where applicable. Editing
+/// the same ref more than once overrides the previous edit.
+pub struct Transaction<'a> {
+    storage: &'a Storage,
+    edits: BTreeMap<String, Edit>,
+}
+
+impl<'a> Transaction<'a> {
+    pub(super) fn new(storage: &'a Storage) -> Self {
+        Self {
+            storage,
+            edits: BTreeMap::new(),
+        }
+    }
+
+    /// Point the direct ref `name` to `target`.
+    pub fn update(
+        &mut self,
+        name: impl ToString,
+        target: impl Into<ext::Oid>,
+        previous: Previous,
+        msg: &str,
+    ) -> &mut Self {
+        self.edits.insert(
+            name.to_string(),
+            Edit::Direct {
+                target: target.into(),
+                previous,
+                msg: msg.to_owned(),
+            },
+        );
+        self
+    }
+
+    /// Point the symbolic ref `name` to `target`.
+    ///
+    /// `target` must exist, or be created by this transaction.
+    pub fn symbolic(&mut self, name: impl ToString, target: impl ToString, msg: &str) -> &mut Self {
+        self.edits.insert(
+            name.to_string(),
+            Edit::Symbolic {
+                target: target.to_string(),
+                msg: msg.to_owned(),
+            },
+        );
+        self
+    }
+
+    /// Delete the ref `name`.
+    ///
+    /// Deleting a ref which doesn't exist is not an error, unless `previous`
+    /// says otherwise.
+    pub fn delete(&mut self, name: impl ToString, previous: Previous) -> &mut Self {
+        self.edits
+            .insert(name.to_string(), Edit::Delete { previous });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+
+    /// Lock all refs to be edited, and check that they are in the expected
+    /// state.
+    pub fn prepare(self) -> Result<Prepared<'a>, Error> {
+        let repo = self.storage.as_raw();
+        let mut tx = repo.transaction()?;
+        for name in self.edits.keys() {
+            tx.lock_ref(name)?;
+        }
+
+        let created = self
+            .edits
+            .iter()
+            .filter_map(|(name, edit)| match edit {
+                Edit::Direct { .. } | Edit::Symbolic { .. } => Some(name.as_str()),
+                Edit::Delete { .. } => None,
+            })
+            .collect::<BTreeSet<_>>();
+        let mut entries = Vec::with_capacity(self.edits.len());
+        for (name, edit) in &self.edits {
+            let old = State::read(repo, name)?;
+            let new = match edit {
+                Edit::Direct { target, .. } => State::Direct(*target),
+                Edit::Symbolic { target, .. } => State::Symbolic(target.clone()),
+                Edit::Delete { .. } => State::Absent,
+            };
+            if old != new {
+                entries.push(Entry {
+                    name: name.clone(),
+                    old,
+                    new,
+                });
+            }
+            match edit {
+                Edit::Direct {
+                    target,
+                    previous,
+                    msg,
+                } => {
+                    check(repo, name, *previous)?;
+                    if force_reflog(name) {
+                        repo.reference_ensure_log(name)?;
+                    }
+                    tx.set_target(name, **target, None, msg)?;
+                },
+                Edit::Symbolic { target, msg } => {
+                    if !created.contains(target.as_str()) {
+                        repo.refname_to_id(target).map_err(|e| {
+                            if is_not_found_err(&e) {
+                                Error::Dangling {
+                                    name: name.clone(),
+                                    target: target.clone(),
+                                }
+                            } else {
+                                Error::Git(e)
+                            }
+                        })?;
+                    }
+                    if force_reflog(name) {
+                        repo.reference_ensure_log(name)?;
+                    }
+                    tx.set_symbolic_target(name, target, None, msg)?;
+                },
+                Edit::Delete { previous } => {
+                    if check(repo, name, *previous)? {
+                        tx.remove(name)?;
+                    }
+                },
+            }
+        }
+
+        Ok(Prepared {
+            tx,
+            names: self.edits.into_keys().collect(),
+            entries,
+            storage: self.storage,
+        })
+    }
+
+    /// Shorthand for [`Transaction::prepare`] followed by
+    /// [`Prepared::commit`].
+    pub fn commit(self) -> Result<Vec<String>, Error> {
+        self.prepare()?.commit()
+    }
+}
+
+/// A [`Transaction`] holding the locks on all refs it edits.
+pub struct Prepared<'a> {
+    tx: git2::Transaction<'a>,
+    names: Vec<String>,
+    /// The edits which change a ref.
+    entries: Vec<Entry>,
+    storage: &'a Storage,
+}
+
+impl<'a> Prepared<'a> {
+    /// Apply all edits, returning the names of the edited refs.
+    ///
+    /// If an error is returned, none of the edits is applied.
+    pub fn commit(self) -> Result<Vec<String>, Error> {
+        #[cfg(feature = "fault-injection")]
+        self.storage.inject(super::faults::Op::RefUpdate)?;
+        if self.entries.is_empty() {
+            self.tx.commit()?;
+            return Ok(self.names);
+        }
+
+        let repo = self.storage.as_raw();
+        let journal = Journal::write(self.storage.path(), &self.entries)?;
+        if let Err(e) = self.tx.commit() {
+            journal::rollback(repo, &self.entries)?;
+            journal.remove()?;
+            return Err(e.into());
+        }
+        #[cfg(feature = "fault-injection")]
+        self.storage.inject(super::faults::Op::JournalRemove)?;
+        if let Err(e) = journal.remove() {
+            journal::rollback(repo, &self.entries)?;
+            return Err(e.into());
+        }
+        tracing::debug!(refs = ?self.names, "committed ref transaction");
+
+        Ok(self.names)
+    }
+
+    /// Release the locks without applying any edits.
+    ///
+    /// Equivalent to dropping `self`.
+    pub fn abort(self) {
+        tracing::debug!(refs = ?self.names, "aborted ref transaction");
+    }
+}
+
+/// Whether to create a reflog for `name` even if `core.logAllRefUpdates`
+/// wouldn't.
+///
+/// This is the case for `refs/rad/*` and `refs/remotes/<peer>/rad/*`, possibly
+/// within a namespace, whose history is useful for debugging replication.
+fn force_reflog(name: &str) -> bool {
+    let name = name
+        .strip_prefix("refs/namespaces/")
+        .and_then(|ns| ns.split_once('/'))
+        .map_or(name, |(_, name)| name);
+    match name.strip_prefix("refs/") {
+        Some(name) if name.starts_with("rad/") => true,
+        Some(name) => name
+            .strip_prefix("remotes/")
+            .and_then(|name| name.split_once('/'))
+            .map_or(false, |(_, name)| name.starts_with("rad/")),
+        None => false,
+    }
+}
+
+/// Check the (locked) ref `name` against `previous`, returning whether it
+/// exists.
+fn check(repo: &git2::Repository, name: &str, previous: Previous) -> Result<bool, Error> {
+    let (exists, actual) = match repo.find_reference(name) {
+        Ok(r) => (true, r.target().map(ext::Oid::from)),
+        Err(e) if is_not_found_err(&e) => (false, None),
+        Err(e) => return Err(e.into()),
+    };
+    match previous {
+        Previous::Any => Ok(exists),
+        Previous::Absent if !exists => Ok(false),
+        Previous::Is(expected) if actual == Some(expected) => Ok(true),
+        expected => Err(Error::Conflict {
+            name: name.to_owned(),
+            expected,
+            actual,
+        }),
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Undo journal of the [`super::Prepared`] transactions being committed.
+//!
+//! Journals live in the `link-transactions` directory of the monorepo, one
+//! file per transaction. A journal is written, and locked using `flock(2)`,
+//! before the first lockfile is moved into place, and removed once all of them
+//! are. A journal which exists but is not locked thus belongs to a process
+//! which died while committing, and is rolled back by [`recover`].
+//!
+//! On platforms without `flock(2)`, abandoned journals can not be told apart
+//! from live ones, and are left alone.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+
+use git_ext::{self as ext, is_not_found_err};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::Error;
+
+const DIR: &str = "link-transactions";
+const EXT: &str = "journal";
+const TMP_PREFIX: &str = ".tmp";
+
+/// The state of a ref.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum State {
+    Absent,
+    Direct(ext::Oid),
+    Symbolic(String),
+}
+
+impl State {
+    pub(super) fn read(repo: &git2::Repository, name: &str) -> Result<Self, git2::Error> {
+        match repo.find_reference(name) {
+            Ok(r) => Ok(match r.symbolic_target() {
+                Some(target) => Self::Symbolic(target.to_owned()),
+                None => r
+                    .target()
+                    .map_or(Self::Absent, |oid| Self::Direct(oid.into())),
+            }),
+            Err(e) if is_not_found_err(&e) => Ok(Self::Absent),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// The state of the ref `name` before and after the transaction.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(super) struct Entry {
+    pub name: String,
+    pub old: State,
+    pub new: State,
+}
+
+/// A journal on disk, locked for as long as it is alive.
+pub(super) struct Journal {
+    path: PathBuf,
+    _file: File,
+}
+
+impl Journal {
+    /// Durably write a journal of `entries` in the monorepo at `git_dir`.
+    pub(super) fn write(git_dir: &Path, entries: &[Entry]) -> io::Result<Self> {
+        let dir = git_dir.join(DIR);
+        fs::create_dir_all(&dir)?;
+
+        // Lock before the journal becomes visible under its final name, so
+        // `recover` never mistakes it for an abandoned one
+        let mut tmp = tempfile::Builder::new()
+            .prefix(TMP_PREFIX)
+            .tempfile_in(&dir)?;
+        lock(tmp.as_file())?;
+        serde_json::to_writer(&mut tmp, entries)?;
+        tmp.as_file().sync_all()?;
+        let path = dir.join(format!("{}.{}", Uuid::new_v4(), EXT));
+        let file = tmp.persist(&path).map_err(|e| e.error)?;
+        sync_dir(&dir)?;
+
+        Ok(Self { path, _file: file })
+    }
+
+    /// Remove the journal, marking the transaction as complete.
+    pub(super) fn remove(&self) -> io::Result<()> {
+        fs::remove_file(&self.path)
+    }
+}
+
+/// Undo the edits of `entries` which were applied.
+///
+/// Refs which are not in the state the transaction left them in are left
+/// alone, as they were edited since. Thus rolling back is idempotent.
+pub(super) fn rollback(repo: &git2::Repository, entries: &[Entry]) -> Result<(), git2::Error> {
+    let mut applied = Vec::new();
+    for entry in entries.iter().filter(|entry| entry.old != entry.new) {
+        if State::read(repo, &entry.name)? == entry.new {
+            applied.push(entry);
+        }
+    }
+    if applied.is_empty() {
+        return Ok(());
+    }
+
+    let mut tx = repo.transaction()?;
+    for entry in &applied {
+        tx.lock_ref(&entry.name)?;
+    }
+    for entry in applied {
+        // Re-check now that the ref is locked
+        if State::read(repo, &entry.name)? != entry.new {
+            continue;
+        }
+        match &entry.old {
+            State::Absent => tx.remove(&entry.name)?,
+            State::Direct(oid) => tx.set_target(&entry.name, **oid, None, "rollback")?,
+            State::Symbolic(target) => {
+                tx.set_symbolic_target(&entry.name, target, None, "rollback")?
+            },
+        }
+    }
+    tx.commit()?;
+    tracing::debug!(
+        refs = ?entries.iter().map(|e| &e.name).collect::<Vec<_>>(),
+        "rolled back ref transaction"
+    );
+
+    Ok(())
+}
+
+/// Roll back the transactions of the monorepo `repo` whose process died while
+/// committing them.
+pub(in crate::git::storage) fn recover(repo: &git2::Repository) -> Result<(), Error> {
+    let dir = repo.path().join(DIR);
+    let journals = match fs::read_dir(&dir) {
+        Ok(journals) => journals,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for journal in journals {
+        let path = journal?.path();
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            // Completed concurrently
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if !try_lock(&file)? || is_removed(&file)? {
+            continue;
+        }
+
+        let is_tmp = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| name.starts_with(TMP_PREFIX));
+        if !is_tmp && path.extension().and_then(|ext| ext.to_str()) == Some(EXT) {
+            let entries: Vec<Entry> =
+                serde_json::from_reader(BufReader::new(&file)).map_err(io::Error::from)?;
+            tracing::warn!(journal = %path.display(), "rolling back interrupted ref transaction");
+            rollback(repo, &entries)?;
+        }
+        fs::remove_file(&path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn lock(file: &File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd as _;
+
+    match unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(unix))]
+fn lock(_: &File) -> io::Result<()> {
+    Ok(())
+}
+
+/// Try to lock `file`, returning `false` if it is locked by someone else.
+#[cfg(unix)]
+fn try_lock(file: &File) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd as _;
+
+    match unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } {
+        0 => Ok(true),
+        _ => {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::WouldBlock {
+                Ok(false)
+            } else {
+                Err(e)
+            }
+        },
+    }
+}
+
+#[cfg(not(unix))]
+fn try_lock(_: &File) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Whether `file` was removed after it was opened, ie. its transaction
+/// completed in the meantime.
+#[cfg(unix)]
+fn is_removed(file: &File) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt as _;
+
+    Ok(file.metadata()?.nlink() == 0)
+}
+
+#[cfg(not(unix))]
+fn is_removed(_: &File) -> io::Result<bool> {
+    Ok(false)
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -5,6 +5,8 @@
 
 pub use crate::identities::git::Urn;
 
+pub mod auto;
+pub mod graph;
 mod odb;
 mod refdb;
 pub mod v1;
@@ -27,6 +29,7 @@ pub use link_tracking::{
             tracked,
             tracked_peers,
             untrack,
+            untrack_all,
             PreviousError,
             Ref,
             Tracked,
@@ -0,0 +1,305 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Policies for tracking URNs automatically, eg. on seed nodes.
+//!
+//! A [`Policy`] decides whether a URN announced by a peer (via gossip or
+//! request-pull) should be tracked. It consists of a `deny` and an `allow`
+//! list of [`Rules`], and a `default` [`Action`] for URNs matched by neither.
+//! A match on the `deny` list always wins.
+//!
+//! Rules on URNs and peers can be evaluated right away. Rules on the
+//! delegates or the name of an identity require the identity to be present
+//! in local storage. If it isn't, and the outcome depends on it, the
+//! [`Decision`] is [`Decision::Pending`]: the caller is expected to replicate
+//! the URN, and evaluate the policy again afterwards.
+//!
+//! Policies are read from [`Paths::tracking_policy_file`], for example:
+//!
+//! ```json
+//! {
+//!   "default": "ignore",
+//!   "allow": {
+//!     "delegates": ["rad:git:hnrkyghsrokxzxpy9pww69xr11dr9q7edbxfo"],
+//!     "names": ["radicle-*"]
+//!   },
+//!   "deny": {
+//!     "peers": ["hynkyndc6w3p8urucakobzna7sxwgcqny7xxtw88dtx3pkf7m3nrzc"]
+//!   }
+//! }
+//! ```
+
+use std::{collections::BTreeSet, fs, io, path::PathBuf};
+
+use either::Either;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::Urn;
+use crate::{
+    git::{
+        identities::{self, SomeIdentity},
+        storage,
+    },
+    paths::Paths,
+    PeerId,
+};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("failed to read `{path}`")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("malformed `{path}`")]
+    Malformed {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("invalid pattern `{pattern}`")]
+    Pattern {
+        pattern: String,
+        #[source]
+        source: globset::Error,
+    },
+
+    #[error(transparent)]
+    Identities(#[from] identities::Error),
+}
+
+/// What to do with a URN.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Action {
+    Track,
+    Ignore,
+}
+
+impl Default for Action {
+    fn default() -> Self {
+        Self::Ignore
+    }
+}
+
+/// An identity delegate, see [`Rules::delegates`].
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Delegate {
+    /// A person identity.
+    Person(Urn),
+    /// A key, ie. either a direct delegation, or a key of a person identity.
+    Key(PeerId),
+}
+
+/// A list of rules, matching if any of the rules matches.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rules {
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub urns: BTreeSet<Urn>,
+    /// The announcing peers.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub peers: BTreeSet<PeerId>,
+    /// Delegates of the identity.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub delegates: Vec<Delegate>,
+    /// Glob patterns matched against the name of the identity, ie. the
+    /// project or person name.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub names: Vec<String>,
+}
+
+impl Rules {
+    fn needs_identity(&self) -> bool {
+        !self.delegates.is_empty() || !self.names.is_empty()
+    }
+
+    fn matches_announcement(&self, peer: &PeerId, urn: &Urn) -> bool {
+        self.peers.contains(peer) || self.urns.contains(urn)
+    }
+
+    fn matches_identity(&self, identity: &SomeIdentity) -> Result<bool, Error> {
+        if !self.needs_identity() {
+            return Ok(false);
+        }
+
+        let (name, keys, persons) = match identity {
+            SomeIdentity::Project(project) => {
+                let mut keys = BTreeSet::new();
+                let mut persons = BTreeSet::new();
+                for delegate in project.delegations() {
+                    match delegate {
+                        Either::Left(key) => {
+                            keys.insert(PeerId::from(*key));
+                        },
+                        Either::Right(person) => {
+                            persons.insert(person.urn());
+                            keys.extend(person.delegations().iter().copied().map(PeerId::from));
+                        },
+                    }
+                }
+                (project.subject().name.as_str(), keys, persons)
+            },
+            SomeIdentity::Person(person) => (
+                person.subject().name.as_str(),
+                person
+                    .delegations()
+                    .iter()
+                    .copied()
+                    .map(PeerId::from)
+                    .collect(),
+                BTreeSet::new(),
+            ),
+            _ => return Ok(false),
+        };
+
+        let delegated = self.delegates.iter().any(|delegate| match delegate {
+            Delegate::Person(urn) => persons.contains(&urn.clone().with_path(None)),
+            Delegate::Key(peer) => keys.contains(peer),
+        });
+
+        Ok(delegated || self.names()?.is_match(name))
+    }
+
+    fn names(&self) -> Result<GlobSet, Error> {
+        let pattern_error = |source| Error::Pattern {
+            pattern: self.names.join(", "),
+            source,
+        };
+        let mut builder = GlobSetBuilder::new();
+        for name in &self.names {
+            builder.add(Glob::new(name).map_err(pattern_error)?);
+        }
+        builder.build().map_err(pattern_error)
+    }
+}
+
+/// The outcome of evaluating a [`Policy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Track,
+    Ignore,
+    /// The outcome depends on the identity, which is not (yet) available.
+    Pending,
+}
+
+impl From<Action> for Decision {
+    fn from(action: Action) -> Self {
+        match action {
+            Action::Track => Self::Track,
+            Action::Ignore => Self::Ignore,
+        }
+    }
+}
+
+/// An auto-track policy, see the [module documentation](self).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Policy {
+    /// The [`Action`] for URNs matched by neither `allow` nor `deny`.
+    #[serde(default)]
+    pub default: Action,
+    #[serde(default)]
+    pub allow: Rules,
+    #[serde(default)]
+    pub deny: Rules,
+}
+
+impl Policy {
+    /// A policy tracking everything.
+    pub fn everything() -> Self {
+        Self {
+            default: Action::Track,
+            ..Self::default()
+        }
+    }
+
+    /// Whether [`Policy::evaluate`] may need the identity of the announced
+    /// URN.
+    pub fn needs_identity(&self) -> bool {
+        self.allow.needs_identity() || self.deny.needs_identity()
+    }
+
+    /// Decide whether to track `urn` as announced by `peer`.
+    ///
+    /// `identity` is the identity `urn` resolves to, if it is available.
+    pub fn evaluate(
+        &self,
+        peer: &PeerId,
+        urn: &Urn,
+        identity: Option<&SomeIdentity>,
+    ) -> Result<Decision, Error> {
+        let urn = urn.clone().with_path(None);
+        if self.deny.matches_announcement(peer, &urn) {
+            return Ok(Decision::Ignore);
+        }
+
+        match identity {
+            Some(identity) => {
+                if self.deny.matches_identity(identity)? {
+                    Ok(Decision::Ignore)
+                } else if self.allow.matches_announcement(peer, &urn)
+                    || self.allow.matches_identity(identity)?
+                {
+                    Ok(Decision::Track)
+                } else {
+                    Ok(self.default.into())
+                }
+            },
+            None => {
+                if self.deny.needs_identity() {
+                    Ok(Decision::Pending)
+                } else if self.allow.matches_announcement(peer, &urn) {
+                    Ok(Decision::Track)
+                } else if self.allow.needs_identity() && self.default == Action::Ignore {
+                    Ok(Decision::Pending)
+                } else {
+                    Ok(self.default.into())
+                }
+            },
+        }
+    }
+
+    /// Like [`Policy::evaluate`], but reads the identity from `storage` if
+    /// needed.
+    pub fn evaluate_in<S>(&self, storage: &S, peer: &PeerId, urn: &Urn) -> Result<Decision, Error>
+    where
+        S: AsRef<storage::ReadOnly>,
+    {
+        let identity = if self.needs_identity() {
+            identities::any::get(storage, &urn.clone().with_path(None))?
+        } else {
+            None
+        };
+        self.evaluate(peer, urn, identity.as_ref())
+    }
+}
+
+/// Load the [`Policy`] configured in [`Paths::tracking_policy_file`].
+///
+/// If the file does not exist, `None` is returned.
+pub fn load(paths: &Paths) -> Result<Option<Policy>, Error> {
+    let path = paths.tracking_policy_file();
+    match fs::read(path) {
+        Ok(json) => serde_json::from_slice(&json)
+            .map(Some)
+            .map_err(|source| Error::Malformed {
+                path: path.to_path_buf(),
+                source,
+            }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(Error::Read {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
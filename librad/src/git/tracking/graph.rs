@@ -0,0 +1,223 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! The tracking graph as seen from the local peer.
+//!
+//! For every tracked URN, the local peer tracks a set of peers (degree `1`).
+//! Those peers, in turn, publish whom they track for the URN as part of their
+//! signed refs, which are replicated along with their data. Following these
+//! edges yields peers of degree `2`, and so on. A [`Graph`] is a snapshot of
+//! these relationships up to some maximum degree, which can be queried for
+//! eg. the peers [likely to provide](Graph::providers) a URN, or the
+//! [contributors](Graph::contributors) to a project.
+//!
+//! Note that only signed refs present in local storage are consulted, so the
+//! graph reflects the state as of the last replication.
+
+use std::{
+    cmp::Reverse,
+    collections::{btree_map::Entry, BTreeMap, BTreeSet},
+};
+
+use thiserror::Error;
+
+use super::{error, tracked, Urn};
+use crate::{
+    git::{refs::Refs, storage},
+    PeerId,
+};
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Tracked(#[from] error::Tracked),
+}
+
+/// A peer in the tracking graph of a URN.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Node {
+    /// The length of the shortest tracking path from the local peer.
+    pub degree: usize,
+    /// The peers of degree `degree - 1` tracking this peer.
+    ///
+    /// Empty for peers tracked by the local peer.
+    pub via: BTreeSet<PeerId>,
+    /// Whether the signed refs of this peer are present in local storage, ie.
+    /// the peer is known to have published data for the URN.
+    pub published: bool,
+}
+
+/// A peer returned by a [`Graph`] query, in order of relevance.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ranked {
+    pub peer: PeerId,
+    /// See [`Node::degree`].
+    pub degree: usize,
+    /// The number of distinct tracking paths of length `degree` leading to
+    /// the peer.
+    pub paths: usize,
+    /// See [`Node::published`].
+    pub published: bool,
+}
+
+impl Ranked {
+    fn new(peer: PeerId, node: &Node) -> Self {
+        Self {
+            peer,
+            degree: node.degree,
+            paths: node.via.len().max(1),
+            published: node.published,
+        }
+    }
+}
+
+/// Snapshot of the tracking graph, see the [module documentation](self).
+#[derive(Clone, Debug, Default)]
+pub struct Graph {
+    urns: BTreeMap<Urn, BTreeMap<PeerId, Node>>,
+}
+
+impl Graph {
+    /// Walk the tracking graph of all tracked URNs, up to peers of degree
+    /// `max_degree`.
+    #[tracing::instrument(level = "debug", skip(storage))]
+    pub fn load<S>(storage: &S, max_degree: usize) -> Result<Self, Error>
+    where
+        S: AsRef<storage::ReadOnly>,
+    {
+        let storage = storage.as_ref();
+        let local_id = *storage.peer_id();
+        let mut urns = BTreeMap::<Urn, BTreeMap<PeerId, Node>>::new();
+        if max_degree == 0 {
+            return Ok(Self { urns });
+        }
+
+        for entry in tracked(storage, None)? {
+            let entry = entry?;
+            let peers = urns.entry(entry.urn().clone()).or_default();
+            if let Some(peer) = entry.peer_id().filter(|peer| peer != &local_id) {
+                peers.entry(peer).or_insert_with(|| Node {
+                    degree: 1,
+                    ..Node::default()
+                });
+            }
+        }
+
+        for (urn, peers) in &mut urns {
+            let mut frontier = peers.keys().copied().collect::<Vec<_>>();
+            for degree in 1..=max_degree {
+                let mut next = Vec::new();
+                for peer in frontier {
+                    let refs = match Refs::load(storage, urn, peer) {
+                        Ok(Some(refs)) => refs,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            tracing::warn!(%urn, %peer, err = %e, "skipping invalid signed refs");
+                            continue;
+                        },
+                    };
+                    if let Some(node) = peers.get_mut(&peer) {
+                        node.published = true;
+                    }
+                    if degree == max_degree {
+                        continue;
+                    }
+                    for tracked in refs.remotes.keys().filter(|p| *p != &local_id) {
+                        match peers.entry(*tracked) {
+                            Entry::Vacant(entry) => {
+                                entry.insert(Node {
+                                    degree: degree + 1,
+                                    via: BTreeSet::from([peer]),
+                                    published: false,
+                                });
+                                next.push(*tracked);
+                            },
+                            Entry::Occupied(mut entry) => {
+                                let node = entry.get_mut();
+                                if node.degree == degree + 1 {
+                                    node.via.insert(peer);
+                                }
+                            },
+                        }
+                    }
+                }
+                frontier = next;
+            }
+        }
+
+        Ok(Self { urns })
+    }
+
+    /// The tracked URNs.
+    pub fn urns(&self) -> impl Iterator<Item = &Urn> + '_ {
+        self.urns.keys()
+    }
+
+    /// The peers in the tracking graph of `urn`.
+    pub fn nodes(&self, urn: &Urn) -> impl Iterator<Item = (&PeerId, &Node)> + '_ {
+        self.urns
+            .get(&urn.clone().with_path(None))
+            .into_iter()
+            .flatten()
+    }
+
+    /// The peers likely to be able to serve `urn`, most likely first.
+    ///
+    /// Peers known to have published data for `urn` rank before the ones
+    /// which are merely tracked, closer peers before more distant ones, and
+    /// peers reachable via more paths before less connected ones.
+    pub fn providers(&self, urn: &Urn) -> Vec<Ranked> {
+        rank(
+            self.nodes(urn)
+                .map(|(peer, node)| Ranked::new(*peer, node))
+                .collect(),
+        )
+    }
+
+    /// The peers up to `max_degree` which have published data for `urn`,
+    /// ranked as per [`Graph::providers`].
+    pub fn contributors(&self, urn: &Urn, max_degree: usize) -> Vec<Ranked> {
+        rank(
+            self.nodes(urn)
+                .filter(|(_, node)| node.published && node.degree <= max_degree)
+                .map(|(peer, node)| Ranked::new(*peer, node))
+                .collect(),
+        )
+    }
+
+    /// All peers in the graph, across URNs.
+    ///
+    /// The [`Ranked::degree`] of a peer is its minimum degree across URNs,
+    /// and [`Ranked::paths`] the sum of the paths of that length.
+    pub fn peers(&self) -> Vec<Ranked> {
+        let mut peers = BTreeMap::<PeerId, Ranked>::new();
+        for (peer, node) in self.urns.values().flatten() {
+            let ranked = Ranked::new(*peer, node);
+            match peers.entry(*peer) {
+                Entry::Vacant(entry) => {
+                    entry.insert(ranked);
+                },
+                Entry::Occupied(mut entry) => {
+                    let prev = entry.get_mut();
+                    prev.published |= ranked.published;
+                    if ranked.degree < prev.degree {
+                        prev.degree = ranked.degree;
+                        prev.paths = ranked.paths;
+                    } else if ranked.degree == prev.degree {
+                        prev.paths += ranked.paths;
+                    }
+                },
+            }
+        }
+
+        rank(peers.into_values().collect())
+    }
+}
+
+fn rank(mut peers: Vec<Ranked>) -> Vec<Ranked> {
+    peers.sort_by_key(|r| (Reverse(r.published), r.degree, Reverse(r.paths), r.peer));
+    peers
+}
@@ -304,6 +304,19 @@ impl<N, R> Reference<N, R, One> {
         }
     }
 
+    /// Build a reference that points to:
+    ///     * `refs/namespaces/<namespace>/refs/rad/attestations`
+    ///     * `refs/namespaces/<namespace>/refs/remote/<peer_id>/rad/
+    ///       attestations`
+    pub fn rad_attestations(namespace: impl Into<Option<N>>, remote: impl Into<Option<R>>) -> Self {
+        Self {
+            remote: remote.into(),
+            category: RefsCategory::Rad,
+            name: reflike!("attestations"),
+            namespace: namespace.into(),
+        }
+    }
+
     /// Build a reference that points to:
     ///     * `refs/namespaces/<namespace>/refs/heads/<name>`
     ///     * `refs/namespaces/<namespace>/refs/remote/<peer_id>/heads/<name>
@@ -21,13 +21,18 @@ pub extern crate radicle_data as data;
 pub extern crate radicle_git_ext as git_ext;
 pub extern crate radicle_std_ext as std_ext;
 
+pub mod backup;
+pub mod cache;
 pub mod collaborative_objects;
 pub mod git;
 pub mod internal;
+#[cfg(feature = "net")]
 pub mod net;
+pub mod notifications;
 pub mod paths;
 pub mod profile;
 pub mod rate_limit;
+pub mod report;
 
 // Re-exports
 pub use link_crypto::{keystore, PeerId, PublicKey, SecStr, SecretKey, Signature, Signer};
@@ -7,11 +7,16 @@ use std::{borrow::Cow, fmt::Display, str::FromStr};
 
 pub mod codec;
 pub mod connection;
+#[cfg(feature = "discovery")]
 pub mod discovery;
+pub mod happy_eyeballs;
+pub mod metrics;
 pub mod peer;
 pub mod protocol;
 pub mod quic;
 pub mod replication;
+pub mod resolve;
+pub mod tcp;
 pub mod tls;
 pub mod upgrade;
 pub mod x509;
@@ -10,9 +10,21 @@ use std::{
     io,
     iter::FromIterator,
     net::{SocketAddr, ToSocketAddrs},
+    time::Duration,
 };
 
-use crate::PeerId;
+use futures::stream::{BoxStream, StreamExt as _};
+
+use crate::{
+    net::resolve::{Host, Resolver},
+    PeerId,
+};
+
+pub mod dns;
+pub use dns::Dns;
+pub mod mdns;
+pub use mdns::Mdns;
+pub mod wire;
 
 pub trait Discovery {
     type Addr;
@@ -21,6 +33,24 @@ pub trait Discovery {
     fn discover(self) -> Self::Stream;
 }
 
+/// Discovery of the peers discovered by either of two [`Discovery`]s, in the
+/// order they are discovered.
+#[derive(Clone)]
+pub struct Merge<A, B>(pub A, pub B);
+
+impl<A, B> Discovery for Merge<A, B>
+where
+    A: Discovery,
+    B: Discovery<Addr = A::Addr>,
+{
+    type Addr = A::Addr;
+    type Stream = futures::stream::Select<A::Stream, B::Stream>;
+
+    fn discover(self) -> Self::Stream {
+        futures::stream::select(self.0.discover(), self.1.discover())
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct Static {
     peers: BTreeMap<PeerId, Vec<SocketAddr>>,
@@ -112,3 +142,85 @@ impl Discovery for Static {
         futures::stream::iter(self.peers.into_iter())
     }
 }
+
+/// Discovery of a fixed set of peers, whose addresses are given as [`Host`]s.
+///
+/// The hosts are resolved asynchronously using a [`Resolver`]. Unless
+/// disabled, the peers are discovered again every [`Resolving::refresh`]
+/// interval. As the protocol ignores discovered peers it is already connected
+/// to, this amounts to retrying the peers we failed to connect to -- which is
+/// why the hosts are re-resolved, bypassing the cache, in this case. Peers
+/// behind dynamic DNS thus remain reachable.
+#[derive(Clone)]
+pub struct Resolving {
+    peers: BTreeMap<PeerId, Vec<Host>>,
+    resolver: Resolver,
+    refresh: Option<Duration>,
+}
+
+impl Resolving {
+    pub const DEFAULT_REFRESH: Duration = Duration::from_secs(5 * 60);
+
+    pub fn new<I>(resolver: Resolver, peers: I) -> Self
+    where
+        I: IntoIterator<Item = (PeerId, Host)>,
+    {
+        let mut map = BTreeMap::<PeerId, Vec<Host>>::new();
+        for (peer, host) in peers {
+            let hosts = map.entry(peer).or_default();
+            if !hosts.contains(&host) {
+                hosts.push(host)
+            }
+        }
+
+        Self {
+            peers: map,
+            resolver,
+            refresh: Some(Self::DEFAULT_REFRESH),
+        }
+    }
+
+    /// Set the interval in which to discover the peers again. `None` means
+    /// to discover them only once.
+    pub fn refresh(self, interval: Option<Duration>) -> Self {
+        Self {
+            refresh: interval,
+            ..self
+        }
+    }
+}
+
+impl Discovery for Resolving {
+    type Addr = SocketAddr;
+    type Stream = BoxStream<'static, (PeerId, Vec<SocketAddr>)>;
+
+    fn discover(self) -> Self::Stream {
+        let Self {
+            peers,
+            resolver,
+            refresh,
+        } = self;
+        async_stream::stream! {
+            let mut retry = false;
+            loop {
+                for (peer, hosts) in &peers {
+                    if retry {
+                        for host in hosts {
+                            resolver.invalidate(host)
+                        }
+                    }
+                    let addrs = resolver.resolve_all(hosts).await;
+                    if !addrs.is_empty() {
+                        yield (*peer, addrs);
+                    }
+                }
+                match refresh {
+                    None => break,
+                    Some(interval) => link_async::sleep(interval).await,
+                }
+                retry = true;
+            }
+        }
+        .boxed()
+    }
+}
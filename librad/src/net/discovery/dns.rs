@@ -0,0 +1,327 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Discovery of seeds published in the DNS.
+//!
+//! A domain publishes its seeds as `SRV` records of `_radicle._udp.<domain>`,
+//! each of whose targets carries a `TXT` record `id=<peer>`:
+//!
+//! ```text
+//! _radicle._udp.example.com. SRV 0 0 8776 seed1.example.com.
+//! seed1.example.com.         TXT "id=hynkyndc6w3p8urucakobzna7sxwgcqny7xxtw88dtx3pkf7m3nrzc"
+//! ```
+//!
+//! Targets without such a record are skipped. The targets themselves are
+//! resolved using a [`Resolver`], like the [`Host`]s of a
+//! [`super::Resolving`] discovery.
+//!
+//! `SRV` and `TXT` records can't be looked up via the system resolver, so
+//! they are queried from [`Config::nameservers`] directly, over UDP. Responses
+//! truncated to fit a datagram are discarded, which limits a domain to a few
+//! dozen seeds.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
+    time::Duration,
+};
+
+use futures::stream::{BoxStream, StreamExt as _};
+use thiserror::Error;
+use tokio::net::UdpSocket;
+
+use super::{
+    wire::{self, Builder, Srv, CLASS_IN, TYPE_SRV, TYPE_TXT},
+    Discovery,
+};
+use crate::{
+    net::resolve::{Host, Resolver},
+    PeerId,
+};
+
+/// The service label under which seeds are published.
+pub const SERVICE: &str = "_radicle._udp";
+
+const RCODE_NXDOMAIN: u16 = 3;
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The nameservers to query, in order of preference.
+    pub nameservers: Vec<SocketAddr>,
+    /// How long to wait for a response from a nameserver.
+    pub timeout: Duration,
+}
+
+impl Config {
+    /// The nameservers configured in `/etc/resolv.conf`, if any.
+    pub fn system() -> Self {
+        let nameservers = match nameservers("/etc/resolv.conf") {
+            Ok(nameservers) => nameservers,
+            Err(e) => {
+                tracing::warn!(err = %e, "failed to read system nameservers");
+                vec![]
+            },
+        };
+        Self {
+            nameservers,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            nameservers: vec![],
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Parse the `nameserver` lines of a `resolv.conf(5)` file.
+pub fn nameservers(path: impl AsRef<Path>) -> io::Result<Vec<SocketAddr>> {
+    let conf = std::fs::read_to_string(path)?;
+    Ok(conf
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("nameserver") => words.next()?.parse::<IpAddr>().ok(),
+                _ => None,
+            }
+        })
+        .map(|ip| SocketAddr::new(ip, 53))
+        .collect())
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("no nameservers configured")]
+    NoNameservers,
+
+    #[error("query for `{name}` failed with response code {rcode}")]
+    Rcode { name: String, rcode: u16 },
+
+    #[error("response for `{0}` was truncated")]
+    Truncated(String),
+
+    #[error("response for `{0}` does not match the query")]
+    Mismatch(String),
+
+    #[error("query for `{0}` timed out")]
+    Timeout(String),
+
+    #[error(transparent)]
+    Wire(#[from] wire::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Look up the `SRV` records of `name`.
+pub async fn srv(config: &Config, name: &str) -> Result<Vec<Srv>, Error> {
+    lookup(config, name, TYPE_SRV, |rdata| rdata.srv()).await
+}
+
+/// Look up the `TXT` records of `name`, each as its list of strings.
+pub async fn txt(config: &Config, name: &str) -> Result<Vec<Vec<String>>, Error> {
+    lookup(config, name, TYPE_TXT, |rdata| {
+        rdata
+            .txt()
+            .map(|strings| strings.into_iter().map(ToOwned::to_owned).collect())
+    })
+    .await
+}
+
+/// The seeds published under `domain`, see the [module documentation](self).
+pub async fn seeds(config: &Config, domain: &str) -> Result<Vec<(PeerId, Host)>, Error> {
+    let mut records = srv(config, &format!("{}.{}", SERVICE, domain)).await?;
+    records.sort_by_key(|srv| srv.priority);
+
+    let mut seeds = Vec::new();
+    for Srv { port, target, .. } in records {
+        let peer = match txt(config, &target).await {
+            Ok(records) => records
+                .iter()
+                .flatten()
+                .find_map(|s| s.strip_prefix("id=").and_then(|id| id.parse().ok())),
+            Err(e) => {
+                tracing::warn!(%domain, %target, err = %e, "failed to look up seed peer id");
+                continue;
+            },
+        };
+        match peer {
+            Some(peer) => seeds.push((peer, Host { name: target, port })),
+            None => tracing::warn!(%domain, %target, "skipping seed without peer id"),
+        }
+    }
+    Ok(seeds)
+}
+
+async fn lookup<T, F>(config: &Config, name: &str, typ: u16, decode: F) -> Result<Vec<T>, Error>
+where
+    F: Fn(&wire::Rdata) -> Result<T, wire::Error>,
+{
+    let mut err = Error::NoNameservers;
+    for nameserver in &config.nameservers {
+        let response = match query(config, *nameserver, name, typ).await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::debug!(%nameserver, %name, err = %e, "nameserver query failed");
+                err = e;
+                continue;
+            },
+        };
+        let msg = wire::parse(&response)?;
+        return match msg.rcode() {
+            0 => msg
+                .records
+                .iter()
+                .filter(|record| record.typ == typ)
+                .map(|record| decode(&record.rdata).map_err(Error::from))
+                .collect(),
+            RCODE_NXDOMAIN => Ok(vec![]),
+            rcode => Err(Error::Rcode {
+                name: name.to_owned(),
+                rcode,
+            }),
+        };
+    }
+    Err(err)
+}
+
+async fn query(
+    config: &Config,
+    nameserver: SocketAddr,
+    name: &str,
+    typ: u16,
+) -> Result<Vec<u8>, Error> {
+    let id = rand::random();
+    let query = Builder::new(id, wire::FLAG_RECURSION_DESIRED, 1, 0)
+        .question(name, typ, CLASS_IN)
+        .finish();
+
+    let local = match nameserver {
+        SocketAddr::V4(_) => IpAddr::from(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::from(Ipv6Addr::UNSPECIFIED),
+    };
+    let socket = UdpSocket::bind((local, 0)).await?;
+    socket.connect(nameserver).await?;
+    socket.send(&query).await?;
+
+    let mut buf = vec![0; 512];
+    let len = link_async::timeout(config.timeout, socket.recv(&mut buf))
+        .await
+        .map_err(|link_async::Elapsed| Error::Timeout(name.to_owned()))??;
+    buf.truncate(len);
+
+    let msg = wire::parse(&buf)?;
+    let answers = msg
+        .questions
+        .iter()
+        .any(|q| q.name.eq_ignore_ascii_case(name.trim_end_matches('.')));
+    if msg.id != id || msg.flags & wire::FLAG_RESPONSE == 0 || !answers {
+        return Err(Error::Mismatch(name.to_owned()));
+    }
+    if msg.flags & wire::FLAG_TRUNCATED != 0 {
+        return Err(Error::Truncated(name.to_owned()));
+    }
+    Ok(buf)
+}
+
+/// Discovery of the seeds published under a set of domains, see the [module
+/// documentation](self).
+///
+/// Unless disabled, the seeds are looked up again every [`Dns::refresh`]
+/// interval, bypassing the [`Resolver`] cache for their targets, so changes
+/// to the published seeds take effect without a restart.
+#[derive(Clone)]
+pub struct Dns {
+    domains: Vec<String>,
+    config: Config,
+    resolver: Resolver,
+    refresh: Option<Duration>,
+}
+
+impl Dns {
+    pub const DEFAULT_REFRESH: Duration = Duration::from_secs(5 * 60);
+
+    pub fn new<I>(config: Config, resolver: Resolver, domains: I) -> Self
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut domains = domains.into_iter().collect::<Vec<_>>();
+        domains.sort();
+        domains.dedup();
+        Self {
+            domains,
+            config,
+            resolver,
+            refresh: Some(Self::DEFAULT_REFRESH),
+        }
+    }
+
+    /// Set the interval in which to look up the seeds again. `None` means
+    /// to look them up only once.
+    pub fn refresh(self, interval: Option<Duration>) -> Self {
+        Self {
+            refresh: interval,
+            ..self
+        }
+    }
+}
+
+impl Discovery for Dns {
+    type Addr = SocketAddr;
+    type Stream = BoxStream<'static, (PeerId, Vec<SocketAddr>)>;
+
+    fn discover(self) -> Self::Stream {
+        let Self {
+            domains,
+            config,
+            resolver,
+            refresh,
+        } = self;
+        async_stream::stream! {
+            if domains.is_empty() {
+                return;
+            }
+            let mut retry = false;
+            loop {
+                let mut peers = BTreeMap::<PeerId, Vec<Host>>::new();
+                for domain in &domains {
+                    match seeds(&config, domain).await {
+                        Ok(seeds) => {
+                            for (peer, host) in seeds {
+                                peers.entry(peer).or_default().push(host)
+                            }
+                        },
+                        Err(e) => tracing::warn!(%domain, err = %e, "failed to look up seeds"),
+                    }
+                }
+                for (peer, hosts) in &peers {
+                    if retry {
+                        for host in hosts {
+                            resolver.invalidate(host)
+                        }
+                    }
+                    let addrs = resolver.resolve_all(hosts).await;
+                    if !addrs.is_empty() {
+                        yield (*peer, addrs);
+                    }
+                }
+                match refresh {
+                    None => break,
+                    Some(interval) => link_async::sleep(interval).await,
+                }
+                retry = true;
+            }
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,275 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Discovery of peers on the local network via multicast DNS.
+//!
+//! Peers advertise a DNS-SD service instance of type [`message::SERVICE`],
+//! whose instance name is the [`PeerId`]. Its `TXT` record carries the peer
+//! id and listen addresses as `id=<peer>` and `addr=<socket addr>` strings,
+//! which avoids the need for `SRV` and address records. Unspecified listen
+//! addresses (eg. `0.0.0.0:12345`) are resolved by the receiver to the
+//! source address of the announcement.
+//!
+//! An [`Mdns`] discovery queries for other peers, and announces the local
+//! peer, when started and every [`Config::interval`] thereafter, and answers
+//! queries from other peers in between. Only IPv4 is supported.
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::Arc,
+    time::Duration,
+};
+
+use futures::stream::{BoxStream, StreamExt as _};
+use tokio::net::UdpSocket;
+
+use super::Discovery;
+use crate::PeerId;
+
+/// The mDNS IPv4 multicast group.
+pub const MULTICAST_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+/// The mDNS port.
+pub const PORT: u16 = 5353;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// How often to query for peers and announce the local peer.
+    pub interval: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Discovery of peers on the local network, see the [module
+/// documentation](self).
+#[derive(Clone)]
+pub struct Mdns {
+    config: Config,
+    local_id: PeerId,
+    listen_addrs: Vec<SocketAddr>,
+}
+
+impl Mdns {
+    /// Discover peers other than `local_id`, announcing the local peer as
+    /// listening on `listen_addrs`.
+    pub fn new(config: Config, local_id: PeerId, listen_addrs: Vec<SocketAddr>) -> Self {
+        Self {
+            config,
+            local_id,
+            listen_addrs: listen_addrs
+                .into_iter()
+                .filter(SocketAddr::is_ipv4)
+                .collect(),
+        }
+    }
+}
+
+impl Discovery for Mdns {
+    type Addr = SocketAddr;
+    type Stream = BoxStream<'static, (PeerId, Vec<SocketAddr>)>;
+
+    fn discover(self) -> Self::Stream {
+        let Self {
+            config,
+            local_id,
+            listen_addrs,
+        } = self;
+        async_stream::stream! {
+            let socket = match bind() {
+                Ok(socket) => Arc::new(socket),
+                Err(e) => {
+                    tracing::warn!(err = %e, "failed to bind mDNS socket, disabling mDNS discovery");
+                    return;
+                },
+            };
+            let group = SocketAddr::from((MULTICAST_ADDR, PORT));
+            let announcement = message::announcement(&local_id, &listen_addrs);
+
+            let ticks = futures::stream::unfold(true, move |first| async move {
+                if !first {
+                    link_async::sleep(config.interval).await;
+                }
+                Some((Event::Tick, false))
+            });
+            let packets = futures::stream::unfold(socket.clone(), |socket| async move {
+                let mut buf = vec![0; 9000];
+                let recv = socket.recv_from(&mut buf).await.map(|(len, from)| {
+                    buf.truncate(len);
+                    (buf, from)
+                });
+                Some((Event::Recv(recv), socket))
+            });
+            let mut events = futures::stream::select(ticks.boxed(), packets.boxed());
+
+            while let Some(event) = events.next().await {
+                let (packet, from) = match event {
+                    Event::Tick => {
+                        for packet in [message::query(), announcement.clone()] {
+                            if let Err(e) = socket.send_to(&packet, group).await {
+                                tracing::warn!(err = %e, "failed to send mDNS packet");
+                            }
+                        }
+                        continue;
+                    },
+                    Event::Recv(Ok(recv)) => recv,
+                    Event::Recv(Err(e)) => {
+                        tracing::warn!(err = %e, "mDNS receive error");
+                        continue;
+                    },
+                };
+                let parsed = match message::parse(&packet) {
+                    Ok(parsed) => parsed,
+                    Err(e) => {
+                        tracing::trace!(%from, err = %e, "ignoring malformed mDNS packet");
+                        continue;
+                    },
+                };
+                if parsed.query {
+                    if let Err(e) = socket.send_to(&announcement, group).await {
+                        tracing::warn!(err = %e, "failed to send mDNS announcement");
+                    }
+                }
+                for (peer, addrs) in parsed.announced {
+                    if peer == local_id {
+                        continue;
+                    }
+                    let addrs = addrs
+                        .into_iter()
+                        .map(|addr| resolve_unspecified(addr, from.ip()))
+                        .collect::<Vec<_>>();
+                    if !addrs.is_empty() {
+                        tracing::debug!(%peer, ?addrs, "discovered peer via mDNS");
+                        yield (peer, addrs);
+                    }
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+enum Event {
+    Tick,
+    Recv(io::Result<(Vec<u8>, SocketAddr)>),
+}
+
+fn bind() -> io::Result<UdpSocket> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let sock = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    sock.set_reuse_address(true)?;
+    #[cfg(unix)]
+    sock.set_reuse_port(true)?;
+    sock.bind(&SocketAddr::from(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT)).into())?;
+    sock.join_multicast_v4(&MULTICAST_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+    sock.set_multicast_loop_v4(true)?;
+    sock.set_nonblocking(true)?;
+    UdpSocket::from_std(sock.into())
+}
+
+fn resolve_unspecified(addr: SocketAddr, from: IpAddr) -> SocketAddr {
+    if addr.ip().is_unspecified() {
+        SocketAddr::new(from, addr.port())
+    } else {
+        addr
+    }
+}
+
+/// Encoding and decoding of the messages used for discovery.
+pub mod message {
+    use std::net::SocketAddr;
+
+    use crate::{
+        net::discovery::wire::{self, Builder, CLASS_IN, TYPE_ANY, TYPE_PTR, TYPE_TXT},
+        PeerId,
+    };
+
+    pub use wire::Error;
+
+    /// The DNS-SD service type of radicle-link peers.
+    pub const SERVICE: &str = "_radicle-link._udp.local";
+
+    const CACHE_FLUSH: u16 = 0x8000;
+    const TTL: u32 = 120;
+
+    /// The parts of a message relevant to discovery.
+    #[derive(Clone, Debug, Default, PartialEq, Eq)]
+    pub struct Parsed {
+        /// Whether the message asks for instances of [`SERVICE`].
+        pub query: bool,
+        /// The peers announced in the message.
+        pub announced: Vec<(PeerId, Vec<SocketAddr>)>,
+    }
+
+    /// A query for instances of [`SERVICE`].
+    pub fn query() -> Vec<u8> {
+        Builder::new(0, 0, 1, 0)
+            .question(SERVICE, TYPE_PTR, CLASS_IN)
+            .finish()
+    }
+
+    /// An unsolicited response announcing `peer` as listening on `addrs`.
+    pub fn announcement(peer: &PeerId, addrs: &[SocketAddr]) -> Vec<u8> {
+        let instance = format!("{}.{}", peer.default_encoding(), SERVICE);
+        let strings = std::iter::once(format!("id={}", peer.default_encoding()))
+            .chain(addrs.iter().map(|addr| format!("addr={}", addr)))
+            .collect::<Vec<_>>();
+
+        Builder::new(0, wire::FLAG_RESPONSE | wire::FLAG_AUTHORITATIVE, 0, 2)
+            .record(SERVICE, TYPE_PTR, CLASS_IN, TTL, |rdata| {
+                wire::name(rdata, &instance)
+            })
+            .record(&instance, TYPE_TXT, CLASS_IN | CACHE_FLUSH, TTL, |rdata| {
+                wire::txt(rdata, strings.iter().map(String::as_str))
+            })
+            .finish()
+    }
+
+    /// Parse a message, extracting queries for and announcements of
+    /// [`SERVICE`] instances. Other questions and records are ignored.
+    pub fn parse(msg: &[u8]) -> Result<Parsed, Error> {
+        let msg = wire::parse(msg)?;
+        let query = msg.questions.iter().any(|q| {
+            q.name.eq_ignore_ascii_case(SERVICE) && (q.typ == TYPE_PTR || q.typ == TYPE_ANY)
+        });
+
+        let mut announced = Vec::new();
+        for record in &msg.records {
+            let is_instance = record
+                .name
+                .to_ascii_lowercase()
+                .strip_suffix(SERVICE)
+                .map_or(false, |instance| instance.ends_with('.'));
+            if record.typ == TYPE_TXT && is_instance {
+                if let Some(peer) = record.rdata.txt().ok().and_then(|txt| read_txt(&txt)) {
+                    announced.push(peer);
+                }
+            }
+        }
+
+        Ok(Parsed { query, announced })
+    }
+
+    fn read_txt(strings: &[&str]) -> Option<(PeerId, Vec<SocketAddr>)> {
+        let mut peer = None;
+        let mut addrs = Vec::new();
+        for s in strings {
+            if let Some(id) = s.strip_prefix("id=") {
+                peer = id.parse().ok();
+            } else if let Some(addr) = s.strip_prefix("addr=") {
+                if let Ok(addr) = addr.parse() {
+                    addrs.push(addr);
+                }
+            }
+        }
+        peer.map(|peer| (peer, addrs))
+    }
+}
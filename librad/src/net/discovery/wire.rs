@@ -0,0 +1,265 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Encoding and decoding of the subset of the DNS wire format used for
+//! discovery, see [RFC 1035](https://datatracker.ietf.org/doc/html/rfc1035).
+
+use std::convert::TryFrom;
+
+use thiserror::Error;
+
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_SRV: u16 = 33;
+pub const TYPE_ANY: u16 = 255;
+pub const CLASS_IN: u16 = 1;
+
+pub const FLAG_RESPONSE: u16 = 0x8000;
+pub const FLAG_AUTHORITATIVE: u16 = 0x0400;
+pub const FLAG_TRUNCATED: u16 = 0x0200;
+pub const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("unexpected end of message")]
+    Truncated,
+
+    #[error("invalid name")]
+    Name,
+}
+
+/// Incremental encoding of a message.
+///
+/// The counts of questions and records given to [`Builder::new`] must match
+/// the number of [`Builder::question`] and [`Builder::record`] calls.
+pub struct Builder {
+    buf: Vec<u8>,
+}
+
+impl Builder {
+    pub fn new(id: u16, flags: u16, questions: u16, answers: u16) -> Self {
+        let mut buf = Vec::with_capacity(512);
+        for field in [id, flags, questions, answers, 0, 0] {
+            buf.extend_from_slice(&field.to_be_bytes());
+        }
+        Self { buf }
+    }
+
+    pub fn question(mut self, qname: &str, typ: u16, class: u16) -> Self {
+        name(&mut self.buf, qname);
+        self.buf.extend_from_slice(&typ.to_be_bytes());
+        self.buf.extend_from_slice(&class.to_be_bytes());
+        self
+    }
+
+    pub fn record<F>(mut self, rname: &str, typ: u16, class: u16, ttl: u32, rdata: F) -> Self
+    where
+        F: FnOnce(&mut Vec<u8>),
+    {
+        name(&mut self.buf, rname);
+        self.buf.extend_from_slice(&typ.to_be_bytes());
+        self.buf.extend_from_slice(&class.to_be_bytes());
+        self.buf.extend_from_slice(&ttl.to_be_bytes());
+        let mut data = Vec::new();
+        rdata(&mut data);
+        self.buf
+            .extend_from_slice(&(data.len() as u16).to_be_bytes());
+        self.buf.extend_from_slice(&data);
+        self
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Encode `name` uncompressed.
+pub fn name(buf: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+}
+
+/// Encode `strings` as `TXT` record data.
+///
+/// Strings are limited to 255 bytes, longer ones are truncated.
+pub fn txt<'a>(buf: &mut Vec<u8>, strings: impl IntoIterator<Item = &'a str>) {
+    for s in strings {
+        let s = &s.as_bytes()[..s.len().min(255)];
+        buf.push(s.len() as u8);
+        buf.extend_from_slice(s);
+    }
+}
+
+/// A decoded message.
+#[derive(Clone, Debug)]
+pub struct Message<'a> {
+    pub id: u16,
+    pub flags: u16,
+    pub questions: Vec<Question>,
+    /// The answer, authority, and additional records, in this order.
+    pub records: Vec<Record<'a>>,
+}
+
+impl Message<'_> {
+    /// The response code.
+    pub fn rcode(&self) -> u16 {
+        self.flags & 0xf
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Question {
+    pub name: String,
+    pub typ: u16,
+}
+
+#[derive(Clone, Debug)]
+pub struct Record<'a> {
+    pub name: String,
+    pub typ: u16,
+    pub rdata: Rdata<'a>,
+}
+
+/// The data of a [`Record`].
+///
+/// Retains the whole message, as names in the data may be compressed.
+#[derive(Clone, Debug)]
+pub struct Rdata<'a> {
+    msg: &'a [u8],
+    start: usize,
+    len: usize,
+}
+
+/// The data of a `SRV` record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Srv {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+impl<'a> Rdata<'a> {
+    pub fn bytes(&self) -> &'a [u8] {
+        &self.msg[self.start..self.start + self.len]
+    }
+
+    /// Decode as `TXT` record data, skipping strings which aren't valid
+    /// UTF-8.
+    pub fn txt(&self) -> Result<Vec<&'a str>, Error> {
+        let mut rdata = self.bytes();
+        let mut strings = Vec::new();
+        while let Some((len, rest)) = rdata.split_first() {
+            let len = *len as usize;
+            let s = rest.get(..len).ok_or(Error::Truncated)?;
+            if let Ok(s) = std::str::from_utf8(s) {
+                strings.push(s)
+            }
+            rdata = &rest[len..];
+        }
+        Ok(strings)
+    }
+
+    /// Decode as `SRV` record data.
+    pub fn srv(&self) -> Result<Srv, Error> {
+        let rdata = self.bytes();
+        let field = |i| read_u16(rdata, i);
+        let (target, end) = read_name(self.msg, self.start + 6)?;
+        if end > self.start + self.len {
+            return Err(Error::Truncated);
+        }
+        Ok(Srv {
+            priority: field(0)?,
+            weight: field(2)?,
+            port: field(4)?,
+            target,
+        })
+    }
+
+    /// Decode as `PTR` record data.
+    pub fn ptr(&self) -> Result<String, Error> {
+        read_name(self.msg, self.start).map(|(name, _)| name)
+    }
+}
+
+pub fn parse(msg: &[u8]) -> Result<Message, Error> {
+    let field = |i| read_u16(msg, i);
+    let questions = field(4)?;
+    let records = field(6)? as usize + field(8)? as usize + field(10)? as usize;
+
+    let mut parsed = Message {
+        id: field(0)?,
+        flags: field(2)?,
+        questions: Vec::with_capacity(questions.into()),
+        records: Vec::new(),
+    };
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        let (name, next) = read_name(msg, pos)?;
+        let typ = field(next)?;
+        read_u16(msg, next + 2)?;
+        pos = next + 4;
+        parsed.questions.push(Question { name, typ });
+    }
+
+    for _ in 0..records {
+        let (name, next) = read_name(msg, pos)?;
+        let typ = field(next)?;
+        let len = field(next + 8)? as usize;
+        let start = next + 10;
+        if msg.len() < start + len {
+            return Err(Error::Truncated);
+        }
+        pos = start + len;
+        parsed.records.push(Record {
+            name,
+            typ,
+            rdata: Rdata { msg, start, len },
+        });
+    }
+
+    Ok(parsed)
+}
+
+fn read_u16(buf: &[u8], i: usize) -> Result<u16, Error> {
+    Ok(u16::from_be_bytes(
+        <[u8; 2]>::try_from(buf.get(i..i + 2).ok_or(Error::Truncated)?).unwrap(),
+    ))
+}
+
+/// Decode the possibly compressed name at `pos`, returning it along with the
+/// position following it.
+fn read_name(msg: &[u8], mut pos: usize) -> Result<(String, usize), Error> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Bound the number of compression pointers followed, so cycles
+    // terminate
+    for _ in 0..128 {
+        let len = *msg.get(pos).ok_or(Error::Truncated)? as usize;
+        match len {
+            0 => {
+                let end = end.unwrap_or(pos + 1);
+                return Ok((labels.join("."), end));
+            },
+            len if len & 0xc0 == 0xc0 => {
+                let lo = *msg.get(pos + 1).ok_or(Error::Truncated)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = (len & 0x3f) << 8 | lo;
+            },
+            len if len & 0xc0 == 0 => {
+                let label = msg.get(pos + 1..pos + 1 + len).ok_or(Error::Truncated)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len;
+            },
+            _ => return Err(Error::Name),
+        }
+    }
+    Err(Error::Name)
+}
@@ -0,0 +1,210 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Connection establishment racing multiple addresses, following [RFC 8305].
+//!
+//! A peer is typically known by several address hints, some of which may be
+//! stale or unreachable from where we are (eg. an IPv6 address on an IPv4-only
+//! network). Instead of waiting for each attempt to time out, [`race`] starts
+//! connection attempts in [`sort`] order, staggered by
+//! [`CONNECTION_ATTEMPT_DELAY`]: the next attempt is started when the delay
+//! elapses, or as soon as all attempts in flight have failed. The first
+//! attempt to succeed wins, and all others are cancelled.
+//!
+//! The order of attempts is informed by [`Reachability`], which records the
+//! outcome of past attempts per address: addresses known to work are tried
+//! first, fastest first, and addresses which failed last time are tried last.
+//! Address families are interleaved, so a broken family can't delay an
+//! attempt in the working one by more than a single delay.
+//!
+//! [RFC 8305]: https://datatracker.ietf.org/doc/html/rfc8305
+
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::{
+    future::{self, Either},
+    stream::{FuturesUnordered, StreamExt as _},
+};
+use parking_lot::Mutex;
+
+use crate::PeerId;
+
+/// Delay between starting two consecutive connection attempts, as
+/// recommended by RFC 8305, section 5.
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Number of distinct peer addresses [`Reachability`] remembers.
+const MAX_RECORDS: usize = 4096;
+
+#[derive(Clone, Copy, Debug)]
+struct Record {
+    /// Time it took to establish the last successful connection.
+    latency: Option<Duration>,
+    /// Whether the last attempt failed.
+    failed: bool,
+    updated: Instant,
+}
+
+/// Outcomes of past connection attempts per peer address.
+#[derive(Clone, Default)]
+pub struct Reachability {
+    records: Arc<Mutex<HashMap<(PeerId, SocketAddr), Record>>>,
+}
+
+impl Reachability {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that connecting to `addr` of `peer` succeeded after `latency`.
+    pub fn success(&self, peer: PeerId, addr: SocketAddr, latency: Duration) {
+        self.record(
+            peer,
+            addr,
+            Record {
+                latency: Some(latency),
+                failed: false,
+                updated: Instant::now(),
+            },
+        )
+    }
+
+    /// Record that connecting to `addr` of `peer` failed.
+    pub fn failure(&self, peer: PeerId, addr: SocketAddr) {
+        let latency = self
+            .records
+            .lock()
+            .get(&(peer, addr))
+            .and_then(|r| r.latency);
+        self.record(
+            peer,
+            addr,
+            Record {
+                latency,
+                failed: true,
+                updated: Instant::now(),
+            },
+        )
+    }
+
+    fn record(&self, peer: PeerId, addr: SocketAddr, record: Record) {
+        let mut records = self.records.lock();
+        if records.len() >= MAX_RECORDS && !records.contains_key(&(peer, addr)) {
+            let oldest = records
+                .iter()
+                .min_by_key(|(_, r)| r.updated)
+                .map(|(k, _)| *k);
+            if let Some(oldest) = oldest {
+                records.remove(&oldest);
+            }
+        }
+        records.insert((peer, addr), record);
+    }
+
+    /// Order `addrs` of `peer` for connection attempts, see [`sort`].
+    pub fn sort(&self, peer: PeerId, addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        let records = self.records.lock();
+        sort(addrs, |addr| records.get(&(peer, *addr)).copied())
+    }
+}
+
+/// Order `addrs` for connection attempts when nothing is known about them,
+/// ie. only interleave address families, starting with IPv6.
+pub fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    sort(addrs, |_| None)
+}
+
+/// Order `addrs` for connection attempts.
+///
+/// Addresses are ranked by their last known outcome: those we connected to
+/// successfully (lowest latency first), followed by unknown ones, followed by
+/// those which failed last time. The ranking is stable, so the given order is
+/// preserved among equals. Address families are then interleaved, starting
+/// with the family of the best ranked address, or IPv6 if none is known.
+fn sort<F>(mut addrs: Vec<SocketAddr>, record: F) -> Vec<SocketAddr>
+where
+    F: Fn(&SocketAddr) -> Option<Record>,
+{
+    addrs.sort_by_key(|addr| match record(addr) {
+        Some(Record {
+            failed: false,
+            latency,
+            ..
+        }) => (0, latency.unwrap_or_default()),
+        None => (1, Duration::ZERO),
+        Some(Record { failed: true, .. }) => (2, Duration::ZERO),
+    });
+    let ipv6_first = match addrs.first() {
+        Some(addr) if record(addr).map_or(false, |r| !r.failed) => addr.is_ipv6(),
+        _ => true,
+    };
+
+    let (v6, v4): (VecDeque<_>, VecDeque<_>) = addrs.into_iter().partition(|a| a.is_ipv6());
+    let (mut first, mut second) = if ipv6_first { (v6, v4) } else { (v4, v6) };
+    let mut sorted = Vec::with_capacity(first.len() + second.len());
+    loop {
+        match (first.pop_front(), second.pop_front()) {
+            (None, None) => break,
+            (a, b) => sorted.extend(a.into_iter().chain(b)),
+        }
+    }
+    sorted
+}
+
+/// Attempt to `connect` to `addrs`, in order, staggered by `delay`.
+///
+/// Returns the first connection established, or the errors of all attempts
+/// if none succeeded. Attempts still in flight when one succeeds are
+/// dropped.
+pub async fn race<T, E, F, Fut>(
+    addrs: Vec<SocketAddr>,
+    delay: Duration,
+    mut connect: F,
+) -> Result<T, Vec<(SocketAddr, E)>>
+where
+    F: FnMut(SocketAddr) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut addrs = addrs.into_iter().peekable();
+    let mut attempts = FuturesUnordered::new();
+    let mut errors = Vec::new();
+    loop {
+        match addrs.next() {
+            Some(addr) => {
+                tracing::trace!(remote_addr = %addr, "starting connection attempt");
+                let attempt = connect(addr);
+                attempts.push(async move { (addr, attempt.await) })
+            },
+            None if attempts.is_empty() => return Err(errors),
+            None => {},
+        }
+
+        let mut timer = if addrs.peek().is_some() {
+            Either::Left(Box::pin(link_async::sleep(delay)))
+        } else {
+            Either::Right(future::pending())
+        };
+        loop {
+            match future::select(attempts.next(), &mut timer).await {
+                Either::Left((Some((_, Ok(conn))), _)) => return Ok(conn),
+                Either::Left((Some((addr, Err(e))), _)) => {
+                    errors.push((addr, e));
+                    // Start the next attempt right away if nothing is in
+                    // flight anymore.
+                    if attempts.is_empty() {
+                        break;
+                    }
+                },
+                Either::Left((None, _)) | Either::Right(_) => break,
+            }
+        }
+    }
+}
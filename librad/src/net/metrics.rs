@@ -0,0 +1,172 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Instrumentation of the protocol stack.
+//!
+//! With the `metrics` feature enabled, a [`Metrics`] recorder can be handed to
+//! a [`crate::net::peer::Peer`] via
+//! [`crate::net::peer::config::Runtime::metrics`]. The peer then reports the
+//! metrics listed in [`names`] to it. [`Registry`] is a recorder which keeps
+//! the metrics in memory, and renders them in the [Prometheus text format],
+//! so binaries can serve them over HTTP without librad depending on an HTTP
+//! stack.
+//!
+//! Without the feature, instrumentation compiles to nothing.
+//!
+//! [Prometheus text format]: https://prometheus.io/docs/instrumenting/exposition_formats/
+
+use std::time::Duration;
+
+use crate::{
+    git::storage::pool,
+    net::protocol::{broadcast, membership, throttle},
+};
+
+/// The names of the metrics reported, and their labels.
+pub mod names {
+    /// Counter of gossip messages received, labelled by `kind`: `have` or
+    /// `want`.
+    pub const GOSSIP_MESSAGES_RECEIVED: &str = "link_gossip_messages_received_total";
+    /// Counter of replication runs, labelled by `outcome`: `success` or
+    /// `failure`.
+    pub const REPLICATIONS: &str = "link_replications_total";
+    /// Histogram of the durations of replication runs in seconds, labelled by
+    /// `outcome`. Time spent waiting for a replication slot is not included.
+    pub const FETCH_DURATION: &str = "link_fetch_duration_seconds";
+    /// Gauge of the storage instances borrowed from a pool, labelled by
+    /// `pool`: `user` or `protocol`.
+    pub const STORAGE_POOL_IN_USE: &str = "link_storage_pool_in_use";
+    /// Gauge of the maximum number of storage instances of a pool, labelled
+    /// by `pool`.
+    pub const STORAGE_POOL_SIZE: &str = "link_storage_pool_size";
+    /// Gauge of the tasks waiting for a storage instance of a pool, labelled
+    /// by `pool`.
+    pub const STORAGE_POOL_WAITING: &str = "link_storage_pool_waiting";
+    /// Histogram of the time it took to check out a storage instance from a
+    /// pool in seconds, labelled by `pool`.
+    pub const STORAGE_POOL_WAIT: &str = "link_storage_pool_wait_seconds";
+    /// Counter of checkouts from a pool which timed out, labelled by `pool`.
+    pub const STORAGE_POOL_TIMEOUTS: &str = "link_storage_pool_timeouts_total";
+    /// Counter of membership transitions, labelled by `transition`:
+    /// `promoted`, `demoted`, or `evicted`.
+    pub const MEMBERSHIP_TRANSITIONS: &str = "link_membership_transitions_total";
+    /// Counter of inbound messages shed by rate limiting, labelled by `kind`:
+    /// `gossip` or `request-pull`, and by `limit`: `peer` or `ip`.
+    pub const LOAD_SHED: &str = "link_load_shed_total";
+}
+
+/// Labels of a metric, as `(name, value)` pairs.
+pub type Labels<'a> = &'a [(&'static str, &'static str)];
+
+/// A recorder of metrics.
+#[cfg(feature = "metrics")]
+pub trait Metrics: Send + Sync {
+    /// Increment the counter `name` by one.
+    fn increment(&self, name: &'static str, labels: Labels);
+
+    /// Set the gauge `name` to `value`.
+    fn set(&self, name: &'static str, labels: Labels, value: f64);
+
+    /// Record `value` in the histogram `name`.
+    fn observe(&self, name: &'static str, labels: Labels, value: f64);
+}
+
+#[cfg(feature = "metrics")]
+mod registry;
+#[cfg(feature = "metrics")]
+pub use registry::Registry;
+
+/// The recorder of a peer, if any.
+#[derive(Clone, Default)]
+pub(crate) struct Recorder {
+    #[cfg(feature = "metrics")]
+    inner: Option<std::sync::Arc<dyn Metrics>>,
+}
+
+#[cfg(feature = "metrics")]
+impl From<Option<std::sync::Arc<dyn Metrics>>> for Recorder {
+    fn from(inner: Option<std::sync::Arc<dyn Metrics>>) -> Self {
+        Self { inner }
+    }
+}
+
+#[allow(unused_variables)]
+impl Recorder {
+    pub(crate) fn gossip_received<A, P>(&self, msg: &broadcast::Message<A, P>) {
+        let kind = match msg {
+            broadcast::Message::Have { .. } => "have",
+            broadcast::Message::Want { .. } => "want",
+        };
+        self.increment(names::GOSSIP_MESSAGES_RECEIVED, &[("kind", kind)])
+    }
+
+    pub(crate) fn replicated(&self, success: bool, elapsed: Duration) {
+        let labels = &[("outcome", if success { "success" } else { "failure" })];
+        self.increment(names::REPLICATIONS, labels);
+        self.observe(names::FETCH_DURATION, labels, elapsed.as_secs_f64());
+    }
+
+    pub(crate) fn storage_pool(&self, pool: &'static str, status: pool::Status) {
+        let labels = &[("pool", pool)];
+        self.set(
+            names::STORAGE_POOL_IN_USE,
+            labels,
+            status.size.saturating_sub(status.idle) as f64,
+        );
+        self.set(names::STORAGE_POOL_SIZE, labels, status.max_size as f64);
+        self.set(names::STORAGE_POOL_WAITING, labels, status.waiting as f64);
+    }
+
+    pub(crate) fn storage_checkout<S>(&self, pool: &'static str, storage: &pool::PooledRef<S>) {
+        self.observe(
+            names::STORAGE_POOL_WAIT,
+            &[("pool", pool)],
+            storage.waited().as_secs_f64(),
+        )
+    }
+
+    pub(crate) fn storage_checkout_failed(&self, pool: &'static str, err: &pool::PoolError) {
+        if let pool::PoolError::Timeout { .. } = err {
+            self.increment(names::STORAGE_POOL_TIMEOUTS, &[("pool", pool)])
+        }
+    }
+
+    pub(crate) fn membership<A>(&self, transition: &membership::Transition<A>) {
+        let transition = match transition {
+            membership::Transition::Promoted(_) => "promoted",
+            membership::Transition::Demoted(_) => "demoted",
+            membership::Transition::Evicted(_) => "evicted",
+        };
+        self.increment(names::MEMBERSHIP_TRANSITIONS, &[("transition", transition)])
+    }
+
+    pub(crate) fn shed(&self, kind: throttle::Kind, limit: throttle::Limit) {
+        self.increment(
+            names::LOAD_SHED,
+            &[("kind", kind.as_str()), ("limit", limit.as_str())],
+        )
+    }
+
+    fn increment(&self, name: &'static str, labels: Labels) {
+        #[cfg(feature = "metrics")]
+        if let Some(inner) = &self.inner {
+            inner.increment(name, labels)
+        }
+    }
+
+    fn set(&self, name: &'static str, labels: Labels, value: f64) {
+        #[cfg(feature = "metrics")]
+        if let Some(inner) = &self.inner {
+            inner.set(name, labels, value)
+        }
+    }
+
+    fn observe(&self, name: &'static str, labels: Labels, value: f64) {
+        #[cfg(feature = "metrics")]
+        if let Some(inner) = &self.inner {
+            inner.observe(name, labels, value)
+        }
+    }
+}
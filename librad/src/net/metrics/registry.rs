@@ -0,0 +1,163 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{collections::BTreeMap, fmt::Write as _};
+
+use parking_lot::Mutex;
+
+use super::{Labels, Metrics};
+
+/// The upper bounds of the buckets of histograms, in seconds.
+pub const BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+type Key = (&'static str, Vec<(&'static str, &'static str)>);
+
+enum Value {
+    Counter(u64),
+    Gauge(f64),
+    Histogram {
+        /// Non-cumulative counts per bucket in [`BUCKETS`], followed by the
+        /// count of observations exceeding the last bucket.
+        buckets: Vec<u64>,
+        sum: f64,
+        count: u64,
+    },
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Counter(_) => "counter",
+            Self::Gauge(_) => "gauge",
+            Self::Histogram { .. } => "histogram",
+        }
+    }
+}
+
+/// An in-memory [`Metrics`] recorder.
+///
+/// Metrics are kept for the lifetime of the [`Registry`], and can be exported
+/// via [`Registry::render`].
+#[derive(Default)]
+pub struct Registry {
+    metrics: Mutex<BTreeMap<Key, Value>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Render all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let metrics = self.metrics.lock();
+        let mut out = String::new();
+        let mut prev = None;
+        for ((name, labels), value) in metrics.iter() {
+            if prev != Some(name) {
+                writeln!(out, "# TYPE {} {}", name, value.kind()).ok();
+                prev = Some(name);
+            }
+            match value {
+                Value::Counter(n) => {
+                    writeln!(out, "{}{} {}", name, render_labels(labels, None), n).ok();
+                },
+                Value::Gauge(v) => {
+                    writeln!(out, "{}{} {}", name, render_labels(labels, None), v).ok();
+                },
+                Value::Histogram {
+                    buckets,
+                    sum,
+                    count,
+                } => {
+                    let mut cumulative = 0;
+                    let bounds = BUCKETS
+                        .iter()
+                        .map(|le| le.to_string())
+                        .chain(Some("+Inf".to_owned()));
+                    for (le, n) in bounds.zip(buckets) {
+                        cumulative += n;
+                        writeln!(
+                            out,
+                            "{}_bucket{} {}",
+                            name,
+                            render_labels(labels, Some(&le)),
+                            cumulative
+                        )
+                        .ok();
+                    }
+                    writeln!(out, "{}_sum{} {}", name, render_labels(labels, None), sum).ok();
+                    writeln!(
+                        out,
+                        "{}_count{} {}",
+                        name,
+                        render_labels(labels, None),
+                        count
+                    )
+                    .ok();
+                },
+            }
+        }
+        out
+    }
+}
+
+impl Metrics for Registry {
+    fn increment(&self, name: &'static str, labels: Labels) {
+        let mut metrics = self.metrics.lock();
+        let value = metrics
+            .entry((name, labels.to_vec()))
+            .or_insert(Value::Counter(0));
+        if let Value::Counter(n) = value {
+            *n += 1
+        }
+    }
+
+    fn set(&self, name: &'static str, labels: Labels, value: f64) {
+        self.metrics
+            .lock()
+            .insert((name, labels.to_vec()), Value::Gauge(value));
+    }
+
+    fn observe(&self, name: &'static str, labels: Labels, value: f64) {
+        let mut metrics = self.metrics.lock();
+        let entry = metrics
+            .entry((name, labels.to_vec()))
+            .or_insert_with(|| Value::Histogram {
+                buckets: vec![0; BUCKETS.len() + 1],
+                sum: 0.0,
+                count: 0,
+            });
+        if let Value::Histogram {
+            buckets,
+            sum,
+            count,
+        } = entry
+        {
+            let bucket = BUCKETS
+                .iter()
+                .position(|le| value <= *le)
+                .unwrap_or(BUCKETS.len());
+            buckets[bucket] += 1;
+            *sum += value;
+            *count += 1;
+        }
+    }
+}
+
+fn render_labels(labels: &[(&'static str, &'static str)], le: Option<&str>) -> String {
+    let labels = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, v))
+        .chain(le.map(|le| format!("le=\"{}\"", le)))
+        .collect::<Vec<_>>();
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    }
+}
@@ -3,10 +3,19 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use futures::{future, StreamExt as _, TryFutureExt as _, TryStreamExt as _};
 use link_async::Spawner;
+use std_ext::time::unix_now;
 
 use crate::{
     git::{self, identities::local::LocalIdentity, Urn},
@@ -35,17 +44,82 @@ pub use crate::net::protocol::{
 pub mod error;
 pub mod storage;
 pub use storage::Storage as PeerStorage;
+pub mod introspection;
+pub use introspection::Introspection;
+pub mod support;
 
 #[derive(Clone)]
 pub struct Config<Signer, Guard = config::DenyAll> {
     pub signer: Signer,
     pub protocol: protocol::Config<Guard>,
     pub storage: config::Storage,
+    pub runtime: config::Runtime,
 }
 
 pub mod config {
+    use std::{sync::Arc, time::Duration};
+
+    use link_async::Spawner;
+
+    use crate::net::metrics::Recorder;
+
     pub use super::protocol::config::{Denied, DenyAll};
 
+    /// Settings for the async runtime used by the [`super::Peer`].
+    ///
+    /// By default, the runtime of the ambient async context is used, and the
+    /// number of concurrent blocking tasks is only limited by the process-wide
+    /// thread pool.
+    #[derive(Clone, Default)]
+    pub struct Runtime {
+        /// Use this [`Spawner`] for all tasks.
+        ///
+        /// Allows to share a [`Spawner`] (and its stats) with the host
+        /// application. If set, `handle` and `max_blocking` are ignored.
+        pub spawner: Option<Arc<Spawner>>,
+        /// Spawn tasks onto this runtime, instead of the ambient one.
+        pub handle: Option<tokio::runtime::Handle>,
+        /// Maximum number of blocking tasks (eg. storage access) to run
+        /// concurrently.
+        ///
+        /// Cf. [`Spawner::with_max_blocking`]
+        pub max_blocking: Option<usize>,
+        /// Report the metrics of the protocol stack to this recorder.
+        ///
+        /// Cf. [`crate::net::metrics`]
+        #[cfg(feature = "metrics")]
+        pub metrics: Option<Arc<dyn crate::net::metrics::Metrics>>,
+    }
+
+    impl Runtime {
+        pub(super) fn spawner(&self) -> Option<Arc<Spawner>> {
+            if let Some(spawner) = &self.spawner {
+                return Some(Arc::clone(spawner));
+            }
+
+            let spawner = match &self.handle {
+                Some(handle) => Spawner::tokio(handle.clone()),
+                None => Spawner::from_current()?,
+            };
+            let spawner = match self.max_blocking {
+                Some(max) => spawner.with_max_blocking(max),
+                None => spawner,
+            };
+            Some(Arc::new(spawner))
+        }
+
+        pub(super) fn metrics(&self) -> Recorder {
+            #[cfg(feature = "metrics")]
+            {
+                Recorder::from(self.metrics.clone())
+            }
+            #[cfg(not(feature = "metrics"))]
+            {
+                Recorder::default()
+            }
+        }
+    }
+
     #[derive(Clone, Copy, Default)]
     pub struct Storage {
         pub user: UserStorage,
@@ -59,12 +133,27 @@ pub mod config {
     pub struct UserStorage {
         /// Number of [`crate::git::storage::Storage`] instances to reserve.
         pub pool_size: usize,
+        /// Announce the URNs whose signed refs were updated when a storage
+        /// borrowed via [`super::Peer::using_storage`] or
+        /// [`super::Peer::storage`] is returned to the pool.
+        ///
+        /// Default: `false`, ie. callers are responsible for calling
+        /// [`super::Peer::announce`].
+        pub announce_writes: bool,
+        /// How long to wait for a storage instance when the pool is
+        /// exhausted, before failing with
+        /// [`crate::git::storage::PoolError::Timeout`].
+        ///
+        /// Default: `None`, ie. wait indefinitely.
+        pub checkout_timeout: Option<Duration>,
     }
 
     impl Default for UserStorage {
         fn default() -> Self {
             Self {
                 pool_size: num_cpus::get_physical(),
+                announce_writes: false,
+                checkout_timeout: None,
             }
         }
     }
@@ -76,12 +165,18 @@ pub mod config {
     pub struct ProtocolStorage {
         /// Number of [`crate::git::storage::Storage`] instances to reserve.
         pub pool_size: usize,
+        /// How long to wait for a storage instance when the pool is
+        /// exhausted, cf. [`UserStorage::checkout_timeout`].
+        ///
+        /// Answering gossip queries never waits, regardless of this setting.
+        pub checkout_timeout: Option<Duration>,
     }
 
     impl Default for ProtocolStorage {
         fn default() -> Self {
             Self {
                 pool_size: num_cpus::get_physical(),
+                checkout_timeout: None,
             }
         }
     }
@@ -96,6 +191,9 @@ pub struct Peer<S, G = config::DenyAll> {
     caches: protocol::Caches,
     spawner: Arc<Spawner>,
     repl: Replication,
+    inflight: replication::InFlight,
+    #[cfg(feature = "hooks")]
+    hooks: git::hooks::Dispatcher<git_ext::Oid>,
 }
 
 impl<S, G> Peer<S, G>
@@ -103,46 +201,87 @@ where
     S: Signer + Clone,
     G: RequestPullGuard,
 {
-    pub fn new(config: Config<S, G>) -> Result<Self, error::Init> {
-        let spawner = Spawner::from_current()
-            .map(Arc::new)
-            .ok_or(error::Init::Runtime)?;
-        let phone = protocol::TinCans::default();
+    pub fn new(mut config: Config<S, G>) -> Result<Self, error::Init> {
+        let spawner = config.runtime.spawner().ok_or(error::Init::Runtime)?;
+        let metrics = config.runtime.metrics();
+        let phone = protocol::TinCans::default().with_metrics(metrics.clone());
         let storage_lock = git::storage::pool::Initialised::no();
-        let pool = git::storage::Pool::new(
+        let pool = git::storage::Pool::with_timeout(
             git::storage::pool::ReadWriteConfig::new(
                 config.protocol.paths.clone(),
                 config.signer.clone(),
                 storage_lock.clone(),
             ),
             config.storage.protocol.pool_size,
+            config.storage.protocol.checkout_timeout,
         );
         let caches = {
             let store = git::storage::Storage::open(&config.protocol.paths, config.signer.clone())?;
             let phone = phone.clone();
             let urns = protocol::cache::urns::Filter::new(store, move |ev| phone.emit(ev))?;
-            protocol::Caches { urns }
+            let provided = protocol::cache::provided::Provided::open(
+                &config.protocol.paths,
+                protocol::cache::provided::DEFAULT_TTL,
+                protocol::cache::provided::DEFAULT_MAX_BYTES,
+            )?;
+            protocol::Caches {
+                urns,
+                seen: Default::default(),
+                provided,
+                misses: Default::default(),
+            }
         };
 
-        let repl = Replication::new(&config.protocol.paths, config.protocol.replication)?;
+        let inflight = replication::InFlight::default();
+        config.protocol.replication.scheduler = {
+            let phone = phone.clone();
+            config
+                .protocol
+                .replication
+                .scheduler
+                .clone()
+                .on_event(move |ev| phone.emit(ev))
+        };
+        #[cfg(feature = "hooks")]
+        let hooks = {
+            let (hooks, run) = git::hooks::Dispatcher::new(Default::default());
+            spawner.spawn(run).detach();
+            hooks
+        };
+        let repl = {
+            let (evicted, lifecycle) = (phone.clone(), phone.clone());
+            let repl =
+                Replication::new(&config.protocol.paths, config.protocol.replication.clone())?
+                    .on_evicted(move |ev| evicted.emit(ev))
+                    .on_lifecycle(move |ev| lifecycle.emit(ev))
+                    .tracking(inflight.clone())
+                    .metrics(metrics.clone());
+            #[cfg(feature = "hooks")]
+            let repl = repl.on_data(dispatch_data(hooks.clone()));
+            repl
+        };
 
         let peer_store = PeerStorage::new(
             storage::Config {
                 fetch_quota: config.protocol.rate_limits.gossip.fetches_per_peer_and_urn,
             },
             spawner.clone(),
+            PeerId::from_signer(&config.signer),
             pool,
+            git::storage::Reader::open(&config.protocol.paths)?,
             caches.urns.clone(),
-            repl.clone(),
+            caches.provided.clone(),
+            repl.clone().prioritise(replication::Priority::Gossip),
             phone.clone(),
         );
-        let user_store = git::storage::Pool::new(
+        let user_store = git::storage::Pool::with_timeout(
             git::storage::pool::ReadWriteConfig::new(
                 config.protocol.paths.clone(),
                 config.signer.clone(),
                 storage_lock,
             ),
             config.storage.user.pool_size,
+            config.storage.user.checkout_timeout,
         );
 
         Ok(Self {
@@ -153,6 +292,9 @@ where
             caches,
             spawner,
             repl,
+            inflight,
+            #[cfg(feature = "hooks")]
+            hooks,
         })
     }
 
@@ -173,7 +315,18 @@ where
             user_storage: self.user_store.clone().into(),
             ..self.config.clone().into()
         };
-        Client::new(config, self.spawner.clone(), self.phone.clone())
+        let (evicted, lifecycle) = (self.phone.clone(), self.phone.clone());
+        Client::new(config, self.spawner.clone(), self.phone.clone()).map(|client| {
+            let client = client
+                .on_evicted(move |ev| evicted.emit(ev))
+                .on_lifecycle(move |ev| lifecycle.emit(ev))
+                .tracking(self.inflight.clone())
+                .metrics(self.phone.metrics().clone())
+                .misses(self.caches.misses.clone());
+            #[cfg(feature = "hooks")]
+            let client = client.on_data(dispatch_data(self.hooks.clone()));
+            client
+        })
     }
 
     pub fn announce(&self, have: gossip::Payload) -> Result<(), gossip::Payload> {
@@ -184,6 +337,12 @@ where
         self.phone.query(want)
     }
 
+    /// Query the network for providers of `urn`, yielding them as they
+    /// respond until `timeout` elapses.
+    ///
+    /// If no provider responded to a query for `urn` recently, the query is
+    /// not repeated and the stream is empty, unless a gossip `Have` for `urn`
+    /// arrived in the meantime.
     pub fn providers(
         &self,
         urn: Urn,
@@ -191,11 +350,25 @@ where
     ) -> impl futures::Stream<Item = PeerInfo<SocketAddr>> {
         use protocol::event::{upstream::Gossip, Upstream};
 
+        let misses = self.caches.misses.providers.clone();
+        let key = urn.clone().with_path(None);
+        if misses.contains(&key) {
+            tracing::debug!(%urn, "no providers found recently, skipping query");
+            return futures::stream::empty().boxed();
+        }
+
+        let found = Arc::new(AtomicBool::new(false));
         let events = self.subscribe();
         let providers = futures::stream::select(
-            futures::stream::once(async move {
-                link_async::sleep(timeout).await;
-                Err("timed out")
+            futures::stream::once({
+                let found = found.clone();
+                async move {
+                    link_async::sleep(timeout).await;
+                    if !found.load(Ordering::Relaxed) {
+                        misses.insert(key);
+                    }
+                    Err("timed out")
+                }
             }),
             {
                 let urn = urn.clone();
@@ -221,18 +394,36 @@ where
             },
         )
         .take_while(|x| future::ready(x.is_ok()))
-        .map(Result::unwrap);
+        .map(Result::unwrap)
+        .inspect(move |_| found.store(true, Ordering::Relaxed));
 
         match self.query(gossip::Payload {
             urn,
             rev: None,
             origin: None,
+            cob: None,
+            batch: None,
         }) {
             Ok(()) => providers.boxed(),
             Err(_) => futures::stream::empty().boxed(),
         }
     }
 
+    /// Register an in-process `hook`, invoked for each ref changed by
+    /// replication, with ordering guaranteed per URN.
+    ///
+    /// See [`git::hooks::dispatch`].
+    #[cfg(feature = "hooks")]
+    pub fn register_hook(&self, hook: Arc<dyn git::hooks::dispatch::Hook<git_ext::Oid>>) {
+        self.hooks.register(hook)
+    }
+
+    /// Drain the notifications registered hooks failed to process.
+    #[cfg(feature = "hooks")]
+    pub fn hook_dead_letters(&self) -> Vec<git::hooks::DeadLetter<git_ext::Oid>> {
+        self.hooks.dead_letters()
+    }
+
     pub async fn connected_peers(&self) -> Vec<PeerId> {
         self.phone.connected_peers().await
     }
@@ -241,10 +432,99 @@ where
         self.phone.membership().await
     }
 
+    /// Capture the membership topology of this peer, for diagnostics.
+    ///
+    /// Returns `None` if the protocol stack is not running.
+    pub async fn membership_snapshot(&self) -> Option<protocol::membership::Snapshot<SocketAddr>> {
+        self.phone.membership_snapshot().await
+    }
+
+    /// The URNs observed in gossip within the last hour, most recently seen
+    /// first.
+    ///
+    /// Cf. [`protocol::cache::seen`]
+    pub fn recently_seen(&self) -> Vec<protocol::cache::seen::Seen> {
+        self.caches.seen.get()
+    }
+
     pub async fn stats(&self) -> Stats {
         self.phone.stats().await
     }
 
+    /// Keep a connection to `peer`, eg. a seed or a delegate of a project of
+    /// interest, re-establishing it if it is lost.
+    ///
+    /// See [`protocol::health`].
+    pub fn watch<I>(&self, peer: PeerId, addrs: I)
+    where
+        I: IntoIterator<Item = SocketAddr>,
+    {
+        self.config.protocol.health.watch.watch(peer, addrs)
+    }
+
+    /// Stop keeping a connection to `peer`, see [`Peer::watch`].
+    pub fn unwatch(&self, peer: &PeerId) -> bool {
+        self.config.protocol.health.watch.unwatch(peer)
+    }
+
+    /// The health of the connections to the watched peers, as of the last
+    /// check.
+    pub fn health(&self) -> BTreeMap<PeerId, protocol::health::Status> {
+        self.config.protocol.health.watch.status()
+    }
+
+    /// Capture the state of the connections and replication runs of this
+    /// peer, see [`Introspection`].
+    pub async fn introspect(&self) -> Introspection {
+        let MembershipInfo { active, passive } = self.membership().await;
+        Introspection {
+            peer_id: self.peer_id(),
+            membership: introspection::Membership { active, passive },
+            connections: self
+                .phone
+                .connections()
+                .await
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            fetches: self
+                .inflight
+                .fetches()
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        }
+    }
+
+    /// Collect redacted diagnostics into a [`support::Bundle`], and write it
+    /// to `path`.
+    ///
+    /// The bundle is returned as well, eg. for inspection before submitting it
+    /// alongside a bug report.
+    pub async fn support_bundle(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<support::Bundle, support::Error> {
+        let storage = self.using_read_only(support::Storage::collect).await??;
+        let bundle = support::Bundle {
+            format_version: support::FORMAT_VERSION,
+            created_at: unix_now(),
+            peer_id: self.peer_id(),
+            version: support::Version::current(),
+            config: support::Config::from(&self.config.protocol),
+            storage,
+            stats: self.stats().await.into(),
+            membership: self
+                .membership_snapshot()
+                .await
+                .map(support::redact_membership),
+            recent_gossip: self.recently_seen().into_iter().map(Into::into).collect(),
+        };
+        bundle.write(path.as_ref())?;
+
+        Ok(bundle)
+    }
+
     #[deprecated(
         note = "use of `self.interrogate(..)` is deprecated in favour of going through `self.client(..)?.interrogate(..)`"
     )]
@@ -254,10 +534,14 @@ where
     ) -> Result<Interrogation, error::NoConnection> {
         let from = from.into();
         let remote_peer = from.0;
-        let Connected(conn) = self
-            .connect(from)
-            .await
-            .ok_or(error::NoConnection(remote_peer))?;
+        let unreachable = &self.caches.misses.peers;
+        if unreachable.contains(&remote_peer) {
+            return Err(error::NoConnection(remote_peer));
+        }
+        let Connected(conn) = self.connect(from).await.ok_or_else(|| {
+            unreachable.insert(remote_peer);
+            error::NoConnection(remote_peer)
+        })?;
         Ok(self.phone.interrogate(remote_peer, conn))
     }
 
@@ -304,7 +588,7 @@ where
             .connect(from)
             .await
             .ok_or(error::Replicate::NoConnection(remote_peer))?;
-        let store = self.user_store.get().await?;
+        let store = self.user_storage().await?;
         self.repl
             .replicate(&self.spawner, store, conn, urn, whoami)
             .err_into()
@@ -323,6 +607,27 @@ where
         self.phone.subscribe()
     }
 
+    /// Subscribe to the [`event::upstream::Lifecycle`] events of this peer.
+    ///
+    /// The subscription is bounded: if the consumer falls behind, the oldest
+    /// events are dropped (and a warning is logged) instead of stalling the
+    /// protocol. The stream ends when the peer is dropped.
+    pub fn lifecycle(&self) -> impl futures::Stream<Item = event::upstream::Lifecycle> {
+        self.subscribe()
+            .take_while(|evt| future::ready(!matches!(evt, Err(protocol::RecvError::Closed))))
+            .filter_map(|evt| {
+                future::ready(match evt {
+                    Ok(ProtocolEvent::Lifecycle(lifecycle)) => Some(lifecycle),
+                    Ok(_) => None,
+                    Err(protocol::RecvError::Lagged(n)) => {
+                        tracing::warn!(skipped = n, "lifecycle subscriber lagging behind");
+                        None
+                    },
+                    Err(protocol::RecvError::Closed) => None,
+                })
+            })
+    }
+
     /// Borrow a [`git::storage::Storage`] from the pool, and run a blocking
     /// computation on it.
     pub async fn using_storage<F, T>(&self, blocking: F) -> Result<T, error::Storage>
@@ -330,8 +635,11 @@ where
         F: FnOnce(&git::storage::Storage) -> T + Send + 'static,
         T: Send + 'static,
     {
-        let storage = self.user_store.get().await?;
-        Ok(self.spawner.blocking(move || blocking(&storage)).await)
+        let storage = self.announcing(self.user_storage().await?);
+        Ok(self
+            .spawner
+            .blocking(move || blocking(storage.as_ref()))
+            .await)
     }
 
     /// Borrow a [`git::storage::ReadOnly`] from the pool, and run a blocking
@@ -341,7 +649,7 @@ where
         F: FnOnce(&git::storage::ReadOnly) -> T + Send + 'static,
         T: Send + 'static,
     {
-        let storage = self.user_store.get().await?;
+        let storage = self.user_storage().await?;
         Ok(self
             .spawner
             .blocking(move || blocking(storage.read_only()))
@@ -364,12 +672,41 @@ where
     pub async fn storage(
         &self,
     ) -> Result<impl AsRef<git::storage::Storage>, git::storage::pool::PoolError> {
-        self.user_store
-            .get()
-            .map_ok(git::storage::pool::PooledRef::from)
+        self.user_storage()
+            .map_ok(|storage| self.announcing(storage))
             .await
     }
 
+    /// Borrow a [`git::storage::Storage`] from the user pool, reporting the
+    /// pool's utilisation.
+    async fn user_storage(
+        &self,
+    ) -> Result<git::storage::PooledRef<git::storage::Storage>, git::storage::pool::PoolError> {
+        let metrics = self.phone.metrics();
+        let storage = self.user_store.get().await;
+        metrics.storage_pool("user", self.user_store.status());
+        match &storage {
+            Ok(storage) => metrics.storage_checkout("user", storage),
+            Err(e) => metrics.storage_checkout_failed("user", e),
+        }
+        storage
+    }
+
+    fn announcing<T>(&self, storage: T) -> Announcing<T>
+    where
+        T: AsRef<git::storage::Storage>,
+    {
+        Announcing {
+            storage,
+            phone: self
+                .config
+                .storage
+                .user
+                .announce_writes
+                .then(|| self.phone.clone()),
+        }
+    }
+
     pub async fn bind(
         &self,
     ) -> Result<protocol::Bound<PeerStorage, G>, protocol::error::Bootstrap> {
@@ -385,6 +722,38 @@ where
     }
 }
 
+/// A borrowed [`git::storage::Storage`] which announces the URNs updated
+/// through it when dropped, cf. [`config::UserStorage::announce_writes`].
+struct Announcing<T: AsRef<git::storage::Storage>> {
+    storage: T,
+    phone: Option<TinCans>,
+}
+
+impl<T: AsRef<git::storage::Storage>> AsRef<git::storage::Storage> for Announcing<T> {
+    fn as_ref(&self) -> &git::storage::Storage {
+        self.storage.as_ref()
+    }
+}
+
+impl<T: AsRef<git::storage::Storage>> Drop for Announcing<T> {
+    fn drop(&mut self) {
+        if let Some(phone) = &self.phone {
+            for (urn, at) in self.storage.as_ref().take_touched() {
+                let have = gossip::Payload {
+                    urn: urn.with_path(reflike!("refs/rad/signed_refs")),
+                    rev: Some(gossip::Rev::Git(at.into())),
+                    origin: None,
+                    cob: None,
+                    batch: None,
+                };
+                if let Err(have) = phone.announce(have) {
+                    tracing::warn!(urn = %have.urn, "failed to announce local update");
+                }
+            }
+        }
+    }
+}
+
 impl<S, G> git::local::transport::CanOpenStorage for Peer<S, G>
 where
     S: Signer + Clone,
@@ -402,3 +771,16 @@ where
         }
     }
 }
+
+/// Dispatch [`git::hooks::Data`] to `hooks` without waiting, dropping it if
+/// the hooks can't keep up.
+#[cfg(feature = "hooks")]
+fn dispatch_data(
+    hooks: git::hooks::Dispatcher<git_ext::Oid>,
+) -> impl Fn(git::hooks::Data<git_ext::Oid>) + Send + Sync + 'static {
+    move |data| {
+        if let Err(n) = hooks.try_dispatch(data.into()) {
+            tracing::warn!(notification = %n, "hooks lagging behind, dropping notification")
+        }
+    }
+}
@@ -35,11 +35,17 @@ pub enum Init {
     #[error(transparent)]
     Storage(#[from] storage::error::Init),
 
+    #[error("failed to open the storage reader")]
+    Reader(#[from] storage::reader::error::Open),
+
     #[error(transparent)]
     Cache(#[from] Box<cache::urns::Error>),
 
     #[error(transparent)]
     Replication(#[from] replication::error::Init),
+
+    #[error("failed to open the gossip cache")]
+    Provided(#[from] crate::cache::Error),
 }
 
 impl From<cache::urns::Error> for Init {
@@ -0,0 +1,78 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Runtime state of a [`super::Peer`], for monitoring and debugging.
+//!
+//! An [`Introspection`] is obtained via [`super::Peer::introspect`]. Unlike a
+//! [`super::support::Bundle`], it is not redacted, and cheap enough to be
+//! polled, eg. by a node exposing it to local clients.
+
+use std::net::SocketAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    git::Urn,
+    net::{protocol::event::downstream::ConnectionInfo, replication::inflight},
+    PeerId,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Introspection {
+    pub peer_id: PeerId,
+    pub membership: Membership,
+    /// Empty if the protocol stack is not running.
+    pub connections: Vec<Connection>,
+    pub fetches: Vec<Fetch>,
+}
+
+/// The peers in the membership views.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Membership {
+    pub active: Vec<PeerId>,
+    pub passive: Vec<PeerId>,
+}
+
+/// An established QUIC connection.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Connection {
+    pub peer: PeerId,
+    pub remote_addr: SocketAddr,
+    /// Milliseconds.
+    pub rtt: u128,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl From<ConnectionInfo> for Connection {
+    fn from(info: ConnectionInfo) -> Self {
+        Self {
+            peer: info.peer,
+            remote_addr: info.remote_addr,
+            rtt: info.stats.rtt.as_millis(),
+            bytes_sent: info.stats.bytes_sent,
+            bytes_received: info.stats.bytes_received,
+        }
+    }
+}
+
+/// A replication run in progress.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Fetch {
+    pub urn: Urn,
+    pub remote_peer: PeerId,
+    /// Milliseconds since the run started.
+    pub elapsed: u128,
+}
+
+impl From<inflight::Fetch> for Fetch {
+    fn from(fetch: inflight::Fetch) -> Self {
+        Self {
+            urn: fetch.urn,
+            remote_peer: fetch.remote_peer,
+            elapsed: fetch.started.elapsed().as_millis(),
+        }
+    }
+}
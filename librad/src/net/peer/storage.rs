@@ -14,7 +14,7 @@ use nonzero_ext::nonzero;
 
 use crate::{
     git::{
-        storage::{self, Pool, PoolError, PooledRef, ReadOnlyStorage as _},
+        storage::{self, Pool, PoolError, PooledRef, ReadOnlyStorage as _, Reader},
         tracking,
         Urn,
     },
@@ -37,8 +37,11 @@ pub struct Config {
 
 #[derive(Clone)]
 pub struct Storage {
+    local_peer_id: PeerId,
     pool: Pool<storage::Storage>,
+    reader: Reader,
     urns: cache::urns::Filter,
+    provided: cache::provided::Provided,
     rate: Arc<RateLimiter<Keyed<(PeerId, Urn)>>>,
     exec: Arc<Spawner>,
     repl: Replication,
@@ -46,17 +49,24 @@ pub struct Storage {
 }
 
 impl Storage {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         conf: Config,
         exec: Arc<Spawner>,
+        local_peer_id: PeerId,
         pool: Pool<storage::Storage>,
+        reader: Reader,
         urns: cache::urns::Filter,
+        provided: cache::provided::Provided,
         repl: Replication,
         tins: TinCans,
     ) -> Self {
         Self {
+            local_peer_id,
             pool,
+            reader,
             urns,
+            provided,
             rate: Arc::new(RateLimiter::keyed(
                 conf.fetch_quota,
                 nonzero!(256 * 1024usize),
@@ -67,6 +77,33 @@ impl Storage {
         }
     }
 
+    /// Borrow a [`storage::Storage`] from the pool, reporting the pool's
+    /// utilisation.
+    async fn git(&self) -> Result<PooledRef<storage::Storage>, PoolError> {
+        let metrics = self.tins.metrics();
+        let git = self.pool.get().await;
+        metrics.storage_pool("protocol", self.pool.status());
+        match &git {
+            Ok(git) => metrics.storage_checkout("protocol", git),
+            Err(e) => metrics.storage_checkout_failed("protocol", e),
+        }
+        git
+    }
+
+    /// Borrow a [`storage::Storage`] from the pool only if one is available
+    /// right away, reporting the pool's utilisation.
+    async fn try_git(&self) -> Result<Option<PooledRef<storage::Storage>>, PoolError> {
+        let metrics = self.tins.metrics();
+        let git = self.pool.try_checkout().await;
+        metrics.storage_pool("protocol", self.pool.status());
+        match &git {
+            Ok(Some(git)) => metrics.storage_checkout("protocol", git),
+            Ok(None) => {},
+            Err(e) => metrics.storage_checkout_failed("protocol", e),
+        }
+        git
+    }
+
     fn is_rate_limited(&self, remote_peer: PeerId, urn: Urn) -> bool {
         self.rate.check_key(&(remote_peer, urn)).is_err()
     }
@@ -83,8 +120,8 @@ impl Storage {
             }
         }
 
-        let git = self.pool.get().await?;
-        let urn = urn_context(*git.peer_id(), urn);
+        let git = self.git().await?;
+        let urn = urn_context(self.local_peer_id, urn);
         let from = from.into();
         let remote_peer = from.0;
         if self.is_rate_limited(remote_peer, urn.clone().with_path(None)) {
@@ -102,30 +139,160 @@ impl Storage {
         }
     }
 
-    /// Determine if we have the given object locally
+    /// Fetch `has.urn` if any of the tips of the compound announcement `has`
+    /// are not known yet.
+    async fn batch_fetch(
+        &self,
+        from: impl Into<(PeerId, Vec<SocketAddr>)>,
+        origin: PeerId,
+        has: &gossip::Payload,
+    ) -> Result<replication::Success, Error> {
+        if self.has_tips(origin, has).await {
+            if let Some(gossip::Rev::Git(head)) = has.rev {
+                return Err(Error::KnownObject(head));
+            }
+        }
+        let urn = Right(Originates {
+            from: origin,
+            value: has.urn.clone(),
+        });
+        self.git_fetch(from, urn, None::<git2::Oid>).await
+    }
+
+    /// Whether we have all tips of the compound announcement `has`.
+    async fn has_tips(&self, origin: PeerId, has: &gossip::Payload) -> bool {
+        for single in has.unbatch() {
+            let urn = Right(Originates {
+                from: origin,
+                value: single.urn,
+            });
+            let head = single.rev.map(|gossip::Rev::Git(head)| head);
+            if !self.git_has(urn, head).await {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Fetch only the collaborative object `cob` of `urn`.
+    ///
+    /// Falls back to [`Self::git_fetch`] if we don't have `urn` yet, as a
+    /// namespace consisting of a single object is not of much use.
+    async fn cob_fetch(
+        &self,
+        from: impl Into<(PeerId, Vec<SocketAddr>)>,
+        urn: Either<Urn, Originates<Urn>>,
+        cob: &gossip::Cob,
+    ) -> Result<replication::Success, Error> {
+        if !self.git_has(urn.clone(), None::<git2::Oid>).await {
+            return self.git_fetch(from, urn, None::<git2::Oid>).await;
+        }
+        if let Some(known) = self.cob_has(urn.clone(), cob).await {
+            return Err(Error::KnownObject(known));
+        }
+
+        let git = self.git().await?;
+        let urn = urn_context(self.local_peer_id, urn);
+        let from = from.into();
+        let remote_peer = from.0;
+        if self.is_rate_limited(remote_peer, urn.clone().with_path(None)) {
+            return Err(Error::RateLimited { remote_peer, urn });
+        }
+
+        match self.tins.connect(from).await {
+            None => Err(Error::NoConnection { remote_peer }),
+            Some(Connected(conn)) => {
+                self.repl
+                    .replicate_cob(&self.exec, git, conn, urn, &cob.typename, &cob.object_id)
+                    .err_into::<Error>()
+                    .await
+            },
+        }
+    }
+
+    /// Determine if we have all tips of `cob` locally, returning the first one
+    /// if so.
+    async fn cob_has(
+        &self,
+        urn: Either<Urn, Originates<Urn>>,
+        cob: &gossip::Cob,
+    ) -> Option<git2::Oid> {
+        let urn = urn.map_either(
+            |urn| urn.with_path(cob.refname()),
+            |Originates { from, value }| Originates {
+                from,
+                value: value.with_path(cob.refname()),
+            },
+        );
+        let mut tips = cob.tips().peekable();
+        let first = *tips.peek()?;
+        for tip in tips {
+            if !self.git_has(urn.clone(), Some(tip)).await {
+                return None;
+            }
+        }
+
+        Some(first)
+    }
+
+    /// Determine if we have the given object locally.
+    ///
+    /// This is on the hot path of gossip handling, and so uses the
+    /// [`storage::Reader`] instead of borrowing from the pool. Should the
+    /// reader fail, libgit2 is asked instead, provided a storage is available
+    /// right away.
     async fn git_has(
         &self,
         urn: Either<Urn, Originates<Urn>>,
         head: impl Into<Option<git2::Oid>>,
     ) -> bool {
-        let git = self
-            .pool
-            .get()
-            .await
-            .expect("unable to acquire storage from pool");
-        let urn = urn_context(*git.peer_id(), urn);
+        let urn = urn_context(self.local_peer_id, urn);
 
         if !self.urns.contains(&urn.clone().with_path(None).into()) {
             return false;
         }
 
         let head = head.into().map(ext::Oid::from);
+        let reader = self.reader.clone();
+        let has = self
+            .exec
+            .blocking({
+                let urn = urn.clone();
+                move || match head {
+                    None => reader.has_urn(&urn),
+                    Some(head) => Ok(reader.has_commit(&urn, head)? || reader.has_tag(&urn, head)?),
+                }
+            })
+            .await;
+        match has {
+            Ok(has) => has,
+            Err(e) => {
+                tracing::warn!(err = %e, "reader failed, falling back to libgit2");
+                self.git_has_fallback(urn, head).await
+            },
+        }
+    }
+
+    /// [`Self::git_has`] using libgit2, or `false` if no storage is available
+    /// right away.
+    async fn git_has_fallback(&self, urn: Urn, head: Option<ext::Oid>) -> bool {
+        let git = match self.try_git().await {
+            Ok(Some(git)) => git,
+            Ok(None) => {
+                tracing::debug!("no storage available");
+                return false;
+            },
+            Err(e) => {
+                tracing::error!(err = %e, "unable to acquire storage from pool");
+                return false;
+            },
+        };
         self.exec
             .blocking(move || match head {
-                None => git.as_ref().has_urn(&urn).unwrap_or(false),
+                None => git.has_urn(&urn).unwrap_or(false),
                 Some(head) => {
-                    git.as_ref().has_commit(&urn, head).unwrap_or(false)
-                        || git.as_ref().has_tag(&urn, head).unwrap_or(false)
+                    git.has_commit(&urn, head).unwrap_or(false)
+                        || git.has_tag(&urn, head).unwrap_or(false)
                 },
             })
             .await
@@ -136,11 +303,11 @@ impl Storage {
     /// want to passively replicate the `urn`. Otherwise, the `urn` is only
     /// considered tracked if we have a tracked entry for the given `peer`.
     async fn is_tracked(&self, urn: Urn, peer: PeerId) -> Result<bool, Error> {
-        let git = self.pool.get().await?;
+        let git = self.git().await?;
         self.exec
             .blocking(move || -> Result<bool, Error> {
-                Ok(tracking::is_tracked(git.as_ref(), &urn, Some(peer))?
-                    || tracking::default_only(git.as_ref(), &urn)?)
+                Ok(tracking::is_tracked(&*git, &urn, Some(peer))?
+                    || tracking::default_only(&*git, &urn)?)
             })
             .await
     }
@@ -194,6 +361,12 @@ impl broadcast::LocalStorage<SocketAddr> for Storage {
 
         let (provider, addr_hints) = provider.into();
 
+        // We already applied this before, possibly before a restart.
+        if self.provided.contains(&has) {
+            tracing::trace!("recently provided");
+            return PutResult::Stale;
+        }
+
         // If the `has` doesn't tell us to look into a specific remote-tracking
         // branch, assume we want the `provider`'s.
         let origin = has.origin.unwrap_or(provider);
@@ -212,10 +385,20 @@ impl broadcast::LocalStorage<SocketAddr> for Storage {
             });
             let head = has.rev.as_ref().map(|gossip::Rev::Git(head)| *head);
 
-            match self
-                .git_fetch((provider, addr_hints), urn.clone(), head)
-                .await
-            {
+            let fetched = match &has.cob {
+                None if has.batch.is_some() => {
+                    self.batch_fetch((provider, addr_hints), origin, &has).await
+                },
+                None => {
+                    self.git_fetch((provider, addr_hints), urn.clone(), head)
+                        .await
+                },
+                Some(cob) => {
+                    self.cob_fetch((provider, addr_hints), urn.clone(), cob)
+                        .await
+                },
+            };
+            match fetched {
                 Ok(_) => {
                     // Verify that the announced data is stored locally now.
                     //
@@ -227,7 +410,13 @@ impl broadcast::LocalStorage<SocketAddr> for Storage {
                     // tracking them, and there was no error, but the data is
                     // still not there. In this case, returning `Stale` will
                     // just terminate the broadcast here.
-                    if self.git_has(urn, head).await {
+                    let has_announced = match &has.cob {
+                        None if has.batch.is_some() => self.has_tips(origin, &has).await,
+                        None => self.git_has(urn, head).await,
+                        Some(cob) => self.cob_has(urn, cob).await.is_some(),
+                    };
+                    if has_announced {
+                        self.provided.record(&has);
                         PutResult::Applied(gossip::Payload {
                             origin: Some(origin),
                             ..has
@@ -243,7 +432,18 @@ impl broadcast::LocalStorage<SocketAddr> for Storage {
                 },
 
                 Err(e) => match e {
-                    Error::KnownObject(_) => PutResult::Stale,
+                    Error::KnownObject(_) => {
+                        self.provided.record(&has);
+                        PutResult::Stale
+                    },
+                    Error::Replication(replication::error::Replicate::Filtered(refname)) => {
+                        tracing::debug!(%refname, "announced object is filtered");
+                        PutResult::Uninteresting
+                    },
+                    Error::Replication(replication::error::Replicate::NamespaceLimit(e)) => {
+                        tracing::warn!(err = %e, "not replicating new namespace");
+                        PutResult::Uninteresting
+                    },
                     Error::RateLimited { remote_peer, urn } => {
                         tracing::warn!(
                             "skipped fetch of {} from {} due to rate limiting",
@@ -265,23 +465,26 @@ impl broadcast::LocalStorage<SocketAddr> for Storage {
 
     #[tracing::instrument(level = "debug", skip(self))]
     async fn ask(&self, want: Self::Update) -> bool {
-        self.git_has(
-            match want.origin {
-                Some(origin) => Right(Originates {
-                    from: origin,
-                    value: want.urn,
-                }),
-                None => Left(want.urn),
+        let urn = match want.origin {
+            Some(origin) => Right(Originates {
+                from: origin,
+                value: want.urn,
+            }),
+            None => Left(want.urn),
+        };
+        match &want.cob {
+            None => {
+                self.git_has(urn, want.rev.map(|gossip::Rev::Git(head)| head))
+                    .await
             },
-            want.rev.map(|gossip::Rev::Git(head)| head),
-        )
-        .await
+            Some(cob) => self.cob_has(urn, cob).await.is_some(),
+        }
     }
 }
 
 #[async_trait]
 impl storage::Pooled<storage::Storage> for Storage {
     async fn get(&self) -> Result<PooledRef<storage::Storage>, PoolError> {
-        self.pool.get().await.map(PooledRef::from)
+        self.git().await
     }
 }
@@ -0,0 +1,326 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Diagnostics for bug reports.
+//!
+//! A [`Bundle`] is a snapshot of the state of a [`super::Peer`], obtained via
+//! [`super::Peer::support_bundle`], and written to a single JSON document. It
+//! is meant to be attached to bug reports, so it is redacted: IP addresses
+//! other than loopback and unspecified ones are masked (ports are retained),
+//! filesystem paths and custom network identifiers are omitted, and the
+//! signer is never touched.
+//!
+//! Note that the bundle does not include log output, as the `tracing`
+//! subscriber is owned by the application. The recent activity captured is
+//! the gossip observed within the [`protocol::cache::seen`] window, and the
+//! recent membership shuffles.
+
+use std::{
+    io::{self, Write as _},
+    net::SocketAddr,
+    path::Path,
+};
+
+use serde::Serialize;
+use std_ext::time::unix_secs;
+use thiserror::Error;
+
+use super::{error, Stats};
+use crate::{
+    git::{
+        identities,
+        storage::{quota, ReadOnly},
+        Urn,
+    },
+    net::{protocol, Network},
+    PeerId,
+};
+
+/// Bump when the layout of [`Bundle`] changes incompatibly.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(transparent)]
+    Storage(#[from] error::Storage),
+
+    #[error(transparent)]
+    Identities(#[from] identities::Error),
+
+    #[error(transparent)]
+    Quota(#[from] quota::Error),
+
+    #[error("failed to serialise support bundle")]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Bundle {
+    pub format_version: u32,
+    /// Seconds since the UNIX epoch.
+    pub created_at: u64,
+    pub peer_id: PeerId,
+    pub version: Version,
+    pub config: Config,
+    pub storage: Storage,
+    pub stats: Statistics,
+    /// `None` if the protocol stack was not running.
+    pub membership: Option<protocol::membership::Snapshot<String>>,
+    pub recent_gossip: Vec<Seen>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Version {
+    pub librad: &'static str,
+    pub libgit2: String,
+    pub os: &'static str,
+    pub arch: &'static str,
+}
+
+impl Version {
+    pub fn current() -> Self {
+        let (major, minor, patch) = git2::Version::get().libgit2_version();
+        Self {
+            librad: env!("CARGO_PKG_VERSION"),
+            libgit2: format!("{}.{}.{}", major, minor, patch),
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+        }
+    }
+}
+
+/// The protocol configuration, redacted.
+#[derive(Clone, Debug, Serialize)]
+pub struct Config {
+    pub listen_addr: String,
+    pub advertised_addrs: Vec<String>,
+    /// `main`, or `custom` -- the identifier of a custom network is omitted.
+    pub network: &'static str,
+    pub membership: protocol::membership::Params,
+    pub replication: Replication,
+    pub read_access: String,
+    /// Milliseconds.
+    pub announcement_window: u128,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Replication {
+    pub limit_peek: u64,
+    pub limit_data: u64,
+    /// Maximum number of tracked peers per fetch.
+    pub batch_size: Option<usize>,
+    pub slots: usize,
+    pub slots_per_urn: usize,
+    /// Milliseconds.
+    pub wait_slot: u128,
+    pub shared_haves: usize,
+    pub quota_namespace: Option<u64>,
+    pub quota_global: Option<u64>,
+    /// Number of URNs with a ref filter.
+    pub filters: usize,
+    pub namespace_limit: Option<usize>,
+}
+
+impl<G> From<&protocol::Config<G>> for Config {
+    fn from(config: &protocol::Config<G>) -> Self {
+        let repl = &config.replication;
+        Self {
+            listen_addr: redact(&config.listen_addr),
+            advertised_addrs: config
+                .advertised_addrs
+                .iter()
+                .flatten()
+                .map(redact)
+                .collect(),
+            network: match config.network {
+                Network::Main => "main",
+                Network::Custom(_) => "custom",
+            },
+            membership: config.membership.clone(),
+            replication: Replication {
+                limit_peek: repl.limit.peek,
+                limit_data: repl.limit.data,
+                batch_size: repl.limit.batch_size.map(|n| n.get()),
+                slots: repl.scheduler.config().slots,
+                slots_per_urn: repl.scheduler.config().per_urn,
+                wait_slot: repl.wait_slot.as_millis(),
+                shared_haves: repl.shared_haves,
+                quota_namespace: repl.quota.namespace,
+                quota_global: repl.quota.global,
+                filters: repl.filters.len(),
+                namespace_limit: repl.namespace_limit.as_ref().map(|limit| limit.max),
+            },
+            read_access: format!("{:?}", config.read_access),
+            announcement_window: config.announcements.window.as_millis(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Storage {
+    pub namespaces: usize,
+    /// Size of the object database, in bytes.
+    pub object_db_bytes: u64,
+    /// Bytes received per namespace, see [`quota::Usage`].
+    pub received_bytes: Vec<(Urn, u64)>,
+}
+
+impl Storage {
+    pub fn collect(storage: &ReadOnly) -> Result<Self, Error> {
+        let namespaces = identities::any::list_urns(storage)?
+            .collect::<Result<Vec<_>, _>>()?
+            .len();
+        let usage = quota::usage(storage)?;
+        Ok(Self {
+            namespaces,
+            object_db_bytes: usage.global,
+            received_bytes: usage.namespaces.into_iter().collect(),
+        })
+    }
+}
+
+/// [`Stats`], redacted.
+#[derive(Clone, Debug, Serialize)]
+pub struct Statistics {
+    pub connections_total: usize,
+    pub connected_peers: Vec<(PeerId, Vec<String>)>,
+    pub membership_active: usize,
+    pub membership_passive: usize,
+    pub urns_cache_elements: usize,
+    pub urns_cache_fingerprints: usize,
+    pub egress_queued: u64,
+    pub egress_sent: u64,
+    pub egress_dropped: u64,
+    pub egress_discarded: u64,
+    pub breakers_open: usize,
+    pub breakers_half_open: usize,
+    pub breakers_refused: u64,
+}
+
+impl From<Stats> for Statistics {
+    fn from(stats: Stats) -> Self {
+        let mut connected_peers = stats
+            .connected_peers
+            .into_iter()
+            .map(|(peer, addrs)| (peer, addrs.iter().map(redact).collect()))
+            .collect::<Vec<_>>();
+        connected_peers.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Self {
+            connections_total: stats.connections_total,
+            connected_peers,
+            membership_active: stats.membership_active,
+            membership_passive: stats.membership_passive,
+            urns_cache_elements: stats.caches.urns.elements,
+            urns_cache_fingerprints: stats.caches.urns.fingerprints,
+            egress_queued: stats.egress.queued,
+            egress_sent: stats.egress.sent,
+            egress_dropped: stats.egress.dropped,
+            egress_discarded: stats.egress.discarded,
+            breakers_open: stats.breakers.open,
+            breakers_half_open: stats.breakers.half_open,
+            breakers_refused: stats.breakers.refused,
+        }
+    }
+}
+
+/// A [`protocol::cache::seen::Seen`], with timestamps in seconds since the
+/// UNIX epoch.
+#[derive(Clone, Debug, Serialize)]
+pub struct Seen {
+    pub urn: Urn,
+    pub last_seen: u64,
+    pub origins: Vec<Origin>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Origin {
+    pub peer: PeerId,
+    /// `have` or `want`.
+    pub kind: &'static str,
+    pub seen_at: u64,
+}
+
+impl From<protocol::cache::seen::Seen> for Seen {
+    fn from(seen: protocol::cache::seen::Seen) -> Self {
+        use protocol::cache::seen::Kind;
+
+        Self {
+            urn: seen.urn,
+            last_seen: unix_secs(seen.last_seen),
+            origins: seen
+                .origins
+                .into_iter()
+                .map(|origin| Origin {
+                    peer: origin.peer,
+                    kind: match origin.kind {
+                        Kind::Have => "have",
+                        Kind::Want => "want",
+                    },
+                    seen_at: unix_secs(origin.seen_at),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Mask the addresses in `snapshot`, see [`redact`].
+pub fn redact_membership(
+    snapshot: protocol::membership::Snapshot<SocketAddr>,
+) -> protocol::membership::Snapshot<String> {
+    use protocol::membership::snapshot::{Member, Snapshot};
+
+    let members = |members: Vec<Member<SocketAddr>>| {
+        members
+            .into_iter()
+            .map(|Member { peer_id, addrs }| Member {
+                peer_id,
+                addrs: addrs.iter().map(redact).collect(),
+            })
+            .collect()
+    };
+    Snapshot {
+        local_id: snapshot.local_id,
+        params: snapshot.params,
+        active: members(snapshot.active),
+        passive: members(snapshot.passive),
+        shuffles: snapshot.shuffles,
+    }
+}
+
+/// Render `addr`, masking the IP address unless it is loopback or
+/// unspecified.
+pub fn redact(addr: &SocketAddr) -> String {
+    let ip = addr.ip();
+    if ip.is_loopback() || ip.is_unspecified() {
+        addr.to_string()
+    } else if ip.is_ipv4() {
+        format!("<ipv4>:{}", addr.port())
+    } else {
+        format!("[<ipv6>]:{}", addr.port())
+    }
+}
+
+impl Bundle {
+    /// Write the bundle as pretty-printed JSON to `path`.
+    ///
+    /// The file is written to a temporary location first, so `path` is never
+    /// left with a partial bundle.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+        serde_json::to_writer_pretty(&mut tmp, self)?;
+        tmp.write_all(b"\n")?;
+        tmp.as_file().sync_all()?;
+        tmp.persist(path).map_err(|e| e.error)?;
+        Ok(())
+    }
+}
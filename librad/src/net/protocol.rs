@@ -17,7 +17,9 @@ use tracing::Instrument as _;
 pub use super::quic::SendOnly;
 use super::{
     connection::{LocalAddr, LocalPeer},
+    happy_eyeballs::Reachability,
     quic,
+    tcp,
     upgrade,
     Network,
 };
@@ -30,19 +32,27 @@ use crate::{
     Signer,
 };
 
+pub mod address_book;
+pub mod breaker;
 pub mod broadcast;
 
 pub mod cache;
 pub use cache::Caches;
 
+pub mod compression;
+pub mod egress;
 pub mod error;
 pub mod event;
 pub mod gossip;
+pub mod health;
 pub mod interrogation;
 pub mod io;
 pub mod membership;
+pub mod read_access;
 pub mod request_pull;
+pub mod request_push;
 pub mod rpc;
+pub mod throttle;
 
 mod info;
 pub use info::{Capability, PartialPeerInfo, PeerAdvertisement, PeerInfo};
@@ -61,6 +71,7 @@ pub use state::Quota;
 use state::{RateLimits, State, StateConfig, Storage};
 
 pub type Endpoint = quic::Endpoint<2>;
+pub type Router = quic::Router<2>;
 
 #[derive(Clone, Debug)]
 pub struct Config<Guard = config::DenyAll> {
@@ -72,6 +83,29 @@ pub struct Config<Guard = config::DenyAll> {
     pub replication: replication::Config,
     pub rate_limits: Quota,
     pub request_pull: Guard,
+    /// Authorization of request-pulls, evaluated before the `request_pull`
+    /// guard, see [`request_pull::policy`].
+    pub request_pull_policy: request_pull::Authorization,
+    /// Access control for the git server, see [`read_access`].
+    pub read_access: read_access::ReadAccess,
+    /// Coalescing of our own announcements, see [`gossip::batch`].
+    pub announcements: gossip::batch::Config,
+    /// Enforcement of review policies when serving request-pull, see
+    /// [`request_pull::review`].
+    #[cfg(feature = "request-pull")]
+    pub review: request_pull::review::Config,
+    /// Also accept connections over TCP on this address, for clients which
+    /// can't use QUIC, see [`tcp`]. Default: disabled.
+    pub tcp_listen_addr: Option<SocketAddr>,
+    /// Keep-alive and health checks of connections, see [`health`].
+    pub health: health::Config,
+    /// Compression of gossip and membership messages, see [`compression`].
+    pub compression: compression::Config,
+    /// Share the QUIC endpoint of this [`Router`] with the other peers
+    /// registered with it, instead of binding `listen_addr`. Allows to host
+    /// several profiles in one process, see [`quic::router`]. Default:
+    /// disabled.
+    pub router: Option<Router>,
     // TODO: transport, ...
 }
 
@@ -120,7 +154,10 @@ pub struct Bound<S, G = config::DenyAll> {
     phone: TinCans,
     state: State<S, G>,
     incoming: quic::IncomingConnections<'static>,
+    tcp: Option<tcp::Listener>,
     periodic: BoxStream<'static, membership::Periodic<SocketAddr>>,
+    /// Peers from the [`address_book`] to join the network through.
+    known: Vec<(PeerId, Vec<SocketAddr>)>,
 }
 
 impl<S, G> Bound<S, G> {
@@ -132,6 +169,14 @@ impl<S, G> Bound<S, G> {
         self.state.endpoint.listen_addrs()
     }
 
+    /// The address TCP connections are accepted on, if enabled via
+    /// [`Config::tcp_listen_addr`].
+    pub fn tcp_listen_addr(&self) -> Option<SocketAddr> {
+        self.tcp
+            .as_ref()
+            .and_then(|tcp| tcp.listen_addrs().into_iter().next())
+    }
+
     /// Start accepting connections from remote peers.
     ///
     /// Returns a tuple of
@@ -186,14 +231,28 @@ where
     Guard: RequestPullGuard,
 {
     let local_id = PeerId::from_signer(&signer);
-    let quic::BoundEndpoint { endpoint, incoming } = quic::Endpoint::bind(
-        signer,
-        &spawner,
-        config.listen_addr,
-        config.advertised_addrs,
-        config.network,
-    )
-    .await?;
+    let address_book = address_book::AddressBook::open(&config.paths)?;
+    let known = address_book.recent(config.membership.max_active);
+    let tcp = match config.tcp_listen_addr {
+        Some(addr) => {
+            Some(tcp::Listener::bind(signer.clone(), addr, config.network.clone()).await?)
+        },
+        None => None,
+    };
+    let quic::BoundEndpoint { endpoint, incoming } = match &config.router {
+        Some(router) => router.register(signer)?,
+        None => {
+            quic::Endpoint::bind(
+                signer,
+                &spawner,
+                config.listen_addr,
+                config.advertised_addrs,
+                config.network,
+                config.health.keep_alive,
+            )
+            .await?
+        },
+    };
     let (membership, periodic) = membership::Hpv::<_, SocketAddr>::new(
         local_id,
         Pcg64Mcg::new(rand::random()),
@@ -204,42 +263,63 @@ where
         (),
     );
     let request_pull = request_pull::State::new(
-        Storage::new(storage, config.rate_limits.storage),
+        Storage::new(storage.clone(), config.rate_limits.storage),
         config.paths.clone(),
         config.request_pull,
-    );
+    )
+    .with_max_concurrent(config.rate_limits.request_pull.max_concurrent)
+    .with_scheduler(config.replication.scheduler.clone())
+    .with_haves(config.replication.haves.clone())
+    .with_policy(config.request_pull_policy);
+    #[cfg(feature = "request-pull")]
+    let request_pull = request_pull.with_review(config.review);
+    let egress = egress::Egress::new(config.rate_limits.egress);
+    let breakers = breaker::Breakers::new(config.rate_limits.breaker);
     let limits = RateLimits {
         membership: Arc::new(RateLimiter::keyed(
             config.rate_limits.membership,
             nonzero!(1024 * 1024usize),
         )),
+        ingress: throttle::Throttle::new(config.rate_limits.ingress),
     };
 
     let state = State {
         local_id,
         endpoint,
         membership,
+        storage,
         gossip,
         request_pull,
         phone: phone.clone(),
         config: StateConfig {
             paths: Arc::new(config.paths),
+            read_access: config.read_access,
+            compression: config.compression,
+            capabilities: config.compression.capabilities(),
         },
         caches,
         spawner,
         limits,
+        egress,
+        breakers,
+        reachability: Reachability::new(),
+        address_book,
+        health: config.health,
+        announcements: gossip::batch::Batcher::new(config.announcements),
     };
 
     Ok(Bound {
         phone,
         state,
         incoming,
+        tcp,
         periodic: periodic.boxed(),
+        known,
     })
 }
 
 #[tracing::instrument(
-    skip(phone, state, incoming, periodic, disco),
+    skip(phone, state, incoming, tcp, periodic, known, disco),
     fields(peer_id = %state.local_id),
 )]
 pub fn accept<Store, Guard, Disco>(
@@ -247,7 +327,9 @@ pub fn accept<Store, Guard, Disco>(
         phone,
         state,
         incoming,
+        tcp,
         periodic,
+        known,
     }: Bound<Store, Guard>,
     disco: Disco,
 ) -> (
@@ -262,7 +344,8 @@ where
     let endpoint = state.endpoint.clone();
     let spawner = state.spawner.clone();
 
-    let tasks = [
+    let mut tasks = vec![
+        spawner.spawn(accept::known(state.clone(), known)),
         spawner.spawn(accept::disco(state.clone(), disco)),
         spawner.spawn(accept::periodic(state.clone(), periodic)),
         spawner.spawn(accept::ground_control(
@@ -273,13 +356,29 @@ where
             },
         )),
     ];
+    if let Some(tcp) = tcp {
+        tasks.push(spawner.spawn(io::streams::tcp(state.clone(), tcp)));
+    }
+    tasks.push(spawner.spawn(health::run(
+        state.clone(),
+        state.health.watch.clone(),
+        state.health.check_interval,
+    )));
+    tasks.push(spawner.spawn(accept::flush_address_book(
+        state.address_book.clone(),
+        address_book::FLUSH_INTERVAL,
+    )));
     let run = {
         let endpoint = endpoint.clone();
+        let address_book = state.address_book.clone();
         async move {
             let res = io::connections::incoming(state, incoming).await;
             tracing::debug!("waiting on idle connections...");
             endpoint.wait_idle().await;
             drop(tasks);
+            if let Err(e) = address_book.flush() {
+                tracing::warn!(err = ?e, "failed to write address book");
+            }
             tracing::debug!("protocol shut down");
             res
         }
@@ -3,11 +3,12 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{iter, net::SocketAddr};
+use std::{iter, net::SocketAddr, time::Duration};
 
 use futures::stream::{self, StreamExt as _};
 
 use super::{
+    address_book::AddressBook,
     control,
     event,
     gossip,
@@ -32,11 +33,40 @@ where
     disco
         .for_each(|(peer, addrs)| {
             let state = state.clone();
-            async move { io::discovered(state, peer, addrs).await }
+            async move {
+                state.health.watch.discovered(&peer, &addrs);
+                state.address_book.seen(peer, addrs.iter().copied());
+                io::discovered(state, peer, addrs).await
+            }
         })
         .await
 }
 
+/// Connect to the peers recalled from the [`AddressBook`].
+#[tracing::instrument(skip(state, known))]
+pub(super) async fn known<S, G>(state: State<S, G>, known: Vec<(PeerId, Vec<SocketAddr>)>)
+where
+    S: ProtocolStorage<SocketAddr, Update = gossip::Payload> + 'static,
+    G: RequestPullGuard,
+{
+    stream::iter(known)
+        .for_each_concurrent(None, |(peer, addrs)| {
+            io::discovered(state.clone(), peer, addrs)
+        })
+        .await
+}
+
+/// Write back the [`AddressBook`] every `interval`.
+#[tracing::instrument(skip(address_book))]
+pub(super) async fn flush_address_book(address_book: AddressBook, interval: Duration) {
+    loop {
+        link_async::sleep(interval).await;
+        if let Err(e) = address_book.flush() {
+            tracing::warn!(err = ?e, "failed to write address book");
+        }
+    }
+}
+
 #[tracing::instrument(skip(state, tasks))]
 pub(super) async fn periodic<S, G, P>(state: State<S, G>, tasks: P)
 where
@@ -55,7 +85,10 @@ where
                             to: info,
                             message: state
                                 .membership
-                                .hello(io::peer_advertisement(&state.endpoint)())
+                                .hello(io::peer_advertisement(
+                                    &state.endpoint,
+                                    &state.config.capabilities,
+                                )())
                                 .into(),
                         })
                         .collect::<Vec<_>>(),
@@ -73,7 +106,10 @@ where
                     message: membership::Message::Shuffle {
                         origin: PeerInfo {
                             peer_id: state.local_id,
-                            advertised_info: io::peer_advertisement(&state.endpoint)(),
+                            advertised_info: io::peer_advertisement(
+                                &state.endpoint,
+                                &state.config.capabilities,
+                            )(),
                             seen_addrs: iter::empty().into(),
                         },
                         peers: sample,
@@ -134,7 +170,7 @@ where
             },
 
             Ok(evt) => match evt {
-                Downstream::Gossip(x) => control::gossip(&state, x, None).await,
+                Downstream::Gossip(x) => control::batched(&state, x).await,
                 Downstream::Info(x) => control::info(&state, x),
                 Downstream::Interrogation(x) => control::interrogation(x).await,
                 Downstream::RequestPull(x) => control::request_pull(x).await,
@@ -0,0 +1,363 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Persistent record of the addresses of other peers.
+//!
+//! Addresses learned while running -- via discovery, or by connecting to a
+//! peer -- are otherwise forgotten on restart, so a peer would have to learn
+//! them anew from its bootstrap nodes. The [`AddressBook`] records the last
+//! known addresses of each peer, when they were last seen, and how often
+//! connecting to them succeeded. It is stored at [`Paths::address_book_file`].
+//!
+//! The connection layer adds the known addresses of a peer to the address
+//! hints it is given, and tries the most reliable ones first. On startup, the
+//! most recently seen peers are used to join the network before discovery
+//! yields any.
+//!
+//! The book is held in memory, and written back periodically by the protocol
+//! stack, see [`AddressBook::flush`].
+
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    fs,
+    io,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std_ext::time::unix_now;
+use thiserror::Error;
+
+use crate::{paths::Paths, PeerId};
+
+/// Maximum number of addresses retained per peer.
+pub const MAX_ADDRS_PER_PEER: usize = 8;
+/// Maximum number of peers retained.
+pub const MAX_PEERS: usize = 4096;
+/// Addresses not seen for this long are forgotten.
+pub const EXPIRY: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// Interval at which the protocol stack writes back the address book.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("failed to read the address book at `{path}`")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("failed to write the address book at `{path}`")]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("malformed address book at `{path}`")]
+    Malformed {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// What is known about an address of a peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Address {
+    pub addr: SocketAddr,
+    /// When the address was last learned or connected to, in seconds since
+    /// the epoch.
+    pub last_seen: u64,
+    /// Number of successful connection attempts.
+    pub successes: u32,
+    /// Number of failed connection attempts.
+    pub failures: u32,
+}
+
+impl Address {
+    /// The estimated probability of connecting successfully.
+    ///
+    /// Addresses without any attempts score `0.5`.
+    pub fn score(&self) -> f64 {
+        (f64::from(self.successes) + 1.0) / (f64::from(self.successes + self.failures) + 2.0)
+    }
+
+    fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            last_seen: unix_now(),
+            successes: 0,
+            failures: 0,
+        }
+    }
+
+    /// Best first: highest score, then most recently seen.
+    fn rank(&self, other: &Self) -> Ordering {
+        other
+            .score()
+            .partial_cmp(&self.score())
+            .unwrap_or(Ordering::Equal)
+            .then(other.last_seen.cmp(&self.last_seen))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    peer: PeerId,
+    addrs: Vec<Address>,
+}
+
+#[derive(Default)]
+struct Inner {
+    peers: BTreeMap<PeerId, Vec<Address>>,
+    dirty: bool,
+}
+
+/// The last known addresses of other peers.
+///
+/// Cloning yields a handle to the same address book.
+#[derive(Clone, Default)]
+pub struct AddressBook {
+    file: Option<PathBuf>,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl AddressBook {
+    /// Load the address book from [`Paths::address_book_file`].
+    ///
+    /// If the file does not exist yet, the address book is empty. Expired
+    /// addresses are dropped.
+    pub fn open(paths: &Paths) -> Result<Self, Error> {
+        let file = paths.address_book_file().to_path_buf();
+        let entries: Vec<Entry> = match fs::read(&file) {
+            Ok(json) => serde_json::from_slice(&json).map_err(|source| Error::Malformed {
+                path: file.clone(),
+                source,
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(source) => return Err(Error::Read { path: file, source }),
+        };
+
+        let cutoff = unix_now().saturating_sub(EXPIRY.as_secs());
+        let peers = entries
+            .into_iter()
+            .filter_map(|Entry { peer, mut addrs }| {
+                addrs.retain(|addr| addr.last_seen >= cutoff);
+                (!addrs.is_empty()).then(|| (peer, addrs))
+            })
+            .collect();
+
+        Ok(Self {
+            file: Some(file),
+            inner: Arc::new(Mutex::new(Inner {
+                peers,
+                dirty: false,
+            })),
+        })
+    }
+
+    /// An address book which is never persisted.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Write the address book back to disk, if it changed since it was last
+    /// written.
+    pub fn flush(&self) -> Result<(), Error> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let entries = {
+            let mut inner = self.inner.lock();
+            if !inner.dirty {
+                return Ok(());
+            }
+            inner.dirty = false;
+            inner
+                .peers
+                .iter()
+                .map(|(peer, addrs)| Entry {
+                    peer: *peer,
+                    addrs: addrs.clone(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let write_err = |source| Error::Write {
+            path: file.clone(),
+            source,
+        };
+        let json = serde_json::to_vec(&entries).map_err(|source| Error::Malformed {
+            path: file.clone(),
+            source,
+        })?;
+        let tmp = file.with_extension("json.tmp");
+        fs::write(&tmp, json)
+            .and_then(|()| fs::rename(&tmp, file))
+            .map_err(|source| {
+                // Try again next time
+                self.inner.lock().dirty = true;
+                write_err(source)
+            })
+    }
+
+    /// The known addresses of `peer`, best first.
+    pub fn get(&self, peer: &PeerId) -> Vec<Address> {
+        let mut addrs = self
+            .inner
+            .lock()
+            .peers
+            .get(peer)
+            .cloned()
+            .unwrap_or_default();
+        addrs.sort_by(Address::rank);
+        addrs
+    }
+
+    /// Up to `limit` peers, most recently seen first, along with their
+    /// addresses, best first.
+    pub fn recent(&self, limit: usize) -> Vec<(PeerId, Vec<SocketAddr>)> {
+        let inner = self.inner.lock();
+        let mut peers = inner
+            .peers
+            .iter()
+            .map(|(peer, addrs)| {
+                let last_seen = addrs.iter().map(|a| a.last_seen).max().unwrap_or_default();
+                let mut addrs = addrs.clone();
+                addrs.sort_by(Address::rank);
+                (
+                    last_seen,
+                    *peer,
+                    addrs.into_iter().map(|a| a.addr).collect(),
+                )
+            })
+            .collect::<Vec<_>>();
+        peers.sort_by(|(a, _, _), (b, _, _)| b.cmp(a));
+        peers
+            .into_iter()
+            .take(limit)
+            .map(|(_, peer, addrs)| (peer, addrs))
+            .collect()
+    }
+
+    /// Merge the known addresses of `peer` into `hints`, and order them for
+    /// connection attempts.
+    ///
+    /// Known addresses are ordered by [`Address::score`], unknown ones score
+    /// like addresses without any attempts. The order of `hints` is preserved
+    /// among equals.
+    pub fn order(&self, peer: &PeerId, hints: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        let known = self.get(peer);
+        let mut addrs = hints
+            .into_iter()
+            .map(|addr| {
+                known
+                    .iter()
+                    .find(|a| a.addr == addr)
+                    .copied()
+                    .unwrap_or_else(|| Address::new(addr))
+            })
+            .collect::<Vec<_>>();
+        for addr in known {
+            if !addrs.iter().any(|a| a.addr == addr.addr) {
+                addrs.push(addr)
+            }
+        }
+        addrs.sort_by(|a, b| b.score().partial_cmp(&a.score()).unwrap_or(Ordering::Equal));
+        addrs.into_iter().map(|a| a.addr).collect()
+    }
+
+    /// Record that `peer` is reachable at `addrs`, as far as we know.
+    pub fn seen<I>(&self, peer: PeerId, addrs: I)
+    where
+        I: IntoIterator<Item = SocketAddr>,
+    {
+        let mut inner = self.inner.lock();
+        for addr in addrs {
+            inner.update(peer, addr, |_| {});
+        }
+    }
+
+    /// Record that connecting to `addr` of `peer` succeeded.
+    pub fn success(&self, peer: PeerId, addr: SocketAddr) {
+        self.inner
+            .lock()
+            .update(peer, addr, |a| a.successes = a.successes.saturating_add(1))
+    }
+
+    /// Record that connecting to `addr` of `peer` failed.
+    pub fn failure(&self, peer: PeerId, addr: SocketAddr) {
+        self.inner
+            .lock()
+            .update(peer, addr, |a| a.failures = a.failures.saturating_add(1))
+    }
+
+    /// Forget all addresses of `peer`.
+    pub fn remove(&self, peer: &PeerId) -> bool {
+        let mut inner = self.inner.lock();
+        let removed = inner.peers.remove(peer).is_some();
+        inner.dirty |= removed;
+        removed
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Inner {
+    fn update<F>(&mut self, peer: PeerId, addr: SocketAddr, f: F)
+    where
+        F: FnOnce(&mut Address),
+    {
+        if !self.peers.contains_key(&peer) && self.peers.len() >= MAX_PEERS {
+            self.evict_peer();
+        }
+        let addrs = self.peers.entry(peer).or_default();
+        match addrs.iter_mut().find(|a| a.addr == addr) {
+            Some(known) => {
+                known.last_seen = unix_now();
+                f(known)
+            },
+            None => {
+                if addrs.len() >= MAX_ADDRS_PER_PEER {
+                    addrs.sort_by(Address::rank);
+                    addrs.pop();
+                }
+                let mut new = Address::new(addr);
+                f(&mut new);
+                addrs.push(new)
+            },
+        }
+        self.dirty = true;
+    }
+
+    /// Evict the peer which was seen least recently.
+    fn evict_peer(&mut self) {
+        let oldest = self
+            .peers
+            .iter()
+            .min_by_key(|(_, addrs)| addrs.iter().map(|a| a.last_seen).max())
+            .map(|(peer, _)| *peer);
+        if let Some(oldest) = oldest {
+            self.peers.remove(&oldest);
+        }
+    }
+}
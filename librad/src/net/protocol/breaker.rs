@@ -0,0 +1,282 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Circuit breakers for outbound connection attempts.
+//!
+//! Every address of a peer we attempt to dial has a breaker attached. After
+//! [`Quota::max_failures`] consecutive failures, the breaker "opens", and
+//! further dials to that address are refused without touching the network.
+//! Once [`Quota::backoff`] has elapsed, a single probe is let through
+//! ("half-open"): if it succeeds, the breaker closes again, otherwise the
+//! backoff is doubled (up to [`Quota::max_backoff`]).
+//!
+//! Dials are permitted by handing out a [`Permit`], through which the outcome
+//! is reported. Should a probe be dropped without reporting an outcome (eg.
+//! because a connection to another address was established first), the
+//! breaker is open again, and permits another probe right away.
+//!
+//! Only addresses which failed recently have a breaker. Breakers without
+//! failures for [`Quota::expiry`] are forgotten, and at most
+//! [`Quota::max_breakers`] are kept, evicting the least recently failed.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    num::NonZeroU32,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use nonzero_ext::nonzero;
+use parking_lot::Mutex;
+
+use crate::PeerId;
+
+/// Circuit breaker quota.
+#[derive(Clone, Debug)]
+pub struct Quota {
+    /// Consecutive failures after which the breaker opens.
+    ///
+    /// Default: 3
+    pub max_failures: NonZeroU32,
+    /// Time after which an open breaker permits a probe.
+    ///
+    /// Default: 5s
+    pub backoff: Duration,
+    /// Upper bound of the backoff, which is doubled on every failed probe.
+    ///
+    /// Default: 10min
+    pub max_backoff: Duration,
+    /// Time after the last failure after which a breaker is forgotten.
+    ///
+    /// Default: 1h
+    pub expiry: Duration,
+    /// Maximum number of breakers to keep.
+    ///
+    /// Default: 4096
+    pub max_breakers: usize,
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Self {
+            max_failures: nonzero!(3u32),
+            backoff: Duration::from_secs(5),
+            max_backoff: Duration::from_secs(600),
+            expiry: Duration::from_secs(3600),
+            max_breakers: 4096,
+        }
+    }
+}
+
+/// Circuit breaker status.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    /// Addresses which are currently refused.
+    pub open: usize,
+    /// Addresses for which a probe is in flight.
+    pub half_open: usize,
+    /// Dials refused because of an open breaker, cumulative since startup.
+    pub refused: u64,
+}
+
+struct Open {
+    until: Instant,
+    backoff: Duration,
+    probing: bool,
+}
+
+struct Breaker {
+    failures: u32,
+    last_failure: Instant,
+    open: Option<Open>,
+}
+
+type Key = (PeerId, SocketAddr);
+
+#[derive(Clone)]
+pub struct Breakers {
+    quota: Quota,
+    breakers: Arc<Mutex<HashMap<Key, Breaker>>>,
+    refused: Arc<AtomicU64>,
+}
+
+/// Permission to dial an address, obtained from [`Breakers::permit`].
+///
+/// The outcome of the dial must be reported via [`Permit::success`] or
+/// [`Permit::failure`]. Dropping the permit instead means the dial was
+/// cancelled.
+#[must_use = "the outcome of the dial must be reported"]
+pub struct Permit {
+    breakers: Breakers,
+    key: Key,
+    probe: bool,
+}
+
+impl Permit {
+    /// Whether this permit is the probe of an open breaker.
+    pub fn is_probe(&self) -> bool {
+        self.probe
+    }
+
+    /// Record a successful dial, closing the breaker.
+    pub fn success(mut self) {
+        self.probe = false;
+        self.breakers.breakers.lock().remove(&self.key);
+    }
+
+    /// Record a failed dial.
+    pub fn failure(mut self) {
+        self.probe = false;
+        self.breakers.failure(self.key);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if self.probe {
+            self.breakers.cancelled(self.key)
+        }
+    }
+}
+
+impl Breakers {
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            breakers: Default::default(),
+            refused: Default::default(),
+        }
+    }
+
+    /// Determine if `addr` of `peer` may be dialed.
+    ///
+    /// If the breaker is open and the backoff has elapsed, the returned
+    /// [`Permit`] is a probe: no other probe is permitted until its outcome
+    /// is reported, or it is dropped.
+    pub fn permit(&self, peer: PeerId, addr: SocketAddr) -> Option<Permit> {
+        let key = (peer, addr);
+        let mut breakers = self.breakers.lock();
+        let probe = match breakers.get_mut(&key) {
+            Some(Breaker {
+                open: Some(open), ..
+            }) => {
+                let now = Instant::now();
+                if open.probing || now < open.until {
+                    self.refused.fetch_add(1, Ordering::Relaxed);
+                    return None;
+                }
+                open.probing = true;
+                true
+            },
+            _ => false,
+        };
+
+        Some(Permit {
+            breakers: self.clone(),
+            key,
+            probe,
+        })
+    }
+
+    fn failure(&self, key: Key) {
+        let (peer, addr) = key;
+        let mut breakers = self.breakers.lock();
+        let now = Instant::now();
+        if !breakers.contains_key(&key) {
+            self.evict(&mut breakers, now);
+        }
+        let breaker = breakers.entry(key).or_insert_with(|| Breaker {
+            failures: 0,
+            last_failure: now,
+            open: None,
+        });
+        breaker.failures = breaker.failures.saturating_add(1);
+        breaker.last_failure = now;
+        match breaker.open.as_mut() {
+            Some(open) if open.probing => {
+                open.backoff = (open.backoff * 2).min(self.quota.max_backoff);
+                open.until = now + open.backoff;
+                open.probing = false;
+                tracing::debug!(
+                    remote_id = %peer,
+                    remote_addr = %addr,
+                    backoff = ?open.backoff,
+                    "probe failed"
+                );
+            },
+            Some(_) => {},
+            None if breaker.failures >= self.quota.max_failures.get() => {
+                tracing::info!(remote_id = %peer, remote_addr = %addr, "opening circuit breaker");
+                breaker.open = Some(Open {
+                    until: now + self.quota.backoff,
+                    backoff: self.quota.backoff,
+                    probing: false,
+                });
+            },
+            None => {},
+        }
+    }
+
+    /// A probe was dropped without an outcome: permit the next one right away,
+    /// without changing the backoff.
+    fn cancelled(&self, key: Key) {
+        if let Some(open) = self
+            .breakers
+            .lock()
+            .get_mut(&key)
+            .and_then(|breaker| breaker.open.as_mut())
+        {
+            open.probing = false;
+        }
+    }
+
+    /// Make room for another breaker.
+    fn evict(&self, breakers: &mut HashMap<Key, Breaker>, now: Instant) {
+        if breakers.len() < self.quota.max_breakers {
+            return;
+        }
+        breakers.retain(|_, breaker| now.duration_since(breaker.last_failure) < self.quota.expiry);
+        while breakers.len() >= self.quota.max_breakers.max(1) {
+            let oldest = breakers
+                .iter()
+                .min_by_key(|(_, breaker)| breaker.last_failure)
+                .map(|(key, _)| *key);
+            match oldest {
+                Some(key) => breakers.remove(&key),
+                None => break,
+            };
+        }
+    }
+
+    /// The number of breakers currently kept.
+    pub fn len(&self) -> usize {
+        self.breakers.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            refused: self.refused.load(Ordering::Relaxed),
+            ..Stats::default()
+        };
+        let breakers = self.breakers.lock();
+        for open in breakers.values().filter_map(|b| b.open.as_ref()) {
+            if open.probing {
+                stats.half_open += 1
+            } else {
+                stats.open += 1
+            }
+        }
+
+        stats
+    }
+}
@@ -390,3 +390,37 @@ where
         },
     }
 }
+
+#[cfg(feature = "fuzzing")]
+mod fuzzing {
+    use arbitrary::{Arbitrary, Result, Unstructured};
+
+    use super::{Ext, Message};
+
+    impl<'a, A, P> Arbitrary<'a> for Message<A, P>
+    where
+        A: Arbitrary<'a>,
+        P: Arbitrary<'a>,
+    {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            let origin = u.arbitrary()?;
+            let val = u.arbitrary()?;
+            let ext = u.arbitrary()?;
+            Ok(if u.arbitrary()? {
+                Self::Have { origin, val, ext }
+            } else {
+                Self::Want { origin, val, ext }
+            })
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for Ext {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            Ok(Self {
+                seqno: u.arbitrary()?,
+                hop: u.arbitrary()?,
+                sig: u.arbitrary()?,
+            })
+        }
+    }
+}
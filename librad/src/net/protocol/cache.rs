@@ -10,7 +10,7 @@ use std::{
     time::{Duration, Instant},
 };
 
-use parking_lot::{RwLock, RwLockReadGuard};
+use parking_lot::{Mutex, RwLock, RwLockReadGuard};
 use thiserror::Error;
 
 use crate::{
@@ -24,6 +24,9 @@ use crate::{
 #[derive(Clone)]
 pub struct Caches {
     pub urns: urns::Filter,
+    pub seen: seen::Recent,
+    pub provided: provided::Provided,
+    pub misses: negative::Misses,
 }
 
 pub mod urns {
@@ -217,3 +220,428 @@ pub mod urns {
         identities::any::xor_filter(&storage).map(|res| (FilterInner::from(res), start.elapsed()))
     }
 }
+
+/// URNs recently observed in gossip.
+///
+/// Keeps track of the URNs mentioned in gossip messages received within a
+/// sliding [`seen::Recent::window`], along with the peers which originated the
+/// messages. Memory usage is bounded by the maximum number of URNs and the
+/// maximum number of origins per URN retained.
+pub mod seen {
+    use std::time::SystemTime;
+
+    use indexmap::IndexMap;
+
+    use super::*;
+    use crate::{git::Urn, PeerId};
+
+    /// Default [`Recent::window`]: one hour.
+    pub const DEFAULT_WINDOW: Duration = Duration::from_secs(60 * 60);
+    /// Default maximum number of URNs retained.
+    pub const DEFAULT_CAPACITY: usize = 1024;
+    /// Maximum number of origins retained per URN.
+    pub const MAX_ORIGINS: usize = 16;
+
+    /// The kind of gossip message a URN was observed in.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Kind {
+        Have,
+        Want,
+    }
+
+    /// A peer which originated a gossip message about a [`Seen::urn`].
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct Origin {
+        pub peer: PeerId,
+        pub kind: Kind,
+        pub seen_at: SystemTime,
+    }
+
+    /// A URN observed in gossip.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct Seen {
+        /// The URN, without path.
+        pub urn: Urn,
+        pub last_seen: SystemTime,
+        /// The most recent origins, most recent first.
+        pub origins: Vec<Origin>,
+    }
+
+    #[derive(Clone)]
+    pub struct Recent {
+        inner: Arc<Mutex<RecentInner>>,
+    }
+
+    struct RecentInner {
+        window: Duration,
+        capacity: usize,
+        /// Ordered by `last_seen`, oldest first.
+        urns: IndexMap<Urn, Entry>,
+    }
+
+    struct Entry {
+        last_seen: Instant,
+        /// Ordered by the `Instant`, oldest first.
+        origins: IndexMap<PeerId, (Kind, Instant)>,
+    }
+
+    impl Default for Recent {
+        fn default() -> Self {
+            Self::new(DEFAULT_WINDOW, DEFAULT_CAPACITY)
+        }
+    }
+
+    impl Recent {
+        /// Retain URNs seen within `window`, but no more than `capacity` URNs.
+        pub fn new(window: Duration, capacity: usize) -> Self {
+            Self {
+                inner: Arc::new(Mutex::new(RecentInner {
+                    window,
+                    capacity,
+                    urns: IndexMap::new(),
+                })),
+            }
+        }
+
+        pub fn window(&self) -> Duration {
+            self.inner.lock().window
+        }
+
+        /// Record that `urn` was mentioned in a gossip message originated by
+        /// `origin`.
+        pub fn record(&self, urn: Urn, origin: PeerId, kind: Kind) {
+            let now = Instant::now();
+            let urn = urn.with_path(None);
+            let mut inner = self.inner.lock();
+            inner.expire(now);
+
+            let mut entry = inner.urns.shift_remove(&urn).unwrap_or_else(|| Entry {
+                last_seen: now,
+                origins: IndexMap::new(),
+            });
+            entry.last_seen = now;
+            entry.origins.shift_remove(&origin);
+            entry.origins.insert(origin, (kind, now));
+            if entry.origins.len() > MAX_ORIGINS {
+                evict_oldest(&mut entry.origins);
+            }
+            inner.urns.insert(urn, entry);
+            while inner.urns.len() > inner.capacity {
+                evict_oldest(&mut inner.urns);
+            }
+        }
+
+        /// The URNs seen within the window, most recently seen first.
+        pub fn get(&self) -> Vec<Seen> {
+            let now = Instant::now();
+            let wall = SystemTime::now();
+            let at = |instant: Instant| wall - now.saturating_duration_since(instant);
+
+            let mut inner = self.inner.lock();
+            inner.expire(now);
+            inner
+                .urns
+                .iter()
+                .rev()
+                .map(|(urn, entry)| Seen {
+                    urn: urn.clone(),
+                    last_seen: at(entry.last_seen),
+                    origins: entry
+                        .origins
+                        .iter()
+                        .rev()
+                        .map(|(peer, (kind, seen_at))| Origin {
+                            peer: *peer,
+                            kind: *kind,
+                            seen_at: at(*seen_at),
+                        })
+                        .collect(),
+                })
+                .collect()
+        }
+
+        /// The number of URNs currently retained.
+        pub fn len(&self) -> usize {
+            self.inner.lock().urns.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+    }
+
+    impl RecentInner {
+        fn expire(&mut self, now: Instant) {
+            while let Some(entry) = self.urns.values().next() {
+                if now.saturating_duration_since(entry.last_seen) <= self.window {
+                    break;
+                }
+                evict_oldest(&mut self.urns);
+            }
+        }
+    }
+
+    fn evict_oldest<K, V>(map: &mut IndexMap<K, V>)
+    where
+        K: std::hash::Hash + Eq + Clone,
+    {
+        if let Some(k) = map.keys().next().cloned() {
+            map.shift_remove(&k);
+        }
+    }
+}
+
+/// Gossip payloads which were recently provided, by us or to us.
+///
+/// The broadcast layer only deduplicates messages it has seen since the
+/// process started. To avoid re-fetching and re-announcing everything after a
+/// restart, payloads which were successfully applied or announced are also
+/// recorded in a persistent [`crate::cache::Cache`], and considered known until
+/// their [`Provided::ttl`] expires. The size of the cache is bounded, evicting
+/// the least recently recorded payloads first.
+pub mod provided {
+    use std::path::Path;
+
+    use std_ext::time::unix_now;
+
+    use super::*;
+    use crate::{cache::Cache, net::protocol::gossip, paths::Paths};
+
+    /// Name of the cache under [`Paths::caches_dir`].
+    pub const NAME: &str = "gossip-provided";
+    /// Bump when the encoding of [`gossip::Payload`] changes incompatibly.
+    pub const VERSION: u32 = 1;
+    /// Default [`Provided::ttl`]: one hour.
+    pub const DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+    /// Default size bound of the cache.
+    pub const DEFAULT_MAX_BYTES: u64 = 1024 * 1024;
+
+    #[derive(Clone)]
+    pub struct Provided {
+        cache: Option<Cache<u64>>,
+        ttl: Duration,
+    }
+
+    impl Default for Provided {
+        /// A cache which doesn't record anything.
+        fn default() -> Self {
+            Self {
+                cache: None,
+                ttl: DEFAULT_TTL,
+            }
+        }
+    }
+
+    impl Provided {
+        pub fn open(
+            paths: &Paths,
+            ttl: Duration,
+            max_bytes: u64,
+        ) -> Result<Self, crate::cache::Error> {
+            Self::open_in(paths.caches_dir(), ttl, max_bytes)
+        }
+
+        /// Like [`Provided::open`], but rooted at `root` instead of
+        /// [`Paths::caches_dir`].
+        pub fn open_in(
+            root: impl AsRef<Path>,
+            ttl: Duration,
+            max_bytes: u64,
+        ) -> Result<Self, crate::cache::Error> {
+            Ok(Self {
+                cache: Some(Cache::open_in(root, NAME, VERSION, max_bytes)?),
+                ttl,
+            })
+        }
+
+        pub fn ttl(&self) -> Duration {
+            self.ttl
+        }
+
+        /// Whether `payload` was [`Provided::record`]ed within the
+        /// [`Provided::ttl`]. The resolution is one second.
+        ///
+        /// Errors accessing the cache are logged, and yield `false`.
+        pub fn contains(&self, payload: &gossip::Payload) -> bool {
+            let cache = match &self.cache {
+                None => return false,
+                Some(cache) => cache,
+            };
+            let key = key(payload);
+            match cache.get(&key) {
+                Ok(Some(at)) if unix_now().saturating_sub(at) < self.ttl.as_secs() => true,
+                Ok(Some(_)) => {
+                    if let Err(e) = cache.remove(&key) {
+                        tracing::warn!(err = %e, "failed to remove expired gossip cache entry");
+                    }
+                    false
+                },
+                Ok(None) => false,
+                Err(e) => {
+                    tracing::warn!(err = %e, "failed to read gossip cache");
+                    false
+                },
+            }
+        }
+
+        /// Record that `payload` was provided just now.
+        ///
+        /// Errors accessing the cache are logged.
+        pub fn record(&self, payload: &gossip::Payload) {
+            if let Some(cache) = &self.cache {
+                if let Err(e) = cache.put(key(payload), &unix_now()) {
+                    tracing::warn!(err = %e, "failed to write gossip cache");
+                }
+            }
+        }
+    }
+
+    fn key(payload: &gossip::Payload) -> Vec<u8> {
+        minicbor::to_vec(payload).expect("encoding to a `Vec` is infallible")
+    }
+}
+
+/// Lookups which recently came up empty.
+///
+/// Asking for a URN we don't have, or connecting to a peer which never
+/// answers, tends to be repeated, and is expensive every single time. The
+/// negative outcome is thus remembered for a short [`Negative::ttl`], during
+/// which the lookup is skipped. Since a gossip `Have` for a URN is a good
+/// indication that lookups would now succeed, the relevant entries are busted
+/// when one arrives (see [`Misses::bust`]).
+pub mod negative {
+    use std::hash::Hash;
+
+    use indexmap::IndexMap;
+
+    use super::*;
+    use crate::{git::Urn, PeerId};
+
+    /// Default [`Negative::ttl`]: 30 seconds.
+    pub const DEFAULT_TTL: Duration = Duration::from_secs(30);
+    /// Default maximum number of entries retained per cache.
+    pub const DEFAULT_CAPACITY: usize = 4096;
+
+    /// A set of keys which expire after the [`Negative::ttl`].
+    #[derive(Clone)]
+    pub struct Negative<K> {
+        inner: Arc<Mutex<NegativeInner<K>>>,
+    }
+
+    struct NegativeInner<K> {
+        ttl: Duration,
+        capacity: usize,
+        /// Ordered by insertion time, oldest first.
+        entries: IndexMap<K, Instant>,
+    }
+
+    impl<K: Hash + Eq + Clone> Default for Negative<K> {
+        fn default() -> Self {
+            Self::new(DEFAULT_TTL, DEFAULT_CAPACITY)
+        }
+    }
+
+    impl<K: Hash + Eq + Clone> Negative<K> {
+        /// Retain keys for `ttl`, but no more than `capacity` keys.
+        pub fn new(ttl: Duration, capacity: usize) -> Self {
+            Self {
+                inner: Arc::new(Mutex::new(NegativeInner {
+                    ttl,
+                    capacity,
+                    entries: IndexMap::new(),
+                })),
+            }
+        }
+
+        pub fn ttl(&self) -> Duration {
+            self.inner.lock().ttl
+        }
+
+        /// Record that the lookup of `key` came up empty just now.
+        pub fn insert(&self, key: K) {
+            let now = Instant::now();
+            let mut inner = self.inner.lock();
+            inner.expire(now);
+            inner.entries.shift_remove(&key);
+            inner.entries.insert(key, now);
+            while inner.entries.len() > inner.capacity {
+                inner.entries.shift_remove_index(0);
+            }
+        }
+
+        /// Whether the lookup of `key` came up empty within the
+        /// [`Negative::ttl`].
+        pub fn contains(&self, key: &K) -> bool {
+            let mut inner = self.inner.lock();
+            inner.expire(Instant::now());
+            inner.entries.contains_key(key)
+        }
+
+        pub fn remove(&self, key: &K) {
+            self.inner.lock().entries.shift_remove(key);
+        }
+
+        /// Retain only the keys for which `f` returns `true`.
+        pub fn retain<F>(&self, f: F)
+        where
+            F: Fn(&K) -> bool,
+        {
+            self.inner.lock().entries.retain(|k, _| f(k))
+        }
+
+        /// The number of keys currently retained.
+        pub fn len(&self) -> usize {
+            let mut inner = self.inner.lock();
+            inner.expire(Instant::now());
+            inner.entries.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+    }
+
+    impl<K: Hash + Eq> NegativeInner<K> {
+        fn expire(&mut self, now: Instant) {
+            while let Some(inserted) = self.entries.values().next() {
+                if now.saturating_duration_since(*inserted) < self.ttl {
+                    break;
+                }
+                self.entries.shift_remove_index(0);
+            }
+        }
+    }
+
+    /// The negative caches of a peer.
+    ///
+    /// URNs are stored without path.
+    #[derive(Clone, Default)]
+    pub struct Misses {
+        /// URNs for which no provider responded to a query.
+        pub providers: Negative<Urn>,
+        /// URNs a remote peer reported not to have.
+        pub remote: Negative<(PeerId, Urn)>,
+        /// Peers which could not be connected to.
+        pub peers: Negative<PeerId>,
+    }
+
+    impl Misses {
+        /// Use `ttl` and `capacity` for all caches.
+        pub fn new(ttl: Duration, capacity: usize) -> Self {
+            Self {
+                providers: Negative::new(ttl, capacity),
+                remote: Negative::new(ttl, capacity),
+                peers: Negative::new(ttl, capacity),
+            }
+        }
+
+        /// Forget all misses concerning `urn`, as well as `provider` being
+        /// unreachable, because `provider` just announced that it has `urn`.
+        pub fn bust(&self, urn: &Urn, provider: PeerId) {
+            let urn = urn.clone().with_path(None);
+            self.providers.remove(&urn);
+            self.remote.retain(|(_, x)| x != &urn);
+            self.peers.remove(&provider);
+        }
+    }
+}
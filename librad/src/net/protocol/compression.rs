@@ -0,0 +1,95 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Compression of gossip and membership messages.
+//!
+//! Peers able to decompress messages advertise [`Capability::Zstd`] in their
+//! [`super::PeerAdvertisement`], which peers predating compression ignore. When
+//! sending to such a peer, messages whose
+//! encoding is at least [`Config::threshold`] bytes long are compressed using
+//! [zstd], provided that this actually saves space.
+//!
+//! A compressed message is framed as a CBOR byte string of the compressed
+//! encoding, tagged with [`TAG`]. Receivers always accept compressed messages,
+//! regardless of their own [`Config`], so the framing can be detected
+//! unambiguously per message: plain messages are never tagged at the top
+//! level.
+//!
+//! [zstd]: https://facebook.github.io/zstd/
+
+use std::{collections::BTreeSet, io};
+
+use super::info::Capability;
+
+/// CBOR tag marking a zstd-compressed message.
+///
+/// From the "first come first served" range of the [IANA registry], spelling
+/// `zstd` in ASCII.
+///
+/// [IANA registry]: https://www.iana.org/assignments/cbor-tags/cbor-tags.xhtml
+pub const TAG: u64 = 0x7a73_7464;
+
+/// Maximum size of a decompressed message.
+///
+/// Protects against decompression bombs. Gossip and membership messages are
+/// orders of magnitude smaller.
+pub const MAX_DECOMPRESSED: usize = 1024 * 1024;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// Whether to advertise [`Capability::Zstd`], and compress messages sent
+    /// to peers advertising it.
+    ///
+    /// Default: `true`
+    pub enabled: bool,
+    /// Minimum length in bytes of the encoding of a message to compress it.
+    ///
+    /// Default: 256
+    pub threshold: usize,
+    /// The zstd compression level.
+    ///
+    /// Default: 3
+    pub level: i32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 256,
+            level: 3,
+        }
+    }
+}
+
+impl Config {
+    /// The [`Capability`]s to advertise.
+    pub fn capabilities(&self) -> BTreeSet<Capability> {
+        if self.enabled {
+            BTreeSet::from([Capability::Zstd])
+        } else {
+            BTreeSet::new()
+        }
+    }
+
+    /// The compression to use with a peer advertising `remote` capabilities,
+    /// if any.
+    pub fn negotiate(&self, remote: &BTreeSet<Capability>) -> Option<Self> {
+        (self.enabled && remote.contains(&Capability::Zstd)).then(|| *self)
+    }
+
+    /// Compress `bytes` at [`Config::level`].
+    ///
+    /// Note that this does not check [`Config::threshold`].
+    pub fn compress(&self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        zstd::bulk::compress(bytes, self.level)
+    }
+}
+
+/// Decompress `bytes`, failing if the result would exceed
+/// [`MAX_DECOMPRESSED`].
+pub fn decompress(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    zstd::bulk::decompress(bytes, MAX_DECOMPRESSED)
+}
@@ -21,7 +21,10 @@ use super::{
     RequestPullGuard,
     State,
 };
-use crate::PeerId;
+use crate::{
+    net::connection::{RemoteAddr as _, RemotePeer as _},
+    PeerId,
+};
 
 pub(super) async fn gossip<S, G>(
     state: &State<S, G>,
@@ -35,7 +38,7 @@ pub(super) async fn gossip<S, G>(
 
     let origin = PeerInfo {
         peer_id: state.local_id,
-        advertised_info: io::peer_advertisement(&state.endpoint)(),
+        advertised_info: io::peer_advertisement(&state.endpoint, &state.config.capabilities)(),
         seen_addrs: iter::empty().into(),
     };
     // TODO: answer `Want`s from a provider cache
@@ -43,25 +46,59 @@ pub(super) async fn gossip<S, G>(
         Gossip::Announce(payload) => broadcast::Message::have(origin, payload),
         Gossip::Query(payload) => broadcast::Message::want(origin, payload),
     };
-    stream::iter(
-        state
-            .membership
-            .broadcast_recipients(exclude)
-            .into_iter()
-            .map(|to| tick::Tock::SendConnected {
-                to,
-                message: rpc.clone().into(),
-            }),
-    )
-    .for_each(|tock| tick::tock(state.clone(), tock))
-    .await
+    // Apply backpressure instead of dropping our own messages, but wait for
+    // all recipients at once, so a slow one doesn't delay the others.
+    stream::iter(state.membership.broadcast_recipients(exclude))
+        .for_each_concurrent(None, |to| {
+            let message = rpc.clone().into();
+            async move {
+                if !state.egress.ready(&to).await {
+                    tracing::debug!(peer = %to, "egress queue still full, enqueueing anyway");
+                }
+                tick::tock(state.clone(), tick::Tock::SendConnected { to, message }).await
+            }
+        })
+        .await
+}
+
+/// Like [`gossip`], but coalescing announcements as per [`gossip::batch`].
+pub(super) async fn batched<S, G>(state: &State<S, G>, evt: event::downstream::Gossip)
+where
+    S: ProtocolStorage<SocketAddr, Update = gossip::Payload> + 'static,
+    G: RequestPullGuard,
+{
+    use event::downstream::Gossip;
+    use gossip::batch::Push;
+
+    match evt {
+        Gossip::Announce(payload) => match state.announcements.push(payload) {
+            Push::Bypass(payload) => gossip(state, Gossip::Announce(payload), None).await,
+            Push::Joined => {},
+            Push::Opened(key) => state.spawner.spawn(flush(state.clone(), key)).detach(),
+        },
+        query @ Gossip::Query(_) => gossip(state, query, None).await,
+    }
+}
+
+/// Broadcast the batch identified by `key` once its window has elapsed.
+async fn flush<S, G>(state: State<S, G>, key: gossip::batch::Key)
+where
+    S: ProtocolStorage<SocketAddr, Update = gossip::Payload> + 'static,
+    G: RequestPullGuard,
+{
+    use event::downstream::Gossip;
+
+    link_async::sleep(state.announcements.window()).await;
+    if let Some(payload) = state.announcements.take(&key) {
+        gossip(&state, Gossip::Announce(payload), None).await
+    }
 }
 
 pub(super) fn info<S, G>(state: &State<S, G>, evt: event::downstream::Info)
 where
     S: ProtocolStorage<SocketAddr, Update = gossip::Payload> + 'static,
 {
-    use event::downstream::{CacheStats, Info, MembershipInfo, Stats};
+    use event::downstream::{CacheStats, ConnectionInfo, Info, MembershipInfo, Stats};
 
     match evt {
         Info::ConnectedPeers(reply) => {
@@ -71,6 +108,23 @@ where
             }
         },
 
+        Info::Connections(reply) => {
+            let chan = reply.lock().take();
+            if let Some(tx) = chan {
+                let conns = state
+                    .endpoint
+                    .connections()
+                    .into_iter()
+                    .map(|conn| ConnectionInfo {
+                        peer: conn.remote_peer_id(),
+                        remote_addr: conn.remote_addr(),
+                        stats: conn.stats(),
+                    })
+                    .collect();
+                tx.send(conns).ok();
+            }
+        },
+
         Info::Membership(reply) => {
             let chan = reply.lock().take();
             if let Some(tx) = chan {
@@ -82,6 +136,13 @@ where
             }
         },
 
+        Info::MembershipSnapshot(reply) => {
+            let chan = reply.lock().take();
+            if let Some(tx) = chan {
+                tx.send(Some(state.membership.snapshot())).ok();
+            }
+        },
+
         Info::Stats(reply) => {
             let chan = reply.lock().take();
             if let Some(tx) = chan {
@@ -94,6 +155,9 @@ where
                     caches: CacheStats {
                         urns: state.caches.urns.stats(),
                     },
+                    egress: state.egress.stats(),
+                    breakers: state.breakers.stats(),
+                    throttle: state.limits.ingress.stats(),
                 })
                 .ok();
             }
@@ -0,0 +1,242 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Per-peer queues of outbound messages.
+//!
+//! Messages to connected peers are not sent directly, but put on a queue which
+//! is drained by a task per peer. Membership messages are never dropped, as
+//! losing them would leave the overlay in an inconsistent state. Gossip
+//! messages, however, are bounded per peer: if a peer can't keep up, messages
+//! are dropped according to the [`DropPolicy`].
+//!
+//! Producers of announcements which should not be dropped can wait for the
+//! queue to have capacity using [`Egress::ready`]. The wait is bounded by
+//! [`Quota::ready_timeout`], so a single stalled peer can't hold up a
+//! producer indefinitely.
+
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    net::SocketAddr,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use nonzero_ext::nonzero;
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+use super::{gossip, io::Rpc};
+use crate::PeerId;
+
+pub type Message = Rpc<SocketAddr, gossip::Payload>;
+
+/// Which gossip message to drop when a peer's queue is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Drop the oldest queued message to make room for the new one.
+    ///
+    /// Newer announcements are more likely to be relevant, so this is the
+    /// default.
+    DropOldest,
+    /// Drop the new message.
+    DropNewest,
+}
+
+impl Default for DropPolicy {
+    fn default() -> Self {
+        Self::DropOldest
+    }
+}
+
+/// Egress queue quota.
+#[derive(Clone, Debug)]
+pub struct Quota {
+    /// Maximum number of gossip messages to queue per peer.
+    ///
+    /// Default: 256
+    pub max_gossip: NonZeroUsize,
+    /// See [`DropPolicy`].
+    ///
+    /// Default: [`DropPolicy::DropOldest`]
+    pub drop_policy: DropPolicy,
+    /// How long [`Egress::ready`] waits for a peer's queue to have room.
+    ///
+    /// Default: 5s
+    pub ready_timeout: Duration,
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Self {
+            max_gossip: nonzero!(256usize),
+            drop_policy: DropPolicy::default(),
+            ready_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Egress queue counters, cumulative since startup.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    /// Messages currently queued, across all peers.
+    pub queued: u64,
+    /// Messages sent.
+    pub sent: u64,
+    /// Gossip messages dropped because a peer's queue was full.
+    pub dropped: u64,
+    /// Messages discarded because the connection to the peer was lost.
+    pub discarded: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    queued: AtomicU64,
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    discarded: AtomicU64,
+}
+
+#[derive(Default)]
+struct Queue {
+    membership: VecDeque<Message>,
+    gossip: VecDeque<Message>,
+}
+
+impl Queue {
+    fn len(&self) -> usize {
+        self.membership.len() + self.gossip.len()
+    }
+}
+
+#[derive(Clone)]
+pub struct Egress {
+    quota: Quota,
+    queues: Arc<Mutex<HashMap<PeerId, Queue>>>,
+    capacity: Arc<Notify>,
+    counters: Arc<Counters>,
+}
+
+impl Egress {
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            queues: Default::default(),
+            capacity: Arc::new(Notify::new()),
+            counters: Default::default(),
+        }
+    }
+
+    /// Enqueue `message` for sending to `to`.
+    ///
+    /// Returns `true` if there was no queue for `to` before, in which case
+    /// the caller must start draining it.
+    pub fn push(&self, to: PeerId, message: Message) -> bool {
+        let mut queues = self.queues.lock();
+        let (queue, fresh) = match queues.entry(to) {
+            Entry::Occupied(entry) => (entry.into_mut(), false),
+            Entry::Vacant(entry) => (entry.insert(Queue::default()), true),
+        };
+        match message {
+            Rpc::Membership(_) => queue.membership.push_back(message),
+            Rpc::Gossip(_) => {
+                if queue.gossip.len() >= self.quota.max_gossip.get() {
+                    self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                    match self.quota.drop_policy {
+                        DropPolicy::DropNewest => {
+                            tracing::debug!(peer = %to, "egress queue full, dropping message");
+                            return fresh;
+                        },
+                        DropPolicy::DropOldest => {
+                            tracing::debug!(peer = %to, "egress queue full, dropping oldest");
+                            queue.gossip.pop_front();
+                            self.counters.queued.fetch_sub(1, Ordering::Relaxed);
+                        },
+                    }
+                }
+                queue.gossip.push_back(message)
+            },
+        }
+        self.counters.queued.fetch_add(1, Ordering::Relaxed);
+
+        fresh
+    }
+
+    /// Take the next message to send to `to`.
+    ///
+    /// Membership messages are preferred over gossip. If the queue is empty,
+    /// it is removed and `None` is returned: the caller must stop draining.
+    pub fn pop(&self, to: &PeerId) -> Option<Message> {
+        let mut queues = self.queues.lock();
+        let queue = queues.get_mut(to)?;
+        let msg = queue
+            .membership
+            .pop_front()
+            .or_else(|| queue.gossip.pop_front());
+        if msg.is_some() {
+            self.counters.queued.fetch_sub(1, Ordering::Relaxed);
+        } else {
+            queues.remove(to);
+        }
+        self.capacity.notify_waiters();
+
+        msg
+    }
+
+    /// Record that a message was sent successfully.
+    pub fn sent(&self) {
+        self.counters.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Discard all messages queued for `to`, eg. because the connection was
+    /// lost.
+    pub fn discard(&self, to: &PeerId) {
+        if let Some(queue) = self.queues.lock().remove(to) {
+            let n = queue.len() as u64;
+            self.counters.queued.fetch_sub(n, Ordering::Relaxed);
+            self.counters.discarded.fetch_add(n, Ordering::Relaxed);
+            self.capacity.notify_waiters();
+        }
+    }
+
+    /// Wait until the gossip queue for `to` has room for another message.
+    ///
+    /// Returns `false` if there is still no room after
+    /// [`Quota::ready_timeout`]. Pushing anyway is subject to the
+    /// [`DropPolicy`].
+    pub async fn ready(&self, to: &PeerId) -> bool {
+        link_async::timeout(self.quota.ready_timeout, self.has_room(to))
+            .await
+            .is_ok()
+    }
+
+    async fn has_room(&self, to: &PeerId) {
+        loop {
+            let notified = self.capacity.notified();
+            let full = self
+                .queues
+                .lock()
+                .get(to)
+                .map(|queue| queue.gossip.len() >= self.quota.max_gossip.get())
+                .unwrap_or(false);
+            if !full {
+                break;
+            }
+            notified.await
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            queued: self.counters.queued.load(Ordering::Relaxed),
+            sent: self.counters.sent.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+            discarded: self.counters.discarded.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -7,8 +7,12 @@ use std::fmt::Debug;
 
 use thiserror::Error;
 
-use super::interrogation;
-use crate::{git::storage::pool::PoolError, net::quic, PeerId};
+use super::{address_book, interrogation};
+use crate::{
+    git::storage::pool::PoolError,
+    net::{quic, tcp},
+    PeerId,
+};
 
 mod internal;
 pub(super) use internal::*;
@@ -21,6 +25,12 @@ pub enum Bootstrap {
 
     #[error(transparent)]
     Quic(#[from] quic::Error),
+
+    #[error(transparent)]
+    Tcp(#[from] tcp::Error),
+
+    #[error(transparent)]
+    AddressBook(#[from] address_book::Error),
 }
 
 #[derive(Debug, Error)]
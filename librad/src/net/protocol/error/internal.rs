@@ -15,6 +15,7 @@ use crate::{
         codec::{CborCodecError, CborError},
         protocol::{membership, PeerInfo},
         quic,
+        tcp,
         upgrade,
     },
     PeerId,
@@ -83,6 +84,9 @@ pub enum Rpc<S: 'static> {
     #[error(transparent)]
     Quic(#[from] quic::Error),
 
+    #[error(transparent)]
+    Tcp(#[from] tcp::Error),
+
     #[error(transparent)]
     Cbor(#[from] CborError),
 
@@ -97,6 +101,7 @@ impl<S> Debug for Rpc<S> {
         match self {
             Self::Upgrade(x) => f.debug_tuple("Upgrade").field(x).finish(),
             Self::Quic(x) => f.debug_tuple("Quic").field(x).finish(),
+            Self::Tcp(x) => f.debug_tuple("Tcp").field(x).finish(),
             Self::Cbor(x) => f.debug_tuple("Cbor").field(x).finish(),
             Self::Io(x) => f.debug_tuple("Io").field(x).finish(),
         }
@@ -5,7 +5,19 @@
 
 use std::{collections::HashMap, net::SocketAddr};
 
-use super::{broadcast, cache, error, gossip, interrogation, membership, quic, request_pull};
+use super::{
+    breaker,
+    broadcast,
+    cache,
+    egress,
+    error,
+    gossip,
+    interrogation,
+    membership,
+    quic,
+    request_pull,
+    throttle,
+};
 use crate::PeerId;
 
 #[derive(Clone)]
@@ -46,10 +58,19 @@ pub mod downstream {
     #[derive(Clone)]
     pub enum Info {
         ConnectedPeers(Reply<Vec<PeerId>>),
+        Connections(Reply<Vec<ConnectionInfo>>),
         Membership(Reply<MembershipInfo>),
+        MembershipSnapshot(Reply<Option<membership::Snapshot<SocketAddr>>>),
         Stats(Reply<Stats>),
     }
 
+    #[derive(Clone, Debug)]
+    pub struct ConnectionInfo {
+        pub peer: PeerId,
+        pub remote_addr: SocketAddr,
+        pub stats: quic::ConnectionStats,
+    }
+
     #[derive(Clone, Debug, Default)]
     pub struct MembershipInfo {
         pub active: Vec<PeerId>,
@@ -63,6 +84,9 @@ pub mod downstream {
         pub membership_active: usize,
         pub membership_passive: usize,
         pub caches: CacheStats,
+        pub egress: egress::Stats,
+        pub breakers: breaker::Stats,
+        pub throttle: throttle::Stats,
     }
 
     #[derive(Clone, Copy, Debug, Default)]
@@ -100,6 +124,10 @@ pub enum Upstream {
     Gossip(Box<upstream::Gossip<SocketAddr, gossip::Payload>>),
     Membership(membership::Transition<SocketAddr>),
     Caches(upstream::Caches),
+    RequestPull(upstream::RequestPull),
+    Evicted(crate::net::replication::Evicted),
+    Lifecycle(upstream::Lifecycle),
+    Scheduled(crate::net::replication::scheduler::Event),
 }
 
 pub mod upstream {
@@ -167,6 +195,65 @@ pub mod upstream {
         }
     }
 
+    /// Triggered after serving a request-pull successfully.
+    #[derive(Clone, Debug)]
+    pub struct RequestPull {
+        /// The peer who made the request.
+        pub peer: PeerId,
+        pub urn: crate::git::Urn,
+        /// The refs which were updated by replicating from `peer`.
+        pub refs: Vec<request_pull::Ref>,
+    }
+
+    impl From<RequestPull> for Upstream {
+        fn from(r: RequestPull) -> Self {
+            Self::RequestPull(r)
+        }
+    }
+
+    impl From<crate::net::replication::Evicted> for Upstream {
+        fn from(e: crate::net::replication::Evicted) -> Self {
+            Self::Evicted(e)
+        }
+    }
+
+    impl From<crate::net::replication::scheduler::Event> for Upstream {
+        fn from(e: crate::net::replication::scheduler::Event) -> Self {
+            Self::Scheduled(e)
+        }
+    }
+
+    /// Changes to the local state of the peer, triggered by replication.
+    ///
+    /// These are delivered to subscribers on a best-effort basis, like all
+    /// [`Upstream`] events: a subscriber which falls behind misses events
+    /// rather than stalling the protocol, cf. [`RecvError::Lagged`].
+    #[derive(Clone, Debug)]
+    #[non_exhaustive]
+    pub enum Lifecycle {
+        /// A replication run of `urn` from `remote_peer` completed.
+        Replicated {
+            urn: crate::git::Urn,
+            remote_peer: PeerId,
+        },
+        /// `peer` is now tracked for `urn`.
+        Tracked { urn: crate::git::Urn, peer: PeerId },
+        /// The identity document at `urn` was updated. `peer` is the remote
+        /// whose view of the identity changed, or `None` for the local view.
+        IdentityUpdated {
+            urn: crate::git::Urn,
+            peer: Option<PeerId>,
+        },
+        /// The signed refs of `peer` for `urn` were updated.
+        SignedRefsUpdated { urn: crate::git::Urn, peer: PeerId },
+    }
+
+    impl From<Lifecycle> for Upstream {
+        fn from(l: Lifecycle) -> Self {
+            Self::Lifecycle(l)
+        }
+    }
+
     #[derive(Debug, Error)]
     pub enum ExpectError {
         #[error("timeout waiting for matching event")]
@@ -3,11 +3,17 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::hash::Hash;
+use std::{convert::TryFrom as _, hash::Hash};
 
 use minicbor::{Decode, Decoder, Encode, Encoder};
 
-use crate::{identities::git::Urn, PeerId};
+use crate::{
+    collaborative_objects::{ObjectId, TypeName},
+    identities::git::Urn,
+    PeerId,
+};
+
+pub mod batch;
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum Rev {
@@ -78,4 +84,249 @@ pub struct Payload {
     /// is, it may map to `remotes/<origin>/<urn.path@rev>`.
     #[n(2)]
     pub origin: Option<PeerId>,
+
+    /// The collaborative object updated or wanted.
+    ///
+    /// If `Some`, the announcement concerns only the given object, and
+    /// receivers replicate just its refs instead of the whole of `urn`. `rev`
+    /// is ignored in this case. Peers not aware of this field treat the
+    /// payload as an announcement of `urn` as a whole.
+    #[n(3)]
+    pub cob: Option<Cob>,
+
+    /// Further tips of `urn`, announced together with `rev`.
+    ///
+    /// Set when coalescing announcements, see [`batch`]. Peers not aware of
+    /// this field treat the payload as an announcement of `urn` and `rev`
+    /// only, which still causes them to fetch `urn` as a whole.
+    #[n(4)]
+    pub batch: Option<Vec<Tip>>,
+}
+
+impl Payload {
+    /// Announce or query the `tips` of the collaborative object
+    /// `typename/object_id` of `urn`.
+    pub fn cob(
+        urn: Urn,
+        typename: TypeName,
+        object_id: ObjectId,
+        tips: impl IntoIterator<Item = git2::Oid>,
+        origin: Option<PeerId>,
+    ) -> Self {
+        Self {
+            urn,
+            rev: None,
+            origin,
+            cob: Some(Cob {
+                typename,
+                object_id,
+                tips: tips.into_iter().map(Rev::Git).collect(),
+            }),
+            batch: None,
+        }
+    }
+
+    /// Split a compound payload into the individual announcements it
+    /// consists of, starting with `urn` and `rev`.
+    ///
+    /// A payload without a `batch` yields only itself.
+    pub fn unbatch(&self) -> impl Iterator<Item = Payload> + '_ {
+        let head = Self {
+            batch: None,
+            ..self.clone()
+        };
+        let tips = self.batch.iter().flatten().map(move |tip| Self {
+            urn: self.urn.clone().with_path(tip.path.clone()),
+            rev: Some(tip.rev.clone()),
+            origin: self.origin,
+            cob: None,
+            batch: None,
+        });
+
+        std::iter::once(head).chain(tips)
+    }
+}
+
+/// A tip announced as part of a [`Payload::batch`].
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Encode, Decode)]
+#[cbor(array)]
+pub struct Tip {
+    /// The named branch `rev` was applied to, as in [`Payload::urn`].
+    #[n(0)]
+    #[cbor(with = "encoding::path")]
+    pub path: Option<git_ext::RefLike>,
+
+    #[n(1)]
+    pub rev: Rev,
+}
+
+/// The tips of a collaborative object.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Encode, Decode)]
+#[cbor(array)]
+pub struct Cob {
+    #[n(0)]
+    #[cbor(with = "encoding::typename")]
+    pub typename: TypeName,
+
+    #[n(1)]
+    #[cbor(with = "encoding::object_id")]
+    pub object_id: ObjectId,
+
+    /// The change commits the object's ref points to, or which it is a
+    /// descendant of.
+    #[n(2)]
+    pub tips: Vec<Rev>,
+}
+
+impl Cob {
+    /// The ref of the object, relative to a namespace.
+    pub fn refname(&self) -> git_ext::RefLike {
+        reflike!("refs/cobs")
+            .join(git_ext::RefLike::try_from(self.typename.to_string()).expect("valid typename"))
+            .join(git_ext::RefLike::try_from(self.object_id.to_string()).expect("valid object id"))
+    }
+
+    pub fn tips(&self) -> impl Iterator<Item = git2::Oid> + '_ {
+        self.tips.iter().map(|Rev::Git(oid)| *oid)
+    }
+}
+
+mod encoding {
+    pub(super) mod path {
+        use std::convert::TryFrom as _;
+
+        use minicbor::{
+            data::Type,
+            decode::{Decoder, Error as DecodeError},
+            encode::{Encoder, Error as EncodeError, Write},
+        };
+
+        pub fn encode<W: Write>(
+            v: &Option<git_ext::RefLike>,
+            e: &mut Encoder<W>,
+        ) -> Result<(), EncodeError<W::Error>> {
+            match v {
+                None => e.null()?,
+                Some(path) => e.str(path.as_str())?,
+            };
+            Ok(())
+        }
+
+        pub fn decode(d: &mut Decoder<'_>) -> Result<Option<git_ext::RefLike>, DecodeError> {
+            if d.datatype()? == Type::Null {
+                d.skip()?;
+                return Ok(None);
+            }
+            git_ext::RefLike::try_from(d.str()?)
+                .map(Some)
+                .map_err(|_| DecodeError::Message("invalid path"))
+        }
+    }
+
+    pub(super) mod typename {
+        use std::str::FromStr as _;
+
+        use minicbor::{
+            decode::{Decoder, Error as DecodeError},
+            encode::{Encoder, Error as EncodeError, Write},
+        };
+
+        use crate::collaborative_objects::TypeName;
+
+        pub fn encode<W: Write>(
+            v: &TypeName,
+            e: &mut Encoder<W>,
+        ) -> Result<(), EncodeError<W::Error>> {
+            e.str(v.to_string().as_str())?;
+            Ok(())
+        }
+
+        pub fn decode(d: &mut Decoder<'_>) -> Result<TypeName, DecodeError> {
+            TypeName::from_str(d.str()?).map_err(|_| DecodeError::Message("invalid typename"))
+        }
+    }
+
+    pub(super) mod object_id {
+        use std::str::FromStr as _;
+
+        use minicbor::{
+            decode::{Decoder, Error as DecodeError},
+            encode::{Encoder, Error as EncodeError, Write},
+        };
+
+        use crate::collaborative_objects::ObjectId;
+
+        pub fn encode<W: Write>(
+            v: &ObjectId,
+            e: &mut Encoder<W>,
+        ) -> Result<(), EncodeError<W::Error>> {
+            e.str(v.to_string().as_str())?;
+            Ok(())
+        }
+
+        pub fn decode(d: &mut Decoder<'_>) -> Result<ObjectId, DecodeError> {
+            ObjectId::from_str(d.str()?).map_err(|_| DecodeError::Message("invalid object id"))
+        }
+    }
+}
+
+#[cfg(feature = "fuzzing")]
+mod fuzzing {
+    use std::str::FromStr as _;
+
+    use arbitrary::{Arbitrary, Error, Result, Unstructured};
+
+    use super::{Cob, Payload, Rev, Tip};
+    use crate::collaborative_objects::{ObjectId, TypeName};
+
+    impl<'a> Arbitrary<'a> for Rev {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            u.arbitrary::<git_ext::Oid>().map(Self::from)
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for Tip {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            Ok(Self {
+                path: u.arbitrary()?,
+                rev: u.arbitrary()?,
+            })
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for Cob {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+            let depth = u.int_in_range(1..=4)?;
+            let mut components = Vec::with_capacity(depth);
+            for _ in 0..depth {
+                let len = u.int_in_range(1..=8)?;
+                let component = (0..len)
+                    .map(|_| u.choose(ALPHABET).map(|c| char::from(*c)))
+                    .collect::<Result<String>>()?;
+                components.push(component);
+            }
+            let typename =
+                TypeName::from_str(&components.join(".")).map_err(|_| Error::IncorrectFormat)?;
+
+            Ok(Self {
+                typename,
+                object_id: u.arbitrary::<git_ext::Oid>().map(ObjectId::from)?,
+                tips: u.arbitrary()?,
+            })
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for Payload {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            Ok(Self {
+                urn: u.arbitrary()?,
+                rev: u.arbitrary()?,
+                origin: u.arbitrary()?,
+                cob: u.arbitrary()?,
+                batch: u.arbitrary()?,
+            })
+        }
+    }
 }
@@ -0,0 +1,131 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Coalescing of announcements.
+//!
+//! Updating many refs at once would otherwise result in a `Have` being
+//! broadcast for each of them. Instead, announcements of the same [`Urn`] and
+//! origin made within [`Config::window`] are collected, deduplicated by their
+//! path and revision, and broadcast as a single [`Payload`], the further tips
+//! being carried in [`Payload::batch`].
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use parking_lot::Mutex;
+
+use super::{Payload, Tip};
+use crate::{identities::git::Urn, PeerId};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// How long to wait for further announcements of the same [`Urn`] before
+    /// broadcasting them. A zero duration disables batching.
+    pub window: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_millis(250),
+        }
+    }
+}
+
+/// Identifies a batch: announcements of the same [`Urn`] (regardless of its
+/// path), and origin.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct Key {
+    pub urn: Urn,
+    pub origin: Option<PeerId>,
+}
+
+/// The outcome of [`Batcher::push`].
+#[derive(Debug)]
+pub enum Push {
+    /// The payload can't be batched, and should be broadcast right away.
+    Bypass(Payload),
+    /// A new batch was opened. The caller is responsible for
+    /// [`Batcher::take`]ing it after [`Config::window`] has elapsed.
+    Opened(Key),
+    /// The payload was added to an already open batch, or is a duplicate.
+    Joined,
+}
+
+#[derive(Clone)]
+pub struct Batcher {
+    config: Config,
+    open: Arc<Mutex<HashMap<Key, Vec<Payload>>>>,
+}
+
+impl Batcher {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            open: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.config.window
+    }
+
+    /// Add an announcement to its batch.
+    ///
+    /// Announcements of collaborative objects, and those without a revision,
+    /// are not batched. Neither is anything if batching is disabled.
+    pub fn push(&self, payload: Payload) -> Push {
+        if self.config.window.is_zero() || payload.cob.is_some() || payload.rev.is_none() {
+            return Push::Bypass(payload);
+        }
+
+        let key = Key {
+            urn: payload.urn.clone().with_path(None),
+            origin: payload.origin,
+        };
+        let mut open = self.open.lock();
+        let opened = !open.contains_key(&key);
+        let batch = open.entry(key.clone()).or_default();
+        for single in payload.unbatch() {
+            if !batch.contains(&single) {
+                batch.push(single)
+            }
+        }
+
+        if opened {
+            Push::Opened(key)
+        } else {
+            Push::Joined
+        }
+    }
+
+    /// Close the batch identified by `key`, and combine it into a single
+    /// [`Payload`].
+    pub fn take(&self, key: &Key) -> Option<Payload> {
+        let batch = self.open.lock().remove(key)?;
+        compound(batch)
+    }
+}
+
+/// Combine announcements of the same [`Urn`] into a single [`Payload`].
+///
+/// The first announcement determines `urn`, `rev`, and `origin`, the
+/// remaining ones end up in [`Payload::batch`].
+pub fn compound(payloads: impl IntoIterator<Item = Payload>) -> Option<Payload> {
+    let mut payloads = payloads.into_iter();
+    let head = payloads.next()?;
+    let tips = payloads
+        .filter_map(|p| {
+            p.rev.map(|rev| Tip {
+                path: p.urn.path,
+                rev,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Some(Payload {
+        batch: if tips.is_empty() { None } else { Some(tips) },
+        ..head
+    })
+}
@@ -0,0 +1,208 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Health checks of the connections to high-value peers.
+//!
+//! Idle connections are kept alive by QUIC keep-alive pings, see
+//! [`quic::KeepAlive`]. Pings can't, however, revive a connection which was
+//! torn down anyway, eg. because the NAT mapping expired while the remote end
+//! was unreachable. Replicating from such a peer then pays for establishing a
+//! new connection first.
+//!
+//! Peers added to the [`Watchlist`] -- typically seeds and the delegates of
+//! projects of interest -- are checked every [`Config::check_interval`]: the
+//! round-trip time of live connections is recorded, and connections which
+//! were lost are re-established proactively. The outcome of the checks can be
+//! obtained via [`Watchlist::status`].
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use futures::future;
+use parking_lot::RwLock;
+
+use super::{gossip, io, ProtocolStorage, RequestPullGuard, State};
+use crate::{
+    net::{connection::RemoteAddr as _, quic},
+    PeerId,
+};
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Keep-alive settings of the QUIC endpoint.
+    pub keep_alive: quic::KeepAlive,
+    /// Interval at which the connections to the peers in [`Config::watch`]
+    /// are checked.
+    ///
+    /// Default: 60s
+    pub check_interval: Duration,
+    /// The peers to keep connections to.
+    pub watch: Watchlist,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            keep_alive: quic::KeepAlive::default(),
+            check_interval: Duration::from_secs(60),
+            watch: Watchlist::default(),
+        }
+    }
+}
+
+/// The health of the connection to a watched peer, as of the last check.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Status {
+    /// Whether a connection was established at the last check.
+    pub connected: bool,
+    /// The smoothed round-trip time of the connection.
+    pub rtt: Option<Duration>,
+    /// When a connection was last observed to be established.
+    pub last_connected: Option<SystemTime>,
+    /// The number of checks which failed to (re-)establish a connection since
+    /// the last successful one.
+    pub failures: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Watched {
+    addrs: Vec<SocketAddr>,
+    status: Status,
+}
+
+/// The set of peers whose connections are health-checked.
+///
+/// Cloning yields a handle to the same set.
+#[derive(Clone, Debug, Default)]
+pub struct Watchlist {
+    peers: Arc<RwLock<BTreeMap<PeerId, Watched>>>,
+}
+
+impl Watchlist {
+    /// Keep a connection to `peer`, reachable at `addrs`.
+    ///
+    /// `addrs` are added to the addresses already known for `peer`, if any.
+    /// Peers without addresses are still reconnected to if their addresses
+    /// are discovered, or if they connected to us before.
+    pub fn watch<I>(&self, peer: PeerId, addrs: I)
+    where
+        I: IntoIterator<Item = SocketAddr>,
+    {
+        let mut peers = self.peers.write();
+        let watched = peers.entry(peer).or_default();
+        for addr in addrs {
+            if !watched.addrs.contains(&addr) {
+                watched.addrs.push(addr)
+            }
+        }
+    }
+
+    /// Stop checking the connection to `peer`.
+    ///
+    /// Returns `false` if `peer` was not watched.
+    pub fn unwatch(&self, peer: &PeerId) -> bool {
+        self.peers.write().remove(peer).is_some()
+    }
+
+    pub fn contains(&self, peer: &PeerId) -> bool {
+        self.peers.read().contains_key(peer)
+    }
+
+    /// The watched peers and the health of their connections.
+    pub fn status(&self) -> BTreeMap<PeerId, Status> {
+        self.peers
+            .read()
+            .iter()
+            .map(|(peer, watched)| (*peer, watched.status.clone()))
+            .collect()
+    }
+
+    /// Remember `addrs` of `peer` if it is watched.
+    pub(super) fn discovered(&self, peer: &PeerId, addrs: &[SocketAddr]) {
+        if let Some(watched) = self.peers.write().get_mut(peer) {
+            for addr in addrs {
+                if !watched.addrs.contains(addr) {
+                    watched.addrs.push(*addr)
+                }
+            }
+        }
+    }
+
+    fn snapshot(&self) -> Vec<(PeerId, Vec<SocketAddr>)> {
+        self.peers
+            .read()
+            .iter()
+            .map(|(peer, watched)| (*peer, watched.addrs.clone()))
+            .collect()
+    }
+
+    fn update<F>(&self, peer: &PeerId, f: F)
+    where
+        F: FnOnce(&mut Watched),
+    {
+        if let Some(watched) = self.peers.write().get_mut(peer) {
+            f(watched)
+        }
+    }
+}
+
+/// Check the watched peers every [`Config::check_interval`].
+#[tracing::instrument(skip(state, watch))]
+pub(super) async fn run<S, G>(state: State<S, G>, watch: Watchlist, interval: Duration)
+where
+    S: ProtocolStorage<SocketAddr, Update = gossip::Payload> + Clone + 'static,
+    G: RequestPullGuard,
+{
+    loop {
+        link_async::sleep(interval).await;
+        future::join_all(
+            watch
+                .snapshot()
+                .into_iter()
+                .map(|(peer, addrs)| check(&state, &watch, peer, addrs)),
+        )
+        .await;
+    }
+}
+
+async fn check<S, G>(state: &State<S, G>, watch: &Watchlist, peer: PeerId, addrs: Vec<SocketAddr>)
+where
+    S: ProtocolStorage<SocketAddr, Update = gossip::Payload> + Clone + 'static,
+    G: RequestPullGuard,
+{
+    let conn = match state.endpoint.get_connection(peer) {
+        Some(conn) => Some(conn),
+        None if addrs.is_empty() => None,
+        None => {
+            tracing::debug!(remote_id = %peer, "re-establishing connection");
+            io::discovered(state.clone(), peer, addrs).await;
+            state.endpoint.get_connection(peer)
+        },
+    };
+    watch.update(&peer, |watched| match conn {
+        Some(conn) => {
+            let remote_addr = conn.remote_addr();
+            if !watched.addrs.contains(&remote_addr) {
+                watched.addrs.push(remote_addr)
+            }
+            watched.status = Status {
+                connected: true,
+                rtt: Some(conn.stats().rtt),
+                last_connected: Some(SystemTime::now()),
+                failures: 0,
+            };
+        },
+        None => {
+            tracing::warn!(remote_id = %peer, "watched peer unreachable");
+            watched.status.connected = false;
+            watched.status.rtt = None;
+            watched.status.failures = watched.status.failures.saturating_add(1);
+        },
+    })
+}
@@ -6,7 +6,7 @@
 use std::collections::BTreeSet;
 
 use data::BoundedVec;
-use minicbor::{Decode, Encode};
+use minicbor::{Decode, Decoder, Encode, Encoder};
 use typenum::U16;
 
 use crate::PeerId;
@@ -16,6 +16,24 @@ use crate::PeerId;
 pub enum Capability {
     #[n(0)]
     Reserved = 0,
+    /// Accepts zstd-compressed messages, see [`super::compression`].
+    #[n(1)]
+    Zstd = 1,
+}
+
+impl Capability {
+    /// Whether all versions of the protocol know about `self`.
+    ///
+    /// Peers predating [`Capability::Zstd`] fail to decode a
+    /// [`PeerAdvertisement`] listing a capability they don't know about,
+    /// so the ones for which this is `false` are sent in a separate field,
+    /// which those peers ignore.
+    fn is_universal(&self) -> bool {
+        match self {
+            Self::Reserved => true,
+            Self::Zstd => false,
+        }
+    }
 }
 
 pub type PeerInfo<Addr> = GenericPeerInfo<Addr, PeerAdvertisement<Addr>>;
@@ -99,13 +117,14 @@ impl<Addr> GenericPeerInfo<Addr, PeerAdvertisement<Addr>> {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Decode, Encode)]
-#[cbor(array)]
+/// The info a peer advertises about itself.
+///
+/// Encoded as an array, in which index `2` holds the [`Capability`]s known to
+/// all versions of the protocol, and index `3` the others. Older peers ignore
+/// the latter, instead of failing to decode the advertisement.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PeerAdvertisement<Addr> {
-    #[n(0)]
     pub listen_addrs: BoundedVec<U16, Addr>,
-
-    #[n(2)]
     pub capabilities: BTreeSet<Capability>,
 }
 
@@ -117,3 +136,155 @@ impl<Addr> PeerAdvertisement<Addr> {
         }
     }
 }
+
+impl<Addr: Encode> Encode for PeerAdvertisement<Addr> {
+    fn encode<W: minicbor::encode::Write>(
+        &self,
+        e: &mut Encoder<W>,
+    ) -> Result<(), minicbor::encode::Error<W::Error>> {
+        let (universal, extended): (BTreeSet<_>, BTreeSet<_>) =
+            self.capabilities.iter().partition(|cap| cap.is_universal());
+        // Index `1` is unused
+        e.array(if extended.is_empty() { 3 } else { 4 })?
+            .encode(&self.listen_addrs)?
+            .null()?
+            .encode(universal)?;
+        if !extended.is_empty() {
+            e.encode(extended)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'b, Addr: Decode<'b>> Decode<'b> for PeerAdvertisement<Addr> {
+    fn decode(d: &mut Decoder<'b>) -> Result<Self, minicbor::decode::Error> {
+        let mut listen_addrs = None;
+        let mut capabilities = None;
+        let mut extended = BTreeSet::new();
+        elements(d, |d, i| {
+            match i {
+                0 => listen_addrs = Some(d.decode()?),
+                2 => capabilities = Some(decode_capabilities(d)?),
+                3 => extended = decode_capabilities(d)?,
+                _ => d.skip()?,
+            }
+            Ok(())
+        })?;
+
+        let listen_addrs = listen_addrs.ok_or(minicbor::decode::Error::MissingValue(
+            0,
+            "PeerAdvertisement::listen_addrs",
+        ))?;
+        let mut capabilities = capabilities.ok_or(minicbor::decode::Error::MissingValue(
+            2,
+            "PeerAdvertisement::capabilities",
+        ))?;
+        capabilities.append(&mut extended);
+
+        Ok(Self {
+            listen_addrs,
+            capabilities,
+        })
+    }
+}
+
+/// Decode the [`Capability`]s of a [`PeerAdvertisement`], skipping the ones
+/// this implementation doesn't know about.
+fn decode_capabilities(
+    d: &mut Decoder<'_>,
+) -> Result<BTreeSet<Capability>, minicbor::decode::Error> {
+    let mut caps = BTreeSet::new();
+    elements(d, |d, _| {
+        let pos = d.position();
+        match d.decode() {
+            Ok(cap) => {
+                caps.insert(cap);
+                Ok(())
+            },
+            Err(minicbor::decode::Error::UnknownVariant(_)) => {
+                d.set_position(pos);
+                d.skip()
+            },
+            Err(e) => Err(e),
+        }
+    })?;
+
+    Ok(caps)
+}
+
+/// Call `f` with the index of every element of the (possibly indefinite)
+/// array at the current position of `d`.
+fn elements<'b, F>(d: &mut Decoder<'b>, mut f: F) -> Result<(), minicbor::decode::Error>
+where
+    F: FnMut(&mut Decoder<'b>, u64) -> Result<(), minicbor::decode::Error>,
+{
+    match d.array()? {
+        Some(n) => {
+            for i in 0..n {
+                f(d, i)?;
+            }
+        },
+        None => {
+            let mut i = 0;
+            while d.datatype()? != minicbor::data::Type::Break {
+                f(d, i)?;
+                i += 1;
+            }
+            // Consume the break
+            d.set_position(d.position() + 1);
+        },
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "fuzzing")]
+mod fuzzing {
+    use std::iter;
+
+    use arbitrary::{Arbitrary, Result, Unstructured};
+    use data::BoundedVec;
+    use typenum::U16;
+
+    use super::{Capability, GenericPeerInfo, PeerAdvertisement};
+
+    /// Up to 16 addresses, as allowed on the wire.
+    fn addrs<'a, A: Arbitrary<'a>>(u: &mut Unstructured<'a>) -> Result<BoundedVec<U16, A>> {
+        let mut addrs = BoundedVec::from(iter::empty());
+        addrs.extend_fill(u.arbitrary::<Vec<A>>()?);
+        Ok(addrs)
+    }
+
+    impl<'a> Arbitrary<'a> for Capability {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            u.choose(&[Self::Reserved, Self::Zstd]).cloned()
+        }
+    }
+
+    impl<'a, A> Arbitrary<'a> for PeerAdvertisement<A>
+    where
+        A: Arbitrary<'a>,
+    {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            Ok(Self {
+                listen_addrs: addrs(u)?,
+                capabilities: u.arbitrary()?,
+            })
+        }
+    }
+
+    impl<'a, A, T> Arbitrary<'a> for GenericPeerInfo<A, T>
+    where
+        A: Arbitrary<'a>,
+        T: Arbitrary<'a>,
+    {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            Ok(Self {
+                peer_id: u.arbitrary()?,
+                advertised_info: u.arbitrary()?,
+                seen_addrs: addrs(u)?,
+            })
+        }
+    }
+}
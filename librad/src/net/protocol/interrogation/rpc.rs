@@ -3,12 +3,18 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::BTreeMap};
+
+use git_ext as ext;
 
 use super::PeerAdvertisement;
-use crate::identities::xor;
+use crate::{
+    git::{storage::digest, Urn},
+    identities::xor,
+    PeerId,
+};
 
-#[derive(Clone, Copy, Debug, minicbor::Encode, minicbor::Decode)]
+#[derive(Clone, Debug, minicbor::Encode, minicbor::Decode)]
 pub enum Request {
     /// Request the remote peer's [`PeerAdvertisement`]
     #[n(0)]
@@ -27,6 +33,37 @@ pub enum Request {
     #[n(2)]
     #[cbor(array)]
     GetUrns,
+
+    /// Request the tips of the `rad/signed_refs` the remote peer has for the
+    /// given URN, both its own and those of the peers it tracks.
+    ///
+    /// This allows to determine cheaply whether fetching from the remote peer
+    /// would yield anything new.
+    #[n(3)]
+    #[cbor(array)]
+    GetSigrefTips(#[n(0)] Urn),
+
+    /// Request the [`digest::Summary`] of the given URN, optionally including
+    /// the `rad/signed_refs` (see [`digest::Options::sigrefs`]).
+    ///
+    /// This allows to determine whether the views of the remote and the local
+    /// peer diverge with a single round-trip.
+    #[n(4)]
+    #[cbor(array)]
+    GetDigest(#[n(0)] Urn, #[n(1)] bool),
+}
+
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for Request {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(match u.int_in_range(0..=4)? {
+            0 => Self::GetAdvertisement,
+            1 => Self::EchoAddr,
+            2 => Self::GetUrns,
+            3 => Self::GetSigrefTips(u.arbitrary()?),
+            _ => Self::GetDigest(u.arbitrary()?, u.arbitrary()?),
+        })
+    }
 }
 
 #[derive(minicbor::Encode, minicbor::Decode)]
@@ -56,6 +93,23 @@ where
     #[n(3)]
     #[cbor(array)]
     Urns(#[n(0)] Cow<'a, xor::Xor>),
+
+    /// Response to a [`Request::GetSigrefTips`].
+    ///
+    /// Maps each peer to the tip of its `rad/signed_refs`, as seen by the
+    /// responder. The responder's own tip is keyed by its [`PeerId`]. If the
+    /// responder doesn't have the URN, the map is empty.
+    #[n(4)]
+    #[cbor(array)]
+    SigrefTips(#[n(0)] BTreeMap<PeerId, ext::Oid>),
+
+    /// Response to a [`Request::GetDigest`].
+    ///
+    /// `None` if the responder doesn't have the URN. Refs hidden from the
+    /// requester (see [`crate::net::protocol::read_access`]) are excluded.
+    #[n(5)]
+    #[cbor(array)]
+    Digest(#[n(0)] Option<digest::Summary>),
 }
 
 /// Error response.
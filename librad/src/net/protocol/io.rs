@@ -3,13 +3,13 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{iter, net::SocketAddr};
+use std::{collections::BTreeSet, iter, net::SocketAddr};
 
 use data::BoundedVec;
 
 use super::{
     gossip,
-    info::{PartialPeerInfo, PeerAdvertisement},
+    info::{Capability, PartialPeerInfo, PeerAdvertisement},
     membership,
     Endpoint,
     ProtocolStorage,
@@ -18,7 +18,7 @@ use super::{
 };
 use crate::{net::connection::RemoteAddr as _, PeerId};
 
-mod codec;
+pub mod codec;
 
 pub(super) mod connections;
 pub(super) use connections::connect;
@@ -41,12 +41,24 @@ where
         return;
     }
 
-    if let Some((conn, ingress)) = connect(&state.endpoint, peer, addrs).await {
+    let connected = connect(
+        &state.endpoint,
+        &state.breakers,
+        &state.reachability,
+        &state.address_book,
+        peer,
+        addrs,
+    )
+    .await;
+    if let Some((conn, ingress)) = connected {
+        // The capabilities of `peer` are not known until it replies
         let rpc_sent = send_rpc::<_, ()>(
             &conn,
-            state
-                .membership
-                .hello(peer_advertisement(&state.endpoint)()),
+            state.membership.hello(peer_advertisement(
+                &state.endpoint,
+                &state.config.capabilities,
+            )()),
+            None,
         )
         .await;
 
@@ -64,7 +76,7 @@ where
                 state
                     .tick(membership::tocks(
                         &state.membership,
-                        peer_advertisement(&state.endpoint),
+                        peer_advertisement(&state.endpoint, &state.config.capabilities),
                         ticks,
                     ))
                     .await;
@@ -77,15 +89,16 @@ where
     }
 }
 
-pub(super) fn peer_advertisement(
-    endpoint: &Endpoint,
-) -> impl Fn() -> PeerAdvertisement<SocketAddr> + '_ {
+pub(super) fn peer_advertisement<'a>(
+    endpoint: &'a Endpoint,
+    capabilities: &'a BTreeSet<Capability>,
+) -> impl Fn() -> PeerAdvertisement<SocketAddr> + 'a {
     move || {
         let mut listen_addrs = BoundedVec::from(iter::empty());
         listen_addrs.extend_fill(endpoint.listen_addrs());
         PeerAdvertisement {
             listen_addrs,
-            capabilities: Default::default(),
+            capabilities: capabilities.clone(),
         }
     }
 }
@@ -5,12 +5,148 @@
 
 use std::net::SocketAddr;
 
+use bytes::{Buf as _, BufMut as _, BytesMut};
+use futures_codec::{Decoder, Encoder};
+use minicbor::{
+    data::{Tag, Type},
+    Decode,
+    Encode,
+};
+
 use crate::net::{
-    codec::CborCodec,
-    protocol::{broadcast, membership},
+    codec::{CborCodec, CborCodecError, CborError},
+    protocol::{broadcast, compression, membership},
 };
 
 pub type Codec<T> = CborCodec<T, T>;
 
-pub type Gossip<T> = Codec<broadcast::Message<SocketAddr, T>>;
-pub type Membership = Codec<membership::Message<SocketAddr>>;
+pub type Gossip<T> = Compressed<broadcast::Message<SocketAddr, T>>;
+pub type Membership = Compressed<membership::Message<SocketAddr>>;
+
+/// [`Codec`] which compresses messages as per [`compression::Config`].
+///
+/// Compressed messages are always accepted when decoding.
+#[derive(Clone)]
+pub struct Compressed<T> {
+    plain: Codec<T>,
+    compression: Option<compression::Config>,
+}
+
+impl<T> Compressed<T> {
+    /// A codec which does not compress, but accepts compressed messages.
+    pub fn new() -> Self {
+        Self::with_compression(None)
+    }
+
+    pub fn with_compression(compression: Option<compression::Config>) -> Self {
+        Self {
+            plain: Codec::new(),
+            compression,
+        }
+    }
+}
+
+impl<T> Encoder for Compressed<T>
+where
+    T: Encode,
+{
+    type Item = T;
+    type Error = CborCodecError;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let bytes = minicbor::to_vec(&item).map_err(CborError::from)?;
+        let framed = match self.compression {
+            Some(cfg) if bytes.len() >= cfg.threshold => {
+                let compressed = cfg.compress(&bytes)?;
+                if compressed.len() < bytes.len() {
+                    let mut framed = Vec::with_capacity(compressed.len() + 16);
+                    minicbor::Encoder::new(&mut framed)
+                        .tag(Tag::Unassigned(compression::TAG))
+                        .and_then(|e| e.bytes(&compressed))
+                        .map_err(CborError::from)?;
+                    framed
+                } else {
+                    bytes
+                }
+            },
+            _ => bytes,
+        };
+
+        dst.reserve(framed.len());
+        dst.put_slice(&framed);
+
+        Ok(())
+    }
+}
+
+impl<T> Compressed<T>
+where
+    for<'b> T: Decode<'b>,
+{
+    fn decode_compressed(
+        &mut self,
+        src: &mut BytesMut,
+        eof: bool,
+    ) -> Result<Option<T>, CborCodecError> {
+        let mut decoder = minicbor::Decoder::new(src);
+        let compressed = match decoder.tag().and_then(|_| decoder.bytes()) {
+            Err(minicbor::decode::Error::EndOfInput) if !eof => return Ok(None),
+            Err(e) => {
+                let off = decoder.position();
+                src.advance(off);
+                return Err(CborError::from(e).into());
+            },
+            Ok(compressed) => compression::decompress(compressed),
+        };
+        let off = decoder.position();
+        src.advance(off);
+
+        Ok(Some(
+            minicbor::decode(&compressed?).map_err(CborError::from)?,
+        ))
+    }
+
+    /// Whether the next message in `src` is compressed, or `None` if there
+    /// is not enough input to tell.
+    ///
+    /// Malformed input is treated as not compressed, so it is reported by the
+    /// plain [`Codec`].
+    fn is_compressed(src: &BytesMut) -> Option<bool> {
+        use minicbor::decode::Error::EndOfInput;
+
+        let mut decoder = minicbor::Decoder::new(src);
+        match decoder.datatype() {
+            Ok(Type::Tag) => match decoder.tag() {
+                Ok(tag) => Some(tag == Tag::Unassigned(compression::TAG)),
+                Err(EndOfInput) => None,
+                Err(_) => Some(false),
+            },
+            Ok(_) => Some(false),
+            Err(EndOfInput) => None,
+            Err(_) => Some(false),
+        }
+    }
+}
+
+impl<T> Decoder for Compressed<T>
+where
+    for<'b> T: Decode<'b>,
+{
+    type Item = T;
+    type Error = CborCodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match Self::is_compressed(src) {
+            None => Ok(None),
+            Some(true) => self.decode_compressed(src, false),
+            Some(false) => self.plain.decode(src),
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match Self::is_compressed(src) {
+            Some(true) => self.decode_compressed(src, true),
+            _ => self.plain.decode_eof(src),
+        }
+    }
+}
@@ -3,21 +3,24 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::net::{IpAddr, SocketAddr};
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Instant,
+};
 
 use either::Either;
-use futures::{
-    future::{self, TryFutureExt as _},
-    stream::{Stream, StreamExt as _},
-};
-use indexmap::IndexSet;
+use futures::stream::{Stream, StreamExt as _};
+use indexmap::{IndexMap, IndexSet};
 use std_ext::Void;
 
 pub use super::error;
 use super::streams;
 use crate::{
     net::{
+        happy_eyeballs::{self, Reachability},
         protocol::{
+            address_book::AddressBook,
+            breaker::Breakers,
             event::upstream as event,
             gossip,
             Endpoint,
@@ -78,9 +81,19 @@ where
     Err(error::Accept::Done)
 }
 
-#[tracing::instrument(skip(endpoint, addrs))]
+/// Dial `remote_id` at any of the given `addrs`, returning the first
+/// connection established.
+///
+/// `addrs` are supplemented by the addresses recorded in the [`AddressBook`],
+/// most reliable first. Addresses for which the [`Breakers`] are open are
+/// skipped. The remaining ones are raced in the order determined by
+/// [`Reachability`], see [`happy_eyeballs`].
+#[tracing::instrument(skip(endpoint, breakers, reachability, address_book, addrs))]
 pub async fn connect<'a, Addrs>(
     endpoint: &Endpoint,
+    breakers: &Breakers,
+    reachability: &Reachability,
+    address_book: &AddressBook,
     remote_id: PeerId,
     addrs: Addrs,
 ) -> Option<(
@@ -106,26 +119,60 @@ where
             })
     }
 
-    let addrs = addrs.into_iter().filter(routable).collect::<IndexSet<_>>();
+    let addrs = address_book
+        .order(&remote_id, addrs.into_iter().collect())
+        .into_iter()
+        .filter(routable)
+        .collect::<IndexSet<_>>();
     if addrs.is_empty() {
         tracing::debug!("no routable addrs");
+        return None;
+    }
+    let mut permits = addrs
+        .into_iter()
+        .filter_map(|addr| {
+            breakers
+                .permit(remote_id, addr)
+                .map(|permit| (addr, permit))
+        })
+        .collect::<IndexMap<_, _>>();
+    if permits.is_empty() {
+        tracing::debug!("circuit breakers open for all addrs");
         None
     } else {
-        future::select_ok(addrs.iter().map(|addr| {
+        let addrs = reachability.sort(remote_id, permits.keys().copied().collect());
+        // Permits of addresses not attempted, or whose attempt is abandoned
+        // once another one succeeded, are dropped, releasing their probes.
+        happy_eyeballs::race(addrs, happy_eyeballs::CONNECTION_ATTEMPT_DELAY, |addr| {
             let mut endpoint = endpoint.clone();
+            let permit = permits.remove(&addr);
+            let reachability = reachability.clone();
+            let address_book = address_book.clone();
+            let started = Instant::now();
             tracing::info!(remote_addr = %addr, "establishing connection");
-            Box::pin(async move {
-                endpoint
-                    .connect(remote_id, addr)
-                    .map_err(|e| {
+            async move {
+                let res = endpoint.connect(remote_id, &addr).await;
+                match &res {
+                    Ok(_) => {
+                        if let Some(permit) = permit {
+                            permit.success()
+                        }
+                        reachability.success(remote_id, addr, started.elapsed());
+                        address_book.success(remote_id, addr);
+                    },
+                    Err(e) => {
                         tracing::warn!(err = ?e, remote_addr = %addr, "could not connect");
-                        e
-                    })
-                    .await
-            })
-        }))
+                        if let Some(permit) = permit {
+                            permit.failure()
+                        }
+                        reachability.failure(remote_id, addr);
+                        address_book.failure(remote_id, addr);
+                    },
+                }
+                res
+            }
+        })
         .await
         .ok()
-        .map(|(success, _pending)| success)
     }
 }
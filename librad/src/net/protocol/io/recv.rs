@@ -15,5 +15,12 @@ pub(in crate::net::protocol) use interrogation::interrogation;
 mod membership;
 pub(in crate::net::protocol) use membership::{connection_lost, membership};
 
+#[cfg(feature = "request-pull")]
 pub(in crate::net::protocol) mod request_pull;
+#[cfg(feature = "request-pull")]
 pub(in crate::net::protocol) use request_pull::request_pull;
+
+#[cfg(feature = "request-pull")]
+pub(in crate::net::protocol) mod request_push;
+#[cfg(feature = "request-pull")]
+pub(in crate::net::protocol) use request_push::request_push;
@@ -4,19 +4,29 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{io, process::ExitStatus};
+use std::{
+    io,
+    process::ExitStatus,
+    sync::atomic::{AtomicBool, Ordering},
+    time::SystemTime,
+};
 
 use futures::io::{AsyncRead, AsyncWrite};
-use link_git::protocol::upload_pack::{upload_pack, Header};
+use link_async::Spawner;
+use link_git::protocol::upload_pack::{upload_pack_with, Header};
 use thiserror::Error;
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::{
+    git::{
+        storage::{self, eviction, ReadOnlyStorage as _},
+        Urn,
+    },
     net::{
-        connection::Duplex,
+        connection::{Duplex, RemotePeer as _},
+        protocol::{read_access::Decision, StateConfig},
         upgrade::{self, Upgraded},
     },
-    paths::Paths,
 };
 
 #[derive(Debug, Error)]
@@ -24,31 +34,77 @@ enum Error {
     #[error("upload-pack exited with {0}")]
     UploadPack(ExitStatus),
 
+    #[error("access to `{0}` denied")]
+    Denied(String),
+
+    #[error("namespace `{0}` not found")]
+    NotFound(String),
+
+    #[error(transparent)]
+    Init(#[from] storage::read::error::Init),
+
+    #[error(transparent)]
+    Storage(#[from] storage::Error),
+
     #[error(transparent)]
     Io(#[from] io::Error),
 }
 
-pub(in crate::net::protocol) async fn git<T>(paths: &Paths, stream: Upgraded<upgrade::Git, T>)
-where
+pub(in crate::net::protocol) async fn git<T>(
+    config: &StateConfig,
+    spawner: &Spawner,
+    stream: Upgraded<upgrade::Git, T>,
+) where
     T: Duplex,
     T::Read: AsyncRead + Unpin,
     T::Write: AsyncWrite + Unpin,
 {
-    if let Err(e) = serve(paths, stream).await {
-        error!(err = ?e, "upload-pack error");
+    match serve(config, spawner, stream).await {
+        Err(Error::Denied(namespace)) => info!(%namespace, "upload-pack denied"),
+        Err(Error::NotFound(namespace)) => debug!(%namespace, "upload-pack of unknown namespace"),
+        Err(e) => error!(err = ?e, "upload-pack error"),
+        Ok(()) => {},
     }
 }
 
-async fn serve<T>(paths: &Paths, stream: Upgraded<upgrade::Git, T>) -> Result<(), Error>
+async fn serve<T>(
+    config: &StateConfig,
+    spawner: &Spawner,
+    stream: Upgraded<upgrade::Git, T>,
+) -> Result<(), Error>
 where
     T: Duplex,
     T::Read: AsyncRead + Unpin,
     T::Write: AsyncWrite + Unpin,
 {
+    let remote_peer = stream.remote_peer_id();
     let (recv, send) = stream.into_stream().split();
-    let git_dir = paths.git_dir();
+    let git_dir = config.paths.git_dir();
 
-    let (Header { path, host, extra }, run) = upload_pack(git_dir, recv, send).await?;
+    let denied = AtomicBool::new(false);
+    let (Header { path, host, extra }, run) = upload_pack_with(git_dir, recv, send, |hdr| {
+        // legacy clients redundantly send a full URN
+        let namespace = hdr.path.strip_prefix("rad:git:").unwrap_or(&hdr.path);
+        match config.read_access.decide(&remote_peer, namespace) {
+            Decision::Allow => Vec::new(),
+            Decision::Hide(categories) => categories.iter().map(|c| c.pattern()).collect(),
+            Decision::Deny => {
+                denied.store(true, Ordering::Relaxed);
+                Vec::new()
+            },
+        }
+    })
+    .await?;
+    // legacy clients redundantly send a full URN
+    let namespace = path.strip_prefix("rad:git:").unwrap_or(&path);
+    // Dropping `run` without polling it closes the stream without spawning
+    // `git upload-pack`
+    if !has_namespace(config, spawner, namespace).await {
+        return Err(Error::NotFound(path));
+    }
+    if denied.load(Ordering::Relaxed) {
+        return Err(Error::Denied(path));
+    }
     info!(%path, ?host, ?extra, "upload-pack");
 
     let status = run.await?;
@@ -58,5 +114,34 @@ where
         return Err(Error::UploadPack(status));
     }
 
+    if let Err(e) = eviction::record_served(git_dir, namespace, SystemTime::now()) {
+        warn!(err = %e, %namespace, "failed to record served namespace");
+    }
+
     Ok(())
 }
+
+/// Whether the local storage has `namespace`.
+///
+/// Misses are not cached, as the storage can gain `namespace` at any time.
+/// Errors are logged, and yield `true`, so `git upload-pack` gets to decide.
+async fn has_namespace(config: &StateConfig, spawner: &Spawner, namespace: &str) -> bool {
+    let urn = match Urn::try_from_id(namespace) {
+        Ok(urn) => urn,
+        Err(_) => return true,
+    };
+    let paths = config.paths.clone();
+    let has = spawner
+        .blocking(move || {
+            let storage = storage::ReadOnly::open(&paths)?;
+            Ok::<_, Error>(storage.has_urn(&urn)?)
+        })
+        .await;
+    match has {
+        Ok(has) => has,
+        Err(e) => {
+            warn!(err = ?e, %namespace, "failed to resolve namespace");
+            true
+        },
+    }
+}
@@ -13,13 +13,15 @@ use futures_codec::FramedRead;
 
 use crate::{
     net::{
-        connection::RemotePeer,
+        connection::RemoteInfo,
         protocol::{
             broadcast,
+            cache::seen,
             gossip,
             info::PeerInfo,
             io::{codec, peer_advertisement},
             membership,
+            throttle,
             ProtocolStorage,
             RequestPullGuard,
             State,
@@ -35,9 +37,10 @@ pub(in crate::net::protocol) async fn gossip<S, G, T>(
 ) where
     S: ProtocolStorage<SocketAddr, Update = gossip::Payload> + Clone + 'static,
     G: RequestPullGuard,
-    T: RemotePeer + AsyncRead + Unpin,
+    T: RemoteInfo<Addr = SocketAddr> + AsyncRead + Unpin,
 {
     let remote_id = stream.remote_peer_id();
+    let remote_ip = stream.remote_addr().ip();
 
     let mut recv = FramedRead::new(
         BufReader::with_capacity(100, stream.into_stream()),
@@ -53,7 +56,7 @@ pub(in crate::net::protocol) async fn gossip<S, G, T>(
                 state
                     .tick(membership::tocks(
                         &state.membership,
-                        peer_advertisement(&state.endpoint),
+                        peer_advertisement(&state.endpoint, &state.config.capabilities),
                         ticks,
                     ))
                     .await;
@@ -62,11 +65,32 @@ pub(in crate::net::protocol) async fn gossip<S, G, T>(
             },
 
             Ok(msg) => {
+                state.phone.metrics().gossip_received(&msg);
+                if let Err(limit) =
+                    state
+                        .limits
+                        .ingress
+                        .check(throttle::Kind::Gossip, &remote_id, &remote_ip)
+                {
+                    tracing::debug!(remote_id = %remote_id, ?limit, "gossip rate limited");
+                    state.phone.metrics().shed(throttle::Kind::Gossip, limit);
+                    continue;
+                }
                 let peer_info = || PeerInfo {
                     peer_id: state.local_id,
-                    advertised_info: peer_advertisement(&state.endpoint)(),
+                    advertised_info: peer_advertisement(
+                        &state.endpoint,
+                        &state.config.capabilities,
+                    )(),
                     seen_addrs: iter::empty().into(),
                 };
+                let sighting = {
+                    let kind = match &msg {
+                        broadcast::Message::Have { .. } => seen::Kind::Have,
+                        broadcast::Message::Want { .. } => seen::Kind::Want,
+                    };
+                    (msg.payload().urn.clone(), msg.origin().peer_id, kind)
+                };
                 match state
                     .gossip
                     .apply(&state.membership, peer_info, remote_id, msg)
@@ -83,7 +107,7 @@ pub(in crate::net::protocol) async fn gossip<S, G, T>(
                         state
                             .tick(membership::tocks(
                                 &state.membership,
-                                peer_advertisement(&state.endpoint),
+                                peer_advertisement(&state.endpoint, &state.config.capabilities),
                                 Some(disconnect(remote_id)),
                             ))
                             .await;
@@ -92,6 +116,11 @@ pub(in crate::net::protocol) async fn gossip<S, G, T>(
                     },
 
                     Ok((may_event, tocks)) => {
+                        let (urn, origin, kind) = sighting;
+                        if kind == seen::Kind::Have {
+                            state.caches.misses.bust(&urn, origin);
+                        }
+                        state.caches.seen.record(urn, origin, kind);
                         state.emit(may_event);
                         state.tick(tocks).await;
                     },
@@ -3,7 +3,7 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{borrow::Cow, net::SocketAddr};
+use std::{borrow::Cow, collections::BTreeMap, net::SocketAddr};
 
 use futures::{
     io::{AsyncRead, AsyncWrite, AsyncWriteExt as _, BufReader, BufWriter},
@@ -11,27 +11,45 @@ use futures::{
     StreamExt as _,
 };
 use futures_codec::FramedRead;
+use git_ext as ext;
 use thiserror::Error;
 
 use crate::{
-    git::storage,
+    git::{
+        storage::{self, digest, ReadOnlyStorage as _},
+        tracking,
+        types::{Namespace, Reference},
+        Urn,
+    },
     net::{
-        connection::Duplex,
+        connection::{Duplex, RemotePeer as _},
         protocol::{
-            cache,
             interrogation::{self, Request, Response},
             io::{self, codec},
-            Endpoint,
+            read_access::{Category, Decision},
             State,
         },
         upgrade::{self, Upgraded},
     },
+    PeerId,
 };
 
 #[derive(Debug, Error)]
 enum Error {
     #[error(transparent)]
     Cbor(#[from] minicbor::encode::Error<std::io::Error>),
+
+    #[error(transparent)]
+    Pool(#[from] storage::PoolError),
+
+    #[error(transparent)]
+    Storage(#[from] storage::Error),
+
+    #[error(transparent)]
+    Tracked(#[from] tracking::error::TrackedPeers),
+
+    #[error(transparent)]
+    Digest(#[from] digest::Error),
 }
 
 lazy_static! {
@@ -49,6 +67,7 @@ pub(in crate::net::protocol) async fn interrogation<S, G, T>(
     T::Write: AsyncWrite + Unpin,
 {
     let remote_addr = stream.remote_addr();
+    let remote_peer = stream.remote_peer_id();
 
     let (recv, send) = stream.into_stream().split();
     let recv = BufReader::with_capacity(interrogation::FRAMED_BUFSIZ, recv);
@@ -59,13 +78,12 @@ pub(in crate::net::protocol) async fn interrogation<S, G, T>(
         match x {
             Err(e) => tracing::warn!(err = ?e, "interrogation recv error"),
             Ok(req) => {
-                let resp = handle_request(&state.endpoint, &state.caches.urns, remote_addr, req)
+                let resp = handle_request(&state, remote_peer, remote_addr, req)
+                    .await
                     .map(Cow::from)
                     .unwrap_or_else(|e| {
                         tracing::error!(err = ?e, "error handling request");
-                        match e {
-                            Error::Cbor(_) => Cow::from(&*INTERNAL_ERROR),
-                        }
+                        Cow::from(&*INTERNAL_ERROR)
                     });
 
                 if let Err(e) = send.into_sink().send(resp).await {
@@ -76,27 +94,105 @@ pub(in crate::net::protocol) async fn interrogation<S, G, T>(
     }
 }
 
-fn handle_request(
-    endpoint: &Endpoint,
-    urns: &cache::urns::Filter,
+async fn handle_request<S, G>(
+    state: &State<S, G>,
+    remote_peer: PeerId,
     remote_addr: SocketAddr,
     req: interrogation::Request,
-) -> Result<Vec<u8>, Error> {
+) -> Result<Vec<u8>, Error>
+where
+    S: storage::Pooled<storage::Storage> + Send + 'static,
+{
     use either::Either::*;
 
     match req {
-        Request::GetAdvertisement => {
-            Left(Response::Advertisement(io::peer_advertisement(endpoint)()))
-        },
+        Request::GetAdvertisement => Left(Response::Advertisement(io::peer_advertisement(
+            &state.endpoint,
+            &state.config.capabilities,
+        )())),
         Request::EchoAddr => Left(Response::YourAddr(remote_addr)),
         Request::GetUrns => {
-            let urns = urns.get();
+            let urns = state.caches.urns.get();
             Right(encode(&Response::<SocketAddr>::Urns(Cow::Borrowed(&*urns))))
         },
+        Request::GetSigrefTips(urn) => {
+            let decision = state
+                .config
+                .read_access
+                .decide(&remote_peer, &urn.encode_id());
+            let tips = match decision {
+                Decision::Deny => BTreeMap::new(),
+                Decision::Allow => sigref_tips(state, &urn, &[]).await?,
+                Decision::Hide(hidden) => sigref_tips(state, &urn, &hidden).await?,
+            };
+            Left(Response::SigrefTips(tips))
+        },
+        Request::GetDigest(urn, sigrefs) => {
+            let decision = state
+                .config
+                .read_access
+                .decide(&remote_peer, &urn.encode_id());
+            let hide = match decision {
+                Decision::Deny => None,
+                Decision::Allow => Some(vec![]),
+                Decision::Hide(hidden) => Some(hidden.iter().map(Category::pattern).collect()),
+            };
+            let summary = match hide {
+                None => None,
+                Some(hide) => {
+                    let storage = state.storage.get().await?;
+                    digest::summary(&*storage, &urn, &digest::Options { sigrefs, hide })?
+                },
+            };
+            Left(Response::Digest(summary))
+        },
     }
     .right_or_else(|resp| encode(&resp))
 }
 
+/// The `rad/signed_refs` tips of the local peer and the tracked peers of `urn`,
+/// excluding remote peers whose view is `hidden` from the requester.
+async fn sigref_tips<S, G>(
+    state: &State<S, G>,
+    urn: &Urn,
+    hidden: &[Category],
+) -> Result<BTreeMap<PeerId, ext::Oid>, Error>
+where
+    S: storage::Pooled<storage::Storage> + Send + 'static,
+{
+    let storage = state.storage.get().await?;
+    let mut tips = BTreeMap::new();
+    if !storage.has_urn(urn)? {
+        return Ok(tips);
+    }
+
+    let tip = |peer: Option<PeerId>| -> Result<Option<ext::Oid>, Error> {
+        let reference = Reference::rad_signed_refs(Namespace::from(urn), peer);
+        match storage.reference_oid(&reference) {
+            Ok(oid) => Ok(Some(oid)),
+            Err(storage::Error::Git(e)) if ext::is_not_found_err(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    };
+
+    if let Some(oid) = tip(None)? {
+        tips.insert(state.local_id, oid);
+    }
+    if !hidden.contains(&Category::Remotes) {
+        for peer in tracking::tracked_peers(&*storage, Some(urn))? {
+            let peer = peer?;
+            if hidden.contains(&Category::Remote(peer)) {
+                continue;
+            }
+            if let Some(oid) = tip(Some(peer))? {
+                tips.insert(peer, oid);
+            }
+        }
+    }
+
+    Ok(tips)
+}
+
 fn encode(resp: &interrogation::Response<SocketAddr>) -> Result<Vec<u8>, Error> {
     Ok(minicbor::to_vec(resp)?)
 }
@@ -64,7 +64,7 @@ pub(in crate::net::protocol) async fn membership<S, G, T>(
 
                     let disconnect = membership::tocks(
                         &state.membership,
-                        peer_advertisement(&state.endpoint),
+                        peer_advertisement(&state.endpoint, &state.config.capabilities),
                         Some(membership::Tick::Reply {
                             to: remote_id,
                             message: membership::Message::Disconnect,
@@ -81,7 +81,7 @@ pub(in crate::net::protocol) async fn membership<S, G, T>(
 
                 match membership::apply(
                     &state.membership,
-                    peer_advertisement(&state.endpoint),
+                    peer_advertisement(&state.endpoint, &state.config.capabilities),
                     remote_id,
                     remote_addr,
                     msg,
@@ -111,7 +111,7 @@ where
     state
         .tick(membership::tocks(
             &state.membership,
-            peer_advertisement(&state.endpoint),
+            peer_advertisement(&state.endpoint, &state.config.capabilities),
             ticks,
         ))
         .await
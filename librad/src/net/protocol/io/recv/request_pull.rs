@@ -8,8 +8,9 @@
 use std::net::SocketAddr;
 
 use futures::{
-    future,
-    io::{AsyncWrite, AsyncWriteExt as _, BufReader, BufWriter, IntoSink},
+    channel::mpsc,
+    future::{self, FutureExt as _},
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt as _, BufReader, BufWriter, IntoSink},
     SinkExt as _,
     StreamExt as _,
 };
@@ -19,42 +20,71 @@ use thiserror::Error;
 use crate::{
     git::Urn,
     net::{
-        connection::{Duplex, RemotePeer as _},
+        connection::{Duplex, RemoteAddr as _, RemotePeer as _},
         peer::event::downstream::Gossip,
         protocol::{
             self,
             control,
+            event,
             gossip,
             io::codec,
-            request_pull::{self, error, progress, Progress, Ref, Request, Response},
+            request_pull::{self, error, progress, Progress, Ref, Request, Response, Success},
+            throttle,
             State,
         },
-        quic,
+        replication::Fetcher,
         upgrade::{self, Upgraded},
     },
     PeerId,
 };
 
 #[derive(Debug, Error)]
-enum Error {
+pub(super) enum Error {
     #[error(transparent)]
     Cbor(#[from] minicbor::encode::Error<std::io::Error>),
 }
 
-pub(in crate::net::protocol) async fn request_pull<S, G>(
+lazy_static! {
+    pub(super) static ref RATE_LIMITED: Vec<u8> = encode(&error::rate_limited().into()).unwrap();
+}
+
+/// Serve a request-pull received on `stream`, replicating from its remote
+/// end via `conn`.
+pub(in crate::net::protocol) async fn request_pull<S, G, T, F>(
     state: State<S, G>,
-    stream: Upgraded<upgrade::RequestPull, quic::BidiStream>,
+    stream: Upgraded<upgrade::RequestPull, T>,
+    conn: F,
 ) where
     S: protocol::ProtocolStorage<SocketAddr, Update = gossip::Payload> + 'static,
     G: protocol::RequestPullGuard,
+    T: Duplex<Addr = SocketAddr>,
+    T::Read: AsyncRead + Unpin,
+    T::Write: AsyncWrite + Unpin,
+    F: Fetcher,
 {
     let remote_peer = stream.remote_peer_id();
-    let conn = stream.connection().clone();
+    let remote_addr = stream.remote_addr();
     let (recv, send) = stream.into_stream().split();
     let recv = BufReader::with_capacity(request_pull::FRAMED_BUFSIZ, recv);
     let send = BufWriter::with_capacity(request_pull::FRAMED_BUFSIZ, send);
     let mut sink = send.into_sink();
 
+    // Shed load before decoding the request
+    if let Err(limit) =
+        state
+            .limits
+            .ingress
+            .check(throttle::Kind::RequestPull, &remote_peer, &remote_addr.ip())
+    {
+        tracing::debug!(%remote_peer, %remote_addr, ?limit, "request-pull rate limited");
+        state
+            .phone
+            .metrics()
+            .shed(throttle::Kind::RequestPull, limit);
+        sink.send(RATE_LIMITED.clone()).await.ok();
+        return;
+    }
+
     let mut recv = FramedRead::new(recv, codec::Codec::<Request>::new());
     if let Some(x) = recv.next().await {
         match x {
@@ -92,15 +122,15 @@ pub(in crate::net::protocol) async fn request_pull<S, G>(
 
 // Since async closures are unstable, this struct acts as a mechanism
 // for allowing progress messages to be sent to a sink.
-struct Reporter<'a, W> {
-    sink: &'a mut IntoSink<W, Vec<u8>>,
+pub(super) struct Reporter<'a, W> {
+    pub(super) sink: &'a mut IntoSink<W, Vec<u8>>,
 }
 
 impl<'a, W> Reporter<'a, W>
 where
     W: AsyncWrite + Unpin,
 {
-    async fn progress(&mut self, progress: Progress) {
+    pub(super) async fn progress(&mut self, progress: Progress) {
         match encode(&progress.into()) {
             Err(e) => tracing::warn!(err = ?e, "request-pull progress error"),
             Ok(progress) => {
@@ -112,40 +142,83 @@ where
     }
 }
 
-async fn handle_request<'a, S, G, W>(
+async fn handle_request<'a, S, G, W, F>(
     state: State<S, G>,
     peer: PeerId,
     Request { urn }: Request,
-    conn: quic::Connection,
+    conn: F,
     report: &mut Reporter<'a, W>,
 ) -> Response
 where
     S: protocol::ProtocolStorage<SocketAddr, Update = gossip::Payload> + 'static,
     G: protocol::RequestPullGuard,
     W: AsyncWrite + Unpin,
+    F: Fetcher,
 {
     report.progress(progress::authorizing(&urn)).await;
+    if let Err(err) = state.request_pull.authorize(&peer, &urn).await {
+        return error::guard(err).into();
+    }
     match state.request_pull.guard(&peer, &urn) {
         Ok(guard) => report.progress(progress::guard(guard)).await,
         Err(err) => return error::guard(err).into(),
     }
 
     report.progress(progress::replicating(&urn)).await;
-    match state
-        .request_pull
-        .replicate(&state.spawner, urn.clone(), conn)
-        .await
-    {
+    match replicate(&state, &urn, conn, report).await {
         Ok(success) => {
             let tips = success.refs.iter().map(|Ref { oid, .. }| oid).copied();
             gossip(&state, peer, &urn, tips).await;
+            state.emit(Some(event::upstream::RequestPull {
+                peer,
+                urn,
+                refs: success.refs.clone(),
+            }));
             success.into()
         },
         Err(err) => error::replication_error(err).into(),
     }
 }
 
-async fn gossip<S, G>(
+/// Replicate `urn` from the remote end of `conn`, reporting the progress of
+/// the fetches while replication is running.
+pub(super) async fn replicate<'a, S, G, W, F>(
+    state: &State<S, G>,
+    urn: &Urn,
+    conn: F,
+    report: &mut Reporter<'a, W>,
+) -> Result<Success, error::Replicate>
+where
+    S: protocol::ProtocolStorage<SocketAddr, Update = gossip::Payload> + 'static,
+    G: protocol::RequestPullGuard,
+    W: AsyncWrite + Unpin,
+    F: Fetcher,
+{
+    let (tx, mut rx) = mpsc::unbounded();
+    let replicate = state
+        .request_pull
+        .replicate(
+            &state.spawner,
+            urn.clone(),
+            request_pull::Observed::new(conn, tx),
+        )
+        .fuse();
+    futures::pin_mut!(replicate);
+    let res = loop {
+        futures::select! {
+            res = replicate => break res,
+            progress = rx.select_next_some() => report.progress(progress).await,
+        }
+    };
+    // Reported right before replication finished
+    while let Ok(Some(progress)) = rx.try_next() {
+        report.progress(progress).await
+    }
+
+    res
+}
+
+pub(super) async fn gossip<S, G>(
     state: &State<S, G>,
     exclude: PeerId,
     urn: &Urn,
@@ -161,6 +234,8 @@ async fn gossip<S, G>(
                 urn: urn.clone(),
                 rev: Some(rev.into()),
                 origin: None,
+                cob: None,
+                batch: None,
             }),
             Some(exclude),
         )
@@ -168,6 +243,6 @@ async fn gossip<S, G>(
     .await;
 }
 
-fn encode(resp: &Response) -> Result<Vec<u8>, Error> {
+pub(super) fn encode(resp: &Response) -> Result<Vec<u8>, Error> {
     Ok(minicbor::to_vec(resp)?)
 }
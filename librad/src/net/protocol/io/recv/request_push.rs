@@ -0,0 +1,216 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::net::SocketAddr;
+
+use bstr::BString;
+use futures::{
+    io::{AsyncWrite, AsyncWriteExt as _, BufReader, BufWriter},
+    SinkExt as _,
+    StreamExt as _,
+};
+use futures_codec::FramedRead;
+use thiserror::Error;
+
+use super::request_pull::{encode, gossip, replicate, Error, Reporter, RATE_LIMITED};
+use crate::{
+    git::Urn,
+    net::{
+        connection::{Duplex, RemoteAddr as _, RemotePeer as _},
+        protocol::{
+            self,
+            gossip,
+            io::codec,
+            request_pull::{self, progress, Ref},
+            request_push::{self, Request, Response},
+            throttle,
+            State,
+        },
+        quic,
+        upgrade::{self, Upgraded},
+    },
+    PeerId,
+};
+
+#[derive(Debug, Error)]
+enum LsRefs {
+    #[error(transparent)]
+    Upgrade(#[from] Box<upgrade::Error<quic::BidiStream>>),
+
+    #[error(transparent)]
+    Quic(#[from] quic::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub(in crate::net::protocol) async fn request_push<S, G>(
+    state: State<S, G>,
+    stream: Upgraded<upgrade::RequestPush, quic::BidiStream>,
+) where
+    S: protocol::ProtocolStorage<SocketAddr, Update = gossip::Payload> + 'static,
+    G: protocol::RequestPullGuard,
+{
+    let remote_peer = stream.remote_peer_id();
+    let remote_addr = stream.remote_addr();
+    let conn = stream.connection().clone();
+    let (recv, send) = stream.into_stream().split();
+    let recv = BufReader::with_capacity(request_push::FRAMED_BUFSIZ, recv);
+    let send = BufWriter::with_capacity(request_push::FRAMED_BUFSIZ, send);
+    let mut sink = send.into_sink();
+
+    // Shed load before decoding the request
+    if let Err(limit) =
+        state
+            .limits
+            .ingress
+            .check(throttle::Kind::RequestPush, &remote_peer, &remote_addr.ip())
+    {
+        tracing::debug!(%remote_peer, %remote_addr, ?limit, "request-push rate limited");
+        state
+            .phone
+            .metrics()
+            .shed(throttle::Kind::RequestPush, limit);
+        sink.send(RATE_LIMITED.clone()).await.ok();
+        return;
+    }
+
+    let mut recv = FramedRead::new(recv, codec::Codec::<Request>::new());
+    if let Some(x) = recv.next().await {
+        match x {
+            Err(e) => {
+                tracing::warn!(err = ?e, "request-push recv error");
+                if let Ok(resp) = encode(&request_pull::error::decode_failed().into()) {
+                    sink.send(resp).await.ok();
+                }
+            },
+            Ok(req) => {
+                let resp = encode(
+                    &handle_request(
+                        state,
+                        remote_peer,
+                        req,
+                        conn,
+                        &mut Reporter { sink: &mut sink },
+                    )
+                    .await,
+                )
+                .unwrap_or_else(|e| {
+                    tracing::error!(err = ?e, "error handling request");
+                    match e {
+                        Error::Cbor(_) => {
+                            encode(&request_pull::error::internal_error().into()).unwrap()
+                        },
+                    }
+                });
+
+                if let Err(e) = sink.send(resp).await {
+                    tracing::warn!(err = ?e, "request-push send error")
+                }
+            },
+        }
+    }
+}
+
+async fn handle_request<'a, S, G, W>(
+    state: State<S, G>,
+    peer: PeerId,
+    Request { urn, signed_refs }: Request,
+    conn: quic::Connection,
+    report: &mut Reporter<'a, W>,
+) -> Response
+where
+    S: protocol::ProtocolStorage<SocketAddr, Update = gossip::Payload> + 'static,
+    G: protocol::RequestPullGuard,
+    W: AsyncWrite + Unpin,
+{
+    use request_pull::error;
+
+    report.progress(progress::authorizing(&urn)).await;
+    if let Err(err) = state.request_pull.authorize(&peer, &urn).await {
+        return error::guard(err).into();
+    }
+    match state.request_pull.guard(&peer, &urn) {
+        Ok(guard) => report.progress(progress::guard(guard)).await,
+        Err(err) => return error::guard(err).into(),
+    }
+
+    // Verify the offer before fetching anything: only delegates may push, and
+    // the offered signed refs must be the ones the requester advertises.
+    report
+        .progress(request_push::progress::verifying(&urn))
+        .await;
+    match state.request_pull.is_delegate(&urn, peer).await {
+        Ok(Some(true)) => {},
+        Ok(Some(false)) => return request_push::error::not_a_delegate(&urn, peer).into(),
+        Ok(None) => return request_push::error::unknown_urn(&urn).into(),
+        Err(err) => return error::replication_error(err).into(),
+    }
+    match advertised_signed_refs(&conn, &urn).await {
+        Ok(Some(advertised)) if advertised == signed_refs => {},
+        Ok(advertised) => {
+            return request_push::error::advertised_mismatch(signed_refs, advertised).into()
+        },
+        Err(err) => {
+            tracing::warn!(err = %err, "request-push ls-refs error");
+            return request_push::error::advertised_mismatch(signed_refs, None).into();
+        },
+    }
+
+    report.progress(progress::replicating(&urn)).await;
+    let success = match replicate(&state, &urn, conn, report).await {
+        Ok(success) => success,
+        Err(err) => return error::replication_error(err).into(),
+    };
+
+    // The requester could have moved its signed refs in the meantime. The
+    // fetch verified whatever it obtained, and storage already holds it, so
+    // report it as it is.
+    match state.request_pull.signed_refs(&urn, peer).await {
+        Ok(fetched) if fetched != Some(signed_refs) => {
+            tracing::debug!(
+                %urn,
+                %peer,
+                offered = %signed_refs,
+                ?fetched,
+                "signed refs moved during request-push"
+            )
+        },
+        Ok(_) => {},
+        Err(err) => tracing::warn!(%urn, err = %err, "failed to look up fetched signed refs"),
+    }
+    let tips = success.refs.iter().map(|Ref { oid, .. }| oid).copied();
+    gossip(&state, peer, &urn, tips).await;
+    success.into()
+}
+
+/// Ask the requester which `rad/signed_refs` it has for `urn`.
+async fn advertised_signed_refs(
+    conn: &quic::Connection,
+    urn: &Urn,
+) -> Result<Option<git_ext::Oid>, LsRefs> {
+    const SIGNED_REFS: &str = "refs/rad/signed_refs";
+
+    let stream = conn.open_bidi().await?;
+    let (recv, send) = upgrade::upgrade(stream, upgrade::Git)
+        .await
+        .map_err(Box::new)?
+        .into_stream()
+        .split();
+    let refs = link_git::protocol::ls_refs(
+        link_git::protocol::ls::Options {
+            repo: BString::from(urn.encode_id()),
+            extra_params: vec![],
+            ref_prefixes: vec![BString::from(SIGNED_REFS)],
+        },
+        recv,
+        send,
+    )
+    .await?;
+
+    Ok(refs
+        .into_iter()
+        .map(link_replication::refs::into_unpacked)
+        .find(|(name, _)| name == SIGNED_REFS)
+        .map(|(_, oid)| git_ext::Oid::from(oid)))
+}
@@ -11,7 +11,8 @@ use futures_codec::Framed;
 use crate::net::{
     codec::CborCodec,
     connection::{RemoteAddr as _, RemotePeer as _},
-    protocol::{error, interrogation, quic, request_pull, upgrade},
+    protocol::{error, interrogation, quic, request_pull, request_push, upgrade},
+    tcp,
 };
 
 pub trait Request {
@@ -32,6 +33,12 @@ impl Request for request_pull::Request {
     const UPGRADE: Self::Upgrade = upgrade::RequestPull;
 }
 
+impl Request for request_push::Request {
+    type Response = request_push::Response;
+    type Upgrade = upgrade::RequestPush;
+    const UPGRADE: Self::Upgrade = upgrade::RequestPush;
+}
+
 #[tracing::instrument(
     skip(conn, req),
     fields(
@@ -102,3 +109,32 @@ where
     framing.send(req).await?;
     Ok(framing.map(|item| item.map_err(error::Rpc::from)))
 }
+
+/// Like [`request`], but over the TCP fallback transport, see [`tcp`].
+#[tracing::instrument(
+    skip(conn, req),
+    fields(
+        remote_id = %conn.remote_peer_id(),
+        remote_addr = %conn.remote_addr()
+    ),
+    err
+)]
+pub async fn request_tcp<R>(
+    conn: &tcp::Connection,
+    req: R,
+    buf_size: usize,
+) -> Result<
+    impl futures::Stream<Item = Result<R::Response, error::Rpc<tcp::BidiStream>>>,
+    error::Rpc<tcp::BidiStream>,
+>
+where
+    R: Request + minicbor::Encode,
+    for<'a> R::Response: minicbor::Decode<'a>,
+{
+    let stream = conn.open_bidi().await?;
+    let upgraded = upgrade::upgrade(stream, R::UPGRADE).await?;
+    let buf = BufReader::with_capacity(buf_size, upgraded.into_stream());
+    let mut framing = Framed::new(buf, CborCodec::<R, R::Response>::new());
+    framing.send(req).await?;
+    Ok(framing.map(|item| item.map_err(error::Rpc::from)))
+}
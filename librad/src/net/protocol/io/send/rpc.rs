@@ -10,7 +10,7 @@ use futures_codec::FramedWrite;
 
 use crate::net::{
     connection::{RemoteAddr as _, RemotePeer},
-    protocol::{broadcast, error, io::codec, membership},
+    protocol::{broadcast, compression, error, io::codec, membership},
     quic,
     upgrade,
 };
@@ -33,9 +33,13 @@ impl<A, P> From<broadcast::Message<A, P>> for Rpc<A, P> {
     }
 }
 
+/// Send `rpc` over `conn`, compressing it as per `compression`.
+///
+/// `compression` must only be given if the remote peer advertised support
+/// for it, see [`compression::Config::negotiate`].
 #[allow(clippy::unit_arg)]
 #[tracing::instrument(
-    skip(conn, rpc),
+    skip(conn, rpc, compression),
     fields(
         remote_id = %conn.remote_peer_id(),
         remote_addr = %conn.remote_addr()
@@ -45,6 +49,7 @@ impl<A, P> From<broadcast::Message<A, P>> for Rpc<A, P> {
 pub async fn send_rpc<R, P>(
     conn: &quic::Connection,
     rpc: R,
+    compression: Option<compression::Config>,
 ) -> Result<(), error::Rpc<quic::SendStream>>
 where
     R: Into<Rpc<SocketAddr, P>>,
@@ -81,9 +86,12 @@ where
                 })
                 .await
                 .map_err(into_protocol_error)?;
-            FramedWrite::new(stream.deref_mut(), codec::Membership::new())
-                .send(msg)
-                .await?;
+            FramedWrite::new(
+                stream.deref_mut(),
+                codec::Membership::with_compression(compression),
+            )
+            .send(msg)
+            .await?;
         },
 
         Gossip(msg) => {
@@ -93,9 +101,12 @@ where
                 })
                 .await
                 .map_err(into_protocol_error)?;
-            FramedWrite::new(stream.deref_mut(), codec::Gossip::new())
-                .send(msg)
-                .await?;
+            FramedWrite::new(
+                stream.deref_mut(),
+                codec::Gossip::with_compression(compression),
+            )
+            .send(msg)
+            .await?;
         },
     }
 
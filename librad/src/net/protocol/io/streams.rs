@@ -13,6 +13,7 @@ use crate::net::{
     connection::{CloseReason, RemoteAddr as _, RemotePeer},
     protocol::{gossip, ProtocolStorage, RequestPullGuard, State},
     quic,
+    tcp,
     upgrade,
 };
 
@@ -72,6 +73,57 @@ pub(in crate::net::protocol) async fn incoming<S, G, I>(
     }
 }
 
+/// Accept connections over the TCP fallback transport, see [`tcp`].
+pub(in crate::net::protocol) async fn tcp<S, G>(state: State<S, G>, listener: tcp::Listener)
+where
+    S: ProtocolStorage<SocketAddr, Update = gossip::Payload> + Clone + 'static,
+    G: RequestPullGuard,
+{
+    let connections = listener.incoming();
+    futures::pin_mut!(connections);
+    while let Some((_conn, streams)) = connections.next().await {
+        tracing::info!(
+            remote_id = %streams.remote_peer_id(),
+            remote_addr = %streams.remote_addr(),
+            "new tcp connection"
+        );
+        state
+            .spawner
+            .spawn(incoming_tcp(state.clone(), streams))
+            .detach();
+    }
+}
+
+/// Dispatch incoming streams of a connection over the TCP fallback transport.
+///
+/// Only git and request-pull streams are served.
+#[tracing::instrument(
+    skip(state, streams),
+    fields(
+        remote_id = %streams.remote_peer_id(),
+        remote_addr = %streams.remote_addr()
+    )
+)]
+async fn incoming_tcp<S, G>(state: State<S, G>, streams: tcp::IncomingStreams)
+where
+    S: ProtocolStorage<SocketAddr, Update = gossip::Payload> + Clone + 'static,
+    G: RequestPullGuard,
+{
+    futures::pin_mut!(streams);
+    while let Some(stream) = streams.next().await {
+        match stream {
+            Ok(bidi) => state
+                .spawner
+                .spawn(incoming::tcp(state.clone(), bidi))
+                .detach(),
+            Err(e) => {
+                tracing::warn!(err = ?e, "tcp ingress stream error");
+                break;
+            },
+        }
+    }
+}
+
 mod incoming {
     use super::*;
 
@@ -90,11 +142,21 @@ mod incoming {
                 stream.close(CloseReason::InvalidUpgrade)
             },
 
-            Ok(Git(up)) => recv::git(&state.config.paths, up).await,
+            Ok(Git(up)) => recv::git(&state.config, &state.spawner, up).await,
             Ok(Gossip(up)) => recv::gossip(state, up).await,
             Ok(Membership(up)) => recv::membership(state, up).await,
             Ok(Interrogation(up)) => recv::interrogation(state, up).await,
-            Ok(RequestPull(up)) => recv::request_pull(state, up).await,
+            #[cfg(feature = "request-pull")]
+            Ok(RequestPull(up)) => {
+                let conn = up.connection().clone();
+                recv::request_pull(state, up, conn).await
+            },
+            #[cfg(feature = "request-pull")]
+            Ok(RequestPush(up)) => recv::request_push(state, up).await,
+            #[cfg(not(feature = "request-pull"))]
+            Ok(RequestPull(up)) => deny_bidi(up.into_stream(), "request-pull"),
+            #[cfg(not(feature = "request-pull"))]
+            Ok(RequestPush(up)) => deny_bidi(up.into_stream(), "request-push"),
         }
     }
 
@@ -114,14 +176,54 @@ mod incoming {
             Ok(Git(up)) => deny_uni(up.into_stream(), "git"),
             Ok(Interrogation(up)) => deny_uni(up.into_stream(), "interrogation"),
             Ok(RequestPull(up)) => deny_uni(up.into_stream(), "request-pull"),
+            Ok(RequestPush(up)) => deny_uni(up.into_stream(), "request-push"),
 
             Ok(Gossip(up)) => recv::gossip(state, up).await,
             Ok(Membership(up)) => recv::membership(state, up).await,
         }
     }
 
+    pub(super) async fn tcp<S, G>(state: State<S, G>, stream: tcp::BidiStream)
+    where
+        S: ProtocolStorage<SocketAddr, Update = gossip::Payload> + Clone + 'static,
+        G: RequestPullGuard,
+    {
+        use upgrade::SomeUpgraded::*;
+
+        match upgrade::with_upgraded(stream).await {
+            Err(upgrade::Error { stream, source }) => {
+                tracing::warn!(err = ?source, "invalid upgrade");
+                stream.close(CloseReason::InvalidUpgrade)
+            },
+
+            Ok(Git(up)) => recv::git(&state.config, &state.spawner, up).await,
+            #[cfg(feature = "request-pull")]
+            Ok(RequestPull(up)) => {
+                let conn = up.connection().clone();
+                recv::request_pull(state, up, conn).await
+            },
+            #[cfg(not(feature = "request-pull"))]
+            Ok(RequestPull(up)) => deny_tcp(up.into_stream(), "request-pull"),
+            Ok(RequestPush(up)) => deny_tcp(up.into_stream(), "request-push"),
+            Ok(Gossip(up)) => deny_tcp(up.into_stream(), "gossip"),
+            Ok(Membership(up)) => deny_tcp(up.into_stream(), "membership"),
+            Ok(Interrogation(up)) => deny_tcp(up.into_stream(), "interrogation"),
+        }
+    }
+
+    fn deny_tcp(stream: tcp::BidiStream, kind: &str) {
+        tracing::warn!("{} requested over tcp, but not supported", kind);
+        stream.close(CloseReason::InvalidUpgrade)
+    }
+
     fn deny_uni(stream: quic::RecvStream, kind: &str) {
         tracing::warn!("unidirectional {} requested", kind);
         stream.close(CloseReason::InvalidUpgrade)
     }
+
+    #[cfg(not(feature = "request-pull"))]
+    fn deny_bidi(stream: quic::BidiStream, kind: &str) {
+        tracing::warn!("{} requested, but not supported", kind);
+        stream.close(CloseReason::InvalidUpgrade)
+    }
 }
@@ -12,8 +12,7 @@ pub mod error;
 pub use error::Error;
 
 mod hpv;
-pub(super) use hpv::TnT;
-pub use hpv::{Hpv, Shuffle};
+pub use hpv::{Hpv, Shuffle, TnT};
 
 mod params;
 pub use params::Params;
@@ -25,7 +24,10 @@ mod periodic;
 pub use periodic::Periodic;
 
 mod rpc;
-pub use rpc::Message;
+pub use rpc::{Message, Priority};
+
+pub mod snapshot;
+pub use snapshot::Snapshot;
 
 mod tick;
 pub use tick::Tick;
@@ -4,6 +4,7 @@
 // Linking Exception. For full terms see the included LICENSE file.
 
 use std::{
+    collections::BTreeSet,
     fmt::Debug,
     iter::{self, FromIterator},
     ops::Mul,
@@ -20,11 +21,12 @@ use super::{
     partial_view::{PartialView, Transition},
     periodic::{periodic_tasks, Periodic},
     rpc,
+    snapshot::{self, Direction, History, ShuffleEvent, Snapshot},
     Params,
     Tick,
 };
 use crate::{
-    net::protocol::info::{PartialPeerInfo, PeerAdvertisement, PeerInfo},
+    net::protocol::info::{Capability, PartialPeerInfo, PeerAdvertisement, PeerInfo},
     PeerId,
 };
 
@@ -131,12 +133,21 @@ where
         Rng: Send + Sync + 'static,
         Addr: Send + Sync + 'static,
     {
-        let this = Self(Arc::new(RwLock::new(HpvInner::new(local_id, rng, params))));
+        let this = Self::unscheduled(local_id, rng, params);
         let periodic = periodic_tasks(this.clone());
 
         (this, periodic)
     }
 
+    /// Create a [`Hpv`] without a timer-driven stream of [`Periodic`] tasks.
+    ///
+    /// The caller is responsible for invoking [`Hpv::shuffle`] and
+    /// [`Hpv::choose_passive_to_promote`] at the configured intervals, eg.
+    /// from a simulated clock.
+    pub fn unscheduled(local_id: PeerId, rng: Rng, params: Params) -> Self {
+        Self(Arc::new(RwLock::new(HpvInner::new(local_id, rng, params))))
+    }
+
     pub fn view_stats(&self) -> (usize, usize) {
         let guard = self.0.read();
         (guard.num_active(), guard.num_passive())
@@ -166,6 +177,11 @@ where
         self.0.read().passive().collect()
     }
 
+    /// The capabilities `peer` advertised, empty if unknown.
+    pub fn capabilities(&self, peer: &PeerId) -> BTreeSet<Capability> {
+        self.0.read().capabilities(peer)
+    }
+
     #[tracing::instrument(level = "debug", skip(self))]
     #[must_use = "ticks must be interpreted"]
     pub fn connection_lost(&self, remote_peer: PeerId) -> TnT<Addr> {
@@ -179,11 +195,11 @@ where
     }
 
     #[must_use = "shuffles must be dispatched"]
-    pub(super) fn shuffle(&self) -> Option<Shuffle<Addr>> {
+    pub fn shuffle(&self) -> Option<Shuffle<Addr>> {
         self.0.write().shuffle()
     }
 
-    pub(super) fn choose_passive_to_promote(&self) -> Vec<PeerInfo<Addr>> {
+    pub fn choose_passive_to_promote(&self) -> Vec<PeerInfo<Addr>> {
         self.0.write().choose_passive_to_promote()
     }
 
@@ -221,6 +237,11 @@ where
     pub(super) fn params(&self) -> Params {
         self.0.read().params.clone()
     }
+
+    /// Capture the current membership state, including recent shuffles.
+    pub fn snapshot(&self) -> Snapshot<Addr> {
+        self.0.read().snapshot()
+    }
 }
 
 struct HpvInner<Rng, Addr> {
@@ -228,6 +249,7 @@ struct HpvInner<Rng, Addr> {
     params: Params,
     rng: Rng,
     view: PartialView<Rng, Addr>,
+    shuffles: History,
 }
 
 impl<Rng, Addr> HpvInner<Rng, Addr>
@@ -242,6 +264,7 @@ where
             params,
             rng,
             view,
+            shuffles: History::default(),
         }
     }
 
@@ -261,6 +284,10 @@ where
         self.view.num_active()
     }
 
+    pub fn capabilities(&self, peer: &PeerId) -> BTreeSet<Capability> {
+        self.view.capabilities(peer).cloned().unwrap_or_default()
+    }
+
     pub fn num_passive(&self) -> usize {
         self.view.num_passive()
     }
@@ -310,6 +337,11 @@ where
             if sample.is_empty() {
                 None
             } else {
+                self.shuffles.record(ShuffleEvent::new(
+                    Direction::Sent,
+                    recipient,
+                    sample.iter().map(|info| info.peer_id).collect(),
+                ));
                 Some(Shuffle {
                     recipient,
                     sample,
@@ -424,6 +456,11 @@ where
             Disconnect => Ok(self.view.demote(&remote_peer).into_iter().collect()),
 
             Shuffle { origin, peers, ttl } if ttl == 0 && origin.peer_id != self.local_id => {
+                self.shuffles.record(ShuffleEvent::new(
+                    Direction::Received,
+                    origin.peer_id,
+                    peers.iter().map(|info| info.peer_id).collect(),
+                ));
                 let sample = self.sample(peers.len()).collect::<Vec<_>>();
                 let tnt = if !sample.is_empty() {
                     iter::once(Try {
@@ -474,9 +511,16 @@ where
             // TTL expired
             Shuffle { .. } => Ok(TnT::default()),
 
-            ShuffleReply { peers } => Ok(peers.into_iter().fold(TnT::default(), |acc, info| {
-                acc * self.view.add_passive(info).into_iter().collect()
-            })),
+            ShuffleReply { peers } => {
+                self.shuffles.record(ShuffleEvent::new(
+                    Direction::Replied,
+                    remote_peer,
+                    peers.iter().map(|info| info.peer_id).collect(),
+                ));
+                Ok(peers.into_iter().fold(TnT::default(), |acc, info| {
+                    acc * self.view.add_passive(info).into_iter().collect()
+                }))
+            },
         };
 
         tracing::debug!(
@@ -489,6 +533,33 @@ where
         res
     }
 
+    pub fn snapshot(&self) -> Snapshot<Addr> {
+        let active = self
+            .view
+            .active_info()
+            .map(|info| {
+                let (peer_id, addrs) = info.into();
+                snapshot::Member { peer_id, addrs }
+            })
+            .collect();
+        let passive = self
+            .view
+            .passive_info()
+            .map(|info| {
+                let (peer_id, addrs) = info.into();
+                snapshot::Member { peer_id, addrs }
+            })
+            .collect();
+
+        Snapshot {
+            local_id: self.local_id,
+            params: self.params.clone(),
+            active,
+            passive,
+            shuffles: self.shuffles.to_vec(),
+        }
+    }
+
     fn random_active(&mut self) -> Option<PeerId> {
         self.view.active().choose(&mut self.rng)
     }
@@ -5,7 +5,7 @@
 
 use std::time::Duration;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct Params {
     /// Maximum number of active connections.
     pub max_active: usize,
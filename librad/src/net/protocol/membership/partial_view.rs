@@ -3,12 +3,15 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{collections::BTreeMap, iter};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    iter,
+};
 
 use rand::seq::IteratorRandom as _;
 
 use crate::{
-    net::protocol::info::{PartialPeerInfo, PeerInfo},
+    net::protocol::info::{Capability, PartialPeerInfo, PeerInfo},
     PeerId,
 };
 
@@ -81,6 +84,15 @@ where
         self.passive.values().cloned()
     }
 
+    /// The capabilities `peer` advertised, if it is known and did advertise.
+    pub fn capabilities(&self, peer: &PeerId) -> Option<&BTreeSet<Capability>> {
+        self.active
+            .get(peer)
+            .and_then(|info| info.advertised_info.as_ref())
+            .or_else(|| self.passive.get(peer).map(|info| &info.advertised_info))
+            .map(|ad| &ad.capabilities)
+    }
+
     pub fn num_active(&self) -> usize {
         self.active.len()
     }
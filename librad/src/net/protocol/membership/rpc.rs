@@ -103,3 +103,46 @@ impl<'b> minicbor::Decode<'b> for Priority {
         }
     }
 }
+
+#[cfg(feature = "fuzzing")]
+mod fuzzing {
+    use arbitrary::{Arbitrary, Result, Unstructured};
+
+    use super::{Message, Priority};
+
+    impl<'a, A> Arbitrary<'a> for Message<A>
+    where
+        A: Arbitrary<'a>,
+    {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            Ok(match u.int_in_range(0..=5)? {
+                0 => Self::Join {
+                    info: u.arbitrary()?,
+                },
+                1 => Self::ForwardJoin {
+                    joined: u.arbitrary()?,
+                    ttl: u.arbitrary()?,
+                },
+                2 => Self::Neighbour {
+                    info: u.arbitrary()?,
+                    prio: u.arbitrary()?,
+                },
+                3 => Self::Shuffle {
+                    origin: u.arbitrary()?,
+                    peers: u.arbitrary()?,
+                    ttl: u.arbitrary()?,
+                },
+                4 => Self::ShuffleReply {
+                    peers: u.arbitrary()?,
+                },
+                _ => Self::Disconnect,
+            })
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for Priority {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+            u.choose(&[Self::Normal, Self::High]).copied()
+        }
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    collections::VecDeque,
+    fmt::{Display, Write as _},
+};
+
+use serde::Serialize;
+use std_ext::time::unix_now;
+
+use super::Params;
+use crate::PeerId;
+
+/// Number of [`ShuffleEvent`]s to retain.
+pub(super) const SHUFFLE_HISTORY: usize = 32;
+
+/// A point-in-time view of the membership state of a peer.
+///
+/// Obtained via [`super::Hpv::snapshot`], or
+/// [`crate::net::peer::Peer::membership_snapshot`].
+#[derive(Clone, Debug, Serialize)]
+pub struct Snapshot<Addr> {
+    pub local_id: PeerId,
+    pub params: Params,
+    pub active: Vec<Member<Addr>>,
+    pub passive: Vec<Member<Addr>>,
+    /// The most recent shuffles, oldest first.
+    pub shuffles: Vec<ShuffleEvent>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Member<Addr> {
+    pub peer_id: PeerId,
+    pub addrs: Vec<Addr>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// We initiated a shuffle with `peer`.
+    Sent,
+    /// A shuffle originating from `peer` terminated at us.
+    Received,
+    /// `peer` replied to a shuffle we initiated.
+    Replied,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ShuffleEvent {
+    /// Seconds since the UNIX epoch.
+    pub timestamp: u64,
+    pub direction: Direction,
+    pub peer: PeerId,
+    /// The peers exchanged in the shuffle.
+    pub sample: Vec<PeerId>,
+}
+
+impl ShuffleEvent {
+    pub(super) fn new(direction: Direction, peer: PeerId, sample: Vec<PeerId>) -> Self {
+        Self {
+            timestamp: unix_now(),
+            direction,
+            peer,
+            sample,
+        }
+    }
+}
+
+/// Bounded history of [`ShuffleEvent`]s.
+#[derive(Clone, Debug, Default)]
+pub(super) struct History(VecDeque<ShuffleEvent>);
+
+impl History {
+    pub fn record(&mut self, event: ShuffleEvent) {
+        if self.0.len() == SHUFFLE_HISTORY {
+            self.0.pop_front();
+        }
+        self.0.push_back(event)
+    }
+
+    pub fn to_vec(&self) -> Vec<ShuffleEvent> {
+        self.0.iter().cloned().collect()
+    }
+}
+
+impl<Addr> Snapshot<Addr> {
+    /// Render the snapshot as a graphviz digraph.
+    ///
+    /// Edges from the local peer to active members are drawn solid, edges to
+    /// passive members dashed. Peers learned about through recent shuffles are
+    /// connected to the peer they were exchanged with using dotted edges.
+    pub fn to_dot(&self) -> String
+    where
+        Addr: Display,
+    {
+        let mut dot = String::from("digraph membership {\n");
+        writeln!(dot, "  \"{}\" [shape=doublecircle];", self.local_id).ok();
+        for (members, style) in [(&self.active, "solid"), (&self.passive, "dashed")] {
+            for Member { peer_id, addrs } in members {
+                let label = addrs
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("\\n");
+                writeln!(
+                    dot,
+                    "  \"{}\" [label=\"{}\\n{}\"];",
+                    peer_id, peer_id, label
+                )
+                .ok();
+                writeln!(
+                    dot,
+                    "  \"{}\" -> \"{}\" [style={}];",
+                    self.local_id, peer_id, style
+                )
+                .ok();
+            }
+        }
+        for ShuffleEvent { peer, sample, .. } in &self.shuffles {
+            for sampled in sample {
+                writeln!(dot, "  \"{}\" -> \"{}\" [style=dotted];", peer, sampled).ok();
+            }
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Access control for the git server.
+//!
+//! By default, any peer may fetch any namespace from the monorepo. Embedding
+//! applications can install a [`ReadAccess`] callback via
+//! [`super::Config::read_access`], which is consulted whenever a peer requests
+//! a namespace. It may refuse to serve the namespace altogether, or hide some
+//! categories of refs from the peer.
+//!
+//! Note that hiding refs which are listed in the `rad/signed_refs` of a peer
+//! will cause replication of that peer's view to fail for the requesting peer.
+//!
+//! Clients of the git http server (`crate::git::http`) are not authenticated,
+//! and are subject to [`ReadAccess::with_anonymous`] instead. Once a callback
+//! for peers is installed, anonymous clients are denied unless such a callback
+//! is installed, too.
+
+use std::{fmt, sync::Arc};
+
+use crate::{git::Urn, PeerId};
+
+/// A category of refs within a namespace.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    /// `refs/heads`
+    Heads,
+    /// `refs/tags`
+    Tags,
+    /// `refs/notes`
+    Notes,
+    /// `refs/cobs`
+    Cobs,
+    /// `refs/remotes`, ie. the views of all other peers
+    Remotes,
+    /// `refs/remotes/<peer>`, ie. the view of a particular peer
+    Remote(PeerId),
+}
+
+impl Category {
+    /// The `uploadpack.hideRefs` pattern, relative to the namespace.
+    pub fn pattern(&self) -> String {
+        match self {
+            Self::Heads => "refs/heads".to_owned(),
+            Self::Tags => "refs/tags".to_owned(),
+            Self::Notes => "refs/notes".to_owned(),
+            Self::Cobs => "refs/cobs".to_owned(),
+            Self::Remotes => "refs/remotes".to_owned(),
+            Self::Remote(peer) => format!("refs/remotes/{}", peer),
+        }
+    }
+}
+
+/// The decision of a [`ReadAccess`] callback.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// Serve the namespace.
+    Allow,
+    /// Serve the namespace, but hide the given categories of refs.
+    Hide(Vec<Category>),
+    /// Refuse to serve the namespace.
+    Deny,
+}
+
+type Callback = dyn Fn(&PeerId, &Urn) -> Decision + Send + Sync;
+type AnonymousCallback = dyn Fn(&Urn) -> Decision + Send + Sync;
+
+/// Decides whether a (authenticated) peer may fetch a namespace.
+///
+/// The callback is invoked synchronously on the protocol's executor, and
+/// should thus return quickly.
+#[derive(Clone, Default)]
+pub struct ReadAccess {
+    peer: Option<Arc<Callback>>,
+    anonymous: Option<Arc<AnonymousCallback>>,
+}
+
+impl ReadAccess {
+    /// Allow any peer to fetch any namespace.
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(&PeerId, &Urn) -> Decision + Send + Sync + 'static,
+    {
+        Self {
+            peer: Some(Arc::new(f)),
+            anonymous: None,
+        }
+    }
+
+    /// Decide with `f` whether unauthenticated clients may fetch a namespace.
+    pub fn with_anonymous<F>(self, f: F) -> Self
+    where
+        F: Fn(&Urn) -> Decision + Send + Sync + 'static,
+    {
+        Self {
+            anonymous: Some(Arc::new(f)),
+            ..self
+        }
+    }
+
+    /// Decide whether `peer` may fetch the namespace `namespace`, as requested
+    /// by it.
+    ///
+    /// If a callback is installed, requests for invalid namespaces are denied.
+    pub fn decide(&self, peer: &PeerId, namespace: &str) -> Decision {
+        match &self.peer {
+            None => Decision::Allow,
+            Some(f) => match Urn::try_from_id(namespace) {
+                Ok(urn) => f(peer, &urn),
+                Err(_) => Decision::Deny,
+            },
+        }
+    }
+
+    /// Decide whether an unauthenticated client may fetch `urn`.
+    ///
+    /// Without any callbacks, this is allowed. If only a callback for peers is
+    /// installed, it is denied.
+    pub fn decide_anonymous(&self, urn: &Urn) -> Decision {
+        match (&self.anonymous, &self.peer) {
+            (Some(f), _) => f(urn),
+            (None, Some(_)) => Decision::Deny,
+            (None, None) => Decision::Allow,
+        }
+    }
+}
+
+impl fmt::Debug for ReadAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.peer, &self.anonymous) {
+            (None, None) => f.write_str("ReadAccess(AllowAll)"),
+            (Some(_), None) => f.write_str("ReadAccess(<callback>)"),
+            (None, Some(_)) => f.write_str("ReadAccess(<anonymous callback>)"),
+            (Some(_), Some(_)) => f.write_str("ReadAccess(<callback>, <anonymous callback>)"),
+        }
+    }
+}
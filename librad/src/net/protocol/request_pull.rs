@@ -1,18 +1,33 @@
 // Copyright © 2022 The Radicle Link Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+// Without the `request-pull` feature, only the client side is available.
+#![cfg_attr(not(feature = "request-pull"), allow(dead_code))]
+
+use std::{num::NonZeroUsize, sync::Arc};
+
 use link_async::Spawner;
 use thiserror::Error;
+use tokio::sync::Semaphore;
 
 use crate::{
-    git::{storage, storage::PoolError, Urn},
-    net::{quic, replication},
+    git::{identities, storage, storage::PoolError, Urn},
+    net::replication,
     paths::Paths,
     PeerId,
 };
 
+pub mod policy;
+pub use policy::{Authorization, Decision, Policy};
+
+#[cfg(feature = "request-pull")]
+pub mod review;
+
+mod observe;
+pub(in crate::net::protocol) use observe::Observed;
+
 mod rpc;
-pub use rpc::{Error, Progress, Ref, Request, Response, Success};
+pub use rpc::{Error, Phase, Progress, Ref, Request, Response, Success};
 
 /// Buffer size for writing and reading request-pull RPC messages.
 /// It is based on the [`Success`] response which would be considered the
@@ -36,12 +51,22 @@ pub trait Guard {
     fn guard(&self, peer: &PeerId, urn: &Urn) -> Result<Self::Output, Self::Error>;
 }
 
+/// Default limit of concurrent replications, cf.
+/// [`State::with_max_concurrent`].
+const DEFAULT_MAX_CONCURRENT: usize = 8;
+
 /// State for serving request-pull calls.
 #[derive(Clone)]
 pub struct State<S, G> {
     storage: S,
     paths: Paths,
     guard: G,
+    policy: Authorization,
+    replications: Arc<Semaphore>,
+    scheduler: replication::Scheduler,
+    haves: replication::haves::Cache,
+    #[cfg(feature = "request-pull")]
+    review: review::Config,
 }
 
 impl<S, G: Guard> State<S, G> {
@@ -50,9 +75,51 @@ impl<S, G: Guard> State<S, G> {
             storage,
             paths,
             guard,
+            policy: Authorization::default(),
+            replications: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT)),
+            scheduler: replication::Scheduler::default(),
+            haves: replication::haves::Cache::default(),
+            #[cfg(feature = "request-pull")]
+            review: review::Config::default(),
         }
     }
 
+    /// Limit the number of replications run concurrently to `max`.
+    ///
+    /// Requests exceeding the limit wait for a slot to become available.
+    pub fn with_max_concurrent(self, max: NonZeroUsize) -> Self {
+        Self {
+            replications: Arc::new(Semaphore::new(max.get())),
+            ..self
+        }
+    }
+
+    /// Obtain replication slots from `scheduler`, shared with the other
+    /// replication runs of the peer.
+    ///
+    /// Request-pulls are scheduled with [`replication::Priority::Interactive`].
+    pub fn with_scheduler(self, scheduler: replication::Scheduler) -> Self {
+        Self { scheduler, ..self }
+    }
+
+    /// Pick the `have`s hinting at objects from other namespaces from
+    /// `haves`, shared with the other replication runs of the peer.
+    pub fn with_haves(self, haves: replication::haves::Cache) -> Self {
+        Self { haves, ..self }
+    }
+
+    /// Check updates of the default branch against the review policy of the
+    /// project, see [`review`].
+    #[cfg(feature = "request-pull")]
+    pub fn with_review(self, review: review::Config) -> Self {
+        Self { review, ..self }
+    }
+
+    /// Evaluate `policy` before running the [`Guard`], see [`policy`].
+    pub fn with_policy(self, policy: Authorization) -> Self {
+        Self { policy, ..self }
+    }
+
     pub fn guard(&self, peer: &PeerId, urn: &Urn) -> Result<G::Output, G::Error> {
         self.guard.guard(peer, urn)
     }
@@ -71,6 +138,11 @@ pub(in crate::net::protocol) mod error {
         Init(#[from] replication::error::Init),
         #[error("internal error: failed to look up symbolic-ref target")]
         Read(#[from] storage::read::Error),
+        #[error("internal error: failed to read identity")]
+        Identities(#[from] identities::Error),
+        #[cfg(feature = "request-pull")]
+        #[error(transparent)]
+        Review(#[from] review::Error),
     }
 
     pub fn decode_failed() -> Error {
@@ -79,6 +151,12 @@ pub(in crate::net::protocol) mod error {
         }
     }
 
+    pub fn rate_limited() -> Error {
+        Error {
+            message: "rate limit exceeded, try again later".into(),
+        }
+    }
+
     pub fn internal_error() -> Error {
         Error {
             message: "internal error".into(),
@@ -102,21 +180,96 @@ impl<S, G> State<S, G>
 where
     S: storage::Pooled<storage::Storage> + Send + Sync + 'static,
 {
+    /// Evaluate the authorization [`Policy`] for `peer` requesting `urn`.
+    pub(in crate::net::protocol) async fn authorize(
+        &self,
+        peer: &PeerId,
+        urn: &Urn,
+    ) -> Result<(), policy::Error> {
+        self.policy.authorize(&self.storage, peer, urn).await
+    }
+
+    /// Look up the tip of the `rad/signed_refs` of `peer` for `urn`.
+    pub(in crate::net::protocol) async fn signed_refs(
+        &self,
+        urn: &Urn,
+        peer: PeerId,
+    ) -> Result<Option<git_ext::Oid>, error::Replicate> {
+        use crate::git::{
+            storage::ReadOnlyStorage as _,
+            types::{Namespace, Reference},
+        };
+
+        let storage = self.storage.get().await?;
+        let tip = (*storage)
+            .reference(&Reference::rad_signed_refs(Namespace::from(urn), peer))?
+            .and_then(|r| r.target())
+            .map(git_ext::Oid::from);
+        Ok(tip)
+    }
+
+    /// Whether `peer` is a delegate of `urn`, or `None` if `urn` is not known.
+    pub(in crate::net::protocol) async fn is_delegate(
+        &self,
+        urn: &Urn,
+        peer: PeerId,
+    ) -> Result<Option<bool>, error::Replicate> {
+        use crate::git::identities::SomeIdentity;
+
+        let storage = self.storage.get().await?;
+        let delegate = identities::any::get(&*storage, urn)?.map(|identity| match identity {
+            SomeIdentity::Project(project) => {
+                project.delegations().owner(peer.as_public_key()).is_some()
+            },
+            SomeIdentity::Person(person) => person.delegations().contains(peer.as_public_key()),
+            _ => false,
+        });
+        Ok(delegate)
+    }
+
     /// Run replication and convert the updated tips into [`Ref`]s.
-    pub(in crate::net::protocol) async fn replicate(
+    pub(in crate::net::protocol) async fn replicate<F>(
         &self,
         spawner: &Spawner,
         urn: Urn,
-        conn: quic::Connection,
-    ) -> Result<Success, error::Replicate> {
+        conn: F,
+    ) -> Result<Success, error::Replicate>
+    where
+        F: replication::Fetcher,
+    {
         use crate::git::storage::ReadOnlyStorage as _;
         use link_replication::Updated;
 
-        let repl = replication::Replication::new(&self.paths, replication::Config::default())?;
+        let _permit = self
+            .replications
+            .acquire()
+            .await
+            .expect("semaphore is never closed");
+        let repl = replication::Replication::new(
+            &self.paths,
+            replication::Config {
+                scheduler: self.scheduler.clone(),
+                haves: self.haves.clone(),
+                ..replication::Config::default()
+            },
+        )?
+        .prioritise(replication::Priority::Interactive);
         let storage = self.storage.get().await?;
-        let succ = repl.replicate(spawner, storage, conn, urn, None).await?;
+        #[cfg(feature = "request-pull")]
+        let before = if self.review.enforce {
+            Some(review::Snapshot::take(&storage, &urn)?)
+        } else {
+            None
+        };
+        let succ = repl
+            .replicate(spawner, storage, conn, urn.clone(), None)
+            .await?;
 
         let storage = self.storage.get().await?;
+        #[cfg(feature = "request-pull")]
+        if let Some(before) = before {
+            review::enforce(&storage, &urn, &before)?;
+        }
         succ.updated_refs()
             .iter()
             .try_fold(Success::default(), |mut success, up| match up {
@@ -149,18 +302,43 @@ pub mod progress {
     pub fn replicating(urn: &Urn) -> Progress {
         Progress {
             message: format!("Starting replication for `{}`", urn),
+            phase: Some(Phase::Replicating),
         }
     }
 
     pub fn authorizing(urn: &Urn) -> Progress {
         Progress {
             message: format!("Checking if request-pull is allowed for `{}`", urn),
+            phase: Some(Phase::Authorizing),
         }
     }
 
     pub fn guard<T: ToString>(t: T) -> Progress {
         Progress {
             message: t.to_string(),
+            phase: None,
+        }
+    }
+
+    pub fn negotiated(refs: u64) -> Progress {
+        Progress {
+            message: format!("Fetching {} tips", refs),
+            phase: Some(Phase::Negotiated { refs }),
+        }
+    }
+
+    pub fn receiving(objects: u64, total_objects: Option<u64>, bytes: u64) -> Progress {
+        let message = match total_objects {
+            Some(total) => format!("Received {}/{} objects, {} bytes", objects, total, bytes),
+            None => format!("Received {} objects, {} bytes", objects, bytes),
+        };
+        Progress {
+            message,
+            phase: Some(Phase::Receiving {
+                objects,
+                total_objects,
+                bytes,
+            }),
         }
     }
 }
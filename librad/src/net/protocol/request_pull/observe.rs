@@ -0,0 +1,97 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{io, path::Path};
+
+use futures::channel::mpsc;
+use link_git::protocol::Ref;
+use link_replication::{io as rio, LsRefs, Net, ObjectId};
+use radicle_data::NonEmptyVec;
+
+use super::{progress, Progress};
+use crate::{
+    identities::git::Urn,
+    net::replication::{fetcher::Metered, Fetcher},
+    PeerId,
+};
+
+/// A [`Fetcher`] which reports the [`super::Phase::Negotiated`] and
+/// [`super::Phase::Receiving`] phases of every fetch to `progress`.
+///
+/// Replication runs on a different task than the one serving the request, so
+/// the [`Progress`] is sent over a channel. Sending fails silently if the
+/// receiver is gone.
+pub(in crate::net::protocol) struct Observed<F> {
+    inner: F,
+    progress: mpsc::UnboundedSender<Progress>,
+}
+
+impl<F> Observed<F> {
+    pub fn new(inner: F, progress: mpsc::UnboundedSender<Progress>) -> Self {
+        Self { inner, progress }
+    }
+
+    fn report(&self, progress: Progress) {
+        self.progress.unbounded_send(progress).ok();
+    }
+}
+
+impl<F> Fetcher for Observed<F>
+where
+    F: Fetcher,
+{
+    type Net = Observed<F::Net>;
+
+    fn remote_peer(&self) -> PeerId {
+        self.inner.remote_peer()
+    }
+
+    fn net(self, refdb: rio::Refdb<rio::Odb>, git_dir: &Path, urn: &Urn) -> Self::Net {
+        Observed {
+            inner: self.inner.net(refdb, git_dir, urn),
+            progress: self.progress,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl<N> Net for Observed<N>
+where
+    N: Metered,
+{
+    type Error = io::Error;
+
+    async fn run_ls_refs(&self, ls: LsRefs) -> Result<Vec<Ref>, Self::Error> {
+        self.inner.run_ls_refs(ls).await
+    }
+
+    async fn run_fetch(
+        &self,
+        max_pack_bytes: u64,
+        wants: NonEmptyVec<ObjectId>,
+        haves: Vec<ObjectId>,
+    ) -> Result<(), Self::Error> {
+        self.report(progress::negotiated(wants.len() as u64));
+        self.inner.run_fetch(max_pack_bytes, wants, haves).await?;
+        self.report(progress::receiving(
+            self.inner.received_objects(),
+            None,
+            self.inner.received_bytes(),
+        ));
+
+        Ok(())
+    }
+}
+
+impl<N> Metered for Observed<N>
+where
+    N: Metered,
+{
+    fn received_bytes(&self) -> u64 {
+        self.inner.received_bytes()
+    }
+
+    fn received_objects(&self) -> u64 {
+        self.inner.received_objects()
+    }
+}
@@ -0,0 +1,146 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Authorization policies for serving request-pull.
+//!
+//! A [`Policy`] decides whether a request is accepted, rejected, or only
+//! accepted if the requested [`Urn`] is delegated to one of a set of
+//! allow-listed identities. Embedders install it via
+//! [`crate::net::protocol::Config::request_pull_policy`], and it is evaluated
+//! before the [`super::Guard`] of the request-pull responder is run.
+//!
+//! Unlike the [`super::Guard`], which is invoked synchronously, the
+//! [`Authorization`] is evaluated asynchronously: checking delegations
+//! requires a handle to the storage, which is only checked out if the
+//! [`Policy`] returns [`Decision::RequireDelegate`].
+
+use std::{collections::BTreeSet, fmt, sync::Arc};
+
+use thiserror::Error;
+
+use crate::{
+    git::{
+        identities::{self, SomeIdentity},
+        storage,
+        Urn,
+    },
+    PeerId,
+};
+
+/// The outcome of evaluating a [`Policy`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// Accept the request-pull, subject to the [`Guard`].
+    Accept,
+    /// Reject the request-pull. The `reason` is reported back to the
+    /// requester.
+    Reject { reason: String },
+    /// Accept the request-pull only if the requested [`Urn`] is a project
+    /// which is delegated to at least one of the `allowed` person identities,
+    /// or is itself one of the `allowed` identities.
+    RequireDelegate { allowed: BTreeSet<Urn> },
+}
+
+pub trait Policy {
+    /// Decide whether `peer` may request-pull `urn`.
+    fn decide(&self, peer: &PeerId, urn: &Urn) -> Decision;
+}
+
+impl<F> Policy for F
+where
+    F: Fn(&PeerId, &Urn) -> Decision,
+{
+    fn decide(&self, peer: &PeerId, urn: &Urn) -> Decision {
+        self(peer, urn)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("request-pull rejected for `{urn}`: {reason}")]
+    Rejected { urn: Urn, reason: String },
+
+    #[error("request-pull rejected: `{0}` is not delegated to an allowed identity")]
+    NotDelegated(Urn),
+
+    #[error("request-pull rejected: `{0}` is not known")]
+    NotFound(Urn),
+
+    #[error("internal error: failed to get handle to storage")]
+    Pool(#[from] storage::PoolError),
+
+    #[error("internal error: failed to read identity")]
+    Identities(#[from] identities::Error),
+}
+
+/// An optional [`Policy`], evaluated before the request-pull [`super::Guard`].
+///
+/// The default accepts all requests, leaving the decision to the
+/// [`super::Guard`].
+#[derive(Clone, Default)]
+pub struct Authorization(Option<Arc<dyn Policy + Send + Sync>>);
+
+impl Authorization {
+    /// Accept all requests.
+    pub fn allow_all() -> Self {
+        Self(None)
+    }
+
+    pub fn new<P>(policy: P) -> Self
+    where
+        P: Policy + Send + Sync + 'static,
+    {
+        Self(Some(Arc::new(policy)))
+    }
+
+    /// Evaluate the [`Policy`] for `peer` requesting `urn`.
+    ///
+    /// `storage` is only checked out if the [`Decision`] is
+    /// [`Decision::RequireDelegate`].
+    pub async fn authorize<S, T>(&self, storage: &S, peer: &PeerId, urn: &Urn) -> Result<(), Error>
+    where
+        S: storage::Pooled<T> + Send + Sync,
+        T: AsRef<storage::ReadOnly> + Send + 'static,
+    {
+        let policy = match &self.0 {
+            None => return Ok(()),
+            Some(policy) => policy,
+        };
+        match policy.decide(peer, urn) {
+            Decision::Accept => Ok(()),
+            Decision::Reject { reason } => Err(Error::Rejected {
+                urn: urn.clone(),
+                reason,
+            }),
+            Decision::RequireDelegate { allowed } => {
+                if allowed.contains(urn) {
+                    return Ok(());
+                }
+                let storage = storage.get().await?;
+                let delegated = match identities::any::get(&*storage, urn)? {
+                    None => return Err(Error::NotFound(urn.clone())),
+                    Some(SomeIdentity::Project(project)) => project
+                        .delegations()
+                        .into_iter()
+                        .indirect()
+                        .any(|person| allowed.contains(&person.urn())),
+                    Some(_) => false,
+                };
+                if delegated {
+                    Ok(())
+                } else {
+                    Err(Error::NotDelegated(urn.clone()))
+                }
+            },
+        }
+    }
+}
+
+impl fmt::Debug for Authorization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            None => f.write_str("Authorization(AllowAll)"),
+            Some(_) => f.write_str("Authorization(<policy>)"),
+        }
+    }
+}
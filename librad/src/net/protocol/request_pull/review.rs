@@ -0,0 +1,357 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Enforced review of updates to the default branch.
+//!
+//! A project may declare a [`Review`] policy, which maps paths to the keys
+//! required to approve changes to them. When [`Config::enforce`] is set, a
+//! request-pull responder checks, after replicating, every peer's update of
+//! the project's default branch against the policy: each path touched
+//! between the previous and the new tip must be covered by enough approvals
+//! of the new tip by the keys its [`crate::git::identities::review::Rule`]
+//! lists. If an update lacks approvals, all refs of the offending peer are
+//! rolled back to their state before the request, and the request is
+//! rejected.
+//!
+//! Approvals are collaborative objects of type [`struct@APPROVAL`], whose
+//! contents name the approved commit, see [`approval`]. The keys an approval
+//! counts for are those which signed the change creating the object --
+//! subsequent changes to the object are ignored. The requester is expected
+//! to carry the approvals along with the update, ie. have them under its
+//! `cobs` refs.
+//!
+//! If a peer's default branch was not known before, the whole tree of the
+//! new tip is considered touched.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+};
+
+use git_ext as ext;
+use thiserror::Error;
+
+use crate::{
+    collaborative_objects::{self, EntryContents, ObjectId, TypeName},
+    git::{
+        identities::{self, review::Review},
+        storage::{self, transaction::Previous, Storage, Transaction},
+        Urn,
+    },
+    identities::sign::Signatures,
+    PublicKey,
+};
+
+lazy_static! {
+    /// The type of collaborative objects approving a commit.
+    pub static ref APPROVAL: TypeName = "xyz.radicle.approval".parse().unwrap();
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Config {
+    /// Reject updates of the default branch which lack the approvals
+    /// required by the [`Review`] policy of the project.
+    pub enforce: bool,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error(
+        "update of `{name}` to {tip} lacks approvals for {}",
+        .missing.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+    )]
+    Unapproved {
+        name: String,
+        tip: ext::Oid,
+        missing: Vec<Missing>,
+    },
+
+    #[error(transparent)]
+    Policy(#[from] identities::review::Error),
+
+    #[error(transparent)]
+    Identities(#[from] identities::Error),
+
+    #[error(transparent)]
+    Cob(#[from] collaborative_objects::error::Retrieve),
+
+    #[error(transparent)]
+    Transaction(#[from] storage::transaction::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// A [`crate::git::identities::review::Rule`] lacking approvals.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Missing {
+    pub path: String,
+    pub approvals: usize,
+    pub required: usize,
+}
+
+impl std::fmt::Display for Missing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "`{}` ({}/{} approvals)",
+            self.path, self.approvals, self.required
+        )
+    }
+}
+
+/// An approval of a commit.
+#[derive(Clone, Debug)]
+pub struct Approval {
+    pub object: ObjectId,
+    pub commit: ext::Oid,
+    /// The keys which validly signed the approval.
+    pub keys: BTreeSet<PublicKey>,
+}
+
+/// The contents of a new [`struct@APPROVAL`] object approving `commit`.
+pub fn approval(commit: ext::Oid) -> EntryContents {
+    let mut backend = automerge::Backend::new();
+    let mut frontend = automerge::Frontend::new();
+    let (_, change) = frontend
+        .change::<_, _, automerge::InvalidChangeRequest>(None, |d| {
+            d.add_change(automerge::LocalChange::set(
+                automerge::Path::root().key("commit"),
+                automerge::Value::Primitive(automerge::Primitive::Str(commit.to_string().into())),
+            ))
+        })
+        .expect("setting a key of an empty document is valid");
+    let (_, change) = backend
+        .apply_local_change(change.expect("document was changed"))
+        .expect("applying a fresh local change is valid");
+    EntryContents::Automerge(change.raw_bytes().to_vec())
+}
+
+/// All valid [`Approval`]s stored for `urn`.
+pub fn approvals(storage: &Storage, urn: &Urn) -> Result<Vec<Approval>, Error> {
+    let repo = storage.as_raw();
+    let mut approvals = Vec::new();
+    for object in storage.collaborative_objects(None).list(urn, &APPROVAL)? {
+        let change = repo.find_commit((*object.id()).into())?;
+        let keys = match Signatures::try_from(&change) {
+            Ok(sigs) => sigs
+                .iter()
+                .filter(|(key, sig)| key.verify(sig, change.tree_id().as_bytes()))
+                .map(|(key, _)| *key)
+                .collect(),
+            Err(e) => {
+                tracing::warn!(object = %object.id(), err = %e, "skipping unsigned approval");
+                continue;
+            },
+        };
+        let contents = match change.tree()?.get_name("change") {
+            Some(entry) => entry.to_object(repo)?.peel_to_blob()?.content().to_vec(),
+            None => continue,
+        };
+        match approved(contents) {
+            Some(commit) => approvals.push(Approval {
+                object: *object.id(),
+                commit,
+                keys,
+            }),
+            None => {
+                tracing::warn!(object = %object.id(), "skipping malformed approval")
+            },
+        }
+    }
+    Ok(approvals)
+}
+
+fn approved(contents: Vec<u8>) -> Option<ext::Oid> {
+    let change = automerge::Change::from_bytes(contents).ok()?;
+    let mut backend = automerge::Backend::new();
+    backend.apply_changes(vec![change]).ok()?;
+    let mut frontend = automerge::Frontend::new();
+    frontend.apply_patch(backend.get_patch().ok()?).ok()?;
+    let commit = frontend.state().to_json();
+    let commit = commit.get("commit")?.as_str()?;
+    git2::Oid::from_str(commit).ok().map(ext::Oid::from)
+}
+
+/// The paths touched between the trees of `base` and `tip`.
+///
+/// If `base` is `None`, all paths of `tip` are considered touched.
+pub fn touched(
+    repo: &git2::Repository,
+    base: Option<git2::Oid>,
+    tip: git2::Oid,
+) -> Result<BTreeSet<String>, git2::Error> {
+    let old = base
+        .map(|base| repo.find_commit(base).and_then(|commit| commit.tree()))
+        .transpose()?;
+    let new = repo.find_commit(tip)?.tree()?;
+    let diff = repo.diff_tree_to_tree(old.as_ref(), Some(&new), None)?;
+
+    let mut paths = BTreeSet::new();
+    for delta in diff.deltas() {
+        for file in [delta.old_file(), delta.new_file()] {
+            if let Some(path) = file.path().and_then(|path| path.to_str()) {
+                paths.insert(path.to_owned());
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// The rules of `policy` which lack approvals of `tip` for the `paths` it
+/// touches.
+pub fn missing<'a>(
+    policy: &Review,
+    paths: impl IntoIterator<Item = &'a String>,
+    tip: ext::Oid,
+    approvals: &[Approval],
+) -> Vec<Missing> {
+    let approved_by = approvals
+        .iter()
+        .filter(|approval| approval.commit == tip)
+        .flat_map(|approval| approval.keys.iter())
+        .collect::<BTreeSet<_>>();
+
+    let mut rules = BTreeMap::new();
+    for path in paths {
+        if let Some(rule) = policy.rule(path) {
+            rules.insert(rule.path.as_str(), rule);
+        }
+    }
+
+    rules
+        .into_values()
+        .filter_map(|rule| {
+            let approvals = rule
+                .approvers
+                .iter()
+                .filter(|key| approved_by.contains(key))
+                .count();
+            (approvals < rule.required).then(|| Missing {
+                path: rule.path.clone(),
+                approvals,
+                required: rule.required,
+            })
+        })
+        .collect()
+}
+
+/// The refs of a namespace at some point in time.
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot(BTreeMap<String, git2::Oid>);
+
+impl Snapshot {
+    pub fn take(storage: &Storage, urn: &Urn) -> Result<Self, Error> {
+        let mut refs = BTreeMap::new();
+        for reference in storage
+            .as_raw()
+            .references_glob(&format!("{}*", namespace(urn)))?
+        {
+            let reference = reference?;
+            if let (Some(name), Some(oid)) = (reference.name(), reference.target()) {
+                refs.insert(name.to_owned(), oid);
+            }
+        }
+        Ok(Self(refs))
+    }
+
+    fn under<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a String, &'a git2::Oid)> {
+        self.0
+            .range(prefix.to_owned()..)
+            .take_while(move |(name, _)| name.starts_with(prefix))
+    }
+}
+
+/// Check the updates of the default branch of `urn` since `before` against
+/// the [`Review`] policy of `urn`.
+///
+/// The refs of peers whose update lacks approvals are restored to their
+/// state in `before`, and the first such update is reported as
+/// [`Error::Unapproved`]. Projects without a policy or default branch are
+/// not checked.
+pub fn enforce(storage: &Storage, urn: &Urn, before: &Snapshot) -> Result<(), Error> {
+    let policy = match identities::review::get(storage, urn)? {
+        Some(policy) => policy,
+        None => return Ok(()),
+    };
+    let branch = match identities::project::get(storage, urn)?
+        .and_then(|project| project.payload().subject.default_branch.clone())
+    {
+        Some(branch) => format!("heads/{}", branch),
+        None => return Ok(()),
+    };
+
+    let after = Snapshot::take(storage, urn)?;
+    let remotes = format!("{}refs/remotes/", namespace(urn));
+    let mut cached = None;
+    let mut rejected = Vec::new();
+    for (name, tip) in after.under(&remotes) {
+        let peer = match name[remotes.len()..].split_once('/') {
+            Some((peer, rest)) if rest == branch => peer,
+            _ => continue,
+        };
+        let base = before.0.get(name).copied();
+        if base == Some(*tip) {
+            continue;
+        }
+
+        let paths = touched(storage.as_raw(), base, *tip)?;
+        if cached.is_none() {
+            cached = Some(approvals(storage, urn)?);
+        }
+        let missing = missing(
+            &policy,
+            &paths,
+            (*tip).into(),
+            cached.as_deref().unwrap_or_default(),
+        );
+        if !missing.is_empty() {
+            tracing::warn!(%urn, %peer, "rejecting unapproved update of default branch");
+            rejected.push((
+                format!("{}{}/", remotes, peer),
+                Error::Unapproved {
+                    name: name[namespace(urn).len()..].to_owned(),
+                    tip: (*tip).into(),
+                    missing,
+                },
+            ));
+        }
+    }
+
+    let mut tx = storage.transaction();
+    for (prefix, _) in &rejected {
+        restore(&mut tx, prefix, before, &after);
+    }
+    tx.commit()?;
+    match rejected.into_iter().next() {
+        None => Ok(()),
+        Some((_, err)) => Err(err),
+    }
+}
+
+/// Stage restoring the refs under `prefix` to their state in `before`.
+fn restore(tx: &mut Transaction, prefix: &str, before: &Snapshot, after: &Snapshot) {
+    const MSG: &str = "review: rejected update";
+
+    for (name, oid) in after.under(prefix) {
+        match before.0.get(name) {
+            Some(prev) if prev == oid => {},
+            Some(prev) => {
+                tx.update(name, *prev, Previous::Is((*oid).into()), MSG);
+            },
+            None => {
+                tx.delete(name, Previous::Is((*oid).into()));
+            },
+        }
+    }
+    for (name, prev) in before.under(prefix) {
+        if !after.0.contains_key(name) {
+            tx.update(name, *prev, Previous::Absent, MSG);
+        }
+    }
+}
+
+fn namespace(urn: &Urn) -> String {
+    format!("refs/namespaces/{}/", urn.encode_id())
+}
@@ -46,6 +46,15 @@ pub struct Request {
     pub urn: Urn,
 }
 
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for Request {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self {
+            urn: u.arbitrary()?,
+        })
+    }
+}
+
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Encode, Decode)]
 #[cbor(array)]
 pub struct Error {
@@ -84,6 +93,20 @@ pub struct Ref {
 pub struct Progress {
     #[n(0)]
     pub message: String,
+    /// The structured counterpart of `message`.
+    ///
+    /// This is `None` if the responder does not support structured progress,
+    /// or if the message is purely informational (eg. the output of a
+    /// [`super::Guard`]).
+    #[n(1)]
+    pub phase: Option<Phase>,
+}
+
+impl Progress {
+    /// The completion percentage of the current [`Phase`], if known.
+    pub fn percentage(&self) -> Option<u8> {
+        self.phase.as_ref().and_then(Phase::percentage)
+    }
 }
 
 impl fmt::Display for Progress {
@@ -91,3 +114,65 @@ impl fmt::Display for Progress {
         f.write_str(&self.message)
     }
 }
+
+/// The phase a request-pull is in, as reported by the responder.
+///
+/// Phases are reported in the order of declaration, although a responder may
+/// skip phases it does not perform, or report [`Phase::Receiving`] multiple
+/// times.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Encode, Decode)]
+pub enum Phase {
+    /// Checking whether the request is allowed.
+    #[n(0)]
+    #[cbor(array)]
+    Authorizing,
+    /// The request was allowed, replication is about to start.
+    #[n(1)]
+    #[cbor(array)]
+    Replicating,
+    /// The refs to fetch have been negotiated with the requester, and a fetch
+    /// of `refs` tips is about to start.
+    ///
+    /// Replication may fetch in several rounds, each of which is reported.
+    #[n(2)]
+    #[cbor(array)]
+    Negotiated {
+        #[n(0)]
+        refs: u64,
+    },
+    /// Objects are being received from the requester.
+    ///
+    /// The counts are cumulative over all fetch rounds so far.
+    #[n(3)]
+    #[cbor(array)]
+    Receiving {
+        #[n(0)]
+        objects: u64,
+        /// The total number of objects to receive, if known.
+        #[n(1)]
+        total_objects: Option<u64>,
+        #[n(2)]
+        bytes: u64,
+    },
+    /// Verifying the received state.
+    #[n(4)]
+    #[cbor(array)]
+    Verifying,
+}
+
+impl Phase {
+    /// The completion percentage of this phase, if known.
+    ///
+    /// Only [`Phase::Receiving`] with a known, non-zero `total_objects`
+    /// yields a value.
+    pub fn percentage(&self) -> Option<u8> {
+        match self {
+            Self::Receiving {
+                objects,
+                total_objects: Some(total),
+                ..
+            } if *total > 0 => Some((objects.min(total) * 100 / total) as u8),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! The counterpart to [`super::request_pull`].
+//!
+//! A request-push offers the responder the requester's current
+//! `rad/signed_refs` for a [`Urn`]. Before fetching anything, the responder
+//! verifies that the requester is a delegate of the [`Urn`] as it is known
+//! locally, and that the requester advertises the offered signed refs. Only
+//! then does it fetch from the requester. The requester may have moved its
+//! signed refs by the time the fetch happens, in which case the later ones are
+//! replicated, verified like any other fetch. The response shape is the same as
+//! for request-pull.
+
+use minicbor::{Decode, Encode};
+
+use crate::{git::Urn, PeerId};
+
+pub use super::request_pull::{Error, Phase, Progress, Ref, Response, Success, FRAMED_BUFSIZ};
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Encode, Decode)]
+#[cbor(array)]
+pub struct Request {
+    #[n(0)]
+    pub urn: Urn,
+    /// The tip of the requester's `rad/signed_refs` for `urn`.
+    #[n(1)]
+    pub signed_refs: git_ext::Oid,
+}
+
+#[cfg(feature = "request-pull")]
+pub(in crate::net::protocol) mod error {
+    use super::*;
+
+    pub fn unknown_urn(urn: &Urn) -> Error {
+        Error {
+            message: format!(
+                "request-push rejected: `{}` is not known, use request-pull instead",
+                urn
+            ),
+        }
+    }
+
+    pub fn not_a_delegate(urn: &Urn, peer: PeerId) -> Error {
+        Error {
+            message: format!(
+                "request-push rejected: {} is not a delegate of `{}`",
+                peer, urn
+            ),
+        }
+    }
+
+    pub fn advertised_mismatch(offered: git_ext::Oid, advertised: Option<git_ext::Oid>) -> Error {
+        let message = match advertised {
+            None => format!(
+                "request-push verification error: offered signed refs {} are not advertised",
+                offered
+            ),
+            Some(advertised) => format!(
+                "request-push verification error: offered signed refs {}, but advertised {}",
+                offered, advertised
+            ),
+        };
+        Error { message }
+    }
+}
+
+pub mod progress {
+    use super::*;
+
+    pub fn verifying(urn: &Urn) -> Progress {
+        Progress {
+            message: format!("Verifying signed refs for `{}`", urn),
+            phase: Some(Phase::Verifying),
+        }
+    }
+}
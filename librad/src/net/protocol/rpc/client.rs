@@ -1,7 +1,7 @@
 // Copyright © 2022 The Radicle Link Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{collections::BTreeSet, net::SocketAddr, sync::Arc};
 
 use crypto::Signer;
 
@@ -10,10 +10,19 @@ use futures::TryFutureExt;
 use link_async::Spawner;
 
 use crate::{
-    git::{self, identities::local::LocalIdentity, Urn},
+    git::{
+        self,
+        identities::{links, local::LocalIdentity, supersede},
+        storage::ReadOnlyStorage as _,
+        types::{Namespace, Reference},
+        Urn,
+    },
     net::{
+        metrics::Recorder,
+        protocol,
         quic::ConnectPeer,
         replication::{self, Replication},
+        tcp,
     },
     paths::Paths,
     PeerId,
@@ -22,11 +31,16 @@ use crate::{
 pub mod config;
 pub use config::Config;
 pub mod error;
+pub mod failover;
 
 mod interrogation;
 pub use interrogation::Interrogation;
 mod request_pull;
-pub use request_pull::RequestPull;
+pub use request_pull::{RequestPull, RequestPullBatch};
+mod request_push;
+pub use request_push::RequestPush;
+pub mod smart;
+pub use smart::Smart;
 
 mod streams;
 
@@ -39,6 +53,7 @@ pub struct Client<Signer, Endpoint: Clone + Send + Sync> {
     endpoint: Endpoint,
     repl: Replication,
     user_store: git::storage::Pool<git::storage::Storage>,
+    misses: protocol::cache::negative::Misses,
 }
 
 impl<S, E: Clone + Send + Sync> Client<S, E>
@@ -49,7 +64,7 @@ where
         let paths = config.paths.clone();
         let local_id = PeerId::from_signer(&config.signer);
         let user_store = config.storage();
-        let repl = Replication::new(&paths, config.replication)?;
+        let repl = Replication::new(&paths, config.replication.clone())?;
 
         Ok(Self {
             config,
@@ -59,8 +74,77 @@ where
             endpoint,
             repl,
             user_store,
+            misses: Default::default(),
         })
     }
+
+    /// Call `f` whenever namespaces were evicted by replication initiated
+    /// through this client.
+    pub(crate) fn on_evicted<F>(self, f: F) -> Self
+    where
+        F: Fn(replication::Evicted) + Send + Sync + 'static,
+    {
+        Self {
+            repl: self.repl.on_evicted(f),
+            ..self
+        }
+    }
+
+    /// Call `f` for each change to the local state resulting from replication
+    /// initiated through this client.
+    pub(crate) fn on_lifecycle<F>(self, f: F) -> Self
+    where
+        F: Fn(protocol::event::upstream::Lifecycle) + Send + Sync + 'static,
+    {
+        Self {
+            repl: self.repl.on_lifecycle(f),
+            ..self
+        }
+    }
+
+    /// Call `f` for each ref changed by replication initiated through this
+    /// client.
+    #[cfg(feature = "hooks")]
+    pub(crate) fn on_data<F>(self, f: F) -> Self
+    where
+        F: Fn(git::hooks::Data<git_ext::Oid>) + Send + Sync + 'static,
+    {
+        Self {
+            repl: self.repl.on_data(f),
+            ..self
+        }
+    }
+
+    /// Record replication runs initiated through this client in `inflight`.
+    pub(crate) fn tracking(self, inflight: replication::InFlight) -> Self {
+        Self {
+            repl: self.repl.tracking(inflight),
+            ..self
+        }
+    }
+
+    /// Report replication runs initiated through this client to `metrics`.
+    pub(crate) fn metrics(self, metrics: Recorder) -> Self {
+        Self {
+            repl: self.repl.metrics(metrics),
+            ..self
+        }
+    }
+
+    /// Schedule replication runs initiated through this client with the given
+    /// `priority`, see [`replication::scheduler`]. Default:
+    /// [`replication::Priority::Interactive`].
+    pub fn prioritise(self, priority: replication::Priority) -> Self {
+        Self {
+            repl: self.repl.prioritise(priority),
+            ..self
+        }
+    }
+
+    /// Share the negative caches of interrogations with `misses`.
+    pub(crate) fn misses(self, misses: protocol::cache::negative::Misses) -> Self {
+        Self { misses, ..self }
+    }
 }
 
 impl<S, E> Client<S, E>
@@ -76,6 +160,10 @@ where
         self.local_id
     }
 
+    /// Replicate `urn` from `from`.
+    ///
+    /// If `from` can't be reached via QUIC, the [`tcp`] fallback transport is
+    /// tried on the same addresses.
     pub async fn replicate(
         &self,
         from: impl Into<(PeerId, Vec<SocketAddr>)>,
@@ -84,20 +172,269 @@ where
     ) -> Result<replication::Success, error::Replicate> {
         // TODO: errors
         let (remote_peer, addrs) = from.into();
-        let conn = self
-            .endpoint
-            .connect(remote_peer, addrs)
-            .await
-            .ok_or(error::NoConnection(remote_peer))?
-            .connection()
-            .clone();
         let store = self.user_store.get().await?;
-        self.repl
-            .replicate(&self.spawner, store, conn, urn, whoami)
-            .err_into()
+        match self.endpoint.connect(remote_peer, addrs.clone()).await {
+            Some(ingress) => {
+                let conn = ingress.connection().clone();
+                self.repl
+                    .replicate(&self.spawner, store, conn, urn, whoami)
+                    .err_into()
+                    .await
+            },
+            None => {
+                let (conn, incoming) = self.connect_tcp(remote_peer, addrs).await?;
+                // Drives the connection until replication is done.
+                let _driver = self.spawner.spawn(incoming.deny());
+                self.repl
+                    .replicate(&self.spawner, store, conn, urn, whoami)
+                    .err_into()
+                    .await
+            },
+        }
+    }
+
+    /// Like [`Self::replicate`], but skip fetching if `from` has nothing new.
+    ///
+    /// `from` is interrogated for its [`git::storage::digest::Summary`] of
+    /// `urn` first. If the local view of every peer in it (except the local
+    /// peer itself) is the same, `None` is returned. If the summary can't be
+    /// obtained, eg. because `from` doesn't support the request, replication
+    /// proceeds as usual.
+    pub async fn replicate_diverged(
+        &self,
+        from: impl Into<(PeerId, Vec<SocketAddr>)>,
+        urn: Urn,
+        whoami: Option<LocalIdentity>,
+    ) -> Result<Option<replication::Success>, error::Replicate> {
+        let from = from.into();
+        if self.is_covered(from.clone(), &urn).await {
+            tracing::debug!(urn = %urn, peer = %from.0, "skipping replication, no divergence");
+            return Ok(None);
+        }
+        self.replicate(from, urn, whoami).await.map(Some)
+    }
+
+    /// Whether our view of `urn` covers the one of `from`.
+    async fn is_covered(&self, from: (PeerId, Vec<SocketAddr>), urn: &Urn) -> bool {
+        use git::storage::digest;
+
+        let theirs = match self.interrogate(from).await {
+            Ok(interrogation) => interrogation.digest(urn.clone(), true).await,
+            Err(e) => {
+                tracing::debug!(err = %e, "unable to interrogate for digest");
+                return false;
+            },
+        };
+        let mut theirs = match theirs {
+            Ok(Some(theirs)) => theirs,
+            Ok(None) => return false,
+            Err(e) => {
+                tracing::debug!(err = %e, "unable to obtain remote digest");
+                return false;
+            },
+        };
+        theirs.peers.remove(&self.local_id);
+
+        let ours = {
+            let urn = urn.clone();
+            self.using_storage(move |storage| {
+                let opts = digest::Options {
+                    sigrefs: true,
+                    hide: vec![],
+                };
+                digest::summary(storage, &urn, &opts)
+            })
             .await
+        };
+        match ours {
+            Ok(Ok(Some(ours))) => ours.covers(&theirs),
+            Ok(Ok(None)) => false,
+            Ok(Err(e)) => {
+                tracing::warn!(err = %e, "unable to compute local digest");
+                false
+            },
+            Err(e) => {
+                tracing::warn!(err = %e, "unable to compute local digest");
+                false
+            },
+        }
+    }
+
+    /// Replicate `urn` from the first of `candidates` able to serve it.
+    ///
+    /// Candidates are tried in turn, retrying transient failures with
+    /// exponential backoff until the attempts allowed by `policy` are used
+    /// up, see [`failover`]. The returned [`failover::Served`] records which
+    /// peer ultimately served the data.
+    pub async fn replicate_any<I>(
+        &self,
+        candidates: I,
+        urn: Urn,
+        whoami: Option<LocalIdentity>,
+        policy: failover::Policy,
+    ) -> Result<failover::Served, error::ReplicateAny>
+    where
+        I: IntoIterator<Item = (PeerId, Vec<SocketAddr>)>,
+    {
+        use failover::{Failure, Next};
+
+        let mut candidates = candidates.into_iter().collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Err(error::ReplicateAny::NoCandidates(urn));
+        }
+        let mut failures = Vec::new();
+        let mut round = 0;
+        loop {
+            let mut next = Vec::with_capacity(candidates.len());
+            for (peer, addrs) in candidates {
+                if failures.len() >= policy.max_attempts {
+                    return Err(error::ReplicateAny::Exhausted { urn, failures });
+                }
+                match self
+                    .replicate((peer, addrs.clone()), urn.clone(), whoami.clone())
+                    .await
+                {
+                    Ok(success) => {
+                        return Ok(failover::Served {
+                            peer,
+                            success,
+                            failures,
+                        })
+                    },
+                    Err(e) => {
+                        tracing::debug!(
+                            err = %e,
+                            urn = %urn,
+                            peer = %peer,
+                            "replication attempt failed"
+                        );
+                        match failover::classify(&e) {
+                            Next::Retry => next.push((peer, addrs)),
+                            Next::Failover => {},
+                            Next::Abort => {
+                                return Err(error::ReplicateAny::Aborted {
+                                    urn,
+                                    peer,
+                                    source: e,
+                                })
+                            },
+                        }
+                        failures.push(Failure { peer, error: e });
+                    },
+                }
+            }
+            if next.is_empty() || failures.len() >= policy.max_attempts {
+                return Err(error::ReplicateAny::Exhausted { urn, failures });
+            }
+            link_async::sleep(policy.backoff(round)).await;
+            round += 1;
+            candidates = next;
+        }
+    }
+
+    /// Replicate `urn` from `from`, along with the identities it transitively
+    /// [`links`][git::identities::links] to, up to `max_depth` hops.
+    ///
+    /// Returns the [`links::Closure`] of `urn` after replication. Use
+    /// [`links::verify`] to check that all pinned revisions were obtained.
+    pub async fn replicate_links(
+        &self,
+        from: impl Into<(PeerId, Vec<SocketAddr>)>,
+        urn: Urn,
+        max_depth: usize,
+    ) -> Result<links::Closure, error::ReplicateLinks> {
+        let from = from.into();
+        let mut seen = BTreeSet::new();
+        let mut frontier = vec![urn.clone()];
+        for depth in 0..=max_depth {
+            let mut next = Vec::new();
+            for urn in frontier {
+                if !seen.insert(urn.clone()) {
+                    continue;
+                }
+                self.replicate(from.clone(), urn.clone(), None)
+                    .await
+                    .map_err(|source| error::ReplicateLinks::Replicate {
+                        urn: urn.clone(),
+                        source,
+                    })?;
+                if depth < max_depth {
+                    let declared = self
+                        .using_storage(move |storage| links::get(storage, &urn))
+                        .await??;
+                    next.extend(
+                        declared
+                            .into_iter()
+                            .flat_map(|links| links.0.into_values())
+                            .map(|link| link.urn.with_path(None)),
+                    );
+                }
+            }
+            frontier = next;
+        }
+
+        Ok(self
+            .using_storage(move |storage| links::closure(storage, &urn, max_depth))
+            .await??)
+    }
+
+    /// Replicate `urn` from `from`, following [`supersede`] declarations up
+    /// to `max_depth` hops.
+    ///
+    /// Every successor is replicated from the same peer, and inherits the
+    /// tracking entries of the project it supersedes, so trackers of the old
+    /// URN converge on the new one. Returns the [`supersede::Resolved`] URN
+    /// after replication.
+    pub async fn replicate_superseded(
+        &self,
+        from: impl Into<(PeerId, Vec<SocketAddr>)>,
+        urn: Urn,
+        max_depth: usize,
+    ) -> Result<supersede::Resolved, error::ReplicateSuperseded> {
+        let from = from.into();
+        let urn = urn.with_path(None);
+        let mut current = urn.clone();
+        for depth in 0..=max_depth {
+            self.replicate(from.clone(), current.clone(), None)
+                .await
+                .map_err(|source| error::ReplicateSuperseded::Replicate {
+                    urn: current.clone(),
+                    source,
+                })?;
+            if depth == max_depth {
+                break;
+            }
+            let next = self
+                .using_storage({
+                    let current = current.clone();
+                    move |storage| -> Result<_, supersede::Error> {
+                        match supersede::get(storage, &current)? {
+                            None => Ok(None),
+                            Some(supersede::Supersede { urn: next, .. }) => {
+                                let next = next.with_path(None);
+                                supersede::inherit_tracking(storage, &current, &next)?;
+                                Ok(Some(next))
+                            },
+                        }
+                    }
+                })
+                .await??;
+            match next {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+
+        Ok(self
+            .using_storage(move |storage| supersede::resolve(storage, &urn, max_depth))
+            .await??)
     }
 
+    /// Ask the peer `to` to fetch `urn` from us.
+    ///
+    /// If `to` can't be reached via QUIC, eg. because UDP is blocked by a
+    /// firewall, the [`tcp`] fallback transport is tried on the same
+    /// addresses.
     pub async fn request_pull(
         &self,
         to: impl Into<(PeerId, Vec<SocketAddr>)>,
@@ -105,6 +442,85 @@ where
     ) -> Result<RequestPull, error::RequestPull> {
         let (remote_peer, addrs) = to.into();
 
+        let ingress = match self.endpoint.connect(remote_peer, addrs.clone()).await {
+            Some(ingress) => ingress,
+            None => {
+                let (conn, incoming) = self.connect_tcp(remote_peer, addrs).await?;
+                return Ok(RequestPull::tcp(
+                    conn,
+                    incoming,
+                    urn,
+                    self.paths.clone(),
+                    self.spawner.clone(),
+                ));
+            },
+        };
+        let (conn, incoming) = match ingress {
+            crate::net::quic::Ingress::Remote(conn) => (conn, None),
+            crate::net::quic::Ingress::Local { conn, streams } => (conn, Some(streams)),
+        };
+
+        RequestPull::new(
+            conn,
+            incoming,
+            urn,
+            self.paths.clone(),
+            self.spawner.clone(),
+        )
+        .await
+    }
+
+    /// Like [`Client::request_pull`], but for a set of `urns`.
+    ///
+    /// All requests are sent over a single connection, and their responses
+    /// are multiplexed onto the returned [`RequestPullBatch`]. The peer
+    /// processes the requests concurrently, subject to its own limits.
+    pub async fn request_pull_batch(
+        &self,
+        to: impl Into<(PeerId, Vec<SocketAddr>)>,
+        urns: impl IntoIterator<Item = Urn>,
+    ) -> Result<RequestPullBatch, error::RequestPullBatch> {
+        let (remote_peer, addrs) = to.into();
+
+        let ingress = self
+            .endpoint
+            .connect(remote_peer, addrs)
+            .await
+            .ok_or(error::NoConnection(remote_peer))?;
+        let (conn, incoming) = match ingress {
+            crate::net::quic::Ingress::Remote(conn) => (conn, None),
+            crate::net::quic::Ingress::Local { conn, streams } => (conn, Some(streams)),
+        };
+
+        Ok(RequestPullBatch::new(
+            conn,
+            incoming,
+            urns,
+            self.paths.clone(),
+            self.spawner.clone(),
+        ))
+    }
+
+    /// Ask the peer `to` to fetch the current state of `urn` from us.
+    ///
+    /// The tip of the local `rad/signed_refs` is offered to the peer, which
+    /// verifies that it is advertised before fetching.
+    pub async fn request_push(
+        &self,
+        to: impl Into<(PeerId, Vec<SocketAddr>)>,
+        urn: Urn,
+    ) -> Result<RequestPush, error::RequestPush> {
+        let (remote_peer, addrs) = to.into();
+
+        let signed_refs = {
+            let storage = self.user_store.get().await.map_err(error::Storage::from)?;
+            (*storage)
+                .reference(&Reference::rad_signed_refs(Namespace::from(&urn), None))
+                .map_err(error::Storage::from)?
+                .and_then(|r| r.target())
+                .ok_or_else(|| error::RequestPush::NoSignedRefs(urn.clone()))?
+        };
+
         let ingress = self
             .endpoint
             .connect(remote_peer, addrs)
@@ -115,13 +531,53 @@ where
             crate::net::quic::Ingress::Local { conn, streams } => (conn, Some(streams)),
         };
 
-        RequestPull::new(conn, incoming, urn, self.paths.clone()).await
+        RequestPush::new(
+            conn,
+            incoming,
+            protocol::request_push::Request {
+                urn,
+                signed_refs: signed_refs.into(),
+            },
+            self.paths.clone(),
+            self.spawner.clone(),
+        )
+        .await
     }
 
+    /// Connect to `from` for interrogation.
+    ///
+    /// If connecting to `from` failed recently, no connection is attempted
+    /// until a gossip `Have` from `from` arrives, or the failure expires from
+    /// the negative cache.
     pub async fn interrogate(
         &self,
         from: impl Into<(PeerId, Vec<SocketAddr>)>,
     ) -> Result<Interrogation, error::NoConnection> {
+        let (remote_peer, addrs) = from.into();
+        if self.misses.peers.contains(&remote_peer) {
+            return Err(error::NoConnection(remote_peer));
+        }
+        let ingress = match self.endpoint.connect(remote_peer, addrs).await {
+            Some(ingress) => ingress,
+            None => {
+                self.misses.peers.insert(remote_peer);
+                return Err(error::NoConnection(remote_peer));
+            },
+        };
+
+        Ok(Interrogation {
+            peer: remote_peer,
+            conn: ingress.connection().clone(),
+            misses: self.misses.remote.clone(),
+        })
+    }
+
+    /// Connect to `from` for driving the git smart protocol directly, see
+    /// [`smart`].
+    pub async fn smart(
+        &self,
+        from: impl Into<(PeerId, Vec<SocketAddr>)>,
+    ) -> Result<Smart, error::NoConnection> {
         let (remote_peer, addrs) = from.into();
         let ingress = self
             .endpoint
@@ -129,12 +585,30 @@ where
             .await
             .ok_or(error::NoConnection(remote_peer))?;
 
-        Ok(Interrogation {
+        Ok(Smart {
             peer: remote_peer,
             conn: ingress.connection().clone(),
         })
     }
 
+    async fn connect_tcp(
+        &self,
+        remote_peer: PeerId,
+        addrs: Vec<SocketAddr>,
+    ) -> Result<(tcp::Connection, tcp::IncomingStreams), error::NoConnection> {
+        tcp::connect(
+            self.config.signer.clone(),
+            self.config.network.clone(),
+            remote_peer,
+            addrs,
+        )
+        .await
+        .map_err(|e| {
+            tracing::debug!(peer = %remote_peer, err = %e, "tcp fallback failed");
+            error::NoConnection(remote_peer)
+        })
+    }
+
     /// Borrow a [`git::storage::Storage`] from the pool, and run a blocking
     /// computation on it.
     pub async fn using_storage<F, T>(&self, blocking: F) -> Result<T, error::Storage>
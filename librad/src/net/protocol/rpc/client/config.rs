@@ -1,6 +1,8 @@
 // Copyright © 2022 The Radicle Link Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+use std::time::Duration;
+
 use crate::{
     crypto::Signer,
     git::{
@@ -23,13 +25,14 @@ pub struct Config<Signer> {
 impl<S: Clone + Signer> Config<S> {
     pub fn storage(&self) -> Pool<git::storage::Storage> {
         match &self.user_storage {
-            Storage::New(user_storage) => Pool::new(
+            Storage::New(user_storage) => Pool::with_timeout(
                 pool::ReadWriteConfig::new(
                     self.paths.clone(),
                     self.signer.clone(),
                     pool::Initialised::no(),
                 ),
                 user_storage.pool_size,
+                user_storage.checkout_timeout,
             ),
             Storage::Initialised(pool) => pool.clone(),
         }
@@ -75,11 +78,21 @@ impl From<Pool<git::storage::Storage>> for Storage {
 #[derive(Clone, Debug)]
 pub struct UserStorage {
     pub pool_size: usize,
+    pub checkout_timeout: Option<Duration>,
 }
 
 impl From<peer::config::UserStorage> for UserStorage {
-    fn from(peer::config::UserStorage { pool_size }: peer::config::UserStorage) -> Self {
-        Self { pool_size }
+    fn from(
+        peer::config::UserStorage {
+            pool_size,
+            checkout_timeout,
+            ..
+        }: peer::config::UserStorage,
+    ) -> Self {
+        Self {
+            pool_size,
+            checkout_timeout,
+        }
     }
 }
 
@@ -87,6 +100,7 @@ impl Default for UserStorage {
     fn default() -> Self {
         Self {
             pool_size: num_cpus::get_physical(),
+            checkout_timeout: None,
         }
     }
 }
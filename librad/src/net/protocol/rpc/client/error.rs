@@ -4,11 +4,17 @@
 use thiserror::Error;
 
 use crate::{
-    git::storage,
+    git::{
+        identities::{links, supersede},
+        storage,
+        Urn,
+    },
     net::{
         protocol::{self, interrogation},
         quic,
         replication,
+        tcp,
+        upgrade,
     },
     PeerId,
 };
@@ -56,6 +62,9 @@ pub enum RequestPull {
 
     #[error(transparent)]
     Rpc(#[from] Box<protocol::error::Rpc<quic::BidiStream>>),
+
+    #[error(transparent)]
+    RpcTcp(#[from] Box<protocol::error::Rpc<tcp::BidiStream>>),
 }
 
 impl From<protocol::error::Rpc<quic::BidiStream>> for RequestPull {
@@ -64,6 +73,54 @@ impl From<protocol::error::Rpc<quic::BidiStream>> for RequestPull {
     }
 }
 
+impl From<protocol::error::Rpc<tcp::BidiStream>> for RequestPull {
+    fn from(e: protocol::error::Rpc<tcp::BidiStream>) -> Self {
+        Self::RpcTcp(Box::new(e))
+    }
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum RequestPullBatch {
+    #[error("request-pull failed for `{urn}`")]
+    Urn {
+        urn: Urn,
+        #[source]
+        source: RequestPull,
+    },
+
+    #[error(transparent)]
+    Incoming(#[from] Incoming),
+
+    #[error(transparent)]
+    NoConnection(#[from] NoConnection),
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum RequestPush {
+    #[error("no signed refs found for `{0}`")]
+    NoSignedRefs(Urn),
+
+    #[error(transparent)]
+    Incoming(#[from] Incoming),
+
+    #[error(transparent)]
+    NoConnection(#[from] NoConnection),
+
+    #[error(transparent)]
+    Storage(#[from] Storage),
+
+    #[error(transparent)]
+    Rpc(#[from] Box<protocol::error::Rpc<quic::BidiStream>>),
+}
+
+impl From<protocol::error::Rpc<quic::BidiStream>> for RequestPush {
+    fn from(e: protocol::error::Rpc<quic::BidiStream>) -> Self {
+        Self::Rpc(Box::new(e))
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum Replicate {
     #[error(transparent)]
@@ -76,6 +133,61 @@ pub enum Replicate {
     Replicate(#[from] replication::error::Replicate),
 }
 
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ReplicateAny {
+    #[error("no candidates to replicate `{0}` from")]
+    NoCandidates(Urn),
+
+    #[error("failed to replicate `{urn}` after {} attempts", failures.len())]
+    Exhausted {
+        urn: Urn,
+        failures: Vec<super::failover::Failure>,
+    },
+
+    #[error("failed to replicate `{urn}` from {peer}")]
+    Aborted {
+        urn: Urn,
+        peer: PeerId,
+        #[source]
+        source: Replicate,
+    },
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ReplicateLinks {
+    #[error("failed to replicate `{urn}`")]
+    Replicate {
+        urn: Urn,
+        #[source]
+        source: Replicate,
+    },
+
+    #[error(transparent)]
+    Links(#[from] links::Error),
+
+    #[error(transparent)]
+    Storage(#[from] Storage),
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum ReplicateSuperseded {
+    #[error("failed to replicate `{urn}`")]
+    Replicate {
+        urn: Urn,
+        #[source]
+        source: Replicate,
+    },
+
+    #[error(transparent)]
+    Supersede(#[from] supersede::Error),
+
+    #[error(transparent)]
+    Storage(#[from] Storage),
+}
+
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum Storage {
@@ -100,8 +212,23 @@ pub struct NoConnection(pub PeerId);
 pub enum Incoming {
     #[error(transparent)]
     Quic(#[from] quic::error::Error),
+    #[error(transparent)]
+    Tcp(#[from] tcp::Error),
     #[error("expected bidirectional connection, but found a unidirectional connection")]
     Uni,
     #[error("connection lost")]
     ConnectionLost,
 }
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Smart {
+    #[error(transparent)]
+    Upgrade(#[from] Box<upgrade::Error<quic::BidiStream>>),
+
+    #[error(transparent)]
+    Quic(#[from] quic::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
@@ -0,0 +1,116 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Replicating from any of several candidate peers, see
+//! [`super::Client::replicate_any`].
+//!
+//! Candidates are tried in turn. A candidate which fails transiently -- eg.
+//! because it can't be reached, or the connection broke mid-fetch -- is
+//! tried again in the next round, after an exponentially growing delay. A
+//! candidate which failed otherwise, eg. because it served data which did not
+//! verify, is not tried again. Failures which no other candidate could
+//! remedy, such as a full disk or a [`replication::Filter`] excluding the
+//! requested ref, end the attempt right away.
+
+use std::time::Duration;
+
+use super::error;
+use crate::{
+    net::replication::{self, error::Replicate},
+    PeerId,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Policy {
+    /// Maximum number of replication attempts, across all candidates.
+    ///
+    /// Default: 8
+    pub max_attempts: usize,
+    /// The delay before the second round of attempts, doubled for every
+    /// subsequent round.
+    ///
+    /// Default: 1s
+    pub initial_backoff: Duration,
+    /// The maximum delay between rounds.
+    ///
+    /// Default: 30s
+    pub max_backoff: Duration,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Policy {
+    /// The delay before the round following `round`, counting from `0`.
+    pub fn backoff(&self, round: u32) -> Duration {
+        self.initial_backoff
+            .checked_mul(1 << round.min(31))
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+}
+
+/// The outcome of a successful [`super::Client::replicate_any`].
+#[derive(Debug)]
+pub struct Served {
+    /// The peer which ultimately served the data.
+    pub peer: PeerId,
+    pub success: replication::Success,
+    /// The failed attempts preceding the successful one, in order.
+    pub failures: Vec<Failure>,
+}
+
+#[derive(Debug)]
+pub struct Failure {
+    pub peer: PeerId,
+    pub error: error::Replicate,
+}
+
+/// What to do after an attempt failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Next {
+    /// Try the same peer again in the next round.
+    Retry,
+    /// Don't try the same peer again, but do try the others.
+    Failover,
+    /// Give up.
+    Abort,
+}
+
+pub(super) fn classify(e: &error::Replicate) -> Next {
+    match e {
+        error::Replicate::NoConnection(_) | error::Replicate::Pool(_) => Next::Retry,
+        error::Replicate::Replicate(e) => match e {
+            Replicate::Timeout(_) => Next::Retry,
+            Replicate::Filtered(_) | Replicate::NamespaceLimit(_) | Replicate::Store(_) => {
+                Next::Abort
+            },
+            Replicate::Replicate(e) => {
+                if is_io(e.as_ref()) {
+                    Next::Retry
+                } else {
+                    Next::Failover
+                }
+            },
+        },
+    }
+}
+
+/// Whether an I/O error is the cause of `e`, which we assume to be the
+/// network.
+fn is_io(e: &(dyn std::error::Error + 'static)) -> bool {
+    let mut cur = Some(e);
+    while let Some(e) = cur {
+        if e.is::<std::io::Error>() {
+            return true;
+        }
+        cur = e.source();
+    }
+    false
+}
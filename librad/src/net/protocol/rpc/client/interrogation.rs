@@ -1,12 +1,15 @@
 // Copyright © 2022 The Radicle Link Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::net::SocketAddr;
+use std::{collections::BTreeMap, net::SocketAddr};
+
+use git_ext as ext;
 
 use crate::{
+    git::{storage::digest, Urn},
     identities::Xor,
     net::{
-        protocol::{interrogation, io, PeerAdvertisement},
+        protocol::{cache::negative::Negative, interrogation, io, PeerAdvertisement},
         quic,
     },
     PeerId,
@@ -17,6 +20,8 @@ use super::error;
 pub struct Interrogation {
     pub(super) peer: PeerId,
     pub(super) conn: quic::Connection,
+    /// URNs the interrogated peer recently reported not to have.
+    pub(super) misses: Negative<(PeerId, Urn)>,
 }
 
 impl Interrogation {
@@ -65,6 +70,71 @@ impl Interrogation {
             })
     }
 
+    /// Ask the interrogated peer to send the tips of the `rad/signed_refs` it
+    /// has for `urn`, keyed by the peer they belong to.
+    ///
+    /// Comparing those to the local state allows to skip fetching from the
+    /// interrogated peer if it doesn't have anything new.
+    ///
+    /// An empty response is remembered for a short while, during which the
+    /// request is not repeated.
+    pub async fn sigref_tips(
+        &self,
+        urn: Urn,
+    ) -> Result<BTreeMap<PeerId, ext::Oid>, error::Interrogation> {
+        use interrogation::{Request, Response};
+
+        let key = (self.peer, urn.clone().with_path(None));
+        if self.misses.contains(&key) {
+            return Ok(BTreeMap::new());
+        }
+        let tips = self
+            .request(Request::GetSigrefTips(urn))
+            .await
+            .and_then(|resp| match resp {
+                Response::SigrefTips(tips) => Ok(tips),
+                Response::Error(e) => Err(error::Interrogation::ErrorResponse(e)),
+                _ => Err(error::Interrogation::InvalidResponse),
+            })?;
+        if tips.is_empty() {
+            self.misses.insert(key);
+        }
+        Ok(tips)
+    }
+
+    /// Ask the interrogated peer to send the [`digest::Summary`] of `urn`,
+    /// optionally including the `rad/signed_refs`.
+    ///
+    /// Returns `None` if the interrogated peer doesn't have `urn`. Comparing
+    /// the summary to the local one (see [`digest::summary`]) reveals whether,
+    /// and for which peers, the views diverge. Like for
+    /// [`Interrogation::sigref_tips`], a `None` response is remembered for a
+    /// short while.
+    pub async fn digest(
+        &self,
+        urn: Urn,
+        sigrefs: bool,
+    ) -> Result<Option<digest::Summary>, error::Interrogation> {
+        use interrogation::{Request, Response};
+
+        let key = (self.peer, urn.clone().with_path(None));
+        if self.misses.contains(&key) {
+            return Ok(None);
+        }
+        let summary = self
+            .request(Request::GetDigest(urn, sigrefs))
+            .await
+            .and_then(|resp| match resp {
+                Response::Digest(summary) => Ok(summary),
+                Response::Error(e) => Err(error::Interrogation::ErrorResponse(e)),
+                _ => Err(error::Interrogation::InvalidResponse),
+            })?;
+        if summary.is_none() {
+            self.misses.insert(key);
+        }
+        Ok(summary)
+    }
+
     async fn request(
         &self,
         request: interrogation::Request,
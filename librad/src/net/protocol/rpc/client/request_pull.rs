@@ -10,17 +10,19 @@ use std::{
 use futures::{
     self,
     future::{self, BoxFuture},
-    stream::BoxStream,
+    stream::{self, BoxStream},
     FutureExt as _,
     Stream,
     StreamExt as _,
 };
+use link_async::Spawner;
 
 use crate::{
     git::Urn,
     net::{
         protocol::{self, request_pull},
         quic,
+        tcp,
     },
     paths::Paths,
 };
@@ -38,7 +40,7 @@ use super::{error, streams};
 ///   * An error,  [`error::RequestPull`]
 pub struct RequestPull {
     resp: BoxStream<'static, Result<request_pull::Response, error::RequestPull>>,
-    repl: BoxFuture<'static, Result<(), error::Incoming>>,
+    repl: future::Fuse<BoxFuture<'static, Result<(), error::Incoming>>>,
 }
 
 trait AssertSend: Send {}
@@ -50,6 +52,7 @@ impl RequestPull {
         streams: Option<quic::BoxedIncomingStreams<'static>>,
         urn: Urn,
         paths: Arc<Paths>,
+        spawner: Arc<Spawner>,
     ) -> Result<Self, error::RequestPull> {
         let resp = protocol::io::send::multi_response(
             &conn,
@@ -61,11 +64,44 @@ impl RequestPull {
         .boxed();
 
         let repl = match streams {
-            Some(streams) => streams::git(paths, streams).boxed(),
+            Some(streams) => streams::git(paths, spawner, streams).boxed(),
             None => future::pending().boxed(),
         };
 
-        Ok(Self { resp, repl })
+        Ok(Self {
+            resp,
+            repl: repl.fuse(),
+        })
+    }
+
+    /// Like [`RequestPull::new`], but over the TCP fallback transport.
+    ///
+    /// The request is only sent once the `RequestPull` is polled, as polling
+    /// is also what drives the connection.
+    pub fn tcp(
+        conn: tcp::Connection,
+        streams: tcp::IncomingStreams,
+        urn: Urn,
+        paths: Arc<Paths>,
+        spawner: Arc<Spawner>,
+    ) -> Self {
+        let resp = async move {
+            protocol::io::send::request_tcp(
+                &conn,
+                protocol::request_pull::Request { urn },
+                protocol::request_pull::FRAMED_BUFSIZ,
+            )
+            .await
+        }
+        .map(|res| match res {
+            Ok(resp) => resp.map(|i| i.map_err(error::RequestPull::from)).boxed(),
+            Err(e) => stream::once(future::err(e.into())).boxed(),
+        })
+        .flatten_stream()
+        .boxed();
+        let repl = streams::git_tcp(paths, spawner, streams).boxed().fuse();
+
+        Self { resp, repl }
     }
 }
 
@@ -80,3 +116,72 @@ impl Stream for RequestPull {
         self.resp.poll_next_unpin(cx)
     }
 }
+
+/// A series of request-pull responses for multiple URNs.
+///
+/// Obtained from [`super::Client::request_pull_batch`]. The responses for each
+/// URN are interleaved in the order they arrive, and are finished for a given
+/// URN according to the same rules as [`RequestPull`]. The stream ends once
+/// all URNs are finished.
+pub struct RequestPullBatch {
+    resp: BoxStream<'static, Result<(Urn, request_pull::Response), error::RequestPullBatch>>,
+    repl: BoxFuture<'static, Result<(), error::Incoming>>,
+}
+
+impl AssertSend for RequestPullBatch {}
+
+impl RequestPullBatch {
+    pub fn new(
+        conn: quic::Connection,
+        streams: Option<quic::BoxedIncomingStreams<'static>>,
+        urns: impl IntoIterator<Item = Urn>,
+        paths: Arc<Paths>,
+        spawner: Arc<Spawner>,
+    ) -> Self {
+        let resp = stream::select_all(urns.into_iter().map(|urn| {
+            let conn = conn.clone();
+            let req = protocol::request_pull::Request { urn: urn.clone() };
+            async move {
+                protocol::io::send::multi_response(
+                    &conn,
+                    req,
+                    protocol::request_pull::FRAMED_BUFSIZ,
+                )
+                .await
+            }
+            .map(|res| match res {
+                Ok(resp) => resp.map(|i| i.map_err(error::RequestPull::from)).boxed(),
+                Err(e) => stream::once(future::err(e.into())).boxed(),
+            })
+            .flatten_stream()
+            .map(move |i| {
+                i.map(|resp| (urn.clone(), resp))
+                    .map_err(|source| error::RequestPullBatch::Urn {
+                        urn: urn.clone(),
+                        source,
+                    })
+            })
+            .boxed()
+        }))
+        .boxed();
+
+        let repl = match streams {
+            Some(streams) => streams::git(paths, spawner, streams).boxed(),
+            None => future::pending().boxed(),
+        };
+
+        Self { resp, repl }
+    }
+}
+
+impl Stream for RequestPullBatch {
+    type Item = Result<(Urn, request_pull::Response), error::RequestPullBatch>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Poll::Ready(Err(e)) = self.repl.poll_unpin(cx) {
+            return Poll::Ready(Some(Err(e.into())));
+        }
+
+        self.resp.poll_next_unpin(cx)
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::{
+    self,
+    future::{self, BoxFuture},
+    stream::BoxStream,
+    FutureExt as _,
+    Stream,
+    StreamExt as _,
+};
+use link_async::Spawner;
+
+use crate::{
+    net::{
+        protocol::{self, request_push},
+        quic,
+    },
+    paths::Paths,
+};
+
+use super::{error, streams};
+
+/// A series of request-push responses.
+///
+/// Behaves like [`super::RequestPull`]: the responses will be finished once
+/// the next result will either one of the following:
+///   * `None` was returned
+///   * A successful response, [`request_push::Response::Success`]
+///   * An error response, [`request_push::Response::Error`]
+///   * An error,  [`error::RequestPush`]
+pub struct RequestPush {
+    resp: BoxStream<'static, Result<request_push::Response, error::RequestPush>>,
+    repl: BoxFuture<'static, Result<(), error::Incoming>>,
+}
+
+trait AssertSend: Send {}
+impl AssertSend for RequestPush {}
+
+impl RequestPush {
+    pub async fn new(
+        conn: quic::Connection,
+        streams: Option<quic::BoxedIncomingStreams<'static>>,
+        request: request_push::Request,
+        paths: Arc<Paths>,
+        spawner: Arc<Spawner>,
+    ) -> Result<Self, error::RequestPush> {
+        let resp = protocol::io::send::multi_response(&conn, request, request_push::FRAMED_BUFSIZ)
+            .await?
+            .map(|i| i.map_err(error::RequestPush::from))
+            .boxed();
+
+        let repl = match streams {
+            Some(streams) => streams::git(paths, spawner, streams).boxed(),
+            None => future::pending().boxed(),
+        };
+
+        Ok(Self { resp, repl })
+    }
+}
+
+impl Stream for RequestPush {
+    type Item = Result<request_push::Response, error::RequestPush>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Poll::Ready(Err(e)) = self.repl.poll_unpin(cx) {
+            return Poll::Ready(Some(Err(e.into())));
+        }
+
+        self.resp.poll_next_unpin(cx)
+    }
+}
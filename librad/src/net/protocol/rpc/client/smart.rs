@@ -0,0 +1,151 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Direct access to the git smart protocol spoken by a remote peer.
+//!
+//! [`Smart`] exposes the `ls-refs` and `fetch` commands of protocol v2 over a
+//! connection established (and authenticated) like for replication, but
+//! leaves the choice of refs, wants and haves to the caller. This allows
+//! tools to drive custom negotiations, eg. to inspect history without
+//! storing it, or to mirror a subset of refs into a repository of their own.
+//!
+//! Nothing is verified: the objects received are not checked against the
+//! signed refs of the remote, and no refs are updated locally. Use
+//! [`super::Client::replicate`] for that.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use bstr::BString;
+
+use crate::{
+    data::NonEmptyVec,
+    git::Urn,
+    net::{connection::Duplex as _, quic, upgrade},
+    PeerId,
+};
+
+pub use link_git::protocol::{
+    fetch::Outputs,
+    packwriter::{self, PackWriter},
+    ObjectId,
+    Ref,
+};
+
+use super::error;
+
+pub struct Smart {
+    pub(super) peer: PeerId,
+    pub(super) conn: quic::Connection,
+}
+
+impl Smart {
+    pub fn remote_peer(&self) -> PeerId {
+        self.peer
+    }
+
+    /// List the refs the remote peer has for `urn`.
+    ///
+    /// Only refs starting with one of `prefixes` are listed, unless
+    /// `prefixes` is empty. Ref names are relative to the namespace of `urn`,
+    /// eg. `refs/rad/id` or `refs/remotes/<peer>/heads/main`.
+    pub async fn ls_refs<I, P>(&self, urn: &Urn, prefixes: I) -> Result<Vec<Ref>, error::Smart>
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<BString>,
+    {
+        let (recv, send) = self.open_stream().await?;
+        let mut ref_prefixes = prefixes.into_iter().map(Into::into).collect::<Vec<_>>();
+        ref_prefixes.sort();
+        ref_prefixes.dedup();
+
+        Ok(link_git::protocol::ls_refs(
+            link_git::protocol::ls::Options {
+                repo: repo(urn),
+                extra_params: vec![],
+                ref_prefixes,
+            },
+            recv,
+            send,
+        )
+        .await?)
+    }
+
+    /// Fetch the objects reachable from `wants`, but not from `haves`, in
+    /// the namespace of `urn`.
+    ///
+    /// The packfile sent by the remote peer is handed to the [`PackWriter`]
+    /// returned by `build_pack_writer`, which is passed a flag signalling
+    /// that the fetch was cancelled. See [`Smart::fetch_into`] for the common
+    /// case of adding the packfile to a repository.
+    pub async fn fetch<B, P>(
+        &self,
+        urn: &Urn,
+        wants: NonEmptyVec<ObjectId>,
+        haves: Vec<ObjectId>,
+        build_pack_writer: B,
+    ) -> Result<Outputs<P::Output>, error::Smart>
+    where
+        B: FnOnce(Arc<AtomicBool>) -> P,
+        P: PackWriter + Send + 'static,
+        P::Output: Send + 'static,
+    {
+        let (recv, send) = self.open_stream().await?;
+        let wants = {
+            let NonEmptyVec { head, mut tail } = wants;
+            tail.insert(0, head);
+            tail
+        };
+
+        Ok(link_git::protocol::fetch(
+            link_git::protocol::fetch::Options {
+                repo: repo(urn),
+                extra_params: vec![],
+                wants,
+                haves,
+                want_refs: vec![],
+            },
+            build_pack_writer,
+            recv,
+            send,
+        )
+        .await?)
+    }
+
+    /// Like [`Smart::fetch`], but write the packfile and its index to the
+    /// object database of the repository at `git_dir`.
+    ///
+    /// Objects the (thin) packfile refers to, but does not contain, are
+    /// looked up in the same object database.
+    pub async fn fetch_into(
+        &self,
+        urn: &Urn,
+        wants: NonEmptyVec<ObjectId>,
+        haves: Vec<ObjectId>,
+        git_dir: impl AsRef<Path>,
+        opts: packwriter::Options,
+    ) -> Result<Outputs<packwriter::PackReceived>, error::Smart> {
+        let git_dir: PathBuf = git_dir.as_ref().to_owned();
+        self.fetch(urn, wants, haves, move |stop| {
+            packwriter::Standard::new(
+                &git_dir,
+                opts,
+                packwriter::StandardThickener::new(&git_dir),
+                stop,
+            )
+        })
+        .await
+    }
+
+    async fn open_stream(&self) -> Result<(quic::RecvStream, quic::SendStream), error::Smart> {
+        let bi = self.conn.open_bidi().await?;
+        let up = upgrade::upgrade(bi, upgrade::Git).await.map_err(Box::new)?;
+        Ok(up.into_stream().split())
+    }
+}
+
+fn repo(urn: &Urn) -> BString {
+    BString::from(urn.encode_id())
+}
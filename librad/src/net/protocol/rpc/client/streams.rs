@@ -4,13 +4,15 @@
 use std::sync::Arc;
 
 use either::Either;
-use futures::StreamExt as _;
+use futures::{StreamExt as _, TryStreamExt as _};
+use link_async::Spawner;
 
 use crate::{
     net::{
         connection::{CloseReason, RemoteAddr as _, RemotePeer},
-        protocol::io,
+        protocol::{io, read_access::ReadAccess, StateConfig},
         quic,
+        tcp,
         upgrade,
     },
     paths::Paths,
@@ -34,13 +36,14 @@ use super::error;
 )]
 pub(super) async fn git(
     paths: Arc<Paths>,
+    spawner: Arc<Spawner>,
     mut incoming: quic::BoxedIncomingStreams<'static>,
 ) -> Result<(), error::Incoming> {
     use Either::{Left, Right};
 
     while let Some(stream) = incoming.next().await {
         match stream? {
-            Left(bidi) => incoming::bidi(paths.clone(), bidi).await,
+            Left(bidi) => incoming::bidi(paths.clone(), &spawner, bidi).await,
             Right(uni) => {
                 incoming::deny_uni(uni);
                 return Err(error::Incoming::Uni);
@@ -51,23 +54,87 @@ pub(super) async fn git(
     Ok(())
 }
 
+/// Like [`git`], but for a connection over the TCP fallback transport.
+///
+/// Streams are served concurrently, as the connection is only driven while
+/// `incoming` is polled.
+#[tracing::instrument(
+    skip(incoming),
+    fields(
+        remote_id = %incoming.remote_peer_id(),
+        remote_addr = %incoming.remote_addr()
+    )
+)]
+pub(super) async fn git_tcp(
+    paths: Arc<Paths>,
+    spawner: Arc<Spawner>,
+    incoming: tcp::IncomingStreams,
+) -> Result<(), error::Incoming> {
+    incoming
+        .err_into::<error::Incoming>()
+        .try_for_each_concurrent(None, |bidi| {
+            let paths = paths.clone();
+            let spawner = spawner.clone();
+            async move {
+                incoming::tcp(paths, &spawner, bidi).await;
+                Ok(())
+            }
+        })
+        .await
+}
+
 mod incoming {
     use super::*;
 
-    pub(super) async fn bidi(paths: Arc<Paths>, stream: quic::BidiStream) {
+    pub(super) async fn bidi(paths: Arc<Paths>, spawner: &Spawner, stream: quic::BidiStream) {
         use upgrade::SomeUpgraded::*;
 
+        // Only the peer we initiated the exchange with fetches from us here,
+        // so access control is not worth it.
+        let config = StateConfig {
+            paths,
+            read_access: ReadAccess::allow_all(),
+            compression: Default::default(),
+            capabilities: Default::default(),
+        };
+
         match upgrade::with_upgraded(stream).await {
             Err(upgrade::Error { stream, source }) => {
                 tracing::warn!(err = ?source, "invalid upgrade");
                 stream.close(CloseReason::InvalidUpgrade)
             },
 
-            Ok(Git(up)) => io::recv::git(&paths, up).await,
+            Ok(Git(up)) => io::recv::git(&config, spawner, up).await,
             Ok(Gossip(up)) => deny_bidi(up.into_stream(), "gossip"),
             Ok(Membership(up)) => deny_bidi(up.into_stream(), "membership"),
             Ok(Interrogation(up)) => deny_bidi(up.into_stream(), "interrogation"),
             Ok(RequestPull(up)) => deny_bidi(up.into_stream(), "request-pull"),
+            Ok(RequestPush(up)) => deny_bidi(up.into_stream(), "request-push"),
+        }
+    }
+
+    pub(super) async fn tcp(paths: Arc<Paths>, spawner: &Spawner, stream: tcp::BidiStream) {
+        use upgrade::SomeUpgraded::*;
+
+        let config = StateConfig {
+            paths,
+            read_access: ReadAccess::allow_all(),
+            compression: Default::default(),
+            capabilities: Default::default(),
+        };
+
+        match upgrade::with_upgraded(stream).await {
+            Err(upgrade::Error { stream, source }) => {
+                tracing::warn!(err = ?source, "invalid upgrade");
+                stream.close(CloseReason::InvalidUpgrade)
+            },
+
+            Ok(Git(up)) => io::recv::git(&config, spawner, up).await,
+            Ok(Gossip(up)) => deny_tcp(up.into_stream(), "gossip"),
+            Ok(Membership(up)) => deny_tcp(up.into_stream(), "membership"),
+            Ok(Interrogation(up)) => deny_tcp(up.into_stream(), "interrogation"),
+            Ok(RequestPull(up)) => deny_tcp(up.into_stream(), "request-pull"),
+            Ok(RequestPush(up)) => deny_tcp(up.into_stream(), "request-push"),
         }
     }
 
@@ -80,4 +147,9 @@ mod incoming {
         tracing::warn!("non-git bidirectional {} requested", kind);
         stream.close(CloseReason::InvalidUpgrade)
     }
+
+    fn deny_tcp(stream: tcp::BidiStream, kind: &str) {
+        tracing::warn!("non-git tcp {} requested", kind);
+        stream.close(CloseReason::InvalidUpgrade)
+    }
 }
@@ -3,7 +3,7 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{net::SocketAddr, ops::Deref, sync::Arc};
+use std::{collections::BTreeSet, net::SocketAddr, num::NonZeroUsize, ops::Deref, sync::Arc};
 
 use link_async::Spawner;
 use nonzero_ext::nonzero;
@@ -11,12 +11,20 @@ use rand_pcg::Pcg64Mcg;
 use tracing::Instrument as _;
 
 use super::{
+    address_book::AddressBook,
+    breaker,
     broadcast,
     cache,
+    compression,
+    egress,
     event,
     gossip,
+    health,
+    info::Capability,
     membership,
+    read_access::ReadAccess,
     request_pull,
+    throttle,
     tick,
     Endpoint,
     ProtocolStorage,
@@ -25,7 +33,7 @@ use super::{
 };
 use crate::{
     git::storage::{self, PoolError, PooledRef},
-    net::quic,
+    net::{happy_eyeballs::Reachability, quic},
     paths::Paths,
     rate_limit::{self, Direct, Keyed, RateLimiter},
     PeerId,
@@ -34,6 +42,10 @@ use crate::{
 #[derive(Clone)]
 pub(super) struct StateConfig {
     pub paths: Arc<Paths>,
+    pub read_access: ReadAccess,
+    pub compression: compression::Config,
+    /// The capabilities advertised to other peers.
+    pub capabilities: BTreeSet<Capability>,
 }
 
 /// Runtime state of a protocol instance.
@@ -44,6 +56,7 @@ pub(super) struct State<S, G> {
     pub local_id: PeerId,
     pub endpoint: Endpoint,
     pub membership: membership::Hpv<Pcg64Mcg, SocketAddr>,
+    pub storage: S,
     pub gossip: broadcast::State<Storage<S>, ()>,
     pub request_pull: request_pull::State<Storage<S>, G>,
     pub phone: TinCans,
@@ -51,6 +64,12 @@ pub(super) struct State<S, G> {
     pub caches: cache::Caches,
     pub spawner: Arc<Spawner>,
     pub limits: RateLimits,
+    pub egress: egress::Egress,
+    pub breakers: breaker::Breakers,
+    pub reachability: Reachability,
+    pub address_book: AddressBook,
+    pub health: health::Config,
+    pub announcements: gossip::batch::Batcher,
 }
 
 impl<S, G> State<S, G> {
@@ -92,18 +111,32 @@ where
 
         match self.endpoint.get_connection(to) {
             Some(conn) => Some(conn),
-            None => io::connect(&self.endpoint, to, addr_hints)
-                .in_current_span()
-                .await
-                .map(|(conn, ingress)| {
-                    self.spawner
-                        .spawn(io::streams::incoming(self.clone(), ingress))
-                        .detach();
-                    conn
-                }),
+            None => io::connect(
+                &self.endpoint,
+                &self.breakers,
+                &self.reachability,
+                &self.address_book,
+                to,
+                addr_hints,
+            )
+            .in_current_span()
+            .await
+            .map(|(conn, ingress)| {
+                self.spawner
+                    .spawn(io::streams::incoming(self.clone(), ingress))
+                    .detach();
+                conn
+            }),
         }
     }
 
+    /// The compression to apply to messages sent to `peer`, if any.
+    pub fn compression(&self, peer: &PeerId) -> Option<compression::Config> {
+        self.config
+            .compression
+            .negotiate(&self.membership.capabilities(peer))
+    }
+
     pub fn has_connection(&self, to: PeerId) -> bool {
         self.endpoint.get_connection(to).is_some()
     }
@@ -116,6 +149,7 @@ where
 #[derive(Clone)]
 pub(super) struct RateLimits {
     pub membership: Arc<RateLimiter<Keyed<PeerId>>>,
+    pub ingress: throttle::Throttle,
 }
 
 /// Rate limit quota.
@@ -132,6 +166,14 @@ pub struct Quota {
     pub membership: rate_limit::Quota,
     /// See [`StorageQuota`].
     pub storage: StorageQuota,
+    /// See [`RequestPullQuota`].
+    pub request_pull: RequestPullQuota,
+    /// See [`egress::Quota`].
+    pub egress: egress::Quota,
+    /// See [`breaker::Quota`].
+    pub breaker: breaker::Quota,
+    /// See [`throttle::Quota`].
+    pub ingress: throttle::Quota,
 }
 
 impl Default for Quota {
@@ -140,6 +182,10 @@ impl Default for Quota {
             gossip: GossipQuota::default(),
             membership: rate_limit::Quota::per_second(nonzero!(1u32)).allow_burst(nonzero!(10u32)),
             storage: StorageQuota::default(),
+            request_pull: RequestPullQuota::default(),
+            egress: egress::Quota::default(),
+            breaker: breaker::Quota::default(),
+            ingress: throttle::Quota::default(),
         }
     }
 }
@@ -189,6 +235,26 @@ impl Default for StorageQuota {
     }
 }
 
+/// Request-pull quota.
+#[derive(Clone, Debug)]
+pub struct RequestPullQuota {
+    /// Replications to run concurrently on behalf of request-pull requests.
+    ///
+    /// Requests exceeding this limit are queued. Note that a batched
+    /// request-pull counts once per URN.
+    ///
+    /// Default: 8
+    pub max_concurrent: NonZeroUsize,
+}
+
+impl Default for RequestPullQuota {
+    fn default() -> Self {
+        Self {
+            max_concurrent: nonzero!(8usize),
+        }
+    }
+}
+
 //
 // Peer Storage (gossip)
 //
@@ -0,0 +1,281 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Rate limiting of inbound protocol messages.
+//!
+//! Every inbound gossip message, request-pull and request-push is checked
+//! against two token buckets: one keyed by the [`PeerId`] of the sender, and
+//! one keyed by its IP address. The latter prevents a single host from evading
+//! the limit by minting fresh peer ids. Since IPv6 hosts are usually assigned a
+//! whole /64, IPv6 addresses are keyed by their /64 prefix. If either bucket is
+//! exhausted, the message is shed before doing any work on it: gossip is
+//! dropped silently, and request-pulls and request-pushes are answered with a
+//! canned error response. A shed message doesn't consume a token from either
+//! bucket, so a peer sharing an exhausted IP keeps its own quota.
+//!
+//! The number of keys tracked per bucket map is bounded. When the map is full,
+//! keys whose bucket has refilled are swept, and if that doesn't free up
+//! space, the key closest to a full bucket is evicted.
+//!
+//! Shed messages are counted in [`Stats`], and reported as
+//! [`crate::net::metrics::names::LOAD_SHED`].
+
+use std::{
+    cmp::max,
+    collections::HashMap,
+    hash::Hash,
+    mem,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use nonzero_ext::nonzero;
+use parking_lot::Mutex;
+
+use crate::{rate_limit, PeerId};
+
+/// Memory to retain per bucket map before keys are swept or evicted.
+const LIMITER_MEM: usize = 1024 * 1024;
+
+/// The kinds of inbound messages subject to rate limiting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Gossip,
+    RequestPull,
+    RequestPush,
+}
+
+impl Kind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gossip => "gossip",
+            Self::RequestPull => "request-pull",
+            Self::RequestPush => "request-push",
+        }
+    }
+}
+
+/// The bucket which was exhausted when a message was shed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Peer,
+    Ip,
+}
+
+impl Limit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Peer => "peer",
+            Self::Ip => "ip",
+        }
+    }
+}
+
+/// Rate limits for one [`Kind`] of message.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// Messages per remote peer.
+    pub per_peer: rate_limit::Quota,
+    /// Messages per remote IP address.
+    pub per_ip: rate_limit::Quota,
+}
+
+/// Inbound rate limit quota.
+#[derive(Clone, Debug)]
+pub struct Quota {
+    /// Gossip messages.
+    ///
+    /// Default: 10/sec (burst: 100) per peer, 50/sec (burst: 500) per IP
+    pub gossip: Limits,
+    /// Request-pulls.
+    ///
+    /// Default: 10/min (burst: 20) per peer, 30/min (burst: 60) per IP
+    pub request_pull: Limits,
+    /// Request-pushes.
+    ///
+    /// Default: 10/min (burst: 20) per peer, 30/min (burst: 60) per IP
+    pub request_push: Limits,
+}
+
+impl Default for Quota {
+    fn default() -> Self {
+        Self {
+            gossip: Limits {
+                per_peer: rate_limit::Quota::per_second(nonzero!(10u32))
+                    .allow_burst(nonzero!(100u32)),
+                per_ip: rate_limit::Quota::per_second(nonzero!(50u32))
+                    .allow_burst(nonzero!(500u32)),
+            },
+            request_pull: Limits {
+                per_peer: rate_limit::Quota::per_minute(nonzero!(10u32))
+                    .allow_burst(nonzero!(20u32)),
+                per_ip: rate_limit::Quota::per_minute(nonzero!(30u32)).allow_burst(nonzero!(60u32)),
+            },
+            request_push: Limits {
+                per_peer: rate_limit::Quota::per_minute(nonzero!(10u32))
+                    .allow_burst(nonzero!(20u32)),
+                per_ip: rate_limit::Quota::per_minute(nonzero!(30u32)).allow_burst(nonzero!(60u32)),
+            },
+        }
+    }
+}
+
+/// Shed message counters, cumulative since startup.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    /// Gossip messages dropped.
+    pub gossip: u64,
+    /// Request-pulls rejected.
+    pub request_pull: u64,
+    /// Request-pushes rejected.
+    pub request_push: u64,
+}
+
+/// Keyed token buckets implementing the generic cell rate algorithm.
+///
+/// Unlike [`rate_limit::RateLimiter`], this allows to check whether a key has
+/// a token left without taking it.
+struct Cells<K> {
+    /// Time to replenish one token.
+    interval: Duration,
+    /// Time to replenish a full bucket.
+    capacity: Duration,
+    /// Theoretical arrival time of the next message per key.
+    tats: HashMap<K, Instant>,
+}
+
+impl<K: Clone + Eq + Hash> Cells<K> {
+    fn new(quota: rate_limit::Quota) -> Self {
+        let interval = quota.replenish_interval();
+        Self {
+            interval,
+            capacity: interval * quota.burst_size().get(),
+            tats: HashMap::new(),
+        }
+    }
+
+    /// The new theoretical arrival time for `key` if it has a token left at
+    /// `now`.
+    fn peek(&self, key: &K, now: Instant) -> Option<Instant> {
+        let tat = self.tats.get(key).map_or(now, |tat| max(*tat, now)) + self.interval;
+        (tat - now <= self.capacity).then(|| tat)
+    }
+
+    /// Take a token for `key`, as previously determined by [`Cells::peek`].
+    fn take(&mut self, key: K, tat: Instant, now: Instant) {
+        let max_keys = LIMITER_MEM / mem::size_of::<(K, Instant)>();
+        if self.tats.len() >= max_keys && !self.tats.contains_key(&key) {
+            // Full buckets behave the same as absent ones
+            self.tats.retain(|_, tat| *tat > now);
+            if self.tats.len() >= max_keys {
+                let oldest = self
+                    .tats
+                    .iter()
+                    .min_by_key(|(_, tat)| **tat)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    self.tats.remove(&oldest);
+                }
+            }
+        }
+        self.tats.insert(key, tat);
+    }
+}
+
+struct Buckets {
+    peers: Cells<PeerId>,
+    ips: Cells<IpAddr>,
+}
+
+impl Buckets {
+    fn new(limits: Limits) -> Self {
+        Self {
+            peers: Cells::new(limits.per_peer),
+            ips: Cells::new(limits.per_ip),
+        }
+    }
+
+    /// Take a token from both buckets, or none if either is exhausted.
+    fn check(&mut self, peer: &PeerId, ip: &IpAddr) -> Result<(), Limit> {
+        let now = Instant::now();
+        let peer_tat = self.peers.peek(peer, now).ok_or(Limit::Peer)?;
+        let ip = ip_key(ip);
+        let ip_tat = self.ips.peek(&ip, now).ok_or(Limit::Ip)?;
+        self.peers.take(*peer, peer_tat, now);
+        self.ips.take(ip, ip_tat, now);
+
+        Ok(())
+    }
+}
+
+/// The key of the per-IP bucket for `ip`.
+///
+/// IPv6 addresses are truncated to their /64 prefix, while IPv4-mapped IPv6
+/// addresses are keyed by the IPv4 address they map.
+fn ip_key(ip: &IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => *ip,
+        IpAddr::V6(v6) => match v6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, hi, lo] => {
+                IpAddr::V4(Ipv4Addr::from(u32::from(hi) << 16 | u32::from(lo)))
+            },
+            _ => IpAddr::V6(Ipv6Addr::from(u128::from(*v6) & !u128::from(u64::MAX))),
+        },
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    gossip: AtomicU64,
+    request_pull: AtomicU64,
+    request_push: AtomicU64,
+}
+
+/// The inbound rate limiter, see the [module documentation](self).
+///
+/// Clones share the buckets.
+#[derive(Clone)]
+pub struct Throttle {
+    gossip: Arc<Mutex<Buckets>>,
+    request_pull: Arc<Mutex<Buckets>>,
+    request_push: Arc<Mutex<Buckets>>,
+    counters: Arc<Counters>,
+}
+
+impl Throttle {
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            gossip: Arc::new(Mutex::new(Buckets::new(quota.gossip))),
+            request_pull: Arc::new(Mutex::new(Buckets::new(quota.request_pull))),
+            request_push: Arc::new(Mutex::new(Buckets::new(quota.request_push))),
+            counters: Default::default(),
+        }
+    }
+
+    /// Take a token for a message of `kind` from `peer` at `ip`.
+    pub fn check(&self, kind: Kind, peer: &PeerId, ip: &IpAddr) -> Result<(), Limit> {
+        let (buckets, counter) = match kind {
+            Kind::Gossip => (&self.gossip, &self.counters.gossip),
+            Kind::RequestPull => (&self.request_pull, &self.counters.request_pull),
+            Kind::RequestPush => (&self.request_push, &self.counters.request_push),
+        };
+        buckets.lock().check(peer, ip).map_err(|limit| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            limit
+        })
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            gossip: self.counters.gossip.load(Ordering::Relaxed),
+            request_pull: self.counters.request_pull.load(Ordering::Relaxed),
+            request_push: self.counters.request_push.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -33,6 +33,10 @@ pub(super) enum Tock<A, P> {
 
     /// Close connections due to eviction from partial view
     Disconnect { peer: PeerId },
+
+    /// Send the messages queued for a connected peer, or notify of connection
+    /// loss
+    Drain { to: PeerId },
 }
 
 #[tracing::instrument(level = "debug", skip(state))]
@@ -61,7 +65,7 @@ where
             mcfly.extend(
                 membership::tocks(
                     &state.membership,
-                    io::peer_advertisement(&state.endpoint),
+                    io::peer_advertisement(&state.endpoint, &state.config.capabilities),
                     Some(tick),
                 )
                 .into_iter()
@@ -84,31 +88,21 @@ where
     async move {
         let mut events = vec![];
         let res = match tock {
-            SendConnected { to, message } => match state.connection(to, None).await {
-                None => {
-                    let membership::TnT { trans, ticks: cont } =
-                        state.membership.connection_lost(to);
-                    events = trans;
-                    Err(error::Tock::Reliable(error::ReliableSend {
-                        cont,
-                        source: error::ReliableSendSource::NotConnected { to },
-                    }))
-                },
-
-                Some(conn) => {
-                    io::send_rpc(&conn, message)
-                        .map_err(|e| {
-                            let membership::TnT { trans, ticks: cont } =
-                                state.membership.connection_lost(to);
-                            events = trans;
-                            error::Tock::Reliable(error::ReliableSend {
-                                cont,
-                                source: e.into(),
-                            })
-                        })
-                        .await?;
-                    Ok(vec![])
-                },
+            SendConnected { to, message } => {
+                // Draining may take a while if `to` is slow. Hand it off, so the
+                // caller doesn't have to wait for it.
+                if state.egress.push(to, message) {
+                    state
+                        .spawner
+                        .spawn(self::tock(state.clone(), Drain { to }))
+                        .detach();
+                }
+                Ok(vec![])
+            },
+
+            Drain { to } => {
+                drain(&state, to, &mut events).await?;
+                Ok(vec![])
             },
 
             AttemptSend { to, message } => {
@@ -138,6 +132,40 @@ where
     .boxed()
 }
 
+/// Send the messages queued for `to` until the queue is empty.
+///
+/// If sending fails, the remaining messages are discarded, and the error is
+/// returned along with the continuations of losing the connection.
+async fn drain<S, G>(
+    state: &State<S, G>,
+    to: PeerId,
+    events: &mut Vec<membership::Transition<SocketAddr>>,
+) -> Result<(), error::ReliableSend<SocketAddr>>
+where
+    S: ProtocolStorage<SocketAddr, Update = gossip::Payload> + 'static,
+    G: RequestPullGuard,
+{
+    while let Some(message) = state.egress.pop(&to) {
+        let res = match state.connection(to, None).await {
+            None => Err(error::ReliableSendSource::NotConnected { to }),
+            Some(conn) => io::send_rpc(&conn, message, state.compression(&to))
+                .await
+                .map_err(Into::into),
+        };
+        match res {
+            Ok(()) => state.egress.sent(),
+            Err(source) => {
+                state.egress.discard(&to);
+                let membership::TnT { trans, ticks: cont } = state.membership.connection_lost(to);
+                events.extend(trans);
+                return Err(error::ReliableSend { cont, source });
+            },
+        }
+    }
+
+    Ok(())
+}
+
 async fn try_connect_and_send<S, G>(
     state: &State<S, G>,
     to: &PeerInfo<SocketAddr>,
@@ -151,7 +179,11 @@ where
         .connection(to.peer_id, to.addrs().copied().collect::<Vec<_>>())
         .await
         .ok_or_else(|| error::BestEffortSend::CouldNotConnect { to: to.clone() })?;
-    io::send_rpc(&conn, message)
+    let compression = state
+        .config
+        .compression
+        .negotiate(&to.advertised_info.capabilities);
+    io::send_rpc(&conn, message, compression)
         .map_err(error::BestEffortSend::SendGossip)
         .await
 }
@@ -3,8 +3,9 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{collections::BTreeMap, net::SocketAddr, sync::Arc};
 
+use git_ext as ext;
 use parking_lot::Mutex;
 pub use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::{broadcast as tincan, mpsc, oneshot::Receiver};
@@ -15,12 +16,16 @@ use super::{
     gossip,
     info::PeerAdvertisement,
     interrogation,
+    membership,
     request_pull,
 };
 use crate::{
-    git::Urn,
+    git::{storage::digest, Urn},
     identities::xor::Xor,
-    net::quic::{self, ConnectPeer},
+    net::{
+        metrics::Recorder,
+        quic::{self, ConnectPeer},
+    },
     PeerId,
 };
 
@@ -30,6 +35,7 @@ pub struct Connected(pub(crate) quic::Connection);
 pub struct TinCans {
     pub(super) downstream: tincan::Sender<event::Downstream>,
     pub(super) upstream: tincan::Sender<event::Upstream>,
+    metrics: Recorder,
 }
 
 impl TinCans {
@@ -37,9 +43,18 @@ impl TinCans {
         Self {
             downstream: tincan::channel(16).0,
             upstream: tincan::channel(16).0,
+            metrics: Recorder::default(),
         }
     }
 
+    pub(crate) fn with_metrics(self, metrics: Recorder) -> Self {
+        Self { metrics, ..self }
+    }
+
+    pub(crate) fn metrics(&self) -> &Recorder {
+        &self.metrics
+    }
+
     pub fn announce(&self, have: gossip::Payload) -> Result<(), gossip::Payload> {
         use event::downstream::Gossip::Announce;
 
@@ -88,6 +103,30 @@ impl TinCans {
         rx.await.unwrap_or_default()
     }
 
+    pub async fn connections(&self) -> Vec<event::downstream::ConnectionInfo> {
+        use event::downstream::Info::*;
+
+        let (tx, rx) = replier();
+        if let Err(tincan::error::SendError(e)) =
+            self.downstream.send(Downstream::Info(Connections(tx)))
+        {
+            match e {
+                Downstream::Info(Connections(reply)) => {
+                    reply
+                        .lock()
+                        .take()
+                        .expect("if chan send failed, there can't be another contender")
+                        .send(vec![])
+                        .ok();
+                },
+
+                _ => unreachable!(),
+            }
+        }
+
+        rx.await.unwrap_or_default()
+    }
+
     pub async fn membership(&self) -> event::downstream::MembershipInfo {
         use event::downstream::{Info::*, MembershipInfo};
 
@@ -111,6 +150,30 @@ impl TinCans {
         rx.await.unwrap_or_default()
     }
 
+    pub async fn membership_snapshot(&self) -> Option<membership::Snapshot<SocketAddr>> {
+        use event::downstream::Info::*;
+
+        let (tx, rx) = replier();
+        if let Err(tincan::error::SendError(e)) = self
+            .downstream
+            .send(Downstream::Info(MembershipSnapshot(tx)))
+        {
+            match e {
+                Downstream::Info(MembershipSnapshot(reply)) => {
+                    reply
+                        .lock()
+                        .take()
+                        .expect("if chan send failed, there can't be another contender")
+                        .send(None)
+                        .ok();
+                },
+                _ => unreachable!(),
+            }
+        }
+
+        rx.await.unwrap_or_default()
+    }
+
     pub async fn stats(&self) -> event::downstream::Stats {
         use event::downstream::{Info::*, Stats};
 
@@ -200,7 +263,11 @@ impl TinCans {
     }
 
     pub(crate) fn emit(&self, evt: impl Into<event::Upstream>) {
-        self.upstream.send(evt.into()).ok();
+        let evt = evt.into();
+        if let event::Upstream::Membership(transition) = &evt {
+            self.metrics.membership(transition)
+        }
+        self.upstream.send(evt).ok();
     }
 }
 
@@ -276,6 +343,48 @@ impl Interrogation {
             })
     }
 
+    /// Ask the interrogated peer to send the tips of the `rad/signed_refs` it
+    /// has for `urn`, keyed by the peer they belong to.
+    ///
+    /// Comparing those to the local state allows to skip fetching from the
+    /// interrogated peer if it doesn't have anything new.
+    pub async fn sigref_tips(
+        &self,
+        urn: Urn,
+    ) -> Result<BTreeMap<PeerId, ext::Oid>, error::Interrogation> {
+        use interrogation::{Request, Response};
+
+        self.request(Request::GetSigrefTips(urn))
+            .await
+            .and_then(|resp| match resp {
+                Response::SigrefTips(tips) => Ok(tips),
+                Response::Error(e) => Err(error::Interrogation::ErrorResponse(e)),
+                _ => Err(error::Interrogation::InvalidResponse),
+            })
+    }
+
+    /// Ask the interrogated peer to send the [`digest::Summary`] of `urn`,
+    /// optionally including the `rad/signed_refs`.
+    ///
+    /// Returns `None` if the interrogated peer doesn't have `urn`. Comparing
+    /// the summary to the local one (see [`digest::summary`]) reveals whether,
+    /// and for which peers, the views diverge.
+    pub async fn digest(
+        &self,
+        urn: Urn,
+        sigrefs: bool,
+    ) -> Result<Option<digest::Summary>, error::Interrogation> {
+        use interrogation::{Request, Response};
+
+        self.request(Request::GetDigest(urn, sigrefs))
+            .await
+            .and_then(|resp| match resp {
+                Response::Digest(summary) => Ok(summary),
+                Response::Error(e) => Err(error::Interrogation::ErrorResponse(e)),
+                _ => Err(error::Interrogation::InvalidResponse),
+            })
+    }
+
     async fn request(
         &self,
         request: interrogation::Request,
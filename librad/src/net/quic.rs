@@ -13,14 +13,19 @@ pub use connection::{
     ConnectionId,
     Conntrack,
     IncomingStreams,
+    Stats as ConnectionStats,
 };
 
 mod endpoint;
+pub(in crate::net) use endpoint::alpn;
 pub use endpoint::{BoundEndpoint, ConnectPeer, Endpoint, IncomingConnections, Ingress, SendOnly};
 
 pub mod error;
 pub use error::{Error, Result};
 
+pub mod router;
+pub use router::Router;
+
 mod stream;
 pub use stream::{BidiStream, RecvStream, SendStream};
 
@@ -31,8 +36,9 @@ const ALPN_PREFIX: &[u8] = b"rad";
 
 /// Connection keep alive interval.
 ///
-/// Only set for initiators (clients). The value of 30s is recommended for
-/// keeping middlebox UDP flows alive.
+/// Only set for initiators (clients), unless configured otherwise via
+/// [`KeepAlive`]. The value of 30s is recommended for keeping middlebox UDP
+/// flows alive.
 const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
 
 /// Connection idle timeout.
@@ -41,5 +47,37 @@ const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
 /// keep alive probes. Should tolerate the loss of 1-2 keep-alive probes.
 pub(in crate::net) const MAX_IDLE_TIMEOUT: Duration = Duration::from_secs(65);
 
+/// Keep-alive settings of an [`Endpoint`].
+#[derive(Clone, Copy, Debug)]
+pub struct KeepAlive {
+    /// Interval of keep-alive pings on connections we initiated.
+    ///
+    /// Values greater than half of the idle timeout of 65s are clamped, so
+    /// the loss of a single ping doesn't time out the connection.
+    ///
+    /// Default: 30s
+    pub interval: Duration,
+    /// Also send keep-alive pings on connections initiated by the remote
+    /// peer, eg. to keep the NAT mapping of a peer behind a NAT alive.
+    ///
+    /// Default: false
+    pub responder: bool,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self {
+            interval: KEEP_ALIVE_INTERVAL,
+            responder: false,
+        }
+    }
+}
+
+impl KeepAlive {
+    fn interval(&self) -> Duration {
+        self.interval.min(MAX_IDLE_TIMEOUT / 2)
+    }
+}
+
 /// Maximum number of connections to a single peer.
 const MAX_PEER_CONNECTIONS: usize = 5;
@@ -13,6 +13,7 @@ use std::{
     result::Result as StdResult,
     sync::Arc,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use either::Either;
@@ -138,6 +139,17 @@ pub enum BorrowUniError<E: std::error::Error + 'static> {
     Upgrade(#[source] E),
 }
 
+/// Transport statistics of a [`Connection`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    /// The current best estimate of the round-trip time.
+    pub rtt: Duration,
+    /// Bytes sent in UDP datagrams, including protocol overhead.
+    pub bytes_sent: u64,
+    /// Bytes received in UDP datagrams, including protocol overhead.
+    pub bytes_received: u64,
+}
+
 #[derive(Clone)]
 pub struct Connection {
     peer: PeerId,
@@ -268,6 +280,15 @@ impl Connection {
     pub fn stable_id(&self) -> usize {
         self.conn.stable_id()
     }
+
+    pub fn stats(&self) -> Stats {
+        let stats = self.conn.stats();
+        Stats {
+            rtt: self.conn.rtt(),
+            bytes_sent: stats.udp_tx.bytes,
+            bytes_received: stats.udp_rx.bytes,
+        }
+    }
 }
 
 impl RemotePeer for Connection {
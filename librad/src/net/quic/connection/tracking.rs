@@ -115,6 +115,16 @@ impl Conntrack {
             .collect()
     }
 
+    /// Get all tracked connections.
+    ///
+    /// Liveness of the connections is not checked.
+    pub fn connections(&self) -> Vec<Connection> {
+        self.connections
+            .iter()
+            .map(|r| r.value().connection.clone())
+            .collect()
+    }
+
     /// Get the currently-connected peers.
     ///
     /// Liveness of the connection(s) associated with each peer is not checked,
@@ -9,13 +9,11 @@ use std::{
     net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
     pin::Pin,
     sync::{Arc, Weak},
+    time::Instant,
 };
 
 use async_trait::async_trait;
-use futures::{
-    future,
-    stream::{BoxStream, StreamExt as _, TryStreamExt as _},
-};
+use futures::stream::{BoxStream, StreamExt as _, TryStreamExt as _};
 use if_watch::IfWatcher;
 use link_async::Spawner;
 use nonempty::NonEmpty;
@@ -23,10 +21,11 @@ use parking_lot::RwLock;
 use quinn::{NewConnection, TransportConfig};
 use socket2::{Domain, Protocol, Socket, Type};
 
-use super::{BoxedIncomingStreams, Connection, Conntrack, Error, Result};
+use super::{router::Route, BoxedIncomingStreams, Connection, Conntrack, Error, KeepAlive, Result};
 use crate::{
     net::{
         connection::{CloseReason, LocalAddr, LocalPeer},
+        happy_eyeballs,
         tls,
         x509,
         Network,
@@ -106,6 +105,9 @@ pub struct Endpoint<const R: usize> {
     endpoint: quinn::Endpoint,
     listen_addrs: Arc<RwLock<BTreeSet<SocketAddr>>>,
     conntrack: Conntrack,
+    /// Set if the `endpoint` is shared via a [`super::Router`], in which case
+    /// outbound connections use this peer's client config.
+    shared: Option<(quinn::ClientConfig, Arc<Route>)>,
     _refcount: Arc<()>,
 }
 
@@ -116,6 +118,7 @@ impl<const R: usize> Endpoint<R> {
         listen_addr: SocketAddr,
         advertised_addrs: Option<NonEmpty<SocketAddr>>,
         network: Network,
+        keep_alive: KeepAlive,
     ) -> Result<BoundEndpoint<'a, R>>
     where
         S: Signer + Clone + Send + Sync + 'static,
@@ -124,26 +127,16 @@ impl<const R: usize> Endpoint<R> {
         let peer_id = PeerId::from_signer(&signer);
 
         let sock = bind_socket(listen_addr)?;
-        let listen_addr = sock.local_addr()?;
-        let addrs = {
-            let listen_addrs = Arc::new(RwLock::new(BTreeSet::new()));
-            match advertised_addrs {
-                Some(addrs) => listen_addrs.write().extend(addrs),
-                None if listen_addr.ip().is_unspecified() => {
-                    ifwatch(spawner, listen_addr, Arc::downgrade(&listen_addrs)).await?
-                },
-                None => listen_addrs.write().extend(Some(listen_addr)),
-            }
-            listen_addrs
-        };
+        let addrs = listen_addrs(spawner, &sock, advertised_addrs).await?;
 
-        let (endpoint, incoming) = make_endpoint(signer, sock, alpn(network)).await?;
+        let (endpoint, incoming) = make_endpoint(signer, sock, alpn(network), keep_alive).await?;
         let conntrack = Conntrack::new();
         let endpoint = Endpoint {
             peer_id,
             endpoint,
             listen_addrs: addrs,
             conntrack: conntrack.clone(),
+            shared: None,
             _refcount: Arc::new(()),
         };
         let incoming = incoming
@@ -152,16 +145,7 @@ impl<const R: usize> Endpoint<R> {
                 let conntrack = conntrack.clone();
                 async move {
                     let conn = connecting.await?;
-                    let remote_peer = remote_peer(&conn)?;
-                    debug_assert!(
-                        remote_peer != peer_id,
-                        "self-connections are prevented in the TLS handshake"
-                    );
-                    let (conn, streams) =
-                        Connection::new(Some(conntrack.clone()), R, remote_peer, conn);
-                    conntrack.connected(&conn);
-
-                    Ok((conn, streams.boxed()))
+                    accepted::<R>(peer_id, &conntrack, conn)
                 }
             })
             .boxed();
@@ -169,6 +153,24 @@ impl<const R: usize> Endpoint<R> {
         Ok(BoundEndpoint { endpoint, incoming })
     }
 
+    pub(super) fn shared(
+        peer_id: PeerId,
+        endpoint: quinn::Endpoint,
+        listen_addrs: Arc<RwLock<BTreeSet<SocketAddr>>>,
+        conntrack: Conntrack,
+        client_config: quinn::ClientConfig,
+        route: Route,
+    ) -> Self {
+        Self {
+            peer_id,
+            endpoint,
+            listen_addrs,
+            conntrack,
+            shared: Some((client_config, Arc::new(route))),
+            _refcount: Arc::new(()),
+        }
+    }
+
     pub fn listen_addrs(&self) -> Vec<SocketAddr> {
         self.listen_addrs.read().iter().copied().collect()
     }
@@ -185,6 +187,10 @@ impl<const R: usize> Endpoint<R> {
         self.conntrack.peers()
     }
 
+    pub fn connections(&self) -> Vec<Connection> {
+        self.conntrack.connections()
+    }
+
     pub async fn connect<'a>(
         &mut self,
         peer: PeerId,
@@ -194,10 +200,16 @@ impl<const R: usize> Endpoint<R> {
             return Err(Error::SelfConnect);
         }
 
-        let conn = self
-            .endpoint
-            .connect(addr, peer.as_dns_name().as_ref().into())?
-            .await?;
+        let server_name = peer.as_dns_name();
+        let connecting = match &self.shared {
+            Some((client_config, _)) => self.endpoint.connect_with(
+                client_config.clone(),
+                addr,
+                server_name.as_ref().into(),
+            )?,
+            None => self.endpoint.connect(addr, server_name.as_ref().into())?,
+        };
+        let conn = connecting.await?;
         let (conn, streams) = Connection::new(Some(self.conntrack.clone()), R, peer, conn);
         self.conntrack.connected(&conn);
 
@@ -217,14 +229,24 @@ impl<const R: usize> Endpoint<R> {
             connections = self.conntrack.total(),
             "endpoint shutdown requested"
         );
-        let reason = CloseReason::ServerShutdown;
-        self.endpoint
-            .close((reason as u32).into(), reason.reason_phrase());
+        match &self.shared {
+            // Other peers may still be using the endpoint
+            Some((_, route)) => route.remove(),
+            None => {
+                let reason = CloseReason::ServerShutdown;
+                self.endpoint
+                    .close((reason as u32).into(), reason.reason_phrase());
+            },
+        }
         self.conntrack.disconnect_all();
     }
 
     pub async fn wait_idle(&self) {
-        self.endpoint.wait_idle().await
+        // The connections of other peers sharing the endpoint are none of our
+        // business
+        if self.shared.is_none() {
+            self.endpoint.wait_idle().await
+        }
     }
 }
 
@@ -248,6 +270,7 @@ impl<const R: usize> LocalAddr for Endpoint<R> {
 pub struct SendOnly {
     peer_id: PeerId,
     endpoint: quinn::Endpoint,
+    reachability: happy_eyeballs::Reachability,
 }
 
 impl SendOnly {
@@ -261,7 +284,11 @@ impl SendOnly {
         let listen_addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 0));
         let sock = bind_socket(listen_addr)?;
         let endpoint = make_send_only(signer, sock, alpn(network)).await?;
-        Ok(Self { peer_id, endpoint })
+        Ok(Self {
+            peer_id,
+            endpoint,
+            reachability: happy_eyeballs::Reachability::new(),
+        })
     }
 
     pub async fn connect<'a>(
@@ -295,27 +322,71 @@ impl ConnectPeer for SendOnly {
             return None;
         }
 
-        future::select_ok(addrs.into_iter().map(|addr| {
+        let addrs = self.reachability.sort(peer, addrs.into_iter().collect());
+        happy_eyeballs::race(addrs, happy_eyeballs::CONNECTION_ATTEMPT_DELAY, |addr| {
             let endpoint = self.clone();
+            let started = Instant::now();
             tracing::info!(remote_addr = %addr, "establishing connection");
-            Box::pin(async move {
-                Self::connect(&endpoint, peer, &addr)
-                    .await
-                    .map_err(|e| {
+            async move {
+                match Self::connect(&endpoint, peer, &addr).await {
+                    Ok((conn, streams)) => {
+                        endpoint.reachability.success(peer, addr, started.elapsed());
+                        Ok(Ingress::Local { conn, streams })
+                    },
+                    Err(e) => {
                         tracing::warn!(err = ?e, remote_addr = %addr, "could not connect");
-                        e
-                    })
-                    .map(|(conn, streams)| Ingress::Local { conn, streams })
-            })
-        }))
+                        endpoint.reachability.failure(peer, addr);
+                        Err(e)
+                    },
+                }
+            }
+        })
         .await
         .ok()
-        .map(|(success, _pending)| success)
     }
 }
 
+/// Wrap an inbound connection accepted on behalf of `local_id`.
+pub(super) fn accepted<'a, const R: usize>(
+    local_id: PeerId,
+    conntrack: &Conntrack,
+    conn: NewConnection,
+) -> Result<(Connection, BoxedIncomingStreams<'a>)> {
+    let remote_peer = remote_peer(&conn)?;
+    debug_assert!(
+        remote_peer != local_id,
+        "self-connections are prevented in the TLS handshake"
+    );
+    let (conn, streams) = Connection::new(Some(conntrack.clone()), R, remote_peer, conn);
+    conntrack.connected(&conn);
+
+    Ok((conn, streams.boxed()))
+}
+
+/// The addresses to advertise for the endpoint bound to `sock`.
+///
+/// If `advertised_addrs` are not given and `sock` is bound to the unspecified
+/// address, the addresses of all network interfaces are tracked.
+pub(super) async fn listen_addrs(
+    spawner: &Spawner,
+    sock: &UdpSocket,
+    advertised_addrs: Option<NonEmpty<SocketAddr>>,
+) -> Result<Arc<RwLock<BTreeSet<SocketAddr>>>> {
+    let listen_addr = sock.local_addr()?;
+    let listen_addrs = Arc::new(RwLock::new(BTreeSet::new()));
+    match advertised_addrs {
+        Some(addrs) => listen_addrs.write().extend(addrs),
+        None if listen_addr.ip().is_unspecified() => {
+            ifwatch(spawner, listen_addr, Arc::downgrade(&listen_addrs)).await?
+        },
+        None => listen_addrs.write().extend(Some(listen_addr)),
+    }
+
+    Ok(listen_addrs)
+}
+
 // TODO: tune buffer sizes
-fn bind_socket(listen_addr: SocketAddr) -> Result<UdpSocket> {
+pub(super) fn bind_socket(listen_addr: SocketAddr) -> Result<UdpSocket> {
     let sock = Socket::new(
         Domain::for_address(listen_addr),
         Type::DGRAM,
@@ -411,7 +482,7 @@ fn remote_peer(conn: &NewConnection) -> Result<PeerId> {
 
 type Alpn = Vec<u8>;
 
-fn alpn(network: Network) -> Alpn {
+pub(in crate::net) fn alpn(network: Network) -> Alpn {
     let mut alpn = super::ALPN_PREFIX.to_vec();
     alpn.push(b'/');
     alpn.push(PROTOCOL_VERSION);
@@ -431,7 +502,7 @@ where
     S::Error: std::error::Error + Send + Sync + 'static,
 {
     let mut builder = quinn::Endpoint::builder();
-    builder.default_client_config(make_client_config(signer, alpn)?);
+    builder.default_client_config(make_client_config(signer, alpn, KeepAlive::default())?);
 
     Ok(builder.with_socket(sock)?.0)
 }
@@ -440,19 +511,28 @@ async fn make_endpoint<S>(
     signer: S,
     sock: UdpSocket,
     alpn: Alpn,
+    keep_alive: KeepAlive,
 ) -> Result<(quinn::Endpoint, quinn::Incoming)>
 where
     S: Signer + Clone + Send + Sync + 'static,
     S::Error: std::error::Error + Send + Sync + 'static,
 {
     let mut builder = quinn::Endpoint::builder();
-    builder.default_client_config(make_client_config(signer.clone(), alpn.clone())?);
-    builder.listen(make_server_config(signer, alpn)?);
+    builder.default_client_config(make_client_config(
+        signer.clone(),
+        alpn.clone(),
+        keep_alive,
+    )?);
+    builder.listen(make_server_config(signer, alpn, keep_alive)?);
 
     Ok(builder.with_socket(sock)?)
 }
 
-fn make_client_config<S>(signer: S, alpn: Vec<u8>) -> Result<quinn::ClientConfig>
+pub(super) fn make_client_config<S>(
+    signer: S,
+    alpn: Vec<u8>,
+    keep_alive: KeepAlive,
+) -> Result<quinn::ClientConfig>
 where
     S: Signer + Clone + Send + Sync + 'static,
     S::Error: std::error::Error + Send + Sync + 'static,
@@ -462,7 +542,7 @@ where
 
     let mut transport_config = TransportConfig::default();
     transport_config
-        .keep_alive_interval(Some(super::KEEP_ALIVE_INTERVAL))
+        .keep_alive_interval(Some(keep_alive.interval()))
         // Set idle timeout anyway, as the default is smaller than our
         // keep-alive
         .max_idle_timeout(Some(super::MAX_IDLE_TIMEOUT))
@@ -475,18 +555,33 @@ where
     Ok(quic_config)
 }
 
-fn make_server_config<S>(signer: S, alpn: Vec<u8>) -> Result<quinn::ServerConfig>
+fn make_server_config<S>(
+    signer: S,
+    alpn: Vec<u8>,
+    keep_alive: KeepAlive,
+) -> Result<quinn::ServerConfig>
 where
     S: Signer + Clone + Send + Sync + 'static,
     S::Error: std::error::Error + Send + Sync + 'static,
 {
-    let mut tls_config = tls::make_server_config(signer).map_err(|e| Error::Signer(Box::new(e)))?;
+    let tls_config = tls::make_server_config(signer).map_err(|e| Error::Signer(Box::new(e)))?;
+    Ok(make_quic_server_config(tls_config, alpn, keep_alive))
+}
+
+pub(super) fn make_quic_server_config(
+    mut tls_config: rustls::ServerConfig,
+    alpn: Vec<u8>,
+    keep_alive: KeepAlive,
+) -> quinn::ServerConfig {
     tls_config.alpn_protocols = vec![alpn];
 
     let mut transport_config = TransportConfig::default();
     transport_config
         .max_idle_timeout(Some(super::MAX_IDLE_TIMEOUT))
         .expect("idle timeout is in vetted range");
+    if keep_alive.responder {
+        transport_config.keep_alive_interval(Some(keep_alive.interval()));
+    }
 
     let mut quic_config = quinn::ServerConfigBuilder::default().build();
     quic_config.crypto = Arc::new(tls_config);
@@ -499,5 +594,5 @@ where
         .migration(true)
         .concurrent_connections(100_000);
 
-    Ok(quic_config)
+    quic_config
 }
@@ -0,0 +1,261 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Sharing a QUIC endpoint between several local peers.
+//!
+//! A [`Router`] binds a single socket, on which any number of local peers --
+//! eg. the work and personal profiles of a user -- can be registered. Each
+//! registered peer obtains its own [`Endpoint`], with its own connections.
+//!
+//! Remote peers name the peer they want to connect to via SNI. The TLS
+//! handshake presents the certificate of that peer, and the established
+//! connection is dispatched to its [`Endpoint`]. Outbound connections present
+//! the client certificate of the peer initiating them.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    net::SocketAddr,
+    str::FromStr as _,
+    sync::{Arc, Weak},
+};
+
+use futures::{channel::mpsc, stream::StreamExt as _};
+use link_async::Spawner;
+use nonempty::NonEmpty;
+use parking_lot::RwLock;
+use quinn::NewConnection;
+
+use super::{
+    endpoint::{
+        accepted,
+        alpn,
+        bind_socket,
+        listen_addrs,
+        make_client_config,
+        make_quic_server_config,
+    },
+    BoundEndpoint,
+    Conntrack,
+    Endpoint,
+    Error,
+    KeepAlive,
+    Result,
+};
+use crate::{
+    net::{connection::CloseReason, tls, Network},
+    PeerId,
+    Signer,
+};
+
+type Routes = RwLock<HashMap<PeerId, mpsc::UnboundedSender<NewConnection>>>;
+
+/// A QUIC endpoint shared by several local peers, see the [module
+/// documentation](self).
+#[derive(Clone)]
+pub struct Router<const R: usize> {
+    endpoint: quinn::Endpoint,
+    identities: tls::Identities,
+    routes: Arc<Routes>,
+    listen_addrs: Arc<RwLock<BTreeSet<SocketAddr>>>,
+    network: Network,
+    keep_alive: KeepAlive,
+}
+
+impl<const R: usize> Router<R> {
+    /// Bind the shared endpoint.
+    ///
+    /// The parameters are as for [`Endpoint::bind`], and apply to all peers
+    /// registered with the router.
+    pub async fn bind(
+        spawner: &Spawner,
+        listen_addr: SocketAddr,
+        advertised_addrs: Option<NonEmpty<SocketAddr>>,
+        network: Network,
+        keep_alive: KeepAlive,
+    ) -> Result<Self> {
+        let sock = bind_socket(listen_addr)?;
+        let listen_addrs = listen_addrs(spawner, &sock, advertised_addrs).await?;
+
+        let identities = tls::Identities::default();
+        let mut builder = quinn::Endpoint::builder();
+        builder.listen(make_quic_server_config(
+            tls::make_shared_server_config(identities.clone()),
+            alpn(network.clone()),
+            keep_alive,
+        ));
+        let (endpoint, incoming) = builder.with_socket(sock)?;
+
+        let routes = Arc::new(Routes::default());
+        spawner
+            .spawn(dispatch(incoming, Arc::downgrade(&routes)))
+            .detach();
+
+        Ok(Self {
+            endpoint,
+            identities,
+            routes,
+            listen_addrs,
+            network,
+            keep_alive,
+        })
+    }
+
+    /// Register the local peer identified by `signer`, returning its
+    /// [`Endpoint`] and the stream of its inbound connections.
+    ///
+    /// Registering a peer which is already registered replaces the previous
+    /// registration as the recipient of inbound connections.
+    pub fn register<'a, S>(&self, signer: S) -> Result<BoundEndpoint<'a, R>>
+    where
+        S: Signer + Clone + Send + Sync + 'static,
+        S::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let client_config =
+            make_client_config(signer.clone(), alpn(self.network.clone()), self.keep_alive)?;
+        let peer_id = self
+            .identities
+            .insert(signer)
+            .map_err(|e| Error::Signer(Box::new(e)))?;
+        let (tx, rx) = mpsc::unbounded();
+        self.routes.write().insert(peer_id, tx.clone());
+        tracing::info!(%peer_id, "registered peer with shared endpoint");
+
+        let conntrack = Conntrack::new();
+        let endpoint = Endpoint::shared(
+            peer_id,
+            self.endpoint.clone(),
+            self.listen_addrs.clone(),
+            conntrack.clone(),
+            client_config,
+            Route {
+                peer_id,
+                tx,
+                identities: self.identities.clone(),
+                routes: Arc::downgrade(&self.routes),
+            },
+        );
+        let incoming = rx
+            .map(move |conn| accepted::<R>(peer_id, &conntrack, conn))
+            .boxed();
+
+        Ok(BoundEndpoint { endpoint, incoming })
+    }
+
+    /// The local peers currently registered.
+    pub fn peers(&self) -> Vec<PeerId> {
+        self.routes.read().keys().copied().collect()
+    }
+
+    pub fn listen_addrs(&self) -> Vec<SocketAddr> {
+        self.listen_addrs.read().iter().copied().collect()
+    }
+
+    /// Close the endpoint, and thereby the connections of all peers.
+    pub fn close(&self) {
+        let reason = CloseReason::ServerShutdown;
+        self.endpoint
+            .close((reason as u32).into(), reason.reason_phrase());
+        self.routes.write().clear();
+    }
+
+    pub async fn wait_idle(&self) {
+        self.endpoint.wait_idle().await
+    }
+}
+
+impl<const R: usize> fmt::Debug for Router<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Router")
+            .field("peers", &self.peers())
+            .field("listen_addrs", &self.listen_addrs())
+            .finish()
+    }
+}
+
+/// The registration of a peer with a [`Router`].
+///
+/// Removed when the last clone of the peer's [`Endpoint`] is dropped, or the
+/// endpoint is closed.
+pub(super) struct Route {
+    peer_id: PeerId,
+    tx: mpsc::UnboundedSender<NewConnection>,
+    identities: tls::Identities,
+    routes: Weak<Routes>,
+}
+
+impl Route {
+    pub(super) fn remove(&self) {
+        // Ends the stream of inbound connections
+        self.tx.close_channel();
+        if let Some(routes) = self.routes.upgrade() {
+            let mut routes = routes.write();
+            // A newer registration may have replaced this one
+            if routes
+                .get(&self.peer_id)
+                .map(|tx| tx.same_receiver(&self.tx))
+                .unwrap_or(false)
+            {
+                routes.remove(&self.peer_id);
+                self.identities.remove(&self.peer_id);
+                tracing::info!(peer_id = %self.peer_id, "removed peer from shared endpoint");
+            }
+        }
+    }
+}
+
+impl Drop for Route {
+    fn drop(&mut self) {
+        self.remove()
+    }
+}
+
+async fn dispatch(incoming: quinn::Incoming, routes: Weak<Routes>) {
+    incoming
+        .for_each_concurrent(None, |connecting| {
+            let routes = routes.clone();
+            async move {
+                let conn = match connecting.await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        tracing::warn!(err = ?e, "inbound connection failed");
+                        return;
+                    },
+                };
+                let routes = match routes.upgrade() {
+                    Some(routes) => routes,
+                    None => return,
+                };
+
+                // The TLS handshake ensures the SNI names a registered peer
+                let local_peer = conn
+                    .connection
+                    .handshake_data()
+                    .and_then(|data| data.server_name)
+                    .and_then(|name| PeerId::from_str(&name).ok());
+                let route = local_peer.and_then(|peer| routes.read().get(&peer).cloned());
+                match route {
+                    Some(tx) => {
+                        if let Err(e) = tx.unbounded_send(conn) {
+                            tracing::debug!(peer = ?local_peer, "peer is gone");
+                            close(&e.into_inner());
+                        }
+                    },
+                    None => {
+                        tracing::warn!(peer = ?local_peer, "no route for inbound connection");
+                        close(&conn);
+                    },
+                }
+            }
+        })
+        .await;
+    tracing::info!("shared endpoint shut down");
+}
+
+fn close(conn: &NewConnection) {
+    let reason = CloseReason::ServerShutdown;
+    conn.connection
+        .close((reason as u32).into(), reason.reason_phrase());
+}
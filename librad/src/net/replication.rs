@@ -3,29 +3,54 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use async_lock::Semaphore;
 use link_async::{timeout, Spawner};
 use link_replication::io::UserInfo;
 use tracing::debug;
 
 use crate::{
+    collaborative_objects::{index, ObjectId, TypeName},
     git::{
         identities::local::LocalIdentity,
-        storage::{read::ReadOnlyStorage as _, Storage},
+        storage::{eviction, gc, quota::Quota, read::ReadOnlyStorage as _, Storage},
     },
     identities::git::Urn,
-    net::{connection::RemotePeer as _, quic},
+    net::{metrics::Recorder, protocol::event::upstream::Lifecycle},
+    notifications::{self, Inbox},
     paths::Paths,
     PeerId,
 };
 
+#[cfg(feature = "hooks")]
+use crate::git::hooks;
+
 pub use link_replication::FetchLimit;
 
+pub mod bundle;
+pub use bundle::Bundle;
+
 mod context;
 use context::Context;
 
+pub mod fetcher;
+pub use fetcher::Fetcher;
+
+pub mod filter;
+pub use filter::Filter;
+
+pub mod haves;
+
+pub mod inflight;
+pub use inflight::InFlight;
+
+pub mod scheduler;
+pub use scheduler::{Priority, Scheduler};
+
 pub mod error {
     use thiserror::Error;
 
@@ -43,6 +68,15 @@ pub mod error {
         #[error("timeout waiting for replication slot")]
         Timeout(#[from] link_async::Elapsed),
 
+        #[error("`{0}` is excluded from replication")]
+        Filtered(String),
+
+        #[error("no room for a new namespace")]
+        NamespaceLimit(#[from] crate::git::storage::eviction::Error),
+
+        #[error(transparent)]
+        Store(#[from] crate::git::storage::Error),
+
         #[error(transparent)]
         Replicate(#[from] link_replication::Error),
     }
@@ -50,19 +84,73 @@ pub mod error {
 
 pub type Success = link_replication::Success<context::Urn>;
 
-#[derive(Clone, Copy, Debug)]
+/// Namespaces were evicted to make room for a newly replicated one.
+#[derive(Clone, Debug)]
+pub struct Evicted {
+    pub urns: Vec<Urn>,
+    /// The namespace which was replicated.
+    pub to_make_room_for: Urn,
+}
+
+#[derive(Clone, Debug)]
 pub struct Config {
     pub limit: FetchLimit,
-    pub slots: usize,
+    /// Allocation of replication slots, see [`scheduler`].
+    ///
+    /// Clones of the config share the scheduler.
+    pub scheduler: Scheduler,
     pub wait_slot: Duration,
+    /// Maximum number of branch tips from other namespaces to advertise as
+    /// `have`s when fetching.
+    ///
+    /// All namespaces share the object database of the monorepo, so objects
+    /// are never stored twice. Without hinting the remote end about them,
+    /// however, replicating a project which shares history with one we
+    /// already have (eg. a fork) transfers that history again. Setting this to
+    /// `0` disables the hints. See [`haves`] for how the tips are chosen.
+    pub shared_haves: usize,
+    /// Cache of the branch tips of all namespaces, see [`haves`].
+    ///
+    /// Clones of the config share the cache.
+    pub haves: haves::Cache,
+    /// Storage quota to enforce when fetching.
+    ///
+    /// Fetches which would exceed the quota are aborted. Default: unlimited.
+    pub quota: Quota,
+    /// Per-URN restrictions of which refs to replicate.
+    ///
+    /// URNs without an entry replicate all signed refs. See [`Filter`].
+    pub filters: HashMap<Urn, Filter>,
+    /// Maximum number of namespaces to host.
+    ///
+    /// When a namespace we didn't have yet was replicated successfully, and
+    /// the limit is exceeded, other namespaces are evicted. If not enough of
+    /// them can be, replicating is refused before fetching anything. See
+    /// [`eviction`]. Concurrent
+    /// replications may exceed the limit by at most the number of
+    /// [`scheduler::Config::slots`].
+    /// Default: unlimited.
+    pub namespace_limit: Option<eviction::Limit>,
+    /// Bring the index of collaborative objects of a namespace up to date
+    /// after replicating it, see [`crate::collaborative_objects::index`].
+    ///
+    /// The index is also refreshed lazily when queried, so this merely
+    /// moves the cost out of the query path. Default: `false`.
+    pub index_cobs: bool,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             limit: FetchLimit::default(),
-            slots: 4,
+            scheduler: Scheduler::default(),
             wait_slot: Duration::from_secs(20),
+            shared_haves: 64,
+            haves: haves::Cache::default(),
+            quota: Quota::default(),
+            filters: HashMap::new(),
+            namespace_limit: None,
+            index_cobs: false,
         }
     }
 }
@@ -70,62 +158,221 @@ impl Default for Config {
 #[derive(Clone)]
 pub struct Replication {
     config: Config,
-    slots: Arc<Semaphore>,
+    priority: Priority,
     odb: link_replication::io::Odb,
     rdb: link_git::refs::db::Refdb,
+    on_evicted: Option<Arc<dyn Fn(Evicted) + Send + Sync>>,
+    on_lifecycle: Option<Arc<dyn Fn(Lifecycle) + Send + Sync>>,
+    #[cfg(feature = "hooks")]
+    on_data: Option<Arc<dyn Fn(hooks::Data<git_ext::Oid>) + Send + Sync>>,
+    notify: Option<Paths>,
+    inflight: InFlight,
+    metrics: Recorder,
 }
 
 impl Replication {
     pub fn new(paths: &Paths, config: Config) -> Result<Self, error::Init> {
-        let slots = Arc::new(Semaphore::new(config.slots));
         let odb = link_replication::io::Odb::open(paths.git_dir()).map_err(error::Init::Odb)?;
         let rdb = link_git::refs::db::Refdb::open(paths.git_dir())?;
 
         Ok(Self {
             config,
-            slots,
+            priority: Priority::default(),
             odb,
             rdb,
+            on_evicted: None,
+            on_lifecycle: None,
+            #[cfg(feature = "hooks")]
+            on_data: None,
+            notify: None,
+            inflight: InFlight::default(),
+            metrics: Recorder::default(),
         })
     }
 
-    pub async fn replicate<S>(
+    /// Call `f` whenever namespaces were evicted.
+    pub fn on_evicted<F>(self, f: F) -> Self
+    where
+        F: Fn(Evicted) + Send + Sync + 'static,
+    {
+        Self {
+            on_evicted: Some(Arc::new(f)),
+            ..self
+        }
+    }
+
+    /// Call `f` for each change to the local state resulting from a
+    /// successful replication run.
+    pub fn on_lifecycle<F>(self, f: F) -> Self
+    where
+        F: Fn(Lifecycle) + Send + Sync + 'static,
+    {
+        Self {
+            on_lifecycle: Some(Arc::new(f)),
+            ..self
+        }
+    }
+
+    /// Call `f` for each ref of the replicated namespace which was created,
+    /// updated, or pruned by a successful replication run.
+    #[cfg(feature = "hooks")]
+    pub fn on_data<F>(self, f: F) -> Self
+    where
+        F: Fn(hooks::Data<git_ext::Oid>) + Send + Sync + 'static,
+    {
+        Self {
+            on_data: Some(Arc::new(f)),
+            ..self
+        }
+    }
+
+    /// Add [`notifications`] about the refs changed by successful replication
+    /// runs to the [`Inbox`] found at [`Paths::inbox_file`].
+    pub fn notify(self, paths: &Paths) -> Self {
+        Self {
+            notify: Some(paths.clone()),
+            ..self
+        }
+    }
+
+    /// Record runs in progress in `inflight`, instead of a registry private
+    /// to this [`Replication`] and its clones.
+    pub fn tracking(self, inflight: InFlight) -> Self {
+        Self { inflight, ..self }
+    }
+
+    /// Schedule runs with the given `priority`. Default:
+    /// [`Priority::Interactive`].
+    pub fn prioritise(self, priority: Priority) -> Self {
+        Self { priority, ..self }
+    }
+
+    /// Report the outcomes and durations of replication runs to `metrics`.
+    pub(crate) fn metrics(self, metrics: Recorder) -> Self {
+        Self { metrics, ..self }
+    }
+
+    /// The replication runs currently in progress.
+    pub fn in_flight(&self) -> Vec<inflight::Fetch> {
+        self.inflight.fetches()
+    }
+
+    /// Replicate `urn` from the remote end of `conn`.
+    ///
+    /// `conn` is typically a [`crate::net::quic::Connection`], see [`Fetcher`].
+    pub async fn replicate<S, F>(
         &self,
         spawner: &Spawner,
         store: S,
-        conn: quic::Connection,
+        conn: F,
         urn: Urn,
         whoami: Option<LocalIdentity>,
     ) -> Result<Success, error::Replicate>
     where
         S: AsRef<Storage> + Send + 'static,
+        F: Fetcher,
+    {
+        let filter = self
+            .config
+            .filters
+            .get(&urn.clone().with_path(None))
+            .cloned();
+        self.replicate_filtered(spawner, store, conn, urn, whoami, filter)
+            .await
+    }
+
+    /// Replicate only the collaborative object `typename/object_id` of `urn`.
+    ///
+    /// The `rad/` refs are still fetched, as they are required for
+    /// verification, but none of the other refs are.
+    ///
+    /// # Errors
+    ///
+    /// If the configured [`Filter`] for `urn` excludes the object.
+    pub async fn replicate_cob<S, F>(
+        &self,
+        spawner: &Spawner,
+        store: S,
+        conn: F,
+        urn: Urn,
+        typename: &TypeName,
+        object_id: &ObjectId,
+    ) -> Result<Success, error::Replicate>
+    where
+        S: AsRef<Storage> + Send + 'static,
+        F: Fetcher,
     {
-        let slot = timeout(self.config.wait_slot, self.slots.acquire_arc()).await?;
+        let refname = format!("refs/cobs/{}/{}", typename, object_id);
+        if let Some(filter) = self.config.filters.get(&urn.clone().with_path(None)) {
+            if !filter.is_match(&refname) {
+                return Err(error::Replicate::Filtered(refname));
+            }
+        }
+        let filter = Filter::new(Some(refname)).expect("cob refname is a valid pattern");
+        self.replicate_filtered(spawner, store, conn, urn, None, Some(filter))
+            .await
+    }
+
+    async fn replicate_filtered<S, F>(
+        &self,
+        spawner: &Spawner,
+        store: S,
+        conn: F,
+        urn: Urn,
+        whoami: Option<LocalIdentity>,
+        filter: Option<Filter>,
+    ) -> Result<Success, error::Replicate>
+    where
+        S: AsRef<Storage> + Send + 'static,
+        F: Fetcher,
+    {
+        let slot = timeout(
+            self.config.wait_slot,
+            self.config.scheduler.acquire(urn.clone(), self.priority),
+        )
+        .await?;
+        let remote_peer = conn.remote_peer();
+        let inflight = self.inflight.register(urn.clone(), remote_peer);
+        let started = Instant::now();
+        let store = self.check_room(spawner, store, urn.clone()).await?;
+        let namespace_limit = self.config.namespace_limit.clone();
         let limit = self.config.limit;
+        let shared_haves = self.config.shared_haves;
+        let haves = self.config.haves.clone();
+        let quota = self.config.quota;
         let odb = self.odb.clone();
         let rdb = self.rdb.clone();
+        let replicated = urn.clone();
+        let index_cobs = self.config.index_cobs;
+        let notify = self.notify.clone();
+        #[cfg(feature = "hooks")]
+        let snapshot = self.on_data.is_some() || notify.is_some();
+        #[cfg(not(feature = "hooks"))]
+        let snapshot = notify.is_some();
         let res = spawner
             .blocking(move || {
+                let _gc = gc::fetch_lock();
                 let store = store.as_ref();
                 let have_urn = store.has_urn(&urn)?;
-                let remote_id = conn.remote_peer_id();
+                let before = if snapshot { refs_of(store, &urn) } else { None };
+                let remote_id = conn.remote_peer();
+                let index_urn = urn.clone();
                 let info = UserInfo {
                     name: store.config()?.user_name()?,
                     peer_id: *store.peer_id(),
                 };
+                let shared_haves = haves.select(store, &urn, &remote_id, shared_haves);
                 let urn = context::Urn::from(urn);
                 let refdb = link_replication::io::Refdb::new(info, odb.clone(), rdb.clone(), &urn)?;
-                let net = link_replication::io::Network::new(
-                    refdb.clone(),
-                    conn,
-                    store.path(),
-                    urn.clone(),
-                );
+                let net = conn.net(refdb.clone(), store.path(), &urn);
                 let mut cx = Context {
                     urn,
                     store,
                     refdb,
                     net,
+                    shared_haves,
+                    quota,
+                    filter,
                 };
                 let whoami = whoami.map(|id| link_replication::LocalIdentity {
                     tip: id.content_id.into(),
@@ -137,17 +384,243 @@ impl Replication {
                         .collect(),
                 });
 
-                if have_urn {
+                let success = if have_urn {
                     debug!("pull");
                     link_replication::pull(&mut cx, limit, remote_id, whoami)
                 } else {
                     debug!("clone");
                     link_replication::clone(&mut cx, limit, remote_id, whoami)
+                }?;
+                let evicted = match &namespace_limit {
+                    Some(max) if !have_urn => match eviction::make_room(store, max, &index_urn) {
+                        Ok(evicted) => evicted,
+                        Err(e) => {
+                            tracing::warn!(err = %e, urn = %index_urn, "failed to evict namespaces");
+                            vec![]
+                        },
+                    },
+                    _ => vec![],
+                };
+                if index_cobs {
+                    if let Err(e) = index::refresh(&store.collaborative_objects(None), &index_urn)
+                    {
+                        tracing::warn!(err = %e, urn = %index_urn, "failed to update cob index");
+                    }
                 }
+                if let (Some(paths), Some(before)) = (&notify, &before) {
+                    let record = || -> Result<(), notifications::Error> {
+                        let mut inbox = Inbox::load(paths)?;
+                        if !inbox
+                            .record(store, updates(&index_urn, before, &success))?
+                            .is_empty()
+                        {
+                            inbox.save()?;
+                        }
+                        Ok(())
+                    };
+                    if let Err(e) = record() {
+                        tracing::warn!(err = %e, urn = %index_urn, "failed to record notifications");
+                    }
+                }
+                Ok::<_, link_replication::Error>((success, before, evicted))
             })
             .await
             .map_err(error::Replicate::Replicate);
+        self.metrics.replicated(res.is_ok(), started.elapsed());
+        // Even a failed run may have updated some refs
+        self.config.haves.invalidate(&replicated);
+        let res = res.map(|(success, _before, evicted)| {
+            for urn in &evicted {
+                self.config.haves.invalidate(urn)
+            }
+            if let (false, Some(on_evicted)) = (evicted.is_empty(), &self.on_evicted) {
+                on_evicted(Evicted {
+                    urns: evicted,
+                    to_make_room_for: replicated.clone(),
+                })
+            }
+            #[cfg(feature = "hooks")]
+            if let (Some(before), Some(on_data)) = (_before, &self.on_data) {
+                for data in data(&replicated, &before, &success) {
+                    on_data(data)
+                }
+            }
+            success
+        });
+        if let (Ok(success), Some(on_lifecycle)) = (&res, &self.on_lifecycle) {
+            for event in lifecycle(replicated, remote_peer, success) {
+                on_lifecycle(event)
+            }
+        }
+        drop(inflight);
         drop(slot);
         res
     }
+
+    /// Check that [`Config::namespace_limit`] permits replicating `urn`.
+    ///
+    /// Nothing is evicted here: this happens only after `urn` was replicated
+    /// successfully, so a failed fetch doesn't cost us other namespaces.
+    async fn check_room<S>(
+        &self,
+        spawner: &Spawner,
+        store: S,
+        urn: Urn,
+    ) -> Result<S, error::Replicate>
+    where
+        S: AsRef<Storage> + Send + 'static,
+    {
+        let limit = match &self.config.namespace_limit {
+            None => return Ok(store),
+            Some(limit) => limit.clone(),
+        };
+        spawner
+            .blocking(move || {
+                {
+                    let store = store.as_ref();
+                    let urn = urn.with_path(None);
+                    if !store.has_urn(&urn)? {
+                        eviction::plan(store, &limit, &urn)?;
+                    }
+                }
+                Ok(store)
+            })
+            .await
+    }
+}
+
+/// The direct refs of the namespace `urn`, keyed by their full name.
+///
+/// Errors are logged, and yield `None`.
+fn refs_of(store: &Storage, urn: &Urn) -> Option<BTreeMap<String, git_ext::Oid>> {
+    let glob = format!("refs/namespaces/{}/*", urn.encode_id());
+    let collect = || -> Result<_, git2::Error> {
+        let mut refs = BTreeMap::new();
+        for r in store.as_raw().references_glob(&glob)? {
+            let r = r?;
+            if let (Some(name), Some(target)) = (r.name(), r.target()) {
+                refs.insert(name.to_owned(), target.into());
+            }
+        }
+        Ok(refs)
+    };
+    collect()
+        .map_err(|e| tracing::warn!(err = %e, %urn, "failed to read refs before replication"))
+        .ok()
+}
+
+/// The [`notifications::Update`]s of the refs of `urn` changed by a
+/// replication run, given the refs `before` it.
+///
+/// Changes to other namespaces are not reported, as their previous state is
+/// not known.
+fn updates(
+    urn: &Urn,
+    before: &BTreeMap<String, git_ext::Oid>,
+    success: &Success,
+) -> Vec<notifications::Update> {
+    use std::convert::TryFrom as _;
+
+    use link_replication::Updated;
+
+    let urn = urn.clone().with_path(None);
+    let namespace = format!("refs/namespaces/{}/", urn.encode_id());
+    success
+        .updated_refs()
+        .iter()
+        .filter_map(|up| match up {
+            Updated::Direct { name, target } => Some((name, Some(git_ext::Oid::from(*target)))),
+            Updated::Prune { name } => Some((name, None)),
+            Updated::Symbolic { .. } => None,
+        })
+        .filter_map(|(name, new)| {
+            let name = if name.as_str().starts_with("refs/namespaces/") {
+                name.to_string()
+            } else {
+                format!("{}{}", namespace, name)
+            };
+            let path = git_ext::RefLike::try_from(name.strip_prefix(&namespace)?).ok()?;
+            let old = before.get(&name).copied();
+            (old != new).then(|| notifications::Update {
+                urn: urn.clone().with_path(path),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+/// The [`hooks::Data`] for the refs of `urn` changed by a replication run,
+/// given the refs `before` it, see [`updates`].
+#[cfg(feature = "hooks")]
+fn data(
+    urn: &Urn,
+    before: &BTreeMap<String, git_ext::Oid>,
+    success: &Success,
+) -> Vec<hooks::Data<git_ext::Oid>> {
+    let zero = git_ext::Oid::from(git2::Oid::zero());
+    updates(urn, before, success)
+        .into_iter()
+        .map(|up| hooks::Data {
+            urn: up.urn,
+            old: up.old.unwrap_or(zero),
+            new: up.new.unwrap_or(zero),
+        })
+        .collect()
+}
+
+/// The [`Lifecycle`] events resulting from replicating `urn` from
+/// `remote_peer`.
+fn lifecycle(urn: Urn, remote_peer: PeerId, success: &Success) -> Vec<Lifecycle> {
+    use link_replication::Updated;
+
+    let mut events = Vec::new();
+    for tracked in success.tracked() {
+        if let either::Either::Left(peer) = tracked {
+            events.push(Lifecycle::Tracked {
+                urn: urn.clone(),
+                peer: *peer,
+            })
+        }
+    }
+    for name in success.updated_refs().iter().filter_map(|up| match up {
+        Updated::Direct { name, .. } | Updated::Symbolic { name, .. } => Some(name),
+        Updated::Prune { .. } => None,
+    }) {
+        let components = name.as_str().split('/').collect::<Vec<_>>();
+        let (namespace, rest) = match &components[..] {
+            ["refs", "namespaces", id, rest @ ..] => match Urn::try_from_id(id) {
+                Ok(ns) => (ns, rest),
+                Err(_) => continue,
+            },
+            rest => (urn.clone(), rest),
+        };
+        match rest {
+            ["refs", "rad", "id"] => events.push(Lifecycle::IdentityUpdated {
+                urn: namespace,
+                peer: None,
+            }),
+            ["refs", "remotes", peer, "rad", kind] => {
+                let peer = match peer.parse::<PeerId>() {
+                    Ok(peer) => peer,
+                    Err(_) => continue,
+                };
+                match *kind {
+                    "id" => events.push(Lifecycle::IdentityUpdated {
+                        urn: namespace,
+                        peer: Some(peer),
+                    }),
+                    "signed_refs" => events.push(Lifecycle::SignedRefsUpdated {
+                        urn: namespace,
+                        peer,
+                    }),
+                    _ => {},
+                }
+            },
+            _ => {},
+        }
+    }
+    events.push(Lifecycle::Replicated { urn, remote_peer });
+
+    events
 }
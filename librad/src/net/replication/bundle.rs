@@ -0,0 +1,239 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Importing bundles created by [`crate::git::storage::bundle::create`].
+//!
+//! A [`Bundle`] is a [`Fetcher`] standing in for the peer which created it:
+//! `ls-refs` is answered from the refs recorded in the bundle, and fetching
+//! indexes the packfile it contains. Importing a bundle is thus subject to the
+//! same verification of identities and signed refs as fetching from its
+//! creator over the network:
+//!
+//! ```ignore
+//! let bundle = Bundle::open(path)?;
+//! let urn = bundle.header().urn.clone();
+//! replication.replicate(&spawner, storage, bundle, urn, whoami).await?;
+//! ```
+
+use std::{
+    fs,
+    io::{self, Read as _, Seek as _, SeekFrom},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use bstr::BString;
+use link_git::protocol::Ref;
+use link_replication::{io as rio, LsRefs, Net, ObjectId, Odb as _};
+use radicle_data::NonEmptyVec;
+
+use super::{fetcher::Metered, Fetcher};
+use crate::{
+    git::storage::bundle::{self, Header},
+    identities::git::Urn,
+    PeerId,
+};
+
+/// A bundle to replicate from, see the [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct Bundle {
+    path: PathBuf,
+    header: Header,
+}
+
+impl Bundle {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, bundle::Error> {
+        let path = path.into();
+        let header = Header::read(&path)?;
+        Ok(Self { path, header })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+}
+
+impl Fetcher for Bundle {
+    type Net = Unbundle;
+
+    fn remote_peer(&self) -> PeerId {
+        self.header.author
+    }
+
+    fn net(self, refdb: rio::Refdb<rio::Odb>, git_dir: &Path, urn: &Urn) -> Self::Net {
+        Unbundle {
+            bundle: self,
+            refdb,
+            git_dir: git_dir.to_path_buf(),
+            urn: urn.clone().with_path(None),
+            received: AtomicU64::new(0),
+            received_objects: AtomicU64::new(0),
+        }
+    }
+}
+
+/// The [`Net`] effects of replicating from a [`Bundle`].
+pub struct Unbundle {
+    bundle: Bundle,
+    refdb: rio::Refdb<rio::Odb>,
+    git_dir: PathBuf,
+    urn: Urn,
+    received: AtomicU64,
+    received_objects: AtomicU64,
+}
+
+impl Unbundle {
+    fn ensure_prerequisites(&self) -> io::Result<()> {
+        let repo = git2::Repository::open_bare(&self.git_dir).map_err(io_other)?;
+        let odb = repo.odb().map_err(io_other)?;
+        for oid in &self.bundle.header.prerequisites {
+            if !odb.exists(**oid) {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "bundle requires {}, which is missing: import the previous bundle first",
+                        oid
+                    ),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Index the packfile of the bundle, returning the path of the index.
+    fn index_pack(&self, max_pack_bytes: u64) -> io::Result<(PathBuf, u64)> {
+        let mut file = fs::File::open(&self.bundle.path)?;
+        let size = file
+            .metadata()?
+            .len()
+            .saturating_sub(self.bundle.header.pack_offset);
+        if size > max_pack_bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("packfile exceeds the limit of {} bytes", max_pack_bytes),
+            ));
+        }
+        file.seek(SeekFrom::Start(self.bundle.header.pack_offset))?;
+
+        let mut child = Command::new("git")
+            .arg("--git-dir")
+            .arg(&self.git_dir)
+            .args(&["index-pack", "--stdin", "--fix-thin"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        io::copy(
+            &mut file.take(size),
+            child.stdin.as_mut().expect("stdin is piped"),
+        )?;
+        drop(child.stdin.take());
+        let out = child.wait_with_output()?;
+        if !out.status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "`git index-pack` exited with {}: {}",
+                    out.status,
+                    String::from_utf8_lossy(&out.stderr)
+                ),
+            ));
+        }
+        // Prints `pack\t<hash>` or `keep\t<hash>`
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        let hash = stdout.trim().split('\t').nth(1).ok_or_else(|| {
+            io_other(format!("unexpected output of `git index-pack`: {}", stdout))
+        })?;
+
+        Ok((
+            self.git_dir
+                .join("objects")
+                .join("pack")
+                .join(format!("pack-{}.idx", hash)),
+            size,
+        ))
+    }
+}
+
+#[async_trait(?Send)]
+impl Net for Unbundle {
+    type Error = io::Error;
+
+    async fn run_ls_refs(&self, ls: LsRefs) -> Result<Vec<Ref>, Self::Error> {
+        if self.bundle.header.urn.id != self.urn.id {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("bundle does not contain {}", self.urn),
+            ));
+        }
+        let prefixes = match ls {
+            LsRefs::Full => vec![],
+            LsRefs::Prefix { prefixes } => prefixes.into_iter().map(BString::from).collect(),
+        };
+
+        Ok(self
+            .bundle
+            .header
+            .refs
+            .iter()
+            .filter(|(name, _)| {
+                prefixes.is_empty() || prefixes.iter().any(|p| name.as_bytes().starts_with(p))
+            })
+            .map(|(name, oid)| Ref::Direct {
+                path: BString::from(name.as_str()),
+                object: ObjectId::from(*oid),
+            })
+            .collect())
+    }
+
+    async fn run_fetch(
+        &self,
+        max_pack_bytes: u64,
+        wants: NonEmptyVec<ObjectId>,
+        _: Vec<ObjectId>,
+    ) -> Result<(), Self::Error> {
+        self.ensure_prerequisites()?;
+        let (idx, size) = self.index_pack(max_pack_bytes)?;
+        self.refdb.add_pack(&idx).map_err(io_other)?;
+        self.received.fetch_add(size, Ordering::Relaxed);
+        {
+            use link_git::odb::index::IndexFile;
+
+            let objects = IndexFile::at(&idx).map_err(io_other)?.num_objects();
+            self.received_objects
+                .fetch_add(u64::from(objects), Ordering::Relaxed);
+        }
+
+        for oid in wants.iter() {
+            if !self.refdb.contains(oid) {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("wanted {} not found in bundle", oid),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Metered for Unbundle {
+    fn received_bytes(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    fn received_objects(&self) -> u64 {
+        self.received_objects.load(Ordering::Relaxed)
+    }
+}
+
+fn io_other<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::Other, e)
+}
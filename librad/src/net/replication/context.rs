@@ -5,7 +5,7 @@
 
 use std::{
     borrow::Cow,
-    collections::{BTreeSet, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     convert::TryFrom,
     ops::Deref,
     path::Path,
@@ -31,20 +31,31 @@ use link_replication::{
     Net,
     ObjectId,
     Odb,
+    Policy,
     RefScan,
     Refdb,
     SignedRefs,
     Sigrefs,
+    SymrefTarget,
     Tracking,
     Update,
+    Updated,
     VerifiedIdentity,
 };
 use multihash::Multihash;
 use radicle_data::NonEmptyVec;
-use std_ext::Void;
+use std_ext::{time::unix_now, Void};
 
 use crate::{
-    git::{self, storage::Storage, tracking},
+    git::{
+        self,
+        storage::{
+            quota::{self, Quota},
+            transaction::{self, Previous, Transaction},
+            Storage,
+        },
+        tracking,
+    },
     identities::{
         self,
         git::{
@@ -57,7 +68,7 @@ use crate::{
             VerifiedProject,
         },
     },
-    net::{self, quic, upgrade},
+    net::{self, quic, tcp, upgrade},
     PeerId,
 };
 
@@ -94,6 +105,9 @@ pub mod error {
 
         #[error(transparent)]
         Refs(#[from] git::refs::stored::Error),
+
+        #[error(transparent)]
+        Tracking(#[from] tracking::error::Get),
     }
 
     #[derive(Debug, Error)]
@@ -104,6 +118,56 @@ pub mod error {
 
         #[error(transparent)]
         Quic(#[from] quic::Error),
+
+        #[error(transparent)]
+        TcpUpgrade(#[from] upgrade::Error<tcp::BidiStream>),
+
+        #[error(transparent)]
+        Tcp(#[from] tcp::Error),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Tx {
+        #[error("non-fast-forward update of {name} (current: {cur}, new: {new})")]
+        NonFF {
+            name: String,
+            new: ObjectId,
+            cur: ObjectId,
+        },
+
+        #[error("rejected type change of {0}")]
+        TypeChange(String),
+
+        #[error("symref target {0} is itself a symref")]
+        TargetSymbolic(String),
+
+        #[error("error determining if {old} is an ancestor of {new} within {name}")]
+        Ancestry {
+            name: String,
+            new: ObjectId,
+            old: ObjectId,
+            #[source]
+            source: <io::Odb as Odb>::RevwalkError,
+        },
+
+        #[error(transparent)]
+        Transaction(#[from] transaction::Error),
+
+        #[error(transparent)]
+        Reload(#[from] <io::Refdb<io::Odb> as Refdb>::ReloadError),
+
+        #[error(transparent)]
+        Git(#[from] git2::Error),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum AddPack {
+        #[error(transparent)]
+        Odb(#[from] <io::Odb as Odb>::AddPackError),
+
+        #[cfg(feature = "fault-injection")]
+        #[error(transparent)]
+        Fault(#[from] git::storage::faults::Fault),
     }
 
     #[derive(Debug, Error)]
@@ -115,19 +179,66 @@ pub mod error {
     }
 }
 
-type Network = io::Network<Urn, io::Refdb<io::Odb>, io::Odb, quic::Connection>;
+pub(super) type Network = io::Network<Urn, io::Refdb<io::Odb>, io::Odb, quic::Connection>;
+pub(super) type TcpNetwork = io::Network<Urn, io::Refdb<io::Odb>, io::Odb, tcp::Connection>;
 
 /// Context for a replication v3 run.
 ///
 /// Implements the (effect) traits required by the `link-replication` crate.
-pub struct Context<'a> {
+/// The network effects are delegated to `N`, cf. [`super::Fetcher`].
+pub struct Context<'a, N = Network> {
     pub(super) urn: Urn,
     pub(super) store: &'a Storage,
     pub(super) refdb: io::Refdb<io::Odb>,
-    pub(super) net: Network,
+    pub(super) net: N,
+    /// Tips from other namespaces, cf. [`super::haves`].
+    pub(super) shared_haves: Vec<ObjectId>,
+    pub(super) quota: Quota,
+    /// Restricts the signed refs to fetch, cf. [`super::Filter`].
+    pub(super) filter: Option<super::Filter>,
 }
 
-impl<'a> Context<'a> {
+impl<'a, N> Context<'a, N> {
+    fn is_wanted(&self, refname: &RefString) -> bool {
+        self.filter
+            .as_ref()
+            .map(|filter| filter.is_match(refname.as_str()))
+            .unwrap_or(true)
+    }
+
+    /// Convert the [`git::refs::Loaded`] signed refs of `of` into [`Sigrefs`],
+    /// retaining only the refs permitted by the [`Self::filter`] and the
+    /// tracking config of `of`.
+    fn sigrefs(
+        &self,
+        of: &PeerId,
+        git::refs::Loaded { at, refs: sigrefs }: git::refs::Loaded,
+        cutoff: usize,
+    ) -> Result<Sigrefs<git_ext::Oid>, error::Sigrefs> {
+        let config = tracking::get(self.store, &self.urn, Some(*of))?;
+        let refs = sigrefs
+            .iter_categorised()
+            .map(|((name, oid), cat)| {
+                // TODO: make `Refs` use `git_ref_format`
+                let refname = RefString::try_from(format!("refs/{}/{}", cat, name))
+                    .expect("`Refs::iter_categorised` yields valid refnames");
+                (refname, *oid)
+            })
+            .filter(|(refname, _)| self.is_wanted(refname))
+            .filter(|(refname, _)| {
+                config
+                    .as_ref()
+                    .map(|tracked| is_permitted(tracked.config(), refname))
+                    .unwrap_or(true)
+            })
+            .collect::<HashMap<_, _>>();
+        let mut remotes = sigrefs.remotes;
+        remotes.cutoff_mut(cutoff);
+        let remotes = remotes.flatten().copied().collect();
+
+        Ok(Sigrefs { at, refs, remotes })
+    }
+
     fn verify<F, T>(
         &self,
         id: SomeIdentity,
@@ -296,7 +407,7 @@ impl<'a> From<&'a Urn> for git_ref_format::Component<'_> {
     }
 }
 
-impl Identities for Context<'_> {
+impl<N> Identities for Context<'_, N> {
     type Urn = Urn;
     type Oid = git_ext::Oid;
 
@@ -360,30 +471,14 @@ impl Identities for Context<'_> {
     }
 }
 
-impl SignedRefs for Context<'_> {
+impl<N> SignedRefs for Context<'_, N> {
     type Oid = git_ext::Oid;
     type Error = error::Sigrefs;
 
     fn load(&self, of: &PeerId, cutoff: usize) -> Result<Option<Sigrefs<Self::Oid>>, Self::Error> {
-        match git::refs::load(&self.store, &self.urn, Some(of))? {
-            None => Ok(None),
-            Some(git::refs::Loaded { at, refs: signed }) => {
-                let refs = signed
-                    .iter_categorised()
-                    .map(|((name, oid), cat)| {
-                        // TODO: make `Refs` use `git_ref_format`
-                        let refname = RefString::try_from(format!("refs/{}/{}", cat, name))
-                            .expect("`Refs::iter_categorised` yields valid refnames");
-                        (refname, *oid)
-                    })
-                    .collect::<HashMap<_, _>>();
-                let mut remotes = git::refs::Refs::from(signed).remotes;
-                remotes.cutoff_mut(cutoff);
-                let remotes = remotes.flatten().copied().collect();
-
-                Ok(Some(Sigrefs { at, refs, remotes }))
-            },
-        }
+        git::refs::load(&self.store, &self.urn, Some(of))?
+            .map(|loaded| self.sigrefs(of, loaded, cutoff))
+            .transpose()
     }
 
     fn load_at(
@@ -392,25 +487,9 @@ impl SignedRefs for Context<'_> {
         signed_by: &PeerId,
         cutoff: usize,
     ) -> Result<Option<Sigrefs<Self::Oid>>, Self::Error> {
-        match git::refs::load_at(&self.store, treeish.into().into(), Some(signed_by))? {
-            None => Ok(None),
-            Some(git::refs::Loaded { at, refs: signed }) => {
-                let refs = signed
-                    .iter_categorised()
-                    .map(|((name, oid), cat)| {
-                        // TODO: make `Refs` use `git_ref_format`
-                        let refname = RefString::try_from(format!("refs/{}/{}", cat, name))
-                            .expect("`Refs::iter_categorised` yields valid refnames");
-                        (refname, *oid)
-                    })
-                    .collect::<HashMap<_, _>>();
-                let mut remotes = git::refs::Refs::from(signed).remotes;
-                remotes.cutoff_mut(cutoff);
-                let remotes = remotes.flatten().copied().collect();
-
-                Ok(Some(Sigrefs { at, refs, remotes }))
-            },
-        }
+        git::refs::load_at(&self.store, treeish.into().into(), Some(signed_by))?
+            .map(|loaded| self.sigrefs(signed_by, loaded, cutoff))
+            .transpose()
     }
 
     fn update(&self) -> Result<Option<Self::Oid>, Self::Error> {
@@ -440,9 +519,35 @@ impl SignedRefs for Context<'_> {
     }
 }
 
-pub struct Tracked<'a>(
-    tracking::TrackedEntries<'a, Storage, <Storage as tracking::git::refdb::Read<'a>>::References>,
-);
+/// Whether the tracking `config` permits replicating `refname`.
+///
+/// Only data refs and collaborative objects are subject to the config, other
+/// categories (notably `rad`) are always replicated.
+fn is_permitted(config: &tracking::Config, refname: &RefString) -> bool {
+    use git_ref_format::{name, Qualified};
+    use tracking::{config::cobs::Policy, git::config::DATA_REFS};
+
+    let qualified = match Qualified::from_refstr(refname) {
+        Some(qualified) => qualified,
+        None => return true,
+    };
+    let (_refs, cat, _, _) = qualified.non_empty_components();
+    let cat: &git_ref_format::RefStr = cat.as_ref();
+    let subject = name::COBS == cat || DATA_REFS.iter().any(|data| data.as_ref() == cat);
+
+    !subject || config.policy_for(&qualified) == Policy::Allow
+}
+
+/// The tracked peers, excluding those whose tracking entry expired as of
+/// `now` (in seconds since the Unix epoch).
+pub struct Tracked<'a> {
+    entries: tracking::TrackedEntries<
+        'a,
+        Storage,
+        <Storage as tracking::git::refdb::Read<'a>>::References,
+    >,
+    now: u64,
+}
 
 impl<'a> Iterator for Tracked<'a> {
     type Item = Result<(PeerId, link_replication::DataPolicy), tracking::error::Tracked>;
@@ -451,8 +556,11 @@ impl<'a> Iterator for Tracked<'a> {
         use link_replication::DataPolicy::*;
 
         loop {
-            match self.0.next()? {
+            match self.entries.next()? {
                 Ok(tracking::Tracked::Default { .. }) => continue,
+                Ok(tracking::Tracked::Peer { config, .. }) if config.is_expired(self.now) => {
+                    continue
+                },
                 Ok(tracking::Tracked::Peer { peer, config, .. }) => {
                     break Some(Ok((peer, if config.data { Allow } else { Deny })))
                 },
@@ -463,7 +571,7 @@ impl<'a> Iterator for Tracked<'a> {
 }
 
 #[allow(clippy::type_complexity)]
-impl<'a> Tracking for Context<'a> {
+impl<'a, N> Tracking for Context<'a, N> {
     type Urn = Urn;
 
     type Tracked = Tracked<'a>;
@@ -491,10 +599,14 @@ impl<'a> Tracking for Context<'a> {
         static CONFIG_FULL: Lazy<tracking::Config> = Lazy::new(|| tracking::Config {
             data: true,
             cobs: tracking::config::Cobs::allow_all(),
+            refs: None,
+            expires: None,
         });
         static CONFIG_MIN: Lazy<tracking::Config> = Lazy::new(|| tracking::Config {
             data: false,
             cobs: tracking::config::Cobs::deny_all(),
+            refs: None,
+            expires: None,
         });
 
         let iter = iter.into_iter();
@@ -543,15 +655,16 @@ impl<'a> Tracking for Context<'a> {
     }
 
     fn tracked(&self) -> Result<Self::Tracked, Self::TrackedError> {
-        tracking::tracked(self.store, Some(&self.urn)).map(Tracked)
+        let now = unix_now();
+        tracking::tracked(self.store, Some(&self.urn)).map(|entries| Tracked { entries, now })
     }
 }
 
-impl<'c> Refdb for Context<'c> {
+impl<'c, N> Refdb for Context<'c, N> {
     type Oid = <io::Refdb<io::Odb> as Refdb>::Oid;
 
     type FindError = <io::Refdb<io::Odb> as Refdb>::FindError;
-    type TxError = <io::Refdb<io::Odb> as Refdb>::TxError;
+    type TxError = error::Tx;
     type ReloadError = <io::Refdb<io::Odb> as Refdb>::ReloadError;
 
     fn refname_to_id<'a, Q>(&self, refname: Q) -> Result<Option<Self::Oid>, Self::FindError>
@@ -561,11 +674,33 @@ impl<'c> Refdb for Context<'c> {
         self.refdb.refname_to_id(refname)
     }
 
+    /// Apply `updates` in a single storage [`Transaction`].
+    ///
+    /// Either all `updates` which are not rejected are applied, or none is, see
+    /// [`crate::git::storage::transaction`].
     fn update<'a, I>(&mut self, updates: I) -> Result<Applied<'a>, Self::TxError>
     where
         I: IntoIterator<Item = Update<'a>>,
     {
-        self.refdb.update(updates)
+        let mut tx = self.store.transaction();
+        let mut rejected = Vec::new();
+        // Later edits of the same ref override earlier ones
+        let mut updated = BTreeMap::new();
+        for up in updates {
+            match self.stage(&mut tx, up)? {
+                Left(rej) => rejected.push(rej),
+                Right(ups) => updated.extend(ups.into_iter().map(|up| (refname(&up).clone(), up))),
+            }
+        }
+        if !tx.is_empty() {
+            tx.commit()?;
+            self.refdb.reload()?;
+        }
+
+        Ok(Applied {
+            rejected,
+            updated: updated.into_values().collect(),
+        })
     }
 
     fn reload(&mut self) -> Result<(), Self::ReloadError> {
@@ -573,7 +708,174 @@ impl<'c> Refdb for Context<'c> {
     }
 }
 
-impl<'a> RefScan for &'a Context<'_> {
+impl<N> Context<'_, N> {
+    fn namespaced(&self, name: &refs::Qualified) -> String {
+        format!("refs/namespaces/{}/{}", self.urn.encode_id(), name)
+    }
+
+    /// Add the edits of `update` to `tx`, or return `update` if it is
+    /// rejected.
+    ///
+    /// Mirrors the semantics of [`io::Refdb`].
+    fn stage<'a>(
+        &self,
+        tx: &mut Transaction<'_>,
+        update: Update<'a>,
+    ) -> Result<Either<Update<'a>, Vec<Updated>>, error::Tx> {
+        let repo = self.store.as_raw();
+        match update {
+            Update::Direct {
+                name,
+                target,
+                no_ff,
+            } => {
+                let name_ns = self.namespaced(&name);
+                let (previous, msg) = match peeled(repo, &name_ns)? {
+                    None => (Previous::Absent, "replicate: create"),
+                    Some(cur) => {
+                        let is_ff = self.is_ff(&name_ns, target, cur)?;
+                        match no_ff {
+                            _ if is_ff => (Previous::Is(cur.into()), "replicate: fast-forward"),
+                            Policy::Abort => {
+                                return Err(error::Tx::NonFF {
+                                    name: name_ns,
+                                    new: target,
+                                    cur,
+                                })
+                            },
+                            Policy::Reject => {
+                                return Ok(Left(Update::Direct {
+                                    name,
+                                    target,
+                                    no_ff,
+                                }))
+                            },
+                            Policy::Allow => (Previous::Is(cur.into()), "replicate: forced update"),
+                        }
+                    },
+                };
+                tx.update(&name_ns, git_ext::Oid::from(target), previous, msg);
+
+                Ok(Right(vec![Updated::Direct {
+                    name: refstring(name_ns),
+                    target,
+                }]))
+            },
+
+            Update::Symbolic {
+                name,
+                target,
+                type_change,
+            } => {
+                let name_ns = self.namespaced(&name);
+                let is_direct = match repo.find_reference(&name_ns) {
+                    Ok(r) => r.kind() == Some(git2::ReferenceType::Direct),
+                    Err(e) if git_ext::is_not_found_err(&e) => false,
+                    Err(e) => return Err(e.into()),
+                };
+                match type_change {
+                    Policy::Abort if is_direct => return Err(error::Tx::TypeChange(name_ns)),
+                    Policy::Reject if is_direct => {
+                        return Ok(Left(Update::Symbolic {
+                            name,
+                            target,
+                            type_change,
+                        }))
+                    },
+                    _ => {},
+                }
+
+                let SymrefTarget {
+                    name: dst_name,
+                    target,
+                } = target;
+                let dst_name = dst_name.to_string();
+                let mut updated = Vec::with_capacity(2);
+                let dst = match repo.find_reference(&dst_name) {
+                    Ok(r) => match r.target() {
+                        None => return Err(error::Tx::TargetSymbolic(dst_name)),
+                        Some(dst) => Some(ObjectId::from(git_ext::Oid::from(dst))),
+                    },
+                    Err(e) if git_ext::is_not_found_err(&e) => None,
+                    Err(e) => return Err(e.into()),
+                };
+                let previous = match dst {
+                    None => Some((Previous::Absent, "replicate: implicit symref target")),
+                    Some(dst) if dst != target && self.is_ff(&dst_name, target, dst)? => Some((
+                        Previous::Is(dst.into()),
+                        "replicate: fast-forward symref target",
+                    )),
+                    Some(_) => None,
+                };
+                if let Some((previous, msg)) = previous {
+                    tx.update(&dst_name, git_ext::Oid::from(target), previous, msg);
+                    updated.push(Updated::Direct {
+                        name: refstring(dst_name.clone()),
+                        target,
+                    });
+                }
+                tx.symbolic(&name_ns, &dst_name, "replicate: symbolic ref");
+                updated.push(Updated::Symbolic {
+                    name: refstring(name_ns),
+                    target: refstring(dst_name),
+                });
+
+                Ok(Right(updated))
+            },
+
+            Update::Prune { name, prev } => {
+                let name_ns = self.namespaced(&name);
+                let previous = match prev {
+                    Left(oid) => Previous::Is(oid.into()),
+                    // Symbolic refs are not checked
+                    Right(_) => Previous::Any,
+                };
+                tx.delete(&name_ns, previous);
+
+                Ok(Right(vec![Updated::Prune {
+                    name: refstring(name_ns),
+                }]))
+            },
+        }
+    }
+
+    fn is_ff(&self, name: &str, new: ObjectId, old: ObjectId) -> Result<bool, error::Tx> {
+        if new == old {
+            return Ok(true);
+        }
+        self.refdb
+            .is_in_ancestry_path(new, old)
+            .map_err(|source| error::Tx::Ancestry {
+                name: name.to_owned(),
+                new,
+                old,
+                source,
+            })
+    }
+}
+
+/// The oid `name` resolves to, if it exists.
+fn peeled(repo: &git2::Repository, name: &str) -> Result<Option<ObjectId>, git2::Error> {
+    match repo.refname_to_id(name) {
+        Ok(oid) => Ok(Some(git_ext::Oid::from(oid).into())),
+        Err(e) if git_ext::is_not_found_err(&e) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn refstring(name: String) -> RefString {
+    RefString::try_from(name).expect("namespaced `Qualified` is a valid refname")
+}
+
+fn refname(up: &Updated) -> &RefString {
+    match up {
+        Updated::Direct { name, .. } | Updated::Symbolic { name, .. } | Updated::Prune { name } => {
+            name
+        },
+    }
+}
+
+impl<'a, N> RefScan for &'a Context<'_, N> {
     type Oid = <&'a io::Refdb<io::Odb> as RefScan>::Oid;
     type Scan = <&'a io::Refdb<io::Odb> as RefScan>::Scan;
     type Error = <&'a io::Refdb<io::Odb> as RefScan>::Error;
@@ -587,10 +889,10 @@ impl<'a> RefScan for &'a Context<'_> {
     }
 }
 
-impl Odb for Context<'_> {
+impl<N> Odb for Context<'_, N> {
     type LookupError = <io::Odb as Odb>::LookupError;
     type RevwalkError = <io::Odb as Odb>::RevwalkError;
-    type AddPackError = <io::Odb as Odb>::AddPackError;
+    type AddPackError = error::AddPack;
 
     fn contains(&self, oid: impl AsRef<oid>) -> bool {
         self.refdb.contains(oid)
@@ -613,13 +915,18 @@ impl Odb for Context<'_> {
     }
 
     fn add_pack(&self, path: impl AsRef<Path>) -> Result<(), Self::AddPackError> {
-        self.refdb.add_pack(path)
+        #[cfg(feature = "fault-injection")]
+        self.store.inject(git::storage::faults::Op::ObjectWrite)?;
+        self.refdb.add_pack(path).map_err(error::AddPack::from)
     }
 }
 
 #[async_trait(?Send)]
-impl Net for Context<'_> {
-    type Error = <Network as Net>::Error;
+impl<N> Net for Context<'_, N>
+where
+    N: super::fetcher::Metered,
+{
+    type Error = std::io::Error;
 
     async fn run_ls_refs(&self, ls: LsRefs) -> Result<Vec<Ref>, Self::Error> {
         self.net.run_ls_refs(ls).await
@@ -629,9 +936,46 @@ impl Net for Context<'_> {
         &self,
         max_pack_bytes: u64,
         wants: NonEmptyVec<ObjectId>,
-        haves: Vec<ObjectId>,
+        mut haves: Vec<ObjectId>,
     ) -> Result<(), Self::Error> {
-        self.net.run_fetch(max_pack_bytes, wants, haves).await
+        let wanted = wants.iter().collect::<BTreeSet<_>>();
+        let shared = self
+            .shared_haves
+            .iter()
+            .filter(|oid| !wanted.contains(oid) && !haves.contains(oid))
+            // The cached tips may have been pruned in the meantime
+            .filter(|oid| self.refdb.contains(oid))
+            .copied()
+            .collect::<Vec<_>>();
+        haves.extend(shared);
+
+        let urn = &self.urn.0;
+        let remaining = quota::remaining(self.store, urn, self.quota)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        let max_pack_bytes = match remaining {
+            Some(0) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!("storage quota exceeded for {}", urn),
+                ))
+            },
+            Some(remaining) => max_pack_bytes.min(remaining),
+            None => max_pack_bytes,
+        };
+
+        #[cfg(feature = "fault-injection")]
+        self.store
+            .inject(git::storage::faults::Op::ObjectWrite)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+
+        let before = self.net.received_bytes();
+        let res = self.net.run_fetch(max_pack_bytes, wants, haves).await;
+        let received = self.net.received_bytes().saturating_sub(before);
+        if let Err(e) = quota::record(self.store, urn, received) {
+            tracing::warn!(err = %e, "failed to record storage usage");
+        }
+
+        res
     }
 }
 
@@ -650,7 +994,22 @@ impl io::Connection for quic::Connection {
     }
 }
 
-impl LocalPeer for Context<'_> {
+#[async_trait]
+impl io::Connection for tcp::Connection {
+    type Read = <tcp::BidiStream as net::connection::Duplex>::Read;
+    type Write = <tcp::BidiStream as net::connection::Duplex>::Write;
+    type Error = error::Connection;
+
+    async fn open_stream(&self) -> Result<(Self::Read, Self::Write), Self::Error> {
+        use net::connection::Duplex as _;
+
+        let bi = self.open_bidi().await?;
+        let up = upgrade::upgrade(bi, upgrade::Git).await?;
+        Ok(up.into_stream().split())
+    }
+}
+
+impl<N> LocalPeer for Context<'_, N> {
     fn id(&self) -> &PeerId {
         self.store.peer_id()
     }
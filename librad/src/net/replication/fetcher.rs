@@ -0,0 +1,91 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::path::Path;
+
+use link_replication::{io, Net};
+
+use super::context;
+use crate::{
+    identities::git::Urn,
+    net::{connection::RemotePeer as _, quic, tcp},
+    PeerId,
+};
+
+/// The remote end of a replication run.
+///
+/// A `Fetcher` is a factory for the [`Net`] effects of a single run. It is
+/// implemented by [`quic::Connection`] and [`tcp::Connection`], which fetch
+/// from the remote peer via the git protocol. Tests and alternative transports
+/// may supply their own implementation, eg. one simulating the state of a
+/// remote peer, to exercise the replication logic without a network or git
+/// remotes.
+pub trait Fetcher: Send + 'static {
+    type Net: Metered;
+
+    /// The peer being fetched from.
+    fn remote_peer(&self) -> PeerId;
+
+    /// Create the [`Net`] for fetching `urn` into the monorepo at `git_dir`.
+    ///
+    /// Fetched packs are to be added to `refdb`, which updates its view of
+    /// the object database.
+    fn net(self, refdb: io::Refdb<io::Odb>, git_dir: &Path, urn: &Urn) -> Self::Net;
+}
+
+/// [`Net`] effects which keep track of the amount of data received.
+///
+/// The count is used to enforce the storage
+/// [`crate::git::storage::quota::Quota`].
+pub trait Metered: Net<Error = std::io::Error> {
+    fn received_bytes(&self) -> u64;
+
+    /// The number of objects received, for progress reporting.
+    fn received_objects(&self) -> u64;
+}
+
+impl Fetcher for quic::Connection {
+    type Net = context::Network;
+
+    fn remote_peer(&self) -> PeerId {
+        self.remote_peer_id()
+    }
+
+    fn net(self, refdb: io::Refdb<io::Odb>, git_dir: &Path, urn: &Urn) -> Self::Net {
+        io::Network::new(refdb, self, git_dir, context::Urn::from(urn.clone()))
+    }
+}
+
+impl Fetcher for tcp::Connection {
+    type Net = context::TcpNetwork;
+
+    fn remote_peer(&self) -> PeerId {
+        self.remote_peer_id()
+    }
+
+    fn net(self, refdb: io::Refdb<io::Odb>, git_dir: &Path, urn: &Urn) -> Self::Net {
+        io::Network::new(refdb, self, git_dir, context::Urn::from(urn.clone()))
+    }
+}
+
+impl Metered for context::Network {
+    fn received_bytes(&self) -> u64 {
+        io::Network::received_bytes(self)
+    }
+
+    fn received_objects(&self) -> u64 {
+        io::Network::received_objects(self)
+    }
+}
+
+impl Metered for context::TcpNetwork {
+    fn received_bytes(&self) -> u64 {
+        io::Network::received_bytes(self)
+    }
+
+    fn received_objects(&self) -> u64 {
+        io::Network::received_objects(self)
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Selective replication of refs.
+//!
+//! A [`Filter`] restricts which of the signed refs of a peer are fetched. Refs
+//! not matching any of its patterns are treated as if they weren't signed:
+//! they are never requested, and any remote tracking branches previously
+//! fetched for them are pruned.
+//!
+//! Patterns are globs over the fully qualified ref name, eg.
+//! `refs/heads/main` or `refs/heads/release/*`. `*` does not match across `/`,
+//! use `**` for that. `rad/` refs are not subject to filtering, as they are
+//! required for verification.
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    #[non_exhaustive]
+    pub enum Filter {
+        #[error("invalid pattern `{pattern}`")]
+        Pattern {
+            pattern: String,
+            #[source]
+            source: globset::Error,
+        },
+
+        #[error(transparent)]
+        Build(#[from] globset::Error),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Filter {
+    patterns: Vec<String>,
+    set: GlobSet,
+}
+
+impl Filter {
+    pub fn new<I, S>(patterns: I) -> Result<Self, error::Filter>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut builder = GlobSetBuilder::new();
+        let mut pats = Vec::new();
+        for pattern in patterns {
+            let pattern = pattern.into();
+            builder.add(glob(&pattern).map_err(|source| error::Filter::Pattern {
+                pattern: pattern.clone(),
+                source,
+            })?);
+            pats.push(pattern);
+        }
+
+        Ok(Self {
+            patterns: pats,
+            set: builder.build()?,
+        })
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// `true` if the fully qualified `refname` should be replicated.
+    pub fn is_match(&self, refname: &str) -> bool {
+        self.set.is_match(refname)
+    }
+}
+
+fn glob(pattern: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(pattern).literal_separator(true).build()
+}
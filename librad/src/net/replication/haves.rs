@@ -0,0 +1,196 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Tips of other namespaces to advertise as `have`s when fetching.
+//!
+//! All namespaces share the object database of the monorepo, so objects are
+//! never stored twice. Without hinting the remote end about them, however,
+//! replicating a project which shares history with one we already have (eg. a
+//! fork) transfers that history again.
+//!
+//! Which namespaces share history with the one being replicated is not known
+//! before fetching, so [`Cache::select`] guesses: namespaces are ranked by the
+//! number of peers they have in common with it -- forks tend to be maintained
+//! by, and replicated from, some of the same peers -- and more recent tips are
+//! preferred within a rank.
+//!
+//! Listing the branches of all namespaces is expensive on a large seed, so
+//! this is done only once per [`Cache`]. Afterwards, only the namespaces which
+//! were [invalidated](Cache::invalidate), eg. because they were replicated to,
+//! are read again.
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    mem,
+    sync::Arc,
+};
+
+use link_replication::ObjectId;
+use parking_lot::Mutex;
+
+use crate::{git::storage::Storage, identities::git::Urn, PeerId};
+
+/// The branch tips of all namespaces, see the [module documentation](self).
+///
+/// Clones share the cache.
+#[derive(Clone, Default)]
+pub struct Cache {
+    inner: Arc<Mutex<State>>,
+}
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.inner.lock();
+        f.debug_struct("Cache")
+            .field("loaded", &state.loaded)
+            .field("namespaces", &state.namespaces.len())
+            .field("stale", &state.stale.len())
+            .finish()
+    }
+}
+
+impl Cache {
+    /// Mark the tips of `urn` as out of date.
+    pub fn invalidate(&self, urn: &Urn) {
+        self.inner.lock().stale.insert(urn.encode_id());
+    }
+
+    /// Select up to `max` branch tips of namespaces other than `urn`'s, which
+    /// are the most likely to share history with it.
+    ///
+    /// `remote` is the peer `urn` is about to be fetched from. Failure to read
+    /// the refs is not fatal, but yields fewer tips.
+    pub fn select(&self, store: &Storage, urn: &Urn, remote: &PeerId, max: usize) -> Vec<ObjectId> {
+        if max == 0 {
+            return vec![];
+        }
+
+        let mut state = self.inner.lock();
+        state.refresh(store);
+
+        let own = urn.encode_id();
+        let mut peers = state
+            .namespaces
+            .get(&own)
+            .map(|ns| ns.peers.clone())
+            .unwrap_or_default();
+        peers.insert(*remote);
+
+        let mut candidates = state
+            .namespaces
+            .iter()
+            .filter(|(id, _)| **id != own)
+            .flat_map(|(_, ns)| {
+                let common = ns.peers.intersection(&peers).count();
+                ns.tips.iter().map(move |(time, oid)| (common, *time, *oid))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_unstable_by_key(|candidate| Reverse(*candidate));
+
+        let mut seen = BTreeSet::new();
+        candidates
+            .into_iter()
+            .map(|(_, _, oid)| oid)
+            .filter(|oid| seen.insert(*oid))
+            .take(max)
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct State {
+    loaded: bool,
+    namespaces: HashMap<String, Namespace>,
+    stale: HashSet<String>,
+}
+
+struct Namespace {
+    /// The peers owning the branches, including the local peer.
+    peers: BTreeSet<PeerId>,
+    /// The branch tips, along with their commit time.
+    tips: Vec<(i64, ObjectId)>,
+}
+
+impl State {
+    fn refresh(&mut self, store: &Storage) {
+        if !self.loaded {
+            match read(store, "refs/namespaces/*") {
+                Ok(namespaces) => {
+                    self.namespaces = namespaces;
+                    self.stale.clear();
+                    self.loaded = true;
+                },
+                Err(e) => tracing::warn!(err = %e, "failed to list refs of other namespaces"),
+            }
+            return;
+        }
+
+        for id in mem::take(&mut self.stale) {
+            match read(store, &format!("refs/namespaces/{}/*", id)) {
+                Ok(mut namespaces) => match namespaces.remove(&id) {
+                    Some(ns) => {
+                        self.namespaces.insert(id, ns);
+                    },
+                    None => {
+                        self.namespaces.remove(&id);
+                    },
+                },
+                Err(e) => {
+                    tracing::warn!(err = %e, namespace = %id, "failed to list refs of namespace");
+                    self.stale.insert(id);
+                },
+            }
+        }
+    }
+}
+
+/// Read the branch tips of the namespaces matching `glob`.
+///
+/// Both owned (`refs/heads`) and remote-tracking (`refs/remotes/*/heads`)
+/// branches are considered, so this yields useful results on seeds, too.
+fn read(store: &Storage, glob: &str) -> Result<HashMap<String, Namespace>, git2::Error> {
+    let local = store.peer_id();
+    let mut namespaces = HashMap::new();
+    for r in store.as_raw().references_glob(glob)?.filter_map(Result::ok) {
+        let (id, peer) = match r.name().and_then(|name| branch_of(name, local)) {
+            None => continue,
+            Some(branch) => branch,
+        };
+        if let Ok(commit) = r.peel_to_commit() {
+            let ns = namespaces
+                .entry(id.to_owned())
+                .or_insert_with(|| Namespace {
+                    peers: BTreeSet::new(),
+                    tips: Vec::new(),
+                });
+            ns.peers.insert(peer);
+            ns.tips.push((
+                commit.time().seconds(),
+                ObjectId::from(git_ext::Oid::from(commit.id())),
+            ));
+        }
+    }
+
+    Ok(namespaces)
+}
+
+/// Split `refs/namespaces/<id>/refs/heads/*` or
+/// `refs/namespaces/<id>/refs/remotes/<peer>/heads/*` into the namespace id
+/// and the peer owning the branch.
+fn branch_of<'a>(name: &'a str, local: &PeerId) -> Option<(&'a str, PeerId)> {
+    let (id, name) = name.strip_prefix("refs/namespaces/")?.split_once('/')?;
+    let name = name.strip_prefix("refs/")?;
+    if name.starts_with("heads/") {
+        return Some((id, *local));
+    }
+    let (peer, name) = name.strip_prefix("remotes/")?.split_once('/')?;
+    if !name.starts_with("heads/") {
+        return None;
+    }
+
+    Some((id, peer.parse().ok()?))
+}
@@ -0,0 +1,71 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use parking_lot::Mutex;
+
+use crate::{identities::git::Urn, PeerId};
+
+/// A replication run in progress.
+#[derive(Clone, Debug)]
+pub struct Fetch {
+    pub urn: Urn,
+    pub remote_peer: PeerId,
+    /// When the run obtained its replication slot.
+    pub started: Instant,
+}
+
+/// Registry of the replication runs in progress.
+///
+/// Clones share the same registry, so it can be handed to several
+/// [`super::Replication`]s via [`super::Replication::tracking`].
+#[derive(Clone, Default)]
+pub struct InFlight {
+    next: Arc<AtomicU64>,
+    fetches: Arc<Mutex<BTreeMap<u64, Fetch>>>,
+}
+
+impl InFlight {
+    /// The runs currently in progress, oldest first.
+    pub fn fetches(&self) -> Vec<Fetch> {
+        self.fetches.lock().values().cloned().collect()
+    }
+
+    pub(super) fn register(&self, urn: Urn, remote_peer: PeerId) -> Registered {
+        let id = self.next.fetch_add(1, Ordering::Relaxed);
+        self.fetches.lock().insert(
+            id,
+            Fetch {
+                urn,
+                remote_peer,
+                started: Instant::now(),
+            },
+        );
+        Registered {
+            inflight: self.clone(),
+            id,
+        }
+    }
+}
+
+/// Removes the run from the [`InFlight`] registry when dropped.
+pub(super) struct Registered {
+    inflight: InFlight,
+    id: u64,
+}
+
+impl Drop for Registered {
+    fn drop(&mut self) {
+        self.inflight.fetches.lock().remove(&self.id);
+    }
+}
@@ -0,0 +1,393 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Scheduling of replication runs.
+//!
+//! Replication runs compete for a limited number of slots. Waiting runs are
+//! granted slots by [`Priority`] first, so that eg. an interactive
+//! request-pull doesn't queue up behind a background re-sync. Within a
+//! priority class, slots are handed out round-robin across namespaces, and a
+//! namespace never occupies more than [`Config::per_urn`] slots at a time.
+//! Thus, a large project with many pending fetches can't starve small
+//! updates to other projects.
+//!
+//! The [`Scheduler`] is shared by all clones of a [`super::Config`], and
+//! thereby by all [`super::Replication`]s created from it.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures::channel::oneshot;
+use parking_lot::Mutex;
+
+use crate::identities::git::Urn;
+
+/// The priority class of a replication run.
+///
+/// Later variants take precedence over earlier ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Periodic re-synchronisation of namespaces we already have.
+    Resync,
+    /// Fetches triggered by gossip from tracked peers.
+    Gossip,
+    /// Request-pull, and replication requested by the user.
+    Interactive,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Self::Interactive
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// Maximum number of replication runs in progress at any time.
+    pub slots: usize,
+    /// Maximum number of replication runs of the same namespace in progress
+    /// at any time.
+    pub per_urn: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            slots: 4,
+            per_urn: 1,
+        }
+    }
+}
+
+/// The state of the queue.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of runs holding a slot.
+    pub running: usize,
+    /// Number of runs waiting for a slot, per priority class.
+    pub waiting: BTreeMap<Priority, usize>,
+}
+
+/// A replication run changed its state in the queue.
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub urn: Urn,
+    pub priority: Priority,
+    pub transition: Transition,
+    /// The state of the queue after the transition.
+    pub queue: Stats,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transition {
+    /// The run is waiting for a slot.
+    Queued,
+    /// The run obtained a slot after waiting for `waited`.
+    Started { waited: Duration },
+    /// The run completed, releasing its slot.
+    Finished,
+    /// The run gave up waiting for a slot, eg. because it timed out.
+    Abandoned,
+}
+
+/// Hands out replication slots, see the [module documentation](self).
+///
+/// Clones share the same queue.
+#[derive(Clone)]
+pub struct Scheduler {
+    queue: Arc<Mutex<Queue>>,
+    on_event: Option<Arc<dyn Fn(Event) + Send + Sync>>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(Config::default())
+    }
+}
+
+impl fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("config", &self.config())
+            .field("queue", &self.stats())
+            .finish()
+    }
+}
+
+impl Scheduler {
+    pub fn new(config: Config) -> Self {
+        Self {
+            queue: Arc::new(Mutex::new(Queue::new(config))),
+            on_event: None,
+        }
+    }
+
+    /// Call `f` for each [`Event`] of the runs scheduled through the returned
+    /// handle.
+    ///
+    /// The queue is still shared with `self` and its clones.
+    pub fn on_event<F>(self, f: F) -> Self
+    where
+        F: Fn(Event) + Send + Sync + 'static,
+    {
+        Self {
+            on_event: Some(Arc::new(f)),
+            ..self
+        }
+    }
+
+    pub fn config(&self) -> Config {
+        self.queue.lock().config
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.queue.lock().stats()
+    }
+
+    /// Queue a run replicating `urn` with the given `priority`.
+    ///
+    /// The returned future resolves once a [`Slot`] is available. Dropping it
+    /// beforehand removes the run from the queue.
+    pub fn acquire(&self, urn: Urn, priority: Priority) -> Waiting {
+        let urn = urn.with_path(None);
+        let (tx, rx) = oneshot::channel();
+        let (id, queue) = {
+            let mut queue = self.queue.lock();
+            let id = queue.enqueue(urn.clone(), priority, tx);
+            queue.dispatch();
+            (id, queue.stats())
+        };
+        self.emit(&urn, priority, Transition::Queued, queue);
+
+        Waiting {
+            scheduler: self.clone(),
+            urn,
+            priority,
+            id,
+            since: Instant::now(),
+            rx: Some(rx),
+        }
+    }
+
+    fn emit(&self, urn: &Urn, priority: Priority, transition: Transition, queue: Stats) {
+        if let Some(on_event) = &self.on_event {
+            on_event(Event {
+                urn: urn.clone(),
+                priority,
+                transition,
+                queue,
+            })
+        }
+    }
+}
+
+/// A run waiting for a [`Slot`], see [`Scheduler::acquire`].
+pub struct Waiting {
+    scheduler: Scheduler,
+    urn: Urn,
+    priority: Priority,
+    id: u64,
+    since: Instant,
+    rx: Option<oneshot::Receiver<()>>,
+}
+
+impl Future for Waiting {
+    type Output = Slot;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let rx = self.rx.as_mut().expect("polled after completion");
+        match Pin::new(rx).poll(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(res) => {
+                res.expect("waiters are only removed when granted or abandoned");
+                self.rx = None;
+                let scheduler = &self.scheduler;
+                scheduler.emit(
+                    &self.urn,
+                    self.priority,
+                    Transition::Started {
+                        waited: self.since.elapsed(),
+                    },
+                    scheduler.stats(),
+                );
+                Poll::Ready(Slot {
+                    scheduler: scheduler.clone(),
+                    urn: self.urn.clone(),
+                    priority: self.priority,
+                })
+            },
+        }
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        if let Some(mut rx) = self.rx.take() {
+            rx.close();
+            let queue = {
+                let mut queue = self.scheduler.queue.lock();
+                match rx.try_recv() {
+                    // Granted, but never observed
+                    Ok(Some(())) => queue.release(&self.urn),
+                    _ => queue.remove(&self.urn, self.priority, self.id),
+                }
+                queue.stats()
+            };
+            self.scheduler
+                .emit(&self.urn, self.priority, Transition::Abandoned, queue);
+        }
+    }
+}
+
+/// Permission to run a replication.
+///
+/// The slot is released when dropped.
+pub struct Slot {
+    scheduler: Scheduler,
+    urn: Urn,
+    priority: Priority,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let queue = {
+            let mut queue = self.scheduler.queue.lock();
+            queue.release(&self.urn);
+            queue.stats()
+        };
+        self.scheduler
+            .emit(&self.urn, self.priority, Transition::Finished, queue);
+    }
+}
+
+/// The waiting runs of a priority class.
+#[derive(Default)]
+struct Class {
+    /// Namespaces with waiting runs, in the order they are served.
+    order: VecDeque<Urn>,
+    waiters: HashMap<Urn, VecDeque<(u64, oneshot::Sender<()>)>>,
+}
+
+struct Queue {
+    config: Config,
+    next: u64,
+    running: usize,
+    per_urn: HashMap<Urn, usize>,
+    classes: BTreeMap<Priority, Class>,
+}
+
+impl Queue {
+    fn new(config: Config) -> Self {
+        Self {
+            config,
+            next: 0,
+            running: 0,
+            per_urn: HashMap::new(),
+            classes: BTreeMap::new(),
+        }
+    }
+
+    fn stats(&self) -> Stats {
+        Stats {
+            running: self.running,
+            waiting: self
+                .classes
+                .iter()
+                .map(|(prio, class)| {
+                    (
+                        *prio,
+                        class.waiters.values().map(VecDeque::len).sum::<usize>(),
+                    )
+                })
+                .filter(|(_, n)| *n > 0)
+                .collect(),
+        }
+    }
+
+    fn enqueue(&mut self, urn: Urn, priority: Priority, tx: oneshot::Sender<()>) -> u64 {
+        let id = self.next;
+        self.next += 1;
+        let class = self.classes.entry(priority).or_default();
+        let waiters = class.waiters.entry(urn.clone()).or_default();
+        if waiters.is_empty() {
+            class.order.push_back(urn);
+        }
+        waiters.push_back((id, tx));
+        id
+    }
+
+    fn remove(&mut self, urn: &Urn, priority: Priority, id: u64) {
+        if let Some(class) = self.classes.get_mut(&priority) {
+            if let Some(waiters) = class.waiters.get_mut(urn) {
+                waiters.retain(|(other, _)| *other != id);
+                if waiters.is_empty() {
+                    class.waiters.remove(urn);
+                    class.order.retain(|other| other != urn);
+                }
+            }
+        }
+    }
+
+    fn release(&mut self, urn: &Urn) {
+        self.running -= 1;
+        if let Some(n) = self.per_urn.get_mut(urn) {
+            *n -= 1;
+            if *n == 0 {
+                self.per_urn.remove(urn);
+            }
+        }
+        self.dispatch()
+    }
+
+    /// Grant slots to waiting runs while there are free ones.
+    fn dispatch(&mut self) {
+        while self.running < self.config.slots {
+            let (urn, tx) = match self.next_waiter() {
+                None => break,
+                Some(next) => next,
+            };
+            if tx.send(()).is_ok() {
+                self.running += 1;
+                *self.per_urn.entry(urn).or_default() += 1;
+            }
+        }
+    }
+
+    /// Pop the next run to grant a slot to: the first in the highest priority
+    /// class whose namespace is below the per-URN limit. Its namespace moves
+    /// to the back of the class.
+    fn next_waiter(&mut self) -> Option<(Urn, oneshot::Sender<()>)> {
+        let per_urn = &self.per_urn;
+        let limit = self.config.per_urn;
+        for class in self.classes.values_mut().rev() {
+            let pos = class
+                .order
+                .iter()
+                .position(|urn| per_urn.get(urn).copied().unwrap_or(0) < limit);
+            if let Some(pos) = pos {
+                let urn = class.order.remove(pos).expect("position is in bounds");
+                let waiters = class
+                    .waiters
+                    .get_mut(&urn)
+                    .expect("ordered urns have waiters");
+                let (_, tx) = waiters.pop_front().expect("waiters are never empty");
+                if waiters.is_empty() {
+                    class.waiters.remove(&urn);
+                } else {
+                    class.order.push_back(urn.clone());
+                }
+                return Some((urn, tx));
+            }
+        }
+        None
+    }
+}
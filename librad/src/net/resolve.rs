@@ -0,0 +1,151 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Asynchronous resolution of [`Host`]s, with caching.
+//!
+//! Resolution is delegated to the system resolver via
+//! [`tokio::net::lookup_host`], which doesn't expose the TTLs of the DNS
+//! records. Cached resolutions thus expire after [`Config::ttl`], which
+//! should be chosen no longer than the TTLs of the records in question. Failed
+//! resolutions are cached for [`Config::negative_ttl`], so unresolvable hosts
+//! don't cause a query on every connection attempt.
+//!
+//! When connecting to a resolved address fails, the address may be stale: use
+//! [`Resolver::refresh`] to bypass the cache when retrying.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+use crate::git::p2p::url::GitUrl;
+pub use crate::git::p2p::url::Host;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// How long to cache successful resolutions.
+    pub ttl: Duration,
+    /// How long to cache failed resolutions.
+    pub negative_ttl: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(60),
+            negative_ttl: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Entry {
+    addrs: Vec<SocketAddr>,
+    expires: Instant,
+}
+
+/// A caching resolver.
+///
+/// Cloning is cheap, and clones share the cache.
+#[derive(Clone)]
+pub struct Resolver {
+    config: Config,
+    cache: Arc<Mutex<HashMap<Host, Entry>>>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new(Config::default())
+    }
+}
+
+impl Resolver {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Resolve `host`, using a cached resolution if it hasn't expired yet.
+    ///
+    /// Resolution errors are logged, and yield no addresses.
+    pub async fn resolve(&self, host: &Host) -> Vec<SocketAddr> {
+        let cached = self
+            .cache
+            .lock()
+            .get(host)
+            .filter(|entry| entry.expires > Instant::now())
+            .map(|entry| entry.addrs.clone());
+        match cached {
+            Some(addrs) => addrs,
+            None => self.refresh(host).await,
+        }
+    }
+
+    /// Resolve `host`, bypassing the cache.
+    pub async fn refresh(&self, host: &Host) -> Vec<SocketAddr> {
+        let addrs = match tokio::net::lookup_host((host.name.as_str(), host.port)).await {
+            Ok(addrs) => addrs.collect::<Vec<_>>(),
+            Err(e) => {
+                tracing::warn!(host = %host, err = %e, "failed to resolve host");
+                vec![]
+            },
+        };
+        let ttl = if addrs.is_empty() {
+            self.config.negative_ttl
+        } else {
+            self.config.ttl
+        };
+        self.cache.lock().insert(
+            host.clone(),
+            Entry {
+                addrs: addrs.clone(),
+                expires: Instant::now() + ttl,
+            },
+        );
+
+        addrs
+    }
+
+    /// Resolve all `hosts`, in order, omitting duplicate addresses.
+    pub async fn resolve_all<'a>(
+        &self,
+        hosts: impl IntoIterator<Item = &'a Host>,
+    ) -> Vec<SocketAddr> {
+        let mut resolved = Vec::new();
+        for host in hosts {
+            for addr in self.resolve(host).await {
+                if !resolved.contains(&addr) {
+                    resolved.push(addr)
+                }
+            }
+        }
+        resolved
+    }
+
+    /// Forget the cached resolution of `host`, if any.
+    pub fn invalidate(&self, host: &Host) {
+        self.cache.lock().remove(host);
+    }
+
+    /// All address hints of `url`: its [`GitUrl::addr_hints`], followed by
+    /// the resolved [`GitUrl::host_hints`], followed by the
+    /// [`GitUrl::tcp_hints`].
+    pub async fn addr_hints<R>(&self, url: &GitUrl<R>) -> Vec<SocketAddr> {
+        let mut addrs = url.addr_hints.clone();
+        let resolved = self.resolve_all(&url.host_hints).await;
+        for addr in resolved.into_iter().chain(url.tcp_hints.iter().copied()) {
+            if !addrs.contains(&addr) {
+                addrs.push(addr)
+            }
+        }
+        addrs
+    }
+}
@@ -0,0 +1,427 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Fallback transport over TCP, for networks which block UDP.
+//!
+//! Connections are secured by TLS 1.3 using the same self-signed, Ed25519
+//! derived certificates and ALPN identifiers as [`crate::net::quic`], so the
+//! remote peer is authenticated exactly as it is over QUIC. As TCP only
+//! provides a single byte stream, streams are multiplexed over the TLS session
+//! using [yamux], which allows either end to open bidirectional streams.
+//!
+//! The transport only serves the client API: fetching via git, and
+//! request-pull. Unidirectional streams, and thus gossip and membership, are
+//! not supported. Clients fall back to it automatically when no QUIC
+//! connection could be established, see
+//! [`crate::net::protocol::rpc::client::Client`].
+//!
+//! As UDP and TCP ports are separate, a peer typically listens for TCP
+//! connections on the same port number as its QUIC endpoint, in which case its
+//! usual address hints apply. Otherwise, the TCP addresses are to be given
+//! alongside the QUIC ones, eg. via the `tcp` hints of a
+//! [`crate::git::p2p::url::GitUrl`].
+//!
+//! [yamux]: https://github.com/hashicorp/yamux/blob/master/spec.md
+
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{
+    io::{AsyncRead, AsyncReadExt as _, AsyncWrite, ReadHalf, WriteHalf},
+    stream::{BoxStream, Stream, StreamExt as _},
+};
+use futures_rustls::{TlsAcceptor, TlsConnector, TlsStream};
+use rustls::Session as _;
+use thiserror::Error;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt as _};
+
+use crate::{
+    net::{
+        connection::{CloseReason, Duplex, LocalAddr, LocalPeer, RemoteAddr, RemotePeer},
+        happy_eyeballs,
+        quic,
+        tls,
+        x509,
+        Network,
+    },
+    PeerId,
+    Signer,
+};
+
+/// Time allowed to establish a TCP connection and complete the TLS handshake.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum number of TLS handshakes [`Listener::incoming`] performs
+/// concurrently.
+const MAX_HANDSHAKES: usize = 64;
+
+/// Time to wait before accepting again after accepting a connection failed,
+/// eg. because we ran out of file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("remote PeerId could not be determined")]
+    RemoteIdUnavailable,
+
+    #[error("connect to self")]
+    SelfConnect,
+
+    #[error("connect to {0} timed out")]
+    Timeout(SocketAddr),
+
+    #[error("signer error")]
+    Signer(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error(transparent)]
+    Certificate(#[from] x509::FromDerError),
+
+    #[error(transparent)]
+    Mux(#[from] yamux::ConnectionError),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+type Tls = TlsStream<Compat<TcpStream>>;
+
+/// A TLS session to a remote peer, over which streams are multiplexed.
+///
+/// The connection is driven by polling its [`IncomingStreams`], and closed
+/// once they are dropped.
+#[derive(Clone)]
+pub struct Connection {
+    remote_peer: PeerId,
+    remote_addr: SocketAddr,
+    control: yamux::Control,
+}
+
+impl Connection {
+    fn new(
+        remote_peer: PeerId,
+        remote_addr: SocketAddr,
+        tls: Tls,
+        mode: yamux::Mode,
+    ) -> (Self, IncomingStreams) {
+        let mux = yamux::Connection::new(tls, yamux::Config::default(), mode);
+        let conn = Self {
+            remote_peer,
+            remote_addr,
+            control: mux.control(),
+        };
+        let incoming = IncomingStreams {
+            conn: conn.clone(),
+            inner: yamux::into_stream(mux).boxed(),
+        };
+        (conn, incoming)
+    }
+
+    pub async fn open_bidi(&self) -> Result<BidiStream> {
+        let stream = self.control.clone().open_stream().await?;
+        Ok(BidiStream {
+            conn: self.clone(),
+            stream,
+        })
+    }
+}
+
+impl RemotePeer for Connection {
+    fn remote_peer_id(&self) -> PeerId {
+        self.remote_peer
+    }
+}
+
+impl RemoteAddr for Connection {
+    type Addr = SocketAddr;
+
+    fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+}
+
+/// The streams opened by the remote end of a [`Connection`].
+pub struct IncomingStreams {
+    conn: Connection,
+    inner: BoxStream<'static, std::result::Result<yamux::Stream, yamux::ConnectionError>>,
+}
+
+impl IncomingStreams {
+    /// Drive the connection, refusing all streams the remote end opens.
+    pub async fn deny(mut self) {
+        while let Some(Ok(stream)) = self.next().await {
+            tracing::warn!(remote_id = %stream.remote_peer_id(), "denying inbound tcp stream");
+            stream.close(CloseReason::InvalidUpgrade)
+        }
+    }
+}
+
+impl RemotePeer for IncomingStreams {
+    fn remote_peer_id(&self) -> PeerId {
+        self.conn.remote_peer
+    }
+}
+
+impl RemoteAddr for IncomingStreams {
+    type Addr = SocketAddr;
+
+    fn remote_addr(&self) -> SocketAddr {
+        self.conn.remote_addr
+    }
+}
+
+impl Stream for IncomingStreams {
+    type Item = Result<BidiStream>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let conn = self.conn.clone();
+        self.inner.poll_next_unpin(cx).map(|item| {
+            item.map(|res| {
+                res.map(|stream| BidiStream { conn, stream })
+                    .map_err(Error::from)
+            })
+        })
+    }
+}
+
+pub struct BidiStream {
+    conn: Connection,
+    stream: yamux::Stream,
+}
+
+impl BidiStream {
+    /// Close the stream.
+    ///
+    /// Unlike QUIC, yamux can't convey the `reason` to the remote end, which
+    /// merely observes a reset.
+    pub fn close(self, reason: CloseReason) {
+        tracing::trace!(
+            reason = %String::from_utf8_lossy(reason.reason_phrase()),
+            "closing tcp stream"
+        );
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+impl RemotePeer for BidiStream {
+    fn remote_peer_id(&self) -> PeerId {
+        self.conn.remote_peer
+    }
+}
+
+impl RemoteAddr for BidiStream {
+    type Addr = SocketAddr;
+
+    fn remote_addr(&self) -> SocketAddr {
+        self.conn.remote_addr
+    }
+}
+
+impl Duplex for BidiStream {
+    type Read = ReadHalf<yamux::Stream>;
+    type Write = WriteHalf<yamux::Stream>;
+
+    fn split(self) -> (Self::Read, Self::Write) {
+        self.stream.split()
+    }
+}
+
+impl AsyncRead for BidiStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        AsyncRead::poll_read(Pin::new(&mut self.get_mut().stream), cx, buf)
+    }
+}
+
+impl AsyncWrite for BidiStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+        AsyncWrite::poll_write(Pin::new(&mut self.get_mut().stream), cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_flush(Pin::new(&mut self.get_mut().stream), cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        AsyncWrite::poll_close(Pin::new(&mut self.get_mut().stream), cx)
+    }
+}
+
+/// Connect to `peer` via TCP, racing `addrs` as described in
+/// [`happy_eyeballs`].
+pub async fn connect<S, I>(
+    signer: S,
+    network: Network,
+    peer: PeerId,
+    addrs: I,
+) -> Result<(Connection, IncomingStreams)>
+where
+    S: Signer + Clone + Send + Sync + 'static,
+    S::Error: std::error::Error + Send + Sync + 'static,
+    I: IntoIterator<Item = SocketAddr>,
+{
+    if peer == PeerId::from_signer(&signer) {
+        return Err(Error::SelfConnect);
+    }
+
+    let mut config = tls::make_client_config(signer).map_err(|e| Error::Signer(Box::new(e)))?;
+    config.alpn_protocols = vec![quic::alpn(network)];
+    let connector = TlsConnector::from(Arc::new(config));
+
+    let addrs = happy_eyeballs::interleave(addrs.into_iter().collect());
+    happy_eyeballs::race(addrs, happy_eyeballs::CONNECTION_ATTEMPT_DELAY, |addr| {
+        let connector = connector.clone();
+        async move {
+            let handshake = async {
+                let tcp = TcpStream::connect(addr).await?;
+                tcp.set_nodelay(true)?;
+                let name = peer.as_dns_name();
+                let tls = connector.connect(name.as_ref(), tcp.compat()).await?;
+                Ok::<_, Error>(TlsStream::from(tls))
+            };
+            match link_async::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+                Ok(Ok(tls)) => {
+                    tracing::debug!(remote_id = %peer, remote_addr = %addr, "tcp connection established");
+                    Ok(Connection::new(peer, addr, tls, yamux::Mode::Client))
+                },
+                Ok(Err(e)) => {
+                    tracing::debug!(err = %e, remote_addr = %addr, "tcp connect failed");
+                    Err(e)
+                },
+                Err(_) => {
+                    tracing::debug!(remote_addr = %addr, "tcp connect timed out");
+                    Err(Error::Timeout(addr))
+                },
+            }
+        }
+    })
+    .await
+    .map_err(|mut errors| {
+        errors
+            .pop()
+            .map_or(Error::RemoteIdUnavailable, |(_, e)| e)
+    })
+}
+
+/// A TCP socket accepting connections from other peers.
+pub struct Listener {
+    peer_id: PeerId,
+    listen_addr: SocketAddr,
+    socket: TcpListener,
+    acceptor: TlsAcceptor,
+}
+
+impl Listener {
+    pub async fn bind<S>(signer: S, listen_addr: SocketAddr, network: Network) -> Result<Self>
+    where
+        S: Signer + Clone + Send + Sync + 'static,
+        S::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let peer_id = PeerId::from_signer(&signer);
+        let mut config = tls::make_server_config(signer).map_err(|e| Error::Signer(Box::new(e)))?;
+        config.alpn_protocols = vec![quic::alpn(network)];
+        let socket = TcpListener::bind(listen_addr).await?;
+        let listen_addr = socket.local_addr()?;
+
+        Ok(Self {
+            peer_id,
+            listen_addr,
+            socket,
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+        })
+    }
+
+    /// The connections established by remote peers.
+    ///
+    /// TLS handshakes are performed concurrently, up to a fixed limit.
+    /// Connections which fail the handshake, as well as errors accepting
+    /// connections, are logged and skipped.
+    pub fn incoming(self) -> impl Stream<Item = (Connection, IncomingStreams)> + Send {
+        let Self {
+            socket, acceptor, ..
+        } = self;
+        futures::stream::unfold(socket, |socket| async move {
+            loop {
+                match socket.accept().await {
+                    Ok(accepted) => return Some((accepted, socket)),
+                    Err(e) => {
+                        tracing::warn!(err = %e, "tcp accept failed");
+                        link_async::sleep(ACCEPT_BACKOFF).await;
+                    },
+                }
+            }
+        })
+        .map(move |(tcp, addr)| {
+            let acceptor = acceptor.clone();
+            async move {
+                let accepted = link_async::timeout(HANDSHAKE_TIMEOUT, accept(&acceptor, tcp, addr))
+                    .await
+                    .unwrap_or(Err(Error::Timeout(addr)));
+                match accepted {
+                    Ok(conn) => Some(conn),
+                    Err(e) => {
+                        tracing::warn!(err = %e, remote_addr = %addr, "tcp handshake failed");
+                        None
+                    },
+                }
+            }
+        })
+        .buffer_unordered(MAX_HANDSHAKES)
+        .filter_map(futures::future::ready)
+    }
+}
+
+impl LocalPeer for Listener {
+    fn local_peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+}
+
+impl LocalAddr for Listener {
+    type Addr = SocketAddr;
+
+    fn listen_addrs(&self) -> Vec<SocketAddr> {
+        vec![self.listen_addr]
+    }
+}
+
+async fn accept(
+    acceptor: &TlsAcceptor,
+    tcp: TcpStream,
+    addr: SocketAddr,
+) -> Result<(Connection, IncomingStreams)> {
+    tcp.set_nodelay(true)?;
+    let tls = acceptor.accept(tcp.compat()).await?;
+    let remote_peer = {
+        let (_, session) = tls.get_ref();
+        let certs = session
+            .get_peer_certificates()
+            .ok_or(Error::RemoteIdUnavailable)?;
+        let first = certs.first().ok_or(Error::RemoteIdUnavailable)?;
+        x509::Certificate::from_der(&first.0)?.peer_id()
+    };
+
+    Ok(Connection::new(
+        remote_peer,
+        addr,
+        TlsStream::from(tls),
+        yamux::Mode::Server,
+    ))
+}
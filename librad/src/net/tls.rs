@@ -4,6 +4,7 @@
 // Linking Exception. For full terms see the included LICENSE file.
 
 use std::{
+    collections::BTreeMap,
     convert::TryFrom,
     sync::{Arc, RwLock},
 };
@@ -64,6 +65,113 @@ where
     Ok(cfg)
 }
 
+/// The local identities of all profiles sharing an endpoint.
+///
+/// Used to make a server config which presents the certificate of the
+/// identity the client asked for via SNI, see [`make_shared_server_config`].
+#[derive(Clone, Default)]
+pub struct Identities {
+    resolvers: Arc<RwLock<BTreeMap<PeerId, Arc<CertResolver>>>>,
+}
+
+impl Identities {
+    pub fn insert<S>(&self, signer: S) -> Result<PeerId, S::Error>
+    where
+        S: Signer + Clone + Send + Sync + 'static,
+        S::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let peer_id = PeerId::from_signer(&signer);
+        let cert = x509::Certificate::generate(&signer)?;
+        self.resolvers
+            .write()
+            .unwrap()
+            .insert(peer_id, Arc::new(CertResolver::new(signer, cert)));
+        Ok(peer_id)
+    }
+
+    pub fn remove(&self, peer_id: &PeerId) -> bool {
+        self.resolvers.write().unwrap().remove(peer_id).is_some()
+    }
+
+    pub fn contains(&self, peer_id: &PeerId) -> bool {
+        self.resolvers.read().unwrap().contains_key(peer_id)
+    }
+}
+
+/// Like [`make_server_config`], but for any of the `identities`.
+pub fn make_shared_server_config(identities: Identities) -> rustls::ServerConfig {
+    let mut cfg = rustls::ServerConfig::new(Arc::new(identities.clone()));
+    cfg.versions = vec![rustls::ProtocolVersion::TLSv1_3];
+    cfg.cert_resolver = Arc::new(identities);
+    // See `make_server_config`
+    cfg.session_storage = Arc::new(NoServerSessionStorage {});
+
+    cfg
+}
+
+impl ResolvesServerCert for Identities {
+    #[tracing::instrument(skip(self, client_hello))]
+    fn resolve(&self, client_hello: ClientHello) -> Option<CertifiedKey> {
+        let peer_id = sni_peer_id(client_hello.server_name())?;
+        match self.resolvers.read().unwrap().get(&peer_id) {
+            Some(resolver) => resolver.certified_key(client_hello.sigschemes()),
+            None => {
+                tracing::warn!(%peer_id, "sni doesn't match any local peer id");
+                None
+            },
+        }
+    }
+}
+
+impl ClientCertVerifier for Identities {
+    fn offer_client_auth(&self) -> bool {
+        true
+    }
+
+    fn client_auth_mandatory(&self, _sni: Option<&webpki::DNSName>) -> Option<bool> {
+        Some(true)
+    }
+
+    fn client_auth_root_subjects(
+        &self,
+        _sni: Option<&webpki::DNSName>,
+    ) -> Option<DistinguishedNames> {
+        Some(rustls::DistinguishedNames::new())
+    }
+
+    fn verify_client_cert(
+        &self,
+        presented_certs: &[rustls::Certificate],
+        sni: Option<&webpki::DNSName>,
+    ) -> Result<ClientCertVerified, TLSError> {
+        let remote = verify_client_cert(presented_certs)?;
+        // Profiles sharing the endpoint may connect to each other, but not to
+        // themselves
+        if sni.and_then(|sni| PeerId::try_from(sni.as_ref()).ok()) == Some(remote) {
+            return Err(TLSError::PeerMisbehavedError(
+                "Self-connections are not permitted".into(),
+            ));
+        }
+
+        Ok(ClientCertVerified::assertion())
+    }
+}
+
+fn sni_peer_id(sni: Option<webpki::DNSNameRef>) -> Option<PeerId> {
+    sni.or_else(|| {
+        tracing::warn!("client missing sni");
+        None
+    })
+    .and_then(|sni| {
+        PeerId::try_from(sni)
+            .map_err(|e| {
+                tracing::warn!(err = ?e, "invalid sni");
+                e
+            })
+            .ok()
+    })
+}
+
 struct Cert {
     expires: Date,
     cert: rustls::Certificate,
@@ -153,26 +261,13 @@ impl ResolvesClientCert for CertResolver {
 impl ResolvesServerCert for CertResolver {
     #[tracing::instrument(skip(self, client_hello))]
     fn resolve(&self, client_hello: ClientHello) -> Option<CertifiedKey> {
-        client_hello
-            .server_name()
-            .or_else(|| {
-                tracing::warn!("client missing sni");
-                None
-            })
-            .and_then(|sni| {
-                let peer_id = PeerId::try_from(sni)
-                    .map_err(|e| {
-                        tracing::warn!(err = ?e, "invalid sni");
-                        e
-                    })
-                    .ok()?;
-                if peer_id == PeerId::from_signer(&self.signer) {
-                    self.certified_key(client_hello.sigschemes())
-                } else {
-                    tracing::warn!("sni doesn't match local peer id");
-                    None
-                }
-            })
+        let peer_id = sni_peer_id(client_hello.server_name())?;
+        if peer_id == PeerId::from_signer(&self.signer) {
+            self.certified_key(client_hello.sigschemes())
+        } else {
+            tracing::warn!("sni doesn't match local peer id");
+            None
+        }
     }
 }
 
@@ -268,22 +363,8 @@ impl ClientCertVerifier for AccursedUnutterableUnsafeInsecureCertificateVerifier
         presented_certs: &[rustls::Certificate],
         _sni: Option<&webpki::DNSName>,
     ) -> Result<ClientCertVerified, TLSError> {
-        let (cert, ca) = presented_cert(presented_certs)?;
-        // Verify that we've got a self-signed ed25519 cert
-        cert.verify_is_valid_tls_client_cert(
-            &[&webpki::ED25519],
-            &webpki::TLSClientTrustAnchors(&[ca]),
-            &[],
-            try_now()?,
-        )
-        .map_err(TLSError::WebPKIError)?;
-
-        // Verify the presented cert's public key is a `PeerId`
-        let cert = x509::Certificate::from_der(&presented_certs[0].0)
-            .map_err(|e| TLSError::PeerIncompatibleError(e.to_string()))?;
-
         // We don't allow self-connections
-        if cert.peer_id_ref() == &self.local_id {
+        if verify_client_cert(presented_certs)? == self.local_id {
             return Err(TLSError::PeerMisbehavedError(
                 "Self-connections are not permitted".into(),
             ));
@@ -293,6 +374,25 @@ impl ClientCertVerifier for AccursedUnutterableUnsafeInsecureCertificateVerifier
     }
 }
 
+/// Verify the client certificate, returning the [`PeerId`] it belongs to.
+fn verify_client_cert(presented_certs: &[rustls::Certificate]) -> Result<PeerId, TLSError> {
+    let (cert, ca) = presented_cert(presented_certs)?;
+    // Verify that we've got a self-signed ed25519 cert
+    cert.verify_is_valid_tls_client_cert(
+        &[&webpki::ED25519],
+        &webpki::TLSClientTrustAnchors(&[ca]),
+        &[],
+        try_now()?,
+    )
+    .map_err(TLSError::WebPKIError)?;
+
+    // Verify the presented cert's public key is a `PeerId`
+    let cert = x509::Certificate::from_der(&presented_certs[0].0)
+        .map_err(|e| TLSError::PeerIncompatibleError(e.to_string()))?;
+
+    Ok(cert.peer_id())
+}
+
 fn presented_cert(
     presented_certs: &[rustls::Certificate],
 ) -> Result<(webpki::EndEntityCert, webpki::TrustAnchor), TLSError> {
@@ -51,6 +51,9 @@ pub struct Interrogation;
 #[derive(Debug)]
 pub struct RequestPull;
 
+#[derive(Debug)]
+pub struct RequestPush;
+
 /// Signal the (sub-) protocol about to be sent over a given QUIC stream.
 ///
 /// This is only valid as the first message sent by the initiator of a fresh
@@ -96,6 +99,9 @@ pub enum UpgradeRequest {
     ///
     /// [rfc]: https://github.com/radicle-dev/radicle-link/blob/master/docs%2Frfc%2F0702-request-pull.adoc
     RequestPull = 200,
+    /// `RequestPush` is the counterpart of `RequestPull`, and is subject to
+    /// the same deprecation.
+    RequestPush = 201,
 }
 
 impl From<Gossip> for UpgradeRequest {
@@ -128,6 +134,12 @@ impl From<RequestPull> for UpgradeRequest {
     }
 }
 
+impl From<RequestPush> for UpgradeRequest {
+    fn from(_request_push: RequestPush) -> Self {
+        UpgradeRequest::RequestPush
+    }
+}
+
 impl minicbor::Encode for UpgradeRequest {
     fn encode<W: minicbor::encode::Write>(
         &self,
@@ -155,6 +167,7 @@ impl<'de> minicbor::Decode<'de> for UpgradeRequest {
                 2 => Ok(Self::Membership),
                 3 => Ok(Self::Interrogation),
                 200 => Ok(Self::RequestPull),
+                201 => Ok(Self::RequestPush),
                 n => Err(minicbor::decode::Error::UnknownVariant(n as u32)),
             },
             n => Err(minicbor::decode::Error::UnknownVariant(n as u32)),
@@ -267,6 +280,7 @@ pub enum SomeUpgraded<S> {
     Membership(Upgraded<Membership, S>),
     Interrogation(Upgraded<Interrogation, S>),
     RequestPull(Upgraded<RequestPull, S>),
+    RequestPush(Upgraded<RequestPush, S>),
 }
 
 impl<S> SomeUpgraded<S> {
@@ -280,6 +294,7 @@ impl<S> SomeUpgraded<S> {
             Self::Membership(up) => SomeUpgraded::Membership(up.map(f)),
             Self::Interrogation(up) => SomeUpgraded::Interrogation(up.map(f)),
             Self::RequestPull(up) => SomeUpgraded::RequestPull(up.map(f)),
+            Self::RequestPush(up) => SomeUpgraded::RequestPush(up.map(f)),
         }
     }
 }
@@ -329,6 +344,7 @@ where
                     SomeUpgraded::Interrogation(Upgraded::new(incoming))
                 },
                 UpgradeRequest::RequestPull => SomeUpgraded::RequestPull(Upgraded::new(incoming)),
+                UpgradeRequest::RequestPush => SomeUpgraded::RequestPush(Upgraded::new(incoming)),
             };
 
             Ok(upgrade)
@@ -0,0 +1,393 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Notifications about replicated updates.
+//!
+//! The refs changed by replicating a namespace are classified into
+//! [`Notification`]s worth bringing to the attention of the local user:
+//!
+//! * new heads of the branches of tracked peers, see [`Kind::Head`]
+//! * changes to collaborative objects mentioning the local identity, see
+//!   [`Kind::Mention`]
+//! * revisions of identities the local peer is a delegate of, but has not
+//!   signed yet, see [`Kind::Signature`]
+//!
+//! Notifications are kept in the [`Inbox`] of the profile, stored at
+//! [`Paths::inbox_file`], where clients can list them and mark them as read.
+//! A node populates the inbox if configured to do so via
+//! [`crate::net::replication::Replication::notify`].
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom as _,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+use git_ext as ext;
+use serde::{Deserialize, Serialize};
+use std_ext::time::unix_now;
+use thiserror::Error;
+
+use crate::{
+    git::{
+        identities::{self, any},
+        storage::{self, config, Storage},
+        types::RefsCategory,
+        Urn,
+    },
+    identities::{delegation::Delegations as _, git::SomeIdentity, sign::Signatures},
+    paths::Paths,
+    PeerId,
+    PublicKey,
+};
+
+/// The name of the blob holding the payload of a change to a collaborative
+/// object.
+const CHANGE_BLOB_NAME: &str = "change";
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("failed to read the inbox at `{path}`")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("failed to write the inbox at `{path}`")]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("malformed inbox at `{path}`")]
+    Malformed {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error(transparent)]
+    Config(#[from] config::Error),
+
+    #[error(transparent)]
+    Identities(#[from] identities::error::Error),
+
+    #[error(transparent)]
+    Storage(#[from] storage::Error),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// A ref of a namespace changed by replication.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Update {
+    /// The namespace, with the [`Urn::path`] set to the name of the ref
+    /// relative to the namespace, eg. `refs/remotes/<peer>/heads/main`.
+    pub urn: Urn,
+    /// The previous target, if the ref existed before.
+    pub old: Option<ext::Oid>,
+    /// The new target, if the ref was not pruned.
+    pub new: Option<ext::Oid>,
+}
+
+/// What a [`Notification`] is about.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum Kind {
+    /// The branch `branch` of `peer` has a new head.
+    Head {
+        peer: PeerId,
+        branch: String,
+        old: Option<ext::Oid>,
+        new: ext::Oid,
+    },
+    /// `peer` replicated a change to a collaborative object which mentions
+    /// the local identity.
+    Mention {
+        peer: PeerId,
+        typename: String,
+        object: String,
+        change: ext::Oid,
+    },
+    /// `peer` proposed a revision of an identity the local peer is a delegate
+    /// of, which the local peer has not signed.
+    Signature { peer: PeerId, revision: ext::Oid },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Notification {
+    pub id: u64,
+    /// The namespace the notification is about.
+    pub urn: Urn,
+    pub kind: Kind,
+    /// When the notification was received, in seconds since the epoch.
+    pub timestamp: u64,
+    pub read: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Contents {
+    next_id: u64,
+    notifications: BTreeMap<u64, Notification>,
+}
+
+/// The persistent list of [`Notification`]s of a profile.
+#[derive(Debug)]
+pub struct Inbox {
+    file: PathBuf,
+    contents: Contents,
+}
+
+impl Inbox {
+    /// Load the inbox from [`Paths::inbox_file`].
+    ///
+    /// If the file does not exist yet, the inbox is empty.
+    pub fn load(paths: &Paths) -> Result<Self, Error> {
+        Self::open(paths.inbox_file())
+    }
+
+    fn open(file: &Path) -> Result<Self, Error> {
+        let file = file.to_path_buf();
+        let contents = match fs::read(&file) {
+            Ok(json) => serde_json::from_slice(&json).map_err(|source| Error::Malformed {
+                path: file.clone(),
+                source,
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Contents::default(),
+            Err(source) => return Err(Error::Read { path: file, source }),
+        };
+
+        Ok(Self { file, contents })
+    }
+
+    /// Persist the inbox.
+    ///
+    /// The file is replaced atomically, so concurrent readers never observe a
+    /// partially written inbox. Concurrent writers may, however, overwrite
+    /// each other's changes.
+    pub fn save(&self) -> Result<(), Error> {
+        let write_err = |source| Error::Write {
+            path: self.file.clone(),
+            source,
+        };
+        let json =
+            serde_json::to_vec_pretty(&self.contents).map_err(|source| Error::Malformed {
+                path: self.file.clone(),
+                source,
+            })?;
+        let tmp = self.file.with_extension("json.tmp");
+        fs::write(&tmp, json).map_err(write_err)?;
+        fs::rename(&tmp, &self.file).map_err(write_err)
+    }
+
+    /// All notifications, oldest first.
+    pub fn notifications(&self) -> impl Iterator<Item = &Notification> {
+        self.contents.notifications.values()
+    }
+
+    /// The notifications which were not marked as read, oldest first.
+    pub fn unread(&self) -> impl Iterator<Item = &Notification> {
+        self.notifications().filter(|n| !n.read)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Notification> {
+        self.contents.notifications.get(&id)
+    }
+
+    /// Add a notification of `kind` about `urn`, and return its id.
+    pub fn push(&mut self, urn: Urn, kind: Kind) -> u64 {
+        let id = self.contents.next_id;
+        self.contents.next_id += 1;
+        let timestamp = unix_now();
+        self.contents.notifications.insert(
+            id,
+            Notification {
+                id,
+                urn: urn.with_path(None),
+                kind,
+                timestamp,
+                read: false,
+            },
+        );
+        id
+    }
+
+    /// Mark the notification `id` as read or unread.
+    ///
+    /// Returns `false` if there is no such notification.
+    pub fn mark(&mut self, id: u64, read: bool) -> bool {
+        match self.contents.notifications.get_mut(&id) {
+            Some(n) => {
+                n.read = read;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Mark all notifications as read, or only those about `urn` if given.
+    pub fn mark_all_read(&mut self, urn: Option<&Urn>) {
+        for n in self.contents.notifications.values_mut() {
+            if urn.map_or(true, |urn| urn == &n.urn) {
+                n.read = true;
+            }
+        }
+    }
+
+    /// Remove the notification `id`, returning it.
+    pub fn remove(&mut self, id: u64) -> Option<Notification> {
+        self.contents.notifications.remove(&id)
+    }
+
+    /// Remove all notifications which were marked as read.
+    pub fn clear_read(&mut self) {
+        self.contents.notifications.retain(|_, n| !n.read)
+    }
+
+    /// Classify `updates` and add the resulting notifications, see
+    /// [`classify`]. Returns the ids of the new notifications.
+    pub fn record<I>(&mut self, storage: &Storage, updates: I) -> Result<Vec<u64>, Error>
+    where
+        I: IntoIterator<Item = Update>,
+    {
+        let mut ids = Vec::new();
+        for update in updates {
+            let urn = update.urn.clone();
+            for kind in classify(storage, &update)? {
+                ids.push(self.push(urn.clone(), kind));
+            }
+        }
+        Ok(ids)
+    }
+}
+
+/// The notifications warranted by `update`.
+///
+/// Only updates of the remote refs of other peers are considered. Mentions
+/// are detected by searching the raw payload of new changes for the
+/// identifier of the local identity configured in `storage`, so mentions in
+/// compressed payloads may be missed.
+pub fn classify(storage: &Storage, update: &Update) -> Result<Vec<Kind>, Error> {
+    let new = match update.new {
+        Some(new) => new,
+        None => return Ok(vec![]),
+    };
+    let path = match &update.urn.path {
+        Some(path) => path.as_str(),
+        None => return Ok(vec![]),
+    };
+    let (peer, rest) = match path
+        .strip_prefix("refs/remotes/")
+        .and_then(|rest| rest.split_once('/'))
+        .and_then(|(peer, rest)| Some((peer.parse::<PeerId>().ok()?, rest)))
+    {
+        Some((peer, _)) if &peer == storage.peer_id() => return Ok(vec![]),
+        Some(remote) => remote,
+        None => return Ok(vec![]),
+    };
+
+    let mut kinds = Vec::new();
+    let heads = format!("{}/", RefsCategory::Heads);
+    let cobs = format!("{}/", RefsCategory::Cobs);
+    if let Some(branch) = rest.strip_prefix(heads.as_str()) {
+        kinds.push(Kind::Head {
+            peer,
+            branch: branch.to_owned(),
+            old: update.old,
+            new,
+        });
+    } else if let Some((typename, object)) = rest
+        .strip_prefix(cobs.as_str())
+        .and_then(|rest| rest.split_once('/'))
+    {
+        if let Some(user) = storage.config()?.user()? {
+            if let Some(change) = mention(storage, &user, update.old, new)? {
+                kinds.push(Kind::Mention {
+                    peer,
+                    typename: typename.to_owned(),
+                    object: object.to_owned(),
+                    change,
+                });
+            }
+        }
+    } else if rest == "rad/id" {
+        let local = storage.peer_id().as_public_key();
+        if let Some(identity) = any::get(storage, &update.urn)? {
+            if needs_signature(&identity, local) {
+                kinds.push(Kind::Signature {
+                    peer,
+                    revision: new,
+                });
+            }
+        }
+    }
+
+    Ok(kinds)
+}
+
+/// The newest change between `old` and `new` which mentions `user`, and was
+/// not signed by the local peer.
+fn mention(
+    storage: &Storage,
+    user: &Urn,
+    old: Option<ext::Oid>,
+    new: ext::Oid,
+) -> Result<Option<ext::Oid>, Error> {
+    let repo = storage.as_raw();
+    let local = storage.peer_id().as_public_key();
+    let needle = user.encode_id();
+    let mut walk = repo.revwalk()?;
+    walk.push(new.into())?;
+    if let Some(old) = old {
+        walk.hide(old.into())?;
+    }
+    for oid in walk {
+        let oid = oid?;
+        let commit = repo.find_commit(oid)?;
+        let authored = Signatures::try_from(&commit)
+            .map(|sigs| sigs.iter().any(|(key, _)| key == local))
+            .unwrap_or(false);
+        if authored {
+            continue;
+        }
+        let blob = match commit.tree()?.get_name(CHANGE_BLOB_NAME) {
+            Some(entry) => entry.to_object(repo)?.peel_to_blob()?,
+            None => continue,
+        };
+        if contains(blob.content(), needle.as_bytes()) {
+            return Ok(Some(oid.into()));
+        }
+    }
+    Ok(None)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    !needle.is_empty() && haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// Whether `key` is a delegate of `identity`, but did not sign it.
+fn needs_signature(identity: &SomeIdentity, key: &PublicKey) -> bool {
+    let (eligible, signed) = match identity {
+        SomeIdentity::Person(person) => (
+            person
+                .eligible(BTreeSet::from([key]))
+                .map_or(false, |keys| !keys.is_empty()),
+            person.signatures.contains_key(key),
+        ),
+        SomeIdentity::Project(project) => (
+            project
+                .eligible(BTreeSet::from([key]))
+                .map_or(false, |keys| !keys.is_empty()),
+            project.signatures.contains_key(key),
+        ),
+    };
+    eligible && !signed
+}
@@ -24,8 +24,16 @@ pub struct Paths {
     git_dir: PathBuf,
     git_includes_dir: PathBuf,
     cob_cache_dir: PathBuf,
+    caches_dir: PathBuf,
     socket_dir: PathBuf,
     seeds_file: PathBuf,
+    working_copies_file: PathBuf,
+    tasks_journal_file: PathBuf,
+    inbox_file: PathBuf,
+    address_book_file: PathBuf,
+    mirrors_file: PathBuf,
+    mirrors_state_file: PathBuf,
+    tracking_policy_file: PathBuf,
     hooks_dir: PathBuf,
 }
 
@@ -45,8 +53,16 @@ impl Paths {
             git_dir: data_dir.join("git"),
             git_includes_dir: config_dir.join("git-includes"),
             cob_cache_dir: cache_dir.join("cob-cache"),
+            caches_dir: cache_dir.join("caches"),
             socket_dir: socket_dir()?,
             seeds_file: config_dir.join("seeds"),
+            working_copies_file: config_dir.join("working-copies.json"),
+            tasks_journal_file: data_dir.join("tasks.journal"),
+            inbox_file: data_dir.join("inbox.json"),
+            address_book_file: data_dir.join("addresses.json"),
+            mirrors_file: config_dir.join("mirrors.json"),
+            mirrors_state_file: data_dir.join("mirrors-state.json"),
+            tracking_policy_file: config_dir.join("tracking-policy.json"),
             hooks_dir: data_dir.join("hooks"),
         }
         .init()
@@ -61,8 +77,16 @@ impl Paths {
             git_dir: root.join("git"),
             git_includes_dir: root.join("git-includes"),
             cob_cache_dir: root.join("cob-cache"),
+            caches_dir: root.join("caches"),
             socket_dir: socket_dir()?,
             seeds_file: root.join("seeds"),
+            working_copies_file: root.join("working-copies.json"),
+            tasks_journal_file: root.join("tasks.journal"),
+            inbox_file: root.join("inbox.json"),
+            address_book_file: root.join("addresses.json"),
+            mirrors_file: root.join("mirrors.json"),
+            mirrors_state_file: root.join("mirrors-state.json"),
+            tracking_policy_file: root.join("tracking-policy.json"),
             hooks_dir: root.join("hooks"),
         }
         .init()
@@ -84,6 +108,11 @@ impl Paths {
         &self.cob_cache_dir
     }
 
+    /// Root directory of [`crate::cache::Cache`]s.
+    pub fn caches_dir(&self) -> &Path {
+        &self.caches_dir
+    }
+
     pub fn hooks_dir(&self) -> &Path {
         &self.hooks_dir
     }
@@ -96,9 +125,17 @@ impl Paths {
             git_dir,
             git_includes_dir,
             cob_cache_dir,
+            caches_dir,
             hooks_dir,
             socket_dir: _,
             seeds_file: _,
+            working_copies_file: _,
+            tasks_journal_file: _,
+            inbox_file: _,
+            address_book_file: _,
+            mirrors_file: _,
+            mirrors_state_file: _,
+            tracking_policy_file: _,
         } = self;
 
         vec![
@@ -106,6 +143,7 @@ impl Paths {
             git_dir.as_path(),
             git_includes_dir.as_path(),
             cob_cache_dir.as_path(),
+            caches_dir.as_path(),
             hooks_dir.as_path(),
         ]
         .into_iter()
@@ -129,6 +167,44 @@ impl Paths {
     pub fn seeds_file(&self) -> &Path {
         &self.seeds_file
     }
+
+    /// Registry of working copies created from the monorepo.
+    pub fn working_copies_file(&self) -> &Path {
+        &self.working_copies_file
+    }
+
+    /// Write-ahead journal of the tasks scheduled by a running node.
+    pub fn tasks_journal_file(&self) -> &Path {
+        &self.tasks_journal_file
+    }
+
+    /// Inbox of [`crate::notifications`] of the profile.
+    pub fn inbox_file(&self) -> &Path {
+        &self.inbox_file
+    }
+
+    /// Last known addresses of other peers, see
+    /// [`crate::net::protocol::address_book`].
+    pub fn address_book_file(&self) -> &Path {
+        &self.address_book_file
+    }
+
+    /// External remotes namespaces are mirrored to, see
+    /// [`crate::git::mirror`].
+    pub fn mirrors_file(&self) -> &Path {
+        &self.mirrors_file
+    }
+
+    /// Sync state of the [`Paths::mirrors_file`].
+    pub fn mirrors_state_file(&self) -> &Path {
+        &self.mirrors_state_file
+    }
+
+    /// Policy for tracking URNs automatically, see
+    /// [`crate::git::tracking::auto`].
+    pub fn tracking_policy_file(&self) -> &Path {
+        &self.tracking_policy_file
+    }
 }
 
 /// Returns [`ProjectDirs`] for this specific project (`radicle`).
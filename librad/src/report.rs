@@ -0,0 +1,34 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Serializable reports of the outcome of core operations.
+//!
+//! These types are intended for machine-readable output of command line tools
+//! and services, such that all of them expose the same structure. They are
+//! converted from the respective library types via [`From`].
+//!
+//! # Stability
+//!
+//! Field names (and the tags of enums) are part of the public interface:
+//!
+//! * Fields and enum variants may be added in minor releases. Hence, all types
+//!   are `#[non_exhaustive]`, and consumers of the serialized form should
+//!   ignore fields they don't know about.
+//! * Fields are never renamed or removed, and their types are not changed,
+//!   without a major release.
+//! * Field names are `snake_case`, enums are internally tagged by a `type`
+//!   field.
+
+pub mod identities;
+#[cfg(feature = "net")]
+pub mod replication;
+pub mod storage;
+pub mod tracking;
+
+pub use identities::Verification;
+#[cfg(feature = "net")]
+pub use replication::Replication;
+pub use storage::StorageStats;
+pub use tracking::Tracking;
@@ -0,0 +1,89 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use either::Either;
+use git_ext as ext;
+use serde::Serialize;
+
+use crate::{
+    git::Urn,
+    identities::git::{VerifiedPerson, VerifiedProject},
+    PeerId,
+};
+
+/// Report of a successfully verified identity.
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct Verification {
+    pub urn: Urn,
+    pub kind: Kind,
+    pub revision: ext::Oid,
+    pub content_id: ext::Oid,
+    pub delegates: Vec<Delegate>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Kind {
+    Person,
+    Project,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+#[non_exhaustive]
+pub enum Delegate {
+    /// A key delegation.
+    Key { peer: PeerId },
+    /// A delegation to a person identity, and its keys.
+    Person { urn: Urn, peers: Vec<PeerId> },
+}
+
+impl From<&VerifiedPerson> for Verification {
+    fn from(person: &VerifiedPerson) -> Self {
+        Self {
+            urn: person.urn(),
+            kind: Kind::Person,
+            revision: person.revision,
+            content_id: person.content_id,
+            delegates: person
+                .delegations()
+                .iter()
+                .map(|key| Delegate::Key {
+                    peer: PeerId::from(*key),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&VerifiedProject> for Verification {
+    fn from(project: &VerifiedProject) -> Self {
+        Self {
+            urn: project.urn(),
+            kind: Kind::Project,
+            revision: project.revision,
+            content_id: project.content_id,
+            delegates: project
+                .delegations()
+                .iter()
+                .map(|delegate| match delegate {
+                    Either::Left(key) => Delegate::Key {
+                        peer: PeerId::from(*key),
+                    },
+                    Either::Right(person) => Delegate::Person {
+                        urn: person.urn(),
+                        peers: person
+                            .delegations()
+                            .iter()
+                            .map(|key| PeerId::from(*key))
+                            .collect(),
+                    },
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::iter::FromIterator;
+
+use either::Either;
+use git_ext as ext;
+use git_ref_format::RefString;
+use serde::Serialize;
+
+use crate::{git::Urn, net::replication, PeerId};
+
+/// Report of a successful replication, cf. [`replication::Success`].
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct Replication {
+    pub references: References,
+    pub rejected: Rejected,
+    pub tracked: Tracked,
+    /// Top-level URNs created by the replication.
+    pub created: Vec<Urn>,
+    pub requires_confirmation: bool,
+    /// Post-validation errors.
+    pub validation: Vec<String>,
+    /// Batches of tracked peers which could not be fetched.
+    pub failed: Vec<FailedBatch>,
+}
+
+impl From<replication::Success> for Replication {
+    fn from(s: replication::Success) -> Self {
+        let created = s.urns_created().map(Urn::from).collect();
+        let validation = s.validation.iter().map(|e| e.to_string()).collect();
+        let failed = s
+            .failed
+            .into_iter()
+            .map(|batch| FailedBatch {
+                error: batch.source.to_string(),
+                peers: batch.peers,
+            })
+            .collect();
+        let references = s.applied.updated.into_iter().collect();
+        let rejected = s.applied.rejected.into_iter().collect();
+        let tracked = s
+            .tracked
+            .into_iter()
+            .map(|t| t.map_right(Urn::from))
+            .collect();
+        Self {
+            references,
+            rejected,
+            tracked,
+            created,
+            requires_confirmation: s.requires_confirmation,
+            validation,
+            failed,
+        }
+    }
+}
+
+/// Tracked peers which could not be fetched, and why.
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct FailedBatch {
+    pub peers: Vec<PeerId>,
+    pub error: String,
+}
+
+/// New tracking relationships established by a replication.
+#[derive(Clone, Debug, Default, Serialize)]
+#[non_exhaustive]
+pub struct Tracked {
+    pub direct: Vec<PeerId>,
+    pub indirect: Vec<Urn>,
+}
+
+impl FromIterator<Either<PeerId, Urn>> for Tracked {
+    fn from_iter<T: IntoIterator<Item = Either<PeerId, Urn>>>(iter: T) -> Self {
+        iter.into_iter().fold(Self::default(), |mut tracked, t| {
+            match t {
+                Either::Left(peer) => tracked.direct.push(peer),
+                Either::Right(urn) => tracked.indirect.push(urn),
+            }
+            tracked
+        })
+    }
+}
+
+/// Ref updates which were rejected.
+#[derive(Clone, Debug, Default, Serialize)]
+#[non_exhaustive]
+pub struct Rejected {
+    pub direct: Vec<Direct>,
+    pub symbolic: Vec<Symbolic>,
+    pub pruned: Vec<RefString>,
+}
+
+impl<'a> FromIterator<link_replication::Update<'a>> for Rejected {
+    fn from_iter<T: IntoIterator<Item = link_replication::Update<'a>>>(iter: T) -> Self {
+        iter.into_iter().fold(Self::default(), |mut rej, update| {
+            match update {
+                link_replication::Update::Direct { name, target, .. } => rej.direct.push(Direct {
+                    name: name.into(),
+                    target: target.into(),
+                }),
+                link_replication::Update::Symbolic { name, target, .. } => {
+                    rej.symbolic.push(Symbolic {
+                        name: name.into(),
+                        target: target.name.strip_namespace().into(),
+                    })
+                },
+                link_replication::Update::Prune { name, .. } => rej.pruned.push(name.into()),
+            }
+            rej
+        })
+    }
+}
+
+/// Refs which were created, updated, or pruned.
+#[derive(Clone, Debug, Default, Serialize)]
+#[non_exhaustive]
+pub struct References {
+    pub updated: Updates,
+    pub pruned: Vec<RefString>,
+}
+
+impl FromIterator<link_replication::Updated> for References {
+    fn from_iter<T: IntoIterator<Item = link_replication::Updated>>(iter: T) -> Self {
+        iter.into_iter().fold(Self::default(), |mut refs, update| {
+            match update {
+                link_replication::Updated::Direct { name, target } => {
+                    refs.updated.direct.push(Direct {
+                        name,
+                        target: target.into(),
+                    })
+                },
+                link_replication::Updated::Symbolic { name, target } => {
+                    refs.updated.symbolic.push(Symbolic { name, target })
+                },
+                link_replication::Updated::Prune { name } => refs.pruned.push(name),
+            }
+            refs
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[non_exhaustive]
+pub struct Updates {
+    pub direct: Vec<Direct>,
+    pub symbolic: Vec<Symbolic>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct Direct {
+    pub name: RefString,
+    pub target: ext::Oid,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct Symbolic {
+    pub name: RefString,
+    pub target: RefString,
+}
@@ -0,0 +1,47 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use serde::Serialize;
+
+use crate::git::{storage::quota, Urn};
+
+/// Report of the disk usage of the storage, cf. [`quota::usage`].
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct StorageStats {
+    /// Size of the object database, in bytes.
+    pub objects_bytes: u64,
+    /// Sum of [`Namespace::received_bytes`].
+    pub received_bytes: u64,
+    pub namespaces: Vec<Namespace>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct Namespace {
+    pub urn: Urn,
+    /// Bytes received when fetching the namespace.
+    pub received_bytes: u64,
+}
+
+impl From<quota::Usage> for StorageStats {
+    fn from(usage: quota::Usage) -> Self {
+        let namespaces = usage
+            .namespaces
+            .into_iter()
+            .map(|(urn, received_bytes)| Namespace {
+                urn,
+                received_bytes,
+            })
+            .collect::<Vec<_>>();
+        Self {
+            objects_bytes: usage.global,
+            received_bytes: namespaces
+                .iter()
+                .fold(0u64, |acc, ns| acc.saturating_add(ns.received_bytes)),
+            namespaces,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::iter::FromIterator;
+
+use link_canonical::Canonical as _;
+use serde::Serialize;
+
+use crate::{
+    git::{tracking, Urn},
+    PeerId,
+};
+
+/// Report of tracking entries, cf. [`tracking::tracked`].
+#[derive(Clone, Debug, Default, Serialize)]
+#[non_exhaustive]
+pub struct Tracking {
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[non_exhaustive]
+pub struct Entry {
+    pub urn: Urn,
+    /// `None` for the default entry of `urn`.
+    pub peer: Option<PeerId>,
+    /// The [`tracking::Config`], in the same form as it is stored.
+    pub config: serde_json::Value,
+}
+
+impl From<tracking::Tracked> for Entry {
+    fn from(tracked: tracking::Tracked) -> Self {
+        let (urn, peer, config) = match tracked {
+            tracking::Tracked::Default { urn, config } => (urn, None, config),
+            tracking::Tracked::Peer { urn, peer, config } => (urn, Some(peer), config),
+        };
+        let config = config
+            .canonical_form()
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or(serde_json::Value::Null);
+
+        Self { urn, peer, config }
+    }
+}
+
+impl FromIterator<tracking::Tracked> for Tracking {
+    fn from_iter<T: IntoIterator<Item = tracking::Tracked>>(iter: T) -> Self {
+        Self {
+            entries: iter.into_iter().map(Entry::from).collect(),
+        }
+    }
+}
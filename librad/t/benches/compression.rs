@@ -0,0 +1,93 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Bandwidth vs. CPU trade-off of compressing gossip messages.
+//!
+//! Benchmarks compressing and decompressing the encoding of gossip payloads of
+//! increasing size, at several zstd levels. The compression ratio of each
+//! input is printed once per level, so it can be related to the throughput
+//! reported by criterion.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use librad::{
+    git::Urn,
+    net::protocol::{
+        compression::{self, Config},
+        gossip,
+    },
+    reflike,
+};
+
+const LEVELS: [i32; 3] = [1, 3, 9];
+
+fn oid(n: usize) -> git2::Oid {
+    git2::Oid::hash_object(git2::ObjectType::Blob, &n.to_be_bytes()).unwrap()
+}
+
+/// The encoding of an announcement of `tips` tips, as produced by
+/// [`gossip::batch`].
+fn payload(tips: usize) -> Vec<u8> {
+    let payload = gossip::Payload {
+        urn: Urn::new(git_ext::Oid::from(oid(0))),
+        rev: Some(oid(1).into()),
+        origin: None,
+        cob: None,
+        batch: (tips > 1).then(|| {
+            (2..=tips)
+                .map(|n| gossip::Tip {
+                    path: Some(reflike!("refs/heads/main")),
+                    rev: oid(n).into(),
+                })
+                .collect()
+        }),
+    };
+    minicbor::to_vec(&payload).unwrap()
+}
+
+fn bench(c: &mut Criterion) {
+    let inputs = [1, 8, 64]
+        .iter()
+        .map(|tips| (*tips, payload(*tips)))
+        .collect::<Vec<_>>();
+
+    let mut compress = c.benchmark_group("compress");
+    for level in LEVELS {
+        let cfg = Config {
+            level,
+            ..Config::default()
+        };
+        for (tips, input) in &inputs {
+            let compressed = cfg.compress(input).unwrap();
+            println!(
+                "level {}, {} tips: {} -> {} bytes ({:.2})",
+                level,
+                tips,
+                input.len(),
+                compressed.len(),
+                compressed.len() as f64 / input.len() as f64
+            );
+            compress.throughput(Throughput::Bytes(input.len() as u64));
+            compress.bench_with_input(
+                BenchmarkId::new(format!("level-{}", level), tips),
+                input,
+                |b, input| b.iter(|| cfg.compress(input).unwrap()),
+            );
+        }
+    }
+    compress.finish();
+
+    let mut decompress = c.benchmark_group("decompress");
+    for (tips, input) in &inputs {
+        let compressed = Config::default().compress(input).unwrap();
+        decompress.throughput(Throughput::Bytes(input.len() as u64));
+        decompress.bench_with_input(BenchmarkId::from_parameter(tips), &compressed, |b, c| {
+            b.iter(|| compression::decompress(c).unwrap())
+        });
+    }
+    decompress.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -3,20 +3,29 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{collections::BTreeSet, iter};
+use std::{collections::BTreeSet, iter, net::SocketAddr, str::FromStr as _};
 
 use git_ref_format::RefString;
 use git_ref_format_test as git_ref;
-use librad::net::protocol::{
-    membership::PartialView,
-    request_pull,
-    PartialPeerInfo,
-    PeerAdvertisement,
+use librad::{
+    collaborative_objects::{ObjectId, TypeName},
+    net::protocol::{
+        broadcast,
+        gossip,
+        interrogation,
+        membership::{self, PartialView},
+        request_pull,
+        Capability,
+        PartialPeerInfo,
+        PeerAdvertisement,
+        PeerInfo,
+    },
 };
 use link_crypto::PeerId;
 use link_crypto_test::gen::gen_peer_id;
-use link_identities_test::gen::urn::gen_oid;
+use link_identities_test::gen::urn::{gen_oid, gen_urn};
 use proptest::{collection, prelude::*};
+use radicle_git_ext::RefLike;
 
 pub fn gen_partial_view() -> impl Strategy<Value = PartialView<rand::rngs::ThreadRng, ()>> {
     gen_peer_id().prop_flat_map(|local_id| {
@@ -60,3 +69,124 @@ pub fn gen_request_pull_success() -> impl Strategy<Value = request_pull::Success
             request_pull::Success { refs, pruned }
         })
 }
+
+pub fn gen_rev() -> impl Strategy<Value = gossip::Rev> {
+    gen_oid(git2::ObjectType::Commit).prop_map(gossip::Rev::from)
+}
+
+fn gen_path() -> impl Strategy<Value = RefLike> {
+    collection::vec("[a-z0-9]+", 1..3).prop_map(|elems| RefLike::try_from(elems.join("/")).unwrap())
+}
+
+fn gen_cob() -> impl Strategy<Value = gossip::Cob> {
+    (
+        "[a-z0-9]+(\\.[a-z0-9]+){0,3}",
+        gen_oid(git2::ObjectType::Commit),
+        collection::vec(gen_rev(), 0..3),
+    )
+        .prop_map(|(typename, object_id, tips)| gossip::Cob {
+            typename: TypeName::from_str(&typename).unwrap(),
+            object_id: ObjectId::from(object_id),
+            tips,
+        })
+}
+
+pub fn gen_payload() -> impl Strategy<Value = gossip::Payload> {
+    (
+        gen_urn(),
+        proptest::option::of(gen_rev()),
+        proptest::option::of(gen_peer_id()),
+        proptest::option::of(gen_cob()),
+        proptest::option::of(collection::vec(
+            (proptest::option::of(gen_path()), gen_rev())
+                .prop_map(|(path, rev)| gossip::Tip { path, rev }),
+            0..5,
+        )),
+    )
+        .prop_map(|(urn, rev, origin, cob, batch)| gossip::Payload {
+            urn,
+            rev,
+            origin,
+            cob,
+            batch,
+        })
+}
+
+pub fn gen_peer_advertisement() -> impl Strategy<Value = PeerAdvertisement<SocketAddr>> {
+    (
+        collection::vec(any::<SocketAddr>(), 0..16),
+        collection::btree_set(Just(Capability::Zstd), 0..2),
+    )
+        .prop_map(|(listen_addrs, capabilities)| {
+            let mut info = PeerAdvertisement {
+                listen_addrs: iter::empty().into(),
+                capabilities,
+            };
+            info.listen_addrs.extend_fill(listen_addrs);
+            info
+        })
+}
+
+pub fn gen_peer_info() -> impl Strategy<Value = PeerInfo<SocketAddr>> {
+    (
+        gen_peer_id(),
+        gen_peer_advertisement(),
+        collection::vec(any::<SocketAddr>(), 0..16),
+    )
+        .prop_map(|(peer_id, advertised_info, seen_addrs)| {
+            let mut info = PeerInfo {
+                peer_id,
+                advertised_info,
+                seen_addrs: iter::empty().into(),
+            };
+            info.seen_addrs.extend_fill(seen_addrs);
+            info
+        })
+}
+
+pub fn gen_gossip() -> impl Strategy<Value = broadcast::Message<SocketAddr, gossip::Payload>> {
+    (gen_peer_info(), gen_payload(), any::<bool>()).prop_map(|(origin, val, have)| {
+        if have {
+            broadcast::Message::have(origin, val)
+        } else {
+            broadcast::Message::want(origin, val)
+        }
+    })
+}
+
+/// Note that [`membership::Priority::High`] is not generated: it is encoded as
+/// nothing at all, and so can only be decoded when followed by more input.
+pub fn gen_membership() -> impl Strategy<Value = membership::Message<SocketAddr>> {
+    use membership::Message;
+
+    let peers = || collection::vec(gen_peer_info(), 0..5);
+    prop_oneof![
+        gen_peer_advertisement().prop_map(|info| Message::Join { info }),
+        (gen_peer_info(), any::<usize>())
+            .prop_map(|(joined, ttl)| Message::ForwardJoin { joined, ttl }),
+        gen_peer_advertisement().prop_map(|info| Message::Neighbour {
+            info,
+            prio: membership::Priority::Normal
+        }),
+        (gen_peer_info(), peers(), any::<usize>())
+            .prop_map(|(origin, peers, ttl)| Message::Shuffle { origin, peers, ttl }),
+        peers().prop_map(|peers| Message::ShuffleReply { peers }),
+        Just(Message::Disconnect),
+    ]
+}
+
+pub fn gen_interrogation_request() -> impl Strategy<Value = interrogation::Request> {
+    use interrogation::Request;
+
+    prop_oneof![
+        Just(Request::GetAdvertisement),
+        Just(Request::EchoAddr),
+        Just(Request::GetUrns),
+        gen_urn().prop_map(Request::GetSigrefTips),
+        (gen_urn(), any::<bool>()).prop_map(|(urn, x)| Request::GetDigest(urn, x)),
+    ]
+}
+
+pub fn gen_request_pull_request() -> impl Strategy<Value = request_pull::Request> {
+    gen_urn().prop_map(|urn| request_pull::Request { urn })
+}
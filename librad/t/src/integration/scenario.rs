@@ -3,12 +3,16 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+mod attestations;
 mod collaboration;
 mod collaborative_objects;
 mod default_branch_head;
+mod interrupted_replication;
 mod menage;
 mod passive_replication;
 mod prune;
+mod simulation;
+mod tags_and_notes;
 mod tracked_references;
 mod tracking_policy;
 mod updated_delegate;
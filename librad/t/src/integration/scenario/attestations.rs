@@ -0,0 +1,112 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::ops::Index as _;
+
+use it_helpers::{fixed::TestProject, testnet};
+use librad::git::{
+    identities,
+    refs::attestation,
+    storage::ReadOnlyStorage as _,
+    tracking,
+    types::{Namespace, Reference},
+};
+use test_helpers::logging;
+
+fn config() -> testnet::Config {
+    testnet::Config {
+        num_peers: nonzero!(2usize),
+        min_connected: 2,
+        bootstrap: testnet::Bootstrap::from_env(),
+    }
+}
+
+/// Attestations published by one peer are visible to the attested peer.
+///
+/// - Create a project in peer1s storage, and make peer1 track peer2
+/// - Pull the project from peer1 to peer2
+/// - Make peer2 attest the signed refs of peer1
+/// - Pull the project from peer2 to peer1
+/// - Assert that peer1 counts peer2 as a witness of its state
+#[test]
+fn attestations_are_replicated() {
+    logging::init();
+
+    let net = testnet::run(config()).unwrap();
+    net.enter(async {
+        let peer1 = net.peers().index(0);
+        let peer2 = net.peers().index(1);
+        let peer1_id = peer1.peer_id();
+        let peer2_id = peer2.peer_id();
+
+        let proj = peer1
+            .using_storage(TestProject::create)
+            .await
+            .unwrap()
+            .unwrap();
+        let urn = proj.project.urn();
+
+        peer1
+            .using_storage({
+                let urn = urn.clone();
+                move |storage| {
+                    let id = identities::local::load(storage, urn.clone())
+                        .unwrap()
+                        .unwrap();
+                    id.link(storage, &urn).unwrap();
+                    assert!(tracking::track(
+                        storage,
+                        &urn,
+                        Some(peer2_id),
+                        tracking::Config::default(),
+                        tracking::policy::Track::Any,
+                    )
+                    .unwrap()
+                    .is_ok());
+                }
+            })
+            .await
+            .unwrap();
+
+        proj.pull(peer1, peer2).await.unwrap();
+
+        let tip = peer2
+            .using_storage({
+                let urn = urn.clone();
+                move |storage| {
+                    let tip = storage
+                        .reference_oid(&Reference::rad_signed_refs(Namespace::from(&urn), peer1_id))
+                        .unwrap();
+                    let created = attestation::attest(storage, &urn).unwrap();
+                    assert_eq!(created.len(), 1);
+                    assert_eq!(created[0].peer, peer1_id);
+                    assert_eq!(created[0].signed_refs, tip);
+
+                    // Nothing changed, so nothing to attest
+                    assert!(attestation::attest(storage, &urn).unwrap().is_empty());
+                    assert_eq!(
+                        attestation::count(storage, &urn, &peer1_id, tip).unwrap(),
+                        1
+                    );
+                    tip
+                }
+            })
+            .await
+            .unwrap();
+
+        proj.pull(peer2, peer1).await.unwrap();
+
+        let witnesses = peer1
+            .using_storage(move |storage| {
+                attestation::witnesses(storage, &urn, &peer1_id, tip).unwrap()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            witnesses.into_iter().map(|w| w.peer).collect::<Vec<_>>(),
+            vec![peer2_id]
+        );
+    })
+}
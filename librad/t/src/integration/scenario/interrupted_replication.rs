@@ -0,0 +1,64 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::ops::Index as _;
+
+use it_helpers::{fixed::TestProject, testnet};
+use librad::git::storage::{
+    faults::{self, Faults, Op, Rule},
+    ReadOnlyStorage as _,
+};
+use test_helpers::logging;
+
+fn config() -> testnet::Config {
+    testnet::Config {
+        num_peers: nonzero!(2usize),
+        min_connected: 2,
+        bootstrap: testnet::Bootstrap::from_env(),
+    }
+}
+
+fn faulty(op: Op) {
+    logging::init();
+
+    let net = testnet::run(config()).unwrap();
+    net.enter(async {
+        let peer1 = net.peers().index(0);
+        let peer2 = net.peers().index(1);
+
+        let proj = peer1
+            .using_storage(TestProject::create)
+            .await
+            .unwrap()
+            .unwrap();
+        let urn = proj.project.urn();
+        let has_urn = || {
+            let urn = urn.clone();
+            peer2.using_storage(move |storage| storage.has_urn(&urn).unwrap())
+        };
+
+        let paths = peer2.protocol_config().paths.clone();
+        let faults = Faults::new().with(Rule::fail(op));
+        faults::install(&paths, faults.clone());
+
+        proj.pull(peer1, peer2).await.ok();
+        assert!(!faults.injected().is_empty(), "no fault was injected");
+        assert!(!has_urn().await.unwrap(), "partial replication is visible");
+
+        faults::uninstall(&paths);
+        proj.pull(peer1, peer2).await.unwrap();
+        assert!(has_urn().await.unwrap());
+    })
+}
+
+#[test]
+fn failed_ref_update() {
+    faulty(Op::RefUpdate)
+}
+
+#[test]
+fn failed_object_write() {
+    faulty(Op::ObjectWrite)
+}
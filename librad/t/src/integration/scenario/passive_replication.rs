@@ -64,6 +64,8 @@ fn can_replicate_from_tracking() {
         peer1
             .announce(gossip::Payload {
                 origin: None,
+                cob: None,
+                batch: None,
                 urn: proj.project.urn(),
                 rev: None,
             })
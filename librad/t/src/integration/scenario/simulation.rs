@@ -0,0 +1,105 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::time::Duration;
+
+use it_helpers::simnet::{Config, Faults, Simnet};
+use test_helpers::logging;
+
+const CONVERGENCE_TIMEOUT: Duration = Duration::from_secs(600);
+
+fn bootstrapped(config: Config) -> Simnet {
+    let mut net = Simnet::new(config);
+    net.join_prev();
+    assert!(
+        net.run_until(CONVERGENCE_TIMEOUT, Simnet::is_connected),
+        "membership did not converge"
+    );
+    net
+}
+
+#[test]
+fn same_seed_same_trace() {
+    logging::init();
+
+    let run = || {
+        let mut net = bootstrapped(Config {
+            seed: 42,
+            faults: Faults {
+                loss: 0.1,
+                ..Faults::default()
+            },
+            ..Config::default()
+        });
+        net.run_for(Duration::from_secs(120));
+        net.trace().to_vec()
+    };
+
+    let trace = run();
+    assert!(!trace.is_empty());
+    assert_eq!(trace, run())
+}
+
+#[test]
+fn converges_despite_loss() {
+    logging::init();
+
+    let mut net = bootstrapped(Config {
+        seed: 7,
+        faults: Faults {
+            loss: 0.3,
+            ..Faults::default()
+        },
+        ..Config::default()
+    });
+    net.run_for(Duration::from_secs(300));
+    assert!(net.is_connected())
+}
+
+#[test]
+fn partition_heals() {
+    logging::init();
+
+    let mut net = bootstrapped(Config {
+        seed: 1,
+        ..Config::default()
+    });
+    let peers = net.peers();
+    let (left, right) = peers.split_at(peers.len() / 2);
+
+    net.partition([left.to_vec(), right.to_vec()]);
+    net.run_for(Duration::from_secs(120));
+    assert!(!net.is_connected());
+
+    assert!(right.iter().all(|peer| {
+        let active = net.peer(peer).unwrap().membership().active();
+        left.iter().all(|remote| !active.contains(remote))
+    }));
+
+    net.heal();
+    assert!(net.run_until(CONVERGENCE_TIMEOUT, Simnet::is_connected))
+}
+
+#[test]
+fn survives_crash() {
+    logging::init();
+
+    let mut net = bootstrapped(Config {
+        seed: 3,
+        ..Config::default()
+    });
+    let peers = net.peers();
+
+    net.crash(&peers[0]);
+    assert!(net.run_until(CONVERGENCE_TIMEOUT, Simnet::is_connected));
+
+    net.restart(&peers[0]);
+    net.join(&peers[0], &peers[1]);
+    assert!(net.run_until(CONVERGENCE_TIMEOUT, Simnet::is_connected));
+    assert!(!net
+        .peer(&peers[0])
+        .unwrap()
+        .membership()
+        .active()
+        .is_empty())
+}
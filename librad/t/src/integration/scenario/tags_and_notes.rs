@@ -0,0 +1,121 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::ops::Index as _;
+
+use it_helpers::{fixed::TestProject, testnet};
+use librad::{
+    git::{refs::Refs, util::quick_commit},
+    git_ext::tree,
+    reflike,
+};
+use test_helpers::logging;
+
+fn config() -> testnet::Config {
+    testnet::Config {
+        num_peers: nonzero!(2usize),
+        min_connected: 2,
+        bootstrap: testnet::Bootstrap::from_env(),
+    }
+}
+
+/// Tags and notes are part of the signed refs, and are replicated like
+/// branches:
+///
+/// - Create a project on peer1, with a commit on `master`
+/// - Tag the commit (annotated), and attach a note to it
+/// - Pull the project from peer1 to peer2
+/// - Assert that peer1's tag and notes refs exist in peer2's storage, and are
+///   listed in peer1's signed refs as seen by peer2
+#[test]
+fn replicates_tags_and_notes() {
+    logging::init();
+
+    let net = testnet::run(config()).unwrap();
+    net.enter(async {
+        let peer1 = net.peers().index(0);
+        let peer2 = net.peers().index(1);
+
+        let proj = peer1
+            .using_storage(TestProject::create)
+            .await
+            .unwrap()
+            .unwrap();
+        let urn = proj.project.urn();
+
+        let (tag, notes) = peer1
+            .using_storage({
+                let urn = urn.clone();
+                move |storage| {
+                    let commit = quick_commit(
+                        storage,
+                        &urn.clone().with_path(reflike!("refs/heads/master")),
+                        vec![("HI", tree::blob(b"Hi Bob"))].into_iter().collect(),
+                        "say hi to bob",
+                    )
+                    .unwrap();
+
+                    let repo = git2::Repository::open(storage.path()).unwrap();
+                    let namespace = format!("refs/namespaces/{}", urn.encode_id());
+                    let sig = repo.signature().unwrap();
+                    let tag = repo
+                        .tag_annotation_create(
+                            "v1.0",
+                            &repo.find_object(commit, None).unwrap(),
+                            &sig,
+                            "v1.0",
+                        )
+                        .unwrap();
+                    repo.reference(&format!("{}/refs/tags/v1.0", namespace), tag, false, "")
+                        .unwrap();
+                    let notes_ref = format!("{}/refs/notes/commits", namespace);
+                    repo.note(&sig, &sig, Some(&notes_ref), commit, "noted", false)
+                        .unwrap();
+                    let notes = repo.refname_to_id(&notes_ref).unwrap();
+
+                    Refs::update(storage, &urn).unwrap();
+                    (tag, notes)
+                }
+            })
+            .await
+            .unwrap();
+
+        proj.pull(peer1, peer2).await.unwrap();
+
+        peer2
+            .using_storage({
+                let peer1_id = peer1.peer_id();
+                move |storage| {
+                    let repo = git2::Repository::open(storage.path()).unwrap();
+                    let remote = format!(
+                        "refs/namespaces/{}/refs/remotes/{}",
+                        urn.encode_id(),
+                        peer1_id
+                    );
+                    assert_eq!(
+                        repo.refname_to_id(&format!("{}/tags/v1.0", remote))
+                            .unwrap(),
+                        tag
+                    );
+                    assert_eq!(
+                        repo.refname_to_id(&format!("{}/notes/commits", remote))
+                            .unwrap(),
+                        notes
+                    );
+
+                    let refs = Refs::load(storage, &urn, Some(peer1_id)).unwrap().unwrap();
+                    assert!(refs
+                        .tags()
+                        .any(|(name, oid)| name.as_str() == "v1.0" && git2::Oid::from(oid) == tag));
+                    assert!(refs
+                        .notes()
+                        .any(|(name, oid)| name.as_str() == "commits"
+                            && git2::Oid::from(oid) == notes));
+                }
+            })
+            .await
+            .unwrap();
+    })
+}
@@ -282,6 +282,8 @@ fn ignore_transitive_tracking() {
                         tracking::Config {
                             data: false,
                             cobs: tracking::config::cobs::Cobs::deny_all(),
+                            refs: None,
+                            expires: None,
                         },
                         tracking::policy::Track::Any,
                     )?
@@ -174,6 +174,8 @@ where
 
     peer.announce(gossip::Payload {
         origin: None,
+        cob: None,
+        batch: None,
         urn: project
             .urn()
             .with_path(Some(master.into_refstring().into())),
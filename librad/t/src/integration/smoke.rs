@@ -7,5 +7,10 @@ mod clone;
 mod fetch_limit;
 mod gossip;
 mod interrogation;
+mod introspection;
+mod lifecycle;
 mod regression;
 mod request_pull;
+mod request_push;
+mod smart;
+mod support;
@@ -3,7 +3,7 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::ops::Index as _;
+use std::{ops::Index as _, time::Duration};
 
 use it_helpers::{
     fixed::TestProject,
@@ -16,6 +16,9 @@ use librad::{
         storage::ReadOnlyStorage as _,
         types::{Namespace, Reference},
     },
+    net::peer::client::{self, failover},
+    PeerId,
+    SecretKey,
 };
 use test_helpers::logging;
 
@@ -132,6 +135,64 @@ fn when_disconnected_and_no_addr_hints() {
     assert!(matches!(res, Err(e) if e.to_string().starts_with("unable to obtain connection to")));
 }
 
+#[test]
+fn fails_over_to_reachable_peer() {
+    logging::init();
+
+    let net = testnet::run(disconnected_config()).unwrap();
+    net.enter(async {
+        let host = Host::init(&net.peers()[0]).await;
+        let leecher = &net.peers()[1];
+        let urn = host.project.project.urn();
+        let host_id = host.peer.peer_id();
+        let unreachable = PeerId::from(SecretKey::new());
+
+        let served = leecher
+            .client()
+            .unwrap()
+            .replicate_any(
+                vec![
+                    (unreachable, vec![]),
+                    (host_id, host.peer.listen_addrs().to_vec()),
+                ],
+                urn.clone(),
+                None,
+                failover::Policy::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(served.peer, host_id);
+        assert_eq!(
+            served
+                .failures
+                .iter()
+                .map(|failure| failure.peer)
+                .collect::<Vec<_>>(),
+            vec![unreachable]
+        );
+
+        let err = leecher
+            .client()
+            .unwrap()
+            .replicate_any(
+                vec![(unreachable, vec![])],
+                urn,
+                None,
+                failover::Policy {
+                    max_attempts: 2,
+                    initial_backoff: Duration::from_millis(10),
+                    ..failover::Policy::default()
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            client::error::ReplicateAny::Exhausted { failures, .. } if failures.len() == 2
+        ));
+    })
+}
+
 struct Host<'a> {
     project: TestProject,
     peer: &'a RunningTestPeer,
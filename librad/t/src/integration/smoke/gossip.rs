@@ -120,6 +120,8 @@ fn fetches_on_gossip_notify() {
         peer1
             .announce(gossip::Payload {
                 origin: None,
+                cob: None,
+                batch: None,
                 urn: project
                     .urn()
                     .with_path(Some(mastor.into_refstring().into())),
@@ -129,6 +131,8 @@ fn fetches_on_gossip_notify() {
         peer1
             .announce(gossip::Payload {
                 origin: None,
+                cob: None,
+                batch: None,
                 urn: project.urn().with_path(reflike!("refs/tags/MY-TAG")),
                 rev: Some(Rev::Git(tag_id)),
             })
@@ -8,6 +8,11 @@ use std::{ops::Index as _, time::Duration};
 use it_helpers::{fixed::TestProject, testnet};
 use librad::{
     data::BoundedVec,
+    git::{
+        storage::{digest, ReadOnlyStorage as _},
+        types::{Namespace, Reference},
+        Urn,
+    },
     identities::SomeUrn,
     net::protocol::{
         event::{self, upstream::predicate},
@@ -82,5 +87,47 @@ fn responds() {
         for urn in &[SomeUrn::Git(project.urn()), SomeUrn::Git(owner.urn())] {
             assert!(urns.contains(urn), "{} not in set", urn)
         }
+
+        let tips = interrogation.sigref_tips(project.urn()).await.unwrap();
+        let expected = responder
+            .using_storage({
+                let urn = project.urn();
+                move |storage| {
+                    storage.reference_oid(&Reference::rad_signed_refs(Namespace::from(&urn), None))
+                }
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(tips.get(&responder.peer_id()), Some(&expected));
+        let unknown = Urn::new(git2::Oid::zero().into());
+        assert!(interrogation
+            .sigref_tips(unknown.clone())
+            .await
+            .unwrap()
+            .is_empty());
+
+        let summary = interrogation
+            .digest(project.urn(), true)
+            .await
+            .unwrap()
+            .unwrap();
+        let expected = responder
+            .using_storage({
+                let urn = project.urn();
+                move |storage| {
+                    let opts = digest::Options {
+                        sigrefs: true,
+                        hide: vec![],
+                    };
+                    digest::summary(storage, &urn, &opts)
+                }
+            })
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(summary, expected);
+        assert!(interrogation.digest(unknown, true).await.unwrap().is_none());
     })
 }
@@ -0,0 +1,42 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::ops::Index as _;
+
+use it_helpers::testnet;
+use librad::net::peer::Introspection;
+use test_helpers::logging;
+
+fn config() -> testnet::Config {
+    testnet::Config {
+        num_peers: nonzero!(2usize),
+        min_connected: 2,
+        bootstrap: testnet::Bootstrap::from_env(),
+    }
+}
+
+#[test]
+fn reports_connections() {
+    logging::init();
+
+    let net = testnet::run(config()).unwrap();
+    net.enter(async {
+        let peer = net.peers().index(0);
+        let other = net.peers().index(1);
+        let snapshot = peer.introspect().await;
+
+        assert_eq!(snapshot.peer_id, peer.peer_id());
+        assert!(snapshot.membership.active.contains(&other.peer_id()));
+        assert!(snapshot
+            .connections
+            .iter()
+            .any(|conn| conn.peer == other.peer_id()));
+        assert!(snapshot.fetches.is_empty());
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let parsed: Introspection = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.connections.len(), snapshot.connections.len());
+    })
+}
@@ -0,0 +1,61 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{ops::Index as _, time::Duration};
+
+use futures::StreamExt as _;
+use it_helpers::{fixed::TestProject, testnet};
+use librad::net::protocol::event::upstream::Lifecycle;
+use test_helpers::logging;
+
+fn config() -> testnet::Config {
+    testnet::Config {
+        num_peers: nonzero!(2usize),
+        min_connected: 2,
+        bootstrap: testnet::Bootstrap::from_env(),
+    }
+}
+
+#[test]
+fn replication_emits_lifecycle_events() {
+    logging::init();
+
+    let net = testnet::run(config()).unwrap();
+    net.enter(async {
+        let peer1 = net.peers().index(0);
+        let peer2 = net.peers().index(1);
+        let peer1_id = peer1.peer_id();
+
+        let proj = peer1
+            .using_storage(TestProject::create)
+            .await
+            .unwrap()
+            .unwrap();
+        let urn = proj.project.urn();
+
+        let events = peer2.lifecycle();
+        proj.pull(peer1, peer2).await.unwrap();
+
+        let events = link_async::timeout(
+            Duration::from_secs(5),
+            events
+                .take_while(|evt| {
+                    futures::future::ready(!matches!(evt, Lifecycle::Replicated { .. }))
+                })
+                .collect::<Vec<_>>(),
+        )
+        .await
+        .unwrap();
+
+        assert!(events.iter().any(|evt| matches!(
+            evt,
+            Lifecycle::SignedRefsUpdated { urn: u, peer } if *u == urn && *peer == peer1_id
+        )));
+        assert!(events.iter().any(|evt| matches!(
+            evt,
+            Lifecycle::IdentityUpdated { urn: u, peer: Some(peer) } if *u == urn && *peer == peer1_id
+        )));
+    })
+}
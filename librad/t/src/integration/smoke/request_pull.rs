@@ -1,12 +1,24 @@
 // Copyright © 2022 The Radicle Link Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use std::ops::Index as _;
+use std::{collections::BTreeSet, ops::Index as _, sync::Arc};
 
 use futures::StreamExt as _;
 
 use it_helpers::{fixed::TestProject, testnet};
-use librad::{git::storage::ReadOnlyStorage as _, net::protocol::request_pull::Response};
+use librad::{
+    git::storage::ReadOnlyStorage as _,
+    identities::payload,
+    net::{
+        protocol::{
+            request_pull::{Phase, Response},
+            rpc::client::RequestPull,
+        },
+        tcp,
+        Network,
+    },
+};
+use link_async::Spawner;
 use test_helpers::logging;
 
 fn peer_and_client() -> testnet::Config {
@@ -70,6 +82,112 @@ fn responds_peer_and_client() {
     })
 }
 
+/// The responder replicates from the requester over the same TCP connection
+/// the request was sent on.
+#[test]
+fn responds_over_tcp() {
+    logging::init();
+
+    let net = testnet::run(peer_and_client()).unwrap();
+    net.enter(async {
+        let responder = net.peers().index(0);
+        let requester = testnet::TestClient::init().await.unwrap();
+        let TestProject { project, .. } = {
+            requester
+                .using_storage(TestProject::create)
+                .await
+                .unwrap()
+                .unwrap()
+        };
+
+        let (conn, incoming) = tcp::connect(
+            requester.signer().clone(),
+            Network::Custom(b"localtestnet".as_ref().into()),
+            responder.peer_id(),
+            responder.tcp_listen_addr(),
+        )
+        .await
+        .unwrap();
+        let mut rp = RequestPull::tcp(
+            conn,
+            incoming,
+            project.urn(),
+            Arc::new(requester.paths().clone()),
+            Arc::new(Spawner::from_current().unwrap()),
+        );
+
+        while let Some(resp) = rp.next().await {
+            match resp.unwrap() {
+                Response::Error(e) => panic!("request-pull failed: {}", e.message),
+                Response::Progress(p) => tracing::debug!(progress = %p.message, "making progress"),
+                Response::Success(_) => break,
+            }
+        }
+
+        let pulled = responder
+            .using_read_only({
+                let urn = project.urn();
+                move |storage| storage.has_urn(&urn)
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(pulled, "responder does not have project");
+    })
+}
+
+#[test]
+fn reports_phases() {
+    logging::init();
+
+    let net = testnet::run(peer_and_client()).unwrap();
+    net.enter(async {
+        let responder = net.peers().index(0);
+        let requester = testnet::TestClient::init().await.unwrap();
+        let TestProject { project, .. } = {
+            requester
+                .using_storage(TestProject::create)
+                .await
+                .unwrap()
+                .unwrap()
+        };
+
+        let mut rp = requester
+            .request_pull(
+                (responder.peer_id(), responder.listen_addrs().to_vec()),
+                project.urn(),
+            )
+            .await
+            .unwrap();
+
+        let mut phases = Vec::new();
+        while let Some(Ok(resp)) = rp.next().await {
+            match resp {
+                Response::Error(e) => panic!("request-pull failed: {}", e.message),
+                Response::Progress(p) => phases.extend(p.phase),
+                Response::Success(_) => break,
+            }
+        }
+
+        assert_eq!(phases[..2], [Phase::Authorizing, Phase::Replicating]);
+        let negotiated = phases
+            .iter()
+            .position(|phase| matches!(phase, Phase::Negotiated { refs } if *refs > 0))
+            .expect("no refs negotiated");
+        let receiving = phases
+            .iter()
+            .position(|phase| {
+                matches!(
+                    phase,
+                    Phase::Receiving { objects, bytes, .. } if *objects > 0 && *bytes > 0
+                )
+            })
+            .expect("no objects received");
+        assert!(negotiated < receiving);
+    })
+}
+
 #[test]
 fn responds_peer_and_peer() {
     logging::init();
@@ -116,3 +234,68 @@ fn responds_peer_and_peer() {
         assert!(pulled, "responder does not have project");
     })
 }
+
+#[test]
+fn responds_batch() {
+    logging::init();
+
+    let net = testnet::run(peer_and_peer()).unwrap();
+    net.enter(async {
+        let responder = net.peers().index(0);
+        let requester = net.peers().index(1);
+        let urns = requester
+            .using_storage(|storage| -> anyhow::Result<BTreeSet<_>> {
+                let TestProject { owner, project } = TestProject::create(storage)?;
+                let other = TestProject::from_project_payload(
+                    storage,
+                    owner,
+                    payload::Project {
+                        name: "radicle-surf".into(),
+                        description: None,
+                        default_branch: Some("main".into()),
+                    },
+                )?;
+                Ok([project.urn(), other.project.urn()].into_iter().collect())
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut rp = requester
+            .client()
+            .unwrap()
+            .request_pull_batch(
+                (responder.peer_id(), responder.listen_addrs().to_vec()),
+                urns.clone(),
+            )
+            .await
+            .unwrap();
+
+        let mut succeeded = BTreeSet::new();
+        while let Some(item) = rp.next().await {
+            let (urn, resp) = item.unwrap();
+            match resp {
+                Response::Error(e) => panic!("request-pull failed for {}: {}", urn, e.message),
+                Response::Progress(p) => {
+                    tracing::debug!(%urn, progress = %p.message, "making progress")
+                },
+                Response::Success(_) => {
+                    succeeded.insert(urn);
+                },
+            }
+        }
+        assert_eq!(succeeded, urns);
+
+        for urn in urns {
+            let pulled = responder
+                .using_read_only({
+                    let urn = urn.clone();
+                    move |storage| storage.has_urn(&urn)
+                })
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(pulled, "responder does not have {}", urn);
+        }
+    })
+}
@@ -0,0 +1,166 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::ops::Index as _;
+
+use futures::{Stream, StreamExt as _};
+
+use it_helpers::{
+    fixed::TestProject,
+    testnet::{self, RunningTestPeer},
+};
+use librad::{
+    git::{
+        storage::ReadOnlyStorage as _,
+        types::{Namespace, Reference},
+        Urn,
+    },
+    net::protocol::request_pull::{Response, Success},
+    PeerId,
+};
+use test_helpers::logging;
+
+fn config() -> testnet::Config {
+    testnet::Config {
+        num_peers: nonzero!(3usize),
+        min_connected: 3,
+        bootstrap: testnet::Bootstrap::from_env(),
+    }
+}
+
+async fn outcome<S, E>(mut responses: S) -> Result<Success, String>
+where
+    S: Stream<Item = Result<Response, E>> + Unpin,
+    E: std::fmt::Debug,
+{
+    while let Some(resp) = responses.next().await {
+        match resp.unwrap() {
+            Response::Error(e) => return Err(e.message),
+            Response::Progress(p) => tracing::debug!(progress = %p.message, "making progress"),
+            Response::Success(s) => return Ok(s),
+        }
+    }
+    panic!("response stream ended prematurely")
+}
+
+async fn request_pull(from: &RunningTestPeer, to: &RunningTestPeer, urn: Urn) {
+    let rp = from
+        .client()
+        .unwrap()
+        .request_pull((to.peer_id(), to.listen_addrs().to_vec()), urn)
+        .await
+        .unwrap();
+    if let Err(e) = outcome(rp).await {
+        panic!("request-pull failed: {}", e)
+    }
+}
+
+async fn request_push(
+    from: &RunningTestPeer,
+    to: &RunningTestPeer,
+    urn: Urn,
+) -> Result<Success, String> {
+    let rp = from
+        .client()
+        .unwrap()
+        .request_push((to.peer_id(), to.listen_addrs().to_vec()), urn)
+        .await
+        .unwrap();
+    outcome(rp).await
+}
+
+async fn has_signed_refs(peer: &RunningTestPeer, urn: &Urn, of: Option<PeerId>) -> bool {
+    peer.using_read_only({
+        let sigrefs = Reference::rad_signed_refs(Namespace::from(urn), of);
+        move |storage| storage.has_ref(&sigrefs)
+    })
+    .await
+    .unwrap()
+    .unwrap()
+}
+
+#[test]
+fn accepts_delegate() {
+    logging::init();
+
+    let net = testnet::run(config()).unwrap();
+    net.enter(async {
+        let responder = net.peers().index(0);
+        let maintainer = net.peers().index(1);
+        let TestProject { project, .. } = maintainer
+            .using_storage(TestProject::create)
+            .await
+            .unwrap()
+            .unwrap();
+        let urn = project.urn();
+
+        request_pull(maintainer, responder, urn.clone()).await;
+        if let Err(e) = request_push(maintainer, responder, urn.clone()).await {
+            panic!("request-push failed: {}", e)
+        }
+        assert!(has_signed_refs(responder, &urn, Some(maintainer.peer_id())).await);
+    })
+}
+
+#[test]
+fn rejects_unknown_urn() {
+    logging::init();
+
+    let net = testnet::run(config()).unwrap();
+    net.enter(async {
+        let responder = net.peers().index(0);
+        let maintainer = net.peers().index(1);
+        let TestProject { project, .. } = maintainer
+            .using_storage(TestProject::create)
+            .await
+            .unwrap()
+            .unwrap();
+        let urn = project.urn();
+
+        let err = request_push(maintainer, responder, urn.clone())
+            .await
+            .unwrap_err();
+        assert!(err.contains("is not known"), "unexpected error: {}", err);
+
+        let known = responder
+            .using_read_only({
+                let urn = urn.clone();
+                move |storage| storage.has_urn(&urn)
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!known, "responder fetched an unknown urn");
+    })
+}
+
+#[test]
+fn rejects_non_delegate_before_fetching() {
+    logging::init();
+
+    let net = testnet::run(config()).unwrap();
+    net.enter(async {
+        let responder = net.peers().index(0);
+        let maintainer = net.peers().index(1);
+        let contributor = net.peers().index(2);
+        let TestProject { project, .. } = maintainer
+            .using_storage(TestProject::create)
+            .await
+            .unwrap()
+            .unwrap();
+        let urn = project.urn();
+
+        request_pull(maintainer, responder, urn.clone()).await;
+        request_pull(maintainer, contributor, urn.clone()).await;
+        assert!(has_signed_refs(contributor, &urn, None).await);
+
+        let err = request_push(contributor, responder, urn.clone())
+            .await
+            .unwrap_err();
+        assert!(err.contains("not a delegate"), "unexpected error: {}", err);
+        assert!(
+            !has_signed_refs(responder, &urn, Some(contributor.peer_id())).await,
+            "refs of a non-delegate were fetched"
+        );
+    })
+}
@@ -0,0 +1,87 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::ops::Index as _;
+
+use it_helpers::{fixed::TestProject, testnet};
+use librad::{
+    data::NonEmptyVec,
+    git::{
+        storage::ReadOnlyStorage as _,
+        types::{Namespace, Reference},
+    },
+    net::protocol::rpc::client::smart::{packwriter, Ref},
+};
+use test_helpers::logging;
+
+fn config() -> testnet::Config {
+    testnet::Config {
+        num_peers: nonzero!(2usize),
+        min_connected: 2,
+        bootstrap: testnet::Bootstrap::from_env(),
+    }
+}
+
+#[test]
+fn ls_refs_and_fetch() {
+    logging::init();
+
+    let net = testnet::run(config()).unwrap();
+    net.enter(async {
+        let responder = net.peers().index(0);
+        let requester = net.peers().index(1);
+        let TestProject { project, .. } = responder
+            .using_storage(TestProject::create)
+            .await
+            .unwrap()
+            .unwrap();
+        let urn = project.urn();
+
+        let smart = requester
+            .client()
+            .unwrap()
+            .smart((responder.peer_id(), responder.listen_addrs().to_vec()))
+            .await
+            .unwrap();
+        assert_eq!(smart.remote_peer(), responder.peer_id());
+
+        let refs = smart.ls_refs(&urn, vec!["refs/rad/id"]).await.unwrap();
+        let id = match refs.as_slice() {
+            [Ref::Direct { path, object }] if path == "refs/rad/id" => *object,
+            x => panic!("unexpected refs: {:?}", x),
+        };
+        let expected = responder
+            .using_storage({
+                let urn = urn.clone();
+                move |storage| storage.reference_oid(&Reference::rad_id(Namespace::from(&urn)))
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(id.as_slice(), expected.as_bytes());
+
+        // Fetch into a repository of our own, without touching the
+        // requester's storage
+        let tmp = tempfile::tempdir().unwrap();
+        let mirror = git2::Repository::init_bare(tmp.path()).unwrap();
+        let out = smart
+            .fetch_into(
+                &urn,
+                NonEmptyVec::new(id),
+                vec![],
+                mirror.path(),
+                packwriter::Options::default(),
+            )
+            .await
+            .unwrap();
+        assert!(out.pack.is_some());
+        assert!(mirror
+            .find_commit(git2::Oid::from_bytes(id.as_slice()).unwrap())
+            .is_ok());
+        assert!(!requester
+            .using_storage(move |storage| storage.has_urn(&urn))
+            .await
+            .unwrap()
+            .unwrap());
+    })
+}
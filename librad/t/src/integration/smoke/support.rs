@@ -0,0 +1,47 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::ops::Index as _;
+
+use it_helpers::{fixed::TestProject, testnet};
+use test_helpers::logging;
+
+fn config() -> testnet::Config {
+    testnet::Config {
+        num_peers: nonzero!(2usize),
+        min_connected: 2,
+        bootstrap: testnet::Bootstrap::from_env(),
+    }
+}
+
+#[test]
+fn writes_bundle() {
+    logging::init();
+
+    let net = testnet::run(config()).unwrap();
+    net.enter(async {
+        let peer = net.peers().index(0);
+        peer.using_storage(TestProject::create)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("bundle.json");
+        let bundle = peer.support_bundle(&path).await.unwrap();
+
+        assert_eq!(bundle.peer_id, peer.peer_id());
+        assert_eq!(bundle.storage.namespaces, 2);
+        assert!(bundle.membership.is_some());
+
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(
+            written["peer_id"],
+            serde_json::to_value(peer.peer_id()).unwrap()
+        );
+        assert_eq!(written["storage"]["namespaces"], 2);
+    })
+}
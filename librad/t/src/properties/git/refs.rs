@@ -3,7 +3,11 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use librad::git::refs::Refs;
+use librad::{
+    git::refs::{Refs, Signed},
+    PeerId,
+    SecretKey,
+};
 use proptest::prelude::*;
 
 use crate::gen::refs::gen_refs;
@@ -15,4 +19,10 @@ proptest! {
         let deserialized: Refs = serde_json::from_slice(&serialized).unwrap();
         assert_eq!(refs, deserialized);
     }
+
+    #[test]
+    fn garbage_signed_refs(bytes in any::<Vec<u8>>()) {
+        let signer = PeerId::from(SecretKey::from_seed([42; 32]));
+        let _ = Signed::from_json(&bytes, &signer);
+    }
 }
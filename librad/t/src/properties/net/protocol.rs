@@ -4,3 +4,4 @@
 // Linking Exception. For full terms see the included LICENSE file.
 
 pub mod membership;
+pub mod wire;
@@ -0,0 +1,141 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Round-trip and robustness properties of the protocol wire types.
+
+use std::fmt::Debug;
+
+use futures::{executor::block_on, SinkExt as _, TryStreamExt as _};
+use futures_codec::{Decoder, Encoder, FramedRead, FramedWrite};
+use librad::net::protocol::{compression, gossip, interrogation, io::codec, request_pull};
+use proptest::{prelude::*, sample::Index};
+
+use crate::gen::protocol::{
+    gen_gossip,
+    gen_interrogation_request,
+    gen_membership,
+    gen_request_pull_request,
+};
+
+proptest! {
+    #[test]
+    fn roundtrip_gossip(msg in gen_gossip(), compress in any::<bool>()) {
+        prop_roundtrip(codec::Gossip::with_compression(compression(compress)), msg)
+    }
+
+    #[test]
+    fn roundtrip_membership(msg in gen_membership(), compress in any::<bool>()) {
+        prop_roundtrip(codec::Membership::with_compression(compression(compress)), msg)
+    }
+
+    #[test]
+    fn roundtrip_interrogation(req in gen_interrogation_request()) {
+        // `interrogation::Request` is not `PartialEq`
+        let bytes = minicbor::to_vec(&req).unwrap();
+        let decoded = minicbor::decode::<interrogation::Request>(&bytes).unwrap();
+        assert_eq!(bytes, minicbor::to_vec(&decoded).unwrap())
+    }
+
+    #[test]
+    fn roundtrip_request_pull(req in gen_request_pull_request()) {
+        prop_roundtrip(codec::Codec::new(), req)
+    }
+
+    #[test]
+    fn garbage_gossip(bytes in any::<Vec<u8>>()) {
+        prop_decode_garbage(codec::Gossip::<gossip::Payload>::new(), bytes)
+    }
+
+    #[test]
+    fn garbage_membership(bytes in any::<Vec<u8>>()) {
+        prop_decode_garbage(codec::Membership::new(), bytes)
+    }
+
+    #[test]
+    fn garbage_interrogation(bytes in any::<Vec<u8>>()) {
+        prop_decode_garbage(codec::Codec::<interrogation::Request>::new(), bytes)
+    }
+
+    #[test]
+    fn garbage_request_pull(bytes in any::<Vec<u8>>()) {
+        prop_decode_garbage(codec::Codec::<request_pull::Request>::new(), bytes)
+    }
+
+    #[test]
+    fn truncated_gossip(
+        msg in gen_gossip(),
+        compress in any::<bool>(),
+        cut in any::<Index>()
+    ) {
+        prop_decode_truncated(codec::Gossip::with_compression(compression(compress)), msg, cut)
+    }
+
+    #[test]
+    fn truncated_membership(
+        msg in gen_membership(),
+        compress in any::<bool>(),
+        cut in any::<Index>()
+    ) {
+        prop_decode_truncated(codec::Membership::with_compression(compression(compress)), msg, cut)
+    }
+}
+
+/// Compress everything, if at all.
+fn compression(compress: bool) -> Option<compression::Config> {
+    compress.then(|| compression::Config {
+        threshold: 0,
+        ..Default::default()
+    })
+}
+
+fn encode<C, T>(codec: C, item: T) -> Vec<u8>
+where
+    C: Encoder<Item = T>,
+    C::Error: Debug,
+{
+    let mut buf = Vec::new();
+    block_on(FramedWrite::new(&mut buf, codec).send(item)).unwrap();
+    buf
+}
+
+/// Decode all items from `bytes`, as a remote peer's stream would be.
+fn decode<C>(codec: C, bytes: &[u8]) -> Result<Vec<C::Item>, C::Error>
+where
+    C: Decoder,
+{
+    block_on(FramedRead::new(bytes, codec).try_collect())
+}
+
+fn prop_roundtrip<C, T>(codec: C, item: T)
+where
+    C: Encoder<Item = T> + Decoder<Item = T> + Clone,
+    <C as Encoder>::Error: Debug,
+    <C as Decoder>::Error: Debug,
+    T: Clone + Debug + PartialEq,
+{
+    let bytes = encode(codec.clone(), item.clone());
+    assert_eq!(vec![item], decode(codec, &bytes).unwrap())
+}
+
+/// Arbitrary input must not panic the decoder.
+fn prop_decode_garbage<C>(codec: C, bytes: Vec<u8>)
+where
+    C: Decoder,
+{
+    let _ = decode(codec, &bytes);
+}
+
+/// A message cut short must not panic the decoder, nor yield a message.
+fn prop_decode_truncated<C, T>(codec: C, item: T, cut: Index)
+where
+    C: Encoder<Item = T> + Decoder<Item = T> + Clone,
+    <C as Encoder>::Error: Debug,
+    <C as Decoder>::Error: Debug,
+    T: Debug,
+{
+    let bytes = encode(codec.clone(), item);
+    let truncated = &bytes[..cut.index(bytes.len())];
+    assert_matches!(decode(codec, truncated).as_deref(), Err(_) | Ok([]))
+}
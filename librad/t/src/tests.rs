@@ -3,7 +3,12 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+mod backup;
+mod cache;
+mod collaborative_objects;
 mod git;
 mod net;
+mod notifications;
 mod paths;
 mod profile;
+mod report;
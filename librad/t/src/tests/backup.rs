@@ -0,0 +1,91 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::fs;
+
+use it_helpers::fixture;
+use librad::{
+    backup,
+    git::{
+        identities,
+        storage::{ReadOnlyStorage as _, Storage},
+    },
+    paths::Paths,
+    SecretKey,
+};
+use test_helpers::logging;
+
+#[test]
+fn backup_and_restore() {
+    logging::init();
+
+    let root = tempfile::tempdir().unwrap();
+    let key = SecretKey::new();
+    let paths = Paths::from_root(root.path().join("old")).unwrap();
+    let store = Storage::open(&paths, key.clone()).unwrap();
+    let (person, project) = fixture::project_from_repo(&store, "backed-up", &["master"]);
+    fs::write(paths.address_book_file(), b"{}").unwrap();
+
+    let archive = root.path().join("peer.backup");
+    let manifest = backup::create(&store, &paths, &archive).unwrap();
+    assert_eq!(manifest.version, backup::VERSION);
+    assert_eq!(&manifest.peer_id, store.peer_id());
+    assert!(manifest.namespaces.contains_key(&person));
+    assert!(manifest.namespaces.contains_key(&project));
+    assert_eq!(backup::manifest(&archive).unwrap(), manifest);
+
+    let new_paths = Paths::from_root(root.path().join("new")).unwrap();
+    let new_store = Storage::open(&new_paths, key).unwrap();
+    let restored = backup::restore(&new_store, &new_paths, &archive).unwrap();
+    assert!(restored.rejected.is_empty());
+    assert!(restored.existing.is_empty());
+    assert_eq!(restored.restored.len(), manifest.namespaces.len());
+    assert!(new_store.has_urn(&person).unwrap());
+    assert!(new_store.has_urn(&project).unwrap());
+    assert!(identities::project::verify(&new_store, &project)
+        .unwrap()
+        .is_some());
+    assert_eq!(fs::read(new_paths.address_book_file()).unwrap(), b"{}");
+
+    // Restoring again leaves everything alone
+    let restored = backup::restore(&new_store, &new_paths, &archive).unwrap();
+    assert!(restored.restored.is_empty());
+    assert_eq!(restored.existing.len(), manifest.namespaces.len());
+}
+
+#[test]
+fn refuses_other_peer() {
+    logging::init();
+
+    let root = tempfile::tempdir().unwrap();
+    let paths = Paths::from_root(root.path().join("old")).unwrap();
+    let store = Storage::open(&paths, SecretKey::new()).unwrap();
+    let (person, _) = fixture::project_from_repo(&store, "backed-up", &["master"]);
+    let archive = root.path().join("peer.backup");
+    backup::create(&store, &paths, &archive).unwrap();
+
+    let new_paths = Paths::from_root(root.path().join("new")).unwrap();
+    let new_store = Storage::open(&new_paths, SecretKey::new()).unwrap();
+    assert!(matches!(
+        backup::restore(&new_store, &new_paths, &archive),
+        Err(backup::Error::PeerMismatch { archive, storage })
+            if &archive == store.peer_id() && &storage == new_store.peer_id()
+    ));
+    assert!(!new_store.has_urn(&person).unwrap());
+}
+
+#[test]
+fn not_an_archive() {
+    let root = tempfile::tempdir().unwrap();
+    let paths = Paths::from_root(root.path()).unwrap();
+    let store = Storage::open(&paths, SecretKey::new()).unwrap();
+
+    let path = root.path().join("garbage");
+    fs::write(&path, b"# v2 git bundle\n").unwrap();
+    assert!(matches!(
+        backup::restore(&store, &paths, &path),
+        Err(backup::Error::NotAnArchive(_))
+    ));
+}
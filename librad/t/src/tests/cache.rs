@@ -0,0 +1,71 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::fs;
+
+use tempfile::tempdir;
+
+use librad::cache::Cache;
+
+#[test]
+fn roundtrip() {
+    let tmp = tempdir().unwrap();
+    let cache = Cache::<String>::open_in(tmp.path(), "test", 1, 1024).unwrap();
+
+    assert_eq!(cache.get("key").unwrap(), None);
+    cache.put("key", &"value".to_owned()).unwrap();
+    assert_eq!(cache.get("key").unwrap(), Some("value".to_owned()));
+    cache.remove("key").unwrap();
+    assert_eq!(cache.get("key").unwrap(), None);
+    assert_eq!(cache.size(), 0);
+}
+
+#[test]
+fn version_bump_invalidates() {
+    let tmp = tempdir().unwrap();
+    {
+        let cache = Cache::<String>::open_in(tmp.path(), "test", 1, 1024).unwrap();
+        cache.put("key", &"value".to_owned()).unwrap();
+    }
+    let cache = Cache::<String>::open_in(tmp.path(), "test", 2, 1024).unwrap();
+    assert_eq!(cache.get("key").unwrap(), None);
+    assert!(!tmp.path().join("test").join("v1").exists());
+}
+
+#[test]
+fn corrupt_entries_are_removed() {
+    let tmp = tempdir().unwrap();
+    let cache = Cache::<String>::open_in(tmp.path(), "test", 1, 1024).unwrap();
+    cache.put("key", &"value".to_owned()).unwrap();
+
+    let dir = tmp.path().join("test").join("v1");
+    let entry = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+    let mut bytes = fs::read(&entry).unwrap();
+    *bytes.last_mut().unwrap() ^= 0xff;
+    fs::write(&entry, bytes).unwrap();
+
+    assert_eq!(cache.get("key").unwrap(), None);
+    assert!(!entry.exists());
+}
+
+#[test]
+fn evicts_when_full() {
+    let tmp = tempdir().unwrap();
+    let cache = Cache::<Vec<u8>>::open_in(tmp.path(), "test", 1, 1024).unwrap();
+    for i in 0..16u8 {
+        cache.put([i], &vec![i; 128]).unwrap();
+    }
+
+    assert!(cache.size() <= 1024);
+    assert_eq!(cache.get([15u8]).unwrap(), Some(vec![15; 128]));
+}
+
+#[test]
+fn rejects_invalid_names() {
+    let tmp = tempdir().unwrap();
+    for name in ["", "..", "a/b"] {
+        assert!(Cache::<String>::open_in(tmp.path(), name, 1, 1024).is_err())
+    }
+}
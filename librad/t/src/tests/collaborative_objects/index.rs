@@ -0,0 +1,89 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::time::{Duration, SystemTime};
+
+use it_helpers::{fixture, tmp};
+use librad::{
+    collaborative_objects::{
+        index::{Index, Query},
+        issue::{Issues, ISSUE},
+    },
+    SecretKey,
+};
+
+#[test]
+fn index_is_updated_on_write() {
+    let store = tmp::storage(SecretKey::new());
+    let (urn, whoami) = fixture::project(&store);
+    let repo = git2::Repository::open(store.path()).unwrap();
+    let issues = Issues::new(&store, None);
+
+    let (first, _) = issues.create(&whoami, &urn, "first", "").unwrap();
+    let (second, _) = issues.create(&whoami, &urn, "second", "").unwrap();
+
+    let index = Index::load(&repo, &urn);
+    let mut ids = vec![first, second];
+    ids.sort();
+    assert_eq!(index.objects.keys().copied().collect::<Vec<_>>(), ids);
+    let entry = &index.objects[&first];
+    assert_eq!(entry.typename, *ISSUE);
+    assert_eq!(entry.author, whoami.urn());
+    assert_eq!(entry.tips.len(), 1);
+
+    issues.comment(&whoami, &urn, &first, "bump").unwrap();
+    let updated = Index::load(&repo, &urn);
+    assert_ne!(updated.objects[&first].tips, entry.tips);
+    assert_eq!(updated.objects[&second], index.objects[&second]);
+}
+
+#[test]
+fn query() {
+    let store = tmp::storage(SecretKey::new());
+    let (urn, whoami) = fixture::project(&store);
+    let issues = Issues::new(&store, None);
+    let cobs = store.collaborative_objects(None);
+
+    issues.create(&whoami, &urn, "first", "").unwrap();
+    issues.create(&whoami, &urn, "second", "").unwrap();
+
+    assert_eq!(cobs.query(&urn, &Query::new()).unwrap().len(), 2);
+    assert_eq!(
+        cobs.query(&urn, &Query::new().typename(ISSUE.clone()).limit(1))
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(
+        cobs.query(
+            &urn,
+            &Query::new().typename("xyz.radicle.other".parse().unwrap())
+        )
+        .unwrap()
+        .len(),
+        0
+    );
+    assert_eq!(
+        cobs.query(&urn, &Query::new().author(whoami.urn()))
+            .unwrap()
+            .len(),
+        2
+    );
+    assert_eq!(
+        cobs.query(&urn, &Query::new().author(urn.clone()))
+            .unwrap()
+            .len(),
+        0
+    );
+    assert_eq!(
+        cobs.query(
+            &urn,
+            &Query::new().since(SystemTime::now() + Duration::from_secs(3600))
+        )
+        .unwrap()
+        .len(),
+        0
+    );
+}
@@ -0,0 +1,80 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use it_helpers::{fixture, tmp};
+use librad::{
+    collaborative_objects::issue::{Issues, State},
+    SecretKey,
+};
+
+#[test]
+fn lifecycle() {
+    let store = tmp::storage(SecretKey::new());
+    let (urn, whoami) = fixture::project(&store);
+    let issues = Issues::new(&store, None);
+
+    let (id, issue) = issues
+        .create(&whoami, &urn, "it's broken", "nothing works")
+        .unwrap();
+    assert_eq!(issue.title, "it's broken");
+    assert_eq!(issue.description, "nothing works");
+    assert_eq!(issue.author, whoami.urn());
+    assert_eq!(issue.state, State::Open);
+
+    issues.comment(&whoami, &urn, &id, "works for me").unwrap();
+    issues.label(&whoami, &urn, &id, "bug").unwrap();
+    issues.label(&whoami, &urn, &id, "wontfix").unwrap();
+    issues.unlabel(&whoami, &urn, &id, "bug").unwrap();
+    issues.close(&whoami, &urn, &id).unwrap();
+
+    let issue = issues.get(&urn, &id).unwrap().unwrap();
+    assert_eq!(issue.state, State::Closed);
+    assert_eq!(
+        issue.labels.into_iter().collect::<Vec<_>>(),
+        vec!["wontfix".to_owned()]
+    );
+    assert_eq!(issue.comments.len(), 1);
+    assert_eq!(issue.comments[0].author, whoami.urn());
+    assert_eq!(issue.comments[0].body, "works for me");
+
+    let issue = issues.reopen(&whoami, &urn, &id).unwrap();
+    assert_eq!(issue.state, State::Open);
+}
+
+#[test]
+fn index_tracks_updates() {
+    let store = tmp::storage(SecretKey::new());
+    let (urn, whoami) = fixture::project(&store);
+    let issues = Issues::new(&store, None);
+
+    let (first, _) = issues.create(&whoami, &urn, "first", "").unwrap();
+    let (second, _) = issues.create(&whoami, &urn, "second", "").unwrap();
+    issues.label(&whoami, &urn, &second, "docs").unwrap();
+
+    let index = issues.index(&urn).unwrap();
+    assert_eq!(index.open().count(), 2);
+    assert_eq!(index.closed().count(), 0);
+    assert_eq!(
+        index
+            .labelled("docs")
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>(),
+        vec![second]
+    );
+
+    issues.close(&whoami, &urn, &first).unwrap();
+    let index = issues.index(&urn).unwrap();
+    assert_eq!(
+        index.open().map(|(id, _)| *id).collect::<Vec<_>>(),
+        vec![second]
+    );
+    assert_eq!(
+        index.closed().map(|(id, _)| *id).collect::<Vec<_>>(),
+        vec![first]
+    );
+
+    // A fresh handle reads the materialised index
+    assert_eq!(Issues::new(&store, None).index(&urn).unwrap(), index);
+}
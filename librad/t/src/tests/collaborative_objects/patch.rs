@@ -0,0 +1,130 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use it_helpers::{fixture, tmp};
+use librad::{
+    collaborative_objects::patch::{Anchor, Error, Patches, State},
+    git_ext as ext,
+    SecretKey,
+};
+
+fn commit(repo: &git2::Repository, parent: Option<ext::Oid>, contents: &[u8]) -> ext::Oid {
+    let mut tree = repo.treebuilder(None).unwrap();
+    let blob = repo.blob(contents).unwrap();
+    tree.insert("README", blob, 0o100644).unwrap();
+    let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+    let sig = git2::Signature::now("author", "author@example.com").unwrap();
+    let parents = parent
+        .map(|oid| repo.find_commit(oid.into()).unwrap())
+        .into_iter()
+        .collect::<Vec<_>>();
+    repo.commit(
+        None,
+        &sig,
+        &sig,
+        "change",
+        &tree,
+        &parents.iter().collect::<Vec<_>>(),
+    )
+    .unwrap()
+    .into()
+}
+
+#[test]
+fn review_and_merge() {
+    let store = tmp::storage(SecretKey::new());
+    let (urn, whoami) = fixture::project(&store);
+    let repo = git2::Repository::open(store.path()).unwrap();
+    let base = commit(&repo, None, b"base");
+    let head = commit(&repo, Some(base), b"head");
+    let patches = Patches::new(&store, None);
+
+    let (id, patch) = patches
+        .create(&whoami, &urn, "improve readme", "", base, head)
+        .unwrap();
+    assert_eq!(patch.state, State::Open);
+    assert_eq!(patch.latest().head, head);
+
+    patches
+        .review(
+            &whoami,
+            &urn,
+            &id,
+            0,
+            Some(Anchor {
+                path: "README".to_owned(),
+                line: Some(1),
+            }),
+            "typo",
+        )
+        .unwrap();
+    assert!(matches!(
+        patches.review(&whoami, &urn, &id, 1, None, "lgtm"),
+        Err(Error::NoSuchRevision { revision: 1, .. })
+    ));
+
+    let fixed = commit(&repo, Some(base), b"fixed");
+    let patch = patches.revise(&whoami, &urn, &id, base, fixed).unwrap();
+    assert_eq!(patch.revisions.len(), 2);
+    patches.review(&whoami, &urn, &id, 1, None, "lgtm").unwrap();
+
+    let patch = patches.merge(&whoami, &urn, &id, 1, fixed).unwrap();
+    assert_eq!(patch.state, State::Merged);
+    assert_eq!(patch.merge.unwrap().commit, fixed);
+    assert_eq!(patch.reviews_of(0).count(), 1);
+    assert_eq!(
+        patch.reviews_of(0).next().unwrap().anchor,
+        Some(Anchor {
+            path: "README".to_owned(),
+            line: Some(1)
+        })
+    );
+    assert_eq!(patch.reviews_of(1).count(), 1);
+    assert!(matches!(
+        patches.close(&whoami, &urn, &id),
+        Err(Error::NotOpen(_))
+    ));
+}
+
+#[test]
+fn sync_follows_force_push() {
+    let store = tmp::storage(SecretKey::new());
+    let (urn, whoami) = fixture::project(&store);
+    let repo = git2::Repository::open(store.path()).unwrap();
+    let base = commit(&repo, None, b"base");
+    let head = commit(&repo, Some(base), b"head");
+    let branch = "refs/heads/proposed";
+    repo.reference(branch, head.into(), true, "propose")
+        .unwrap();
+    let patches = Patches::new(&store, None);
+
+    let (id, _) = patches
+        .create(&whoami, &urn, "proposed", "", base, head)
+        .unwrap();
+    let patch = patches.sync(&whoami, &urn, &id, branch).unwrap();
+    assert_eq!(patch.revisions.len(), 1);
+
+    let rewritten = commit(&repo, Some(base), b"rewritten");
+    repo.reference(branch, rewritten.into(), true, "force push")
+        .unwrap();
+    let patch = patches.sync(&whoami, &urn, &id, branch).unwrap();
+    assert_eq!(patch.revisions.len(), 2);
+    assert_eq!(patch.latest().head, rewritten);
+    assert_eq!(patch.latest().base, base);
+}
+
+#[test]
+fn missing_commits_are_rejected() {
+    let store = tmp::storage(SecretKey::new());
+    let (urn, whoami) = fixture::project(&store);
+    let repo = git2::Repository::open(store.path()).unwrap();
+    let base = commit(&repo, None, b"base");
+    let missing = ext::Oid::from(git2::Oid::hash_object(git2::ObjectType::Blob, b"x").unwrap());
+
+    assert!(matches!(
+        Patches::new(&store, None).create(&whoami, &urn, "missing", "", base, missing),
+        Err(Error::MissingCommit(oid)) if oid == missing
+    ));
+}
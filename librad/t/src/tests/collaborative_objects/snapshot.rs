@@ -0,0 +1,62 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use it_helpers::{fixture, tmp};
+use librad::{
+    collaborative_objects::{
+        issue::{Issues, ISSUE},
+        CollaborativeObjects,
+    },
+    crypto::BoxedSigner,
+    SecretKey,
+};
+
+#[test]
+fn evaluation_resumes_from_snapshot() {
+    let store = tmp::storage(SecretKey::new());
+    let (urn, whoami) = fixture::project(&store);
+    let issues = Issues::new(&store, None);
+    let cobs = store.collaborative_objects(None);
+
+    let (id, _) = issues.create(&whoami, &urn, "snapshotted", "").unwrap();
+    issues.comment(&whoami, &urn, &id, "one").unwrap();
+    assert!(cobs.snapshot(&urn, &ISSUE, &id).unwrap().is_some());
+
+    issues.comment(&whoami, &urn, &id, "two").unwrap();
+    issues.label(&whoami, &urn, &id, "bug").unwrap();
+
+    let object = cobs.retrieve(&urn, &ISSUE, &id).unwrap().unwrap();
+    assert_eq!(object.history().len(), 4);
+    let issue = issues.get(&urn, &id).unwrap().unwrap();
+    assert_eq!(
+        issue
+            .comments
+            .iter()
+            .map(|c| c.body.as_str())
+            .collect::<Vec<_>>(),
+        vec!["one", "two"]
+    );
+    assert!(issue.labels.contains("bug"));
+}
+
+#[test]
+fn untrusted_snapshots_are_ignored() {
+    let store = tmp::storage(SecretKey::new());
+    let (urn, whoami) = fixture::project(&store);
+    let issues = Issues::new(&store, None);
+
+    let (id, _) = issues.create(&whoami, &urn, "snapshotted", "").unwrap();
+    issues.comment(&whoami, &urn, &id, "one").unwrap();
+    let other = CollaborativeObjects::new(BoxedSigner::from(SecretKey::new()), &store, None);
+    assert!(other.snapshot(&urn, &ISSUE, &id).unwrap().is_some());
+
+    issues.comment(&whoami, &urn, &id, "two").unwrap();
+    let object = store
+        .collaborative_objects(None)
+        .retrieve(&urn, &ISSUE, &id)
+        .unwrap()
+        .unwrap();
+    assert_eq!(object.history().len(), 3);
+}
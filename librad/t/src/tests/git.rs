@@ -3,8 +3,11 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+mod http;
+mod identities;
 mod include;
 mod local;
+mod mirror;
 mod p2p;
 mod project;
 mod refs;
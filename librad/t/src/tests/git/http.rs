@@ -0,0 +1,174 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    io::{Read as _, Write as _},
+    net::{SocketAddr, TcpStream},
+    path::Path,
+    process::Command,
+    time::Duration,
+};
+
+use it_helpers::fixture;
+use librad::{
+    git::{http, storage::Storage, Urn},
+    net::protocol::read_access::{Category, Decision, ReadAccess},
+    paths::Paths,
+    SecretKey,
+};
+use test_helpers::logging;
+use tokio::net::TcpListener;
+
+/// Create a project with the branches `master` and `dev`.
+fn served_project(paths: &Paths) -> Urn {
+    let store = Storage::open(paths, SecretKey::new()).unwrap();
+    fixture::project_from_repo(&store, "served", &["master", "dev"]).1
+}
+
+async fn git_clone(addr: SocketAddr, urn: &Urn, dir: &Path) -> Option<git2::Repository> {
+    let url = format!("http://{}/{}", addr, urn);
+    let path = dir.join("cloned");
+    tokio::task::spawn_blocking(move || {
+        let status = Command::new("git")
+            .args(&["clone", &url])
+            .arg(&path)
+            .status()
+            .unwrap();
+        status
+            .success()
+            .then(|| git2::Repository::open(path).unwrap())
+    })
+    .await
+    .unwrap()
+}
+
+/// Send `GET <target>` to `addr`, and return the response.
+async fn get(addr: SocketAddr, target: String) -> String {
+    tokio::task::spawn_blocking(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", target, addr).unwrap();
+        let mut res = String::new();
+        stream.read_to_string(&mut res).unwrap();
+        res
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clone() {
+    logging::init();
+
+    let root = tempfile::tempdir().unwrap();
+    let paths = Paths::from_root(root.path()).unwrap();
+    let urn = served_project(&paths);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(http::serve(listener, paths, http::Config::default()));
+
+    let dir = tempfile::tempdir().unwrap();
+    let cloned = git_clone(addr, &urn, dir.path()).await.unwrap();
+    server.abort();
+
+    assert_eq!(Some("refs/heads/master"), cloned.head().unwrap().name());
+    assert!(cloned.find_reference("refs/remotes/origin/dev").is_ok());
+    assert!(cloned.statuses(None).unwrap().is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn denied() {
+    logging::init();
+
+    let root = tempfile::tempdir().unwrap();
+    let paths = Paths::from_root(root.path()).unwrap();
+    let urn = served_project(&paths);
+
+    // Restricting peers denies anonymous clients, too
+    for read_access in [
+        ReadAccess::new(|_, _| Decision::Allow),
+        ReadAccess::allow_all().with_anonymous(|_| Decision::Deny),
+    ] {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = http::Config {
+            read_access,
+            ..Default::default()
+        };
+        let server = tokio::spawn(http::serve(listener, paths.clone(), config));
+
+        let res = get(addr, format!("/{}/info/refs?service=git-upload-pack", urn)).await;
+        assert!(res.starts_with("HTTP/1.1 404"), "{}", res);
+        let dir = tempfile::tempdir().unwrap();
+        assert!(git_clone(addr, &urn, dir.path()).await.is_none());
+        server.abort();
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hidden() {
+    logging::init();
+
+    let root = tempfile::tempdir().unwrap();
+    let paths = Paths::from_root(root.path()).unwrap();
+    let urn = served_project(&paths);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = http::Config {
+        read_access: ReadAccess::allow_all()
+            .with_anonymous(|_| Decision::Hide(vec![Category::Heads])),
+        ..Default::default()
+    };
+    let server = tokio::spawn(http::serve(listener, paths, config));
+
+    let res = get(addr, format!("/{}/info/refs?service=git-upload-pack", urn)).await;
+    assert!(res.starts_with("HTTP/1.1 200"), "{}", res);
+    assert!(!res.contains("refs/heads/"), "{}", res);
+    let dir = tempfile::tempdir().unwrap();
+    let cloned = git_clone(addr, &urn, dir.path()).await.unwrap();
+    server.abort();
+
+    assert!(cloned.find_reference("refs/remotes/origin/dev").is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn max_requests() {
+    logging::init();
+
+    let root = tempfile::tempdir().unwrap();
+    let paths = Paths::from_root(root.path()).unwrap();
+    let urn = served_project(&paths);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = http::Config {
+        max_requests: 1,
+        ..Default::default()
+    };
+    let server = tokio::spawn(http::serve(listener, paths, config));
+
+    // Occupies the only slot until dropped
+    let stalled = TcpStream::connect(addr).unwrap();
+    let target = format!("/{}/info/refs?service=git-upload-pack", urn);
+    let mut waiting = tokio::spawn(get(addr, target));
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), &mut waiting)
+            .await
+            .is_err()
+    );
+
+    drop(stalled);
+    let res = tokio::time::timeout(Duration::from_secs(10), waiting)
+        .await
+        .unwrap()
+        .unwrap();
+    server.abort();
+
+    assert!(res.starts_with("HTTP/1.1 200"), "{}", res);
+}
@@ -0,0 +1,10 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+mod aliases;
+mod links;
+mod local;
+mod project;
+mod review;
+mod supersede;
+mod webhooks;
@@ -0,0 +1,101 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::BTreeMap;
+
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{
+    git::identities::{
+        self,
+        aliases::{self, Alias, Aliases, Candidate, Resolution},
+    },
+    identities::{
+        delegation::Direct,
+        payload::{self, PersonPayload},
+    },
+    SecretKey,
+};
+
+#[test]
+fn parse_roundtrip() {
+    let alias = "rad:alias:alice/heartwood".parse::<Alias>().unwrap();
+    assert_eq!(alias.person, "alice");
+    assert_eq!(alias.name, "heartwood");
+    assert_eq!(alias.to_string(), "rad:alias:alice/heartwood");
+
+    assert!("rad:git:alice/heartwood".parse::<Alias>().is_err());
+    assert!("rad:alias:alice".parse::<Alias>().is_err());
+    assert!("rad:alias:/heartwood".parse::<Alias>().is_err());
+    assert!("rad:alias:alice/heart/wood".parse::<Alias>().is_err());
+}
+
+#[test]
+fn resolve_by_name_and_id() {
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { owner, project } = TestProject::create(&store).unwrap();
+    aliases::set(&store, &owner.urn(), "mine", project.urn()).unwrap();
+
+    let by_name = Alias {
+        person: "alice".into(),
+        name: "mine".into(),
+    };
+    assert_eq!(
+        aliases::resolve(&store, &by_name).unwrap(),
+        Resolution::Unique {
+            urn: project.urn(),
+            persons: vec![owner.urn()],
+        }
+    );
+
+    let by_id = Alias {
+        person: owner.urn().encode_id(),
+        ..by_name.clone()
+    };
+    assert_eq!(
+        aliases::resolve(&store, &by_id).unwrap().unique(),
+        Some(&project.urn())
+    );
+
+    aliases::remove(&store, &owner.urn(), "mine").unwrap();
+    assert_eq!(
+        aliases::resolve(&store, &by_name).unwrap(),
+        Resolution::NotFound
+    );
+}
+
+#[test]
+fn conflicts_are_surfaced() {
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { owner, project } = TestProject::create(&store).unwrap();
+    aliases::set(&store, &owner.urn(), "mine", project.urn()).unwrap();
+
+    // Another person, also called alice, calling their own URN "mine"
+    let impostor = identities::person::create(
+        &store,
+        PersonPayload::new(payload::Person {
+            name: "alice".into(),
+        })
+        .with_ext(Aliases(BTreeMap::new()))
+        .unwrap(),
+        Direct::new(*store.peer_id().as_public_key()),
+    )
+    .unwrap()
+    .urn();
+    aliases::set(&store, &impostor, "mine", impostor.clone()).unwrap();
+
+    let alias = "rad:alias:alice/mine".parse::<Alias>().unwrap();
+    match aliases::resolve(&store, &alias).unwrap() {
+        Resolution::Conflict(candidates) => {
+            assert_eq!(candidates.len(), 2);
+            assert!(candidates.contains(&Candidate {
+                person: owner.urn(),
+                urn: project.urn(),
+            }));
+            assert!(candidates.contains(&Candidate {
+                person: impostor.clone(),
+                urn: impostor,
+            }));
+        },
+        other => panic!("expected conflict, got {:?}", other),
+    }
+}
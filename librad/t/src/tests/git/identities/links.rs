@@ -0,0 +1,125 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::BTreeMap;
+
+use git_ref_format::{lit, name, Namespaced, Qualified};
+use it_helpers::{fixed::TestProject, git::create_commit, tmp};
+use librad::{
+    git::{
+        identities::{
+            self,
+            links::{self, Link, Links, Unverified},
+        },
+        storage::Storage,
+        Urn,
+    },
+    git_ext::Oid,
+    identities::{
+        delegation,
+        payload::{self, ProjectPayload},
+    },
+    SecretKey,
+};
+
+fn create_linking(storage: &Storage, owner: &TestProject, links: Links) -> Urn {
+    let whoami = identities::local::load(storage, owner.owner.urn())
+        .unwrap()
+        .unwrap();
+    let payload = ProjectPayload::new(payload::Project {
+        name: "linking".into(),
+        description: None,
+        default_branch: Some("main".into()),
+    })
+    .with_ext(links)
+    .unwrap();
+    identities::project::create(
+        storage,
+        whoami,
+        payload,
+        delegation::Indirect::from(owner.owner.clone()),
+    )
+    .unwrap()
+    .urn()
+}
+
+fn commit_on_main(storage: &Storage, urn: &Urn) -> Oid {
+    let repo = git2::Repository::open(storage.path()).unwrap();
+    let branch = Namespaced::from(lit::refs_namespaces(
+        urn,
+        Qualified::from(lit::refs_heads(name::MAIN)),
+    ));
+    create_commit(&repo, branch.into_qualified())
+        .unwrap()
+        .into()
+}
+
+#[test]
+fn resolves_and_verifies() {
+    let store = tmp::storage(SecretKey::new());
+    let dep = TestProject::create(&store).unwrap();
+    let rev = commit_on_main(&store, &dep.project.urn());
+    let urn = create_linking(
+        &store,
+        &dep,
+        Links(BTreeMap::from([(
+            "vendor/dep".to_owned(),
+            Link {
+                urn: dep.project.urn(),
+                rev,
+            },
+        )])),
+    );
+
+    let closure = links::closure(&store, &urn, 3).unwrap();
+    assert_eq!(
+        closure.urns().into_iter().collect::<Vec<_>>(),
+        vec![dep.project.urn()]
+    );
+    assert_eq!(closure.edges[0].depth, 1);
+    assert!(closure.missing.is_empty());
+    assert!(links::verify(&store, &urn, 3).unwrap().is_empty());
+
+    assert!(links::closure(&store, &urn, 0).unwrap().edges.is_empty());
+}
+
+#[test]
+fn reports_missing() {
+    let store = tmp::storage(SecretKey::new());
+    let dep = TestProject::create(&store).unwrap();
+    let unknown = Urn::new(git2::Oid::zero().into());
+    let urn = create_linking(
+        &store,
+        &dep,
+        Links(BTreeMap::from([
+            (
+                "unknown".to_owned(),
+                Link {
+                    urn: unknown.clone(),
+                    rev: git2::Oid::zero().into(),
+                },
+            ),
+            (
+                "dep".to_owned(),
+                Link {
+                    urn: dep.project.urn(),
+                    rev: git2::Oid::zero().into(),
+                },
+            ),
+        ])),
+    );
+
+    let closure = links::closure(&store, &urn, 2).unwrap();
+    assert!(closure.missing.contains(&unknown));
+
+    let unverified = links::verify(&store, &urn, 2).unwrap();
+    assert_eq!(unverified.len(), 2);
+    assert_matches!(
+        &unverified[0],
+        Unverified::MissingRevision(edge) if edge.name == "dep"
+    );
+    assert_matches!(
+        &unverified[1],
+        Unverified::MissingIdentity(edge) if edge.name == "unknown"
+    );
+}
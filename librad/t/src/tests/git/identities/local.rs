@@ -0,0 +1,92 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{
+    git::{
+        identities::{self, local},
+        storage::Storage,
+        Urn,
+    },
+    identities::{delegation::Direct, payload},
+    SecretKey,
+};
+
+fn other_person(store: &Storage) -> Urn {
+    identities::person::create(
+        store,
+        payload::Person { name: "bob".into() },
+        Direct::new(*store.peer_id().as_public_key()),
+    )
+    .unwrap()
+    .urn()
+}
+
+#[test]
+fn default_roundtrip() {
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { owner, project } = TestProject::create(&store).unwrap();
+    let owner = owner.urn();
+
+    let change = local::set_default(&store, &owner).unwrap();
+    assert_eq!(change.old, None);
+    assert_eq!(change.new, Some(owner.clone()));
+    assert_eq!(
+        local::default(&store).unwrap().map(|id| id.urn()),
+        Some(owner.clone())
+    );
+
+    let change = local::clear_default(&store).unwrap();
+    assert_eq!(change.old, Some(owner.clone()));
+    assert_eq!(change.new, None);
+    assert!(local::default(&store).unwrap().is_none());
+    assert_eq!(
+        local::resolve(&store, &project.urn())
+            .unwrap()
+            .map(|id| id.urn()),
+        Some(owner)
+    );
+}
+
+#[test]
+fn per_namespace_override() {
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { owner, project } = TestProject::create(&store).unwrap();
+    let (owner, project) = (owner.urn(), project.urn());
+    let bob = other_person(&store);
+
+    local::set_default(&store, &owner).unwrap();
+    let change = local::set(&store, &project, &bob).unwrap();
+    assert_eq!(change.namespace, Some(project.clone()));
+    assert_eq!(change.old, Some(owner.clone()));
+    assert_eq!(change.new, Some(bob.clone()));
+    assert_eq!(
+        local::resolve(&store, &project).unwrap().map(|id| id.urn()),
+        Some(bob.clone())
+    );
+    assert!(!local::set(&store, &project, &bob).unwrap().is_changed());
+
+    let change = local::clear(&store, &project).unwrap();
+    assert_eq!(change.old, Some(bob));
+    assert_eq!(change.new, None);
+    assert_eq!(
+        local::resolve(&store, &project).unwrap().map(|id| id.urn()),
+        Some(owner)
+    );
+}
+
+#[test]
+fn rejects_unknown_person() {
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let unknown = Urn::new(git2::Oid::zero().into());
+
+    assert!(matches!(
+        local::set_default(&store, &unknown),
+        Err(local::Error::NotFound(_))
+    ));
+    assert!(matches!(
+        local::set(&store, &project.urn(), &unknown),
+        Err(local::Error::NotFound(_))
+    ));
+}
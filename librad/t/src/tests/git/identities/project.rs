@@ -0,0 +1,5 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+mod delegations;
+mod import;
@@ -0,0 +1,58 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::sync::Arc;
+
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{
+    git::{
+        identities::project::delegations::{self, Authority},
+        Urn,
+    },
+    PeerId,
+    SecretKey,
+};
+use test_helpers::logging;
+
+#[test]
+fn resolves_indirect_delegations() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { owner, project } = TestProject::create(&store).unwrap();
+
+    let resolved = delegations::resolve(&store, &project.urn())
+        .unwrap()
+        .unwrap();
+    assert_eq!(resolved.revision, project.revision);
+    assert_eq!(
+        resolved.peers.into_iter().collect::<Vec<_>>(),
+        vec![(
+            *store.peer_id(),
+            Authority {
+                via: owner.urn(),
+                revision: owner.revision,
+            }
+        )]
+    );
+    assert!(!resolved.is_authorised(&PeerId::from(SecretKey::new())));
+}
+
+#[test]
+fn cache_invalidation() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { owner, project } = TestProject::create(&store).unwrap();
+    let cache = delegations::Cache::default();
+
+    let first = cache.resolve(&store, &project.urn()).unwrap().unwrap();
+    let second = cache.resolve(&store, &project.urn()).unwrap().unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+
+    assert_eq!(cache.invalidate(&Urn::new(git2::Oid::zero().into())), 0);
+    assert_eq!(cache.invalidate(&owner.urn()), 1);
+    let third = cache.resolve(&store, &project.urn()).unwrap().unwrap();
+    assert!(!Arc::ptr_eq(&first, &third));
+    assert_eq!(first, third);
+}
@@ -0,0 +1,181 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use git_ref_format::{lit, name, refname, Qualified};
+use it_helpers::{fixed::TestProject, git::create_commit, tmp};
+use librad::{
+    git::{
+        identities::{
+            self,
+            project::import::{self, Branches, Options},
+        },
+        storage::Storage,
+        Urn,
+    },
+    identities::{
+        delegation,
+        payload::{self, ProjectPayload},
+    },
+    SecretKey,
+};
+
+fn payload(default_branch: Option<&str>) -> ProjectPayload {
+    ProjectPayload::new(payload::Project {
+        name: "imported".into(),
+        description: None,
+        default_branch: default_branch.map(Into::into),
+    })
+}
+
+fn import(
+    storage: &Storage,
+    owner: &TestProject,
+    repo: &git2::Repository,
+    default_branch: Option<&str>,
+    opts: Options,
+) -> Result<import::Imported, import::Error> {
+    let whoami = identities::local::load(storage, owner.owner.urn())
+        .unwrap()
+        .unwrap();
+    identities::project::init_from_repo(
+        storage,
+        whoami,
+        payload(default_branch),
+        delegation::Indirect::from(owner.owner.clone()),
+        repo.path(),
+        opts,
+    )
+}
+
+fn branch_tip(storage: &Storage, urn: &Urn, name: &str) -> Option<git2::Oid> {
+    let repo = git2::Repository::open(storage.path()).unwrap();
+    repo.refname_to_id(&format!(
+        "refs/namespaces/{}/refs/heads/{}",
+        urn.encode_id(),
+        name
+    ))
+    .ok()
+}
+
+#[test]
+fn imports_and_renames() {
+    let store = tmp::storage(SecretKey::new());
+    let owner = TestProject::create(&store).unwrap();
+    let repo = tmp::repo().unwrap();
+    let master =
+        create_commit(&repo, Qualified::from(lit::refs_heads(refname!("master")))).unwrap();
+    let dev = create_commit(&repo, Qualified::from(lit::refs_heads(refname!("dev")))).unwrap();
+    repo.set_head("refs/heads/master").unwrap();
+
+    let imported = import(
+        &store,
+        &owner,
+        &repo,
+        None,
+        Options {
+            rename: BTreeMap::from([(refname!("master"), name::MAIN.to_owned())]),
+            ..Options::default()
+        },
+    )
+    .unwrap();
+    let urn = imported.project.urn();
+
+    assert_eq!(
+        imported
+            .project
+            .subject()
+            .default_branch
+            .as_ref()
+            .map(|b| b.as_str()),
+        Some("main")
+    );
+    assert_eq!(branch_tip(&store, &urn, "main"), Some(master));
+    assert_eq!(branch_tip(&store, &urn, "dev"), Some(dev));
+    assert_eq!(branch_tip(&store, &urn, "master"), None);
+
+    imported.remote.configure(&repo).unwrap();
+    let config = repo.config().unwrap().snapshot().unwrap();
+    assert_eq!(
+        config.get_str("remote.rad.url").unwrap(),
+        imported.remote.url.to_string()
+    );
+    assert_eq!(config.get_str("branch.master.remote").unwrap(), "rad");
+    assert_eq!(
+        config.get_str("branch.master.merge").unwrap(),
+        "refs/heads/main"
+    );
+}
+
+#[test]
+fn only_selected_branches() {
+    let store = tmp::storage(SecretKey::new());
+    let owner = TestProject::create(&store).unwrap();
+    let repo = tmp::repo().unwrap();
+    create_commit(&repo, Qualified::from(lit::refs_heads(name::MAIN))).unwrap();
+    create_commit(&repo, Qualified::from(lit::refs_heads(refname!("wip")))).unwrap();
+
+    let imported = import(
+        &store,
+        &owner,
+        &repo,
+        Some("main"),
+        Options {
+            branches: Branches::Only(BTreeSet::from([name::MAIN.to_owned()])),
+            ..Options::default()
+        },
+    )
+    .unwrap();
+    let urn = imported.project.urn();
+
+    assert!(branch_tip(&store, &urn, "main").is_some());
+    assert_eq!(branch_tip(&store, &urn, "wip"), None);
+}
+
+#[test]
+fn missing_default_branch() {
+    let store = tmp::storage(SecretKey::new());
+    let owner = TestProject::create(&store).unwrap();
+    let repo = tmp::repo().unwrap();
+    create_commit(&repo, Qualified::from(lit::refs_heads(refname!("master")))).unwrap();
+
+    assert!(matches!(
+        import(&store, &owner, &repo, Some("main"), Options::default()),
+        Err(import::Error::MissingDefaultBranch(_))
+    ));
+}
+
+#[test]
+fn default_branch_only() {
+    let store = tmp::storage(SecretKey::new());
+    let owner = TestProject::create(&store).unwrap();
+    let repo = tmp::repo().unwrap();
+    let main = create_commit(&repo, Qualified::from(lit::refs_heads(name::MAIN))).unwrap();
+    create_commit(&repo, Qualified::from(lit::refs_heads(refname!("wip")))).unwrap();
+    repo.set_head("refs/heads/main").unwrap();
+
+    let whoami = identities::local::load(&store, owner.owner.urn())
+        .unwrap()
+        .unwrap();
+    let urn = identities::project::import_repo(
+        &store,
+        whoami,
+        payload(None),
+        delegation::Indirect::from(owner.owner.clone()),
+        repo.path(),
+    )
+    .unwrap();
+
+    let project = identities::project::get(&store, &urn).unwrap().unwrap();
+    assert_eq!(
+        project
+            .subject()
+            .default_branch
+            .as_ref()
+            .map(|b| b.as_str()),
+        Some("main")
+    );
+    assert_eq!(branch_tip(&store, &urn, "main"), Some(main));
+    assert_eq!(branch_tip(&store, &urn, "wip"), None);
+}
@@ -0,0 +1,73 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{
+    git::identities::{
+        self,
+        review::{self, Review},
+    },
+    identities::{
+        delegation,
+        payload::{self, ProjectPayload},
+    },
+    SecretKey,
+};
+
+fn policy() -> Review {
+    let keys = (0..3)
+        .map(|_| serde_json::to_value(SecretKey::new().public()).unwrap())
+        .collect::<Vec<_>>();
+    serde_json::from_value(serde_json::json!({
+        "rules": [
+            { "path": "", "approvers": [keys[0]] },
+            { "path": "librad/src/net", "approvers": [keys[1], keys[2]], "required": 2 },
+            { "path": "librad/src/net/", "approvers": [keys[2]] },
+        ]
+    }))
+    .unwrap()
+}
+
+#[test]
+fn declared_in_payload() {
+    let store = tmp::storage(SecretKey::new());
+    let owner = TestProject::create(&store).unwrap();
+    let policy = policy();
+
+    let whoami = identities::local::load(&store, owner.owner.urn())
+        .unwrap()
+        .unwrap();
+    let payload = ProjectPayload::new(payload::Project {
+        name: "reviewed".into(),
+        description: None,
+        default_branch: Some("main".into()),
+    })
+    .with_ext(policy.clone())
+    .unwrap();
+    let urn = identities::project::create(
+        &store,
+        whoami,
+        payload,
+        delegation::Indirect::from(owner.owner.clone()),
+    )
+    .unwrap()
+    .urn();
+
+    assert_eq!(review::get(&store, &urn).unwrap(), Some(policy));
+    assert_eq!(review::get(&store, &owner.project.urn()).unwrap(), None);
+}
+
+#[test]
+fn most_specific_rule() {
+    let policy = policy();
+
+    assert_eq!(policy.rules[0].required, 1);
+    assert_eq!(policy.rule("README.md"), Some(&policy.rules[0]));
+    assert_eq!(policy.rule("librad/src/network.rs"), Some(&policy.rules[0]));
+    assert_eq!(policy.rule("librad/src/net"), Some(&policy.rules[2]));
+    assert_eq!(
+        policy.rule("librad/src/net/peer.rs"),
+        Some(&policy.rules[2])
+    );
+    assert_eq!(Review::default().rule("README.md"), None);
+}
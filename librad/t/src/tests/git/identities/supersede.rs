@@ -0,0 +1,108 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{
+    git::{
+        identities::{
+            self,
+            supersede::{self, Error},
+        },
+        storage::Storage,
+        tracking,
+        Urn,
+    },
+    identities::{delegation, payload},
+    PeerId,
+    SecretKey,
+};
+
+fn successor(store: &Storage, owner: &TestProject, name: &str) -> Urn {
+    let whoami = identities::local::load(store, owner.owner.urn())
+        .unwrap()
+        .unwrap();
+    identities::project::create(
+        store,
+        whoami,
+        payload::Project {
+            name: name.into(),
+            description: None,
+            default_branch: Some("main".into()),
+        },
+        delegation::Indirect::from(owner.owner.clone()),
+    )
+    .unwrap()
+    .urn()
+}
+
+#[test]
+fn resolves_transitively() {
+    let store = tmp::storage(SecretKey::new());
+    let proj = TestProject::create(&store).unwrap();
+    let old = proj.project.urn();
+    let mid = successor(&store, &proj, "mid");
+    let new = successor(&store, &proj, "new");
+
+    supersede::supersede(&store, &old, mid.clone(), Some("lost keys".into())).unwrap();
+    supersede::supersede(&store, &mid, new.clone(), None).unwrap();
+
+    let resolved = supersede::resolve(&store, &old, 5).unwrap();
+    assert_eq!(resolved.urn, new);
+    assert_eq!(
+        resolved
+            .hops
+            .iter()
+            .map(|hop| &hop.from)
+            .collect::<Vec<_>>(),
+        vec![&old, &mid]
+    );
+
+    let bounded = supersede::resolve(&store, &old, 1).unwrap();
+    assert_eq!(bounded.urn, mid);
+
+    let unchanged = supersede::resolve(&store, &new, 5).unwrap();
+    assert!(!unchanged.is_superseded());
+}
+
+#[test]
+fn rejects_self_and_cycles() {
+    let store = tmp::storage(SecretKey::new());
+    let proj = TestProject::create(&store).unwrap();
+    let a = proj.project.urn();
+    let b = successor(&store, &proj, "b");
+
+    assert!(matches!(
+        supersede::supersede(&store, &a, a.clone(), None),
+        Err(Error::SelfReference(_))
+    ));
+
+    supersede::supersede(&store, &a, b.clone(), None).unwrap();
+    supersede::supersede(&store, &b, a.clone(), None).unwrap();
+    assert!(matches!(
+        supersede::resolve(&store, &a, 5),
+        Err(Error::Cycle(_))
+    ));
+}
+
+#[test]
+fn inherits_tracking() {
+    let store = tmp::storage(SecretKey::new());
+    let proj = TestProject::create(&store).unwrap();
+    let old = proj.project.urn();
+    let new = successor(&store, &proj, "new");
+    let peer = PeerId::from(SecretKey::new());
+
+    tracking::track(
+        &store,
+        &old,
+        Some(peer),
+        tracking::Config::default(),
+        tracking::policy::Track::Any,
+    )
+    .unwrap()
+    .unwrap();
+
+    assert_eq!(supersede::inherit_tracking(&store, &old, &new).unwrap(), 1);
+    assert!(tracking::is_tracked(&store, &new, Some(peer)).unwrap());
+    assert_eq!(supersede::inherit_tracking(&store, &old, &new).unwrap(), 0);
+}
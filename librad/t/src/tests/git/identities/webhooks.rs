@@ -0,0 +1,59 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{
+    git::identities::{
+        self,
+        webhooks::{self, Webhooks},
+    },
+    identities::{
+        delegation,
+        payload::{self, ProjectPayload},
+    },
+    SecretKey,
+};
+
+#[test]
+fn declared_in_payload() {
+    let store = tmp::storage(SecretKey::new());
+    let owner = TestProject::create(&store).unwrap();
+    let hooks: Webhooks = serde_json::from_value(serde_json::json!([
+        { "url": "https://ci.example.com/radicle", "secret": "ci" },
+        { "url": "https://chat.example.com/hook" },
+    ]))
+    .unwrap();
+
+    let whoami = identities::local::load(&store, owner.owner.urn())
+        .unwrap()
+        .unwrap();
+    let payload = ProjectPayload::new(payload::Project {
+        name: "hooked".into(),
+        description: None,
+        default_branch: Some("main".into()),
+    })
+    .with_ext(hooks.clone())
+    .unwrap();
+    let urn = identities::project::create(
+        &store,
+        whoami,
+        payload,
+        delegation::Indirect::from(owner.owner.clone()),
+    )
+    .unwrap()
+    .urn();
+
+    assert_eq!(webhooks::get(&store, &urn).unwrap(), Some(hooks));
+    assert_eq!(webhooks::get(&store, &owner.owner.urn()).unwrap(), None);
+}
+
+#[test]
+fn none_declared() {
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+
+    assert_eq!(
+        webhooks::get(&store, &project.urn()).unwrap(),
+        Some(Webhooks::default())
+    );
+}
@@ -0,0 +1,99 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::time::Duration;
+
+use it_helpers::fixture;
+use librad::{
+    git::{
+        mirror::{self, Anonymous, Backoff, Mirror},
+        storage::Storage,
+    },
+    paths::Paths,
+    SecretKey,
+};
+use test_helpers::logging;
+
+#[test]
+fn push_and_prune() {
+    logging::init();
+
+    let root = tempfile::tempdir().unwrap();
+    let paths = Paths::from_root(root.path()).unwrap();
+    let store = Storage::open(&paths, SecretKey::new()).unwrap();
+    let (_, urn) = fixture::project_from_repo(&store, "mirrored", &["master", "dev"]);
+
+    let remote = tempfile::tempdir().unwrap();
+    let remote_repo = git2::Repository::init_bare(remote.path()).unwrap();
+    let mut mirror = Mirror {
+        urn: urn.clone(),
+        name: "upstream".into(),
+        url: remote.path().display().to_string(),
+        refs: mirror::DEFAULT_REFS.iter().map(|s| s.to_string()).collect(),
+        credential: None,
+    };
+
+    let mut state = mirror::State::load(&paths).unwrap();
+    let synced = mirror::sync(&store, &mut state, &mirror, &Anonymous).unwrap();
+    assert_eq!(synced.updated, vec!["refs/heads/dev", "refs/heads/master"]);
+    assert!(remote_repo.find_reference("refs/heads/dev").is_ok());
+    assert!(remote_repo.find_reference("refs/heads/master").is_ok());
+
+    // Nothing changed, nothing to push
+    let synced = mirror::sync(&store, &mut state, &mirror, &Anonymous).unwrap();
+    assert!(synced.updated.is_empty() && synced.deleted.is_empty());
+
+    mirror.refs = vec!["refs/heads/master".into()];
+    let synced = mirror::sync(&store, &mut state, &mirror, &Anonymous).unwrap();
+    assert_eq!(synced.deleted, vec!["refs/heads/dev"]);
+    assert!(remote_repo.find_reference("refs/heads/dev").is_err());
+    assert!(remote_repo.find_reference("refs/heads/master").is_ok());
+
+    state.save().unwrap();
+    let status = mirror::State::load(&paths)
+        .unwrap()
+        .get(&mirror)
+        .cloned()
+        .unwrap();
+    assert_eq!(status.failures, 0);
+    assert!(status.last_success.is_some());
+    assert_eq!(
+        status.refs.keys().collect::<Vec<_>>(),
+        vec!["refs/heads/master"]
+    );
+}
+
+#[test]
+fn failure_backs_off() {
+    logging::init();
+
+    let root = tempfile::tempdir().unwrap();
+    let paths = Paths::from_root(root.path()).unwrap();
+    let store = Storage::open(&paths, SecretKey::new()).unwrap();
+    let (_, urn) = fixture::project_from_repo(&store, "mirrored", &["master", "dev"]);
+
+    let mirror = Mirror {
+        urn,
+        name: "gone".into(),
+        url: root.path().join("does-not-exist").display().to_string(),
+        refs: mirror::DEFAULT_REFS.iter().map(|s| s.to_string()).collect(),
+        credential: None,
+    };
+    let mut state = mirror::State::in_memory();
+    assert!(mirror::sync(&store, &mut state, &mirror, &Anonymous).is_err());
+    assert!(mirror::sync(&store, &mut state, &mirror, &Anonymous).is_err());
+
+    let backoff = Backoff::default();
+    let status = state.get(&mirror).unwrap();
+    assert_eq!(status.failures, 2);
+    assert!(status.last_error.is_some());
+    assert!(status.refs.is_empty());
+    assert_eq!(
+        status.retry_at(&backoff),
+        status.last_attempt.map(|at| at + 60)
+    );
+    assert_eq!(backoff.delay(0), Duration::ZERO);
+    assert_eq!(backoff.delay(100), backoff.max);
+}
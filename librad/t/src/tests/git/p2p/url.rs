@@ -5,7 +5,12 @@
 
 use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 
-use librad::{git::p2p::url::GitUrl, identities::git, PeerId, SecretKey};
+use librad::{
+    git::p2p::url::{GitUrl, Host},
+    identities::git,
+    PeerId,
+    SecretKey,
+};
 use test_helpers::roundtrip;
 
 #[test]
@@ -22,6 +27,14 @@ fn test_str_roundtrip() {
                 0,
             )),
         ],
+        host_hints: vec![Host {
+            name: "seed.radicle.xyz".to_owned(),
+            port: 8776,
+        }],
+        tcp_hints: vec![SocketAddr::V4(SocketAddrV4::new(
+            Ipv4Addr::new(127, 0, 0, 1),
+            443,
+        ))],
         repo: git::Revision::from(git2::Oid::zero()),
     };
 
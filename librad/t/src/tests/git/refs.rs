@@ -309,3 +309,236 @@ mod computing_refs {
         assert_eq!(refs.categorised_refs, expected_refs);
     }
 }
+
+mod v2 {
+    use std::{
+        collections::{BTreeMap, HashMap},
+        path::Path,
+    };
+
+    use it_helpers::fixed::TestProject;
+    use librad::{
+        git::{
+            refs::{
+                v2::{
+                    self,
+                    encoding::{self, Delta, Format},
+                    Sealed,
+                    Shard,
+                },
+                Refs,
+            },
+            storage::ReadOnlyStorage as _,
+            types::{Namespace, Reference},
+            Storage,
+            Urn,
+        },
+        paths::Paths,
+        PeerId,
+        SecretKey,
+    };
+
+    fn temp_storage<P: AsRef<Path>>(path: P) -> (Urn, git2::Repository, Storage) {
+        temp_storage_with(path, true)
+    }
+
+    fn temp_storage_with<P: AsRef<Path>>(path: P, v2: bool) -> (Urn, git2::Repository, Storage) {
+        let paths = Paths::from_root(path.as_ref()).unwrap();
+        let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+        storage.config().unwrap().set_sigrefs_v2(v2).unwrap();
+        let project = TestProject::create(&storage).unwrap();
+        let raw_repo = git2::Repository::open(paths.git_dir()).unwrap();
+        (project.project.urn(), raw_repo, storage)
+    }
+
+    fn signed_refs(storage: &Storage, urn: &Urn) -> radicle_git_ext::Oid {
+        storage
+            .reference_oid(&Reference::rad_signed_refs(Namespace::from(urn), None))
+            .unwrap()
+    }
+
+    fn blob_oid(storage: &Storage, urn: &Urn, path: &str) -> Option<git2::Oid> {
+        storage
+            .blob_at(signed_refs(storage, urn), Path::new(path))
+            .unwrap()
+            .map(|blob| blob.id())
+    }
+
+    #[test]
+    fn roundtrip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (urn, _, storage) = temp_storage(&tmp);
+
+        assert!(blob_oid(&storage, &urn, "manifest").is_some());
+        assert!(blob_oid(&storage, &urn, "categories/heads").is_some());
+        assert!(blob_oid(&storage, &urn, "refs").is_none());
+        assert_eq!(
+            Refs::load(&storage, &urn, None::<PeerId>).unwrap(),
+            Some(Refs::compute(&storage, &urn).unwrap())
+        );
+    }
+
+    #[test]
+    fn v1_by_default() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (urn, _, storage) = temp_storage_with(&tmp, false);
+
+        assert!(blob_oid(&storage, &urn, "manifest").is_none());
+        assert!(blob_oid(&storage, &urn, "refs").is_some());
+        assert_eq!(
+            Refs::load(&storage, &urn, None::<PeerId>).unwrap(),
+            Some(Refs::compute(&storage, &urn).unwrap())
+        );
+    }
+
+    #[test]
+    fn unchanged_shards_are_reused() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (urn, raw_repo, storage) = temp_storage(&tmp);
+        let heads = blob_oid(&storage, &urn, "categories/heads");
+        let tags = blob_oid(&storage, &urn, "categories/tags");
+
+        let target = raw_repo.blob(b"next").unwrap();
+        raw_repo
+            .reference(
+                &format!("refs/namespaces/{}/refs/heads/next", urn.encode_id()),
+                target,
+                false,
+                "",
+            )
+            .unwrap();
+        Refs::update(&storage, &urn).unwrap();
+
+        assert_ne!(heads, blob_oid(&storage, &urn, "categories/heads"));
+        assert_eq!(tags, blob_oid(&storage, &urn, "categories/tags"));
+    }
+
+    #[test]
+    fn load_v1() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (urn, raw_repo, storage) = temp_storage(&tmp);
+
+        let refs = Refs::compute(&storage, &urn).unwrap();
+        let signed = refs.clone().sign(storage.signer()).unwrap();
+        let tree = {
+            let blob = raw_repo
+                .blob(&serde_json::to_vec(&signed).unwrap())
+                .unwrap();
+            let mut builder = raw_repo.treebuilder(None).unwrap();
+            builder.insert("refs", blob, 0o100_644).unwrap();
+            raw_repo.find_tree(builder.write().unwrap()).unwrap()
+        };
+        let author = raw_repo.signature().unwrap();
+        let branch = format!("refs/namespaces/{}/refs/rad/signed_refs", urn.encode_id());
+        raw_repo
+            .commit(
+                Some(branch.as_str()),
+                &author,
+                &author,
+                "v1",
+                &tree,
+                &[&raw_repo
+                    .find_commit(signed_refs(&storage, &urn).into())
+                    .unwrap()],
+            )
+            .unwrap();
+
+        assert!(blob_oid(&storage, &urn, "manifest").is_none());
+        assert_eq!(
+            Refs::load(&storage, &urn, None::<PeerId>).unwrap(),
+            Some(refs)
+        );
+    }
+
+    #[test]
+    fn compressed_roundtrip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (urn, raw_repo, storage) = temp_storage(&tmp);
+        storage
+            .config()
+            .unwrap()
+            .set_sigrefs_compression(true)
+            .unwrap();
+
+        let create_head = |name: &str| {
+            let target = raw_repo.blob(name.as_bytes()).unwrap();
+            raw_repo
+                .reference(
+                    &format!("refs/namespaces/{}/refs/heads/{}", urn.encode_id(), name),
+                    target,
+                    false,
+                    "",
+                )
+                .unwrap();
+        };
+        let format = |path: &str| {
+            let blob = raw_repo
+                .find_blob(blob_oid(&storage, &urn, path).unwrap())
+                .unwrap();
+            let content = blob.content();
+            assert!(content.starts_with(encoding::MAGIC));
+            content[encoding::MAGIC.len()]
+        };
+
+        for i in 0..64 {
+            create_head(&format!("branch-{}", i));
+        }
+        Refs::update(&storage, &urn).unwrap();
+        assert_eq!(format("manifest"), Format::Deflate as u8);
+        assert_eq!(
+            Refs::load(&storage, &urn, None::<PeerId>).unwrap(),
+            Some(Refs::compute(&storage, &urn).unwrap())
+        );
+
+        create_head("next");
+        Refs::update(&storage, &urn).unwrap();
+        assert_eq!(format("categories/heads"), Format::Delta as u8);
+        assert_eq!(
+            Refs::load(&storage, &urn, None::<PeerId>).unwrap(),
+            Some(Refs::compute(&storage, &urn).unwrap())
+        );
+    }
+
+    #[test]
+    fn delta_chain_exceeding_limit() {
+        let key = SecretKey::new();
+        let hash = |content: &[u8]| {
+            radicle_git_ext::Oid::from(
+                git2::Oid::hash_object(git2::ObjectType::Blob, content).unwrap(),
+            )
+        };
+
+        let mut blobs = HashMap::new();
+        let base = Sealed {
+            body: Shard {
+                category: "heads".to_owned(),
+                refs: BTreeMap::new(),
+            },
+            signature: key.sign(b"base"),
+        };
+        let mut tip = encoding::plain(&base).unwrap();
+        for i in 0..4 {
+            let delta = Delta {
+                base: hash(&tip),
+                signature: key.sign(b"delta"),
+                upserts: (0..32)
+                    .map(|j| (format!("branch-{}-{}", i, j), hash(&[i, j])))
+                    .collect(),
+                removals: Default::default(),
+            };
+            let prev =
+                std::mem::replace(&mut tip, encoding::deflate(Format::Delta, &delta).unwrap());
+            blobs.insert(hash(&prev), prev);
+        }
+        let find_blob =
+            |oid: radicle_git_ext::Oid| -> Result<_, v2::Error> { Ok(blobs.get(&oid).cloned()) };
+
+        let (sealed, depth) = encoding::shard(&tip, 64 * 1024, find_blob).unwrap();
+        assert_eq!(depth, 4);
+        assert_eq!(sealed.body.refs.len(), 128);
+        assert!(matches!(
+            encoding::shard(&tip, 4 * 1024, find_blob),
+            Err(v2::Error::TooLarge { .. })
+        ));
+    }
+}
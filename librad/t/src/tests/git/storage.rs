@@ -4,4 +4,16 @@
 // Linking Exception. For full terms see the included LICENSE file.
 
 mod config;
+mod digest;
+mod eviction;
+mod export;
+mod faults;
+mod gc;
+mod migrations;
+mod pinned;
+mod pool;
+mod quota;
+mod reader;
+mod touched;
+mod transaction;
 mod watch;
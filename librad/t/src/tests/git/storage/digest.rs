@@ -0,0 +1,157 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{
+    git::storage::{digest, Storage},
+    PeerId,
+    SecretKey,
+};
+use test_helpers::logging;
+
+fn summary(store: &Storage, project: &TestProject, opts: &digest::Options) -> digest::Summary {
+    digest::summary(store, &project.project.urn(), opts)
+        .unwrap()
+        .unwrap()
+}
+
+/// Copy the local refs of the project namespace to the view of `remote`.
+fn mirror(store: &Storage, project: &TestProject, remote: PeerId) {
+    let raw = git2::Repository::open(store.path()).unwrap();
+    let prefix = format!(
+        "refs/namespaces/{}/refs/",
+        project.project.urn().encode_id()
+    );
+    let local = raw
+        .references_glob(&format!("{}*", prefix))
+        .unwrap()
+        .map(|r| {
+            let r = r.unwrap();
+            (r.name().unwrap().to_owned(), r.target().unwrap())
+        })
+        .filter(|(name, _)| !name.starts_with(&format!("{}remotes/", prefix)))
+        .collect::<Vec<_>>();
+    for (name, oid) in local {
+        let name = format!(
+            "{}remotes/{}/{}",
+            prefix,
+            remote,
+            name.strip_prefix(&prefix).unwrap()
+        );
+        raw.reference(&name, oid, true, "mirror").unwrap();
+    }
+}
+
+#[test]
+fn stable_and_relative() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let project = TestProject::create(&store).unwrap();
+    let opts = digest::Options::default();
+
+    let before = summary(&store, &project, &opts);
+    assert_eq!(before, summary(&store, &project, &opts));
+    assert_eq!(
+        before.peers.keys().collect::<Vec<_>>(),
+        vec![store.peer_id()]
+    );
+
+    let remote = PeerId::from(SecretKey::new());
+    mirror(&store, &project, remote);
+    let after = summary(&store, &project, &opts);
+    assert_eq!(after.peers.get(&remote), after.peers.get(store.peer_id()));
+    assert_ne!(before.root(), after.root());
+    assert_eq!(
+        before.diverged(&after).into_iter().collect::<Vec<_>>(),
+        vec![remote]
+    );
+    assert!(after.covers(&before));
+    assert!(!before.covers(&after));
+}
+
+#[test]
+fn detects_ref_updates() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let project = TestProject::create(&store).unwrap();
+    let opts = digest::Options::default();
+    let before = summary(&store, &project, &opts);
+
+    let raw = git2::Repository::open(store.path()).unwrap();
+    let prefix = format!(
+        "refs/namespaces/{}/refs/",
+        project.project.urn().encode_id()
+    );
+    let oid = raw
+        .find_reference(&format!("{}rad/id", prefix))
+        .unwrap()
+        .target()
+        .unwrap();
+    raw.reference(&format!("{}heads/next", prefix), oid, true, "next")
+        .unwrap();
+
+    let after = summary(&store, &project, &opts);
+    assert_ne!(before.root(), after.root());
+    assert_eq!(
+        before.diverged(&after).into_iter().collect::<Vec<_>>(),
+        vec![*store.peer_id()]
+    );
+
+    let hidden = summary(
+        &store,
+        &project,
+        &digest::Options {
+            sigrefs: false,
+            hide: vec!["refs/heads".to_owned()],
+        },
+    );
+    let hidden_before = {
+        raw.find_reference(&format!("{}heads/next", prefix))
+            .unwrap()
+            .delete()
+            .unwrap();
+        summary(
+            &store,
+            &project,
+            &digest::Options {
+                sigrefs: false,
+                hide: vec!["refs/heads".to_owned()],
+            },
+        )
+    };
+    assert_eq!(hidden, hidden_before);
+}
+
+#[test]
+fn sigrefs_optional() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let project = TestProject::create(&store).unwrap();
+    let without = summary(&store, &project, &digest::Options::default());
+    let with = summary(
+        &store,
+        &project,
+        &digest::Options {
+            sigrefs: true,
+            hide: vec![],
+        },
+    );
+    assert_ne!(without, with);
+}
+
+#[test]
+fn absent_namespace() {
+    let store = tmp::storage(SecretKey::new());
+    let project = TestProject::create(&store).unwrap();
+    let other = tmp::storage(SecretKey::new());
+    assert!(
+        digest::summary(&other, &project.project.urn(), &Default::default())
+            .unwrap()
+            .is_none()
+    );
+}
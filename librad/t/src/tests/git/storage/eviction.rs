@@ -0,0 +1,180 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{collections::BTreeSet, time::SystemTime};
+
+use git_ref_format::{lit, name, Namespaced, Qualified};
+use it_helpers::{fixed::TestProject, git::create_commit, tmp};
+use librad::{
+    git::{
+        identities,
+        storage::{eviction, Storage},
+        Urn,
+    },
+    SecretKey,
+};
+use test_helpers::logging;
+
+/// Pretend we replicated a project of someone else, along with its delegate.
+fn replicate_foreign(store: &Storage) -> TestProject {
+    let other = tmp::storage(SecretKey::new());
+    let foreign = TestProject::create(&other).unwrap();
+    let repo = git2::Repository::open(store.path()).unwrap();
+    let mut remote = repo
+        .remote_anonymous(other.path().to_str().unwrap())
+        .unwrap();
+    remote
+        .fetch(&["+refs/namespaces/*:refs/namespaces/*"], None, None)
+        .unwrap();
+
+    foreign
+}
+
+fn urns(store: &Storage) -> BTreeSet<Urn> {
+    identities::any::list_urns(store)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+fn new_urn() -> Urn {
+    Urn::new(git2::Oid::zero().into())
+}
+
+#[test]
+fn evicts_least_recently_served() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let own = TestProject::create(&store).unwrap();
+    let foreign = replicate_foreign(&store);
+    let served = SystemTime::now();
+    eviction::record_served(store.path(), &foreign.project.urn().encode_id(), served).unwrap();
+    assert!(eviction::last_served(&store, &foreign.project.urn())
+        .unwrap()
+        .is_some());
+
+    assert!(
+        eviction::make_room(&store, &eviction::Limit::new(5), &new_urn())
+            .unwrap()
+            .is_empty()
+    );
+    // Already present, so doesn't need room
+    assert!(
+        eviction::make_room(&store, &eviction::Limit::new(4), &own.project.urn())
+            .unwrap()
+            .is_empty()
+    );
+    // The never served delegate is kept, as the project refers to it
+    let evicted = eviction::make_room(&store, &eviction::Limit::new(4), &new_urn()).unwrap();
+    assert_eq!(evicted, vec![foreign.project.urn()]);
+    assert_eq!(
+        urns(&store),
+        vec![own.owner.urn(), own.project.urn(), foreign.owner.urn()]
+            .into_iter()
+            .collect()
+    );
+
+    let evicted = eviction::make_room(&store, &eviction::Limit::new(3), &new_urn()).unwrap();
+    assert_eq!(evicted, vec![foreign.owner.urn()]);
+    assert_eq!(
+        urns(&store),
+        vec![own.owner.urn(), own.project.urn()]
+            .into_iter()
+            .collect()
+    );
+}
+
+#[test]
+fn never_evicts_pinned() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    TestProject::create(&store).unwrap();
+    let foreign = replicate_foreign(&store);
+
+    let mut limit = eviction::Limit::new(4);
+    limit.pinned.insert(foreign.owner.urn());
+    limit.pinned.insert(foreign.project.urn());
+    assert!(matches!(
+        eviction::plan(&store, &limit, &new_urn()),
+        Err(eviction::Error::Exhausted { max: 4 })
+    ));
+
+    // The delegate of a pinned project is kept
+    limit.pinned.remove(&foreign.owner.urn());
+    assert!(matches!(
+        eviction::plan(&store, &limit, &new_urn()),
+        Err(eviction::Error::Exhausted { max: 4 })
+    ));
+
+    limit.pinned.remove(&foreign.project.urn());
+    limit.pinned.insert(foreign.owner.urn());
+    assert_eq!(
+        eviction::plan(&store, &limit, &new_urn()).unwrap(),
+        vec![foreign.project.urn()]
+    );
+    // Planning doesn't evict
+    assert_eq!(urns(&store).len(), 4);
+    let evicted = eviction::make_room(&store, &limit, &new_urn()).unwrap();
+    assert_eq!(evicted, vec![foreign.project.urn()]);
+    assert!(urns(&store).contains(&foreign.owner.urn()));
+}
+
+#[test]
+fn never_evicts_staked() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let own = TestProject::create(&store).unwrap();
+    let foreign = replicate_foreign(&store);
+
+    // A local branch in a foreign project
+    let repo = git2::Repository::open(store.path()).unwrap();
+    let branch = Namespaced::from(lit::refs_namespaces(
+        &foreign.project.urn(),
+        Qualified::from(lit::refs_heads(name::MAIN)),
+    ));
+    create_commit(&repo, branch.into_qualified()).unwrap();
+
+    assert!(matches!(
+        eviction::make_room(&store, &eviction::Limit::new(2), &new_urn()),
+        Err(eviction::Error::Exhausted { max: 2 })
+    ));
+    assert_eq!(urns(&store).len(), 4);
+
+    assert!(matches!(
+        eviction::make_room(&store, &eviction::Limit::new(4), &new_urn()),
+        Err(eviction::Error::Exhausted { max: 4 })
+    ));
+    let left = urns(&store);
+    assert!(left.contains(&own.owner.urn()), "own identity");
+    assert!(left.contains(&own.project.urn()), "delegated project");
+    assert!(left.contains(&foreign.project.urn()), "local branches");
+    assert!(
+        left.contains(&foreign.owner.urn()),
+        "delegate of a project with local branches"
+    );
+}
+
+#[test]
+fn forget_served() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let id = project.urn().encode_id();
+
+    eviction::record_served(store.path(), &id, SystemTime::now()).unwrap();
+    eviction::flush(store.path()).unwrap();
+    assert!(eviction::last_served(&store, &project.urn())
+        .unwrap()
+        .is_some());
+
+    eviction::forget(&store, &project.urn()).unwrap();
+    assert!(eviction::last_served(&store, &project.urn())
+        .unwrap()
+        .is_none());
+}
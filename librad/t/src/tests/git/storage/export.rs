@@ -0,0 +1,170 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use git_ref_format::{lit, refname, Qualified};
+use it_helpers::{fixed::TestProject, git::create_commit, tmp};
+use librad::{
+    git::{
+        identities::{self, project::import},
+        local::url::LocalUrl,
+        storage::{
+            export::{self, Options, Target},
+            Storage,
+        },
+        Urn,
+    },
+    identities::{
+        delegation,
+        payload::{self, ProjectPayload},
+    },
+    PeerId,
+    SecretKey,
+};
+use test_helpers::logging;
+
+/// Import a project with the branches `master` and `dev`, and a remote peer
+/// with the branch `feature`.
+fn setup(storage: &Storage) -> (Urn, PeerId) {
+    let owner = TestProject::create(storage).unwrap();
+    let repo = tmp::repo().unwrap();
+    let master =
+        create_commit(&repo, Qualified::from(lit::refs_heads(refname!("master")))).unwrap();
+    create_commit(&repo, Qualified::from(lit::refs_heads(refname!("dev")))).unwrap();
+    repo.set_head("refs/heads/master").unwrap();
+
+    let whoami = identities::local::load(storage, owner.owner.urn())
+        .unwrap()
+        .unwrap();
+    let imported = identities::project::init_from_repo(
+        storage,
+        whoami,
+        ProjectPayload::new(payload::Project {
+            name: "exported".into(),
+            description: None,
+            default_branch: Some("master".into()),
+        }),
+        delegation::Indirect::from(owner.owner.clone()),
+        repo.path(),
+        import::Options::default(),
+    )
+    .unwrap();
+    let urn = imported.project.urn();
+
+    let peer = PeerId::from(SecretKey::new());
+    git2::Repository::open(storage.path())
+        .unwrap()
+        .reference(
+            &format!(
+                "refs/namespaces/{}/refs/remotes/{}/heads/feature",
+                urn.encode_id(),
+                peer
+            ),
+            master.into(),
+            false,
+            "test",
+        )
+        .unwrap();
+
+    (urn, peer)
+}
+
+#[test]
+fn export_repository() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let (urn, peer) = setup(&store);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("exported");
+
+    let exported = store
+        .export(
+            &urn,
+            &Target::Repository {
+                path: path.clone(),
+                bare: false,
+            },
+            Options::default(),
+        )
+        .unwrap();
+    assert_eq!(Some("master"), exported.head.as_deref());
+    assert_eq!(Some(&peer), exported.remotes.get(&peer.to_string()));
+
+    let repo = git2::Repository::open(&path).unwrap();
+    assert!(repo.find_reference("refs/heads/master").is_ok());
+    assert!(repo.find_reference("refs/heads/dev").is_ok());
+    assert!(repo
+        .find_reference(&format!("refs/remotes/{}/feature", peer))
+        .is_ok());
+    assert_eq!(Some("refs/heads/master"), repo.head().unwrap().name());
+    assert!(repo.statuses(None).unwrap().is_empty());
+
+    let rad = repo.find_remote(export::REMOTE_NAME).unwrap();
+    assert_eq!(Some(LocalUrl::from(urn).to_string().as_str()), rad.url());
+    assert!(repo.find_remote(&peer.to_string()).is_ok());
+    assert_eq!(
+        Some(export::REMOTE_NAME),
+        repo.config()
+            .unwrap()
+            .get_string("branch.master.remote")
+            .ok()
+            .as_deref()
+    );
+}
+
+#[test]
+fn refuse_non_empty() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let (urn, _) = setup(&store);
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("README"), b"hi").unwrap();
+
+    assert!(matches!(
+        store.export(
+            &urn,
+            &Target::Repository {
+                path: dir.path().to_path_buf(),
+                bare: true,
+            },
+            Options::default(),
+        ),
+        Err(export::Error::Exists(_))
+    ));
+}
+
+#[test]
+fn export_bundle() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let (urn, _) = setup(&store);
+    let dir = tempfile::tempdir().unwrap();
+    let bundle = dir.path().join("exported.bundle");
+
+    store
+        .export(
+            &urn,
+            &Target::Bundle(bundle.clone()),
+            Options {
+                remotes: false,
+                ..Options::default()
+            },
+        )
+        .unwrap();
+
+    let clone = dir.path().join("clone");
+    // libgit2 can't read bundles
+    let status = std::process::Command::new("git")
+        .args(&["clone", "--quiet"])
+        .arg(&bundle)
+        .arg(&clone)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let repo = git2::Repository::open(&clone).unwrap();
+    assert!(repo.find_reference("refs/remotes/origin/master").is_ok());
+}
@@ -0,0 +1,206 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use git_ref_format::{lit, name, Namespaced, Qualified};
+use it_helpers::{fixed::TestProject, git::create_commit, tmp};
+use librad::{
+    git::{
+        refs::{self, Refs, Updated},
+        storage::{
+            faults::{self, Fault, Faults, Op, Rule},
+            pool,
+            transaction::{self, Previous},
+            Pool,
+            PoolError,
+            ReadOnlyStorage as _,
+            Storage,
+        },
+        types::{Namespace, Reference},
+    },
+    SecretKey,
+};
+use test_helpers::logging;
+
+const A: &str = "refs/namespaces/a/refs/heads/main";
+const B: &str = "refs/namespaces/a/refs/heads/next";
+
+fn target(store: &Storage, name: &str) -> Option<git2::Oid> {
+    let repo = git2::Repository::open(store.path()).unwrap();
+    repo.refname_to_id(name).ok()
+}
+
+#[test]
+fn failed_transaction_applies_nothing() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let store = Storage::open(&*paths, SecretKey::new()).unwrap();
+    let oid = git2::Repository::open(store.path())
+        .unwrap()
+        .blob(b"fst")
+        .unwrap();
+    let faults = Faults::new().with(Rule::fail(Op::RefUpdate).once());
+    faults::install(&paths, faults.clone());
+
+    let mut tx = store.transaction();
+    tx.update(A, oid, Previous::Absent, "test")
+        .update(B, oid, Previous::Absent, "test");
+    assert!(matches!(
+        tx.commit(),
+        Err(transaction::Error::Fault(Fault {
+            op: Op::RefUpdate,
+            nth: 0
+        }))
+    ));
+    assert_eq!(target(&store, A), None);
+    assert_eq!(target(&store, B), None);
+
+    // The locks are released, and the next attempt is let through
+    let mut tx = store.transaction();
+    tx.update(A, oid, Previous::Absent, "test")
+        .update(B, oid, Previous::Absent, "test");
+    tx.commit().unwrap();
+    assert_eq!(target(&store, A), Some(oid));
+    assert_eq!(target(&store, B), Some(oid));
+    assert_eq!(faults.occurrences(Op::RefUpdate), 2);
+
+    faults::uninstall(&paths);
+}
+
+#[test]
+fn interrupted_transaction_is_rolled_back_on_open() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let key = SecretKey::new();
+    let store = Storage::open(&*paths, key.clone()).unwrap();
+    let repo = git2::Repository::open(store.path()).unwrap();
+    let fst = repo.blob(b"fst").unwrap();
+    let snd = repo.blob(b"snd").unwrap();
+    let mut tx = store.transaction();
+    tx.update(A, fst, Previous::Absent, "test");
+    tx.commit().unwrap();
+
+    let faults = Faults::new().with(Rule::fail(Op::JournalRemove).once());
+    faults::install(&paths, faults);
+    let mut tx = store.transaction();
+    tx.update(A, snd, Previous::Is(fst.into()), "test")
+        .update(B, snd, Previous::Absent, "test");
+    assert!(matches!(
+        tx.commit(),
+        Err(transaction::Error::Fault(Fault {
+            op: Op::JournalRemove,
+            nth: 0
+        }))
+    ));
+    // As if the process died right after moving the lockfiles into place
+    assert_eq!(target(&store, A), Some(snd));
+    assert_eq!(target(&store, B), Some(snd));
+    faults::uninstall(&paths);
+
+    let store = Storage::open(&*paths, key).unwrap();
+    assert_eq!(target(&store, A), Some(fst));
+    assert_eq!(target(&store, B), None);
+}
+
+#[test]
+fn failed_sigrefs_update_is_not_visible() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let store = Storage::open(&*paths, SecretKey::new()).unwrap();
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+    let sigrefs = Reference::rad_signed_refs(Namespace::from(&urn), None);
+    let before = store.reference_oid(&sigrefs).unwrap();
+
+    let repo = git2::Repository::open(store.path()).unwrap();
+    let branch = Namespaced::from(lit::refs_namespaces(
+        &urn,
+        Qualified::from(lit::refs_heads(name::MAIN)),
+    ));
+    create_commit(&repo, branch.into_qualified()).unwrap();
+
+    // Fail after the objects were written, but before the ref is updated
+    let faults = Faults::new().with(Rule::fail(Op::RefUpdate).once());
+    faults::install(&paths, faults.clone());
+    assert!(matches!(
+        Refs::update(&store, &urn),
+        Err(refs::stored::Error::Fault(_))
+    ));
+    assert_eq!(store.reference_oid(&sigrefs).unwrap(), before);
+    assert_eq!(faults.occurrences(Op::ObjectWrite), 1);
+
+    match Refs::update(&store, &urn).unwrap() {
+        Updated::Updated { at, .. } => {
+            assert_eq!(store.reference_oid(&sigrefs).unwrap(), at.into())
+        },
+        _ => panic!("expected signed refs to be updated"),
+    }
+
+    faults::uninstall(&paths);
+}
+
+#[test]
+fn schedule_skips_and_counts() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let store = Storage::open(&*paths, SecretKey::new()).unwrap();
+    let oid = git2::Repository::open(store.path())
+        .unwrap()
+        .blob(b"fst")
+        .unwrap();
+    let faults = Faults::new().with(Rule::fail(Op::RefUpdate).skip(1).times(2));
+    faults::install(&paths, faults.clone());
+
+    let results = (0..4)
+        .map(|i| {
+            let mut tx = store.transaction();
+            tx.update(format!("{}-{}", A, i), oid, Previous::Absent, "test");
+            tx.commit().is_ok()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(results, vec![true, false, false, true]);
+    assert_eq!(
+        faults.injected(),
+        vec![
+            Fault {
+                op: Op::RefUpdate,
+                nth: 1
+            },
+            Fault {
+                op: Op::RefUpdate,
+                nth: 2
+            }
+        ]
+    );
+
+    faults::uninstall(&paths);
+}
+
+#[tokio::test]
+async fn failed_checkout() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let signer = SecretKey::new();
+    Storage::init(&paths, signer.clone()).unwrap();
+    let pool: Pool<Storage> = Pool::new(
+        pool::ReadWriteConfig::new((*paths).clone(), signer, pool::Initialised::no()),
+        1,
+    );
+    let faults = Faults::new().with(Rule::fail(Op::Checkout).once());
+    faults::install(&paths, faults.clone());
+
+    assert!(matches!(
+        pool.get().await,
+        Err(PoolError::Backend(pool::InitError::Fault(_)))
+    ));
+    assert!(pool.get().await.is_ok());
+    assert_eq!(faults.occurrences(Op::Checkout), 2);
+
+    faults::uninstall(&paths);
+}
@@ -0,0 +1,155 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use git_ref_format::{lit, name, Namespaced, Qualified};
+use it_helpers::{fixed::TestProject, git::create_commit, tmp};
+use librad::{
+    git::{identities, storage::gc, tracking},
+    PeerId,
+    SecretKey,
+};
+use test_helpers::logging;
+
+#[test]
+fn local_branches_are_referenced() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+
+    let repo = git2::Repository::open(store.path()).unwrap();
+    let branch = Namespaced::from(lit::refs_namespaces(
+        &urn,
+        Qualified::from(lit::refs_heads(name::MAIN)),
+    ));
+    create_commit(&repo, branch.into_qualified()).unwrap();
+
+    assert!(!gc::unreferenced(&store).unwrap().contains(&urn));
+}
+
+#[test]
+fn identities_survive() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let own = TestProject::create(&store).unwrap();
+
+    // Pretend we replicated a project of someone else, along with its delegate
+    let other = tmp::storage(SecretKey::new());
+    let foreign = TestProject::create(&other).unwrap();
+    {
+        let repo = git2::Repository::open(store.path()).unwrap();
+        let mut remote = repo
+            .remote_anonymous(other.path().to_str().unwrap())
+            .unwrap();
+        remote
+            .fetch(&["+refs/namespaces/*:refs/namespaces/*"], None, None)
+            .unwrap();
+    }
+
+    let unref = gc::unreferenced(&store).unwrap();
+    assert!(!unref.contains(&own.project.urn()), "owned project");
+    assert!(!unref.contains(&own.owner.urn()), "local identity");
+    assert!(unref.contains(&foreign.project.urn()));
+    assert!(unref.contains(&foreign.owner.urn()));
+
+    tracking::track(
+        &store,
+        &foreign.project.urn(),
+        None,
+        tracking::Config::default(),
+        tracking::policy::Track::Any,
+    )
+    .unwrap()
+    .unwrap();
+
+    let unref = gc::unreferenced(&store).unwrap();
+    assert!(!unref.contains(&foreign.project.urn()), "tracked project");
+    assert!(
+        !unref.contains(&foreign.owner.urn()),
+        "delegate of a tracked project"
+    );
+}
+
+#[test]
+fn remove_namespace() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+
+    assert!(gc::remove_namespace(&store, &urn).unwrap() > 0);
+    let urns = identities::any::list_urns(&store)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert!(!urns.contains(&urn));
+
+    gc::pack(&store, gc::Pack::default()).unwrap();
+}
+
+#[test]
+fn expire_untracked_remotes() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+    let stranger = PeerId::from(SecretKey::new());
+
+    let repo = git2::Repository::open(store.path()).unwrap();
+    let remote = format!(
+        "refs/namespaces/{}/refs/remotes/{}/heads/main",
+        urn.encode_id(),
+        stranger
+    );
+    let head = repo
+        .find_reference(&format!("refs/namespaces/{}/refs/rad/id", urn.encode_id()))
+        .unwrap()
+        .target()
+        .unwrap();
+    repo.reference(&remote, head, false, "test").unwrap();
+
+    let expired = gc::expire_remotes(&store, &urn).unwrap();
+    assert_eq!(expired.into_iter().collect::<Vec<_>>(), vec![stranger]);
+    assert!(repo.find_reference(&remote).is_err());
+}
+
+#[test]
+fn untrack_expired() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+    let expired = PeerId::from(SecretKey::new());
+    let current = PeerId::from(SecretKey::new());
+
+    let now = SystemTime::now();
+    let secs = now.duration_since(UNIX_EPOCH).unwrap().as_secs();
+    for (peer, expires) in [(expired, secs - 1), (current, secs + 60)] {
+        tracking::track(
+            &store,
+            &urn,
+            Some(peer),
+            tracking::Config {
+                expires: Some(expires),
+                ..tracking::Config::default()
+            },
+            tracking::policy::Track::Any,
+        )
+        .unwrap()
+        .unwrap();
+    }
+
+    let untracked = gc::untrack_expired(&store, now).unwrap();
+    assert_eq!(untracked, vec![(urn.clone(), expired)]);
+    assert!(!tracking::is_tracked(&store, &urn, Some(expired)).unwrap());
+    assert!(tracking::is_tracked(&store, &urn, Some(current)).unwrap());
+}
@@ -0,0 +1,109 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{
+    git::{
+        storage::{error, migrations, read, ReadOnly, Storage},
+        tracking,
+    },
+    PeerId,
+    SecretKey,
+};
+use test_helpers::logging;
+
+#[test]
+fn fresh_storage_is_current() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let store = Storage::open(&*paths, SecretKey::new()).unwrap();
+    let repo = git2::Repository::open(store.path()).unwrap();
+    assert_eq!(
+        migrations::SCHEMA_VERSION,
+        migrations::version(&repo).unwrap()
+    );
+
+    let report = migrations::migrate(&store).unwrap();
+    assert_eq!(migrations::SCHEMA_VERSION, report.from);
+    assert_eq!(migrations::SCHEMA_VERSION, report.to);
+    assert!(report.incomplete.is_empty());
+}
+
+#[test]
+fn unversioned_storage_is_migrated() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let key = SecretKey::new();
+    let store = Storage::open(&*paths, key.clone()).unwrap();
+    let repo = git2::Repository::open(store.path()).unwrap();
+    repo.config()
+        .unwrap()
+        .remove(migrations::CONFIG_KEY)
+        .unwrap();
+    assert_eq!(0, migrations::version(&repo).unwrap());
+    drop(store);
+
+    let store = Storage::open(&*paths, key).unwrap();
+    assert_eq!(
+        migrations::SCHEMA_VERSION,
+        migrations::version(&repo).unwrap()
+    );
+
+    // Idempotent
+    let report = migrations::migrate(&store).unwrap();
+    assert_eq!(report.from, report.to);
+}
+
+#[test]
+fn refuse_newer_storage() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let key = SecretKey::new();
+    let store = Storage::open(&*paths, key.clone()).unwrap();
+    let repo = git2::Repository::open(store.path()).unwrap();
+    let newer = migrations::SCHEMA_VERSION + 1;
+    repo.config()
+        .unwrap()
+        .set_i64(migrations::CONFIG_KEY, i64::from(newer))
+        .unwrap();
+    drop(store);
+
+    assert!(matches!(
+        Storage::open(&*paths, key),
+        Err(error::Init::Migration(migrations::Error::TooNew { found, .. })) if found == newer
+    ));
+    assert!(matches!(
+        ReadOnly::open(&*paths),
+        Err(read::error::Init::Migration(
+            migrations::Error::TooNew { .. }
+        ))
+    ));
+}
+
+#[test]
+fn v1_tracking_written_later_is_migrated() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let key = SecretKey::new();
+    let store = Storage::open(&*paths, key.clone()).unwrap();
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+    let peer = PeerId::from(SecretKey::new());
+
+    // As done by an older version sharing the storage
+    assert!(tracking::v1::track(&store, &urn, peer).unwrap());
+    drop(store);
+
+    let store = Storage::open(&*paths, key).unwrap();
+    assert_eq!(0, tracking::v1::tracked(&store, &urn).unwrap().count());
+    assert!(tracking::is_tracked(&store, &urn, Some(peer)).unwrap());
+
+    let report = migrations::migrate(&store).unwrap();
+    assert!(report.rerun.is_empty());
+}
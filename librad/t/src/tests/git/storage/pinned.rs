@@ -0,0 +1,65 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use git_ref_format::{lit, name, Namespaced, Qualified};
+use it_helpers::{fixed::TestProject, git::create_commit, tmp};
+use librad::{
+    git::{
+        refs::{Refs, Updated},
+        storage::pinned,
+    },
+    git_ext::{reference::OneLevel, Oid},
+    reflike,
+    SecretKey,
+};
+use test_helpers::logging;
+
+fn signed_refs_at(updated: Updated) -> Oid {
+    match updated {
+        Updated::Updated { at, .. } | Updated::Unchanged { at, .. } => at.into(),
+        Updated::ConcurrentlyModified => panic!("signed refs concurrently modified"),
+    }
+}
+
+#[test]
+fn resolves_past_heads() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+    let main = OneLevel::from(reflike!("main"));
+
+    let before = signed_refs_at(Refs::update(&store, &urn).unwrap());
+
+    let repo = git2::Repository::open(store.path()).unwrap();
+    let branch = Namespaced::from(lit::refs_namespaces(
+        &urn,
+        Qualified::from(lit::refs_heads(name::MAIN)),
+    ));
+    let commit = create_commit(&repo, branch.into_qualified()).unwrap();
+    let after = signed_refs_at(Refs::update(&store, &urn).unwrap());
+
+    let pinned = store.at_sigref(&urn, None, before).unwrap();
+    assert_eq!(pinned.at(), before);
+    assert_eq!(pinned.head(&main), None);
+
+    let pinned = store.at_sigref(&urn, None, after).unwrap();
+    assert_eq!(pinned.head(&main), Some(commit.into()));
+}
+
+#[test]
+fn rejects_foreign_history() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, owner } = TestProject::create(&store).unwrap();
+    let owner_sigrefs = signed_refs_at(Refs::update(&store, &owner.urn()).unwrap());
+
+    assert_matches!(
+        store.at_sigref(&project.urn(), None, owner_sigrefs),
+        Err(pinned::Error::NotInHistory { .. })
+    )
+}
@@ -0,0 +1,112 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use it_helpers::tmp;
+use librad::{
+    git::storage::{pool, Pool, PoolError, ReadOnly, Storage},
+    SecretKey,
+};
+use test_helpers::logging;
+
+fn read_only_pool(max_size: usize) -> (tmp::TmpPaths, Pool<ReadOnly>) {
+    let paths = tmp::paths();
+    Storage::init(&paths, SecretKey::new()).unwrap();
+    let pool = Pool::new(pool::ReadConfig::new((*paths).clone()), max_size);
+    (paths, pool)
+}
+
+async fn until_waiting(pool: &Pool<ReadOnly>, n: usize) {
+    while pool.status().waiting < n {
+        tokio::task::yield_now().await
+    }
+}
+
+#[tokio::test]
+async fn checkouts_are_served_in_order() {
+    logging::init();
+
+    let (_paths, pool) = read_only_pool(1);
+    let held = pool.get().await.unwrap();
+
+    let served = Arc::new(Mutex::new(Vec::new()));
+    let mut tasks = Vec::new();
+    for i in 0..3 {
+        let pool = pool.clone();
+        let served = served.clone();
+        tasks.push(tokio::spawn(async move {
+            let _storage = pool.get().await.unwrap();
+            served.lock().unwrap().push(i);
+        }));
+        until_waiting(&pool, i + 1).await;
+    }
+
+    drop(held);
+    for task in tasks {
+        task.await.unwrap()
+    }
+    assert_eq!(*served.lock().unwrap(), vec![0, 1, 2]);
+
+    let status = pool.status();
+    assert_eq!(status.size, 1);
+    assert_eq!(status.idle, 1);
+    assert_eq!(status.waiting, 0);
+}
+
+#[tokio::test]
+async fn checkout_times_out() {
+    logging::init();
+
+    let (_paths, pool) = read_only_pool(1);
+    let held = pool.get().await.unwrap();
+
+    assert!(matches!(
+        pool.checkout(Duration::from_millis(50)).await,
+        Err(PoolError::Timeout { .. })
+    ));
+    let status = pool.status();
+    assert_eq!(status.waiting, 0);
+    assert_eq!(status.timeouts, 1);
+
+    drop(held);
+    assert!(pool.checkout(Duration::from_millis(50)).await.is_ok());
+}
+
+#[tokio::test]
+async fn try_checkout_does_not_wait() {
+    logging::init();
+
+    let (_paths, pool) = read_only_pool(1);
+    let held = pool.try_checkout().await.unwrap();
+    assert!(held.is_some());
+    assert!(pool.try_checkout().await.unwrap().is_none());
+
+    drop(held);
+    assert!(pool.try_checkout().await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn try_checkout_does_not_jump_the_queue() {
+    logging::init();
+
+    let (_paths, pool) = read_only_pool(1);
+    let held = pool.get().await.unwrap();
+    let waiter = tokio::spawn({
+        let pool = pool.clone();
+        async move { pool.get().await.map(|storage| storage.waited()) }
+    });
+    until_waiting(&pool, 1).await;
+
+    link_async::sleep(Duration::from_millis(100)).await;
+    drop(held);
+    assert!(pool.try_checkout().await.unwrap().is_none());
+
+    let waited = waiter.await.unwrap().unwrap();
+    assert!(waited >= Duration::from_millis(100));
+}
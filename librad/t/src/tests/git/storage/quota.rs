@@ -0,0 +1,130 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{fs, thread};
+
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{
+    git::storage::{gc, quota, Storage},
+    SecretKey,
+};
+use test_helpers::logging;
+
+#[test]
+fn record_and_reset() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+
+    assert_eq!(0, quota::namespace_usage(&store, &urn).unwrap());
+    quota::record(&store, &urn, 100).unwrap();
+    quota::record(&store, &urn, 23).unwrap();
+    assert_eq!(123, quota::namespace_usage(&store, &urn).unwrap());
+
+    let usage = quota::usage(&store).unwrap();
+    assert_eq!(Some(&123), usage.namespaces.get(&urn));
+    assert!(usage.global > 0);
+
+    quota::reset(&store, &urn).unwrap();
+    assert_eq!(0, quota::namespace_usage(&store, &urn).unwrap());
+    assert!(quota::usage(&store).unwrap().namespaces.is_empty());
+}
+
+#[test]
+fn remaining() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+
+    assert_eq!(
+        None,
+        quota::remaining(&store, &urn, quota::Quota::default()).unwrap()
+    );
+
+    quota::record(&store, &urn, 100).unwrap();
+    let ns = quota::Quota {
+        namespace: Some(150),
+        global: None,
+    };
+    assert_eq!(Some(50), quota::remaining(&store, &urn, ns).unwrap());
+    let exceeded = quota::Quota {
+        namespace: Some(50),
+        global: None,
+    };
+    assert_eq!(Some(0), quota::remaining(&store, &urn, exceeded).unwrap());
+
+    let global = quota::global_usage(&store).unwrap();
+    let both = quota::Quota {
+        namespace: Some(global * 2),
+        global: Some(global + 10),
+    };
+    assert_eq!(Some(10), quota::remaining(&store, &urn, both).unwrap());
+}
+
+#[test]
+fn remove_namespace_resets_usage() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+
+    quota::record(&store, &urn, 42).unwrap();
+    gc::remove_namespace(&store, &urn).unwrap();
+    assert_eq!(0, quota::namespace_usage(&store, &urn).unwrap());
+}
+
+#[test]
+fn concurrent_records_add_up() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let key = SecretKey::new();
+    let urn = {
+        let store = Storage::open(&*paths, key.clone()).unwrap();
+        TestProject::create(&store).unwrap().project.urn()
+    };
+
+    let threads = (0..8)
+        .map(|_| {
+            let paths = (*paths).clone();
+            let key = key.clone();
+            let urn = urn.clone();
+            thread::spawn(move || {
+                let store = Storage::open(&paths, key).unwrap();
+                for _ in 0..10 {
+                    quota::record(&store, &urn, 1).unwrap();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    let store = Storage::open(&*paths, key).unwrap();
+    assert_eq!(80, quota::namespace_usage(&store, &urn).unwrap());
+}
+
+#[test]
+fn global_usage_is_cached_and_recorded_into() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+
+    let before = quota::global_usage(&store).unwrap();
+    // Not observed until the cached value expires
+    fs::write(store.path().join("objects").join("junk"), [0; 1024]).unwrap();
+    assert_eq!(before, quota::global_usage(&store).unwrap());
+
+    quota::record(&store, &urn, 100).unwrap();
+    assert_eq!(before + 100, quota::global_usage(&store).unwrap());
+}
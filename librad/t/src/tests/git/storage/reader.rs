@@ -0,0 +1,140 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::path::Path;
+
+use git_ext::{is_not_found_err, RefLike};
+use git_ref_format::{lit, name, Namespaced, Qualified};
+use it_helpers::{fixed::TestProject, git::create_commit, tmp};
+use librad::{
+    git::{
+        storage::{self, ReadOnlyStorage as _, Reader, Storage},
+        types::{Namespace, Reference},
+        Urn,
+    },
+    SecretKey,
+};
+use test_helpers::logging;
+
+fn main_branch(urn: &Urn) -> Urn {
+    urn.clone().with_path(Some(
+        Qualified::from(lit::refs_heads(name::MAIN))
+            .into_refstring()
+            .into(),
+    ))
+}
+
+#[test]
+fn agrees_with_libgit2() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let store = Storage::open(&*paths, SecretKey::new()).unwrap();
+    let reader = Reader::open(&paths).unwrap();
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+    let unknown = Urn::new(git2::Oid::zero().into());
+
+    assert!(reader.has_urn(&urn).unwrap());
+    assert!(!reader.has_urn(&unknown).unwrap());
+
+    let sigrefs = RefLike::from(&Reference::rad_signed_refs(Namespace::from(&urn), None));
+    let repo = git2::Repository::open(store.path()).unwrap();
+    assert_eq!(
+        reader.reference_oid(&sigrefs).unwrap(),
+        Some(repo.refname_to_id(sigrefs.as_str()).unwrap().into())
+    );
+
+    let branch = Namespaced::from(lit::refs_namespaces(
+        &urn,
+        Qualified::from(lit::refs_heads(name::MAIN)),
+    ));
+    let fst = create_commit(&repo, branch.clone().into_qualified()).unwrap();
+    assert!(reader.has_commit(&main_branch(&urn), fst.into()).unwrap());
+    assert!(!reader.has_tag(&main_branch(&urn), fst.into()).unwrap());
+    assert!(!reader
+        .has_commit(&main_branch(&unknown), fst.into())
+        .unwrap());
+
+    // Written after the reader was opened
+    let snd = create_commit(&repo, branch.into_qualified()).unwrap();
+    assert!(reader.has_object(snd.into()));
+    assert!(reader.has_commit(&main_branch(&urn), snd.into()).unwrap());
+    assert!(reader.has_commit(&main_branch(&urn), fst.into()).unwrap());
+}
+
+#[test]
+fn defers_missing_objects() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let store = Storage::open(&*paths, SecretKey::new()).unwrap();
+    let reader = Reader::open(&paths).unwrap();
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+    let missing: git_ext::Oid = git2::Oid::hash_object(git2::ObjectType::Blob, b"missing")
+        .unwrap()
+        .into();
+
+    assert!(reader.has_commit(&urn, missing).is_err());
+    assert!(reader.has_tag(&urn, missing).is_err());
+    assert!(reader.blob(missing).is_err());
+    assert!(!store.has_commit(&urn, missing).unwrap());
+    assert!(!store.has_tag(&urn, missing).unwrap());
+    let read_only: &storage::ReadOnly = store.as_ref();
+    assert!(read_only.read_blob(missing).unwrap().is_none());
+}
+
+#[test]
+fn reads_blobs() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let store = Storage::open(&*paths, SecretKey::new()).unwrap();
+    let reader = Reader::open(&paths).unwrap();
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+    let at = store
+        .reference_oid(&Reference::rad_signed_refs(Namespace::from(&urn), None))
+        .unwrap();
+
+    let expected = store.blob_at(at, Path::new("refs")).unwrap().unwrap();
+    let blob = reader.blob_at(at, Path::new("refs")).unwrap().unwrap();
+    assert_eq!(blob.id, expected.id().into());
+    assert_eq!(blob.content, expected.content());
+    assert_eq!(reader.blob(blob.id).unwrap(), Some(blob.content.clone()));
+    let read_only: &storage::ReadOnly = store.as_ref();
+    assert_eq!(
+        read_only.read_blob_at(at, Path::new("refs")).unwrap(),
+        Some(blob)
+    );
+
+    assert!(reader
+        .blob_at(at, Path::new("nonexistent"))
+        .unwrap()
+        .is_none());
+    assert!(reader
+        .blob_at(at, Path::new("refs/nested"))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn read_only_storage_semantics() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let store = Storage::open(&*paths, SecretKey::new()).unwrap();
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+
+    let missing = Reference::rad_signed_refs(Namespace::from(&urn), Some(*store.peer_id()));
+    assert!(matches!(
+        store.reference_oid(&missing),
+        Err(storage::Error::Git(e)) if is_not_found_err(&e)
+    ));
+    assert!(!store.has_ref(&missing).unwrap());
+    assert!(store.has_urn(&urn).unwrap());
+}
@@ -0,0 +1,78 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use git_ref_format::{lit, name, Namespaced, Qualified};
+use it_helpers::{fixed::TestProject, git::create_commit, tmp};
+use librad::{
+    git::{
+        refs::{Refs, Updated},
+        storage::{pool, Pool, Storage},
+    },
+    SecretKey,
+};
+use test_helpers::logging;
+
+#[test]
+fn records_signed_refs_updates() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, owner } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+
+    let touched = store.take_touched();
+    assert!(touched.contains_key(&urn));
+    assert!(touched.contains_key(&owner.urn()));
+    assert!(store.take_touched().is_empty());
+
+    let repo = git2::Repository::open(store.path()).unwrap();
+    let branch = Namespaced::from(lit::refs_namespaces(
+        &urn,
+        Qualified::from(lit::refs_heads(name::MAIN)),
+    ));
+    create_commit(&repo, branch.into_qualified()).unwrap();
+    let at = match Refs::update(&store, &urn).unwrap() {
+        Updated::Updated { at, .. } => at,
+        _ => panic!("expected signed refs to be updated"),
+    };
+
+    let touched = store.take_touched();
+    assert_eq!(touched.len(), 1);
+    assert_eq!(touched.get(&urn), Some(&at.into()));
+}
+
+#[test]
+fn ignores_unchanged() {
+    logging::init();
+
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    store.take_touched();
+
+    assert!(matches!(
+        Refs::update(&store, &project.urn()).unwrap(),
+        Updated::Unchanged { .. }
+    ));
+    assert!(store.take_touched().is_empty());
+}
+
+#[tokio::test]
+async fn drained_when_recycled() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let signer = SecretKey::new();
+    Storage::init(&paths, signer.clone()).unwrap();
+    let pool: Pool<Storage> = Pool::new(
+        pool::ReadWriteConfig::new((*paths).clone(), signer, pool::Initialised::no()),
+        1,
+    );
+
+    {
+        let store = pool.get().await.unwrap();
+        TestProject::create(&store).unwrap();
+    }
+    assert!(pool.get().await.unwrap().take_touched().is_empty());
+}
@@ -0,0 +1,109 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use it_helpers::tmp;
+use librad::{
+    git::storage::{transaction, transaction::Previous, Storage},
+    SecretKey,
+};
+use test_helpers::logging;
+
+const A: &str = "refs/namespaces/a/refs/heads/main";
+const B: &str = "refs/namespaces/a/refs/heads/next";
+const HEAD: &str = "refs/namespaces/a/HEAD";
+
+fn oids(store: &Storage) -> (git2::Oid, git2::Oid) {
+    let repo = git2::Repository::open(store.path()).unwrap();
+    (repo.blob(b"fst").unwrap(), repo.blob(b"snd").unwrap())
+}
+
+fn target(store: &Storage, name: &str) -> Option<git2::Oid> {
+    let repo = git2::Repository::open(store.path()).unwrap();
+    repo.refname_to_id(name).ok()
+}
+
+#[test]
+fn applies_all_edits() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let store = Storage::open(&*paths, SecretKey::new()).unwrap();
+    let (fst, snd) = oids(&store);
+
+    let mut tx = store.transaction();
+    tx.update(A, fst, Previous::Absent, "test")
+        .update(B, snd, Previous::Any, "test")
+        .symbolic(HEAD, A, "test");
+    let edited = tx.commit().unwrap();
+
+    assert_eq!(edited, vec![HEAD.to_owned(), A.to_owned(), B.to_owned()]);
+    assert_eq!(target(&store, A), Some(fst));
+    assert_eq!(target(&store, B), Some(snd));
+    assert_eq!(target(&store, HEAD), Some(fst));
+}
+
+#[test]
+fn conflict_applies_nothing() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let store = Storage::open(&*paths, SecretKey::new()).unwrap();
+    let (fst, snd) = oids(&store);
+    let mut tx = store.transaction();
+    tx.update(A, fst, Previous::Absent, "test");
+    tx.commit().unwrap();
+
+    let mut tx = store.transaction();
+    tx.update(A, snd, Previous::Is(snd.into()), "test")
+        .update(B, snd, Previous::Absent, "test");
+    assert!(matches!(
+        tx.commit(),
+        Err(transaction::Error::Conflict { name, .. }) if name == A
+    ));
+    assert_eq!(target(&store, A), Some(fst));
+    assert_eq!(target(&store, B), None);
+}
+
+#[test]
+fn abort_applies_nothing() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let store = Storage::open(&*paths, SecretKey::new()).unwrap();
+    let (fst, _) = oids(&store);
+
+    let mut tx = store.transaction();
+    tx.update(A, fst, Previous::Absent, "test");
+    tx.prepare().unwrap().abort();
+    assert_eq!(target(&store, A), None);
+
+    // The locks are released
+    let mut tx = store.transaction();
+    tx.update(A, fst, Previous::Absent, "test");
+    tx.commit().unwrap();
+    assert_eq!(target(&store, A), Some(fst));
+}
+
+#[test]
+fn reflogs_rad_refs() {
+    logging::init();
+
+    let paths = tmp::paths();
+    let store = Storage::open(&*paths, SecretKey::new()).unwrap();
+    let (fst, _) = oids(&store);
+    let ids = "refs/namespaces/a/refs/rad/id";
+    let remote_ids = "refs/namespaces/a/refs/remotes/b/rad/id";
+
+    let mut tx = store.transaction();
+    tx.update(A, fst, Previous::Absent, "test")
+        .update(ids, fst, Previous::Absent, "test")
+        .update(remote_ids, fst, Previous::Absent, "test");
+    tx.commit().unwrap();
+
+    let repo = git2::Repository::open(store.path()).unwrap();
+    assert_eq!(repo.reflog(ids).unwrap().len(), 1);
+    assert_eq!(repo.reflog(remote_ids).unwrap().len(), 1);
+    assert!(repo.reflog(A).unwrap().is_empty());
+}
@@ -6,11 +6,14 @@
 use std::collections::BTreeSet;
 
 use git_ref_format::{lit, name, Namespaced, Qualified};
-use it_helpers::git::create_commit;
+use it_helpers::{fixed::TestProject, git::create_commit, tmp};
 use librad::{
     git::{
+        identities,
         storage::{ReadOnlyStorage as _, Storage},
         tracking::{
+            auto::{self, Decision},
+            graph::Graph,
             is_tracked,
             migration,
             policy,
@@ -333,3 +336,132 @@ fn migration() {
             .is_some())
     }
 }
+
+#[test]
+fn graph_of_tracked_peers() {
+    let tmp = tempfile::tempdir().unwrap();
+    {
+        let paths = Paths::from_root(&tmp).unwrap();
+        let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+        let urn = Urn::new(git2::Oid::zero().into());
+        let peers = (0..3)
+            .map(|_| PeerId::from(SecretKey::new()))
+            .collect::<BTreeSet<_>>();
+        for peer in &peers {
+            track(
+                &storage,
+                &urn,
+                Some(*peer),
+                Config::default(),
+                policy::Track::Any,
+            )
+            .unwrap()
+            .unwrap();
+        }
+        track(&storage, &urn, None, Config::default(), policy::Track::Any)
+            .unwrap()
+            .unwrap();
+
+        let graph = Graph::load(&storage, 2).unwrap();
+        assert_eq!(graph.urns().collect::<Vec<_>>(), vec![&urn]);
+
+        let providers = graph.providers(&urn);
+        assert_eq!(
+            providers.iter().map(|r| r.peer).collect::<BTreeSet<_>>(),
+            peers
+        );
+        assert!(providers.iter().all(|r| r.degree == 1 && !r.published));
+        assert!(graph.contributors(&urn, 2).is_empty());
+        assert_eq!(graph.peers(), providers);
+
+        assert!(Graph::load(&storage, 0).unwrap().providers(&urn).is_empty());
+    }
+}
+
+#[test]
+fn auto_policy_without_identity() {
+    let urn = Urn::new(git2::Oid::zero().into());
+    let peer = PeerId::from(SecretKey::new());
+    let other = PeerId::from(SecretKey::new());
+
+    let everything = auto::Policy::everything();
+    assert_eq!(
+        everything.evaluate(&peer, &urn, None).unwrap(),
+        Decision::Track
+    );
+
+    let deny_peer = auto::Policy {
+        deny: auto::Rules {
+            peers: std::iter::once(peer).collect(),
+            ..Default::default()
+        },
+        ..everything
+    };
+    assert_eq!(
+        deny_peer.evaluate(&peer, &urn, None).unwrap(),
+        Decision::Ignore
+    );
+    assert_eq!(
+        deny_peer.evaluate(&other, &urn, None).unwrap(),
+        Decision::Track
+    );
+
+    let allow_names = auto::Policy {
+        allow: auto::Rules {
+            names: vec!["radicle-*".to_owned()],
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert_eq!(
+        allow_names.evaluate(&peer, &urn, None).unwrap(),
+        Decision::Pending
+    );
+}
+
+#[test]
+fn auto_policy_with_identity() {
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, owner } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+    let peer = PeerId::from(SecretKey::new());
+
+    let by_name = |pattern: &str| auto::Policy {
+        allow: auto::Rules {
+            names: vec![pattern.to_owned()],
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert_eq!(
+        by_name("radicle-*")
+            .evaluate_in(&*store, &peer, &urn)
+            .unwrap(),
+        Decision::Track
+    );
+    assert_eq!(
+        by_name("heartwood")
+            .evaluate_in(&*store, &peer, &urn)
+            .unwrap(),
+        Decision::Ignore
+    );
+
+    let deny_delegate = auto::Policy {
+        deny: auto::Rules {
+            delegates: vec![auto::Delegate::Person(owner.urn())],
+            ..Default::default()
+        },
+        ..auto::Policy::everything()
+    };
+    let identity = identities::any::get(&*store, &urn).unwrap();
+    assert_eq!(
+        deny_delegate
+            .evaluate(&peer, &urn, identity.as_ref())
+            .unwrap(),
+        Decision::Ignore
+    );
+    assert_eq!(
+        deny_delegate.evaluate(&peer, &urn, None).unwrap(),
+        Decision::Pending
+    );
+}
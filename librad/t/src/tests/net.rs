@@ -4,8 +4,16 @@
 // Linking Exception. For full terms see the included LICENSE file.
 
 mod codec;
+mod dns;
+mod happy_eyeballs;
+mod mdns;
+mod metrics;
 mod peer;
 mod protocol;
+mod quic;
+mod replication;
+mod resolve;
+mod tcp;
 mod tls;
 mod upgrade;
 mod x509;
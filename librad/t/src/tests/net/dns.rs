@@ -0,0 +1,160 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{io::Write as _, net::SocketAddr, time::Duration};
+
+use futures::StreamExt as _;
+use librad::{
+    net::{
+        discovery::{
+            dns::{self, Config, Dns},
+            wire::{self, Builder, Srv, CLASS_IN, TYPE_SRV, TYPE_TXT},
+            Discovery as _,
+        },
+        resolve::{Host, Resolver},
+    },
+    PeerId,
+    SecretKey,
+};
+use tokio::net::UdpSocket;
+
+/// A nameserver publishing `peer` at `127.0.0.1:<port>` under `example.com`,
+/// plus a target lacking a peer id.
+async fn nameserver(peer: PeerId, port: u16) -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = vec![0; 512];
+        loop {
+            let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+            let query = wire::parse(&buf[..len]).unwrap();
+            let question = &query.questions[0];
+            let response = match (question.name.as_str(), question.typ) {
+                ("_radicle._udp.example.com", TYPE_SRV) => {
+                    let mut msg = Builder::new(query.id, wire::FLAG_RESPONSE, 1, 2).question(
+                        &question.name,
+                        TYPE_SRV,
+                        CLASS_IN,
+                    );
+                    for (target, port) in [("127.0.0.1", port), ("localhost", 8776)] {
+                        msg = msg.record(&question.name, TYPE_SRV, CLASS_IN, 60, |rdata| {
+                            for field in [0, 0, port] {
+                                rdata.extend_from_slice(&u16::to_be_bytes(field));
+                            }
+                            wire::name(rdata, target)
+                        });
+                    }
+                    msg
+                },
+                ("127.0.0.1", TYPE_TXT) => {
+                    let id = format!("id={}", peer);
+                    Builder::new(query.id, wire::FLAG_RESPONSE, 1, 1)
+                        .question(&question.name, TYPE_TXT, CLASS_IN)
+                        .record(&question.name, TYPE_TXT, CLASS_IN, 60, |rdata| {
+                            wire::txt(rdata, ["v=spf1 -all", id.as_str()])
+                        })
+                },
+                (name, typ) => Builder::new(query.id, wire::FLAG_RESPONSE | 3, 1, 0)
+                    .question(name, typ, CLASS_IN),
+            };
+            socket.send_to(&response.finish(), from).await.unwrap();
+        }
+    });
+    addr
+}
+
+fn config(nameserver: SocketAddr) -> Config {
+    Config {
+        nameservers: vec![nameserver],
+        timeout: Duration::from_secs(1),
+    }
+}
+
+#[test]
+fn srv_roundtrip() {
+    let msg = Builder::new(42, wire::FLAG_RESPONSE, 0, 1)
+        .record(
+            "_radicle._udp.example.com",
+            TYPE_SRV,
+            CLASS_IN,
+            60,
+            |rdata| {
+                for field in [10u16, 5, 8776] {
+                    rdata.extend_from_slice(&field.to_be_bytes());
+                }
+                wire::name(rdata, "seed.example.com")
+            },
+        )
+        .finish();
+    let parsed = wire::parse(&msg).unwrap();
+
+    assert_eq!(parsed.id, 42);
+    assert_eq!(parsed.records.len(), 1);
+    assert_eq!(
+        parsed.records[0].rdata.srv().unwrap(),
+        Srv {
+            priority: 10,
+            weight: 5,
+            port: 8776,
+            target: "seed.example.com".to_owned(),
+        }
+    );
+}
+
+#[test]
+fn resolv_conf() {
+    let mut conf = tempfile::NamedTempFile::new().unwrap();
+    writeln!(
+        conf,
+        "# generated\nsearch example.com\nnameserver 10.0.0.1\nnameserver ::1\nnameserver bogus"
+    )
+    .unwrap();
+
+    assert_eq!(
+        dns::nameservers(conf.path()).unwrap(),
+        vec![
+            "10.0.0.1:53".parse::<SocketAddr>().unwrap(),
+            "[::1]:53".parse().unwrap()
+        ]
+    );
+}
+
+#[tokio::test]
+async fn looks_up_seeds() {
+    let peer = PeerId::from(SecretKey::new());
+    let ns = nameserver(peer, 8776).await;
+
+    assert_eq!(
+        dns::seeds(&config(ns), "example.com").await.unwrap(),
+        vec![(
+            peer,
+            Host {
+                name: "127.0.0.1".to_owned(),
+                port: 8776
+            }
+        )]
+    );
+    assert_eq!(
+        dns::seeds(&config(ns), "example.org").await.unwrap(),
+        vec![]
+    );
+}
+
+#[tokio::test]
+async fn discovers_seeds() {
+    let peer = PeerId::from(SecretKey::new());
+    let ns = nameserver(peer, 8776).await;
+    let disco = Dns::new(
+        config(ns),
+        Resolver::default(),
+        vec!["example.com".to_owned()],
+    )
+    .refresh(None);
+
+    assert_eq!(
+        disco.discover().collect::<Vec<_>>().await,
+        vec![(peer, vec!["127.0.0.1:8776".parse().unwrap()])]
+    );
+}
@@ -0,0 +1,92 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{net::SocketAddr, time::Duration};
+
+use librad::{
+    net::happy_eyeballs::{interleave, race, Reachability},
+    PeerId,
+    SecretKey,
+};
+
+fn addr(s: &str) -> SocketAddr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn interleaves_families() {
+    let v4a = addr("192.0.2.1:8776");
+    let v4b = addr("192.0.2.2:8776");
+    let v6a = addr("[2001:db8::1]:8776");
+    let v6b = addr("[2001:db8::2]:8776");
+
+    assert_eq!(
+        interleave(vec![v4a, v4b, v6a, v6b]),
+        vec![v6a, v4a, v6b, v4b]
+    );
+    assert_eq!(interleave(vec![v4a, v4b]), vec![v4a, v4b]);
+}
+
+#[test]
+fn prefers_reachable() {
+    let peer = PeerId::from(SecretKey::new());
+    let v4a = addr("192.0.2.1:8776");
+    let v4b = addr("192.0.2.2:8776");
+    let v6a = addr("[2001:db8::1]:8776");
+
+    let reachability = Reachability::new();
+    reachability.failure(peer, v6a);
+    reachability.success(peer, v4b, Duration::from_millis(20));
+
+    assert_eq!(
+        reachability.sort(peer, vec![v6a, v4a, v4b]),
+        vec![v4b, v6a, v4a]
+    );
+
+    reachability.success(peer, v4a, Duration::from_millis(10));
+    assert_eq!(reachability.sort(peer, vec![v4b, v4a]), vec![v4a, v4b]);
+
+    // Other peers are unaffected
+    let other = PeerId::from(SecretKey::new());
+    assert_eq!(reachability.sort(other, vec![v4b, v4a]), vec![v4b, v4a]);
+}
+
+#[tokio::test]
+async fn race_skips_dead_addrs() {
+    let dead = addr("192.0.2.1:8776");
+    let slow = addr("192.0.2.2:8776");
+    let fast = addr("192.0.2.3:8776");
+
+    let winner = race(
+        vec![dead, slow, fast],
+        Duration::from_millis(50),
+        |addr| async move {
+            if addr == dead {
+                Err("dead")
+            } else if addr == slow {
+                link_async::sleep(Duration::from_secs(10)).await;
+                Ok(addr)
+            } else {
+                Ok(addr)
+            }
+        },
+    )
+    .await;
+
+    assert_eq!(winner, Ok(fast));
+}
+
+#[tokio::test]
+async fn race_reports_all_errors() {
+    let a = addr("192.0.2.1:8776");
+    let b = addr("192.0.2.2:8776");
+
+    let res = race(vec![a, b], Duration::from_secs(10), |addr| async move {
+        Err::<(), _>(addr.port())
+    })
+    .await;
+
+    assert_eq!(res, Err(vec![(a, 8776), (b, 8776)]));
+}
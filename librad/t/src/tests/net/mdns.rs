@@ -0,0 +1,101 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::net::SocketAddr;
+
+use librad::{
+    net::discovery::mdns::message::{self, Parsed},
+    PeerId,
+    SecretKey,
+};
+
+#[test]
+fn announcement_roundtrip() {
+    let peer = PeerId::from(SecretKey::new());
+    let addrs: Vec<SocketAddr> = vec![
+        "0.0.0.0:8776".parse().unwrap(),
+        "192.168.1.23:8776".parse().unwrap(),
+    ];
+
+    assert_eq!(
+        message::parse(&message::announcement(&peer, &addrs)).unwrap(),
+        Parsed {
+            query: false,
+            announced: vec![(peer, addrs)],
+        }
+    )
+}
+
+#[test]
+fn query_roundtrip() {
+    assert_eq!(
+        message::parse(&message::query()).unwrap(),
+        Parsed {
+            query: true,
+            announced: vec![],
+        }
+    )
+}
+
+#[test]
+fn ignores_other_services() {
+    let mut msg = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in ["_http", "_tcp", "local"] {
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.extend_from_slice(&[0, 0, 12, 0, 1]);
+
+    assert_eq!(message::parse(&msg).unwrap(), Parsed::default())
+}
+
+#[test]
+fn follows_compressed_names() {
+    let peer = PeerId::from(SecretKey::new());
+    let addr: SocketAddr = "10.0.0.1:8776".parse().unwrap();
+
+    // A response with a PTR record for the service, and a TXT record whose
+    // name points back into the PTR record's rdata
+    let mut msg = vec![0, 0, 0x84, 0, 0, 0, 0, 2, 0, 0, 0, 0];
+    let service = msg.len();
+    for label in message::SERVICE.split('.') {
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+    let instance = peer.default_encoding();
+    msg.extend_from_slice(&[0, 12, 0, 1, 0, 0, 0, 120]);
+    msg.extend_from_slice(&((instance.len() + 3) as u16).to_be_bytes());
+    let instance_at = msg.len();
+    msg.push(instance.len() as u8);
+    msg.extend_from_slice(instance.as_bytes());
+    msg.extend_from_slice(&[0xc0, service as u8]);
+
+    msg.extend_from_slice(&[0xc0, instance_at as u8]);
+    let txt = [format!("id={}", peer), format!("addr={}", addr)];
+    let len = txt.iter().map(|s| s.len() + 1).sum::<usize>();
+    msg.extend_from_slice(&[0, 16, 0x80, 1, 0, 0, 0, 120]);
+    msg.extend_from_slice(&(len as u16).to_be_bytes());
+    for s in &txt {
+        msg.push(s.len() as u8);
+        msg.extend_from_slice(s.as_bytes());
+    }
+
+    assert_eq!(
+        message::parse(&msg).unwrap(),
+        Parsed {
+            query: false,
+            announced: vec![(peer, vec![addr])],
+        }
+    )
+}
+
+#[test]
+fn rejects_truncated() {
+    let peer = PeerId::from(SecretKey::new());
+    let msg = message::announcement(&peer, &["127.0.0.1:8776".parse().unwrap()]);
+
+    assert!(message::parse(&msg[..msg.len() - 4]).is_err())
+}
@@ -0,0 +1,37 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use librad::net::metrics::{names, Metrics as _, Registry};
+
+#[test]
+fn render_counters_and_gauges() {
+    let registry = Registry::new();
+    registry.increment(names::GOSSIP_MESSAGES_RECEIVED, &[("kind", "have")]);
+    registry.increment(names::GOSSIP_MESSAGES_RECEIVED, &[("kind", "have")]);
+    registry.increment(names::GOSSIP_MESSAGES_RECEIVED, &[("kind", "want")]);
+    registry.set(names::STORAGE_POOL_SIZE, &[("pool", "user")], 4.0);
+
+    let out = registry.render();
+    assert!(out.contains("# TYPE link_gossip_messages_received_total counter\n"));
+    assert!(out.contains("link_gossip_messages_received_total{kind=\"have\"} 2\n"));
+    assert!(out.contains("link_gossip_messages_received_total{kind=\"want\"} 1\n"));
+    assert!(out.contains("# TYPE link_storage_pool_size gauge\n"));
+    assert!(out.contains("link_storage_pool_size{pool=\"user\"} 4\n"));
+}
+
+#[test]
+fn render_histograms() {
+    let registry = Registry::new();
+    let labels = &[("outcome", "success")];
+    registry.observe(names::FETCH_DURATION, labels, 0.2);
+    registry.observe(names::FETCH_DURATION, labels, 120.0);
+
+    let out = registry.render();
+    assert!(out.contains("# TYPE link_fetch_duration_seconds histogram\n"));
+    assert!(out.contains("link_fetch_duration_seconds_bucket{outcome=\"success\",le=\"0.1\"} 0\n"));
+    assert!(out.contains("link_fetch_duration_seconds_bucket{outcome=\"success\",le=\"0.25\"} 1\n"));
+    assert!(out.contains("link_fetch_duration_seconds_bucket{outcome=\"success\",le=\"+Inf\"} 2\n"));
+    assert!(out.contains("link_fetch_duration_seconds_count{outcome=\"success\"} 2\n"));
+}
@@ -3,5 +3,16 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+mod address_book;
+mod breaker;
 mod broadcast;
+mod cache;
+mod compression;
+mod egress;
 mod gossip;
+mod health;
+mod membership;
+mod read_access;
+mod request_pull;
+mod review;
+mod throttle;
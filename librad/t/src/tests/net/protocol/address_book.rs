@@ -0,0 +1,45 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::net::SocketAddr;
+
+use librad::{net::protocol::address_book::AddressBook, paths::Paths, PeerId, SecretKey};
+use tempfile::tempdir;
+
+#[test]
+fn roundtrip() {
+    let tmp = tempdir().unwrap();
+    let paths = Paths::from_root(tmp.path()).unwrap();
+    let peer = PeerId::from(SecretKey::new());
+    let addr: SocketAddr = "127.0.0.1:8776".parse().unwrap();
+
+    let book = AddressBook::open(&paths).unwrap();
+    assert!(book.is_empty());
+    book.seen(peer, Some(addr));
+    book.success(peer, addr);
+    book.flush().unwrap();
+
+    let reopened = AddressBook::open(&paths).unwrap();
+    assert_eq!(reopened.get(&peer), book.get(&peer));
+    assert_eq!(reopened.recent(10), vec![(peer, vec![addr])]);
+}
+
+#[test]
+fn order() {
+    let peer = PeerId::from(SecretKey::new());
+    let good: SocketAddr = "127.0.0.1:1".parse().unwrap();
+    let bad: SocketAddr = "127.0.0.1:2".parse().unwrap();
+    let hint: SocketAddr = "127.0.0.1:3".parse().unwrap();
+
+    let book = AddressBook::in_memory();
+    book.success(peer, good);
+    book.failure(peer, bad);
+
+    assert_eq!(book.order(&peer, vec![bad, hint]), vec![good, hint, bad]);
+    assert_eq!(
+        book.order(&PeerId::from(SecretKey::new()), vec![bad, hint]),
+        vec![bad, hint]
+    );
+}
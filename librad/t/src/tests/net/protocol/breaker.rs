@@ -0,0 +1,107 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{net::SocketAddr, thread, time::Duration};
+
+use librad::{
+    net::protocol::breaker::{Breakers, Quota},
+    PeerId,
+    SecretKey,
+};
+use nonzero_ext::nonzero;
+
+const BACKOFF: Duration = Duration::from_millis(50);
+
+fn breakers() -> Breakers {
+    Breakers::new(Quota {
+        max_failures: nonzero!(2u32),
+        backoff: BACKOFF,
+        max_backoff: BACKOFF * 4,
+        ..Quota::default()
+    })
+}
+
+fn addr(port: u16) -> SocketAddr {
+    SocketAddr::from(([192, 0, 2, 1], port))
+}
+
+#[test]
+fn closed_open_half_open_closed() {
+    let breakers = breakers();
+    let peer = PeerId::from(SecretKey::new());
+
+    // Closed
+    for _ in 0..2 {
+        let permit = breakers.permit(peer, addr(1)).unwrap();
+        assert!(!permit.is_probe());
+        permit.failure();
+    }
+
+    // Open
+    assert!(breakers.permit(peer, addr(1)).is_none());
+    assert_eq!(breakers.stats().open, 1);
+    assert_eq!(breakers.stats().refused, 1);
+    // Other addresses are unaffected
+    assert!(breakers.permit(peer, addr(2)).is_some());
+
+    // Half-open: a single probe
+    thread::sleep(BACKOFF);
+    let probe = breakers.permit(peer, addr(1)).unwrap();
+    assert!(probe.is_probe());
+    assert_eq!(breakers.stats().half_open, 1);
+    assert!(breakers.permit(peer, addr(1)).is_none());
+
+    // Closed
+    probe.success();
+    assert!(breakers.is_empty());
+    assert!(!breakers.permit(peer, addr(1)).unwrap().is_probe());
+}
+
+#[test]
+fn failed_probe_backs_off() {
+    let breakers = breakers();
+    let peer = PeerId::from(SecretKey::new());
+    for _ in 0..2 {
+        breakers.permit(peer, addr(1)).unwrap().failure();
+    }
+
+    thread::sleep(BACKOFF);
+    breakers.permit(peer, addr(1)).unwrap().failure();
+    assert_eq!(breakers.stats().open, 1);
+
+    // The backoff doubled
+    thread::sleep(BACKOFF);
+    assert!(breakers.permit(peer, addr(1)).is_none());
+    thread::sleep(BACKOFF);
+    assert!(breakers.permit(peer, addr(1)).unwrap().is_probe());
+}
+
+#[test]
+fn cancelled_probe_is_released() {
+    let breakers = breakers();
+    let peer = PeerId::from(SecretKey::new());
+    for _ in 0..2 {
+        breakers.permit(peer, addr(1)).unwrap().failure();
+    }
+
+    thread::sleep(BACKOFF);
+    drop(breakers.permit(peer, addr(1)).unwrap());
+    assert_eq!(breakers.stats().half_open, 0);
+    assert_eq!(breakers.stats().open, 1);
+    assert!(breakers.permit(peer, addr(1)).unwrap().is_probe());
+}
+
+#[test]
+fn bounded() {
+    let breakers = Breakers::new(Quota {
+        max_breakers: 3,
+        ..Quota::default()
+    });
+    let peer = PeerId::from(SecretKey::new());
+    for port in 0..10 {
+        breakers.permit(peer, addr(port)).unwrap().failure();
+    }
+    assert_eq!(breakers.len(), 3);
+}
@@ -0,0 +1,193 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{thread, time::Duration};
+
+use tempfile::tempdir;
+
+use librad::{
+    git::Urn,
+    git_ext,
+    net::protocol::{
+        cache::{
+            negative::{Misses, Negative},
+            provided::Provided,
+            seen::{Kind, Recent, MAX_ORIGINS},
+        },
+        gossip,
+    },
+    reflike,
+    PeerId,
+    SecretKey,
+};
+
+fn urn(n: u8) -> Urn {
+    Urn::new(git_ext::Oid::from(
+        git2::Oid::hash_object(git2::ObjectType::Blob, &[n]).unwrap(),
+    ))
+}
+
+#[test]
+fn most_recent_first() {
+    let recent = Recent::default();
+    let alice = PeerId::from(SecretKey::new());
+    let bob = PeerId::from(SecretKey::new());
+
+    recent.record(urn(0), alice, Kind::Have);
+    recent.record(urn(1), alice, Kind::Want);
+    recent.record(
+        urn(0).with_path(reflike!("refs/heads/main")),
+        bob,
+        Kind::Have,
+    );
+
+    let seen = recent.get();
+    assert_eq!(
+        seen.iter().map(|s| s.urn.clone()).collect::<Vec<_>>(),
+        vec![urn(0), urn(1)]
+    );
+    assert_eq!(
+        seen[0].origins.iter().map(|o| o.peer).collect::<Vec<_>>(),
+        vec![bob, alice]
+    );
+    assert_eq!(seen[1].origins[0].kind, Kind::Want);
+    assert!(seen[0].last_seen >= seen[1].last_seen);
+}
+
+#[test]
+fn bounded() {
+    let recent = Recent::new(Duration::from_secs(60), 2);
+    let origins = (0..=MAX_ORIGINS)
+        .map(|_| PeerId::from(SecretKey::new()))
+        .collect::<Vec<_>>();
+    for origin in &origins {
+        recent.record(urn(0), *origin, Kind::Have);
+    }
+    recent.record(urn(1), origins[0], Kind::Have);
+    recent.record(urn(2), origins[0], Kind::Have);
+
+    let seen = recent.get();
+    assert_eq!(
+        seen.iter().map(|s| s.urn.clone()).collect::<Vec<_>>(),
+        vec![urn(2), urn(1)]
+    );
+
+    recent.record(urn(0), origins[0], Kind::Have);
+    let seen = recent.get();
+    assert_eq!(seen[0].urn, urn(0));
+    assert_eq!(seen[0].origins.len(), 1);
+
+    for origin in &origins {
+        recent.record(urn(3), *origin, Kind::Have);
+    }
+    let seen = recent.get();
+    assert_eq!(seen[0].origins.len(), MAX_ORIGINS);
+    assert!(!seen[0].origins.iter().any(|o| o.peer == origins[0]));
+}
+
+#[test]
+fn sliding_window() {
+    let recent = Recent::new(Duration::from_millis(50), 10);
+    let peer = PeerId::from(SecretKey::new());
+
+    recent.record(urn(0), peer, Kind::Have);
+    thread::sleep(Duration::from_millis(100));
+    recent.record(urn(1), peer, Kind::Have);
+
+    let seen = recent.get();
+    assert_eq!(
+        seen.iter().map(|s| s.urn.clone()).collect::<Vec<_>>(),
+        vec![urn(1)]
+    );
+    thread::sleep(Duration::from_millis(100));
+    assert!(recent.get().is_empty());
+    assert!(recent.is_empty());
+}
+
+fn have(n: u8) -> gossip::Payload {
+    gossip::Payload {
+        urn: urn(n),
+        rev: Some(gossip::Rev::Git(
+            git2::Oid::hash_object(git2::ObjectType::Commit, &[n]).unwrap(),
+        )),
+        origin: None,
+        cob: None,
+        batch: None,
+    }
+}
+
+#[test]
+fn provided_survives_reopen() {
+    let tmp = tempdir().unwrap();
+    {
+        let provided = Provided::open_in(tmp.path(), Duration::from_secs(60), 1024).unwrap();
+        assert!(!provided.contains(&have(0)));
+        provided.record(&have(0));
+        assert!(provided.contains(&have(0)));
+    }
+    let provided = Provided::open_in(tmp.path(), Duration::from_secs(60), 1024).unwrap();
+    assert!(provided.contains(&have(0)));
+    assert!(!provided.contains(&have(1)));
+}
+
+#[test]
+fn provided_expires() {
+    let tmp = tempdir().unwrap();
+    let provided = Provided::open_in(tmp.path(), Duration::from_secs(0), 1024).unwrap();
+    provided.record(&have(0));
+    assert!(!provided.contains(&have(0)));
+}
+
+#[test]
+fn provided_disabled() {
+    let provided = Provided::default();
+    provided.record(&have(0));
+    assert!(!provided.contains(&have(0)));
+}
+
+#[test]
+fn negative_expires() {
+    let misses = Negative::new(Duration::from_millis(50), 10);
+    misses.insert(urn(0));
+    assert!(misses.contains(&urn(0)));
+    assert!(!misses.contains(&urn(1)));
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(!misses.contains(&urn(0)));
+    assert!(misses.is_empty());
+}
+
+#[test]
+fn negative_bounded() {
+    let misses = Negative::new(Duration::from_secs(60), 2);
+    for n in 0..3 {
+        misses.insert(urn(n));
+    }
+    assert_eq!(misses.len(), 2);
+    assert!(!misses.contains(&urn(0)));
+    assert!(misses.contains(&urn(1)));
+    assert!(misses.contains(&urn(2)));
+}
+
+#[test]
+fn negative_busted_by_have() {
+    let misses = Misses::default();
+    let alice = PeerId::from(SecretKey::new());
+    let bob = PeerId::from(SecretKey::new());
+
+    misses.providers.insert(urn(0));
+    misses.remote.insert((alice, urn(0)));
+    misses.remote.insert((alice, urn(1)));
+    misses.peers.insert(alice);
+    misses.peers.insert(bob);
+
+    misses.bust(&urn(0).with_path(reflike!("refs/heads/main")), alice);
+
+    assert!(!misses.providers.contains(&urn(0)));
+    assert!(!misses.remote.contains(&(alice, urn(0))));
+    assert!(misses.remote.contains(&(alice, urn(1))));
+    assert!(!misses.peers.contains(&alice));
+    assert!(misses.peers.contains(&bob));
+}
@@ -0,0 +1,139 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{collections::BTreeSet, net::SocketAddr};
+
+use librad::net::protocol::{
+    compression::{self, Config},
+    Capability,
+    PeerAdvertisement,
+};
+use minicbor::{Decode, Encode};
+
+#[test]
+fn roundtrip() {
+    let bytes = b"rad:git:hnrkyghsrokxzxpy9pww69xr11dr9q7edbxfo".repeat(16);
+    let compressed = Config::default().compress(&bytes).unwrap();
+    assert!(compressed.len() < bytes.len());
+    assert_eq!(compression::decompress(&compressed).unwrap(), bytes)
+}
+
+#[test]
+fn decompression_is_bounded() {
+    let bomb = Config::default()
+        .compress(&vec![0; compression::MAX_DECOMPRESSED + 1])
+        .unwrap();
+    assert!(compression::decompress(&bomb).is_err())
+}
+
+#[test]
+fn negotiate() {
+    let zstd = BTreeSet::from([Capability::Zstd]);
+    let enabled = Config::default();
+    let disabled = Config {
+        enabled: false,
+        ..Config::default()
+    };
+
+    assert_eq!(enabled.capabilities(), zstd);
+    assert!(disabled.capabilities().is_empty());
+
+    assert!(enabled.negotiate(&zstd).is_some());
+    assert!(enabled.negotiate(&BTreeSet::new()).is_none());
+    assert!(disabled.negotiate(&zstd).is_none());
+}
+
+/// A [`Capability`] as known to a future version.
+#[derive(Encode)]
+enum FutureCapability {
+    #[n(1)]
+    Zstd,
+    #[n(42)]
+    Future,
+}
+
+/// A [`PeerAdvertisement`] as sent by a future version.
+#[derive(Encode)]
+#[cbor(array)]
+struct FutureAdvertisement {
+    #[n(0)]
+    listen_addrs: Vec<SocketAddr>,
+    #[n(2)]
+    capabilities: Vec<FutureCapability>,
+}
+
+#[test]
+fn unknown_capabilities_are_skipped() {
+    let addr: SocketAddr = "127.0.0.1:8776".parse().unwrap();
+    let bytes = minicbor::to_vec(FutureAdvertisement {
+        listen_addrs: vec![addr],
+        capabilities: vec![FutureCapability::Future, FutureCapability::Zstd],
+    })
+    .unwrap();
+
+    let ad = minicbor::decode::<PeerAdvertisement<SocketAddr>>(&bytes).unwrap();
+    assert_eq!(ad.listen_addrs.into_inner(), vec![addr]);
+    assert_eq!(ad.capabilities, BTreeSet::from([Capability::Zstd]))
+}
+
+/// A [`Capability`] as known to versions predating compression.
+#[derive(Debug, Encode, Decode, PartialEq, Eq, PartialOrd, Ord)]
+enum LegacyCapability {
+    #[n(0)]
+    Reserved,
+}
+
+/// A [`PeerAdvertisement`] as known to versions predating compression.
+#[derive(Encode, Decode)]
+#[cbor(array)]
+struct LegacyAdvertisement {
+    #[n(0)]
+    listen_addrs: Vec<SocketAddr>,
+    #[n(2)]
+    capabilities: BTreeSet<LegacyCapability>,
+}
+
+#[test]
+fn legacy_peers_ignore_zstd() {
+    let addr: SocketAddr = "127.0.0.1:8776".parse().unwrap();
+    let mut ad = PeerAdvertisement::new(addr);
+    ad.capabilities = BTreeSet::from([Capability::Reserved, Capability::Zstd]);
+    let bytes = minicbor::to_vec(&ad).unwrap();
+
+    let legacy = minicbor::decode::<LegacyAdvertisement>(&bytes).unwrap();
+    assert_eq!(legacy.listen_addrs, vec![addr]);
+    assert_eq!(
+        legacy.capabilities,
+        BTreeSet::from([LegacyCapability::Reserved])
+    );
+
+    assert_eq!(
+        minicbor::decode::<PeerAdvertisement<SocketAddr>>(&bytes).unwrap(),
+        ad
+    );
+}
+
+#[test]
+fn legacy_advertisements_decode() {
+    let addr: SocketAddr = "127.0.0.1:8776".parse().unwrap();
+    let legacy = LegacyAdvertisement {
+        listen_addrs: vec![addr],
+        capabilities: BTreeSet::from([LegacyCapability::Reserved]),
+    };
+    let bytes = minicbor::to_vec(&legacy).unwrap();
+
+    let ad = minicbor::decode::<PeerAdvertisement<SocketAddr>>(&bytes).unwrap();
+    assert_eq!(ad.listen_addrs.into_inner(), vec![addr]);
+    assert_eq!(ad.capabilities, BTreeSet::from([Capability::Reserved]));
+    // Encodes the same as before compression was introduced
+    assert_eq!(
+        minicbor::to_vec(&PeerAdvertisement {
+            capabilities: BTreeSet::from([Capability::Reserved]),
+            ..PeerAdvertisement::new(addr)
+        })
+        .unwrap(),
+        bytes
+    );
+}
@@ -0,0 +1,149 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{iter, net::SocketAddr, time::Duration};
+
+use librad::{
+    git::Urn,
+    git_ext,
+    net::protocol::{
+        broadcast,
+        egress::{DropPolicy, Egress, Message, Quota},
+        gossip::Payload,
+        io::Rpc,
+        membership,
+        PeerAdvertisement,
+        PeerInfo,
+    },
+    PeerId,
+    SecretKey,
+};
+use nonzero_ext::nonzero;
+
+fn addr() -> SocketAddr {
+    "127.0.0.1:8776".parse().unwrap()
+}
+
+fn gossip(n: u8) -> Message {
+    let origin = PeerInfo {
+        peer_id: PeerId::from(SecretKey::from_seed([n; 32])),
+        advertised_info: PeerAdvertisement::new(addr()),
+        seen_addrs: iter::empty().into(),
+    };
+    let payload = Payload {
+        urn: Urn::new(git_ext::Oid::from(git2::Oid::zero())),
+        rev: None,
+        origin: None,
+        cob: None,
+        batch: None,
+    };
+    broadcast::Message::have(origin, payload).into()
+}
+
+fn membership() -> Message {
+    membership::Message::Join {
+        info: PeerAdvertisement::new(addr()),
+    }
+    .into()
+}
+
+fn origin_of(msg: Message) -> Option<PeerId> {
+    match msg {
+        Rpc::Gossip(broadcast::Message::Have { origin, .. }) => Some(origin.peer_id),
+        _ => None,
+    }
+}
+
+fn egress(drop_policy: DropPolicy) -> Egress {
+    Egress::new(Quota {
+        max_gossip: nonzero!(2usize),
+        drop_policy,
+        ready_timeout: Duration::from_millis(100),
+    })
+}
+
+#[test]
+fn drop_oldest_keeps_membership() {
+    let egress = egress(DropPolicy::DropOldest);
+    let to = PeerId::from(SecretKey::new());
+
+    assert!(egress.push(to, gossip(1)));
+    assert!(!egress.push(to, membership()));
+    assert!(!egress.push(to, gossip(2)));
+    assert!(!egress.push(to, gossip(3)));
+    assert_eq!(egress.stats().dropped, 1);
+    assert_eq!(egress.stats().queued, 3);
+
+    assert!(matches!(egress.pop(&to), Some(Rpc::Membership(_))));
+    assert_eq!(
+        origin_of(egress.pop(&to).unwrap()),
+        Some(PeerId::from(SecretKey::from_seed([2; 32])))
+    );
+    assert_eq!(
+        origin_of(egress.pop(&to).unwrap()),
+        Some(PeerId::from(SecretKey::from_seed([3; 32])))
+    );
+    assert!(egress.pop(&to).is_none());
+    // Queue is gone, the next push must start draining again
+    assert!(egress.push(to, gossip(4)));
+}
+
+#[test]
+fn drop_newest() {
+    let egress = egress(DropPolicy::DropNewest);
+    let to = PeerId::from(SecretKey::new());
+
+    egress.push(to, gossip(1));
+    egress.push(to, gossip(2));
+    egress.push(to, gossip(3));
+    assert_eq!(egress.stats().dropped, 1);
+    assert_eq!(
+        origin_of(egress.pop(&to).unwrap()),
+        Some(PeerId::from(SecretKey::from_seed([1; 32])))
+    );
+}
+
+#[tokio::test]
+async fn ready_when_popped() {
+    let egress = egress(DropPolicy::DropOldest);
+    let to = PeerId::from(SecretKey::new());
+    egress.push(to, gossip(1));
+    egress.push(to, gossip(2));
+
+    let ready = tokio::spawn({
+        let egress = egress.clone();
+        async move { egress.ready(&to).await }
+    });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    egress.pop(&to);
+
+    assert!(ready.await.unwrap())
+}
+
+#[tokio::test]
+async fn ready_times_out_on_stalled_peer() {
+    let egress = egress(DropPolicy::DropOldest);
+    let stalled = PeerId::from(SecretKey::new());
+    let other = PeerId::from(SecretKey::new());
+    egress.push(stalled, gossip(1));
+    egress.push(stalled, gossip(2));
+
+    assert!(egress.ready(&other).await);
+    assert!(!egress.ready(&stalled).await);
+}
+
+#[test]
+fn discard() {
+    let egress = egress(DropPolicy::DropOldest);
+    let to = PeerId::from(SecretKey::new());
+    egress.push(to, membership());
+    egress.push(to, gossip(1));
+    egress.discard(&to);
+
+    let stats = egress.stats();
+    assert_eq!(stats.queued, 0);
+    assert_eq!(stats.discarded, 2);
+    assert!(egress.pop(&to).is_none());
+}
@@ -3,7 +3,17 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use librad::{git::Urn, git_ext, net::protocol::gossip::*, PeerId, SecretKey};
+use std::str::FromStr as _;
+
+use librad::{
+    collaborative_objects::{ObjectId, TypeName},
+    git::Urn,
+    git_ext,
+    net::protocol::gossip::*,
+    reflike,
+    PeerId,
+    SecretKey,
+};
 use test_helpers::roundtrip;
 
 lazy_static! {
@@ -22,7 +32,111 @@ fn roundtrip_payload() {
         urn: Urn::new(git_ext::Oid::from(git2::Oid::zero())),
         rev: Some(Rev::Git(*OID)),
         origin: Some(PeerId::from(SecretKey::new())),
+        cob: None,
+        batch: None,
     };
 
     roundtrip::cbor(payload)
 }
+
+#[test]
+fn roundtrip_cob_payload() {
+    let payload = Payload::cob(
+        Urn::new(git_ext::Oid::from(git2::Oid::zero())),
+        TypeName::from_str("xyz.radicle.issue").unwrap(),
+        ObjectId::from(*OID),
+        vec![*OID],
+        Some(PeerId::from(SecretKey::new())),
+    );
+
+    roundtrip::cbor(payload)
+}
+
+#[test]
+fn cob_refname() {
+    let object_id = ObjectId::from(*OID);
+    let payload = Payload::cob(
+        Urn::new(git_ext::Oid::from(git2::Oid::zero())),
+        TypeName::from_str("xyz.radicle.issue").unwrap(),
+        object_id,
+        vec![*OID],
+        None,
+    );
+
+    assert_eq!(
+        payload.cob.unwrap().refname().as_str(),
+        format!("refs/cobs/xyz.radicle.issue/{}", object_id)
+    )
+}
+
+#[test]
+fn roundtrip_batched_payload() {
+    let payload = Payload {
+        urn: Urn::new(git_ext::Oid::from(git2::Oid::zero())),
+        rev: Some(Rev::Git(*OID)),
+        origin: None,
+        cob: None,
+        batch: Some(vec![
+            Tip {
+                path: Some(reflike!("refs/heads/next")),
+                rev: Rev::Git(*OID),
+            },
+            Tip {
+                path: None,
+                rev: Rev::Git(*OID),
+            },
+        ]),
+    };
+
+    roundtrip::cbor(payload)
+}
+
+#[test]
+fn batching_coalesces_and_deduplicates() {
+    let batcher = batch::Batcher::new(batch::Config::default());
+    let urn = Urn::new(git_ext::Oid::from(git2::Oid::zero()));
+    let have = |path: git_ext::RefLike| Payload {
+        urn: urn.clone().with_path(path),
+        rev: Some(Rev::Git(*OID)),
+        origin: None,
+        cob: None,
+        batch: None,
+    };
+
+    let key = match batcher.push(have(reflike!("refs/heads/main"))) {
+        batch::Push::Opened(key) => key,
+        x => panic!("expected new batch, got {:?}", x),
+    };
+    assert!(matches!(
+        batcher.push(have(reflike!("refs/heads/next"))),
+        batch::Push::Joined
+    ));
+    assert!(matches!(
+        batcher.push(have(reflike!("refs/heads/main"))),
+        batch::Push::Joined
+    ));
+
+    let compound = batcher.take(&key).unwrap();
+    assert_eq!(
+        compound.unbatch().collect::<Vec<_>>(),
+        vec![
+            have(reflike!("refs/heads/main")),
+            have(reflike!("refs/heads/next"))
+        ]
+    );
+    assert!(batcher.take(&key).is_none());
+}
+
+#[test]
+fn batching_bypass() {
+    let batcher = batch::Batcher::new(batch::Config::default());
+    let query = Payload {
+        urn: Urn::new(git_ext::Oid::from(git2::Oid::zero())),
+        rev: None,
+        origin: None,
+        cob: None,
+        batch: None,
+    };
+
+    assert!(matches!(batcher.push(query), batch::Push::Bypass(_)))
+}
@@ -0,0 +1,33 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::net::SocketAddr;
+
+use librad::{
+    net::protocol::health::{Status, Watchlist},
+    PeerId,
+    SecretKey,
+};
+
+#[test]
+fn watchlist() {
+    let peer = PeerId::from(SecretKey::new());
+    let addr: SocketAddr = "127.0.0.1:8776".parse().unwrap();
+
+    let watch = Watchlist::default();
+    let handle = watch.clone();
+    watch.watch(peer, Some(addr));
+    watch.watch(peer, Some(addr));
+
+    assert!(handle.contains(&peer));
+    assert_eq!(
+        handle.status().into_iter().collect::<Vec<_>>(),
+        vec![(peer, Status::default())]
+    );
+
+    assert!(handle.unwatch(&peer));
+    assert!(!watch.contains(&peer));
+    assert!(!watch.unwatch(&peer));
+}
@@ -0,0 +1,75 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::net::SocketAddr;
+
+use librad::{
+    net::protocol::membership::{
+        snapshot::{Direction, Member, ShuffleEvent},
+        Params,
+        Snapshot,
+    },
+    PeerId,
+    SecretKey,
+};
+
+fn snapshot() -> Snapshot<SocketAddr> {
+    let peer = || PeerId::from(SecretKey::new());
+    let active = peer();
+    let passive = peer();
+    let sampled = peer();
+
+    Snapshot {
+        local_id: peer(),
+        params: Params::default(),
+        active: vec![Member {
+            peer_id: active,
+            addrs: vec![([127, 0, 0, 1], 12345).into()],
+        }],
+        passive: vec![Member {
+            peer_id: passive,
+            addrs: vec![],
+        }],
+        shuffles: vec![ShuffleEvent {
+            timestamp: 0,
+            direction: Direction::Sent,
+            peer: active,
+            sample: vec![sampled],
+        }],
+    }
+}
+
+#[test]
+fn dot_edges() {
+    let snap = snapshot();
+    let dot = snap.to_dot();
+
+    assert!(dot.starts_with("digraph membership {"));
+    assert!(dot.contains(&format!(
+        "\"{}\" -> \"{}\" [style=solid];",
+        snap.local_id, snap.active[0].peer_id
+    )));
+    assert!(dot.contains(&format!(
+        "\"{}\" -> \"{}\" [style=dashed];",
+        snap.local_id, snap.passive[0].peer_id
+    )));
+    assert!(dot.contains(&format!(
+        "\"{}\" -> \"{}\" [style=dotted];",
+        snap.shuffles[0].peer, snap.shuffles[0].sample[0]
+    )));
+    assert!(dot.contains("127.0.0.1:12345"));
+}
+
+#[test]
+fn serializes() {
+    let snap = snapshot();
+    let json = serde_json::to_value(&snap).unwrap();
+
+    assert_eq!(json["active"].as_array().unwrap().len(), 1);
+    assert_eq!(json["passive"].as_array().unwrap().len(), 1);
+    assert_eq!(json["shuffles"][0]["direction"], "sent");
+    assert_eq!(
+        json["params"]["max_active"],
+        Params::default().max_active as u64
+    );
+}
@@ -0,0 +1,52 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use librad::{
+    git::Urn,
+    net::protocol::read_access::{Category, Decision, ReadAccess},
+    PeerId,
+    SecretKey,
+};
+
+#[test]
+fn allow_all_by_default() {
+    let peer = PeerId::from(SecretKey::new());
+    assert_eq!(
+        Decision::Allow,
+        ReadAccess::default().decide(&peer, "not a urn")
+    );
+}
+
+#[test]
+fn callback() {
+    let trusted = PeerId::from(SecretKey::new());
+    let stranger = PeerId::from(SecretKey::new());
+    let urn = Urn::new(git2::Oid::zero().into());
+    let access = ReadAccess::new(move |peer, _| {
+        if peer == &trusted {
+            Decision::Allow
+        } else {
+            Decision::Hide(vec![Category::Remotes])
+        }
+    });
+
+    let namespace = urn.encode_id();
+    assert_eq!(Decision::Allow, access.decide(&trusted, &namespace));
+    assert_eq!(
+        Decision::Hide(vec![Category::Remotes]),
+        access.decide(&stranger, &namespace)
+    );
+    assert_eq!(Decision::Deny, access.decide(&trusted, "not a urn"));
+}
+
+#[test]
+fn patterns() {
+    let peer = PeerId::from(SecretKey::new());
+    assert_eq!("refs/heads", Category::Heads.pattern());
+    assert_eq!(
+        format!("refs/remotes/{}", peer),
+        Category::Remote(peer).pattern()
+    );
+}
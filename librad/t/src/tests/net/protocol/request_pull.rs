@@ -0,0 +1,124 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::collections::BTreeSet;
+
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{
+    git::{storage, Storage, Urn},
+    net::protocol::request_pull::{
+        policy::{self, Authorization, Decision},
+        progress,
+        Phase,
+        Progress,
+    },
+    paths::Paths,
+    PeerId,
+    SecretKey,
+};
+
+fn pool(paths: &Paths) -> storage::Pool<storage::ReadOnly> {
+    storage::Pool::new(storage::pool::ReadConfig::new(paths.clone()), 1)
+}
+
+#[tokio::test]
+async fn reject_is_reported() {
+    let paths = tmp::paths();
+    let policy = Authorization::new(|_: &PeerId, _: &Urn| Decision::Reject {
+        reason: "not today".to_owned(),
+    });
+    let urn = Urn::new(git2::Oid::zero().into());
+    let err = policy
+        .authorize(&pool(&paths), &PeerId::from(SecretKey::new()), &urn)
+        .await
+        .unwrap_err();
+
+    assert_matches!(err, policy::Error::Rejected { .. });
+    assert!(err.to_string().ends_with("not today"))
+}
+
+#[tokio::test]
+async fn require_delegate() {
+    let paths = tmp::paths();
+    let storage = Storage::open(&paths, SecretKey::new()).unwrap();
+    let TestProject { project, owner } = TestProject::create(&storage).unwrap();
+    let peer = PeerId::from(SecretKey::new());
+    let pool = pool(&paths);
+
+    let allowed = BTreeSet::from([owner.urn()]);
+    let policy = Authorization::new(move |_: &PeerId, _: &Urn| Decision::RequireDelegate {
+        allowed: allowed.clone(),
+    });
+    assert!(policy.authorize(&pool, &peer, &project.urn()).await.is_ok());
+
+    let policy = Authorization::new(|_: &PeerId, _: &Urn| Decision::RequireDelegate {
+        allowed: BTreeSet::new(),
+    });
+    assert_matches!(
+        policy.authorize(&pool, &peer, &project.urn()).await,
+        Err(policy::Error::NotDelegated(_))
+    )
+}
+
+#[tokio::test]
+async fn require_delegate_does_not_wait_for_storage_when_allowed() {
+    let paths = tmp::paths();
+    Storage::open(&paths, SecretKey::new()).unwrap();
+    let pool = pool(&paths);
+    let _exhausted = pool.get().await.unwrap();
+
+    let urn = Urn::new(git2::Oid::zero().into());
+    let allowed = BTreeSet::from([urn.clone()]);
+    let policy = Authorization::new(move |_: &PeerId, _: &Urn| Decision::RequireDelegate {
+        allowed: allowed.clone(),
+    });
+    assert!(policy
+        .authorize(&pool, &PeerId::from(SecretKey::new()), &urn)
+        .await
+        .is_ok());
+}
+
+#[test]
+fn progress_phases_roundtrip() {
+    test_helpers::roundtrip::cbor(progress::receiving(3, Some(12), 4096));
+    test_helpers::roundtrip::cbor(progress::negotiated(7));
+    test_helpers::roundtrip::cbor(progress::guard("tracked"));
+}
+
+#[test]
+fn progress_percentage() {
+    assert_eq!(
+        progress::receiving(3, Some(12), 4096).percentage(),
+        Some(25)
+    );
+    assert_eq!(progress::receiving(3, None, 4096).percentage(), None);
+    assert_eq!(progress::receiving(0, Some(0), 0).percentage(), None);
+    assert_eq!(progress::negotiated(7).percentage(), None);
+}
+
+#[test]
+fn progress_without_phase_decodes() {
+    #[derive(minicbor::Encode)]
+    #[cbor(array)]
+    struct Legacy {
+        #[n(0)]
+        message: String,
+    }
+
+    let legacy = minicbor::to_vec(Legacy {
+        message: "hello".to_owned(),
+    })
+    .unwrap();
+    let progress = minicbor::decode::<Progress>(&legacy).unwrap();
+    assert_eq!(
+        progress,
+        Progress {
+            message: "hello".to_owned(),
+            phase: None,
+        }
+    );
+    assert_matches!(
+        progress::negotiated(1).phase,
+        Some(Phase::Negotiated { refs: 1 })
+    )
+}
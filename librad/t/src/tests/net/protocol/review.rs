@@ -0,0 +1,161 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{
+    collaborative_objects::NewObjectSpec,
+    git::{
+        identities::{self, local::LocalIdentity, review::Review},
+        Storage,
+        Urn,
+    },
+    identities::{
+        delegation,
+        payload::{self, ProjectPayload},
+    },
+    net::protocol::request_pull::review::{self, Missing, Snapshot},
+    PeerId,
+    SecretKey,
+};
+
+struct Reviewed {
+    urn: Urn,
+    whoami: LocalIdentity,
+}
+
+fn reviewed(store: &Storage, docs: SecretKey) -> Reviewed {
+    let owner = TestProject::create(store).unwrap().owner;
+    let policy: Review = serde_json::from_value(serde_json::json!({
+        "rules": [
+            { "path": "", "approvers": [store.peer_id().as_public_key()] },
+            { "path": "docs", "approvers": [docs.public()] },
+        ]
+    }))
+    .unwrap();
+    let whoami = identities::local::load(store, owner.urn())
+        .unwrap()
+        .unwrap();
+    let payload = ProjectPayload::new(payload::Project {
+        name: "reviewed".into(),
+        description: None,
+        default_branch: Some("main".into()),
+    })
+    .with_ext(policy)
+    .unwrap();
+    let urn = identities::project::create(
+        store,
+        whoami.clone(),
+        payload,
+        delegation::Indirect::from(owner),
+    )
+    .unwrap()
+    .urn();
+
+    Reviewed { urn, whoami }
+}
+
+fn commit(repo: &git2::Repository, parent: Option<git2::Oid>, docs: bool) -> git2::Oid {
+    let mut tree = repo.treebuilder(None).unwrap();
+    let readme = repo.blob(b"reviewed\n").unwrap();
+    tree.insert("README", readme, 0o100644).unwrap();
+    if docs {
+        let mut sub = repo.treebuilder(None).unwrap();
+        let guide = repo.blob(b"read the code\n").unwrap();
+        sub.insert("guide", guide, 0o100644).unwrap();
+        tree.insert("docs", sub.write().unwrap(), 0o040000).unwrap();
+    }
+    let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+    let sig = git2::Signature::now("reviewer", "reviewer@example.com").unwrap();
+    let parents = parent
+        .map(|oid| repo.find_commit(oid).unwrap())
+        .into_iter()
+        .collect::<Vec<_>>();
+    repo.commit(
+        None,
+        &sig,
+        &sig,
+        "change",
+        &tree,
+        &parents.iter().collect::<Vec<_>>(),
+    )
+    .unwrap()
+}
+
+fn approve(store: &Storage, project: &Reviewed, commit: git2::Oid) {
+    store
+        .collaborative_objects(None)
+        .create(
+            &project.whoami,
+            &project.urn,
+            NewObjectSpec {
+                history: review::approval(commit.into()),
+                typename: review::APPROVAL.clone(),
+                message: Some("approve".to_owned()),
+            },
+        )
+        .unwrap();
+}
+
+#[test]
+fn approvals_are_signed() {
+    let store = tmp::storage(SecretKey::new());
+    let project = reviewed(&store, SecretKey::new());
+    let repo = git2::Repository::open(store.path()).unwrap();
+    let tip = commit(&repo, None, false);
+    approve(&store, &project, tip);
+
+    let approvals = review::approvals(&store, &project.urn).unwrap();
+    assert_eq!(approvals.len(), 1);
+    assert_eq!(approvals[0].commit, tip.into());
+    assert_eq!(
+        approvals[0].keys.iter().collect::<Vec<_>>(),
+        vec![store.peer_id().as_public_key()]
+    );
+}
+
+#[test]
+fn enforce_rolls_back_unapproved() {
+    let store = tmp::storage(SecretKey::new());
+    let project = reviewed(&store, SecretKey::new());
+    let repo = git2::Repository::open(store.path()).unwrap();
+    let peer = PeerId::from(SecretKey::new());
+    let main = format!(
+        "refs/namespaces/{}/refs/remotes/{}/heads/main",
+        project.urn.encode_id(),
+        peer
+    );
+
+    // Unapproved new branch is removed
+    let before = Snapshot::take(&store, &project.urn).unwrap();
+    let first = commit(&repo, None, false);
+    repo.reference(&main, first, true, "update").unwrap();
+    assert_matches!(
+        review::enforce(&store, &project.urn, &before),
+        Err(review::Error::Unapproved { .. })
+    );
+    assert!(repo.find_reference(&main).is_err());
+
+    // Approved new branch is retained
+    approve(&store, &project, first);
+    repo.reference(&main, first, true, "update").unwrap();
+    review::enforce(&store, &project.urn, &before).unwrap();
+    assert_eq!(repo.refname_to_id(&main).unwrap(), first);
+
+    // Touching `docs` requires the approval of the docs key
+    let before = Snapshot::take(&store, &project.urn).unwrap();
+    let second = commit(&repo, Some(first), true);
+    approve(&store, &project, second);
+    repo.reference(&main, second, true, "update").unwrap();
+    match review::enforce(&store, &project.urn, &before) {
+        Err(review::Error::Unapproved { missing, .. }) => assert_eq!(
+            missing,
+            vec![Missing {
+                path: "docs".to_owned(),
+                approvals: 0,
+                required: 1,
+            }]
+        ),
+        x => panic!("expected unapproved update, got {:?}", x),
+    }
+    assert_eq!(repo.refname_to_id(&main).unwrap(), first);
+}
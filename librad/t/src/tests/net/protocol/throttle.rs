@@ -0,0 +1,135 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    num::NonZeroU32,
+};
+
+use librad::{
+    net::protocol::throttle::{Kind, Limit, Limits, Quota, Throttle},
+    rate_limit,
+    PeerId,
+    SecretKey,
+};
+use nonzero_ext::nonzero;
+
+fn throttle(per_peer: u32, per_ip: u32) -> Throttle {
+    let limits = |per_peer, per_ip| Limits {
+        per_peer: rate_limit::Quota::per_minute(nonzero!(1u32))
+            .allow_burst(NonZeroU32::new(per_peer).unwrap()),
+        per_ip: rate_limit::Quota::per_minute(nonzero!(1u32))
+            .allow_burst(NonZeroU32::new(per_ip).unwrap()),
+    };
+    Throttle::new(Quota {
+        gossip: limits(per_peer, per_ip),
+        request_pull: limits(per_peer, per_ip),
+        request_push: limits(per_peer, per_ip),
+    })
+}
+
+fn ip(x: u8) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::new(10, 0, 0, x))
+}
+
+#[test]
+fn ip_limit_keeps_peer_token() {
+    let throttle = throttle(2, 1);
+    let peer = PeerId::from(SecretKey::new());
+
+    assert_eq!(throttle.check(Kind::Gossip, &peer, &ip(1)), Ok(()));
+    assert_eq!(throttle.check(Kind::Gossip, &peer, &ip(1)), Err(Limit::Ip));
+    assert_eq!(throttle.check(Kind::Gossip, &peer, &ip(2)), Ok(()));
+    assert_eq!(
+        throttle.check(Kind::Gossip, &peer, &ip(3)),
+        Err(Limit::Peer)
+    );
+}
+
+#[test]
+fn peer_limit_keeps_ip_token() {
+    let throttle = throttle(1, 2);
+    let alice = PeerId::from(SecretKey::new());
+    let bob = PeerId::from(SecretKey::new());
+    let carol = PeerId::from(SecretKey::new());
+
+    assert_eq!(throttle.check(Kind::Gossip, &alice, &ip(1)), Ok(()));
+    assert_eq!(
+        throttle.check(Kind::Gossip, &alice, &ip(1)),
+        Err(Limit::Peer)
+    );
+    assert_eq!(throttle.check(Kind::Gossip, &bob, &ip(1)), Ok(()));
+    assert_eq!(throttle.check(Kind::Gossip, &carol, &ip(1)), Err(Limit::Ip));
+}
+
+#[test]
+fn kinds_are_limited_separately() {
+    let throttle = throttle(1, 1);
+    let peer = PeerId::from(SecretKey::new());
+
+    assert_eq!(throttle.check(Kind::Gossip, &peer, &ip(1)), Ok(()));
+    assert_eq!(throttle.check(Kind::RequestPull, &peer, &ip(1)), Ok(()));
+    assert_eq!(
+        throttle.check(Kind::Gossip, &peer, &ip(1)),
+        Err(Limit::Peer)
+    );
+    assert_eq!(
+        throttle.check(Kind::RequestPull, &peer, &ip(1)),
+        Err(Limit::Peer)
+    );
+
+    let stats = throttle.stats();
+    assert_eq!(stats.gossip, 1);
+    assert_eq!(stats.request_pull, 1);
+}
+
+#[test]
+fn ipv6_limited_per_prefix() {
+    let throttle = throttle(2, 1);
+    let peer = PeerId::from(SecretKey::new());
+    let v6 = |s: &str| IpAddr::V6(s.parse::<Ipv6Addr>().unwrap());
+
+    assert_eq!(
+        throttle.check(Kind::Gossip, &peer, &v6("2001:db8::1")),
+        Ok(())
+    );
+    assert_eq!(
+        throttle.check(Kind::Gossip, &peer, &v6("2001:db8::2:3:4:5")),
+        Err(Limit::Ip)
+    );
+    assert_eq!(
+        throttle.check(Kind::Gossip, &peer, &v6("2001:db8:0:1::1")),
+        Ok(())
+    );
+}
+
+#[test]
+fn ipv4_mapped_limited_as_ipv4() {
+    let throttle = throttle(3, 1);
+    let peer = PeerId::from(SecretKey::new());
+    let mapped = |x| IpAddr::V6(Ipv4Addr::new(10, 0, 0, x).to_ipv6_mapped());
+
+    assert_eq!(throttle.check(Kind::Gossip, &peer, &ip(1)), Ok(()));
+    assert_eq!(
+        throttle.check(Kind::Gossip, &peer, &mapped(1)),
+        Err(Limit::Ip)
+    );
+    assert_eq!(throttle.check(Kind::Gossip, &peer, &mapped(2)), Ok(()));
+}
+
+#[test]
+fn request_push_has_own_buckets() {
+    let throttle = throttle(1, 1);
+    let peer = PeerId::from(SecretKey::new());
+
+    assert_eq!(throttle.check(Kind::RequestPull, &peer, &ip(1)), Ok(()));
+    assert_eq!(throttle.check(Kind::RequestPush, &peer, &ip(1)), Ok(()));
+    assert_eq!(
+        throttle.check(Kind::RequestPush, &peer, &ip(1)),
+        Err(Limit::Peer)
+    );
+    assert_eq!(throttle.stats().request_pull, 0);
+    assert_eq!(throttle.stats().request_push, 1);
+}
@@ -0,0 +1,94 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+use futures::StreamExt as _;
+use librad::{
+    net::{
+        connection::RemotePeer as _,
+        quic::{self, BoundEndpoint, KeepAlive},
+        Network,
+    },
+    PeerId,
+    SecretKey,
+};
+use link_async::Spawner;
+
+fn localhost() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
+}
+
+fn network() -> Network {
+    Network::Custom(b"shared".as_ref().into())
+}
+
+#[tokio::test]
+async fn router_dispatches_by_sni() {
+    let spawner = Spawner::from_current().unwrap();
+    let router =
+        quic::Router::<2>::bind(&spawner, localhost(), None, network(), KeepAlive::default())
+            .await
+            .unwrap();
+    let addr = router.listen_addrs()[0];
+
+    let work = SecretKey::new();
+    let personal = SecretKey::new();
+    let BoundEndpoint {
+        incoming: mut work_incoming,
+        ..
+    } = router.register(work.clone()).unwrap();
+    let BoundEndpoint {
+        endpoint: personal_endpoint,
+        incoming: mut personal_incoming,
+    } = router.register(personal.clone()).unwrap();
+    assert_eq!(router.peers().len(), 2);
+
+    let remote = SecretKey::new();
+    let BoundEndpoint {
+        endpoint: mut remote_endpoint,
+        incoming: _remote_incoming,
+    } = quic::Endpoint::<2>::bind(
+        remote.clone(),
+        &spawner,
+        localhost(),
+        None,
+        network(),
+        KeepAlive::default(),
+    )
+    .await
+    .unwrap();
+
+    let (conn, _) = remote_endpoint
+        .connect(PeerId::from(&personal), &addr)
+        .await
+        .unwrap();
+    assert_eq!(conn.remote_peer_id(), PeerId::from(&personal));
+    let (accepted, _) = personal_incoming.next().await.unwrap().unwrap();
+    assert_eq!(accepted.remote_peer_id(), PeerId::from(&remote));
+
+    let (conn, _) = remote_endpoint
+        .connect(PeerId::from(&work), &addr)
+        .await
+        .unwrap();
+    assert_eq!(conn.remote_peer_id(), PeerId::from(&work));
+    let (accepted, _) = work_incoming.next().await.unwrap().unwrap();
+    assert_eq!(accepted.remote_peer_id(), PeerId::from(&remote));
+
+    // Unknown peers are not served
+    assert!(remote_endpoint
+        .connect(PeerId::from(SecretKey::new()), &addr)
+        .await
+        .is_err());
+
+    // Closing a peer's endpoint doesn't affect the others
+    personal_endpoint.close();
+    assert_eq!(router.peers(), vec![PeerId::from(&work)]);
+    assert!(personal_incoming.next().await.is_none());
+    assert!(remote_endpoint
+        .connect(PeerId::from(&personal), &addr)
+        .await
+        .is_err());
+}
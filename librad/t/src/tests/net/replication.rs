@@ -0,0 +1,473 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use librad::net::replication::Filter;
+
+#[test]
+fn filter_matches_globs() {
+    let filter = Filter::new(vec!["refs/heads/main", "refs/heads/release/*"]).unwrap();
+
+    assert!(filter.is_match("refs/heads/main"));
+    assert!(filter.is_match("refs/heads/release/1.0"));
+    assert!(!filter.is_match("refs/heads/dev"));
+    assert!(!filter.is_match("refs/heads/mainline"));
+    assert!(!filter.is_match("refs/tags/v1.0"));
+}
+
+#[test]
+fn filter_star_does_not_cross_separator() {
+    let filter = Filter::new(vec!["refs/heads/release/*"]).unwrap();
+    assert!(!filter.is_match("refs/heads/release/1.0/hotfix"));
+
+    let filter = Filter::new(vec!["refs/heads/release/**"]).unwrap();
+    assert!(filter.is_match("refs/heads/release/1.0/hotfix"));
+}
+
+#[test]
+fn filter_empty_matches_nothing() {
+    let filter = Filter::new(Vec::<String>::new()).unwrap();
+    assert!(!filter.is_match("refs/heads/main"));
+}
+
+#[test]
+fn filter_invalid_pattern() {
+    assert!(Filter::new(vec!["refs/heads/[main"]).is_err());
+}
+
+mod fetcher {
+    use std::{
+        path::Path,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use librad::{
+        git::{storage::Storage, Urn},
+        net::replication::{self, fetcher::Metered, Fetcher, Replication},
+        PeerId,
+        SecretKey,
+    };
+    use link_async::Spawner;
+    use link_git::protocol::{ObjectId, Ref};
+    use link_replication::{io, LsRefs, Net};
+    use radicle_data::NonEmptyVec;
+
+    /// A remote peer which doesn't advertise any refs.
+    struct Empty {
+        remote: PeerId,
+        ls_refs: Arc<AtomicUsize>,
+        fetches: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait(?Send)]
+    impl Net for Empty {
+        type Error = std::io::Error;
+
+        async fn run_ls_refs(&self, _: LsRefs) -> Result<Vec<Ref>, Self::Error> {
+            self.ls_refs.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![])
+        }
+
+        async fn run_fetch(
+            &self,
+            _: u64,
+            _: NonEmptyVec<ObjectId>,
+            _: Vec<ObjectId>,
+        ) -> Result<(), Self::Error> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    impl Metered for Empty {
+        fn received_bytes(&self) -> u64 {
+            0
+        }
+
+        fn received_objects(&self) -> u64 {
+            0
+        }
+    }
+
+    impl Fetcher for Empty {
+        type Net = Self;
+
+        fn remote_peer(&self) -> PeerId {
+            self.remote
+        }
+
+        fn net(self, _: io::Refdb<io::Odb>, _: &Path, _: &Urn) -> Self::Net {
+            self
+        }
+    }
+
+    #[tokio::test]
+    async fn clone_from_empty_remote() {
+        let paths = it_helpers::tmp::paths();
+        let store = Storage::open(&*paths, SecretKey::new()).unwrap();
+        let repl = Replication::new(&*paths, replication::Config::default()).unwrap();
+        let spawner = Spawner::from_current().unwrap();
+
+        let ls_refs = Arc::new(AtomicUsize::new(0));
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let remote = Empty {
+            remote: PeerId::from(SecretKey::new()),
+            ls_refs: ls_refs.clone(),
+            fetches: fetches.clone(),
+        };
+        let urn = Urn::new(git2::Oid::zero().into());
+
+        let res = repl.replicate(&spawner, store, remote, urn, None).await;
+        assert!(res.is_err());
+        assert!(ls_refs.load(Ordering::SeqCst) > 0);
+        assert_eq!(fetches.load(Ordering::SeqCst), 0);
+    }
+}
+
+mod bundle {
+    use std::path::Path;
+
+    use git_ref_format::{lit, refname, Qualified};
+    use it_helpers::{fixed::TestProject, git::create_commit, tmp};
+    use librad::{
+        git::{
+            identities::{self, project::import},
+            storage::{bundle, Storage},
+            Urn,
+        },
+        identities::{
+            delegation,
+            payload::{self, ProjectPayload},
+        },
+        net::replication::{self, Bundle, Replication},
+        paths::Paths,
+        SecretKey,
+    };
+    use link_async::Spawner;
+
+    fn setup(store: &Storage) -> Urn {
+        let owner = TestProject::create(store).unwrap();
+        let repo = tmp::repo().unwrap();
+        create_commit(&repo, Qualified::from(lit::refs_heads(refname!("master")))).unwrap();
+        repo.set_head("refs/heads/master").unwrap();
+
+        let whoami = identities::local::load(store, owner.owner.urn())
+            .unwrap()
+            .unwrap();
+        identities::project::init_from_repo(
+            store,
+            whoami,
+            ProjectPayload::new(payload::Project {
+                name: "bundled".into(),
+                description: None,
+                default_branch: Some("master".into()),
+            }),
+            delegation::Indirect::from(owner.owner.clone()),
+            repo.path(),
+            import::Options::default(),
+        )
+        .unwrap()
+        .project
+        .urn()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn import_full_then_delta() {
+        let source = tmp::paths();
+        let source_store = Storage::open(&*source, SecretKey::new()).unwrap();
+        let author = *source_store.peer_id();
+        let urn = setup(&source_store);
+
+        let dir = tempfile::tempdir().unwrap();
+        let full = dir.path().join("full.bundle");
+        let delta = dir.path().join("delta.bundle");
+        let header = source_store.read_only().bundle(&urn, &full, None).unwrap();
+        assert_eq!(header, bundle::Header::read(&full).unwrap());
+        assert!(header.refs.contains_key("refs/rad/id"));
+        assert!(header.refs.contains_key("refs/rad/signed_refs"));
+        assert!(header.prerequisites.is_empty());
+        let delta_header = source_store
+            .read_only()
+            .bundle(&urn, &delta, Some(&header.refs))
+            .unwrap();
+        assert_eq!(delta_header.refs, header.refs);
+        assert!(!delta_header.prerequisites.is_empty());
+
+        // The delta requires the objects of the full bundle
+        let target = tmp::paths();
+        let key = SecretKey::new();
+        assert!(import(&*target, &key, &delta).await.is_err());
+
+        import(&*target, &key, &full).await.unwrap();
+        let repo = git2::Repository::open_bare(target.git_dir()).unwrap();
+        let master = format!(
+            "refs/namespaces/{}/refs/remotes/{}/heads/master",
+            urn.encode_id(),
+            author
+        );
+        assert_eq!(
+            repo.refname_to_id(&master).unwrap(),
+            *header.refs["refs/heads/master"]
+        );
+
+        import(&*target, &key, &delta).await.unwrap();
+    }
+
+    async fn import(paths: &Paths, key: &SecretKey, path: &Path) -> Result<(), String> {
+        let store = Storage::open(paths, key.clone()).unwrap();
+        let repl = Replication::new(paths, replication::Config::default()).unwrap();
+        let spawner = Spawner::from_current().unwrap();
+        let bundle = Bundle::open(path).unwrap();
+        let urn = bundle.header().urn.clone();
+        repl.replicate(&spawner, store, bundle, urn, None)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+}
+
+mod scheduler {
+    use std::sync::{Arc, Mutex};
+
+    use futures::FutureExt as _;
+    use librad::{
+        git::Urn,
+        net::replication::scheduler::{self, Priority, Scheduler, Transition},
+    };
+
+    fn urn(n: u8) -> Urn {
+        Urn::new(git_ext::Oid::from(
+            git2::Oid::hash_object(git2::ObjectType::Blob, &[n]).unwrap(),
+        ))
+    }
+
+    fn scheduler(slots: usize, per_urn: usize) -> Scheduler {
+        Scheduler::new(scheduler::Config { slots, per_urn })
+    }
+
+    #[test]
+    fn higher_priority_first() {
+        let sched = scheduler(1, 1);
+        let running = sched.acquire(urn(0), Priority::Resync).now_or_never();
+        assert!(running.is_some());
+
+        let mut resync = sched.acquire(urn(1), Priority::Resync);
+        let mut gossip = sched.acquire(urn(2), Priority::Gossip);
+        let mut interactive = sched.acquire(urn(3), Priority::Interactive);
+        assert_eq!(sched.stats().waiting.values().sum::<usize>(), 3);
+
+        drop(running);
+        let slot = (&mut interactive).now_or_never();
+        assert!(slot.is_some());
+        assert!((&mut gossip).now_or_never().is_none());
+        assert!((&mut resync).now_or_never().is_none());
+
+        drop(slot);
+        assert!((&mut gossip).now_or_never().is_some());
+        assert!((&mut resync).now_or_never().is_some());
+    }
+
+    #[test]
+    fn per_urn_limit() {
+        let sched = scheduler(4, 1);
+        let first = sched.acquire(urn(0), Priority::Gossip).now_or_never();
+        assert!(first.is_some());
+
+        let mut same = sched.acquire(urn(0), Priority::Interactive);
+        let mut other = sched.acquire(urn(1), Priority::Gossip);
+        assert!((&mut same).now_or_never().is_none());
+        assert!((&mut other).now_or_never().is_some());
+
+        drop(first);
+        assert!((&mut same).now_or_never().is_some());
+    }
+
+    #[test]
+    fn round_robin_across_namespaces() {
+        let sched = scheduler(1, 1);
+        let running = sched.acquire(urn(0), Priority::Gossip).now_or_never();
+
+        let mut big = (0..3)
+            .map(|_| sched.acquire(urn(1), Priority::Gossip))
+            .collect::<Vec<_>>();
+        let mut small = sched.acquire(urn(2), Priority::Gossip);
+
+        drop(running);
+        let slot = (&mut big[0]).now_or_never();
+        assert!(slot.is_some());
+        drop(slot);
+        // The small namespace is served before the rest of the big one
+        assert!((&mut big[1]).now_or_never().is_none());
+        assert!((&mut small).now_or_never().is_some());
+    }
+
+    #[test]
+    fn abandoned_runs_leave_the_queue() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sched = scheduler(1, 1).on_event({
+            let events = events.clone();
+            move |ev| events.lock().unwrap().push(ev.transition)
+        });
+        let running = sched.acquire(urn(0), Priority::Gossip).now_or_never();
+
+        let waiting = sched.acquire(urn(1), Priority::Gossip);
+        drop(waiting);
+        assert!(sched.stats().waiting.is_empty());
+
+        drop(running);
+        assert_eq!(sched.stats(), scheduler::Stats::default());
+        let events = events.lock().unwrap();
+        assert!(matches!(
+            events[..],
+            [
+                Transition::Queued,
+                Transition::Started { .. },
+                Transition::Queued,
+                Transition::Abandoned,
+                Transition::Finished
+            ]
+        ));
+    }
+
+    #[test]
+    fn granted_but_unpolled_runs_release_their_slot() {
+        let sched = scheduler(1, 1);
+        let running = sched.acquire(urn(0), Priority::Gossip).now_or_never();
+        let granted = sched.acquire(urn(1), Priority::Gossip);
+        let mut next = sched.acquire(urn(1), Priority::Gossip);
+
+        // `granted` holds the slot without having been polled
+        drop(running);
+        assert_eq!(sched.stats().running, 1);
+        assert!((&mut next).now_or_never().is_none());
+
+        // Its slot, and the per-URN count, are handed on to the next run
+        drop(granted);
+        assert_eq!(sched.stats().running, 1);
+        let slot = (&mut next).now_or_never();
+        assert!(slot.is_some());
+
+        drop(slot);
+        assert_eq!(sched.stats(), scheduler::Stats::default());
+        assert_eq!(sched.stats().running, 0);
+    }
+}
+
+mod haves {
+    use librad::{
+        git::{storage::Storage, Urn},
+        net::replication::haves::Cache,
+        PeerId,
+        SecretKey,
+    };
+    use link_git::protocol::ObjectId;
+
+    fn urn(n: u8) -> Urn {
+        Urn::new(git2::Oid::from_bytes(&[n; 20]).unwrap().into())
+    }
+
+    fn peer(n: u8) -> PeerId {
+        PeerId::from(SecretKey::from_seed([n; 32]))
+    }
+
+    /// Create a branch `name` in the namespace of `urn`, owned by `owner` or
+    /// the local peer, with a single commit made at `time`.
+    fn branch(
+        store: &Storage,
+        urn: &Urn,
+        owner: Option<&PeerId>,
+        name: &str,
+        time: i64,
+    ) -> ObjectId {
+        let repo = git2::Repository::open(store.path()).unwrap();
+        let refname = match owner {
+            None => format!("refs/namespaces/{}/refs/heads/{}", urn.encode_id(), name),
+            Some(peer) => format!(
+                "refs/namespaces/{}/refs/remotes/{}/heads/{}",
+                urn.encode_id(),
+                peer,
+                name
+            ),
+        };
+        let tree = repo
+            .find_tree(repo.treebuilder(None).unwrap().write().unwrap())
+            .unwrap();
+        let sig = git2::Signature::new("Kermit", "kermit@muppets.com", &git2::Time::new(time, 0))
+            .unwrap();
+        let oid = repo
+            .commit(Some(&refname), &sig, &sig, &refname, &tree, &[])
+            .unwrap();
+        ObjectId::from(git_ext::Oid::from(oid))
+    }
+
+    fn storage() -> (it_helpers::tmp::TmpPaths, Storage) {
+        let paths = it_helpers::tmp::paths();
+        let store = Storage::open(&*paths, SecretKey::new()).unwrap();
+        (paths, store)
+    }
+
+    #[test]
+    fn prefers_namespaces_with_common_peers() {
+        let (_paths, store) = storage();
+        let (target, fork, unrelated) = (urn(1), urn(2), urn(3));
+        branch(&store, &target, Some(&peer(1)), "main", 50);
+        let fork_tip = branch(&store, &fork, Some(&peer(1)), "main", 100);
+        let unrelated_tip = branch(&store, &unrelated, None, "main", 200);
+
+        let cache = Cache::default();
+        assert_eq!(cache.select(&store, &target, &peer(2), 1), vec![fork_tip]);
+        assert_eq!(
+            cache.select(&store, &target, &peer(2), 3),
+            vec![fork_tip, unrelated_tip]
+        );
+        // Without any local state, the remote peer decides
+        assert_eq!(cache.select(&store, &urn(4), &peer(1), 1), vec![fork_tip]);
+    }
+
+    #[test]
+    fn prefers_recent_tips() {
+        let (_paths, store) = storage();
+        let old = branch(&store, &urn(1), Some(&peer(1)), "main", 100);
+        let new = branch(&store, &urn(2), Some(&peer(1)), "main", 200);
+
+        let cache = Cache::default();
+        assert_eq!(cache.select(&store, &urn(3), &peer(1), 1), vec![new]);
+        assert_eq!(cache.select(&store, &urn(3), &peer(1), 2), vec![new, old]);
+    }
+
+    #[test]
+    fn caches_until_invalidated() {
+        let (_paths, store) = storage();
+        let fst = branch(&store, &urn(1), Some(&peer(1)), "main", 100);
+
+        let cache = Cache::default();
+        assert_eq!(cache.select(&store, &urn(3), &peer(1), 1), vec![fst]);
+
+        let snd = branch(&store, &urn(1), Some(&peer(1)), "next", 200);
+        let other = branch(&store, &urn(2), Some(&peer(1)), "main", 300);
+        assert_eq!(cache.select(&store, &urn(3), &peer(1), 1), vec![fst]);
+
+        cache.invalidate(&urn(1));
+        assert_eq!(cache.select(&store, &urn(3), &peer(1), 3), vec![snd, fst]);
+
+        let clone = cache.clone();
+        clone.invalidate(&urn(2));
+        assert_eq!(
+            cache.select(&store, &urn(3), &peer(1), 3),
+            vec![other, snd, fst]
+        );
+    }
+
+    #[test]
+    fn disabled() {
+        let (_paths, store) = storage();
+        branch(&store, &urn(1), None, "main", 100);
+        assert!(Cache::default()
+            .select(&store, &urn(2), &peer(1), 0)
+            .is_empty());
+    }
+}
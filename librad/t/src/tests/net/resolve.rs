@@ -0,0 +1,51 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::net::SocketAddr;
+
+use futures::StreamExt as _;
+use librad::{
+    net::{
+        discovery::{Discovery as _, Resolving},
+        resolve::{Host, Resolver},
+    },
+    PeerId,
+    SecretKey,
+};
+
+#[test]
+fn host_roundtrip() {
+    for s in &["seed.radicle.xyz:8776", "127.0.0.1:42", "[::1]:69"] {
+        let host = s.parse::<Host>().unwrap();
+        assert_eq!(&host.to_string(), s)
+    }
+    assert!("seed.radicle.xyz".parse::<Host>().is_err());
+    assert!(":8776".parse::<Host>().is_err());
+}
+
+#[tokio::test]
+async fn resolves_literals() {
+    let resolver = Resolver::default();
+    let addr: SocketAddr = "127.0.0.1:8776".parse().unwrap();
+    let host = Host::from(addr);
+
+    assert_eq!(resolver.resolve(&host).await, vec![addr]);
+    assert_eq!(
+        resolver.resolve_all(&[host.clone(), host]).await,
+        vec![addr]
+    );
+}
+
+#[tokio::test]
+async fn discovers_resolved() {
+    let peer = PeerId::from(SecretKey::new());
+    let addr: SocketAddr = "127.0.0.1:8776".parse().unwrap();
+    let disco = Resolving::new(Resolver::default(), vec![(peer, Host::from(addr))]).refresh(None);
+
+    assert_eq!(
+        disco.discover().collect::<Vec<_>>().await,
+        vec![(peer, vec![addr])]
+    );
+}
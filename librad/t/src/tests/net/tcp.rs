@@ -0,0 +1,89 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{net::SocketAddr, time::Instant};
+
+use futures::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    stream::{BoxStream, StreamExt as _},
+};
+use librad::{
+    net::{
+        connection::{LocalAddr as _, RemotePeer as _},
+        tcp,
+        Network,
+    },
+    PeerId,
+    SecretKey,
+};
+use test_helpers::logging;
+use tokio::net::TcpStream;
+
+fn network() -> Network {
+    Network::Custom(b"tcp".as_ref().into())
+}
+
+async fn listen(
+    key: SecretKey,
+) -> (
+    SocketAddr,
+    BoxStream<'static, (tcp::Connection, tcp::IncomingStreams)>,
+) {
+    let listener = tcp::Listener::bind(key, "127.0.0.1:0".parse().unwrap(), network())
+        .await
+        .unwrap();
+    let addr = listener.listen_addrs()[0];
+    (addr, listener.incoming().boxed())
+}
+
+#[tokio::test]
+async fn stalled_handshake_does_not_block_others() {
+    logging::init();
+
+    let server = SecretKey::new();
+    let (addr, mut incoming) = listen(server.clone()).await;
+
+    // Connects, but never starts the TLS handshake
+    let _stalled = TcpStream::connect(addr).await.unwrap();
+
+    let client = SecretKey::new();
+    let started = Instant::now();
+    let (accepted, connected) = futures::join!(
+        incoming.next(),
+        tcp::connect(client.clone(), network(), PeerId::from(server), vec![addr])
+    );
+    assert!(started.elapsed() < tcp::HANDSHAKE_TIMEOUT);
+    let (conn, _) = accepted.unwrap();
+    assert_eq!(conn.remote_peer_id(), PeerId::from(client));
+    assert!(connected.is_ok());
+}
+
+#[tokio::test]
+async fn bidi_roundtrip() {
+    logging::init();
+
+    let server = SecretKey::new();
+    let (addr, mut incoming) = listen(server.clone()).await;
+
+    let client = SecretKey::new();
+    let (accepted, connected) = futures::join!(
+        incoming.next(),
+        tcp::connect(client, network(), PeerId::from(server), vec![addr])
+    );
+    let (_, mut server_streams) = accepted.unwrap();
+    let (conn, client_streams) = connected.unwrap();
+    tokio::spawn(client_streams.deny());
+
+    // yamux announces the stream along with the first data
+    let mut opened = conn.open_bidi().await.unwrap();
+    opened.write_all(b"ping").await.unwrap();
+    opened.close().await.unwrap();
+
+    let mut inbound = server_streams.next().await.unwrap().unwrap();
+    tokio::spawn(server_streams.deny());
+    let mut buf = Vec::new();
+    inbound.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(buf, b"ping");
+}
@@ -14,6 +14,7 @@ use librad::{
         Interrogation,
         Membership,
         RequestPull,
+        RequestPush,
         SomeUpgraded,
         UpgradeRequest,
     },
@@ -84,6 +85,14 @@ async fn upgrade_request_pull() {
     )
 }
 
+#[tokio::test]
+async fn upgrade_request_push() {
+    assert_matches!(
+        test_upgrade(RequestPush).await,
+        Ok(SomeUpgraded::RequestPush(_))
+    )
+}
+
 #[test]
 fn roundtrip_upgrade_request() {
     roundtrip::cbor(UpgradeRequest::Gossip);
@@ -91,4 +100,5 @@ fn roundtrip_upgrade_request() {
     roundtrip::cbor(UpgradeRequest::Membership);
     roundtrip::cbor(UpgradeRequest::Interrogation);
     roundtrip::cbor(UpgradeRequest::RequestPull);
+    roundtrip::cbor(UpgradeRequest::RequestPush);
 }
@@ -0,0 +1,93 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::convert::TryFrom as _;
+
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{
+    git::Urn,
+    git_ext as ext,
+    notifications::{classify, Inbox, Kind, Update},
+    PeerId,
+    SecretKey,
+};
+
+fn update(urn: &Urn, path: &str, new: Option<ext::Oid>) -> Update {
+    Update {
+        urn: urn.clone().with_path(ext::RefLike::try_from(path).unwrap()),
+        old: None,
+        new,
+    }
+}
+
+#[test]
+fn inbox_roundtrip() {
+    let paths = tmp::paths();
+    let urn = Urn::new(ext::Oid::from(git2::Oid::zero()));
+    let peer = PeerId::from(SecretKey::new());
+    let head = Kind::Head {
+        peer,
+        branch: "main".to_owned(),
+        old: None,
+        new: ext::Oid::from(git2::Oid::zero()),
+    };
+
+    let mut inbox = Inbox::load(&paths).unwrap();
+    assert_eq!(inbox.notifications().count(), 0);
+    let first = inbox.push(urn.clone(), head.clone());
+    let second = inbox.push(urn.clone(), head);
+    assert_ne!(first, second);
+    assert!(inbox.mark(first, true));
+    assert!(!inbox.mark(42, true));
+    inbox.save().unwrap();
+
+    let mut inbox = Inbox::load(&paths).unwrap();
+    assert_eq!(inbox.notifications().count(), 2);
+    assert_eq!(
+        inbox.unread().map(|n| n.id).collect::<Vec<_>>(),
+        vec![second]
+    );
+    inbox.mark_all_read(Some(&urn));
+    assert_eq!(inbox.unread().count(), 0);
+    inbox.clear_read();
+    assert_eq!(inbox.notifications().count(), 0);
+}
+
+#[test]
+fn classify_heads() {
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+    let peer = PeerId::from(SecretKey::new());
+    let oid = ext::Oid::from(git2::Oid::zero());
+
+    let remote = update(
+        &urn,
+        &format!("refs/remotes/{}/heads/main", peer),
+        Some(oid),
+    );
+    assert_eq!(
+        classify(&store, &remote).unwrap(),
+        vec![Kind::Head {
+            peer,
+            branch: "main".to_owned(),
+            old: None,
+            new: oid,
+        }]
+    );
+
+    let pruned = update(&urn, &format!("refs/remotes/{}/heads/main", peer), None);
+    assert!(classify(&store, &pruned).unwrap().is_empty());
+
+    let local = update(
+        &urn,
+        &format!("refs/remotes/{}/heads/main", store.peer_id()),
+        Some(oid),
+    );
+    assert!(classify(&store, &local).unwrap().is_empty());
+
+    let own = update(&urn, "refs/heads/main", Some(oid));
+    assert!(classify(&store, &own).unwrap().is_empty());
+}
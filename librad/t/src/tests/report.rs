@@ -0,0 +1,86 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use it_helpers::{fixed::TestProject, tmp};
+use librad::{
+    git::{identities, storage::quota, tracking},
+    report,
+    PeerId,
+    SecretKey,
+};
+use serde_json::json;
+
+#[test]
+fn verification() {
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, owner } = TestProject::create(&store).unwrap();
+
+    let person = identities::person::verify(&store, &owner.urn())
+        .unwrap()
+        .unwrap();
+    let json = serde_json::to_value(report::Verification::from(&person)).unwrap();
+    assert_eq!(json["urn"], json!(owner.urn().to_string()));
+    assert_eq!(json["kind"], json!("person"));
+    assert_eq!(json["delegates"][0]["type"], json!("key"));
+    assert_eq!(
+        json["delegates"][0]["peer"],
+        json!(store.peer_id().to_string())
+    );
+
+    let project = identities::project::verify(&store, &project.urn())
+        .unwrap()
+        .unwrap();
+    let json = serde_json::to_value(report::Verification::from(&project)).unwrap();
+    assert_eq!(json["kind"], json!("project"));
+    assert_eq!(json["delegates"][0]["type"], json!("person"));
+    assert_eq!(json["delegates"][0]["urn"], json!(owner.urn().to_string()));
+    assert_eq!(
+        json["revision"],
+        json!(project.revision.to_string()),
+        "revision is serialized as hex"
+    );
+}
+
+#[test]
+fn tracking() {
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+    let peer = PeerId::from(SecretKey::new());
+    tracking::track(
+        &*store,
+        &urn,
+        Some(peer),
+        tracking::Config::default(),
+        tracking::policy::Track::Any,
+    )
+    .unwrap()
+    .unwrap();
+
+    let report = tracking::tracked(&*store, Some(&urn))
+        .unwrap()
+        .collect::<Result<report::Tracking, _>>()
+        .unwrap();
+    let json = serde_json::to_value(report).unwrap();
+    let entry = &json["entries"][0];
+    assert_eq!(entry["urn"], json!(urn.to_string()));
+    assert_eq!(entry["peer"], json!(peer.to_string()));
+    assert_eq!(entry["config"]["data"], json!(true));
+}
+
+#[test]
+fn storage_stats() {
+    let store = tmp::storage(SecretKey::new());
+    let TestProject { project, .. } = TestProject::create(&store).unwrap();
+    let urn = project.urn();
+    quota::record(&store, &urn, 42).unwrap();
+
+    let stats = report::StorageStats::from(quota::usage(&store).unwrap());
+    assert_eq!(42, stats.received_bytes);
+    let json = serde_json::to_value(stats).unwrap();
+    assert_eq!(json["namespaces"][0]["urn"], json!(urn.to_string()));
+    assert_eq!(json["namespaces"][0]["received_bytes"], json!(42));
+    assert!(json["objects_bytes"].as_u64().unwrap() > 0);
+}
@@ -24,6 +24,7 @@ use tracing::Instrument as _;
 pub struct Spawner {
     inner: tokio::runtime::Handle,
     stats: StatsMut,
+    max_blocking: Option<Arc<tokio::sync::Semaphore>>,
 }
 
 impl Spawner {
@@ -44,6 +45,26 @@ impl Spawner {
                 spawned: Arc::new(AtomicUsize::new(0)),
                 blocking: Arc::new(AtomicUsize::new(0)),
             },
+            max_blocking: None,
+        }
+    }
+
+    /// Limit the number of functions run concurrently via
+    /// [`blocking()`][`Spawner::blocking`] to `max`.
+    ///
+    /// The underlying thread pool is shared process-wide, so this allows an
+    /// embedding application to bound the number of threads occupied by tasks
+    /// spawned through this [`Spawner`]. Excess tasks are queued until a slot
+    /// becomes available.
+    ///
+    /// # Panics
+    ///
+    /// If `max` is zero.
+    pub fn with_max_blocking(self, max: usize) -> Self {
+        assert!(max > 0, "max_blocking must be greater than zero");
+        Self {
+            max_blocking: Some(Arc::new(tokio::sync::Semaphore::new(max))),
+            ..self
         }
     }
 
@@ -124,6 +145,10 @@ impl Spawner {
     /// program exits. It is the programmer's responsibility to ensure
     /// "graceful shutdown" by driving outstanding futures which may
     /// `.await` blocking tasks to completion.
+    ///
+    /// If a limit was set using
+    /// [`with_max_blocking()`][`Spawner::with_max_blocking`], the task is not
+    /// scheduled until a slot becomes available.
     pub async fn blocking<F, T>(&self, f: F) -> T
     where
         F: FnOnce() -> T + Send + 'static,
@@ -132,7 +157,17 @@ impl Spawner {
         let rt = self.inner.clone();
         let span = tracing::Span::current();
         let counter = Arc::clone(&self.stats.blocking);
+        let permit = match &self.max_blocking {
+            None => None,
+            Some(sem) => Some(
+                Arc::clone(sem)
+                    .acquire_owned()
+                    .await
+                    .expect("semaphore is never closed"),
+            ),
+        };
         blocking::unblock(move || {
+            let _permit = permit;
             counter.fetch_add(1, Relaxed);
             let _span = span.enter();
             let _rt = rt.enter();
@@ -0,0 +1,346 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! [Deterministically encoded CBOR].
+//!
+//! Values are encoded using [`minicbor`], and the result is then rewritten to
+//! satisfy the core deterministic encoding requirements:
+//!
+//! * integers, lengths and tags use the shortest possible form
+//! * indefinite-length items are converted to definite-length ones
+//! * map keys are sorted by the bytewise lexicographic order of their encoding,
+//!   and must be unique
+//!
+//! Like for [Canonical JSON](crate::Cjson), floating point numbers are not
+//! supported.
+//!
+//! [Deterministically encoded CBOR]: https://www.rfc-editor.org/rfc/rfc8949#section-4.2
+
+use std::{
+    convert::TryFrom,
+    ops::{Deref, DerefMut},
+    str,
+};
+
+use minicbor::{Decode, Encode};
+use thiserror::Error;
+
+use crate::Canonical;
+
+/// The maximum nesting depth of arrays, maps and tags accepted by
+/// [`canonicalise`].
+pub const MAX_DEPTH: usize = 128;
+
+const BREAK: u8 = 0xff;
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("failed to encode CBOR: {0}")]
+    Encode(String),
+
+    #[error(transparent)]
+    Decode(#[from] minicbor::decode::Error),
+
+    #[error("unexpected end of input at offset {0}")]
+    Eof(usize),
+
+    #[error("invalid initial byte {byte:#04x} at offset {offset}")]
+    Invalid { byte: u8, offset: usize },
+
+    #[error("invalid UTF-8 in text string at offset {0}")]
+    Utf8(usize),
+
+    #[error("floating point number at offset {0}")]
+    Float(usize),
+
+    #[error("duplicate map key at offset {0}")]
+    DuplicateKey(usize),
+
+    #[error("maximum depth exceeded at offset {0}")]
+    Depth(usize),
+
+    #[error("trailing data at offset {0}")]
+    Trailing(usize),
+}
+
+/// The deterministic CBOR representation of type `T`.
+pub struct Ccbor<T>(pub T);
+
+impl<T> Ccbor<T> {
+    pub fn canonical_form(&self) -> Result<Vec<u8>, Error>
+    where
+        T: Encode,
+    {
+        let bytes = minicbor::to_vec(&self.0).map_err(|e| Error::Encode(e.to_string()))?;
+        canonicalise(&bytes)
+    }
+
+    /// Decode `T` from `bytes`.
+    ///
+    /// Note that `bytes` are not required to be in deterministic form, use
+    /// [`is_canonical`] to check.
+    pub fn from_slice<'a>(bytes: &'a [u8]) -> Result<Self, Error>
+    where
+        T: Decode<'a>,
+    {
+        Self::try_from(bytes)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for Ccbor<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Ccbor<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T> Canonical for Ccbor<T>
+where
+    T: Encode,
+{
+    type Error = Error;
+
+    fn canonical_form(&self) -> Result<Vec<u8>, Self::Error> {
+        self.canonical_form()
+    }
+}
+
+impl<'a, T> TryFrom<&'a [u8]> for Ccbor<T>
+where
+    T: Decode<'a>,
+{
+    type Error = Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        minicbor::decode(bytes).map(Self).map_err(Error::from)
+    }
+}
+
+/// Rewrite the single CBOR data item in `bytes` in deterministic form.
+pub fn canonicalise(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let mut out = Vec::with_capacity(bytes.len());
+    reader.item(&mut out, 0)?;
+    if reader.pos < bytes.len() {
+        return Err(Error::Trailing(reader.pos));
+    }
+    Ok(out)
+}
+
+/// Whether `bytes` is a single CBOR data item in deterministic form.
+pub fn is_canonical(bytes: &[u8]) -> bool {
+    canonicalise(bytes).map_or(false, |canonical| canonical == bytes)
+}
+
+/// The argument of a data item's head.
+enum Arg {
+    Value(u64),
+    Indefinite,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn item(&mut self, out: &mut Vec<u8>, depth: usize) -> Result<(), Error> {
+        let offset = self.pos;
+        let (major, arg) = self.head()?;
+        match (major, arg) {
+            (0 | 1, Arg::Value(n)) => head(out, major, n),
+
+            (2 | 3, Arg::Value(len)) => {
+                let bytes = self.take(len)?;
+                string(out, major, offset, bytes)?
+            },
+            (2 | 3, Arg::Indefinite) => {
+                let mut bytes = Vec::new();
+                while !self.at_break()? {
+                    let chunk = self.pos;
+                    match self.head()? {
+                        (m, Arg::Value(len)) if m == major => bytes.extend(self.take(len)?),
+                        _ => return Err(self.invalid(chunk)),
+                    }
+                }
+                string(out, major, offset, &bytes)?
+            },
+
+            (4, arg) => {
+                let depth = self.descend(depth, offset)?;
+                let mut items = Vec::new();
+                let len = self.items(arg, |this| this.item(&mut items, depth))?;
+                head(out, 4, len);
+                out.extend(items)
+            },
+
+            (5, arg) => {
+                let depth = self.descend(depth, offset)?;
+                let mut entries = Vec::new();
+                self.items(arg, |this| {
+                    let (mut key, mut val) = (Vec::new(), Vec::new());
+                    let key_offset = this.pos;
+                    this.item(&mut key, depth)?;
+                    this.item(&mut val, depth)?;
+                    entries.push((key, key_offset, val));
+                    Ok(())
+                })?;
+                entries.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+                if let Some(dup) = entries.windows(2).find(|w| w[0].0 == w[1].0) {
+                    return Err(Error::DuplicateKey(dup[0].1.max(dup[1].1)));
+                }
+                head(out, 5, entries.len() as u64);
+                for (key, _, val) in entries {
+                    out.extend(key);
+                    out.extend(val);
+                }
+            },
+
+            (6, Arg::Value(tag)) => {
+                let depth = self.descend(depth, offset)?;
+                head(out, 6, tag);
+                self.item(out, depth)?
+            },
+
+            (7, Arg::Value(n)) => match self.bytes[offset] & 0x1f {
+                // false, true, null, undefined and unassigned simple values
+                0..=23 => head(out, 7, n),
+                // simple values 0..32 must use the short form
+                24 if n >= 32 => head(out, 7, n),
+                25..=27 => return Err(Error::Float(offset)),
+                _ => return Err(self.invalid(offset)),
+            },
+
+            _ => return Err(self.invalid(offset)),
+        }
+
+        Ok(())
+    }
+
+    /// Read the items of an array or map, invoking `f` for each, and return
+    /// their number.
+    fn items<F>(&mut self, arg: Arg, mut f: F) -> Result<u64, Error>
+    where
+        F: FnMut(&mut Self) -> Result<(), Error>,
+    {
+        match arg {
+            Arg::Value(len) => {
+                for _ in 0..len {
+                    f(self)?
+                }
+                Ok(len)
+            },
+            Arg::Indefinite => {
+                let mut len = 0;
+                while !self.at_break()? {
+                    f(self)?;
+                    len += 1;
+                }
+                Ok(len)
+            },
+        }
+    }
+
+    fn descend(&self, depth: usize, offset: usize) -> Result<usize, Error> {
+        if depth >= MAX_DEPTH {
+            Err(Error::Depth(offset))
+        } else {
+            Ok(depth + 1)
+        }
+    }
+
+    /// Consume the "break" stop code, if it is next.
+    fn at_break(&mut self) -> Result<bool, Error> {
+        match self.bytes.get(self.pos) {
+            Some(&BREAK) => {
+                self.pos += 1;
+                Ok(true)
+            },
+            Some(_) => Ok(false),
+            None => Err(Error::Eof(self.pos)),
+        }
+    }
+
+    fn head(&mut self) -> Result<(u8, Arg), Error> {
+        let offset = self.pos;
+        let initial = self.take(1)?[0];
+        let major = initial >> 5;
+        let arg = match initial & 0x1f {
+            n @ 0..=23 => Arg::Value(n as u64),
+            24 => Arg::Value(self.take(1)?[0] as u64),
+            25 => Arg::Value(self.uint::<2>()?),
+            26 => Arg::Value(self.uint::<4>()?),
+            27 => Arg::Value(self.uint::<8>()?),
+            31 if matches!(major, 2..=5) => Arg::Indefinite,
+            _ => return Err(self.invalid(offset)),
+        };
+        Ok((major, arg))
+    }
+
+    fn uint<const N: usize>(&mut self) -> Result<u64, Error> {
+        Ok(self
+            .take(N as u64)?
+            .iter()
+            .fold(0, |acc, b| acc << 8 | *b as u64))
+    }
+
+    fn take(&mut self, len: u64) -> Result<&'a [u8], Error> {
+        let end = usize::try_from(len)
+            .ok()
+            .and_then(|len| self.pos.checked_add(len))
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(Error::Eof(self.bytes.len()))?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn invalid(&self, offset: usize) -> Error {
+        Error::Invalid {
+            byte: self.bytes[offset],
+            offset,
+        }
+    }
+}
+
+fn string(out: &mut Vec<u8>, major: u8, offset: usize, bytes: &[u8]) -> Result<(), Error> {
+    if major == 3 && str::from_utf8(bytes).is_err() {
+        return Err(Error::Utf8(offset));
+    }
+    head(out, major, bytes.len() as u64);
+    out.extend(bytes);
+    Ok(())
+}
+
+/// Write the head of a data item, using the shortest possible form.
+fn head(out: &mut Vec<u8>, major: u8, n: u64) {
+    let major = major << 5;
+    if n < 24 {
+        out.push(major | n as u8)
+    } else if n <= u8::MAX as u64 {
+        out.extend([major | 24, n as u8])
+    } else if n <= u16::MAX as u64 {
+        out.push(major | 25);
+        out.extend((n as u16).to_be_bytes())
+    } else if n <= u32::MAX as u64 {
+        out.push(major | 26);
+        out.extend((n as u32).to_be_bytes())
+    } else {
+        out.push(major | 27);
+        out.extend(n.to_be_bytes())
+    }
+}
@@ -13,8 +13,50 @@ use std::{
 
 use crate::{Canonical, Cstring};
 
+mod deserializer;
 mod parser;
+pub mod patch;
 mod ser;
+mod serializer;
+pub mod stream;
+
+pub use deserializer::{from_slice, from_str, from_value};
+pub use serializer::{to_value, to_vec, Serializer};
+
+/// Errors converting between [`Value`] and [`serde`] types.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("floating point numbers are not supported")]
+    Float,
+
+    #[error("integer {0} out of range")]
+    Range(String),
+
+    #[error("object keys must be strings, found {0}")]
+    Key(&'static str),
+
+    #[error("invalid escape sequence in {0}")]
+    Escape(String),
+
+    #[error("{0}")]
+    Parse(String),
+
+    #[error("{0}")]
+    Custom(String),
+}
+
+impl serde::ser::Error for Error {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Value {
@@ -0,0 +1,359 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{convert::TryFrom, str::Chars};
+
+use serde::{
+    de::{
+        self,
+        value::{MapDeserializer, SeqDeserializer, StringDeserializer},
+        DeserializeOwned,
+        DeserializeSeed,
+        IntoDeserializer,
+        Unexpected,
+        Visitor,
+    },
+    forward_to_deserialize_any,
+};
+
+use super::{Error, Number, Value};
+use crate::Cstring;
+
+/// Convert a [`Value`] to any [`DeserializeOwned`] type.
+pub fn from_value<T>(value: Value) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    T::deserialize(value)
+}
+
+/// Deserialize any [`DeserializeOwned`] type from canonical JSON bytes.
+pub fn from_slice<T>(bytes: &[u8]) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    Value::try_from(bytes)
+        .map_err(Error::Parse)
+        .and_then(from_value)
+}
+
+/// Deserialize any [`DeserializeOwned`] type from a canonical JSON string.
+pub fn from_str<T>(s: &str) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    s.parse::<Value>()
+        .map_err(Error::Parse)
+        .and_then(from_value)
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(b) => visitor.visit_bool(b),
+            Value::Number(Number::U64(n)) => visitor.visit_u64(n),
+            Value::Number(Number::I64(n)) => visitor.visit_i64(n),
+            Value::String(s) => visitor.visit_string(unescape(&s)?),
+            Value::Array(array) => {
+                let mut seq = SeqDeserializer::<_, Error>::new(array.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            },
+            Value::Object(map) => {
+                let entries = map
+                    .into_iter()
+                    .map(|(k, v)| Ok((Key(unescape(&k)?), v)))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                let mut map = MapDeserializer::<_, Error>::new(entries.into_iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            },
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_none(),
+            other => visitor.visit_some(other),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::String(s) => {
+                let variant: StringDeserializer<Error> = unescape(&s)?.into_deserializer();
+                visitor.visit_enum(variant)
+            },
+            Value::Object(map) if map.len() == 1 => {
+                let (variant, value) = map
+                    .into_iter()
+                    .next()
+                    .expect("BUG: map has exactly one entry");
+                visitor.visit_enum(Enum {
+                    variant: unescape(&variant)?,
+                    value,
+                })
+            },
+            other => Err(de::Error::invalid_type(
+                unexpected(&other),
+                &"string or object with a single key",
+            )),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+struct Enum {
+    variant: String,
+    value: Value,
+}
+
+impl<'de> de::EnumAccess<'de> for Enum {
+    type Error = Error;
+    type Variant = Value;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Value), Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant: StringDeserializer<Error> = self.variant.into_deserializer();
+        seed.deserialize(variant).map(|v| (v, self.value))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Value {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self {
+            Value::Null => Ok(()),
+            other => Err(de::Error::invalid_type(unexpected(&other), &"unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+/// An (unescaped) object key.
+///
+/// Since the [`super::Serializer`] renders integer keys as strings, they are
+/// parsed back if the type being deserialized expects an integer.
+struct Key(String);
+
+impl<'de> IntoDeserializer<'de, Error> for Key {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Error>
+            where
+                V: Visitor<'de>,
+            {
+                match self.0.parse() {
+                    Ok(n) => visitor.$visit(n),
+                    Err(_) => Err(de::Error::invalid_value(Unexpected::Str(&self.0), &visitor)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Key {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_string(self.0)
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        let variant: StringDeserializer<Error> = self.0.into_deserializer();
+        visitor.visit_enum(variant)
+    }
+
+    forward_to_deserialize_any! {
+        bool f32 f64 char str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+fn unexpected(value: &Value) -> Unexpected<'_> {
+    match value {
+        Value::Null => Unexpected::Unit,
+        Value::Bool(b) => Unexpected::Bool(*b),
+        Value::Number(Number::U64(n)) => Unexpected::Unsigned(*n),
+        Value::Number(Number::I64(n)) => Unexpected::Signed(*n),
+        Value::String(s) => Unexpected::Str(s.as_str()),
+        Value::Array(_) => Unexpected::Seq,
+        Value::Object(_) => Unexpected::Map,
+    }
+}
+
+/// [`Value::String`]s retain the escape sequences of their source, resolve
+/// them.
+pub(super) fn unescape(s: &Cstring) -> Result<String, Error> {
+    let invalid = || Error::Escape(s.to_string());
+
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        let c = match chars.next().ok_or_else(invalid)? {
+            '"' => '"',
+            '\\' => '\\',
+            '/' => '/',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => {
+                let hi = hex4(&mut chars).ok_or_else(invalid)?;
+                let code = if (0xd800..0xdc00).contains(&hi) {
+                    let lo = match (chars.next(), chars.next()) {
+                        (Some('\\'), Some('u')) => hex4(&mut chars).ok_or_else(invalid)?,
+                        _ => return Err(invalid()),
+                    };
+                    if !(0xdc00..0xe000).contains(&lo) {
+                        return Err(invalid());
+                    }
+                    0x10000 + ((hi - 0xd800) << 10) + (lo - 0xdc00)
+                } else {
+                    hi
+                };
+                char::from_u32(code).ok_or_else(invalid)?
+            },
+            _ => return Err(invalid()),
+        };
+        out.push(c);
+    }
+
+    Ok(out)
+}
+
+fn hex4(chars: &mut Chars<'_>) -> Option<u32> {
+    (0..4).try_fold(0, |acc, _| {
+        chars
+            .next()
+            .and_then(|c| c.to_digit(16))
+            .map(|d| acc * 16 + d)
+    })
+}
@@ -0,0 +1,467 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! [JSON Patch] and [JSON Merge Patch] over [`Value`]s.
+//!
+//! Both produce a new [`Value`], the [`crate::Canonical`] form of which is the
+//! canonical re-encoding of the patched document.
+//!
+//! [JSON Patch]: https://www.rfc-editor.org/rfc/rfc6902
+//! [JSON Merge Patch]: https://www.rfc-editor.org/rfc/rfc7386
+
+use std::{
+    convert::TryFrom,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use super::{deserializer::unescape, serializer::escape, Map, ToCjson, Value};
+use crate::Cstring;
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("invalid JSON pointer `{0}`")]
+    Pointer(String),
+
+    #[error("path `{0}` does not exist")]
+    NotFound(Pointer),
+
+    #[error("invalid array index at `{0}`")]
+    Index(Pointer),
+
+    #[error("cannot index into {ty} at `{path}`")]
+    NotContainer { path: Pointer, ty: &'static str },
+
+    #[error("cannot remove the document root")]
+    Root,
+
+    #[error("cannot move `{from}` into its own child `{path}`")]
+    Move { from: Pointer, path: Pointer },
+
+    #[error("test failed at `{0}`")]
+    Test(Pointer),
+
+    #[error("invalid patch operation: {0}")]
+    Operation(String),
+}
+
+/// A [`Patch`] failed to apply.
+#[derive(Debug, thiserror::Error)]
+#[error("patch operation {index} failed")]
+pub struct Failed {
+    /// The index of the failed operation.
+    pub index: usize,
+    #[source]
+    pub error: Error,
+}
+
+/// A [JSON Pointer](https://www.rfc-editor.org/rfc/rfc6901).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pointer {
+    tokens: Vec<String>,
+}
+
+impl Pointer {
+    /// The pointer to the whole document.
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Append a reference token.
+    pub fn push(&mut self, token: impl Into<String>) {
+        self.tokens.push(token.into())
+    }
+
+    fn is_proper_prefix_of(&self, other: &Self) -> bool {
+        self.tokens.len() < other.tokens.len() && other.tokens.starts_with(&self.tokens)
+    }
+}
+
+impl FromStr for Pointer {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Ok(Self::root());
+        }
+        let invalid = || Error::Pointer(s.to_owned());
+        let tokens = s
+            .strip_prefix('/')
+            .ok_or_else(invalid)?
+            .split('/')
+            .map(|token| {
+                let mut out = String::with_capacity(token.len());
+                let mut chars = token.chars();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '~' => match chars.next() {
+                            Some('0') => out.push('~'),
+                            Some('1') => out.push('/'),
+                            _ => return Err(invalid()),
+                        },
+                        c => out.push(c),
+                    }
+                }
+                Ok(out)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { tokens })
+    }
+}
+
+impl Display for Pointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in &self.tokens {
+            write!(f, "/{}", token.replace('~', "~0").replace('/', "~1"))?
+        }
+        Ok(())
+    }
+}
+
+impl ToCjson for Pointer {
+    fn into_cjson(self) -> Value {
+        Value::String(escape(&self.to_string()))
+    }
+}
+
+/// A single [`Patch`] operation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    Add { path: Pointer, value: Value },
+    Remove { path: Pointer },
+    Replace { path: Pointer, value: Value },
+    Move { from: Pointer, path: Pointer },
+    Copy { from: Pointer, path: Pointer },
+    Test { path: Pointer, value: Value },
+}
+
+impl Operation {
+    fn apply(&self, doc: &mut Value) -> Result<(), Error> {
+        match self {
+            Self::Add { path, value } => add(doc, path, value.clone()),
+            Self::Remove { path } => remove(doc, path).map(|_| ()),
+            Self::Replace { path, value } => {
+                *get_mut(doc, path)? = value.clone();
+                Ok(())
+            },
+            Self::Move { from, path } => {
+                if from == path {
+                    return Ok(());
+                }
+                if from.is_proper_prefix_of(path) {
+                    return Err(Error::Move {
+                        from: from.clone(),
+                        path: path.clone(),
+                    });
+                }
+                let value = remove(doc, from)?;
+                add(doc, path, value)
+            },
+            Self::Copy { from, path } => {
+                let value = get(doc, from)?.clone();
+                add(doc, path, value)
+            },
+            Self::Test { path, value } => {
+                if get(doc, path)? == value {
+                    Ok(())
+                } else {
+                    Err(Error::Test(path.clone()))
+                }
+            },
+        }
+    }
+}
+
+impl ToCjson for Operation {
+    fn into_cjson(self) -> Value {
+        let mut map = Map::new();
+        let (op, path) = match self {
+            Self::Add { path, value } => {
+                map.insert("value".into(), value);
+                ("add", path)
+            },
+            Self::Remove { path } => ("remove", path),
+            Self::Replace { path, value } => {
+                map.insert("value".into(), value);
+                ("replace", path)
+            },
+            Self::Move { from, path } => {
+                map.insert("from".into(), from.into_cjson());
+                ("move", path)
+            },
+            Self::Copy { from, path } => {
+                map.insert("from".into(), from.into_cjson());
+                ("copy", path)
+            },
+            Self::Test { path, value } => {
+                map.insert("value".into(), value);
+                ("test", path)
+            },
+        };
+        map.insert("op".into(), op.into_cjson());
+        map.insert("path".into(), path.into_cjson());
+        Value::Object(map)
+    }
+}
+
+impl TryFrom<Value> for Operation {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let mut map = match value {
+            Value::Object(map) => map,
+            other => {
+                return Err(Error::Operation(format!(
+                    "expected object, found {}",
+                    other.ty_name()
+                )))
+            },
+        };
+        let mut field = |name: &str| {
+            map.remove(&Cstring::from(name))
+                .ok_or_else(|| Error::Operation(format!("missing `{}`", name)))
+        };
+        let pointer = |value: Value| -> Result<Pointer, Error> {
+            match value {
+                Value::String(s) => unescape(&s)
+                    .map_err(|e| Error::Operation(e.to_string()))?
+                    .parse(),
+                other => Err(Error::Operation(format!(
+                    "expected pointer, found {}",
+                    other.ty_name()
+                ))),
+            }
+        };
+
+        let op = match field("op")? {
+            Value::String(op) => op,
+            other => {
+                return Err(Error::Operation(format!(
+                    "expected string `op`, found {}",
+                    other.ty_name()
+                )))
+            },
+        };
+        let path = pointer(field("path")?)?;
+        match op.as_str() {
+            "add" => Ok(Self::Add {
+                path,
+                value: field("value")?,
+            }),
+            "remove" => Ok(Self::Remove { path }),
+            "replace" => Ok(Self::Replace {
+                path,
+                value: field("value")?,
+            }),
+            "move" => Ok(Self::Move {
+                from: pointer(field("from")?)?,
+                path,
+            }),
+            "copy" => Ok(Self::Copy {
+                from: pointer(field("from")?)?,
+                path,
+            }),
+            "test" => Ok(Self::Test {
+                path,
+                value: field("value")?,
+            }),
+            other => Err(Error::Operation(format!("unknown op `{}`", other))),
+        }
+    }
+}
+
+/// A [JSON Patch](https://www.rfc-editor.org/rfc/rfc6902) document.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Patch(pub Vec<Operation>);
+
+impl Patch {
+    /// Apply all operations to `doc` in order, returning the patched
+    /// document.
+    ///
+    /// If any operation fails, the patch is not applied at all.
+    pub fn apply(&self, doc: &Value) -> Result<Value, Failed> {
+        let mut doc = doc.clone();
+        for (index, op) in self.0.iter().enumerate() {
+            op.apply(&mut doc)
+                .map_err(|error| Failed { index, error })?;
+        }
+        Ok(doc)
+    }
+}
+
+impl ToCjson for Patch {
+    fn into_cjson(self) -> Value {
+        Value::Array(self.0.into_iter().collect())
+    }
+}
+
+impl TryFrom<Value> for Patch {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Array(ops) => ops
+                .into_iter()
+                .map(Operation::try_from)
+                .collect::<Result<_, _>>()
+                .map(Self),
+            other => Err(Error::Operation(format!(
+                "expected array, found {}",
+                other.ty_name()
+            ))),
+        }
+    }
+}
+
+impl FromStr for Patch {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<Value>()
+            .map_err(Error::Operation)
+            .and_then(Self::try_from)
+    }
+}
+
+/// Apply the [JSON Merge Patch](https://www.rfc-editor.org/rfc/rfc7386)
+/// `patch` to `target`, returning the patched document.
+pub fn merge(target: &Value, patch: &Value) -> Value {
+    match patch {
+        Value::Object(patch) => {
+            let mut target = match target {
+                Value::Object(map) => map.clone(),
+                _ => Map::new(),
+            };
+            let null = Value::Null;
+            for (key, value) in patch {
+                if *value == null {
+                    target.remove(key);
+                } else {
+                    let merged = merge(target.get(key).unwrap_or(&null), value);
+                    target.insert(key.clone(), merged);
+                }
+            }
+            Value::Object(target)
+        },
+        _ => patch.clone(),
+    }
+}
+
+fn get<'a>(doc: &'a Value, path: &Pointer) -> Result<&'a Value, Error> {
+    path.tokens
+        .iter()
+        .enumerate()
+        .try_fold(doc, |val, (i, token)| {
+            let at = || prefix(path, i + 1);
+            match val {
+                Value::Object(map) => map.get(&escape(token)).ok_or_else(|| Error::NotFound(at())),
+                Value::Array(array) => index(token, array.len())
+                    .and_then(|idx| array.0.get(idx))
+                    .ok_or_else(|| Error::NotFound(at())),
+                other => Err(Error::NotContainer {
+                    path: prefix(path, i),
+                    ty: other.ty_name(),
+                }),
+            }
+        })
+}
+
+fn get_mut<'a>(doc: &'a mut Value, path: &Pointer) -> Result<&'a mut Value, Error> {
+    path.tokens
+        .iter()
+        .enumerate()
+        .try_fold(doc, |val, (i, token)| {
+            let at = || prefix(path, i + 1);
+            match val {
+                Value::Object(map) => map
+                    .0
+                    .get_mut(&escape(token))
+                    .ok_or_else(|| Error::NotFound(at())),
+                Value::Array(array) => index(token, array.len())
+                    .and_then(move |idx| array.0.get_mut(idx))
+                    .ok_or_else(|| Error::NotFound(at())),
+                other => Err(Error::NotContainer {
+                    path: prefix(path, i),
+                    ty: other.ty_name(),
+                }),
+            }
+        })
+}
+
+/// The container `path` points into, and the last reference token of `path`.
+fn parent_mut<'a, 'b>(
+    doc: &'a mut Value,
+    path: &'b Pointer,
+) -> Result<Option<(&'a mut Value, &'b str)>, Error> {
+    match path.tokens.split_last() {
+        None => Ok(None),
+        Some((last, _)) => {
+            let parent = get_mut(doc, &prefix(path, path.tokens.len() - 1))?;
+            Ok(Some((parent, last.as_str())))
+        },
+    }
+}
+
+fn add(doc: &mut Value, path: &Pointer, value: Value) -> Result<(), Error> {
+    match parent_mut(doc, path)? {
+        None => *doc = value,
+        Some((Value::Object(map), key)) => {
+            map.insert(escape(key), value);
+        },
+        Some((Value::Array(array), "-")) => array.insert(value),
+        Some((Value::Array(array), token)) => {
+            let idx = index(token, array.len() + 1).ok_or_else(|| Error::Index(path.clone()))?;
+            array.0.insert(idx, value)
+        },
+        Some((other, _)) => {
+            return Err(Error::NotContainer {
+                path: prefix(path, path.tokens.len() - 1),
+                ty: other.ty_name(),
+            })
+        },
+    }
+    Ok(())
+}
+
+fn remove(doc: &mut Value, path: &Pointer) -> Result<Value, Error> {
+    match parent_mut(doc, path)? {
+        None => Err(Error::Root),
+        Some((Value::Object(map), key)) => map
+            .remove(&escape(key))
+            .ok_or_else(|| Error::NotFound(path.clone())),
+        Some((Value::Array(array), token)) => {
+            let idx = index(token, array.len()).ok_or_else(|| Error::Index(path.clone()))?;
+            Ok(array.0.remove(idx))
+        },
+        Some((other, _)) => Err(Error::NotContainer {
+            path: prefix(path, path.tokens.len() - 1),
+            ty: other.ty_name(),
+        }),
+    }
+}
+
+/// Parse an array index, which must be less than `bound`.
+///
+/// Leading zeroes are not permitted.
+fn index(token: &str, bound: usize) -> Option<usize> {
+    if token.is_empty()
+        || (token.len() > 1 && token.starts_with('0'))
+        || !token.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    token.parse().ok().filter(|idx| *idx < bound)
+}
+
+fn prefix(path: &Pointer, len: usize) -> Pointer {
+    Pointer {
+        tokens: path.tokens[..len].to_vec(),
+    }
+}
@@ -0,0 +1,563 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{convert::TryFrom, fmt::Write as _};
+
+use serde::ser::{self, Impossible, Serialize};
+
+use super::{Array, Error, Map, Number, Value};
+use crate::Cstring;
+
+/// Convert any [`Serialize`] type to a [`Value`].
+///
+/// Non-negative integers are always represented as [`Number::U64`], so the
+/// result compares equal to the [`Value`] parsed from its canonical form.
+pub fn to_value<T>(value: &T) -> Result<Value, Error>
+where
+    T: Serialize + ?Sized,
+{
+    value.serialize(Serializer)
+}
+
+/// Serialize any [`Serialize`] type to its canonical JSON form.
+///
+/// The output is deterministic, and identical to the one produced by
+/// [`crate::Cjson::canonical_form`].
+pub fn to_vec<T>(value: &T) -> Result<Vec<u8>, Error>
+where
+    T: Serialize + ?Sized,
+{
+    to_value(value).map(|val| val.to_bytes())
+}
+
+/// A [`serde::Serializer`] producing a [`Value`].
+pub struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Value;
+    type Error = Error;
+
+    type SerializeSeq = SerializeVec;
+    type SerializeTuple = SerializeVec;
+    type SerializeTupleStruct = SerializeVec;
+    type SerializeTupleVariant = SerializeTupleVariant;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeStructVariant;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        match u64::try_from(v) {
+            Ok(v) => self.serialize_u64(v),
+            Err(_) => Ok(Value::Number(Number::I64(v))),
+        }
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, Error> {
+        if let Ok(v) = u64::try_from(v) {
+            self.serialize_u64(v)
+        } else if let Ok(v) = i64::try_from(v) {
+            self.serialize_i64(v)
+        } else {
+            Err(Error::Range(v.to_string()))
+        }
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        Ok(Value::Number(Number::U64(v)))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, Error> {
+        u64::try_from(v)
+            .map_err(|_| Error::Range(v.to_string()))
+            .and_then(|v| self.serialize_u64(v))
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Value, Error> {
+        Err(Error::Float)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Value, Error> {
+        Err(Error::Float)
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(Value::String(escape(v)))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        Ok(Value::Array(
+            v.iter()
+                .map(|b| Value::Number(Number::U64(*b as u64)))
+                .collect(),
+        ))
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Value, Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Value, Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let mut map = Map::new();
+        map.insert(escape(variant), to_value(value)?);
+        Ok(Value::Object(map))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeVec, Error> {
+        Ok(SerializeVec {
+            vec: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeVec, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeVec, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeTupleVariant, Error> {
+        Ok(SerializeTupleVariant {
+            variant,
+            vec: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeMap, Error> {
+        Ok(SerializeMap {
+            map: Map::new(),
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeMap, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeStructVariant, Error> {
+        Ok(SerializeStructVariant {
+            variant,
+            map: Map::new(),
+        })
+    }
+}
+
+pub struct SerializeVec {
+    vec: Vec<Value>,
+}
+
+impl ser::SerializeSeq for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.vec.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Array(self.vec.into_iter().collect()))
+    }
+}
+
+impl ser::SerializeTuple for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeVec {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+pub struct SerializeTupleVariant {
+    variant: &'static str,
+    vec: Vec<Value>,
+}
+
+impl ser::SerializeTupleVariant for SerializeTupleVariant {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.vec.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        let mut map = Map::new();
+        map.insert(
+            escape(self.variant),
+            Value::Array(self.vec.into_iter().collect::<Array>()),
+        );
+        Ok(Value::Object(map))
+    }
+}
+
+pub struct SerializeMap {
+    map: Map,
+    next_key: Option<Cstring>,
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.next_key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let key = self
+            .next_key
+            .take()
+            .expect("BUG: serialize_value called before serialize_key");
+        self.map.insert(key, to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(Value::Object(self.map))
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.map.insert(escape(key), to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+pub struct SerializeStructVariant {
+    variant: &'static str,
+    map: Map,
+}
+
+impl ser::SerializeStructVariant for SerializeStructVariant {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.map.insert(escape(key), to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        let mut map = Map::new();
+        map.insert(escape(self.variant), Value::Object(self.map));
+        Ok(Value::Object(map))
+    }
+}
+
+/// Object keys must be strings. Like `serde_json`, we also accept integers,
+/// and render them as strings.
+struct KeySerializer;
+
+macro_rules! serialize_display {
+    ($($method:ident: $t:ty,)*) => {
+        $(
+            fn $method(self, v: $t) -> Result<Cstring, Error> {
+                Ok(Cstring::from(v.to_string()))
+            }
+        )*
+    };
+}
+
+macro_rules! serialize_invalid {
+    ($($method:ident($($arg:ty),*) => $ty:expr,)*) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<Cstring, Error> {
+                Err(Error::Key($ty))
+            }
+        )*
+    };
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = Cstring;
+    type Error = Error;
+
+    type SerializeSeq = Impossible<Cstring, Error>;
+    type SerializeTuple = Impossible<Cstring, Error>;
+    type SerializeTupleStruct = Impossible<Cstring, Error>;
+    type SerializeTupleVariant = Impossible<Cstring, Error>;
+    type SerializeMap = Impossible<Cstring, Error>;
+    type SerializeStruct = Impossible<Cstring, Error>;
+    type SerializeStructVariant = Impossible<Cstring, Error>;
+
+    serialize_display! {
+        serialize_i8: i8,
+        serialize_i16: i16,
+        serialize_i32: i32,
+        serialize_i64: i64,
+        serialize_i128: i128,
+        serialize_u8: u8,
+        serialize_u16: u16,
+        serialize_u32: u32,
+        serialize_u64: u64,
+        serialize_u128: u128,
+    }
+
+    serialize_invalid! {
+        serialize_bool(bool) => "bool",
+        serialize_f32(f32) => "float",
+        serialize_f64(f64) => "float",
+        serialize_bytes(&[u8]) => "bytes",
+        serialize_none() => "null",
+        serialize_unit() => "null",
+        serialize_unit_struct(&'static str) => "null",
+    }
+
+    fn serialize_char(self, v: char) -> Result<Cstring, Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Cstring, Error> {
+        Ok(escape(v))
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Cstring, Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Cstring, Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Cstring, Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Cstring, Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        Err(Error::Key("object"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(Error::Key("array"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(Error::Key("array"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(Error::Key("array"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(Error::Key("object"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(Error::Key("object"))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Err(Error::Key("object"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(Error::Key("object"))
+    }
+}
+
+/// Normalise `s` and escape it the same way [`crate::formatter`] does, such
+/// that it can be stored in a [`Value::String`].
+pub(super) fn escape(s: &str) -> Cstring {
+    let s = Cstring::from(s);
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                write!(out, "\\u{:04x}", c as u32).expect("writing to a String never fails")
+            },
+            c => out.push(c),
+        }
+    }
+    Cstring::from(out)
+}
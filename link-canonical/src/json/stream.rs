@@ -0,0 +1,404 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Incremental parsing of [`Value`]s.
+//!
+//! Unlike [`Value`]'s [`std::str::FromStr`] impl, the [`Parser`] does not
+//! require the whole input to be in memory: it is fed chunks of bytes as
+//! they arrive, eg. from an [`io::Read`] (see [`from_reader`]) or an
+//! [`AsyncRead`] (see [`from_async_reader`]). The accepted grammar is the
+//! same, but the input is subject to [`Limits`], and errors report the byte
+//! offset at which they occurred.
+
+use std::io;
+
+use futures_lite::io::{AsyncRead, AsyncReadExt as _};
+
+use super::{Map, Number, Value};
+use crate::Cstring;
+
+/// Size of the buffer used by [`from_reader`] and [`from_async_reader`].
+const CHUNK_SIZE: usize = 8 * 1024;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// The maximum nesting depth of arrays and objects.
+    pub max_depth: usize,
+    /// The maximum size of the input in bytes.
+    pub max_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_depth: 128,
+            max_size: 16 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("unexpected byte {byte:#04x} at offset {offset}")]
+    Unexpected { byte: u8, offset: usize },
+
+    #[error("unexpected end of input at offset {0}")]
+    Eof(usize),
+
+    #[error("invalid escape sequence at offset {0}")]
+    Escape(usize),
+
+    #[error("invalid UTF-8 in string starting at offset {0}")]
+    Utf8(usize),
+
+    #[error("floating point number at offset {0}")]
+    Float(usize),
+
+    #[error("number out of range at offset {0}")]
+    Range(usize),
+
+    #[error("maximum depth of {max} exceeded at offset {offset}")]
+    Depth { max: usize, offset: usize },
+
+    #[error("maximum size of {0} bytes exceeded")]
+    Size(usize),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Parse a [`Value`] from `reader`, reading it to the end.
+pub fn from_reader<R>(mut reader: R, limits: Limits) -> Result<Value, Error>
+where
+    R: io::Read,
+{
+    let mut parser = Parser::new(limits);
+    let mut buf = [0; CHUNK_SIZE];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return parser.finish(),
+            Ok(n) => parser.feed(&buf[..n])?,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Parse a [`Value`] from `reader`, reading it to the end.
+pub async fn from_async_reader<R>(mut reader: R, limits: Limits) -> Result<Value, Error>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut parser = Parser::new(limits);
+    let mut buf = [0; CHUNK_SIZE];
+    loop {
+        match reader.read(&mut buf).await {
+            Ok(0) => return parser.finish(),
+            Ok(n) => parser.feed(&buf[..n])?,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+enum Frame {
+    Array(Vec<Value>),
+    Object { map: Map, key: Option<Cstring> },
+}
+
+#[derive(Clone, Copy)]
+enum Escape {
+    None,
+    Backslash,
+    /// A backslash followed by whitespace, all of which is discarded.
+    Whitespace,
+}
+
+enum State {
+    /// Expecting a value. `first` is set after an opening bracket, which may
+    /// also be closed immediately.
+    Value {
+        first: bool,
+    },
+    /// Expecting an object key. `first` is set after an opening brace, which
+    /// may also be closed immediately.
+    Key {
+        first: bool,
+    },
+    Colon,
+    /// Expecting a separator or the end of the enclosing array or object.
+    Comma,
+    String {
+        key: bool,
+        start: usize,
+        escape: Escape,
+    },
+    Number {
+        start: usize,
+        negative: bool,
+    },
+    Literal {
+        rest: &'static [u8],
+        value: fn() -> Value,
+    },
+    Done,
+}
+
+/// Incremental parser of a single [`Value`].
+pub struct Parser {
+    limits: Limits,
+    offset: usize,
+    state: State,
+    stack: Vec<Frame>,
+    /// The bytes of the string or number currently being parsed.
+    buf: Vec<u8>,
+    value: Option<Value>,
+}
+
+impl Parser {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            offset: 0,
+            state: State::Value { first: false },
+            stack: Vec::new(),
+            buf: Vec::new(),
+            value: None,
+        }
+    }
+
+    /// The number of bytes consumed so far.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Feed the next `chunk` of input.
+    ///
+    /// After an error, the parser must not be used any further.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), Error> {
+        for byte in chunk {
+            if self.offset >= self.limits.max_size {
+                return Err(Error::Size(self.limits.max_size));
+            }
+            while !self.step(*byte)? {}
+            self.offset += 1;
+        }
+        Ok(())
+    }
+
+    /// Signal the end of input, yielding the parsed [`Value`].
+    pub fn finish(mut self) -> Result<Value, Error> {
+        if let State::Number { start, negative } = self.state {
+            self.number(start, negative)?;
+        }
+        match (self.state, self.value) {
+            (State::Done, Some(value)) => Ok(value),
+            _ => Err(Error::Eof(self.offset)),
+        }
+    }
+
+    /// Process `byte`, returning whether it was consumed.
+    fn step(&mut self, byte: u8) -> Result<bool, Error> {
+        let offset = self.offset;
+        let unexpected = Error::Unexpected { byte, offset };
+
+        match self.state {
+            State::Value { .. } | State::Key { .. } | State::Colon | State::Comma | State::Done
+                if is_whitespace(byte) => {},
+
+            State::Value { first } => match byte {
+                b']' if first => self.close(),
+                b'"' => self.begin_string(false),
+                b'-' => self.begin_number(None),
+                b'0'..=b'9' => self.begin_number(Some(byte)),
+                b't' => self.begin_literal(b"rue", || Value::Bool(true)),
+                b'f' => self.begin_literal(b"alse", || Value::Bool(false)),
+                b'n' => self.begin_literal(b"ull", || Value::Null),
+                b'[' => {
+                    self.open(Frame::Array(Vec::new()))?;
+                    self.state = State::Value { first: true }
+                },
+                b'{' => {
+                    self.open(Frame::Object {
+                        map: Map::new(),
+                        key: None,
+                    })?;
+                    self.state = State::Key { first: true }
+                },
+                _ => return Err(unexpected),
+            },
+
+            State::Key { first } => match byte {
+                b'}' if first => self.close(),
+                b'"' => self.begin_string(true),
+                _ => return Err(unexpected),
+            },
+
+            State::Colon => match byte {
+                b':' => self.state = State::Value { first: false },
+                _ => return Err(unexpected),
+            },
+
+            State::Comma => match (byte, self.stack.last()) {
+                (b',', Some(Frame::Array(_))) => self.state = State::Value { first: false },
+                (b',', Some(Frame::Object { .. })) => self.state = State::Key { first: false },
+                (b']', Some(Frame::Array(_))) | (b'}', Some(Frame::Object { .. })) => self.close(),
+                _ => return Err(unexpected),
+            },
+
+            State::String { key, start, escape } => match escape {
+                Escape::None => match byte {
+                    b'"' => self.string(key, start)?,
+                    b'\\' => self.escape(key, start, Escape::Backslash),
+                    0x00..=0x1f => return Err(unexpected),
+                    _ => self.buf.push(byte),
+                },
+                Escape::Backslash => match byte {
+                    b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't' | b'u' => {
+                        // Escape sequences are retained verbatim, see `parser::string`
+                        self.buf.extend([b'\\', byte]);
+                        self.escape(key, start, Escape::None)
+                    },
+                    _ if is_whitespace(byte) => self.escape(key, start, Escape::Whitespace),
+                    _ => return Err(Error::Escape(offset - 1)),
+                },
+                Escape::Whitespace => {
+                    if !is_whitespace(byte) {
+                        self.escape(key, start, Escape::None);
+                        return Ok(false);
+                    }
+                },
+            },
+
+            State::Number { start, negative } => match byte {
+                b'0'..=b'9' if self.buf == b"0" => return Err(unexpected),
+                b'0'..=b'9' => self.buf.push(byte),
+                b'.' | b'e' | b'E' => return Err(Error::Float(start)),
+                _ if self.buf.is_empty() => return Err(unexpected),
+                _ => {
+                    self.number(start, negative)?;
+                    return Ok(false);
+                },
+            },
+
+            State::Literal { rest, value } => match rest.split_first() {
+                Some((b, rest)) if *b == byte => {
+                    if rest.is_empty() {
+                        self.complete(value())
+                    } else {
+                        self.state = State::Literal { rest, value }
+                    }
+                },
+                _ => return Err(unexpected),
+            },
+
+            State::Done => return Err(unexpected),
+        }
+
+        Ok(true)
+    }
+
+    fn begin_string(&mut self, key: bool) {
+        self.buf.clear();
+        self.state = State::String {
+            key,
+            start: self.offset,
+            escape: Escape::None,
+        }
+    }
+
+    fn escape(&mut self, key: bool, start: usize, escape: Escape) {
+        self.state = State::String { key, start, escape }
+    }
+
+    fn string(&mut self, is_key: bool, start: usize) -> Result<(), Error> {
+        let s = String::from_utf8(std::mem::take(&mut self.buf))
+            .map(Cstring::from)
+            .map_err(|_| Error::Utf8(start))?;
+        if is_key {
+            match self.stack.last_mut() {
+                Some(Frame::Object { key, .. }) => *key = Some(s),
+                _ => unreachable!("BUG: object key outside of object"),
+            }
+            self.state = State::Colon;
+        } else {
+            self.complete(Value::String(s))
+        }
+        Ok(())
+    }
+
+    fn begin_number(&mut self, digit: Option<u8>) {
+        self.buf.clear();
+        self.buf.extend(digit);
+        self.state = State::Number {
+            start: self.offset,
+            negative: digit.is_none(),
+        }
+    }
+
+    fn number(&mut self, start: usize, negative: bool) -> Result<(), Error> {
+        if self.buf.is_empty() {
+            return Err(Error::Eof(self.offset));
+        }
+        let digits = std::str::from_utf8(&self.buf).expect("BUG: digits are ASCII");
+        let number = if negative {
+            digits.parse::<i64>().map(|n| Number::I64(-n)).ok()
+        } else {
+            digits.parse::<u64>().map(Number::U64).ok()
+        }
+        .ok_or(Error::Range(start))?;
+        self.complete(Value::Number(number));
+        Ok(())
+    }
+
+    fn begin_literal(&mut self, rest: &'static [u8], value: fn() -> Value) {
+        self.state = State::Literal { rest, value }
+    }
+
+    fn open(&mut self, frame: Frame) -> Result<(), Error> {
+        if self.stack.len() >= self.limits.max_depth {
+            return Err(Error::Depth {
+                max: self.limits.max_depth,
+                offset: self.offset,
+            });
+        }
+        self.stack.push(frame);
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        let value = match self.stack.pop() {
+            Some(Frame::Array(values)) => Value::Array(values.into_iter().collect()),
+            Some(Frame::Object { map, .. }) => Value::Object(map),
+            None => unreachable!("BUG: closing without an open array or object"),
+        };
+        self.complete(value)
+    }
+
+    /// Add a completed `value` to the enclosing array or object, or finish
+    /// parsing if it is the top-level value.
+    fn complete(&mut self, value: Value) {
+        match self.stack.last_mut() {
+            None => {
+                self.value = Some(value);
+                self.state = State::Done;
+            },
+            Some(Frame::Array(values)) => {
+                values.push(value);
+                self.state = State::Comma;
+            },
+            Some(Frame::Object { map, key }) => {
+                let key = key.take().expect("BUG: object value without key");
+                map.insert(key, value);
+                self.state = State::Comma;
+            },
+        }
+    }
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\r' | b'\n')
+}
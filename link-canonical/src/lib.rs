@@ -14,6 +14,9 @@ use serde_bytes::ByteBuf;
 use thiserror::Error;
 use unicode_normalization::UnicodeNormalization;
 
+pub mod cbor;
+pub use cbor::Ccbor;
+
 pub mod formatter;
 pub mod json;
 
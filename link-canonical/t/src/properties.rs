@@ -3,7 +3,9 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use link_canonical::{string, Cjson};
+use std::collections::BTreeMap;
+
+use link_canonical::{cbor, json, string, Ccbor, Cjson};
 use pretty_assertions::assert_eq;
 use proptest::prelude::*;
 use test_helpers::roundtrip;
@@ -59,4 +61,53 @@ proptest! {
 
         assert_eq!(t.normalised(), serde_json::from_slice(&canonical).unwrap())
     }
+
+    #[test]
+    fn any_string_serde_cjson(t in gen_t()) {
+        let canonical = json::to_vec(&t).unwrap();
+
+        assert_eq!(canonical, Cjson(&t).canonical_form().unwrap());
+        assert_eq!(t.normalised(), json::from_slice(&canonical).unwrap());
+        assert_eq!(
+            json::to_value(&t).unwrap(),
+            std::str::from_utf8(&canonical)
+                .unwrap()
+                .parse::<json::Value>()
+                .unwrap()
+        )
+    }
+
+    #[test]
+    fn stream_agrees_with_parser(t in gen_t(), chunk in 1..16usize) {
+        let canonical = json::to_vec(&t).unwrap();
+        let mut parser = json::stream::Parser::new(Default::default());
+        for bytes in canonical.chunks(chunk) {
+            parser.feed(bytes).unwrap();
+        }
+
+        assert_eq!(
+            parser.finish().unwrap(),
+            std::str::from_utf8(&canonical)
+                .unwrap()
+                .parse::<json::Value>()
+                .unwrap()
+        )
+    }
+
+    #[test]
+    fn cbor_canonical_idempotent(map in any::<BTreeMap<String, u64>>()) {
+        let canonical = Ccbor(&map).canonical_form().unwrap();
+
+        assert!(cbor::is_canonical(&canonical));
+        assert_eq!(
+            canonical,
+            cbor::canonicalise(&minicbor::to_vec(&map).unwrap()).unwrap()
+        );
+        assert_eq!(
+            map,
+            Ccbor::<BTreeMap<String, u64>>::from_slice(&canonical)
+                .unwrap()
+                .into_inner()
+        )
+    }
 }
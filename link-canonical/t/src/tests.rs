@@ -3,5 +3,8 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+mod cbor;
 mod formatter;
 mod json;
+mod patch;
+mod stream;
@@ -0,0 +1,123 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::collections::BTreeMap;
+
+use link_canonical::{
+    cbor::{canonicalise, is_canonical, Error, MAX_DEPTH},
+    Canonical,
+    Ccbor,
+};
+use minicbor::{Decode, Encode};
+
+#[derive(Debug, PartialEq, Encode, Decode)]
+#[cbor(map)]
+struct Doc {
+    #[n(0)]
+    name: String,
+    #[n(1)]
+    attrs: BTreeMap<String, u64>,
+    #[n(2)]
+    delta: i64,
+}
+
+fn doc() -> Doc {
+    Doc {
+        name: "doc".to_owned(),
+        attrs: vec![("aa".to_owned(), 1), ("b".to_owned(), 1000)]
+            .into_iter()
+            .collect(),
+        delta: -1,
+    }
+}
+
+#[test]
+fn shortest_integers() {
+    assert_eq!(canonicalise(&[0x18, 0x0a]).unwrap(), [0x0a]);
+    assert_eq!(canonicalise(&[0x19, 0x00, 0x64]).unwrap(), [0x18, 0x64]);
+    assert_eq!(
+        canonicalise(&[0x1b, 0, 0, 0, 0, 0, 0, 0x03, 0xe8]).unwrap(),
+        [0x19, 0x03, 0xe8]
+    );
+    assert_eq!(canonicalise(&[0x39, 0x00, 0x00]).unwrap(), [0x20]);
+    // tags and lengths, too
+    assert_eq!(
+        canonicalise(&[0xd8, 0x01, 0x58, 0x01, 0xff]).unwrap(),
+        [0xc1, 0x41, 0xff]
+    );
+}
+
+#[test]
+fn sorted_map_keys() {
+    // "b" sorts before "aa", because its encoding is shorter
+    assert_eq!(
+        Ccbor(doc().attrs).canonical_form().unwrap(),
+        [0xa2, 0x61, b'b', 0x19, 0x03, 0xe8, 0x62, b'a', b'a', 0x01]
+    );
+    assert_eq!(
+        canonicalise(&[0xa2, 0x02, 0x01, 0x01, 0x02]).unwrap(),
+        [0xa2, 0x01, 0x02, 0x02, 0x01]
+    );
+}
+
+#[test]
+fn definite_lengths() {
+    assert_eq!(
+        canonicalise(&[0x9f, 0x01, 0x02, 0xff]).unwrap(),
+        [0x82, 0x01, 0x02]
+    );
+    assert_eq!(
+        canonicalise(&[0x7f, 0x61, b'a', 0x61, b'b', 0xff]).unwrap(),
+        [0x62, b'a', b'b']
+    );
+    assert_eq!(
+        canonicalise(&[0xbf, 0x61, b'b', 0x01, 0x61, b'a', 0x02, 0xff]).unwrap(),
+        [0xa2, 0x61, b'a', 0x02, 0x61, b'b', 0x01]
+    );
+}
+
+#[test]
+fn rejects_non_deterministic() {
+    assert!(matches!(
+        canonicalise(&[0xa2, 0x01, 0x01, 0x01, 0x02]),
+        Err(Error::DuplicateKey(3))
+    ));
+    assert!(matches!(
+        canonicalise(&[0x82, 0xf9, 0x3c, 0x00, 0x01]),
+        Err(Error::Float(1))
+    ));
+    assert!(matches!(
+        canonicalise(&[0x01, 0x02]),
+        Err(Error::Trailing(1))
+    ));
+    assert!(matches!(canonicalise(&[0x18]), Err(Error::Eof(1))));
+    assert!(matches!(
+        canonicalise(&[0x62, 0xff, 0xfe]),
+        Err(Error::Utf8(0))
+    ));
+    assert!(matches!(
+        canonicalise(&[0x7f, 0x41, b'a', 0xff]),
+        Err(Error::Invalid { offset: 1, .. })
+    ));
+
+    let nested = vec![0x81; MAX_DEPTH + 1];
+    assert!(matches!(
+        canonicalise(&[nested, vec![0x00]].concat()),
+        Err(Error::Depth(offset)) if offset == MAX_DEPTH
+    ));
+}
+
+#[test]
+fn roundtrip() {
+    let bytes = Ccbor(doc()).canonical_form().unwrap();
+    assert!(is_canonical(&bytes));
+    assert_eq!(
+        Ccbor::<Doc>::from_slice(&bytes).unwrap().into_inner(),
+        doc()
+    );
+    assert_eq!(bytes, Canonical::canonical_form(&Ccbor(doc())).unwrap());
+
+    assert!(!is_canonical(&minicbor::to_vec(doc().attrs).unwrap()));
+}
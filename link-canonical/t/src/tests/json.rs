@@ -3,9 +3,12 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+use std::collections::BTreeMap;
+
 use link_canonical::{
-    json::{Array, Map, ToCjson, Value},
+    json::{self, Array, Map, ToCjson, Value},
     Canonical,
+    Cjson,
     Cstring,
 };
 
@@ -240,3 +243,105 @@ fn f_canon() {
         vec![("t", "O".into_cjson())].into_iter().collect::<Value>()
     );
 }
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Doc {
+    z_last: Vec<i32>,
+    a_first: Option<String>,
+    nested: BTreeMap<u64, Kind>,
+    kind: Kind,
+    bytes: serde_bytes::ByteBuf,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+enum Kind {
+    Unit,
+    Newtype(bool),
+    Tuple(u8, i8),
+    Struct { x: String },
+}
+
+fn doc() -> Doc {
+    Doc {
+        z_last: vec![-1, 0, 1],
+        a_first: Some("quote \" backslash \\ newline \n".to_owned()),
+        nested: vec![(10, Kind::Unit), (2, Kind::Newtype(false))]
+            .into_iter()
+            .collect(),
+        kind: Kind::Struct {
+            x: "\u{1f}".to_owned(),
+        },
+        bytes: serde_bytes::ByteBuf::from(vec![0, 255]),
+    }
+}
+
+#[test]
+fn serde_canon() {
+    assert_eq!(
+        std::str::from_utf8(&json::to_vec(&doc()).unwrap()).unwrap(),
+        r#"{"aFirst":"quote \" backslash \\ newline \n","bytes":[0,255],"kind":{"Struct":{"x":"\u001f"}},"nested":{"10":"Unit","2":{"Newtype":false}},"zLast":[-1,0,1]}"#
+    );
+}
+
+#[test]
+fn serde_matches_cjson() {
+    assert_eq!(
+        json::to_vec(&doc()).unwrap(),
+        Cjson(doc()).canonical_form().unwrap()
+    );
+    assert_eq!(
+        json::to_vec(&Kind::Tuple(1, -1)).unwrap(),
+        Cjson(Kind::Tuple(1, -1)).canonical_form().unwrap()
+    );
+}
+
+#[test]
+fn serde_roundtrip() {
+    let bytes = json::to_vec(&doc()).unwrap();
+    assert_eq!(json::from_slice::<Doc>(&bytes).unwrap(), doc());
+
+    for kind in [
+        Kind::Unit,
+        Kind::Newtype(true),
+        Kind::Tuple(255, -128),
+        Kind::Struct { x: "y".to_owned() },
+    ] {
+        let val = json::to_value(&kind).unwrap();
+        assert_eq!(json::from_value::<Kind>(val).unwrap(), kind);
+    }
+}
+
+#[test]
+fn serde_value_agrees_with_parser() {
+    let val = json::to_value(&doc()).unwrap();
+    let bytes = val.canonical_form().unwrap();
+    assert_eq!(
+        std::str::from_utf8(&bytes)
+            .unwrap()
+            .parse::<Value>()
+            .unwrap(),
+        val
+    );
+}
+
+#[test]
+fn serde_rejects_floats() {
+    assert!(matches!(json::to_vec(&1.5f64), Err(json::Error::Float)));
+    assert!(json::from_str::<f64>("1.5").is_err());
+}
+
+#[test]
+fn serde_rejects_non_string_keys() {
+    let map = vec![((1, 2), true)].into_iter().collect::<BTreeMap<_, _>>();
+    assert!(matches!(json::to_vec(&map), Err(json::Error::Key("array"))));
+}
+
+#[test]
+fn serde_surrogate_pairs() {
+    assert_eq!(
+        json::from_str::<String>(r#""\ud83d\ude00 \u00e9""#).unwrap(),
+        "\u{1f600} \u{e9}"
+    );
+    assert!(json::from_str::<String>(r#""\ud83d""#).is_err());
+}
@@ -0,0 +1,206 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use link_canonical::{
+    json::{
+        patch::{merge, Error, Failed, Patch, Pointer},
+        ToCjson,
+        Value,
+    },
+    Canonical,
+};
+
+fn value(s: &str) -> Value {
+    s.parse().unwrap()
+}
+
+fn patch(doc: &str, patch: &str) -> Result<String, Failed> {
+    let patch = patch.parse::<Patch>().unwrap();
+    let patched = patch.apply(&value(doc))?;
+    Ok(String::from_utf8(patched.canonical_form().unwrap()).unwrap())
+}
+
+#[test]
+fn rfc6902_examples() {
+    assert_eq!(
+        patch(
+            r#"{"foo":"bar"}"#,
+            r#"[{"op":"add","path":"/baz","value":"qux"}]"#
+        )
+        .unwrap(),
+        r#"{"baz":"qux","foo":"bar"}"#
+    );
+    assert_eq!(
+        patch(
+            r#"{"foo":["bar","baz"]}"#,
+            r#"[{"op":"add","path":"/foo/1","value":"qux"}]"#
+        )
+        .unwrap(),
+        r#"{"foo":["bar","qux","baz"]}"#
+    );
+    assert_eq!(
+        patch(
+            r#"{"baz":"qux","foo":"bar"}"#,
+            r#"[{"op":"remove","path":"/baz"}]"#
+        )
+        .unwrap(),
+        r#"{"foo":"bar"}"#
+    );
+    assert_eq!(
+        patch(
+            r#"{"baz":"qux","foo":"bar"}"#,
+            r#"[{"op":"replace","path":"/baz","value":"boo"}]"#
+        )
+        .unwrap(),
+        r#"{"baz":"boo","foo":"bar"}"#
+    );
+    assert_eq!(
+        patch(
+            r#"{"foo":{"bar":"baz","waldo":"fred"},"qux":{"corge":"grault"}}"#,
+            r#"[{"op":"move","from":"/foo/waldo","path":"/qux/thud"}]"#
+        )
+        .unwrap(),
+        r#"{"foo":{"bar":"baz"},"qux":{"corge":"grault","thud":"fred"}}"#
+    );
+    assert_eq!(
+        patch(
+            r#"{"foo":["all","grass","cows","eat"]}"#,
+            r#"[{"op":"move","from":"/foo/1","path":"/foo/3"}]"#
+        )
+        .unwrap(),
+        r#"{"foo":["all","cows","eat","grass"]}"#
+    );
+    assert_eq!(
+        patch(
+            r#"{"foo":["bar"]}"#,
+            r#"[{"op":"add","path":"/foo/-","value":["abc","def"]}]"#
+        )
+        .unwrap(),
+        r#"{"foo":["bar",["abc","def"]]}"#
+    );
+    assert_eq!(
+        patch(
+            r#"{"/":1,"~":2}"#,
+            r#"[{"op":"copy","from":"/~1","path":"/~0~1"}]"#
+        )
+        .unwrap(),
+        r#"{"/":1,"~":2,"~/":1}"#
+    );
+}
+
+#[test]
+fn test_op() {
+    let doc = r#"{"baz":"qux","foo":["a",2,"c"]}"#;
+    assert!(patch(
+        doc,
+        r#"[{"op":"test","path":"/baz","value":"qux"},{"op":"test","path":"/foo/1","value":2}]"#
+    )
+    .is_ok());
+    assert!(matches!(
+        patch(doc, r#"[{"op":"test","path":"/baz","value":"bar"}]"#),
+        Err(Failed {
+            index: 0,
+            error: Error::Test(_)
+        })
+    ));
+}
+
+#[test]
+fn atomic() {
+    let doc = value(r#"{"a":1}"#);
+    let patch = r#"[{"op":"add","path":"/b","value":2},{"op":"remove","path":"/c"}]"#
+        .parse::<Patch>()
+        .unwrap();
+    assert!(matches!(
+        patch.apply(&doc),
+        Err(Failed {
+            index: 1,
+            error: Error::NotFound(_)
+        })
+    ));
+    assert_eq!(doc, value(r#"{"a":1}"#));
+}
+
+#[test]
+fn invalid_paths() {
+    let doc = r#"{"a":[1,2],"s":"x"}"#;
+    let err = |p: &str| patch(doc, p).unwrap_err().error;
+
+    assert!(matches!(
+        err(r#"[{"op":"add","path":"/a/3","value":0}]"#),
+        Error::Index(_)
+    ));
+    assert!(matches!(
+        err(r#"[{"op":"remove","path":"/a/01"}]"#),
+        Error::Index(_)
+    ));
+    assert!(matches!(
+        err(r#"[{"op":"replace","path":"/b/c","value":0}]"#),
+        Error::NotFound(p) if p.to_string() == "/b"
+    ));
+    assert!(matches!(
+        err(r#"[{"op":"add","path":"/s/t","value":0}]"#),
+        Error::NotContainer { ty: "string", .. }
+    ));
+    assert!(matches!(
+        err(r#"[{"op":"move","from":"/a","path":"/a/0"}]"#),
+        Error::Move { .. }
+    ));
+    assert!(matches!(err(r#"[{"op":"remove","path":""}]"#), Error::Root));
+
+    assert!(matches!("a/b".parse::<Pointer>(), Err(Error::Pointer(_))));
+    assert!(matches!("/a~2".parse::<Pointer>(), Err(Error::Pointer(_))));
+    assert!(matches!(
+        r#"[{"op":"frobnicate","path":"/a"}]"#.parse::<Patch>(),
+        Err(Error::Operation(_))
+    ));
+}
+
+#[test]
+fn patch_roundtrip() {
+    let s = r#"[{"from":"/a~1b","op":"move","path":"/c"},{"op":"add","path":"/d/-","value":{"e":null}}]"#;
+    let patch = s.parse::<Patch>().unwrap();
+    assert_eq!(patch.into_cjson().canonical_form().unwrap(), s.as_bytes());
+}
+
+#[test]
+fn rfc7386_examples() {
+    let cases = [
+        (r#"{"a":"b"}"#, r#"{"a":"c"}"#, r#"{"a":"c"}"#),
+        (r#"{"a":"b"}"#, r#"{"b":"c"}"#, r#"{"a":"b","b":"c"}"#),
+        (r#"{"a":"b"}"#, r#"{"a":null}"#, r#"{}"#),
+        (r#"{"a":"b","b":"c"}"#, r#"{"a":null}"#, r#"{"b":"c"}"#),
+        (r#"{"a":["b"]}"#, r#"{"a":"c"}"#, r#"{"a":"c"}"#),
+        (r#"{"a":"c"}"#, r#"{"a":["b"]}"#, r#"{"a":["b"]}"#),
+        (
+            r#"{"a":{"b":"c"}}"#,
+            r#"{"a":{"b":"d","c":null}}"#,
+            r#"{"a":{"b":"d"}}"#,
+        ),
+        (r#"{"a":[{"b":"c"}]}"#, r#"{"a":[1]}"#, r#"{"a":[1]}"#),
+        (r#"["a","b"]"#, r#"["c","d"]"#, r#"["c","d"]"#),
+        (r#"{"a":"b"}"#, r#"["c"]"#, r#"["c"]"#),
+        (r#"{"a":"foo"}"#, "null", "null"),
+        (r#"{"a":"foo"}"#, r#""bar""#, r#""bar""#),
+        (r#"{"e":null}"#, r#"{"a":1}"#, r#"{"a":1,"e":null}"#),
+        (r#"[1,2]"#, r#"{"a":"b","c":null}"#, r#"{"a":"b"}"#),
+        (
+            r#"{}"#,
+            r#"{"a":{"bb":{"ccc":null}}}"#,
+            r#"{"a":{"bb":{}}}"#,
+        ),
+    ];
+    for (target, merge_patch, expected) in cases {
+        assert_eq!(
+            merge(&value(target), &value(merge_patch))
+                .canonical_form()
+                .unwrap(),
+            expected.as_bytes(),
+            "{} + {}",
+            target,
+            merge_patch
+        );
+    }
+}
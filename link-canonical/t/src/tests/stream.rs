@@ -0,0 +1,142 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use futures_lite::future;
+use link_canonical::json::{
+    stream::{from_async_reader, from_reader, Error, Limits, Parser},
+    Value,
+};
+
+const INPUTS: &[&str] = &[
+    "null",
+    " true ",
+    "false",
+    "[0, -0, 42, -42, 18446744073709551615]",
+    r#""""#,
+    r#""a\"b\\c\u00e9\n""#,
+    "\"escaped \\   \n whitespace\"",
+    "[]",
+    "[ ]",
+    "[1, [2, [3]], {}]",
+    "{}",
+    r#"{ "b" : 2, "a" : [true, null], "c": {"d": "e"} }"#,
+    r#"{"x":1,"x":2}"#,
+];
+
+const REJECTED: &[&str] = &[
+    "",
+    "8.0",
+    "1e3",
+    "01",
+    "-",
+    "[1,]",
+    "[1 2]",
+    r#"{"a"}"#,
+    "{1:2}",
+    "tru",
+    "nul",
+    r#""\x01""#,
+    r#""\q""#,
+    r#""unterminated"#,
+    "[]]",
+    "{} x",
+    "[-9223372036854775808]",
+];
+
+fn byte_by_byte(s: &str, limits: Limits) -> Result<Value, Error> {
+    let mut parser = Parser::new(limits);
+    for byte in s.as_bytes() {
+        parser.feed(&[*byte])?;
+    }
+    parser.finish()
+}
+
+#[test]
+fn agrees_with_parser() {
+    for input in INPUTS {
+        let expected = input.parse::<Value>().unwrap();
+        assert_eq!(
+            from_reader(input.as_bytes(), Limits::default()).unwrap(),
+            expected,
+            "{}",
+            input
+        );
+        assert_eq!(
+            byte_by_byte(input, Limits::default()).unwrap(),
+            expected,
+            "{}",
+            input
+        );
+    }
+}
+
+#[test]
+fn async_reader() {
+    for input in INPUTS {
+        assert_eq!(
+            future::block_on(from_async_reader(input.as_bytes(), Limits::default())).unwrap(),
+            input.parse::<Value>().unwrap()
+        )
+    }
+}
+
+#[test]
+fn rejects_what_parser_rejects() {
+    for input in REJECTED {
+        assert!(input.parse::<Value>().is_err(), "{}", input);
+        assert!(
+            from_reader(input.as_bytes(), Limits::default()).is_err(),
+            "{}",
+            input
+        );
+    }
+}
+
+#[test]
+fn error_offsets() {
+    let limits = Limits::default();
+    assert!(matches!(
+        byte_by_byte(r#"{"a": [1, 2 x]}"#, limits),
+        Err(Error::Unexpected {
+            byte: b'x',
+            offset: 12
+        })
+    ));
+    assert!(matches!(
+        byte_by_byte("[1, 2.5]", limits),
+        Err(Error::Float(4))
+    ));
+    assert!(matches!(
+        byte_by_byte(r#"["ok", "\q"]"#, limits),
+        Err(Error::Escape(8))
+    ));
+    assert!(matches!(byte_by_byte("[1, 2", limits), Err(Error::Eof(5))));
+}
+
+#[test]
+fn depth_limit() {
+    let limits = Limits {
+        max_depth: 2,
+        ..Limits::default()
+    };
+    assert!(byte_by_byte("[{}]", limits).is_ok());
+    assert!(matches!(
+        byte_by_byte(r#"[{"a":[]}]"#, limits),
+        Err(Error::Depth { max: 2, offset: 6 })
+    ));
+}
+
+#[test]
+fn size_limit() {
+    let limits = Limits {
+        max_size: 4,
+        ..Limits::default()
+    };
+    assert!(from_reader(&b"true"[..], limits).is_ok());
+    assert!(matches!(
+        from_reader(&b"false"[..], limits),
+        Err(Error::Size(4))
+    ));
+}
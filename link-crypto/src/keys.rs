@@ -54,6 +54,19 @@ impl From<PublicKey> for sign::PublicKey {
     }
 }
 
+/// Note that the generated bytes are not necessarily a valid curve point.
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for PublicKey {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        // SAFETY: any 32 bytes are valid `VerificationKeyBytes`
+        Ok(Self::from_slice(u.bytes(PUBLICKEYBYTES)?).unwrap())
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (PUBLICKEYBYTES, Some(PUBLICKEYBYTES))
+    }
+}
+
 /// A signature produced by `Key::sign`
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Signature(ed25519::Signature);
@@ -309,6 +322,19 @@ impl From<Signature> for [u8; 64] {
     }
 }
 
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for Signature {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut bytes = [0u8; 64];
+        u.fill_buffer(&mut bytes)?;
+        Ok(Self(ed25519::Signature::from(bytes)))
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (64, Some(64))
+    }
+}
+
 impl Deref for Signature {
     type Target = ed25519::Signature;
 
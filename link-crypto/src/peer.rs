@@ -73,6 +73,17 @@ impl From<PublicKey> for PeerId {
     }
 }
 
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for PeerId {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        u.arbitrary().map(Self)
+    }
+
+    fn size_hint(depth: usize) -> (usize, Option<usize>) {
+        <PublicKey as arbitrary::Arbitrary>::size_hint(depth)
+    }
+}
+
 impl From<SecretKey> for PeerId {
     fn from(k: SecretKey) -> Self {
         Self(k.public())
@@ -8,6 +8,7 @@ use git_protocol::transport::client;
 use versions::Version;
 
 pub mod fetch;
+pub mod io;
 pub mod ls;
 pub mod packwriter;
 pub mod take;
@@ -17,7 +18,7 @@ pub mod upload_pack;
 pub use fetch::{fetch, Ref};
 pub use ls::ls_refs;
 pub use packwriter::PackWriter;
-pub use upload_pack::upload_pack;
+pub use upload_pack::{upload_pack, upload_pack_with};
 
 pub use git_hash::{oid, ObjectId};
 
@@ -0,0 +1,514 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Shovelling bytes between network streams and git.
+//!
+//! Both ends of a pack transfer move large amounts of data in small pieces:
+//! `git_protocol` issues separate writes for every packet line header and
+//! payload, and `git upload-pack` emits side-band packets which we forward
+//! verbatim. Going through [`futures_lite::io::BufReader`] (or
+//! [`futures_lite::io::copy`], which wraps the reader in a fresh one per call)
+//! means every session allocates its own buffers, and every chunk of a
+//! packfile is copied more than once on its way to the network.
+//!
+//! Instead, buffers are checked out of a process-wide pool and handed around
+//! as reference-counted [`Chunk`]s, which can be sliced and queued for sending
+//! without copying. [`BufRecv`] and [`BufSend`] wrap the receiving and sending
+//! halves of a stream respectively, where [`BufSend`] coalesces small writes
+//! and emits queued chunks using vectored writes.
+//!
+//! Note that [`BufSend`] only guarantees data to be sent when it is flushed.
+//! `git_protocol` flushes at the end of every request.
+
+use std::{
+    cmp,
+    collections::VecDeque,
+    io::{self, IoSlice},
+    mem,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_lite::{
+    future,
+    io::{AsyncBufRead, AsyncBufReadExt as _, AsyncRead, AsyncWrite, AsyncWriteExt as _},
+    ready,
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+/// Size of a pooled [`Buffer`].
+///
+/// `upload-pack` writes side-band packets of up to 64KiB, so this allows to
+/// forward a full packet with a single write.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Maximum number of idle buffers to retain in the pool.
+///
+/// Buffers are returned to the allocator if the pool is full.
+const MAX_IDLE: usize = 64;
+
+/// Number of bytes [`BufSend`] queues before it starts writing to the
+/// underlying stream, even if not flushed.
+const HIGH_WATER: usize = 4 * CHUNK_SIZE;
+
+/// Maximum number of [`IoSlice`]s passed to a single vectored write.
+const MAX_IOVS: usize = 64;
+
+static POOL: Lazy<Pool> = Lazy::new(Pool::default);
+
+#[derive(Default)]
+struct Pool {
+    idle: Mutex<Vec<Box<[u8]>>>,
+}
+
+impl Pool {
+    fn get(&'static self) -> Buffer {
+        let buf = self
+            .idle
+            .lock()
+            .pop()
+            .unwrap_or_else(|| vec![0; CHUNK_SIZE].into_boxed_slice());
+        Buffer {
+            buf: Some(buf),
+            pool: self,
+        }
+    }
+
+    fn put(&self, buf: Box<[u8]>) {
+        let mut idle = self.idle.lock();
+        if idle.len() < MAX_IDLE {
+            idle.push(buf)
+        }
+    }
+}
+
+/// A fixed-size byte buffer on loan from the pool.
+///
+/// The buffer is returned to the pool when dropped. Note that its contents are
+/// not zeroed.
+pub struct Buffer {
+    buf: Option<Box<[u8]>>,
+    pool: &'static Pool,
+}
+
+impl Buffer {
+    pub fn get() -> Self {
+        POOL.get()
+    }
+
+    /// Turn the first `len` bytes of this buffer into a [`Chunk`].
+    ///
+    /// # Panics
+    ///
+    /// If `len` is greater than [`CHUNK_SIZE`].
+    pub fn freeze(self, len: usize) -> Chunk {
+        assert!(len <= CHUNK_SIZE, "chunk length out of bounds");
+        Chunk {
+            buf: Arc::new(self),
+            start: 0,
+            end: len,
+        }
+    }
+}
+
+impl Deref for Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.buf.as_deref().expect("buffer is only taken on drop")
+    }
+}
+
+impl DerefMut for Buffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.buf
+            .as_deref_mut()
+            .expect("buffer is only taken on drop")
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        if let Some(buf) = self.buf.take() {
+            self.pool.put(buf)
+        }
+    }
+}
+
+/// A shared, immutable view into a pooled [`Buffer`].
+///
+/// Cloning and splitting a [`Chunk`] does not copy the data. The underlying
+/// buffer is returned to the pool when the last [`Chunk`] referring to it is
+/// dropped.
+#[derive(Clone)]
+pub struct Chunk {
+    buf: Arc<Buffer>,
+    start: usize,
+    end: usize,
+}
+
+impl Chunk {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Split off the first `at` bytes, sharing the underlying buffer.
+    ///
+    /// Afterwards, `self` contains the bytes from `at` onwards.
+    ///
+    /// # Panics
+    ///
+    /// If `at` is greater than [`Chunk::len`].
+    pub fn split_to(&mut self, at: usize) -> Self {
+        assert!(at <= self.len(), "split index out of bounds");
+        let head = Self {
+            buf: Arc::clone(&self.buf),
+            start: self.start,
+            end: self.start + at,
+        };
+        self.start += at;
+        head
+    }
+}
+
+impl Deref for Chunk {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf[self.start..self.end]
+    }
+}
+
+impl AsRef<[u8]> for Chunk {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+/// Buffered reader which reads into pooled [`Buffer`]s.
+///
+/// Besides [`AsyncRead`] and [`AsyncBufRead`], the received data can be
+/// obtained as [`Chunk`]s without copying.
+pub struct BufRecv<R> {
+    inner: R,
+    buf: Arc<Buffer>,
+    pos: usize,
+    filled: usize,
+}
+
+impl<R> BufRecv<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: Arc::new(Buffer::get()),
+            pos: 0,
+            filled: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R> BufRecv<R>
+where
+    R: AsyncRead + Unpin,
+{
+    /// Receive the next [`Chunk`] of data.
+    ///
+    /// Returns any buffered data first. `None` signals EOF.
+    pub async fn chunk(&mut self) -> io::Result<Option<Chunk>> {
+        future::poll_fn(|cx| self.poll_chunk(cx)).await
+    }
+
+    pub fn poll_chunk(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<Chunk>>> {
+        if self.pos >= self.filled {
+            ready!(self.poll_fill(cx))?;
+        }
+        if self.pos >= self.filled {
+            return Poll::Ready(Ok(None));
+        }
+        let chunk = Chunk {
+            buf: Arc::clone(&self.buf),
+            start: self.pos,
+            end: self.filled,
+        };
+        self.pos = self.filled;
+
+        Poll::Ready(Ok(Some(chunk)))
+    }
+
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // If chunks of the current buffer are still alive, we can't overwrite it
+        if Arc::get_mut(&mut self.buf).is_none() {
+            self.buf = Arc::new(Buffer::get());
+            self.pos = 0;
+            self.filled = 0;
+        }
+        let buf = Arc::get_mut(&mut self.buf).expect("buffer is not shared");
+        let n = ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        self.pos = 0;
+        self.filled = n;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R> AsyncRead for BufRecv<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        out: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        // Don't bother buffering reads which would fill the whole buffer anyway
+        if this.pos >= this.filled && out.len() >= CHUNK_SIZE {
+            return Pin::new(&mut this.inner).poll_read(cx, out);
+        }
+        let avail = ready!(Pin::new(&mut *this).poll_fill_buf(cx))?;
+        let n = cmp::min(avail.len(), out.len());
+        out[..n].copy_from_slice(&avail[..n]);
+        this.pos += n;
+
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl<R> AsyncBufRead for BufRecv<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if this.pos >= this.filled {
+            ready!(this.poll_fill(cx))?;
+        }
+        Poll::Ready(Ok(&this.buf[this.pos..this.filled]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.pos = cmp::min(this.pos + amt, this.filled);
+    }
+}
+
+/// Buffered writer which sends queued [`Chunk`]s using vectored writes.
+///
+/// Small writes are coalesced into pooled [`Buffer`]s, while [`Chunk`]s
+/// obtained elsewhere (eg. from a [`BufRecv`]) can be queued without copying
+/// using [`BufSend::enqueue`]. Queued data is written to the underlying stream
+/// when flushed, or when more than a few buffers worth of data is pending.
+pub struct BufSend<W> {
+    inner: W,
+    queue: VecDeque<Chunk>,
+    queued: usize,
+    buf: Option<Buffer>,
+    len: usize,
+}
+
+impl<W> BufSend<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            queue: VecDeque::new(),
+            queued: 0,
+            buf: None,
+            len: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Number of bytes not yet written to the underlying stream.
+    pub fn pending(&self) -> usize {
+        self.queued + self.len
+    }
+
+    /// Queue `chunk` for sending, without copying it.
+    pub fn enqueue(&mut self, chunk: Chunk) {
+        self.freeze();
+        if !chunk.is_empty() {
+            self.queued += chunk.len();
+            self.queue.push_back(chunk)
+        }
+    }
+
+    fn freeze(&mut self) {
+        if self.len > 0 {
+            let len = mem::take(&mut self.len);
+            let buf = self.buf.take().expect("buffer holds data");
+            self.queued += len;
+            self.queue.push_back(buf.freeze(len))
+        }
+    }
+
+    fn advance(&mut self, mut n: usize) {
+        self.queued -= n;
+        while n > 0 {
+            let front = self.queue.front_mut().expect("advanced past queue");
+            if n < front.len() {
+                front.start += n;
+                break;
+            }
+            n -= front.len();
+            self.queue.pop_front();
+        }
+    }
+}
+
+impl<W> BufSend<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.queue.is_empty() {
+            let mut iovs = [IoSlice::new(&[]); MAX_IOVS];
+            let mut cnt = 0;
+            for (iov, chunk) in iovs.iter_mut().zip(&self.queue) {
+                *iov = IoSlice::new(chunk);
+                cnt += 1;
+            }
+            match ready!(Pin::new(&mut self.inner).poll_write_vectored(cx, &iovs[..cnt])) {
+                Ok(0) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Ok(n) => self.advance(n),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W> AsyncWrite for BufSend<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.pending() >= HIGH_WATER {
+            this.freeze();
+            ready!(this.poll_drain(cx))?;
+        }
+        // Don't bother buffering writes which would fill the whole buffer anyway
+        if this.pending() == 0 && data.len() >= CHUNK_SIZE {
+            return Pin::new(&mut this.inner).poll_write(cx, data);
+        }
+
+        let buf = this.buf.get_or_insert_with(Buffer::get);
+        let n = cmp::min(data.len(), CHUNK_SIZE - this.len);
+        buf[this.len..this.len + n].copy_from_slice(&data[..n]);
+        this.len += n;
+        if this.len == CHUNK_SIZE {
+            this.freeze()
+        }
+
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.freeze();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.freeze();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
+
+/// Copy all bytes from `reader` to `writer`, writing directly out of the
+/// reader's buffer.
+///
+/// Returns the number of bytes copied. `writer` is flushed, but not closed.
+pub async fn pipe_buf<R, W>(mut reader: R, mut writer: W) -> io::Result<u64>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut total = 0;
+    loop {
+        let buf = reader.fill_buf().await?;
+        if buf.is_empty() {
+            break;
+        }
+        let n = buf.len();
+        writer.write_all(buf).await?;
+        reader.consume(n);
+        total += n as u64;
+    }
+    writer.flush().await?;
+
+    Ok(total)
+}
+
+/// Copy all bytes from `reader` to `writer`.
+///
+/// Data is read into pooled [`Buffer`]s and written out of them as [`Chunk`]s,
+/// so the bytes are not copied in between.
+///
+/// Returns the number of bytes copied. `writer` is flushed, but not closed.
+pub async fn pipe<R, W>(reader: R, writer: W) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut reader = BufRecv::new(reader);
+    let mut writer = BufSend::new(writer);
+    let mut total = 0;
+    while let Some(chunk) = reader.chunk().await? {
+        total += chunk.len() as u64;
+        writer.enqueue(chunk);
+        // Forward what we have before waiting for more, so the remote end
+        // sees side-band progress in a timely manner
+        writer.flush().await?;
+    }
+    writer.flush().await?;
+
+    Ok(total)
+}
+
+/// Write all of `bufs` using vectored writes.
+///
+/// This allows to send eg. packet line headers and their payloads without
+/// first assembling them in a contiguous buffer.
+pub async fn write_all_vectored<W>(mut writer: W, mut bufs: &mut [IoSlice<'_>]) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    // Skip empty slices upfront, so a zero-length write below is an error
+    IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        match writer.write_vectored(bufs).await {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => IoSlice::advance_slices(&mut bufs, n),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
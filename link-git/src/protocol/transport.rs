@@ -17,8 +17,16 @@ use git_protocol::transport::{
     Service,
 };
 
+use super::io::{BufRecv, BufSend};
+
+/// A [`Transport`] over a pair of streams, which are closed after a single
+/// request.
+///
+/// The streams are wrapped in [`BufRecv`] and [`BufSend`], so the packet lines
+/// `git_protocol` writes are coalesced and sent using vectored writes, and no
+/// per-connection buffers are allocated.
 pub struct Stateless<R, W> {
-    inner: Connection<R, W>,
+    inner: Connection<BufRecv<R>, BufSend<W>>,
 }
 
 impl<R, W> Stateless<R, W>
@@ -29,8 +37,8 @@ where
     pub fn new(repo: BString, recv: R, send: W) -> Self {
         let url = format!("rad://{}", repo);
         let inner = Connection::new(
-            recv,
-            send,
+            BufRecv::new(recv),
+            BufSend::new(send),
             Protocol::V2,
             repo,
             None::<(String, Option<u16>)>,
@@ -3,15 +3,23 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{future::Future, io, path::Path, process::ExitStatus, str::FromStr};
+use std::{
+    future::Future,
+    io::{self, IoSlice},
+    path::Path,
+    process::ExitStatus,
+    str::FromStr,
+};
 
 use async_process::{Command, Stdio};
-use futures_lite::io::{copy, AsyncBufReadExt as _, AsyncRead, AsyncWrite, BufReader};
+use futures_lite::io::{AsyncBufReadExt as _, AsyncRead, AsyncWrite};
 use futures_util::try_join;
 use git_packetline::{self as packetline, PacketLineRef};
 use once_cell::sync::Lazy;
 use versions::Version;
 
+use super::io::{pipe, pipe_buf, write_all_vectored, BufRecv};
+
 mod legacy;
 
 #[derive(Debug, PartialEq, Eq)]
@@ -63,15 +71,36 @@ impl FromStr for Header {
 }
 
 pub async fn upload_pack<R, W>(
+    git_dir: impl AsRef<Path>,
+    recv: R,
+    send: W,
+) -> io::Result<(Header, impl Future<Output = io::Result<ExitStatus>>)>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    upload_pack_with(git_dir, recv, send, |_| Vec::new()).await
+}
+
+/// Like [`upload_pack`], but hiding the refs returned by `hide_refs` from the
+/// client.
+///
+/// `hide_refs` is called with the [`Header`] once it was received, and returns
+/// `uploadpack.hideRefs` patterns relative to the requested namespace (eg.
+/// `refs/remotes`). If any refs are hidden, only objects reachable from the
+/// refs which are not can be fetched.
+pub async fn upload_pack_with<R, W, F>(
     git_dir: impl AsRef<Path>,
     recv: R,
     mut send: W,
+    hide_refs: F,
 ) -> io::Result<(Header, impl Future<Output = io::Result<ExitStatus>>)>
 where
     R: AsyncRead + Unpin,
     W: AsyncWrite + Unpin,
+    F: FnOnce(&Header) -> Vec<String>,
 {
-    let mut recv = BufReader::new(recv);
+    let mut recv = BufRecv::new(recv);
     let header: Header = match recv.fill_buf().await?.first() {
         // legacy clients don't send a proper pktline header :(
         Some(b'g') => {
@@ -129,11 +158,12 @@ where
         .unwrap_or(0);
     // legacy
     let stateless_ls = header.extra.iter().any(|(k, _)| k == "ls");
+    let hidden = hide_refs(&header);
 
     let fut = async move {
         if protocol_version < 2 {
             if stateless_ls {
-                return legacy::advertise_refs(git_dir, &namespace, recv, send).await;
+                return legacy::advertise_refs(git_dir, &namespace, &hidden, recv, send).await;
             }
         } else {
             advertise_capabilities(&mut send).await?;
@@ -151,16 +181,23 @@ where
                 .env("GIT_NAMESPACE", namespace)
                 .args(&[
                     "-c",
-                    "uploadpack.allowanysha1inwant=true",
+                    if hidden.is_empty() {
+                        "uploadpack.allowanysha1inwant=true"
+                    } else {
+                        "uploadpack.allowreachablesha1inwant=true"
+                    },
                     "-c",
                     "uploadpack.allowrefinwant=true",
                     "-c",
                     "lsrefs.unborn=ignore",
-                    "upload-pack",
-                    "--strict",
-                    "--stateless-rpc",
-                    ".",
-                ])
+                ]);
+            // Patterns are matched against the refname with the namespace
+            // stripped
+            for pattern in &hidden {
+                cmd.arg("-c")
+                    .arg(format!("uploadpack.hiderefs={}", pattern));
+            }
+            cmd.args(&["upload-pack", "--strict", "--stateless-rpc", "."])
                 .stdout(Stdio::piped())
                 .stdin(Stdio::piped())
                 .stderr(Stdio::inherit())
@@ -173,8 +210,8 @@ where
         let mut stdout = child.stdout.take().unwrap();
 
         try_join!(
-            copy(&mut recv, &mut stdin),
-            copy(&mut stdout, &mut send),
+            pipe_buf(&mut recv, &mut stdin),
+            pipe(&mut stdout, &mut send),
             child.status(),
         )
         .map(|(_, _, status)| status)
@@ -199,11 +236,27 @@ where
             b"fetch=ref-in-want",
         ]
     });
+    // Packet line length prefixes of the above, accounting for the prefix
+    // itself and the trailing newline
+    static PREFIXES: Lazy<Vec<[u8; 4]>> = Lazy::new(|| {
+        CAPABILITIES
+            .iter()
+            .map(|cap| {
+                let mut prefix = [0; 4];
+                prefix.copy_from_slice(format!("{:04x}", cap.len() + 5).as_bytes());
+                prefix
+            })
+            .collect()
+    });
 
-    for cap in *CAPABILITIES {
-        packetline::encode::text_to_write(cap, &mut send).await?;
+    let mut bufs = Vec::with_capacity(CAPABILITIES.len() * 3 + 1);
+    for (prefix, cap) in PREFIXES.iter().zip(*CAPABILITIES) {
+        bufs.push(IoSlice::new(prefix));
+        bufs.push(IoSlice::new(cap));
+        bufs.push(IoSlice::new(b"\n"));
     }
-    packetline::encode::flush_to_write(&mut send).await?;
+    bufs.push(IoSlice::new(b"0000"));
+    write_all_vectored(&mut send, &mut bufs).await?;
 
     Ok(())
 }
@@ -18,6 +18,7 @@ use git_ref::{
 pub(super) async fn advertise_refs<R, W>(
     git_dir: impl AsRef<Path>,
     namespace: &str,
+    hidden: &[String],
     mut recv: R,
     mut send: W,
 ) -> io::Result<ExitStatus>
@@ -73,6 +74,12 @@ where
             cmd.arg("-c")
                 .arg(format!("uploadpack.hiderefs=!{}", r.as_bstr()));
         }
+        for pattern in hidden {
+            cmd.arg("-c").arg(format!(
+                "uploadpack.hiderefs=refs/namespaces/{}/{}",
+                namespace, pattern
+            ));
+        }
 
         cmd.args(&[
             "upload-pack",
@@ -205,6 +205,43 @@ fn want_ref() {
     )
 }
 
+#[test]
+fn hidden_refs() {
+    let remote = upstream();
+    let (client, server) = futures_ringbuf::Endpoint::pair(256, 256);
+    let client = async move {
+        let (recv, send) = client.split();
+        ls::ls_refs(
+            ls::Options {
+                repo: "foo".into(),
+                extra_params: vec![],
+                ref_prefixes: vec!["refs/heads/".into(), "refs/pulls/".into()],
+            },
+            recv,
+            send,
+        )
+        .await
+    };
+    let server = {
+        let (recv, send) = server.split();
+        upload_pack::upload_pack_with(&remote, recv, send, |hdr| {
+            assert_eq!("foo", hdr.path);
+            vec!["refs/pulls".to_owned()]
+        })
+        .and_then(|(_hdr, run)| run)
+    };
+
+    let (refs, status) =
+        futures::executor::block_on(futures::future::try_join(client, server)).unwrap();
+    assert!(status.success());
+    assert_eq!(
+        refs.iter().map(|r| r.unpack().0).collect::<BTreeSet<_>>(),
+        ["refs/heads/main".into(), "refs/heads/next".into()]
+            .iter()
+            .collect::<BTreeSet<_>>()
+    );
+}
+
 #[test]
 #[should_panic(expected = "`fetch` is empty")]
 fn empty_fetch() {
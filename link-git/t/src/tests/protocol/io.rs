@@ -0,0 +1,236 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    io::{self, IoSlice},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{
+    executor::block_on,
+    io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _, Cursor},
+};
+use link_git::protocol::io::{pipe, BufRecv, BufSend, CHUNK_SIZE};
+
+/// [`AsyncWrite`] which records every write, and accepts at most `max` bytes
+/// per write.
+struct Recorder {
+    max: usize,
+    writes: Vec<Vec<u8>>,
+}
+
+impl Recorder {
+    fn new() -> Self {
+        Self::with_max(usize::MAX)
+    }
+
+    fn with_max(max: usize) -> Self {
+        Self {
+            max,
+            writes: Vec::new(),
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        self.writes.concat()
+    }
+}
+
+impl AsyncWrite for Recorder {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_write_vectored(cx, &[IoSlice::new(buf)])
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let mut write = Vec::new();
+        for buf in bufs {
+            let n = (this.max - write.len()).min(buf.len());
+            write.extend_from_slice(&buf[..n]);
+            if write.len() == this.max {
+                break;
+            }
+        }
+        let n = write.len();
+        this.writes.push(write);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// [`AsyncRead`] which returns at most `max` bytes per read.
+struct Trickle<R> {
+    max: usize,
+    inner: R,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Trickle<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let n = this.max.min(buf.len());
+        Pin::new(&mut this.inner).poll_read(cx, &mut buf[..n])
+    }
+}
+
+fn input(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[test]
+fn recv_reads_everything() {
+    let data = input(3 * CHUNK_SIZE + 17);
+    let out = block_on(async {
+        let mut recv = BufRecv::new(Trickle {
+            max: 1000,
+            inner: Cursor::new(&data),
+        });
+        let mut out = Vec::new();
+        recv.read_to_end(&mut out).await.unwrap();
+        out
+    });
+    assert_eq!(data, out)
+}
+
+#[test]
+fn recv_chunks_outlive_the_next_read() {
+    let data = input(2 * CHUNK_SIZE);
+    block_on(async {
+        let mut recv = BufRecv::new(Trickle {
+            max: CHUNK_SIZE,
+            inner: Cursor::new(&data),
+        });
+        let fst = recv.chunk().await.unwrap().unwrap();
+        let snd = recv.chunk().await.unwrap().unwrap();
+        assert!(recv.chunk().await.unwrap().is_none());
+
+        assert_eq!(&fst[..], &data[..CHUNK_SIZE]);
+        assert_eq!(&snd[..], &data[CHUNK_SIZE..]);
+    })
+}
+
+#[test]
+fn chunk_split_shares_data() {
+    let data = input(100);
+    block_on(async {
+        let mut recv = BufRecv::new(Cursor::new(&data));
+        let mut tail = recv.chunk().await.unwrap().unwrap();
+        let head = tail.split_to(40);
+
+        assert_eq!(&head[..], &data[..40]);
+        assert_eq!(&tail[..], &data[40..]);
+        assert_eq!(head.as_ptr().wrapping_add(40), tail.as_ptr());
+    })
+}
+
+#[test]
+fn send_coalesces_until_flushed() {
+    let writes = block_on(async {
+        let mut send = BufSend::new(Recorder::new());
+        for _ in 0..100 {
+            send.write_all(b"0009").await.unwrap();
+            send.write_all(b"hello").await.unwrap();
+        }
+        assert!(send.get_ref().writes.is_empty());
+        assert_eq!(send.pending(), 900);
+
+        send.flush().await.unwrap();
+        assert_eq!(send.pending(), 0);
+        send.get_ref().writes.clone()
+    });
+
+    assert_eq!(writes.len(), 1);
+    assert_eq!(writes[0], b"0009hello".repeat(100));
+}
+
+#[test]
+fn send_writes_when_above_high_water() {
+    let data = input(CHUNK_SIZE / 2);
+    let send = block_on(async {
+        let mut send = BufSend::new(Recorder::new());
+        for _ in 0..10 {
+            send.write_all(&data).await.unwrap();
+        }
+        assert!(!send.get_ref().writes.is_empty());
+        send.close().await.unwrap();
+        send
+    });
+
+    assert_eq!(send.get_ref().bytes(), data.repeat(10));
+}
+
+#[test]
+fn send_survives_short_writes() {
+    let data = input(CHUNK_SIZE + 3);
+    let send = block_on(async {
+        let mut recv = BufRecv::new(Trickle {
+            max: 1000,
+            inner: Cursor::new(&data),
+        });
+        let mut send = BufSend::new(Recorder::with_max(7));
+        while let Some(chunk) = recv.chunk().await.unwrap() {
+            send.enqueue(chunk);
+        }
+        send.write_all(b"trailer").await.unwrap();
+        send.flush().await.unwrap();
+        send
+    });
+
+    let mut expected = data;
+    expected.extend_from_slice(b"trailer");
+    assert_eq!(send.get_ref().bytes(), expected);
+    assert!(send.get_ref().writes.iter().all(|w| w.len() <= 7));
+}
+
+#[test]
+fn enqueued_chunks_are_written_vectored() {
+    let data = input(3000);
+    let writes = block_on(async {
+        let mut recv = BufRecv::new(Trickle {
+            max: 1000,
+            inner: Cursor::new(&data),
+        });
+        let mut send = BufSend::new(Recorder::new());
+        while let Some(chunk) = recv.chunk().await.unwrap() {
+            send.enqueue(chunk);
+        }
+        send.flush().await.unwrap();
+        send.get_ref().writes.clone()
+    });
+
+    assert_eq!(writes, vec![data]);
+}
+
+#[test]
+fn pipe_copies_everything() {
+    let data = input(5 * CHUNK_SIZE + 1);
+    let (n, out) = block_on(async {
+        let mut out = Recorder::new();
+        let n = pipe(Cursor::new(&data), &mut out).await.unwrap();
+        (n, out.bytes())
+    });
+
+    assert_eq!(n, data.len() as u64);
+    assert_eq!(data, out);
+}
@@ -0,0 +1,207 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Batched payloads.
+//!
+//! Instead of a single [`crate::Data`] or [`crate::Track`] line, a hook may be
+//! sent a [`Batch`] of them:
+//!
+//! ```text
+//! #batch v1 LF
+//! <record> LF
+//! ...
+//! #eot LF
+//! ```
+//!
+//! The header carries the [`VERSION`] of the format, and the [`TRAILER`]
+//! marks the end of the batch, so a hook can process the records as they
+//! arrive, see [`Decoder`].
+
+use std::{fmt, str::FromStr};
+
+use super::{sealed, Display};
+
+/// The current version of the batch format.
+pub const VERSION: u32 = 1;
+/// The prefix of the header line, followed by ` v<version>`.
+pub const HEADER: &str = "#batch";
+/// The line marking the end of a batch.
+pub const TRAILER: &str = "#eot";
+
+/// A batch of records, eg. [`crate::Data`] or [`crate::Track`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Batch<T> {
+    pub records: Vec<T>,
+}
+
+impl<T> Batch<T> {
+    pub fn new(records: Vec<T>) -> Self {
+        Self { records }
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+impl<T> From<Vec<T>> for Batch<T> {
+    fn from(records: Vec<T>) -> Self {
+        Self::new(records)
+    }
+}
+
+impl<T> FromIterator<T> for Batch<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+impl<T> IntoIterator for Batch<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.records.into_iter()
+    }
+}
+
+/// Records are expected to be terminated by a newline already, as the
+/// [`fmt::Display`] impls of [`crate::Data`] and [`crate::Track`] are.
+impl<T: fmt::Display> fmt::Display for Batch<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} v{}", HEADER, VERSION)?;
+        for record in &self.records {
+            write!(f, "{}", record)?;
+        }
+        writeln!(f, "{}", TRAILER)
+    }
+}
+
+impl<T> sealed::Sealed for Batch<T> {}
+impl<T: fmt::Display> Display for Batch<T> {
+    fn display(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl<T, E> FromStr for Batch<T>
+where
+    T: FromStr<Err = E>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    type Err = error::Parse<E>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut decoder = Decoder::new();
+        let mut records = Vec::new();
+        for line in s.split_inclusive('\n') {
+            if decoder.is_done() {
+                return Err(error::Parse::Extra(line.to_owned()));
+            }
+            if let Some(record) = decoder.decode(line)? {
+                records.push(record)
+            }
+        }
+        if !decoder.is_done() {
+            return Err(error::Parse::Missing(TRAILER));
+        }
+
+        Ok(Self { records })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Header,
+    Records,
+    Done,
+}
+
+/// Incremental decoder of a [`Batch`], fed one line at a time.
+#[derive(Debug)]
+pub struct Decoder<T> {
+    state: State,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<T> Default for Decoder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Decoder<T> {
+    pub fn new() -> Self {
+        Self {
+            state: State::Header,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Whether the [`TRAILER`] was decoded.
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+}
+
+impl<T, E> Decoder<T>
+where
+    T: FromStr<Err = E>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    /// Decode the next `line`, including its terminating newline.
+    ///
+    /// Yields the record on the line, or `None` if the line is the header or
+    /// the [`TRAILER`].
+    pub fn decode(&mut self, line: &str) -> Result<Option<T>, error::Parse<E>> {
+        let trimmed = line
+            .strip_suffix('\n')
+            .ok_or_else(|| error::Parse::Newline(line.to_owned()))?;
+        match self.state {
+            State::Header => {
+                let version = trimmed
+                    .strip_prefix(HEADER)
+                    .and_then(|v| v.strip_prefix(" v"))
+                    .ok_or_else(|| error::Parse::Header(trimmed.to_owned()))?;
+                match version.parse::<u32>() {
+                    Ok(VERSION) => {
+                        self.state = State::Records;
+                        Ok(None)
+                    },
+                    _ => Err(error::Parse::Version(version.to_owned())),
+                }
+            },
+            State::Records if trimmed == TRAILER => {
+                self.state = State::Done;
+                Ok(None)
+            },
+            State::Records => line.parse().map(Some).map_err(error::Parse::Record),
+            State::Done => Err(error::Parse::Extra(line.to_owned())),
+        }
+    }
+}
+
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    #[non_exhaustive]
+    pub enum Parse<E: std::error::Error + Send + Sync + 'static> {
+        #[error("expected batch header, but found {0}")]
+        Header(String),
+        #[error("unsupported batch version {0}")]
+        Version(String),
+        #[error("found extra data {0}")]
+        Extra(String),
+        #[error("missing {0}")]
+        Missing(&'static str),
+        #[error("expected newline, but found {0}")]
+        Newline(String),
+        #[error(transparent)]
+        Record(E),
+    }
+}
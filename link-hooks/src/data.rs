@@ -30,6 +30,20 @@ where
     }
 }
 
+#[cfg(feature = "fuzzing")]
+impl<'a, R> arbitrary::Arbitrary<'a> for Data<R>
+where
+    R: arbitrary::Arbitrary<'a>,
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self {
+            urn: u.arbitrary()?,
+            old: u.arbitrary()?,
+            new: u.arbitrary()?,
+        })
+    }
+}
+
 impl<R> fmt::Display for Data<R>
 where
     R: HasProtocol + fmt::Display,
@@ -0,0 +1,229 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! In-process hooks.
+//!
+//! For embedders which want to react to [`Notification`]s without spawning
+//! processes, a [`Hook`] can be registered with a [`Dispatcher`]. The
+//! [`Dispatcher`] invokes all registered hooks in the background, and
+//! guarantees that notifications concerning the same URN are delivered to
+//! each hook in the order they were dispatched. Notifications concerning
+//! different URNs may be delivered concurrently.
+//!
+//! If a hook returns an error, the failed notification is put on a bounded
+//! dead-letter queue, which can be drained via [`Dispatcher::dead_letters`].
+
+use std::{
+    collections::{hash_map::DefaultHasher, VecDeque},
+    fmt,
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex, RwLock},
+};
+
+use futures::{future, Future, FutureExt as _};
+use tokio::sync::mpsc;
+
+use super::{Data, Notification, Track};
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// A hook invoked in-process.
+///
+/// Both methods do nothing by default, so implementors only need to provide
+/// the ones they are interested in.
+#[async_trait]
+pub trait Hook<R>: Send + Sync
+where
+    R: Send + Sync + 'static,
+{
+    /// The name of the hook, identifying it in [`DeadLetter`]s.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// React to a change of the ref `data.urn`.
+    async fn on_data(&self, _data: &Data<R>) -> Result<(), BoxError> {
+        Ok(())
+    }
+
+    /// React to a change of the tracking relationship `track`.
+    async fn on_track(&self, _track: &Track<R>) -> Result<(), BoxError> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// The number of notifications processed concurrently.
+    pub workers: usize,
+    /// The number of notifications buffered per worker, before
+    /// [`Dispatcher::dispatch`] waits.
+    pub buffer: usize,
+    /// The maximum number of [`DeadLetter`]s retained. When exceeded, the
+    /// oldest ones are dropped.
+    pub dead_letters: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            buffer: 64,
+            dead_letters: 256,
+        }
+    }
+}
+
+/// A [`Notification`] a [`Hook`] failed to process.
+pub struct DeadLetter<R> {
+    /// The [`Hook::name`].
+    pub hook: String,
+    pub notification: Notification<R>,
+    pub error: BoxError,
+}
+
+impl<R: fmt::Debug> fmt::Debug for DeadLetter<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeadLetter")
+            .field("hook", &self.hook)
+            .field("notification", &self.notification)
+            .field("error", &self.error.to_string())
+            .finish()
+    }
+}
+
+/// Dispatcher of [`Notification`]s to in-process [`Hook`]s.
+pub struct Dispatcher<R: Send + Sync + 'static> {
+    hooks: Arc<RwLock<Vec<Arc<dyn Hook<R>>>>>,
+    workers: Arc<Vec<mpsc::Sender<Notification<R>>>>,
+    dead_letters: Arc<Mutex<VecDeque<DeadLetter<R>>>>,
+}
+
+impl<R: Send + Sync + 'static> Clone for Dispatcher<R> {
+    fn clone(&self) -> Self {
+        Self {
+            hooks: self.hooks.clone(),
+            workers: self.workers.clone(),
+            dead_letters: self.dead_letters.clone(),
+        }
+    }
+}
+
+impl<R> Dispatcher<R>
+where
+    R: Clone + Hash + Send + Sync + 'static,
+{
+    /// Create a [`Dispatcher`], along with the future processing the
+    /// dispatched notifications.
+    ///
+    /// The future must be spawned for hooks to be invoked. It completes once
+    /// all clones of the [`Dispatcher`] are dropped.
+    pub fn new(config: Config) -> (Self, impl Future<Output = ()> + Send + 'static) {
+        let hooks = Arc::new(RwLock::new(Vec::new()));
+        let dead_letters = Arc::new(Mutex::new(VecDeque::new()));
+        let (workers, run): (Vec<_>, Vec<_>) = (0..config.workers.max(1))
+            .map(|_| {
+                let (tx, rx) = mpsc::channel(config.buffer.max(1));
+                let run = worker(rx, hooks.clone(), dead_letters.clone(), config.dead_letters);
+                (tx, run)
+            })
+            .unzip();
+
+        let this = Self {
+            hooks,
+            workers: Arc::new(workers),
+            dead_letters,
+        };
+        (this, future::join_all(run).map(|_| ()))
+    }
+
+    /// Register `hook`, to be invoked for all subsequently dispatched
+    /// notifications.
+    ///
+    /// Hooks are invoked in the order they were registered.
+    pub fn register(&self, hook: Arc<dyn Hook<R>>) {
+        self.hooks
+            .write()
+            .expect("BUG: hooks lock poisoned")
+            .push(hook)
+    }
+
+    /// Dispatch `notification` to the registered hooks, waiting if the
+    /// responsible worker is busy.
+    pub async fn dispatch(&self, notification: Notification<R>) {
+        if self.worker(&notification).send(notification).await.is_err() {
+            tracing::warn!("hook worker stopped, dropping notification")
+        }
+    }
+
+    /// Dispatch `notification` to the registered hooks without waiting.
+    ///
+    /// Returns the `notification` if the responsible worker is busy.
+    pub fn try_dispatch(&self, notification: Notification<R>) -> Result<(), Notification<R>> {
+        use mpsc::error::TrySendError::*;
+
+        match self.worker(&notification).try_send(notification) {
+            Ok(()) => Ok(()),
+            Err(Full(notification)) => Err(notification),
+            Err(Closed(_)) => {
+                tracing::warn!("hook worker stopped, dropping notification");
+                Ok(())
+            },
+        }
+    }
+
+    /// Drain the [`DeadLetter`]s, oldest first.
+    pub fn dead_letters(&self) -> Vec<DeadLetter<R>> {
+        self.dead_letters
+            .lock()
+            .expect("BUG: dead letters lock poisoned")
+            .drain(..)
+            .collect()
+    }
+
+    /// The worker responsible for the URN of `notification`. The path of the
+    /// URN is ignored, so all notifications concerning a URN are processed in
+    /// order.
+    fn worker(&self, notification: &Notification<R>) -> &mpsc::Sender<Notification<R>> {
+        let id = match notification {
+            Notification::Data(data) => &data.urn.id,
+            Notification::Track(track) => &track.urn.id,
+        };
+        let mut hasher = DefaultHasher::new();
+        id.hash(&mut hasher);
+        &self.workers[hasher.finish() as usize % self.workers.len()]
+    }
+}
+
+async fn worker<R>(
+    mut rx: mpsc::Receiver<Notification<R>>,
+    hooks: Arc<RwLock<Vec<Arc<dyn Hook<R>>>>>,
+    dead_letters: Arc<Mutex<VecDeque<DeadLetter<R>>>>,
+    max_dead_letters: usize,
+) where
+    R: Clone + Send + Sync + 'static,
+{
+    while let Some(notification) = rx.recv().await {
+        let hooks = hooks.read().expect("BUG: hooks lock poisoned").clone();
+        for hook in hooks {
+            let res = match &notification {
+                Notification::Data(data) => hook.on_data(data).await,
+                Notification::Track(track) => hook.on_track(track).await,
+            };
+            if let Err(error) = res {
+                tracing::warn!(hook = %hook.name(), err = %error, "hook failed");
+                let mut dead = dead_letters
+                    .lock()
+                    .expect("BUG: dead letters lock poisoned");
+                dead.push_back(DeadLetter {
+                    hook: hook.name().to_owned(),
+                    notification: notification.clone(),
+                    error,
+                });
+                while dead.len() > max_dead_letters {
+                    dead.pop_front();
+                }
+            }
+        }
+    }
+}
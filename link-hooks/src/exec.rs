@@ -0,0 +1,262 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! One-shot execution of hooks.
+//!
+//! Unlike [`crate::Hooks`], which streams [`Notification`]s to long-running
+//! hook processes, the [`Executor`] spawns each hook anew for every
+//! [`Notification`]. The hook receives the [`Display`]ed notification followed
+//! by the [`EOT`] character on its stdin, and is expected to exit once it has
+//! processed it. With [`Executor::notify_batch`], the hook receives a
+//! [`Batch`] of notifications instead.
+//!
+//! Hooks run in the background: [`Executor::notify`] never waits for them.
+//! The number of hooks running at the same time is bounded by
+//! [`Config::concurrency`], and hooks which do not exit within
+//! [`Config::timeout`] are killed. Hooks which fail are reported as
+//! [`Failure`]s.
+
+use std::{
+    fmt,
+    fs,
+    io,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    sync::Arc,
+    time::Duration,
+};
+
+use multihash::Multihash;
+use thiserror::Error;
+use tokio::{
+    io::AsyncWriteExt as _,
+    process::Command,
+    sync::{mpsc, Semaphore},
+};
+
+use link_identities::urn::HasProtocol;
+
+use super::{batch::Batch, hook::EOT, Display, Notification};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// The duration a hook is given to process a notification and exit,
+    /// before it is killed.
+    pub timeout: Duration,
+    /// The maximum number of hooks running at the same time. Notifications
+    /// arriving while the limit is reached are queued.
+    pub concurrency: usize,
+    /// The number of [`Failure`]s buffered for the receiver returned by
+    /// [`Executor::new`]. Failures are dropped if the buffer is full.
+    pub failures: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            concurrency: 4,
+            failures: 32,
+        }
+    }
+}
+
+/// A hook which failed to process a notification.
+#[derive(Debug)]
+pub struct Failure {
+    /// The path of the hook executable.
+    pub hook: PathBuf,
+    pub error: Error,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("failed to spawn hook")]
+    Spawn(#[source] io::Error),
+
+    #[error("failed to write notification to hook")]
+    Write(#[source] io::Error),
+
+    #[error("failed to wait for hook")]
+    Wait(#[source] io::Error),
+
+    #[error("hook did not exit within {0:?}")]
+    Timeout(Duration),
+
+    #[error("hook exited with {0}")]
+    Exit(ExitStatus),
+}
+
+/// The receiving end of the [`Failure`]s reported by an [`Executor`].
+pub type Failures = mpsc::Receiver<Failure>;
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.hook.display(), self.error)
+    }
+}
+
+/// Discover the hooks in `dir`, ie. the executable files in it.
+///
+/// Entries which are not executable files are skipped. If `dir` does not
+/// exist, there are no hooks.
+pub fn discover(dir: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+
+    let mut hooks = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_file() && is_executable(&meta) {
+            hooks.push(entry.path())
+        } else {
+            tracing::warn!(hook = %entry.path().display(), "skipping hook entry that is not an executable file")
+        }
+    }
+    hooks.sort();
+
+    Ok(hooks)
+}
+
+#[cfg(unix)]
+fn is_executable(meta: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt as _;
+    meta.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_: &fs::Metadata) -> bool {
+    true
+}
+
+/// Executor of one-shot hooks.
+///
+/// Must be used within a tokio runtime.
+#[derive(Clone)]
+pub struct Executor {
+    data_hooks: Arc<Vec<PathBuf>>,
+    track_hooks: Arc<Vec<PathBuf>>,
+    config: Config,
+    permits: Arc<Semaphore>,
+    failures: mpsc::Sender<Failure>,
+}
+
+impl Executor {
+    /// Create an [`Executor`] running `data_hooks` for
+    /// [`Notification::Data`], and `track_hooks` for
+    /// [`Notification::Track`].
+    ///
+    /// The returned receiver yields the [`Failure`]s of hooks. It may be
+    /// dropped if the caller is not interested in them, in which case they
+    /// are only logged.
+    pub fn new(
+        config: Config,
+        data_hooks: Vec<PathBuf>,
+        track_hooks: Vec<PathBuf>,
+    ) -> (Self, Failures) {
+        let (failures, rx) = mpsc::channel(config.failures.max(1));
+        let this = Self {
+            data_hooks: Arc::new(data_hooks),
+            track_hooks: Arc::new(track_hooks),
+            config,
+            permits: Arc::new(Semaphore::new(config.concurrency.max(1))),
+            failures,
+        };
+        (this, rx)
+    }
+
+    /// Run the hooks for `notification` in the background.
+    pub fn notify<R>(&self, notification: Notification<R>)
+    where
+        R: HasProtocol + fmt::Display,
+        for<'a> &'a R: Into<Multihash>,
+    {
+        match &notification {
+            Notification::Data(data) => self.spawn(&self.data_hooks, data.display()),
+            Notification::Track(track) => self.spawn(&self.track_hooks, track.display()),
+        }
+    }
+
+    /// Run the hooks for `notifications` in the background, spawning each
+    /// hook at most once with a [`Batch`] of the relevant notifications.
+    pub fn notify_batch<R, I>(&self, notifications: I)
+    where
+        R: HasProtocol + fmt::Display,
+        for<'a> &'a R: Into<Multihash>,
+        I: IntoIterator<Item = Notification<R>>,
+    {
+        let (mut data, mut track) = (Vec::new(), Vec::new());
+        for notification in notifications {
+            match notification {
+                Notification::Data(d) => data.push(d),
+                Notification::Track(t) => track.push(t),
+            }
+        }
+        if !data.is_empty() {
+            self.spawn(&self.data_hooks, Batch::new(data).display())
+        }
+        if !track.is_empty() {
+            self.spawn(&self.track_hooks, Batch::new(track).display())
+        }
+    }
+
+    fn spawn(&self, hooks: &Arc<Vec<PathBuf>>, payload: String) {
+        let payload = Arc::new(payload);
+        for hook in hooks.iter().cloned() {
+            let payload = payload.clone();
+            let permits = self.permits.clone();
+            let failures = self.failures.clone();
+            let timeout = self.config.timeout;
+            tokio::spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                if let Err(error) = run(&hook, payload.as_bytes(), timeout).await {
+                    tracing::warn!(hook = %hook.display(), err = %error, "hook failed");
+                    failures.try_send(Failure { hook, error }).ok();
+                }
+            });
+        }
+    }
+}
+
+async fn run(hook: &Path, payload: &[u8], timeout: Duration) -> Result<(), Error> {
+    let mut child = Command::new(hook)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(Error::Spawn)?;
+
+    let status = tokio::time::timeout(timeout, async {
+        let mut stdin = child
+            .stdin
+            .take()
+            .expect("BUG: stdin was not set up for subprocess");
+        async {
+            stdin.write_all(payload).await?;
+            stdin.write_all(&[EOT]).await?;
+            stdin.shutdown().await
+        }
+        .await
+        .map_err(Error::Write)?;
+        drop(stdin);
+        child.wait().await.map_err(Error::Wait)
+    })
+    .await;
+
+    match status {
+        Err(_) => {
+            child.kill().await.ok();
+            Err(Error::Timeout(timeout))
+        },
+        Ok(Err(e)) => Err(e),
+        Ok(Ok(status)) if status.success() => Ok(()),
+        Ok(Ok(status)) => Err(Error::Exit(status)),
+    }
+}
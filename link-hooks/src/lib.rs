@@ -10,9 +10,20 @@ pub use data::Data;
 pub mod track;
 pub use track::Track;
 
+pub mod batch;
+pub use batch::Batch;
+
 pub mod hook;
 pub use hook::{Hooks, Notification};
 
+pub mod exec;
+pub use exec::Executor;
+
+pub mod dispatch;
+pub use dispatch::Dispatcher;
+
+pub mod webhook;
+
 mod sealed;
 
 pub trait Display: sealed::Sealed {
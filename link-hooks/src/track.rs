@@ -32,6 +32,21 @@ where
     }
 }
 
+#[cfg(feature = "fuzzing")]
+impl<'a, R> arbitrary::Arbitrary<'a> for Track<R>
+where
+    R: arbitrary::Arbitrary<'a>,
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self {
+            urn: u.arbitrary()?,
+            peer: u.arbitrary()?,
+            old: u.arbitrary()?,
+            new: u.arbitrary()?,
+        })
+    }
+}
+
 impl<R> fmt::Display for Track<R>
 where
     R: HasProtocol + fmt::Display,
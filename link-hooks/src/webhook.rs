@@ -0,0 +1,251 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Delivery of events to HTTP endpoints.
+//!
+//! Unlike [`crate::Hooks`], which are executables installed by the operator of
+//! a node, webhooks are declared by the maintainers of a project and notified
+//! by infrastructure they don't necessarily operate. An [`Event`] is `POST`ed
+//! to each [`Endpoint`] as a JSON object, with the following headers:
+//!
+//! * `Content-Type: application/json`
+//! * `X-Radicle-Event`: the [`Event::kind`]
+//! * `X-Radicle-Signature`: `sha256=<hex>`, the HMAC-SHA256 of the body keyed
+//!   by the shared secret of the [`Endpoint`]. Omitted if there is no secret.
+//!
+//! The HTTP transport is abstracted over by [`Client`].
+
+use std::{fmt, time::Duration};
+
+use futures::future;
+use hmac::{Hmac, Mac as _, NewMac as _};
+use link_crypto::PeerId;
+use link_identities::urn::{HasProtocol, Urn};
+use multihash::Multihash;
+use sha2::Sha256;
+
+pub const HEADER_EVENT: &str = "X-Radicle-Event";
+pub const HEADER_SIGNATURE: &str = "X-Radicle-Signature";
+
+/// An event concerning a project.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event<R> {
+    /// `rev` was replicated from `peer`.
+    Replicated { urn: Urn<R>, peer: PeerId, rev: R },
+    /// A request-pull from `peer` was served, updating `refs`.
+    RequestPull {
+        urn: Urn<R>,
+        peer: PeerId,
+        refs: Vec<(String, R)>,
+    },
+}
+
+impl<R> Event<R> {
+    pub fn urn(&self) -> &Urn<R> {
+        match self {
+            Self::Replicated { urn, .. } | Self::RequestPull { urn, .. } => urn,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Replicated { .. } => "replicated",
+            Self::RequestPull { .. } => "request_pull",
+        }
+    }
+}
+
+impl<R> Event<R>
+where
+    R: HasProtocol + fmt::Display,
+    for<'a> &'a R: Into<Multihash>,
+{
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::json;
+
+        match self {
+            Self::Replicated { urn, peer, rev } => json!({
+                "event": self.kind(),
+                "urn": urn.to_string(),
+                "peer": peer.to_string(),
+                "rev": rev.to_string(),
+            }),
+            Self::RequestPull { urn, peer, refs } => json!({
+                "event": self.kind(),
+                "urn": urn.to_string(),
+                "peer": peer.to_string(),
+                "refs": refs
+                    .iter()
+                    .map(|(name, oid)| (name.clone(), json!(oid.to_string())))
+                    .collect::<serde_json::Map<_, _>>(),
+            }),
+        }
+    }
+}
+
+/// A resolved webhook endpoint.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub url: String,
+    pub secret: Option<Vec<u8>>,
+}
+
+// Don't leak the secret into logs
+impl fmt::Debug for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Endpoint")
+            .field("url", &self.url)
+            .field("secret", &self.secret.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// The HTTP transport used to deliver events.
+#[async_trait]
+pub trait Client {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// `POST` the `body` to `url`, with the additional `headers`.
+    ///
+    /// Must return an error if the response status does not indicate success.
+    async fn post(
+        &self,
+        url: &str,
+        headers: Vec<(&'static str, String)>,
+        body: Vec<u8>,
+    ) -> Result<(), Self::Error>;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// Maximum time to wait for a single delivery attempt.
+    pub timeout: Duration,
+    /// Number of times to attempt delivery before giving up.
+    pub attempts: usize,
+    /// Time to wait before the first retry. Doubled on every subsequent retry.
+    pub backoff: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            attempts: 3,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Deliver<E: std::error::Error + Send + Sync + 'static> {
+        #[error("timed out")]
+        Timeout,
+        #[error(transparent)]
+        Client(E),
+    }
+}
+
+/// Delivers [`Event`]s to [`Endpoint`]s using a [`Client`].
+pub struct Dispatcher<C> {
+    client: C,
+    config: Config,
+}
+
+impl<C> Dispatcher<C>
+where
+    C: Client + Send + Sync,
+{
+    pub fn new(config: Config, client: C) -> Self {
+        Self { client, config }
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    /// Deliver `event` to all `endpoints` concurrently.
+    ///
+    /// Returns the endpoints to which delivery failed after all attempts,
+    /// along with the last error.
+    pub async fn dispatch<R>(
+        &self,
+        endpoints: &[Endpoint],
+        event: &Event<R>,
+    ) -> Vec<(Endpoint, error::Deliver<C::Error>)>
+    where
+        R: HasProtocol + fmt::Display,
+        for<'a> &'a R: Into<Multihash>,
+    {
+        let body = event.to_json().to_string().into_bytes();
+        let kind = event.kind();
+        future::join_all(endpoints.iter().map(|endpoint| {
+            let body = body.clone();
+            async move {
+                self.deliver(endpoint, kind, body)
+                    .await
+                    .err()
+                    .map(|e| (endpoint.clone(), e))
+            }
+        }))
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    #[tracing::instrument(skip(self, body), fields(url = %endpoint.url))]
+    async fn deliver(
+        &self,
+        endpoint: &Endpoint,
+        kind: &'static str,
+        body: Vec<u8>,
+    ) -> Result<(), error::Deliver<C::Error>> {
+        let mut headers = vec![
+            ("Content-Type", "application/json".to_owned()),
+            (HEADER_EVENT, kind.to_owned()),
+        ];
+        if let Some(secret) = &endpoint.secret {
+            headers.push((HEADER_SIGNATURE, sign(secret, &body)));
+        }
+
+        let mut backoff = self.config.backoff;
+        let mut attempt = 1;
+        loop {
+            let res = tokio::time::timeout(
+                self.config.timeout,
+                self.client
+                    .post(&endpoint.url, headers.clone(), body.clone()),
+            )
+            .await;
+            let err = match res {
+                Ok(Ok(())) => return Ok(()),
+                Ok(Err(e)) => error::Deliver::Client(e),
+                Err(_) => error::Deliver::Timeout,
+            };
+            if attempt >= self.config.attempts {
+                return Err(err);
+            }
+            tracing::debug!(err = %err, attempt, "webhook delivery failed, retrying");
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+/// Compute the value of the [`HEADER_SIGNATURE`] header for `body`.
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    let hex = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+
+    format!("sha256={}", hex)
+}
@@ -1,4 +1,7 @@
 // Copyright © 2022 The Radicle Link Contributors
 // SPDX-License-Identifier: GPL-3.0-or-later
 
+mod dispatch;
+mod exec;
 mod smoke;
+mod webhook;
@@ -0,0 +1,92 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use link_hooks::{
+    dispatch::{self, BoxError, Dispatcher, Hook},
+    Data,
+    Notification,
+    Track,
+};
+use radicle_git_ext::Oid;
+use test_helpers::logging;
+
+const DATA: &str = "rad:git:hnrkyzfpih4pqsw3cp1donkmwsgh9w5fwfdwo/refs/heads/main 0c3b4502a83a309b19123adc60a23e4e92bb13fb aeff7e8e964c47ba67a0c6eeba3beb62e29379d4\n";
+const TRACK: &str = "rad:git:hnrkyzfpih4pqsw3cp1donkmwsgh9w5fwfdwo default 0c3b4502a83a309b19123adc60a23e4e92bb13fb aeff7e8e964c47ba67a0c6eeba3beb62e29379d4\n";
+
+#[derive(Default)]
+struct Record(Mutex<Vec<Notification<Oid>>>);
+
+#[async_trait::async_trait]
+impl Hook<Oid> for Record {
+    async fn on_data(&self, data: &Data<Oid>) -> Result<(), BoxError> {
+        self.0.lock().unwrap().push(data.clone().into());
+        Ok(())
+    }
+
+    async fn on_track(&self, track: &Track<Oid>) -> Result<(), BoxError> {
+        self.0.lock().unwrap().push(track.clone().into());
+        Ok(())
+    }
+}
+
+struct Failing;
+
+#[async_trait::async_trait]
+impl Hook<Oid> for Failing {
+    fn name(&self) -> &str {
+        "failing"
+    }
+
+    async fn on_data(&self, _: &Data<Oid>) -> Result<(), BoxError> {
+        Err("nope".into())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn ordered_per_urn_with_dead_letters() {
+    logging::init();
+
+    let (dispatcher, run) = Dispatcher::<Oid>::new(dispatch::Config::default());
+    tokio::spawn(run);
+    let record = Arc::new(Record::default());
+    // Registered first, so it is done by the time `record` has seen everything
+    dispatcher.register(Arc::new(Failing));
+    dispatcher.register(record.clone());
+
+    let data = DATA.parse::<Data<Oid>>().unwrap();
+    let track = TRACK.parse::<Track<Oid>>().unwrap();
+    let notifications = vec![
+        Notification::from(data.clone()),
+        Notification::from(track),
+        Notification::from(data),
+    ];
+    for n in notifications.clone() {
+        dispatcher.dispatch(n).await;
+    }
+
+    let received = link_async::timeout(Duration::from_secs(5), async {
+        loop {
+            let received = record.0.lock().unwrap().clone();
+            if received.len() == notifications.len() {
+                break received;
+            }
+            link_async::sleep(Duration::from_millis(10)).await
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(received, notifications);
+
+    let dead = dispatcher.dead_letters();
+    assert_eq!(dead.len(), 2);
+    assert!(dead.iter().all(|d| d.hook == "failing"));
+    assert!(dead
+        .iter()
+        .all(|d| matches!(d.notification, Notification::Data(_))));
+    assert!(dispatcher.dead_letters().is_empty());
+}
@@ -0,0 +1,134 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt as _,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use link_hooks::{
+    exec::{self, Executor},
+    hook::EOT,
+    Batch,
+    Data,
+    Notification,
+};
+use radicle_git_ext::Oid;
+use tempfile::TempDir;
+use test_helpers::logging;
+
+const DATA: &str = "rad:git:hnrkyzfpih4pqsw3cp1donkmwsgh9w5fwfdwo/refs/heads/main 0c3b4502a83a309b19123adc60a23e4e92bb13fb aeff7e8e964c47ba67a0c6eeba3beb62e29379d4\n";
+
+#[test]
+fn discover_executables() {
+    let dir = TempDir::new().unwrap();
+    let hook = script(dir.path(), "hook", "exit 0");
+    fs::write(dir.path().join("README"), "not a hook").unwrap();
+
+    assert_eq!(exec::discover(dir.path()).unwrap(), vec![hook]);
+    assert!(exec::discover(dir.path().join("missing"))
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn notify_data_hooks() {
+    logging::init();
+
+    let dir = TempDir::new().unwrap();
+    let out = dir.path().join("out");
+    let hook = script(
+        dir.path(),
+        "echo",
+        &format!("cat > {}.tmp && mv {0}.tmp {0}", out.display()),
+    );
+    let (executor, _failures) = Executor::new(exec::Config::default(), vec![hook], vec![]);
+    executor.notify(Notification::from(DATA.parse::<Data<Oid>>().unwrap()));
+
+    let received = wait_for(&out).await;
+    assert_eq!(received, format!("{}{}", DATA, EOT as char));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn notify_data_batch() {
+    logging::init();
+
+    let dir = TempDir::new().unwrap();
+    let out = dir.path().join("out");
+    let hook = script(
+        dir.path(),
+        "echo",
+        &format!("cat > {}.tmp && mv {0}.tmp {0}", out.display()),
+    );
+    let (executor, _failures) = Executor::new(exec::Config::default(), vec![hook], vec![]);
+    let data = DATA.parse::<Data<Oid>>().unwrap();
+    executor.notify_batch(vec![
+        Notification::from(data.clone()),
+        Notification::from(data.clone()),
+    ]);
+
+    let received = wait_for(&out).await;
+    let batch = received
+        .strip_suffix(EOT as char)
+        .unwrap()
+        .parse::<Batch<Data<Oid>>>()
+        .unwrap();
+    assert_eq!(batch, Batch::new(vec![data.clone(), data]));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn report_failures() {
+    logging::init();
+
+    let dir = TempDir::new().unwrap();
+    let failing = script(dir.path(), "failing", "cat > /dev/null; exit 3");
+    let forever = script(dir.path(), "forever", "sleep 10");
+    let config = exec::Config {
+        timeout: Duration::from_millis(500),
+        ..exec::Config::default()
+    };
+    let (executor, mut failures) =
+        Executor::new(config, vec![failing.clone(), forever.clone()], vec![]);
+    executor.notify(Notification::from(DATA.parse::<Data<Oid>>().unwrap()));
+
+    let mut reported = Vec::new();
+    for _ in 0..2 {
+        let failure = link_async::timeout(Duration::from_secs(5), failures.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        match failure.error {
+            exec::Error::Exit(status) => {
+                assert_eq!(failure.hook, failing);
+                assert_eq!(status.code(), Some(3));
+            },
+            exec::Error::Timeout(_) => assert_eq!(failure.hook, forever),
+            e => panic!("unexpected failure: {}", e),
+        }
+        reported.push(failure.hook);
+    }
+    reported.sort();
+    assert_eq!(reported, vec![failing, forever]);
+}
+
+async fn wait_for(out: &Path) -> String {
+    link_async::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Ok(received) = fs::read_to_string(out) {
+                break received;
+            }
+            link_async::sleep(Duration::from_millis(10)).await
+        }
+    })
+    .await
+    .unwrap()
+}
+
+fn script(dir: &Path, name: &str, body: &str) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
@@ -0,0 +1,135 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::{io, sync::Mutex, time::Duration};
+
+use async_trait::async_trait;
+use link_crypto::{PeerId, SecretKey};
+use link_hooks::webhook::{self, Dispatcher, Endpoint, Event};
+use link_identities::urn::Urn;
+use radicle_git_ext::Oid;
+
+struct Post {
+    url: String,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+/// Records all posts, failing the first `failures` of them.
+#[derive(Default)]
+struct Mock {
+    failures: Mutex<usize>,
+    posts: Mutex<Vec<Post>>,
+}
+
+#[async_trait]
+impl webhook::Client for Mock {
+    type Error = io::Error;
+
+    async fn post(
+        &self,
+        url: &str,
+        headers: Vec<(&'static str, String)>,
+        body: Vec<u8>,
+    ) -> Result<(), Self::Error> {
+        self.posts.lock().unwrap().push(Post {
+            url: url.to_owned(),
+            headers,
+            body,
+        });
+        let mut failures = self.failures.lock().unwrap();
+        if *failures > 0 {
+            *failures -= 1;
+            return Err(io::Error::new(io::ErrorKind::Other, "503"));
+        }
+        Ok(())
+    }
+}
+
+fn config() -> webhook::Config {
+    webhook::Config {
+        timeout: Duration::from_secs(1),
+        attempts: 3,
+        backoff: Duration::ZERO,
+    }
+}
+
+fn event() -> Event<Oid> {
+    let oid = Oid::from(git2::Oid::hash_object(git2::ObjectType::Blob, b"webhook").unwrap());
+    Event::Replicated {
+        urn: Urn::new(oid),
+        peer: PeerId::from(SecretKey::new()),
+        rev: oid,
+    }
+}
+
+fn header<'a>(post: &'a Post, name: &str) -> Option<&'a str> {
+    post.headers
+        .iter()
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v.as_str())
+}
+
+#[tokio::test]
+async fn signs_with_secret() {
+    let dispatcher = Dispatcher::new(config(), Mock::default());
+    let endpoints = vec![
+        Endpoint {
+            url: "https://signed.example.com".to_owned(),
+            secret: Some(b"s3cr3t".to_vec()),
+        },
+        Endpoint {
+            url: "https://unsigned.example.com".to_owned(),
+            secret: None,
+        },
+    ];
+    let event = event();
+    assert!(dispatcher.dispatch(&endpoints, &event).await.is_empty());
+
+    let posts = dispatcher.client().posts.lock().unwrap();
+    assert_eq!(posts.len(), 2);
+    for post in posts.iter() {
+        let json: serde_json::Value = serde_json::from_slice(&post.body).unwrap();
+        assert_eq!(json, event.to_json());
+        assert_eq!(header(post, webhook::HEADER_EVENT), Some("replicated"));
+        match post.url.as_str() {
+            "https://signed.example.com" => assert_eq!(
+                header(post, webhook::HEADER_SIGNATURE),
+                Some(webhook::sign(b"s3cr3t", &post.body).as_str())
+            ),
+            _ => assert_eq!(header(post, webhook::HEADER_SIGNATURE), None),
+        }
+    }
+}
+
+#[tokio::test]
+async fn retries() {
+    let mock = Mock {
+        failures: Mutex::new(2),
+        ..Mock::default()
+    };
+    let dispatcher = Dispatcher::new(config(), mock);
+    let endpoints = vec![Endpoint {
+        url: "https://flaky.example.com".to_owned(),
+        secret: None,
+    }];
+    assert!(dispatcher.dispatch(&endpoints, &event()).await.is_empty());
+    assert_eq!(dispatcher.client().posts.lock().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn gives_up() {
+    let mock = Mock {
+        failures: Mutex::new(5),
+        ..Mock::default()
+    };
+    let dispatcher = Dispatcher::new(config(), mock);
+    let endpoints = vec![Endpoint {
+        url: "https://down.example.com".to_owned(),
+        secret: None,
+    }];
+    let failed = dispatcher.dispatch(&endpoints, &event()).await;
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].0, endpoints[0]);
+    assert!(matches!(failed[0].1, webhook::error::Deliver::Client(_)));
+}
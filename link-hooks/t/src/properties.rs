@@ -3,7 +3,7 @@
 
 use proptest::prelude::*;
 
-use link_hooks::{Data, IsZero, Track, Updated};
+use link_hooks::{batch::Decoder, Batch, Data, IsZero, Track, Updated};
 use radicle_git_ext as ext;
 use test_helpers::roundtrip;
 
@@ -20,6 +20,21 @@ proptest! {
         roundtrip::str(track)
     }
 
+    #[test]
+    fn roundtrip_data_batch(data in prop::collection::vec(gen_data(), 0..16)) {
+        roundtrip::str(Batch::new(data))
+    }
+
+    #[test]
+    fn roundtrip_track_batch(track in prop::collection::vec(gen_track(), 0..16)) {
+        roundtrip::str(Batch::new(track))
+    }
+
+    #[test]
+    fn decode_data_batch(data in prop::collection::vec(gen_data(), 0..16)) {
+        prop_decode_batch(data)
+    }
+
     #[test]
     fn track_updated(track in gen_track()) {
         prop_track_created(track.clone());
@@ -33,6 +48,24 @@ proptest! {
         prop_data_deleted(data.clone());
         prop_data_changed(data);
     }
+
+    #[test]
+    fn garbage_data(s in any::<String>()) {
+        let _ = s.parse::<Data<ext::Oid>>();
+        let _ = s.parse::<Track<ext::Oid>>();
+    }
+}
+
+fn prop_decode_batch(data: Vec<Data<ext::Oid>>) {
+    let encoded = Batch::new(data.clone()).to_string();
+    let mut decoder = Decoder::<Data<ext::Oid>>::new();
+    let mut decoded = Vec::new();
+    for line in encoded.split_inclusive('\n') {
+        assert!(!decoder.is_done());
+        decoded.extend(decoder.decode(line).unwrap());
+    }
+    assert!(decoder.is_done());
+    assert_eq!(decoded, data);
 }
 
 fn prop_track_created(track: Track<ext::Oid>) {
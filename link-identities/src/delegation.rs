@@ -12,9 +12,13 @@ use crate::{generic, sealed};
 
 pub mod direct;
 pub mod indirect;
+pub mod policy;
+pub mod validity;
 
 pub use direct::Direct;
 pub use indirect::Indirect;
+pub use policy::Policy;
+pub use validity::Validity;
 
 /// Types which define trust delegations.
 pub trait Delegations: sealed::Sealed {
@@ -30,6 +34,27 @@ pub trait Delegations: sealed::Sealed {
     /// Nb.: "threshold" means that there must be `quorum_threshold() + 1` votes
     /// to form a quorum.
     fn quorum_threshold(&self) -> usize;
+
+    /// Whether the [`Delegations::eligible`] votes form a quorum.
+    ///
+    /// By default, this is the case if there are more than
+    /// [`Delegations::quorum_threshold`] votes.
+    fn is_quorum(&self, eligible: &BTreeSet<&PublicKey>) -> bool {
+        !eligible.is_empty() && eligible.len() > self.quorum_threshold()
+    }
+
+    /// The keys which have been revoked, and may no longer vote.
+    fn revoked(&self) -> BTreeSet<&PublicKey> {
+        BTreeSet::new()
+    }
+
+    /// Whether a vote by `key`, cast at `time` (in seconds since the epoch),
+    /// may be considered by [`Delegations::eligible`].
+    ///
+    /// `time` is `None` if it is not known when the vote was cast.
+    fn permits(&self, _key: &PublicKey, _time: Option<i64>) -> bool {
+        true
+    }
 }
 
 //// Forwarding impls for `Doc` and `Identity`
@@ -47,6 +72,23 @@ where
     fn quorum_threshold(&self) -> usize {
         self.delegations.quorum_threshold()
     }
+
+    fn is_quorum(&self, eligible: &BTreeSet<&PublicKey>) -> bool {
+        match &self.policy {
+            Some(policy) => policy.is_met(eligible),
+            None => self.delegations.is_quorum(eligible),
+        }
+    }
+
+    fn revoked(&self) -> BTreeSet<&PublicKey> {
+        let mut revoked = self.delegations.revoked();
+        revoked.extend(self.validity.revoked.keys());
+        revoked
+    }
+
+    fn permits(&self, key: &PublicKey, time: Option<i64>) -> bool {
+        self.validity.permits(key, time) && self.delegations.permits(key, time)
+    }
 }
 
 impl<T, R, C> Delegations for generic::Identity<T, R, C>
@@ -62,6 +104,18 @@ where
     fn quorum_threshold(&self) -> usize {
         self.doc.quorum_threshold()
     }
+
+    fn is_quorum(&self, eligible: &BTreeSet<&PublicKey>) -> bool {
+        self.doc.is_quorum(eligible)
+    }
+
+    fn revoked(&self) -> BTreeSet<&PublicKey> {
+        self.doc.revoked()
+    }
+
+    fn permits(&self, key: &PublicKey, time: Option<i64>) -> bool {
+        self.doc.permits(key, time)
+    }
 }
 
 /// "Existentialised" delegations.
@@ -0,0 +1,75 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    num::NonZeroU32,
+};
+
+use crypto::PublicKey;
+
+/// An explicit quorum policy, replacing the simple majority of
+/// [`super::Delegations::quorum_threshold`].
+///
+/// A quorum is reached if the sum of the `weights` of the eligible votes is at
+/// least `threshold`. Keys without an explicit weight have a weight of `1`,
+/// so a "2-of-5" policy is expressed as a `threshold` of `2` without any
+/// `weights`.
+///
+/// Weights are assigned to keys, not identities: for indirect delegations,
+/// the weight of the key which voted on behalf of an identity counts.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Policy {
+    pub threshold: NonZeroU32,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub weights: BTreeMap<PublicKey, u32>,
+}
+
+impl Policy {
+    /// A policy requiring `threshold` votes, all keys being weighted equally.
+    pub fn threshold(threshold: NonZeroU32) -> Self {
+        Self {
+            threshold,
+            weights: BTreeMap::new(),
+        }
+    }
+
+    /// Assign `weight` to `key`.
+    pub fn weight(mut self, key: PublicKey, weight: u32) -> Self {
+        self.weights.insert(key, weight);
+        self
+    }
+
+    /// The weight of a vote by `key`.
+    pub fn weight_of(&self, key: &PublicKey) -> u32 {
+        self.weights.get(key).copied().unwrap_or(1)
+    }
+
+    /// Whether the `eligible` votes reach the threshold.
+    pub fn is_met(&self, eligible: &BTreeSet<&PublicKey>) -> bool {
+        let total = eligible
+            .iter()
+            .fold(0u64, |acc, key| acc + u64::from(self.weight_of(key)));
+        total >= u64::from(self.threshold.get())
+    }
+
+    /// Whether the threshold can be reached at all by the `voters`.
+    ///
+    /// Each voter is given as the keys it may vote with, of which at most one
+    /// counts.
+    pub fn is_attainable<'a, V>(&self, voters: impl IntoIterator<Item = V>) -> bool
+    where
+        V: IntoIterator<Item = &'a PublicKey>,
+    {
+        let total = voters.into_iter().fold(0u64, |acc, keys| {
+            acc + keys
+                .into_iter()
+                .map(|key| u64::from(self.weight_of(key)))
+                .max()
+                .unwrap_or(0)
+        });
+        total >= u64::from(self.threshold.get())
+    }
+}
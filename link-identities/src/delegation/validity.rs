@@ -0,0 +1,79 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::collections::BTreeMap;
+
+use crypto::PublicKey;
+
+/// Restrictions on the keys which may sign an identity document.
+///
+/// Revocations are sticky: once a key is revoked in some revision, all
+/// subsequent revisions must carry the revocation, and signatures made by the
+/// key no longer count towards a quorum. Validity windows bound the time at
+/// which a signature is considered to have been made by a key. As the time is
+/// taken from the commit carrying the signatures, which is chosen by the
+/// signer, windows only constrain well-behaved signers -- a compromised key
+/// must be revoked.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Validity {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub revoked: BTreeMap<PublicKey, Revocation>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub windows: BTreeMap<PublicKey, Window>,
+}
+
+impl Validity {
+    pub fn is_empty(&self) -> bool {
+        self.revoked.is_empty() && self.windows.is_empty()
+    }
+
+    pub fn is_revoked(&self, key: &PublicKey) -> bool {
+        self.revoked.contains_key(key)
+    }
+
+    /// Whether a signature by `key` made at `time` (in seconds since the
+    /// epoch) is acceptable.
+    ///
+    /// If `time` is not known, only revocations are taken into account.
+    pub fn permits(&self, key: &PublicKey, time: Option<i64>) -> bool {
+        if self.is_revoked(key) {
+            return false;
+        }
+        match (self.windows.get(key), time) {
+            (Some(window), Some(time)) => window.contains(time),
+            _ => true,
+        }
+    }
+
+    /// Revoke `key`, stating an optional `reason`.
+    ///
+    /// Any validity window of `key` becomes meaningless, and is removed.
+    pub fn revoke(&mut self, key: PublicKey, reason: Option<String>) {
+        self.windows.remove(&key);
+        self.revoked.insert(key, Revocation { reason });
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Revocation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// A time interval, in seconds since the epoch. Both ends are inclusive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Window {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_after: Option<i64>,
+}
+
+impl Window {
+    pub fn contains(&self, time: i64) -> bool {
+        self.not_before.map_or(true, |t| t <= time) && self.not_after.map_or(true, |t| time <= t)
+    }
+}
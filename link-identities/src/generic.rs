@@ -6,14 +6,22 @@
 #![allow(clippy::type_complexity)]
 
 use std::{
+    collections::BTreeSet,
     fmt::{Debug, Display},
     marker::PhantomData,
     ops::Deref,
 };
 
+use crypto::PublicKey;
 use serde::ser::SerializeStruct;
 
-use super::{delegation::Delegations, payload::Payload, sealed, sign::Signatures, urn::Urn};
+use super::{
+    delegation::{Delegations, Policy, Validity},
+    payload::Payload,
+    sealed,
+    sign::Signatures,
+    urn::Urn,
+};
 
 pub mod error;
 
@@ -28,6 +36,14 @@ pub struct Doc<T, D, Revision> {
     pub replaces: Option<Revision>,
     pub payload: T,
     pub delegations: D,
+    /// Revocations and validity windows of keys. Only serialised if not
+    /// empty.
+    #[serde(default)]
+    pub validity: Validity,
+    /// Explicit quorum policy. If `None`, a simple majority of the
+    /// delegations is required. Only serialised if not `None`.
+    #[serde(default)]
+    pub policy: Option<Policy>,
 }
 
 impl<T, D, Revision> serde::Serialize for Doc<T, D, Revision>
@@ -40,11 +56,19 @@ where
     where
         S: serde::Serializer,
     {
-        let mut doc = serializer.serialize_struct("Doc", 4)?;
+        let with_validity = !self.validity.is_empty();
+        let len = 4 + usize::from(with_validity) + usize::from(self.policy.is_some());
+        let mut doc = serializer.serialize_struct("Doc", len)?;
         doc.serialize_field("version", &0)?;
         doc.serialize_field("replaces", &self.replaces)?;
         doc.serialize_field("payload", &self.payload)?;
         doc.serialize_field("delegations", &self.delegations)?;
+        if with_validity {
+            doc.serialize_field("validity", &self.validity)?;
+        }
+        if let Some(policy) = &self.policy {
+            doc.serialize_field("policy", policy)?;
+        }
         doc.end()
     }
 }
@@ -63,6 +87,8 @@ impl<T, D, R> Doc<T, D, R> {
             replaces: self.replaces,
             payload: f(self.payload),
             delegations: g(self.delegations),
+            validity: self.validity,
+            policy: self.policy,
         }
     }
 
@@ -95,6 +121,8 @@ impl<T, D, R> Doc<T, D, R> {
             replaces: doc.replaces,
             payload: doc.payload?,
             delegations: doc.delegations,
+            validity: doc.validity,
+            policy: doc.policy,
         })
     }
 
@@ -111,6 +139,8 @@ impl<T, D, R> Doc<T, D, R> {
             replaces: doc.replaces,
             payload: doc.payload,
             delegations: doc.delegations?,
+            validity: doc.validity,
+            policy: doc.policy,
         })
     }
 }
@@ -253,9 +283,13 @@ pub struct Verified;
 /// The verification status (ie. which predicates where successfully applied to
 /// `T`) is tracked on the type level, as intermediate states may have meaning
 /// elsewhere.
+///
+/// The time at which `T` was signed may be attached using [`Verifying::at`],
+/// in which case it is taken into account by [`Delegations::permits`].
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Verifying<T, S> {
     inner: T,
+    time: Option<i64>,
     state: PhantomData<S>,
 }
 
@@ -274,6 +308,7 @@ impl<T, S> Verifying<T, S> {
     pub fn from_untrusted(t: T) -> Verifying<T, Untrusted> {
         Verifying {
             inner: t,
+            time: None,
             state: PhantomData,
         }
     }
@@ -283,14 +318,38 @@ impl<T, S> Verifying<T, S> {
         self.inner
     }
 
+    /// The time at which `T` was signed, in seconds since the epoch, if known.
+    pub fn time(&self) -> Option<i64> {
+        self.time
+    }
+
     fn coerce<U>(self) -> Verifying<T, U> {
         Verifying {
             inner: self.inner,
+            time: self.time,
             state: PhantomData,
         }
     }
 }
 
+impl<T> Verifying<T, Untrusted> {
+    /// Record the `time` at which `T` was signed, in seconds since the epoch.
+    pub fn at(self, time: impl Into<Option<i64>>) -> Self {
+        Self {
+            time: time.into(),
+            ..self
+        }
+    }
+
+    /// Assume `T` has been verified before.
+    ///
+    /// This is only sound if the verification result was recorded locally, eg.
+    /// by the verification cache.
+    pub(crate) fn assume_verified(self) -> Verifying<T, Verified> {
+        self.coerce()
+    }
+}
+
 impl<T> From<T> for Verifying<T, Untrusted> {
     fn from(t: T) -> Self {
         Self::from_untrusted(t)
@@ -362,8 +421,9 @@ impl<T, R, C> Verifying<Identity<T, R, C>, Signed> {
     ///
     /// # Errors
     ///
-    /// If the number of signatures does not reach the
-    /// [`Delegations::quorum_threshold`].
+    /// If the signatures do not form a quorum as per
+    /// [`Delegations::is_quorum`]. Signatures by keys which are not
+    /// [`Delegations::permits`]ted at [`Verifying::time`] are not counted.
     pub fn quorum(self) -> Result<Verifying<Identity<T, R, C>, Quorum>, error::Verify<R, C>>
     where
         T: Delegations,
@@ -374,11 +434,10 @@ impl<T, R, C> Verifying<Identity<T, R, C>, Signed> {
     {
         let eligible = self
             .doc
-            .eligible(self.signatures.keys().collect())
-            .map_err(error::Verify::eligibility)?
-            .len();
+            .eligible(self.votes(&self.doc))
+            .map_err(error::Verify::eligibility)?;
 
-        if eligible > 0 && eligible > self.doc.quorum_threshold() {
+        if self.doc.is_quorum(&eligible) {
             Ok(self.coerce())
         } else {
             Err(error::Verify::Quorum)
@@ -403,9 +462,9 @@ impl<T, R, C> Verifying<Identity<T, R, C>, Quorum> {
     /// * the `parent` revision doesn't match `replaces`
     /// * `self`'s signatures do not reach a quorum of the `parent`'s
     ///   delegations. In other words,
-    ///   `parent.eligible(self.signatures.keys()).len() >
-    ///   parent.doc.quorum_threshold()`
+    ///   `parent.is_quorum(&parent.eligible(self.signatures.keys()))`
     /// * `parent.eligible(self.signatures.keys())` returns an error
+    /// * a key revoked by `parent` is not also revoked by `self`
     pub fn verified(
         self,
         parent: Option<&Verifying<Identity<T, R, C>, Verified>>,
@@ -437,14 +496,20 @@ impl<T, R, C> Verifying<Identity<T, R, C>, Quorum> {
                         expected: replaces.to_owned(),
                         actual: parent.revision.to_owned(),
                     })
+                } else if let Some(key) = parent
+                    .doc
+                    .revoked()
+                    .into_iter()
+                    .find(|key| !self.doc.revoked().contains(key))
+                {
+                    Err(error::Verify::RevocationDropped(*key))
                 } else {
                     let votes = parent
                         .doc
-                        .eligible(self.signatures.keys().collect())
-                        .map_err(error::Verify::eligibility)?
-                        .len();
+                        .eligible(self.votes(&parent.doc))
+                        .map_err(error::Verify::eligibility)?;
 
-                    if votes > 0 && votes > parent.doc.quorum_threshold() {
+                    if parent.doc.is_quorum(&votes) {
                         Ok(self.coerce())
                     } else {
                         Err(error::Verify::ParentQuorum)
@@ -455,6 +520,16 @@ impl<T, R, C> Verifying<Identity<T, R, C>, Quorum> {
     }
 }
 
+impl<T, R, C, S> Verifying<Identity<T, R, C>, S> {
+    /// The keys which signed `self`, and are permitted to vote by `doc`.
+    fn votes<'a, D: Delegations>(&'a self, doc: &D) -> BTreeSet<&'a PublicKey> {
+        self.signatures
+            .keys()
+            .filter(|key| doc.permits(key, self.time))
+            .collect()
+    }
+}
+
 /// The result of running [`Verifying::verify`].
 ///
 /// In addition to the most recent verified [`Identity`], the parent used to
@@ -480,6 +555,29 @@ impl<T, R, C> Verifying<Identity<T, R, C>, Verified> {
     /// [`Signed`] identities in the progeny, which do not pass [`Quorum`] are
     /// skipped. This is to allow proposals to be made over the same protocol.
     pub fn verify<E>(
+        self,
+        progeny: impl Iterator<Item = Result<Verifying<Identity<T, R, C>, Untrusted>, E>>,
+    ) -> Result<Folded<T, R, C>, error::Verify<R, C>>
+    where
+        T: Delegations + Replaces<Revision = R>,
+        T::Error: std::error::Error + Send + Sync + 'static,
+
+        R: Clone + Debug + Display + PartialEq + AsRef<[u8]>,
+        C: Clone + Debug + Display,
+
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Folded {
+            head: self,
+            parent: None,
+        }
+        .fold(progeny)
+    }
+}
+
+impl<T, R, C> Folded<T, R, C> {
+    /// Continue [`Verifying::verify`] from a previously [`Folded`] state.
+    pub fn fold<E>(
         self,
         mut progeny: impl Iterator<Item = Result<Verifying<Identity<T, R, C>, Untrusted>, E>>,
     ) -> Result<Folded<T, R, C>, error::Verify<R, C>>
@@ -492,43 +590,37 @@ impl<T, R, C> Verifying<Identity<T, R, C>, Verified> {
 
         E: std::error::Error + Send + Sync + 'static,
     {
-        progeny.try_fold(
-            Folded {
-                head: self,
-                parent: None,
-            },
-            |acc, cur| {
-                // Not signed is an error
-                let signed = cur.map_err(error::Verify::history)?.signed()?;
-                match signed.quorum() {
-                    // Not reaching quorum is ok, skip
-                    Err(_) => Ok(acc),
-                    Ok(quorum) => {
-                        // A confirmation of `self` is ok, but `parent` stays
-                        // the same then. We need to be careful to not let a
-                        // current quorum invalidate our already-confirmed state
-                        // -- so skip if this doesn't pass `verified`, instead
-                        // of returning an error (which would render this
-                        // history invalid).
-                        if quorum.revision == acc.head.revision
-                            && quorum.doc.replaces() == acc.head.doc.replaces()
-                        {
-                            match quorum.verified(acc.parent.as_ref()) {
-                                Err(_) => Ok(acc),
-                                Ok(verified) => Ok(Folded {
-                                    head: verified,
-                                    parent: acc.parent,
-                                }),
-                            }
-                        } else {
-                            quorum.verified(Some(&acc.head)).map(|verified| Folded {
+        progeny.try_fold(self, |acc, cur| {
+            // Not signed is an error
+            let signed = cur.map_err(error::Verify::history)?.signed()?;
+            match signed.quorum() {
+                // Not reaching quorum is ok, skip
+                Err(_) => Ok(acc),
+                Ok(quorum) => {
+                    // A confirmation of `self` is ok, but `parent` stays
+                    // the same then. We need to be careful to not let a
+                    // current quorum invalidate our already-confirmed state
+                    // -- so skip if this doesn't pass `verified`, instead
+                    // of returning an error (which would render this
+                    // history invalid).
+                    if quorum.revision == acc.head.revision
+                        && quorum.doc.replaces() == acc.head.doc.replaces()
+                    {
+                        match quorum.verified(acc.parent.as_ref()) {
+                            Err(_) => Ok(acc),
+                            Ok(verified) => Ok(Folded {
                                 head: verified,
-                                parent: Some(acc.head),
-                            })
+                                parent: acc.parent,
+                            }),
                         }
-                    },
-                }
-            },
-        )
+                    } else {
+                        quorum.verified(Some(&acc.head)).map(|verified| Folded {
+                            head: verified,
+                            parent: Some(acc.head),
+                        })
+                    }
+                },
+            }
+        })
     }
 }
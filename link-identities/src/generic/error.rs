@@ -5,6 +5,7 @@
 
 use std::fmt::{Debug, Display};
 
+use crypto::PublicKey;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -44,6 +45,9 @@ where
         actual: Revision,
     },
 
+    #[error("revocation of key {0} was dropped")]
+    RevocationDropped(PublicKey),
+
     #[error("empty history")]
     EmptyHistory,
 
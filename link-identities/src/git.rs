@@ -3,7 +3,12 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{convert::TryFrom, fmt::Debug, marker::PhantomData};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+    fmt::Debug,
+    marker::PhantomData,
+};
 
 use canonical::Cjson;
 use crypto::{PublicKey, Signer};
@@ -25,11 +30,15 @@ pub mod iter;
 
 pub use generic::Verifying;
 
+mod cache;
 mod load;
+pub mod rotation;
 pub mod sign;
 
+pub use rotation::Rotation;
+
 use iter::Iter;
-use load::ByOid;
+use load::{untrusted, ByOid};
 
 pub type Urn = urn::Urn<Revision>;
 
@@ -105,6 +114,7 @@ pub type IndirectDelegation = delegation::Indirect<PersonPayload, Revision, Cont
 #[derive(Clone)]
 pub struct Identities<'a, T> {
     repo: &'a git2::Repository,
+    cache: bool,
     _marker: PhantomData<T>,
 }
 
@@ -112,6 +122,7 @@ impl<'a, T: 'a> From<&'a git2::Repository> for Identities<'a, T> {
     fn from(repo: &'a git2::Repository) -> Self {
         Self {
             repo,
+            cache: false,
             _marker: PhantomData,
         }
     }
@@ -119,11 +130,32 @@ impl<'a, T: 'a> From<&'a git2::Repository> for Identities<'a, T> {
 
 impl<'a, T: 'a> From<&Identities<'a, T>> for Identities<'a, T> {
     fn from(other: &Identities<'a, T>) -> Self {
-        Identities::from(other.repo)
+        Identities {
+            repo: other.repo,
+            cache: other.cache,
+            _marker: PhantomData,
+        }
     }
 }
 
 impl<'a, T: 'a> Identities<'a, T> {
+    /// Record verification results in the repository, and resume verification
+    /// from them.
+    ///
+    /// Verification of an identity history which has been verified before up
+    /// to some commit only needs to consider the commits on top of it. Results
+    /// are recorded per URN and tip, under
+    /// `refs/rad/cache/verified/<id>/<tip>`.
+    ///
+    /// This writes to the repository, so must only be enabled if that is
+    /// permitted.
+    pub fn with_verification_cache(self) -> Self {
+        Self {
+            cache: true,
+            ..self
+        }
+    }
+
     /// Convenience to specialise `T` to [`Person`].
     pub fn as_person(&self) -> Identities<'_, Person> {
         self.coerce()
@@ -147,6 +179,7 @@ impl<'a, T: 'a> Identities<'a, T> {
     pub fn coerce<U>(&self) -> Identities<'_, U> {
         Identities {
             repo: self.repo,
+            cache: self.cache,
             _marker: PhantomData,
         }
     }
@@ -206,6 +239,60 @@ impl<'a, T: 'a> Identities<'a, T> {
         Doc: Delegations + generic::Replaces<Revision = Revision>,
         <Doc as Delegations>::Error: std::error::Error + Send + Sync + 'static,
 
+        Identity<Doc>: TryFrom<ByOid<'a>, Error = error::Load>,
+    {
+        if !self.cache {
+            return self.fold_verify_uncached(head);
+        }
+
+        let urn = Identity::<Doc>::try_from(self.by_oid(head))
+            .map_err(generic::error::Verify::history)?
+            .urn();
+        let tips = cache::tips(self.repo, &urn);
+        let resume = if tips.is_empty() {
+            None
+        } else {
+            self.progeny_since(head, &tips)
+                .map_err(generic::error::Verify::history)?
+                .and_then(|(tip, progeny)| {
+                    cache::get(self.repo, &urn, tip).map(|entry| (entry, progeny))
+                })
+        };
+        let (folded, supersedes) = match resume {
+            // Nothing new since the last verification
+            Some((entry, progeny)) if progeny.is_empty() => return self.load_folded(&entry),
+            Some((entry, progeny)) => (
+                self.load_folded(&entry)?.fold(
+                    progeny
+                        .into_iter()
+                        .map(|oid| untrusted::<Identity<Doc>>(self.repo, oid)),
+                )?,
+                Some(*entry.tip),
+            ),
+            None => (self.fold_verify_uncached(head)?, None),
+        };
+        cache::put(
+            self.repo,
+            &urn,
+            &cache::Entry {
+                tip: head.into(),
+                head: folded.head.content_id,
+                parent: folded.parent.as_ref().map(|parent| parent.content_id),
+            },
+            supersedes,
+        );
+
+        Ok(folded)
+    }
+
+    fn fold_verify_uncached<Doc>(
+        &self,
+        head: git2::Oid,
+    ) -> Result<generic::Folded<Doc, Revision, ContentId>, VerificationError>
+    where
+        Doc: Delegations + generic::Replaces<Revision = Revision>,
+        <Doc as Delegations>::Error: std::error::Error + Send + Sync + 'static,
+
         Identity<Doc>: TryFrom<ByOid<'a>, Error = error::Load>,
     {
         let mut progeny = Iter::<'_, Identity<Doc>>::new(self.repo, head)
@@ -229,6 +316,53 @@ impl<'a, T: 'a> Identities<'a, T> {
         (self.repo, oid)
     }
 
+    /// Load the verified state recorded in a [`cache::Entry`].
+    fn load_folded<Doc>(
+        &self,
+        entry: &cache::Entry,
+    ) -> Result<generic::Folded<Doc, Revision, ContentId>, VerificationError>
+    where
+        Identity<Doc>: TryFrom<ByOid<'a>, Error = error::Load>,
+    {
+        let load = |oid: ContentId| {
+            untrusted::<Identity<Doc>>(self.repo, *oid)
+                .map(Verifying::assume_verified)
+                .map_err(generic::error::Verify::history)
+        };
+
+        Ok(generic::Folded {
+            head: load(entry.head)?,
+            parent: entry.parent.map(load).transpose()?,
+        })
+    }
+
+    /// Find the most recent of `tips` in the first-parent history of `head`,
+    /// and collect the history from `head` down to, but excluding, it,
+    /// oldest-first.
+    ///
+    /// Returns `None` if none of `tips` is a first-parent ancestor of `head`.
+    fn progeny_since(
+        &self,
+        head: git2::Oid,
+        tips: &BTreeSet<git2::Oid>,
+    ) -> Result<Option<(git2::Oid, Vec<git2::Oid>)>, git2::Error> {
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.simplify_first_parent()?;
+        revwalk.push(head)?;
+
+        let mut progeny = Vec::new();
+        for oid in revwalk {
+            let oid = oid?;
+            if tips.contains(&oid) {
+                progeny.reverse();
+                return Ok(Some((oid, progeny)));
+            }
+            progeny.push(oid);
+        }
+
+        Ok(None)
+    }
+
     fn is_in_ancestry_path(&self, commit: git2::Oid, tree: git2::Oid) -> Result<bool, git2::Error> {
         {
             let commit = self.repo.find_commit(commit)?;
@@ -309,8 +443,8 @@ where
     ///    signed by the union of both sets of signatures.
     /// 6. If `theirs` replaces `ours` (ie. `ours.revision ==
     ///    theirs.doc.replaces`), their revision is signed, and becomes the
-    ///    revision of the result. Note that the result has only one
-    ///    signature (by us).
+    ///    revision of the result. Note that the result has only one signature
+    ///    (by us).
     /// 7. Otherwise, there is no apparent relation between `ours` and `theirs`,
     ///    so an error is returned.
     pub fn update_from<S>(
@@ -519,6 +653,8 @@ impl<'a> Identities<'a, Person> {
             replaces: None,
             payload,
             delegations: payload::PersonDelegations::from(delegations),
+            validity: delegation::Validity::default(),
+            policy: None,
         };
         let root: Revision = self.repo.blob(&Cjson(&doc).canonical_form()?)?.into();
         Ok((doc, root))
@@ -531,13 +667,16 @@ impl<'a> Identities<'a, Person> {
     /// the result is the unwrapped [`Person`] of the `base` argument.
     ///
     /// Otherwise, the result is a new [`Person`] whose parent is `base`.
+    ///
+    /// Fails with [`error::SetPolicy::Unattainable`] if the policy of `base`
+    /// could not be met by the resulting delegations.
     pub fn update<S>(
         &self,
         base: SignedPerson,
         payload: impl Into<Option<PersonPayload>>,
         delegations: impl Into<Option<delegation::Direct>>,
         signer: &S,
-    ) -> Result<Person, error::Store>
+    ) -> Result<Person, error::Update>
     where
         S: Signer,
     {
@@ -549,15 +688,117 @@ impl<'a> Identities<'a, Person> {
             return Ok(base.into_inner());
         }
 
+        let delegations = delegations.unwrap_or_else(|| base.delegations().clone());
+        ensure_attainable(
+            base.doc.policy.as_ref(),
+            &base.doc.validity,
+            delegations.iter().map(Some),
+        )?;
         let doc = Doc {
             version: 0,
             replaces: Some(base.revision),
             payload: payload.unwrap_or_else(|| base.payload().clone()),
-            delegations: payload::PersonDelegations::from(
-                delegations.unwrap_or_else(|| base.delegations().clone()),
-            ),
+            delegations: payload::PersonDelegations::from(delegations),
+            validity: base.doc.validity.clone(),
+            policy: base.doc.policy.clone(),
+        };
+
+        Ok(self.store_revision(base, doc, "Updated", signer)?)
+    }
+
+    /// Revoke `key` on top of an existing [`SignedPerson`].
+    ///
+    /// `key` is removed from the delegations, and recorded as revoked in the
+    /// [`delegation::Validity`] of the new revision, optionally stating a
+    /// `reason`. Signatures by `key` don't count towards a quorum of the new
+    /// revision, nor any of its successors. Like any other change of the
+    /// delegations, the result must be confirmed by a quorum of the
+    /// delegations of `base` using [`Self::update_from`].
+    ///
+    /// Fails with [`error::SetPolicy::Unattainable`] if the policy of `base`
+    /// could not be met by the resulting delegations.
+    pub fn revoke<S>(
+        &self,
+        base: SignedPerson,
+        key: PublicKey,
+        reason: Option<String>,
+        signer: &S,
+    ) -> Result<Person, error::Revoke>
+    where
+        S: Signer,
+    {
+        if !base.delegations().contains(&key) {
+            return Err(error::Revoke::NotADelegation(key));
+        }
+        let delegations = delegation::Direct::try_from_iter(
+            base.delegations().iter().filter(|pk| **pk != key).copied(),
+        )
+        .map_err(|_| error::Revoke::LastDelegation(key))?;
+        let mut validity = base.doc.validity.clone();
+        validity.revoke(key, reason);
+        ensure_attainable(
+            base.doc.policy.as_ref(),
+            &validity,
+            delegations.iter().map(Some),
+        )?;
+
+        let doc = Doc {
+            version: 0,
+            replaces: Some(base.revision),
+            payload: base.payload().clone(),
+            delegations: payload::PersonDelegations::from(delegations),
+            validity,
+            policy: base.doc.policy.clone(),
         };
 
+        Ok(self.store_revision(base, doc, &format!("Revoked key {} in", key), signer)?)
+    }
+
+    /// Set the quorum [`delegation::Policy`] on top of an existing
+    /// [`SignedPerson`].
+    ///
+    /// If `policy` is `None`, a simple majority of the delegations is required
+    /// for a quorum. The result must be confirmed by a quorum of `base` as per
+    /// its own policy.
+    pub fn set_policy<S>(
+        &self,
+        base: SignedPerson,
+        policy: Option<delegation::Policy>,
+        signer: &S,
+    ) -> Result<Person, error::SetPolicy>
+    where
+        S: Signer,
+    {
+        ensure_attainable(
+            policy.as_ref(),
+            &base.doc.validity,
+            base.delegations().iter().map(Some),
+        )?;
+
+        let doc = Doc {
+            version: 0,
+            replaces: Some(base.revision),
+            payload: base.payload().clone(),
+            delegations: payload::PersonDelegations::from(base.delegations().clone()),
+            validity: base.doc.validity.clone(),
+            policy,
+        };
+
+        Ok(self.store_revision(base, doc, "Set policy in", signer)?)
+    }
+
+    //// Helpers ////
+
+    fn store_revision<S>(
+        &self,
+        base: SignedPerson,
+        doc: Doc<PersonPayload, payload::PersonDelegations>,
+        action: &str,
+        signer: &S,
+    ) -> Result<Person, error::Store>
+    where
+        S: Signer,
+    {
         let revision = {
             let doc_blob = self.repo.blob(&Cjson(&doc).canonical_form()?)?;
             let base_tree = self.repo.find_tree(*base.revision)?;
@@ -574,7 +815,7 @@ impl<'a> Identities<'a, Person> {
             .map_err(|e| error::Store::Signer(Box::new(e)))?
             .into();
         let content_id = self.commit(
-            &format!("Updated to revision {}", revision),
+            &format!("{} revision {}", action, revision),
             &signatures,
             revision,
             &[&*base],
@@ -617,6 +858,7 @@ impl<'a> Identities<'a, Project> {
         E: std::error::Error + Send + Sync + 'static,
     {
         let generic::Folded { head, parent } = self.fold_verify_generic::<ProjectDoc>(head)?;
+        let time = head.time();
         let head = head
             .into_inner()
             .map(|doc| {
@@ -627,6 +869,7 @@ impl<'a> Identities<'a, Project> {
             .transpose()?;
 
         Ok(generic::Verifying::from(head)
+            .at(time)
             .signed()?
             .quorum()?
             .verified(parent.as_ref())?)
@@ -688,6 +931,8 @@ impl<'a> Identities<'a, Project> {
             replaces: None,
             payload,
             delegations: payload::ProjectDelegations::from(delegations),
+            validity: delegation::Validity::default(),
+            policy: None,
         };
         let root: Revision = self.repo.blob(&Cjson(&doc).canonical_form()?)?.into();
         Ok((doc, root))
@@ -700,13 +945,16 @@ impl<'a> Identities<'a, Project> {
     /// the result is the unwrapped [`Project`] of the `base` argument.
     ///
     /// Otherwise, the result is a new [`Project`] whose parent is `base`.
+    ///
+    /// Fails with [`error::SetPolicy::Unattainable`] if the policy of `base`
+    /// could not be met by the resulting delegations.
     pub fn update<S>(
         &self,
         base: SignedProject,
         payload: impl Into<Option<ProjectPayload>>,
         delegations: impl Into<Option<IndirectDelegation>>,
         signer: &S,
-    ) -> Result<Project, error::Store>
+    ) -> Result<Project, error::Update>
     where
         S: Signer,
     {
@@ -718,6 +966,12 @@ impl<'a> Identities<'a, Project> {
             return Ok(base.into_inner());
         }
 
+        ensure_attainable(
+            base.doc.policy.as_ref(),
+            &base.doc.validity,
+            voters(delegations.as_ref().unwrap_or_else(|| base.delegations())),
+        )?;
+
         // FIXME: reorder stuff to avoid cloning
 
         let doc = Doc {
@@ -728,19 +982,12 @@ impl<'a> Identities<'a, Project> {
                 .clone()
                 .map(payload::ProjectDelegations::from)
                 .unwrap_or_else(|| base.delegations().clone().into()),
+            validity: base.doc.validity.clone(),
+            policy: base.doc.policy.clone(),
         };
 
         let root = base.root;
-        let revision = {
-            let base_tree = self.repo.find_tree(*base.revision)?;
-            let mut builder = self.repo.treebuilder(Some(&base_tree))?;
-            if let Some(ref indirect) = delegations {
-                self.inline_indirect(&mut builder, indirect)?;
-            }
-            let doc_blob = self.repo.blob(&Cjson(&doc).canonical_form()?)?;
-            builder.insert(base.root.to_string(), doc_blob, 0o100_644)?;
-            builder.write().map(Revision::from)
-        }?;
+        let revision = self.write_revision(&base, &doc, delegations.as_ref())?;
 
         if revision == base.revision {
             return Ok(base.into_inner());
@@ -765,8 +1012,166 @@ impl<'a> Identities<'a, Project> {
         })
     }
 
+    /// Set the quorum [`delegation::Policy`] on top of an existing
+    /// [`SignedProject`].
+    ///
+    /// If `policy` is `None`, a simple majority of the delegations is required
+    /// for a quorum. The result must be confirmed by a quorum of `base` as per
+    /// its own policy.
+    pub fn set_policy<S>(
+        &self,
+        base: SignedProject,
+        policy: Option<delegation::Policy>,
+        signer: &S,
+    ) -> Result<Project, error::SetPolicy>
+    where
+        S: Signer,
+    {
+        ensure_attainable(
+            policy.as_ref(),
+            &base.doc.validity,
+            voters(base.delegations()),
+        )?;
+
+        let doc = Doc {
+            version: 0,
+            replaces: Some(base.revision),
+            payload: base.payload().clone(),
+            delegations: payload::ProjectDelegations::from(base.delegations().clone()),
+            validity: base.doc.validity.clone(),
+            policy,
+        };
+        let revision = self.write_revision(&base, &doc, None)?;
+        if revision == base.revision {
+            return Ok(base.into_inner());
+        }
+
+        let signatures = sign(signer, revision)
+            .map_err(|e| error::Store::Signer(Box::new(e)))?
+            .into();
+        let content_id = self.commit(
+            &format!("Set policy in revision {}", revision),
+            &signatures,
+            revision,
+            &[&*base],
+        )?;
+        let base = base.into_inner();
+
+        Ok(Identity {
+            content_id,
+            root: base.root,
+            revision,
+            doc: doc.second(|_| base.doc.delegations),
+            signatures,
+        })
+    }
+
+    /// Propose to replace the delegations of `base` by `delegations`.
+    ///
+    /// The identity document is updated and stored, and the resulting
+    /// [`Rotation`] is signed by `signer`. No commit is made -- the
+    /// [`Rotation`] is meant to be passed around to the other delegates
+    /// until it carries enough signatures to be applied using
+    /// [`Self::rotate`].
+    ///
+    /// The result is deterministic: proposing the same `delegations` on top
+    /// of the same `base` yields the same [`Rotation::revision`], so other
+    /// delegates can verify what they are asked to sign.
+    ///
+    /// Fails with [`error::SetPolicy::Unattainable`] if the policy of `base`
+    /// could not be met by the resulting delegations.
+    pub fn propose_rotation<S>(
+        &self,
+        base: &Project,
+        delegations: IndirectDelegation,
+        signer: &S,
+    ) -> Result<Rotation, error::Rotate>
+    where
+        S: Signer,
+    {
+        ensure_attainable(
+            base.doc.policy.as_ref(),
+            &base.doc.validity,
+            voters(&delegations),
+        )?;
+        let doc = Doc {
+            version: 0,
+            replaces: Some(base.revision),
+            payload: base.payload().clone(),
+            delegations: payload::ProjectDelegations::from(delegations.clone()),
+            validity: base.doc.validity.clone(),
+            policy: base.doc.policy.clone(),
+        };
+        let revision = self.write_revision(base, &doc, Some(&delegations))?;
+        if revision == base.revision {
+            return Err(error::Rotate::Unchanged);
+        }
+
+        let mut rotation = Rotation {
+            base: base.content_id,
+            revision,
+            signatures: Signatures::from(BTreeMap::new()),
+        };
+        rotation.sign(signer)?;
+
+        Ok(rotation)
+    }
+
+    /// Apply a [`Rotation`] on top of `base`.
+    ///
+    /// A new commit is made only if the signatures of `rotation` are valid,
+    /// and the result can be verified against `base`, ie. the signatures
+    /// reach a quorum of both the old and the new delegations.
+    pub fn rotate(
+        &self,
+        base: VerifiedProject,
+        rotation: Rotation,
+    ) -> Result<VerifiedProject, error::Rotate> {
+        if base.content_id != rotation.base {
+            return Err(error::Rotate::BaseMismatch {
+                expected: base.content_id,
+                actual: rotation.base,
+            });
+        }
+        if !rotation
+            .signatures
+            .iter()
+            .all(|(pk, sig)| sig.verify(rotation.revision.as_ref(), pk))
+        {
+            return Err(error::Rotate::Verification(
+                generic::error::Verify::SignatureVerification,
+            ));
+        }
+
+        let content_id = self.commit(
+            &format!("Rotated delegations to revision {}", rotation.revision),
+            &rotation.signatures,
+            rotation.revision,
+            &[&*base],
+        )?;
+        let next = untrusted::<Project>(self.repo, *content_id)?;
+
+        Ok(next.verified(Some(&base))?)
+    }
+
     //// Helpers ////
 
+    fn write_revision(
+        &self,
+        base: &Project,
+        doc: &Doc<ProjectPayload, payload::ProjectDelegations<Revision>>,
+        delegations: Option<&IndirectDelegation>,
+    ) -> Result<Revision, error::Store> {
+        let base_tree = self.repo.find_tree(*base.revision)?;
+        let mut builder = self.repo.treebuilder(Some(&base_tree))?;
+        if let Some(indirect) = delegations {
+            self.inline_indirect(&mut builder, indirect)?;
+        }
+        let doc_blob = self.repo.blob(&Cjson(doc).canonical_form()?)?;
+        builder.insert(base.root.to_string(), doc_blob, 0o100_644)?;
+        Ok(builder.write().map(Revision::from)?)
+    }
+
     fn resolve_delegation_updates<I, F, E>(
         &self,
         current: I,
@@ -846,6 +1251,38 @@ impl<'a> Identities<'a, Project> {
     }
 }
 
+/// Ensure the threshold of `policy`, if any, can be reached by the `voters`,
+/// not counting keys revoked as per `validity`.
+fn ensure_attainable<'a, V>(
+    policy: Option<&delegation::Policy>,
+    validity: &delegation::Validity,
+    voters: impl IntoIterator<Item = V>,
+) -> Result<(), error::SetPolicy>
+where
+    V: IntoIterator<Item = &'a PublicKey>,
+{
+    if let Some(policy) = policy {
+        let voters = voters.into_iter().map(|keys| {
+            keys.into_iter()
+                .filter(|key| !validity.is_revoked(key))
+                .collect::<Vec<_>>()
+        });
+        if !policy.is_attainable(voters) {
+            return Err(error::SetPolicy::Unattainable);
+        }
+    }
+
+    Ok(())
+}
+
+/// The keys each of the `delegations` of a project may vote with.
+fn voters(delegations: &IndirectDelegation) -> impl Iterator<Item = Vec<&PublicKey>> {
+    delegations.iter().map(|d| match d {
+        Left(key) => vec![key],
+        Right(person) => person.delegations().iter().collect(),
+    })
+}
+
 pub fn sign<S>(signer: &S, rev: git_ext::Oid) -> Result<Signature, S::Error>
 where
     S: Signer,
@@ -0,0 +1,99 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Persistent cache of identity verification results.
+//!
+//! Verifying an identity requires to walk and verify its entire history. To
+//! avoid doing this over and over again, the state reached after verifying the
+//! history up to some tip -- ie. the most recent verified identity and its
+//! parent -- is recorded in the repository, keyed by the URN and the tip.
+//! Verification of the same tip, or one whose first-parent history contains
+//! it, resumes from there.
+//!
+//! The outcome only depends on the history, not on which peer it was obtained
+//! from, so the tip identifies the history of every peer which has it. The
+//! histories of different peers may diverge, though, so there may be several
+//! entries per URN: an entry is only replaced by one for a tip which descends
+//! from it. Entries are stored as blobs the refs
+//! `refs/rad/cache/verified/<id>/<tip>` point to, outside of any namespace, so
+//! they are never served to other peers.
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use super::{ContentId, Urn};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct Entry {
+    pub tip: ContentId,
+    pub head: ContentId,
+    pub parent: Option<ContentId>,
+}
+
+fn prefix(urn: &Urn) -> String {
+    format!("refs/rad/cache/verified/{}/", urn.encode_id())
+}
+
+fn refname(urn: &Urn, tip: git2::Oid) -> String {
+    format!("{}{}", prefix(urn), tip)
+}
+
+/// The tips for which there are entries for `urn`.
+///
+/// Entries which can't be read are treated as absent.
+pub(super) fn tips(repo: &git2::Repository, urn: &Urn) -> BTreeSet<git2::Oid> {
+    let prefix = prefix(urn);
+    let names = match repo.references_glob(&format!("{}*", prefix)) {
+        Ok(refs) => refs,
+        Err(_) => return BTreeSet::new(),
+    };
+    names
+        .filter_map(|r| {
+            let r = r.ok()?;
+            r.name()?.strip_prefix(&prefix)?.parse().ok()
+        })
+        .collect()
+}
+
+/// Look up the entry for `urn` at `tip`.
+///
+/// Entries which can't be read are treated as absent.
+pub(super) fn get(repo: &git2::Repository, urn: &Urn, tip: git2::Oid) -> Option<Entry> {
+    let blob = repo
+        .find_reference(&refname(urn, tip))
+        .and_then(|r| r.peel_to_blob())
+        .ok()?;
+    serde_json::from_slice(blob.content()).ok()
+}
+
+/// Record `entry` for `urn`, replacing the entry at `supersedes`, if any.
+///
+/// Failure to do so is not fatal, as the cache can always be rebuilt.
+pub(super) fn put(
+    repo: &git2::Repository,
+    urn: &Urn,
+    entry: &Entry,
+    supersedes: Option<git2::Oid>,
+) {
+    let res = serde_json::to_vec(entry)
+        .map_err(|e| git2::Error::from_str(&e.to_string()))
+        .and_then(|json| repo.blob(&json))
+        .and_then(|blob| {
+            repo.reference(
+                &refname(urn, *entry.tip),
+                blob,
+                true,
+                &format!("verified {} at {}", entry.head, entry.tip),
+            )
+        })
+        .and_then(|_| match supersedes {
+            Some(tip) if tip != *entry.tip => repo.find_reference(&refname(urn, tip))?.delete(),
+            _ => Ok(()),
+        });
+    if let Err(e) = res {
+        tracing::warn!(err = ?e, urn = %urn, "failed to update verification cache")
+    }
+}
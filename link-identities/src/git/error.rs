@@ -6,6 +6,7 @@
 use std::{fmt::Debug, path::PathBuf};
 
 use canonical::CjsonError;
+use crypto::PublicKey;
 use thiserror::Error;
 
 use super::Urn;
@@ -92,6 +93,82 @@ pub enum Merge {
     Git(#[from] git2::Error),
 }
 
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Update {
+    #[error(transparent)]
+    Policy(#[from] self::SetPolicy),
+
+    #[error(transparent)]
+    Store(#[from] self::Store),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Revoke {
+    #[error("{0} is not a delegation")]
+    NotADelegation(PublicKey),
+
+    #[error("{0} is the only delegation, and can't be revoked")]
+    LastDelegation(PublicKey),
+
+    #[error(transparent)]
+    Policy(#[from] self::SetPolicy),
+
+    #[error(transparent)]
+    Store(#[from] self::Store),
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum SetPolicy {
+    #[error("the policy threshold can't be reached by the delegations")]
+    Unattainable,
+
+    #[error(transparent)]
+    Store(#[from] self::Store),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Rotate {
+    #[error("rotation does not change the identity document")]
+    Unchanged,
+
+    #[error("rotation is based on {actual}, but the current identity is {expected}")]
+    BaseMismatch {
+        expected: ContentId,
+        actual: ContentId,
+    },
+
+    #[error("rotations must have the same base and revision")]
+    Mismatch,
+
+    #[error("failed to produce a signature")]
+    Signer(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    #[error(transparent)]
+    Verification(#[from] generic::error::Verify<Revision, ContentId>),
+
+    #[error(transparent)]
+    Policy(#[from] self::SetPolicy),
+
+    #[error(transparent)]
+    Store(#[from] self::Store),
+
+    #[error(transparent)]
+    Load(#[from] self::Load),
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum Signatures {
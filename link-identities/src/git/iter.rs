@@ -5,7 +5,7 @@
 
 use std::{convert::TryFrom, marker::PhantomData};
 
-use super::{error, ByOid};
+use super::{error, load, ByOid};
 use crate::generic::{self, Untrusted};
 
 #[must_use = "iterators are lazy and do nothing unless consumed"]
@@ -37,8 +37,6 @@ where
     type Item = Result<generic::Verifying<T, Untrusted>, error::Load>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.iter
-            .next()
-            .map(|oid| T::try_from((self.repo, oid?)).map(generic::Verifying::from))
+        self.iter.next().map(|oid| load::untrusted(self.repo, oid?))
     }
 }
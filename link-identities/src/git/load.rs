@@ -12,7 +12,7 @@ use std_ext::result::ResultExt as _;
 
 use crate::{
     delegation,
-    generic,
+    generic::{self, Untrusted},
     payload::{
         PersonDelegations,
         PersonPayload,
@@ -29,6 +29,19 @@ use super::{error, ContentId, Doc, Identity, Person, Project, Revision, SomeIden
 
 pub type ByOid<'a> = (&'a git2::Repository, git2::Oid);
 
+/// Load `T` from commit `oid`, recording the commit time as the time it was
+/// signed.
+pub fn untrusted<'a, T>(
+    repo: &'a git2::Repository,
+    oid: git2::Oid,
+) -> Result<generic::Verifying<T, Untrusted>, error::Load>
+where
+    T: TryFrom<ByOid<'a>, Error = error::Load>,
+{
+    let time = repo.find_commit(oid)?.time().seconds();
+    T::try_from((repo, oid)).map(|t| generic::Verifying::from(t).at(time))
+}
+
 #[derive(Debug, serde::Serialize)]
 #[serde(untagged)]
 enum SomeDoc {
@@ -51,6 +64,8 @@ impl<'de> serde::Deserialize<'de> for SomeDoc {
                     replaces: doc.replaces,
                     payload,
                     delegations,
+                    validity: doc.validity,
+                    policy: doc.policy,
                 }))
             },
 
@@ -60,6 +75,8 @@ impl<'de> serde::Deserialize<'de> for SomeDoc {
                     replaces: doc.replaces,
                     payload,
                     delegations,
+                    validity: doc.validity,
+                    policy: doc.policy,
                 }))
             },
 
@@ -69,6 +86,8 @@ impl<'de> serde::Deserialize<'de> for SomeDoc {
                     replaces: doc.replaces,
                     payload,
                     delegations: (*delegations).iter().copied().map(Either::Left).collect(),
+                    validity: doc.validity,
+                    policy: doc.policy,
                 }))
             },
 
@@ -0,0 +1,59 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Out-of-band signature exchange for delegate rotations.
+//!
+//! A [`Rotation`] captures a proposed change of the delegations of a project:
+//! the `content_id` of the identity it is based on, the [`Revision`] of the
+//! updated identity document, and the signatures collected so far. It is
+//! serializable, so it can be passed around between delegates by whatever
+//! means they see fit (eg. as a JSON file).
+//!
+//! Because the [`Revision`] is computed deterministically from the base
+//! identity and the new set of delegations, a delegate can (and should)
+//! re-create the proposal locally using [`super::Identities::propose_rotation`]
+//! instead of signing a foreign one blindly, and [`Rotation::merge`] the
+//! results.
+
+use crypto::Signer;
+
+use super::{error, sign, ContentId, Revision};
+use crate::sign::Signatures;
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Rotation {
+    /// The `content_id` of the identity being rotated.
+    pub base: ContentId,
+    /// The [`Revision`] of the identity document with the new delegations.
+    pub revision: Revision,
+    /// The signatures over `revision` collected so far.
+    pub signatures: Signatures,
+}
+
+impl Rotation {
+    /// Add a signature by `signer` over [`Rotation::revision`].
+    pub fn sign<S>(&mut self, signer: &S) -> Result<(), error::Rotate>
+    where
+        S: Signer,
+    {
+        let sig = sign(signer, self.revision).map_err(|e| error::Rotate::Signer(Box::new(e)))?;
+        self.signatures.extend(Some(sig));
+        Ok(())
+    }
+
+    /// Combine the signatures of `other` with the ones of `self`.
+    ///
+    /// # Errors
+    ///
+    /// If `other` does not propose the same rotation, ie. its `base` or
+    /// `revision` differs.
+    pub fn merge(&mut self, other: Rotation) -> Result<(), error::Rotate> {
+        if self.base != other.base || self.revision != other.revision {
+            return Err(error::Rotate::Mismatch);
+        }
+        self.signatures.extend(other.signatures);
+        Ok(())
+    }
+}
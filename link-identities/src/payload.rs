@@ -27,6 +27,9 @@ use crate::{
     urn::{HasProtocol, Urn},
 };
 
+pub mod schema;
+pub use schema::{Schema, Schemas};
+
 lazy_static! {
     /// Base [`Url`] for [`Person`]
     static ref PERSON_NAMESPACE_BASE: Url =
@@ -0,0 +1,221 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Validation of [`Payload`] extensions.
+//!
+//! Extensions are free-form JSON values, namespaced by a [`Url`]. In order to
+//! agree on their structure, applications can register a [`Schema`] for a
+//! namespace with a [`Schemas`] registry, and validate payloads against it
+//! before storing or after loading an identity document.
+//!
+//! A [`Schema`] is a small subset of [JSON Schema][json-schema], restricted to
+//! what can be expressed in canonical JSON (eg. there are no floating point
+//! numbers). Schemas are themselves (de)serialisable, so they can be
+//! distributed alongside the applications defining them:
+//!
+//! ```json
+//! {
+//!   "type": "object",
+//!   "properties": {
+//!     "topics": { "type": "array", "items": { "type": "string" } }
+//!   },
+//!   "required": ["topics"]
+//! }
+//! ```
+//!
+//! [json-schema]: https://json-schema.org
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json::Value;
+use url::Url;
+
+use super::{Payload, Subject};
+
+pub mod error {
+    use thiserror::Error;
+    use url::Url;
+
+    #[derive(Debug, Error)]
+    #[non_exhaustive]
+    pub enum Validation {
+        #[error("no schema registered for extension `{0}`")]
+        Unregistered(Url),
+
+        #[error("extension `{namespace}`: expected {expected} at `{path}`")]
+        Type {
+            namespace: Url,
+            path: String,
+            expected: &'static str,
+        },
+
+        #[error("extension `{namespace}`: missing required property `{path}`")]
+        Missing { namespace: Url, path: String },
+
+        #[error("extension `{namespace}`: unexpected property `{path}`")]
+        Unexpected { namespace: Url, path: String },
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Schema {
+    /// Any value is accepted.
+    Any,
+    Null,
+    Boolean,
+    Integer,
+    String,
+    Array {
+        items: Box<Schema>,
+    },
+    Object {
+        #[serde(default)]
+        properties: BTreeMap<String, Schema>,
+        #[serde(default)]
+        required: BTreeSet<String>,
+        /// Whether properties not listed in `properties` are allowed.
+        #[serde(default, rename = "additionalProperties")]
+        additional_properties: bool,
+    },
+}
+
+/// Why a value didn't match a [`Schema`].
+enum Mismatch {
+    Type(&'static str),
+    Missing,
+    Unexpected,
+}
+
+impl Schema {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Any => "any value",
+            Self::Null => "null",
+            Self::Boolean => "a boolean",
+            Self::Integer => "an integer",
+            Self::String => "a string",
+            Self::Array { .. } => "an array",
+            Self::Object { .. } => "an object",
+        }
+    }
+
+    fn check(&self, val: &Value, path: &mut Vec<String>) -> Result<(), Mismatch> {
+        match (self, val) {
+            (Self::Any, _)
+            | (Self::Null, Value::Null)
+            | (Self::Boolean, Value::Bool(_))
+            | (Self::String, Value::String(_)) => Ok(()),
+            (Self::Integer, Value::Number(n)) if n.is_i64() || n.is_u64() => Ok(()),
+            (Self::Array { items }, Value::Array(vals)) => {
+                for (i, val) in vals.iter().enumerate() {
+                    path.push(i.to_string());
+                    items.check(val, path)?;
+                    path.pop();
+                }
+                Ok(())
+            },
+            (
+                Self::Object {
+                    properties,
+                    required,
+                    additional_properties,
+                },
+                Value::Object(obj),
+            ) => {
+                for name in required {
+                    if !obj.contains_key(name) {
+                        path.push(name.clone());
+                        return Err(Mismatch::Missing);
+                    }
+                }
+                for (name, val) in obj {
+                    path.push(name.clone());
+                    match properties.get(name) {
+                        Some(schema) => schema.check(val, path)?,
+                        None if *additional_properties => {},
+                        None => return Err(Mismatch::Unexpected),
+                    }
+                    path.pop();
+                }
+                Ok(())
+            },
+            (schema, _) => Err(Mismatch::Type(schema.name())),
+        }
+    }
+
+    /// Validate the extension `val` under `namespace` against `self`.
+    pub fn validate(&self, namespace: &Url, val: &Value) -> Result<(), error::Validation> {
+        let mut path = Vec::new();
+        self.check(val, &mut path).map_err(|mismatch| {
+            let namespace = namespace.clone();
+            let path = format!("/{}", path.join("/"));
+            match mismatch {
+                Mismatch::Type(expected) => error::Validation::Type {
+                    namespace,
+                    path,
+                    expected,
+                },
+                Mismatch::Missing => error::Validation::Missing { namespace, path },
+                Mismatch::Unexpected => error::Validation::Unexpected { namespace, path },
+            }
+        })
+    }
+}
+
+/// Registry of [`Schema`]s by extension namespace.
+#[derive(Clone, Debug, Default)]
+pub struct Schemas {
+    schemas: BTreeMap<Url, Schema>,
+    strict: bool,
+}
+
+impl Schemas {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject extensions for which no [`Schema`] is registered.
+    ///
+    /// By default, unknown extensions are accepted, so as to not break
+    /// applications which are not aware of them.
+    pub fn strict(self) -> Self {
+        Self {
+            strict: true,
+            ..self
+        }
+    }
+
+    /// Register `schema` for the extension `namespace`, returning the
+    /// previously registered one, if any.
+    pub fn register(&mut self, namespace: Url, schema: Schema) -> Option<Schema> {
+        self.schemas.insert(namespace, schema)
+    }
+
+    pub fn get(&self, namespace: &Url) -> Option<&Schema> {
+        self.schemas.get(namespace)
+    }
+
+    /// Validate all extensions of `payload`.
+    ///
+    /// Extensions whose value is `null` are not serialised, and thus not
+    /// validated either.
+    pub fn validate<T>(&self, payload: &Payload<T>) -> Result<(), error::Validation>
+    where
+        T: Subject,
+    {
+        for (namespace, val) in payload.exts().filter(|(_, val)| !val.is_null()) {
+            match self.schemas.get(namespace) {
+                Some(schema) => schema.validate(namespace, val)?,
+                None if self.strict => {
+                    return Err(error::Validation::Unregistered(namespace.clone()))
+                },
+                None => {},
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -184,6 +184,19 @@ impl<R> Urn<R> {
     }
 }
 
+#[cfg(feature = "fuzzing")]
+impl<'a, R> arbitrary::Arbitrary<'a> for Urn<R>
+where
+    R: arbitrary::Arbitrary<'a>,
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self {
+            id: u.arbitrary()?,
+            path: u.arbitrary()?,
+        })
+    }
+}
+
 impl<R> From<R> for Urn<R> {
     fn from(r: R) -> Self {
         Self::new(r)
@@ -119,6 +119,8 @@ where
             replaces: None,
             payload: Boring,
             delegations,
+            validity: delegation::Validity::default(),
+            policy: None,
         },
         signatures,
     }
@@ -168,6 +170,8 @@ where
                     replaces,
                     payload: Boring,
                     delegations,
+                    validity: delegation::Validity::default(),
+                    policy: None,
                 },
                 signatures,
             },
@@ -315,6 +319,8 @@ where
                 replaces: inner_replaces,
                 payload: Boring,
                 delegations,
+                validity: delegation::Validity::default(),
+                policy: None,
             },
             signatures,
         };
@@ -6,7 +6,7 @@
 use anyhow::anyhow;
 use either::Either::*;
 use librad::git::{identities, storage::Storage};
-use link_crypto::SecretKey;
+use link_crypto::{PublicKey, SecretKey};
 use link_identities::{
     delegation,
     git::{error, Person, Urn},
@@ -97,6 +97,25 @@ impl<'a> Device<'a> {
         Ok(Self { cur, ..self })
     }
 
+    pub fn revoke(self, key: PublicKey) -> anyhow::Result<Self> {
+        let cur = self.git.revoke(
+            Verifying::from(self.cur).signed()?,
+            key,
+            Some("lost".into()),
+            self.key,
+        )?;
+
+        Ok(Self { cur, ..self })
+    }
+
+    pub fn set_policy(self, policy: impl Into<Option<delegation::Policy>>) -> anyhow::Result<Self> {
+        let cur =
+            self.git
+                .set_policy(Verifying::from(self.cur).signed()?, policy.into(), self.key)?;
+
+        Ok(Self { cur, ..self })
+    }
+
     pub fn update_from(self, other: &Device<'a>) -> anyhow::Result<Self> {
         let cur = self.git.update_from(
             Verifying::from(self.cur).signed()?,
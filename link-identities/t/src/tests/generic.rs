@@ -27,3 +27,31 @@ fn signed_no_signatures() {
         Err(error::Verify::NoSignatures)
     )
 }
+
+#[test]
+fn quorum_respects_validity_window() {
+    let key = SecretKey::new();
+    let mut id = boring(
+        delegation::Direct::new(key.public()),
+        Signatures::from(
+            Some((key.public(), key.sign(Boring.as_ref())))
+                .into_iter()
+                .collect::<BTreeMap<_, _>>(),
+        ),
+    );
+    id.doc.validity.windows.insert(
+        key.public(),
+        delegation::validity::Window {
+            not_before: None,
+            not_after: Some(100),
+        },
+    );
+
+    assert!(Verifying::from(id.clone()).at(100).quorum().is_ok());
+    assert_matches!(
+        Verifying::from(id.clone()).at(101).quorum(),
+        Err(error::Verify::Quorum)
+    );
+    // Windows can't be judged if the signing time is unknown
+    assert!(Verifying::from(id).quorum().is_ok());
+}
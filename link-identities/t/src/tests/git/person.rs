@@ -5,10 +5,13 @@
 
 use it_helpers::tmp;
 use link_crypto::SecretKey;
+use std::num::NonZeroU32;
+
 use link_identities::{
-    delegation::Direct,
+    delegation::{Direct, Policy},
     git::{error, VerificationError},
     Identities,
+    Person,
 };
 
 use crate::helpers::Device;
@@ -53,6 +56,56 @@ fn update() -> anyhow::Result<()> {
     }
 }
 
+#[test]
+fn update_cached() -> anyhow::Result<()> {
+    let repo = tmp::repo()?;
+    {
+        let uncached = Identities::<Person>::from(&*repo);
+        let desktop = Device::new(
+            &*DESKTOP,
+            Identities::from(&*repo).with_verification_cache(),
+        )?;
+        desktop.assert_verifies()?;
+        assert_eq!(
+            desktop.verify()?,
+            uncached.verify(*desktop.current().content_id)?
+        );
+
+        let urn = desktop.current().urn();
+        let cached = |tip: String| {
+            repo.find_reference(&format!(
+                "refs/rad/cache/verified/{}/{}",
+                urn.encode_id(),
+                tip
+            ))
+            .is_ok()
+        };
+        let root = desktop.current().content_id.to_string();
+        assert!(cached(root.clone()));
+
+        // Resumes from the cached tip, skipping the proposal lacking quorum
+        let desktop = desktop.update(Direct::new(DESKTOP.public()).insert(LAPTOP.public()))?;
+        assert_eq!(
+            desktop.verify()?,
+            uncached.verify(*desktop.current().content_id)?
+        );
+        // Superseded
+        assert!(cached(desktop.current().content_id.to_string()));
+        assert!(!cached(root));
+
+        let laptop = Device::create_from(&*LAPTOP, &desktop)?;
+        let desktop = desktop.update_from(&laptop)?;
+        desktop.assert_verifies()?;
+        assert_eq!(
+            desktop.verify()?,
+            uncached.verify(*desktop.current().content_id)?
+        );
+
+        // Cache hit
+        desktop.assert_verifies()
+    }
+}
+
 #[test]
 fn revoke_a_deux() -> anyhow::Result<()> {
     let repo = tmp::repo()?;
@@ -109,3 +162,122 @@ fn revoke_a_trois() -> anyhow::Result<()> {
         desktop.assert_verifies()
     }
 }
+
+#[test]
+fn revoke_key() -> anyhow::Result<()> {
+    let repo = tmp::repo()?;
+    {
+        let desktop = Device::new(&*DESKTOP, Identities::from(&*repo))?.update(
+            Direct::new(DESKTOP.public())
+                .insert(LAPTOP.public())
+                .insert(PALMTOP.public()),
+        )?;
+        let laptop = Device::create_from(&*LAPTOP, &desktop)?;
+        let desktop = desktop.update_from(&laptop)?;
+        desktop.assert_verifies()?;
+
+        // Palmtop got lost
+        let desktop = desktop.revoke(PALMTOP.public())?;
+        let laptop = laptop.update_from(&desktop)?;
+        laptop.assert_verifies()?;
+        assert!(laptop.current().doc.validity.is_revoked(&PALMTOP.public()));
+        assert!(!laptop.current().delegations().contains(&PALMTOP.public()));
+
+        // Can't revoke twice
+        assert_matches!(
+            laptop
+                .clone()
+                .revoke(PALMTOP.public())
+                .unwrap_err()
+                .downcast_ref::<error::Revoke>(),
+            Some(error::Revoke::NotADelegation(_))
+        );
+
+        // Whoever found palmtop can't add it back
+        let palmtop = Device::create_from(&*PALMTOP, &laptop)?.update(
+            Direct::new(DESKTOP.public())
+                .insert(LAPTOP.public())
+                .insert(PALMTOP.public()),
+        )?;
+        palmtop.assert_no_quorum()
+    }
+}
+
+#[test]
+fn weighted_policy() -> anyhow::Result<()> {
+    let repo = tmp::repo()?;
+    {
+        let desktop = Device::new(&*DESKTOP, Identities::from(&*repo))?.update(
+            Direct::new(DESKTOP.public())
+                .insert(LAPTOP.public())
+                .insert(PALMTOP.public()),
+        )?;
+        let laptop = Device::create_from(&*LAPTOP, &desktop)?;
+        let desktop = desktop.update_from(&laptop)?;
+        desktop.assert_verifies()?;
+
+        let two = NonZeroU32::new(2).unwrap();
+        assert_matches!(
+            desktop
+                .clone()
+                .set_policy(Policy::threshold(NonZeroU32::new(5).unwrap()))
+                .unwrap_err()
+                .downcast_ref::<error::SetPolicy>(),
+            Some(error::SetPolicy::Unattainable)
+        );
+
+        // Setting the policy requires a majority
+        let desktop = desktop.set_policy(Policy::threshold(two).weight(DESKTOP.public(), 2))?;
+        desktop.assert_verifies().unwrap_err();
+        let laptop = laptop.update_from(&desktop)?;
+        laptop.assert_verifies()?;
+
+        // Thereafter, desktop alone can make changes
+        let desktop = desktop
+            .update_from(&laptop)?
+            .update(Direct::new(DESKTOP.public()).insert(LAPTOP.public()))?;
+        desktop.assert_verifies()?;
+
+        // But laptop can't
+        let laptop = laptop.update(Direct::new(LAPTOP.public()).insert(PALMTOP.public()))?;
+        laptop.assert_no_quorum()
+    }
+}
+
+#[test]
+fn policy_stays_attainable() -> anyhow::Result<()> {
+    let repo = tmp::repo()?;
+    {
+        let desktop = Device::new(&*DESKTOP, Identities::from(&*repo))?
+            .update(
+                Direct::new(DESKTOP.public())
+                    .insert(LAPTOP.public())
+                    .insert(PALMTOP.public()),
+            )?
+            .set_policy(Policy::threshold(NonZeroU32::new(3).unwrap()))?;
+
+        assert_matches!(
+            desktop
+                .clone()
+                .revoke(PALMTOP.public())
+                .unwrap_err()
+                .downcast_ref::<error::Revoke>(),
+            Some(error::Revoke::Policy(error::SetPolicy::Unattainable))
+        );
+        assert_matches!(
+            desktop
+                .clone()
+                .update(Direct::new(DESKTOP.public()).insert(LAPTOP.public()))
+                .unwrap_err()
+                .downcast_ref::<error::Update>(),
+            Some(error::Update::Policy(error::SetPolicy::Unattainable))
+        );
+
+        // Lowering the threshold first works
+        desktop
+            .set_policy(Policy::threshold(NonZeroU32::new(2).unwrap()))?
+            .revoke(PALMTOP.public())?;
+
+        Ok(())
+    }
+}
@@ -103,6 +103,40 @@ fn update_payload() -> anyhow::Result<()> {
     }
 }
 
+#[test]
+fn update_preserves_extensions() -> anyhow::Result<()> {
+    let repo = tmp::repo()?;
+    {
+        let cheyenne = Device::new(&*CHEYENNE_DESKTOP, Identities::from(&*repo))?;
+        let dylan = Device::new(&*DYLAN, Identities::from(&*repo))?;
+
+        let project = Project::new(cheyenne.clone())?;
+        let topics = payload::Ext {
+            namespace: "https://semantic.me/topics/v1".parse()?,
+            val: serde_json::json!({ "topics": ["emoji"] }),
+        };
+        let with_ext = project
+            .current()
+            .payload()
+            .clone()
+            .with_ext(topics.clone())?;
+
+        let project = project.update(with_ext, None)?.update(
+            None,
+            IndirectDelegation::try_from_iter(vec![
+                Right(cheyenne.current().clone()),
+                Right(dylan.current().clone()),
+            ])?,
+        )?;
+        assert_eq!(
+            project.current().payload().exts().collect::<Vec<_>>(),
+            vec![(&topics.namespace, &topics.val)]
+        );
+
+        Ok(())
+    }
+}
+
 /// Revoke by just removing a delegation at the top-level
 #[test]
 fn revoke() -> anyhow::Result<()> {
@@ -344,6 +378,100 @@ fn fork() -> anyhow::Result<()> {
     }
 }
 
+#[test]
+fn rotate() -> anyhow::Result<()> {
+    let repo = tmp::repo()?;
+    {
+        let cheyenne = Device::new(&*CHEYENNE_DESKTOP, Identities::from(&*repo))?;
+        let dylan = Device::new(&*DYLAN, Identities::from(&*repo))?;
+
+        let heads = current_heads_from(vec![&cheyenne, &dylan]);
+
+        let cheyenne_project = {
+            let update = IndirectDelegation::try_from_iter(vec![
+                Right(cheyenne.current().clone()),
+                Right(dylan.current().clone()),
+            ])?;
+            Project::new(cheyenne.clone())?.update(None, update)
+        }?;
+        let dylan_project = Project::create_from(dylan.clone(), &cheyenne_project)?;
+        let base = dylan_project.verify(lookup(&heads))?;
+
+        // Hand dylan's key over to the palmtop
+        let palmtop = Device::new(&*CHEYENNE_PALMTOP, Identities::from(&*repo))?;
+        let heads = current_heads_from(vec![&cheyenne, &dylan, &palmtop]);
+        let delegations = IndirectDelegation::try_from_iter(vec![
+            Right(cheyenne.current().clone()),
+            Right(palmtop.current().clone()),
+        ])?;
+
+        let git = dylan.git::<identities::Project>();
+        let mut rotation = git.propose_rotation(&base, delegations.clone(), &*DYLAN)?;
+        // Cheyenne computes the same proposal independently, and sends it over
+        let theirs = {
+            let theirs = cheyenne.git::<identities::Project>().propose_rotation(
+                &base,
+                delegations,
+                &*CHEYENNE_DESKTOP,
+            )?;
+            serde_json::from_str(&serde_json::to_string(&theirs)?)?
+        };
+        rotation.merge(theirs)?;
+        // The palmtop must be able to sign the new revision, too
+        rotation.sign(&*CHEYENNE_PALMTOP)?;
+
+        let rotated = git.rotate(base, rotation)?;
+        assert!(rotated
+            .delegations()
+            .iter()
+            .filter_map(|d| d.right())
+            .any(|id| id.urn() == palmtop.current().urn()));
+        assert_eq!(
+            git.verify(*rotated.content_id, lookup(&heads))?
+                .into_inner()
+                .content_id,
+            rotated.content_id
+        );
+
+        Ok(())
+    }
+}
+
+#[test]
+fn rotate_no_quorum() -> anyhow::Result<()> {
+    let repo = tmp::repo()?;
+    {
+        let cheyenne = Device::new(&*CHEYENNE_DESKTOP, Identities::from(&*repo))?;
+        let dylan = Device::new(&*DYLAN, Identities::from(&*repo))?;
+
+        let heads = current_heads_from(vec![&cheyenne, &dylan]);
+
+        let cheyenne_project = {
+            let update = IndirectDelegation::try_from_iter(vec![
+                Right(cheyenne.current().clone()),
+                Right(dylan.current().clone()),
+            ])?;
+            Project::new(cheyenne.clone())?.update(None, update)
+        }?;
+        let dylan_project = Project::create_from(dylan.clone(), &cheyenne_project)?;
+        let base = dylan_project.verify(lookup(&heads))?;
+
+        // Dylan attempts to oust cheyenne single-handedly
+        let git = dylan.git::<identities::Project>();
+        let rotation = git.propose_rotation(
+            &base,
+            IndirectDelegation::try_from_iter(vec![Right(dylan.current().clone())])?,
+            &*DYLAN,
+        )?;
+        assert_matches!(
+            git.rotate(base, rotation),
+            Err(error::Rotate::Verification(VerificationError::ParentQuorum))
+        );
+
+        Ok(())
+    }
+}
+
 fn current_heads_from<'a>(
     devs: impl IntoIterator<Item = &'a Device<'a>>,
 ) -> BTreeMap<Urn, git2::Oid> {
@@ -7,6 +7,7 @@ use std::fmt::Debug;
 
 use link_crypto::SecretKey;
 use link_identities::payload::{
+    schema::{self, Schema},
     Ext,
     Person,
     PersonDelegations,
@@ -14,6 +15,7 @@ use link_identities::payload::{
     Project,
     ProjectDelegations,
     ProjectPayload,
+    Schemas,
 };
 use pretty_assertions::assert_eq;
 use proptest::prelude::*;
@@ -221,6 +223,73 @@ fn null_extension() {
     assert_eq!(json_actual, json_expected);
 }
 
+fn topics_schema() -> Schema {
+    serde_json::from_str(
+        r#"{
+            "type": "object",
+            "properties": {
+                "topics": { "type": "array", "items": { "type": "string" } },
+                "stars": { "type": "integer" }
+            },
+            "required": ["topics"]
+        }"#,
+    )
+    .unwrap()
+}
+
+fn with_topics(val: serde_json::Value) -> ProjectPayload {
+    ProjectPayload::new(Project {
+        name: "nom".into(),
+        description: None,
+        default_branch: None,
+    })
+    .with_ext(Ext {
+        namespace: "https://semantic.me/topics/v1".parse().unwrap(),
+        val,
+    })
+    .unwrap()
+}
+
+#[test]
+fn schema_validation() {
+    let mut schemas = Schemas::new();
+    schemas.register(
+        "https://semantic.me/topics/v1".parse().unwrap(),
+        topics_schema(),
+    );
+
+    let valid = with_topics(serde_json::json!({ "topics": ["parsing", "rust"] }));
+    assert!(schemas.validate(&valid).is_ok());
+    assert!(matches!(
+        schemas.validate(&with_topics(serde_json::json!({ "topics": ["parsing", 42] }))),
+        Err(schema::error::Validation::Type { path, .. }) if path == "/topics/1"
+    ));
+    assert!(matches!(
+        schemas.validate(&with_topics(serde_json::json!({ "stars": 42 }))),
+        Err(schema::error::Validation::Missing { path, .. }) if path == "/topics"
+    ));
+    assert!(matches!(
+        schemas.validate(&with_topics(serde_json::json!({ "topics": [], "ci": "yes" }))),
+        Err(schema::error::Validation::Unexpected { path, .. }) if path == "/ci"
+    ));
+}
+
+#[test]
+fn schema_strict() {
+    let payload = with_topics(serde_json::json!({ "topics": [] }));
+
+    assert!(Schemas::new().validate(&payload).is_ok());
+    assert!(matches!(
+        Schemas::new().strict().validate(&payload),
+        Err(schema::error::Validation::Unregistered(_))
+    ));
+}
+
+#[test]
+fn schema_roundtrip() {
+    trippin(topics_schema())
+}
+
 /// All serialisation roundtrips required for payload types
 fn trippin<A>(a: A)
 where
@@ -0,0 +1,238 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Preview the effects of a replication run without fetching any objects.
+//!
+//! Given the refs advertised by a remote peer (eg. the result of `ls-refs`
+//! during a peek), [`diff`] computes which of the corresponding
+//! remote-tracking refs in local storage would be created, moved or pruned,
+//! and whether a move would be a fast-forward. Interactive clients can use this
+//! to show "what will change" before deciding to sync.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bstr::ByteSlice as _;
+use futures_lite::future::block_on;
+use git_ref_format::{name, refname, Component, Qualified};
+use link_crypto::PeerId;
+use link_git::protocol::{ObjectId, Ref};
+
+use crate::{error, refdb, refs, LocalPeer, LsRefs, Net, Odb, RefScan, Refdb};
+
+/// Whether moving a ref from its current to the advertised tip would be a
+/// fast-forward.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Forward {
+    /// The current tip is an ancestor of the advertised tip.
+    FastForward,
+    /// The current tip is not an ancestor of the advertised tip, ie. the
+    /// history was rewritten.
+    Forced,
+    /// The advertised tip is not present locally, so ancestry can only be
+    /// determined after fetching.
+    Unknown,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Change {
+    /// The ref does not exist locally.
+    Created { target: ObjectId },
+    /// The ref exists locally, but points to a different object.
+    Updated {
+        prev: ObjectId,
+        target: ObjectId,
+        forward: Forward,
+    },
+    /// The ref exists locally, but is not advertised by its owner anymore.
+    Removed { prev: ObjectId },
+}
+
+#[derive(Clone, Debug)]
+pub struct RefChange {
+    /// The peer owning the ref.
+    pub remote_id: PeerId,
+    /// The local, remote-tracking name of the ref.
+    pub name: Qualified<'static>,
+    pub change: Change,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Diff {
+    /// Refs which would be created, updated or removed.
+    pub changes: Vec<RefChange>,
+    /// Refs which are advertised with the same tip as the local one.
+    pub unchanged: Vec<Qualified<'static>>,
+    /// Peers for which no data is stored locally yet.
+    pub new_peers: BTreeSet<PeerId>,
+}
+
+impl Diff {
+    /// `true` if syncing would not change any refs.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Refs which would be created.
+    pub fn created(&self) -> impl Iterator<Item = &RefChange> {
+        self.changes
+            .iter()
+            .filter(|c| matches!(c.change, Change::Created { .. }))
+    }
+
+    /// Refs which would be removed.
+    pub fn removed(&self) -> impl Iterator<Item = &RefChange> {
+        self.changes
+            .iter()
+            .filter(|c| matches!(c.change, Change::Removed { .. }))
+    }
+
+    /// Refs which would be updated non-fast-forward.
+    ///
+    /// Note that this does not include [`Forward::Unknown`] updates.
+    pub fn forced(&self) -> impl Iterator<Item = &RefChange> {
+        self.changes.iter().filter(|c| {
+            matches!(
+                c.change,
+                Change::Updated {
+                    forward: Forward::Forced,
+                    ..
+                }
+            )
+        })
+    }
+}
+
+/// Compute the [`Diff`] between the `advertised` refs of `remote_id` and the
+/// local state.
+///
+/// Refs which are not scoped (ie. don't start with `refs/remotes/`) are taken
+/// to be owned by `remote_id`. Refs owned by the local peer, and refs which
+/// can't be parsed, are ignored.
+///
+/// Local refs of an owner which are not advertised are reported as
+/// [`Change::Removed`], except for `rad/` refs, which are never pruned.
+#[tracing::instrument(skip(cx, advertised), fields(local_id = %LocalPeer::id(cx)))]
+pub fn diff<C, I>(cx: &C, remote_id: PeerId, advertised: I) -> Result<Diff, error::Diff>
+where
+    C: LocalPeer + Refdb + Odb,
+    for<'a> &'a C: RefScan,
+    I: IntoIterator<Item = Ref>,
+{
+    let local_id = *LocalPeer::id(cx);
+    let mut diff = Diff::default();
+    let mut owners: BTreeMap<PeerId, BTreeSet<Qualified<'static>>> = BTreeMap::new();
+
+    for r in advertised {
+        let (name, target) = refs::into_unpacked(r);
+        let parsed = match refs::parse::<refs::parsed::Identity>(name.as_bstr()) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                warn!(name = %name, err = %e, "skipping malformed ref");
+                continue;
+            },
+        };
+        let owner = parsed.remote.unwrap_or(remote_id);
+        if owner == local_id {
+            continue;
+        }
+        let tracking = Qualified::from(parsed.to_owned().into_remote_tracking(&owner));
+        owners.entry(owner).or_default().insert(tracking.clone());
+        let prev = find(cx, &tracking)?;
+        match prev {
+            None => diff.changes.push(RefChange {
+                remote_id: owner,
+                name: tracking,
+                change: Change::Created { target },
+            }),
+            Some(prev) if prev == target => diff.unchanged.push(tracking),
+            Some(prev) => {
+                let forward = forward(cx, prev, target)?;
+                diff.changes.push(RefChange {
+                    remote_id: owner,
+                    name: tracking,
+                    change: Change::Updated {
+                        prev,
+                        target,
+                        forward,
+                    },
+                })
+            },
+        }
+    }
+
+    for (id, advertised) in owners {
+        let sigrefs =
+            Qualified::from(refs::Owned::refs_rad_signed_refs().into_remote_tracking(&id));
+        if find(cx, &sigrefs)?.is_none() {
+            diff.new_peers.insert(id);
+            continue;
+        }
+
+        let prefix = refname!("refs/remotes").join(Component::from(&id));
+        let prefix_rad = prefix.join(name::RAD);
+        let scan_err = |e: <&C as RefScan>::Error| error::Diff::Scan(e.into());
+        for known in RefScan::scan(cx, prefix.as_str()).map_err(scan_err)? {
+            let refdb::Ref { name, peeled, .. } = known.map_err(scan_err)?;
+            if name.starts_with(prefix_rad.as_str()) || advertised.contains(&name) {
+                continue;
+            }
+            diff.changes.push(RefChange {
+                remote_id: id,
+                name,
+                change: Change::Removed {
+                    prev: peeled.into(),
+                },
+            })
+        }
+    }
+
+    Ok(diff)
+}
+
+/// Like [`diff`], but obtain the advertised refs by running `ls-refs` against
+/// `remote_id`.
+///
+/// No `fetch` is performed, so the remote's tips may not be present locally,
+/// resulting in [`Forward::Unknown`] updates.
+pub fn dry_run<C>(cx: &C, remote_id: PeerId) -> Result<Diff, error::Diff>
+where
+    C: LocalPeer + Net + Refdb + Odb,
+    for<'a> &'a C: RefScan,
+{
+    let advertised =
+        block_on(Net::run_ls_refs(cx, LsRefs::Full)).map_err(|e| error::Diff::LsRefs(e.into()))?;
+    diff(cx, remote_id, advertised)
+}
+
+fn find<C>(cx: &C, name: &Qualified) -> Result<Option<ObjectId>, error::Diff>
+where
+    C: Refdb,
+{
+    Refdb::refname_to_id(cx, name)
+        .map(|oid| oid.map(Into::into))
+        .map_err(|e| error::Diff::FindRef {
+            name: name.clone().into_refstring(),
+            source: e.into(),
+        })
+}
+
+fn forward<C>(cx: &C, prev: ObjectId, target: ObjectId) -> Result<Forward, error::Diff>
+where
+    C: Odb,
+{
+    if !Odb::contains(cx, &target) {
+        return Ok(Forward::Unknown);
+    }
+    let is_ff = Odb::is_in_ancestry_path(cx, target, prev).map_err(|e| error::Diff::Ancestry {
+        new: target,
+        old: prev,
+        source: e.into(),
+    })?;
+    Ok(if is_ff {
+        Forward::FastForward
+    } else {
+        Forward::Forced
+    })
+}
@@ -52,6 +52,70 @@ pub enum Prepare {
     },
 }
 
+/// Inconsistent inputs of a [`crate::fetch::Fetch`], see
+/// [`crate::fetch::Builder`].
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Fetchspecs {
+    #[error("{0} delegates to itself")]
+    SelfDelegation(String),
+
+    #[error("signed refs of delegate {0} not found")]
+    MissingSigrefs(PeerId),
+
+    #[error("signed refs of {0} given, but it is not tracked")]
+    Untracked(PeerId),
+
+    #[error("signed ref `{name}` of {remote} is malformed")]
+    Malformed { remote: PeerId, name: RefString },
+
+    #[error("failed to load signed refs")]
+    Load(#[source] Error),
+}
+
+/// A batch of tracked peers which could not be fetched, see
+/// [`crate::FetchLimit::batch_size`].
+#[derive(Debug, Error)]
+#[error("failed to fetch batch of {} tracked peers", peers.len())]
+pub struct Batch {
+    pub peers: Vec<PeerId>,
+    #[source]
+    pub source: Error,
+}
+
+/// The staged refs of a tracked peer failed validation, and were discarded.
+#[derive(Debug, Error)]
+#[error("staged refs of {remote} failed validation")]
+pub struct Staged {
+    pub remote: PeerId,
+    pub errors: Vec<Validation>,
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Diff {
+    #[error("failed to list remote refs")]
+    LsRefs(#[source] Error),
+
+    #[error("failed to look up ref {name}")]
+    FindRef {
+        name: RefString,
+        #[source]
+        source: Error,
+    },
+
+    #[error("failed to scan local refs")]
+    Scan(#[source] Error),
+
+    #[error("failed to determine if {new} is in the ancestry path of {old}")]
+    Ancestry {
+        new: ObjectId,
+        old: ObjectId,
+        #[source]
+        source: Error,
+    },
+}
+
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum Validation {
@@ -88,6 +152,22 @@ pub enum Validation {
     NoData(LocalOrRemote),
 }
 
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Ownership {
+    #[error("`{0}` is outside of the `refs/rad/` and `refs/remotes/` hierarchies")]
+    Layout(RefString),
+
+    #[error("`{0}` belongs to the local peer")]
+    Local(RefString),
+
+    #[error("`{name}` is not signed by {remote}")]
+    Unsigned { name: RefString, remote: PeerId },
+
+    #[error("`{name}` does not point to valid signed refs of {remote}")]
+    Sigrefs { name: RefString, remote: PeerId },
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum LocalOrRemote {
     LocalId,
@@ -12,8 +12,9 @@ use crate::{
     error,
     fetch,
     ids,
+    ownership,
     peek,
-    refs,
+    quarantine,
     sigrefs::{self, Refs},
     state::FetchState,
     validation,
@@ -31,9 +32,7 @@ use crate::{
     SignedRefs,
     Sigrefs,
     Success,
-    SymrefTarget,
     Tracking,
-    Update,
     VerifiedIdentity,
 };
 
@@ -94,38 +93,54 @@ where
         .collect::<Result<_, _>>()?;
 
     info!("fetching verification refs");
-    let peek = peek::ForFetch {
-        local_id,
-        remote_id,
-        tracked,
-        limit: limit.peek,
+    let mut failed = Vec::new();
+    let tracked = {
+        let peeks = peek::batches(tracked, limit.batch_size)
+            .into_iter()
+            .map(|batch| peek::ForFetch {
+                local_id,
+                remote_id,
+                tracked: batch,
+                limit: limit.peek,
+            })
+            .collect::<Vec<_>>();
+        debug!(?peeks);
+        let results = state.step_all(cx, &peeks)?;
+        let mut fetched = BTreeMap::new();
+        for (peek, res) in peeks.into_iter().zip(results) {
+            let mut batch = peek.tracked;
+            match res {
+                Ok(()) => fetched.append(&mut batch),
+                Err(e) if !batch.values().any(|spec| spec.is_delegate) => {
+                    warn!(err = %e, "failed to fetch verification refs of batch");
+                    state.discard(batch.keys());
+                    failed.push(error::Batch {
+                        peers: batch.into_keys().collect(),
+                        source: e,
+                    });
+                },
+                Err(e) => return Err(e),
+            }
+        }
+        fetched
     };
-    debug!(?peek);
-    state.step(cx, &peek)?;
 
     info!("loading sigrefs");
-    let signed_refs = sigrefs::combined(
-        &state.as_shim(cx),
-        sigrefs::Select {
-            must: &delegates_sans_local,
-            may: &peek
-                .tracked
-                .keys()
-                .filter(|id| !delegates.contains(id))
-                .copied()
-                .collect(),
-            cutoff: 2,
-        },
-    )?;
+    let builder = fetch::Builder::new(local_id, remote_id, limit.data)
+        .batch_size(limit.batch_size)
+        .delegates(&anchor)?
+        .tracked(tracked.keys().copied())
+        .load(&state.as_shim(cx), 2)?;
+    let signed_refs = builder.signed_refs();
     debug!(?signed_refs);
 
     let mut transitive: BTreeMap<PeerId, DataPolicy> = BTreeMap::new();
-    for (id, spec) in &peek.tracked {
+    for (id, spec) in &tracked {
         if let Some(sigrefs) = signed_refs.get(id) {
             for remote_id in &sigrefs.remotes {
                 if remote_id == &local_id
                     || delegates.contains(remote_id)
-                    || peek.tracked.contains_key(remote_id)
+                    || tracked.contains_key(remote_id)
                 {
                     continue;
                 }
@@ -163,58 +178,40 @@ where
         }
     };
 
-    // Apply trackings disovered so far. If this fails, we haven't transferred a
-    // lot of data yet.
-    info!("updating trackings");
-    let newly_tracked = Tracking::track(cx, state.trackings_mut().drain(..))?
-        .into_iter()
-        .collect::<Vec<_>>();
-
-    // Update identity tips already, we will only be looking at sigrefs from now
-    // on. Can improve concurrency.
-    info!("updating identity tips");
-    let mut applied = {
-        let pending = state.updates_mut();
-
-        // `Vec::drain_filter` would help here
-        let mut tips = Vec::new();
-        let mut i = 0;
-        while i < pending.len() {
-            match &pending[i] {
-                Update::Direct { name, .. } if name.ends_with(refs::name::str::REFS_RAD_ID) => {
-                    tips.push(pending.swap_remove(i));
-                },
-                Update::Symbolic {
-                    target: SymrefTarget { name, .. },
-                    ..
-                } if name.ends_with(refs::name::str::REFS_RAD_ID) => {
-                    tips.push(pending.swap_remove(i));
-                },
-                _ => {
-                    i += 1;
-                },
-            }
-        }
-        Refdb::update(cx, tips)?
-    };
-
-    let signed_refs = signed_refs.flattened();
+    // Identity tips and trackings are held back along with all other updates,
+    // so nothing is written if validation fails.
+    let fetches = builder.build()?;
     // Clear rad tips so far. Fetch will ask the remote to advertise
     // all rad refs from the transitive trackings, so we can inspect
     // the state afterwards to see if we got any.
     state.clear_rad_refs();
 
-    let fetch = fetch::Fetch {
-        local_id,
-        remote_id,
-        signed_refs,
-        limit: limit.data,
-    };
     info!("fetching data");
-    debug!(?fetch);
-    state.step(cx, &fetch)?;
-
-    let mut signed_refs = fetch.signed_refs;
+    let mut signed_refs = {
+        debug!(?fetches);
+        let results = state.step_all(cx, &fetches)?;
+        let mut fetched = sigrefs::Flattened::default();
+        for (fetch, res) in fetches.into_iter().zip(results) {
+            let mut batch = fetch.signed_refs;
+            match res {
+                Ok(()) => {
+                    fetched.refs.append(&mut batch.refs);
+                    fetched.remotes.append(&mut batch.remotes);
+                },
+                Err(e) if !batch.refs.keys().any(|id| delegates.contains(id)) => {
+                    warn!(err = %e, "failed to fetch data of batch");
+                    let peers = batch.peers();
+                    state.discard(&peers);
+                    failed.push(error::Batch {
+                        peers: peers.into_iter().collect(),
+                        source: e,
+                    });
+                },
+                Err(e) => return Err(e),
+            }
+        }
+        fetched
+    };
 
     if !state.id_tips().is_empty() {
         info!("transitively tracked data found");
@@ -250,8 +247,36 @@ where
             .append(&mut trans_fetch.signed_refs.flattened().refs);
     }
 
+    info!("verifying ownership of refs");
+    let (updates, quarantined) = ownership::verify::<U, _, _>(
+        &*cx,
+        &local_id,
+        &signed_refs.refs,
+        state.updates_mut().drain(..).collect(),
+    );
+
+    debug!(?signed_refs);
+    info!("validating staged signed trees");
+    let quarantine::Checked { updates, discarded } =
+        quarantine::check::<U, _, _, _>(&*cx, &*cx, &signed_refs.refs, updates)?;
+    for e in discarded {
+        if delegates.contains(&e.remote) {
+            return Err(e.into());
+        }
+        warn!(err = %e, "discarding staged refs");
+        failed.push(error::Batch {
+            peers: vec![e.remote],
+            source: e.into(),
+        });
+    }
+
+    info!("updating trackings");
+    let newly_tracked = Tracking::track(cx, state.trackings_mut().drain(..))?
+        .into_iter()
+        .collect::<Vec<_>>();
+
     info!("updating tips");
-    applied.append(&mut Refdb::update(cx, state.updates_mut().drain(..))?);
+    let applied = Refdb::update(cx, updates)?;
     for u in &applied.updated {
         debug!("applied {:?}", u);
     }
@@ -260,18 +285,6 @@ where
     SignedRefs::update(cx)?;
 
     let mut warnings = Vec::new();
-    debug!(?signed_refs);
-    info!("validating signed trees");
-    for (peer, refs) in &signed_refs.refs {
-        let ws = validation::validate::<U, _, _, _>(&*cx, peer, refs)?;
-        debug_assert!(
-            ws.is_empty(),
-            "expected no warnings for {}, but got {:?}",
-            peer,
-            ws
-        );
-        warnings.extend(ws);
-    }
 
     info!("validating remote trees");
     for peer in &signed_refs.remotes {
@@ -300,6 +313,8 @@ where
         tracked: newly_tracked,
         requires_confirmation,
         validation: warnings,
+        failed,
+        quarantined,
         _marker: PhantomData,
     })
 }
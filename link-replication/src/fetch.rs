@@ -30,6 +30,9 @@ use crate::{
     WantsHaves,
 };
 
+mod builder;
+pub use builder::Builder;
+
 mod transitive;
 pub use transitive::Transitive;
 
@@ -175,8 +178,8 @@ where
 
     for (remote_id, refs) in signed_refs.into_iter() {
         for (name, tip) in refs {
-            // TODO: ensure sigrefs are well-formed. Or else, prune `refs`
-            // iff `remote_id` is in delegates.
+            // Checked by `Builder::build` for `Fetch`, but not for
+            // `Transitive`
             let tracking = Qualified::from_refstr(name)
                 .and_then(|q| refs::remote_tracking(remote_id, q))
                 .ok_or_else(|| transmit::error::WantsHaves::Malformed(name.to_owned()))?;
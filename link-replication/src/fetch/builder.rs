@@ -0,0 +1,168 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{collections::BTreeSet, num::NonZeroUsize};
+
+use git_ref_format::Qualified;
+use link_crypto::PeerId;
+use link_git::protocol::oid;
+
+use super::Fetch;
+use crate::{
+    error,
+    ids::{Urn as _, VerifiedIdentity},
+    refs,
+    sigrefs::{self, SignedRefs},
+};
+
+/// Builds the [`Fetch`]es of the data of tracked peers, checking that their
+/// inputs are consistent:
+///
+/// * the delegations of the identity being replicated don't include itself
+/// * all delegates, except for the local peer, have signed refs
+/// * no signed refs are included for peers which are not tracked
+/// * the signed refs can be mapped to remote-tracking branches
+#[derive(Debug)]
+pub struct Builder<Oid> {
+    local_id: PeerId,
+    remote_id: PeerId,
+    limit: u64,
+    batch_size: Option<NonZeroUsize>,
+    delegates: BTreeSet<PeerId>,
+    tracked: BTreeSet<PeerId>,
+    signed_refs: sigrefs::Combined<Oid>,
+}
+
+impl<Oid: AsRef<oid>> Builder<Oid> {
+    /// Fetch from `remote_id`, limiting the packfile of each [`Fetch`] to
+    /// `limit` bytes.
+    pub fn new(local_id: PeerId, remote_id: PeerId, limit: u64) -> Self {
+        Self {
+            local_id,
+            remote_id,
+            limit,
+            batch_size: None,
+            delegates: BTreeSet::new(),
+            tracked: BTreeSet::new(),
+            signed_refs: sigrefs::Combined::default(),
+        }
+    }
+
+    /// Split the [`Fetch`] into batches of at most `size` peers each, see
+    /// [`sigrefs::Flattened::batches`].
+    pub fn batch_size(mut self, size: Option<NonZeroUsize>) -> Self {
+        self.batch_size = size;
+        self
+    }
+
+    /// Require the signed refs of the delegates of `anchor`.
+    pub fn delegates<V>(mut self, anchor: &V) -> Result<Self, error::Fetchspecs>
+    where
+        V: VerifiedIdentity,
+    {
+        let urn = anchor.urn();
+        if anchor.delegate_urns().contains(&urn) {
+            return Err(error::Fetchspecs::SelfDelegation(urn.encode_id()));
+        }
+        self.delegates.extend(anchor.delegate_ids().iter().copied());
+        Ok(self)
+    }
+
+    /// Include the signed refs of the tracked `peers`, if they have any.
+    pub fn tracked(mut self, peers: impl IntoIterator<Item = PeerId>) -> Self {
+        self.tracked.extend(peers);
+        self
+    }
+
+    /// Use the given `signed_refs`, replacing any loaded or given before.
+    pub fn with_signed_refs(mut self, signed_refs: sigrefs::Combined<Oid>) -> Self {
+        self.signed_refs = signed_refs;
+        self
+    }
+
+    /// Load the signed refs of the delegates and tracked peers from `s`,
+    /// limiting the tracking graph depth to `cutoff`.
+    pub fn load<S>(self, s: &S, cutoff: usize) -> Result<Self, error::Fetchspecs>
+    where
+        S: SignedRefs<Oid = Oid>,
+    {
+        let must = self.required().collect();
+        let may = self
+            .tracked
+            .iter()
+            .filter(|id| !self.delegates.contains(id))
+            .copied()
+            .collect();
+        let signed_refs = sigrefs::combined(
+            s,
+            sigrefs::Select {
+                must: &must,
+                may: &may,
+                cutoff,
+            },
+        )
+        .map_err(|e| match e {
+            sigrefs::error::Combine::NotFound(id) => error::Fetchspecs::MissingSigrefs(id),
+            e => error::Fetchspecs::Load(Box::new(e)),
+        })?;
+
+        Ok(self.with_signed_refs(signed_refs))
+    }
+
+    /// The signed refs loaded or given so far.
+    pub fn signed_refs(&self) -> &sigrefs::Combined<Oid> {
+        &self.signed_refs
+    }
+
+    /// Validate the inputs, and build the [`Fetch`] batches.
+    pub fn build(self) -> Result<Vec<Fetch<Oid>>, error::Fetchspecs> {
+        for id in self.required() {
+            if !self.signed_refs.contains_key(&id) {
+                return Err(error::Fetchspecs::MissingSigrefs(id));
+            }
+        }
+        for (id, sigrefs) in &self.signed_refs {
+            if !self.delegates.contains(id) && !self.tracked.contains(id) {
+                return Err(error::Fetchspecs::Untracked(*id));
+            }
+            for name in sigrefs.refs.keys() {
+                Qualified::from_refstr(name)
+                    .and_then(|q| refs::remote_tracking(id, q))
+                    .ok_or_else(|| error::Fetchspecs::Malformed {
+                        remote: *id,
+                        name: name.clone(),
+                    })?;
+            }
+        }
+
+        let Self {
+            local_id,
+            remote_id,
+            limit,
+            batch_size,
+            signed_refs,
+            ..
+        } = self;
+        Ok(signed_refs
+            .flattened()
+            .batches(batch_size)
+            .into_iter()
+            .map(|batch| Fetch {
+                local_id,
+                remote_id,
+                signed_refs: batch,
+                limit,
+            })
+            .collect())
+    }
+
+    /// The delegates whose signed refs must be present.
+    fn required(&self) -> impl Iterator<Item = PeerId> + '_ {
+        self.delegates
+            .iter()
+            .filter(move |id| *id != &self.local_id)
+            .copied()
+    }
+}
@@ -3,7 +3,12 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{io, marker::PhantomData, path::PathBuf};
+use std::{
+    io,
+    marker::PhantomData,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use bstr::BString;
 use futures_lite::io::{AsyncRead, AsyncWrite};
@@ -29,6 +34,8 @@ pub struct Network<U, D, B, C> {
     urn: U,
     db: D,
     conn: C,
+    received: AtomicU64,
+    received_objects: AtomicU64,
     _marker: PhantomData<B>,
 }
 
@@ -39,9 +46,23 @@ impl<U, D, B, C> Network<U, D, B, C> {
             db,
             conn,
             urn,
+            received: AtomicU64::new(0),
+            received_objects: AtomicU64::new(0),
             _marker: PhantomData,
         }
     }
+
+    /// Total size in bytes of the packfiles received by [`Net::run_fetch`] so
+    /// far.
+    pub fn received_bytes(&self) -> u64 {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    /// Total number of objects in the packfiles received by [`Net::run_fetch`]
+    /// so far.
+    pub fn received_objects(&self) -> u64 {
+        self.received_objects.load(Ordering::Relaxed)
+    }
 }
 
 #[async_trait(?Send)]
@@ -143,7 +164,7 @@ where
             .expect("written packfile must have a path");
 
         // Validate we got all requested tips in the pack
-        {
+        let objects = {
             use link_git::odb::index::IndexFile;
 
             let idx = IndexFile::at(&pack_path).map_err(io_other)?;
@@ -155,10 +176,16 @@ where
                     ));
                 }
             }
-        }
+            idx.num_objects()
+        };
         // abstraction leak: we could add the `Index` directly if we knew the
         // type of our odb.
         self.db.add_pack(&pack_path).map_err(io_other)?;
+        if let Ok(meta) = std::fs::metadata(pack_path.with_extension("pack")) {
+            self.received.fetch_add(meta.len(), Ordering::Relaxed);
+        }
+        self.received_objects
+            .fetch_add(u64::from(objects), Ordering::Relaxed);
 
         Ok(())
     }
@@ -7,7 +7,7 @@
 #![warn(clippy::extra_unused_lifetimes)]
 #![deny(rustdoc::broken_intra_doc_links)]
 
-use std::fmt::Debug;
+use std::{fmt::Debug, num::NonZeroUsize};
 
 #[macro_use]
 extern crate async_trait;
@@ -17,6 +17,9 @@ extern crate tracing;
 use link_crypto::PeerId;
 use radicle_std_ext::prelude::*;
 
+pub mod diff;
+pub use diff::{diff, dry_run, Diff};
+
 pub mod error;
 pub use error::Error;
 
@@ -34,12 +37,17 @@ pub use ids::{AnyIdentity, Identities, LocalIdentity, Urn, VerifiedIdentity};
 pub mod odb;
 pub use odb::Odb;
 
+pub mod ownership;
+pub use ownership::Quarantined;
+
 mod prepare;
 
-mod refdb;
+pub mod quarantine;
+
+pub mod refdb;
 pub use refdb::{Applied, Policy, RefScan, Refdb, SymrefTarget, Update, Updated};
 
-mod sigrefs;
+pub mod sigrefs;
 pub use sigrefs::{SignedRefs, Sigrefs};
 
 mod state;
@@ -71,6 +79,15 @@ pub trait LocalPeer {
 pub struct FetchLimit {
     pub peek: u64,
     pub data: u64,
+    /// Maximum number of tracked peers whose refs are requested in a single
+    /// fetch.
+    ///
+    /// Namespaces with many tracked peers are fetched in several batches,
+    /// each subject to the `peek` and `data` limits. A batch which fails is
+    /// reported via [`Success::failed_batches`], unless it contains
+    /// delegates, in which case the replication fails. `None` fetches all
+    /// tracked peers at once.
+    pub batch_size: Option<NonZeroUsize>,
 }
 
 impl Default for FetchLimit {
@@ -78,6 +95,7 @@ impl Default for FetchLimit {
         Self {
             peek: 1024 * 1024 * 5,
             data: 1024 * 1024 * 1024 * 5,
+            batch_size: NonZeroUsize::new(64),
         }
     }
 }
@@ -0,0 +1,130 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Verification of pending ref updates against the ownership of the refs
+//! they touch.
+//!
+//! The refs a remote peer advertises are mapped into the remote-tracking
+//! hierarchy `refs/remotes/<peer>/` of the peer they claim to belong to. A
+//! remote peer may, however, claim refs on behalf of other peers which those
+//! peers never signed. Before the tips are updated, every pending update is
+//! therefore checked:
+//!
+//! * top-level refs may only be `rad/` refs, which are derived from verified
+//!   identities
+//! * the hierarchy of the local peer is never written to
+//! * `rad/signed_refs` must point to signed refs which verify against the peer
+//!   owning the hierarchy
+//! * any other ref must match the signed refs of its owner
+//!
+//! Updates failing the check are [`Quarantined`] instead of applied.
+
+use std::{collections::BTreeMap, convert::TryFrom as _};
+
+use either::Either::*;
+use link_crypto::PeerId;
+use link_git::protocol::oid;
+
+use crate::{
+    error,
+    ids,
+    refs::{self, parsed::Rad},
+    sigrefs::Refs,
+    SignedRefs,
+    Update,
+};
+
+/// A ref update withheld from the tips, see the [module documentation](self).
+#[derive(Debug)]
+pub struct Quarantined {
+    pub update: Update<'static>,
+    pub reason: error::Ownership,
+}
+
+/// Split `updates` into the ones which may be applied, and the ones which
+/// are [`Quarantined`].
+///
+/// `signed` are the signed refs of the peers whose data was fetched.
+pub fn verify<U, C, O>(
+    cx: &C,
+    local_id: &PeerId,
+    signed: &BTreeMap<PeerId, Refs<O>>,
+    updates: Vec<Update<'static>>,
+) -> (Vec<Update<'static>>, Vec<Quarantined>)
+where
+    U: ids::Urn,
+    C: SignedRefs,
+    O: AsRef<oid>,
+{
+    let mut applicable = Vec::with_capacity(updates.len());
+    let mut quarantined = Vec::new();
+    for update in updates {
+        match check::<U, _, _>(cx, local_id, signed, &update) {
+            Ok(()) => applicable.push(update),
+            Err(reason) => {
+                warn!(%reason, "quarantining ref update");
+                quarantined.push(Quarantined { update, reason })
+            },
+        }
+    }
+
+    (applicable, quarantined)
+}
+
+fn check<U, C, O>(
+    cx: &C,
+    local_id: &PeerId,
+    signed: &BTreeMap<PeerId, Refs<O>>,
+    update: &Update<'_>,
+) -> Result<(), error::Ownership>
+where
+    U: ids::Urn,
+    C: SignedRefs,
+    O: AsRef<oid>,
+{
+    let target = match update {
+        // Pruning is confined to the refs we already have
+        Update::Prune { .. } => return Ok(()),
+        Update::Direct { target, .. } => Some(target),
+        Update::Symbolic { .. } => None,
+    };
+    let name = update.refname().to_owned();
+    let parsed = refs::Parsed::<U>::try_from(name.clone())
+        .map_err(|_| error::Ownership::Layout(name.clone()))?;
+    let remote = match parsed.remote {
+        None => {
+            return match parsed.inner {
+                Left(_) => Ok(()),
+                Right(_) => Err(error::Ownership::Layout(name)),
+            }
+        },
+        Some(remote) if &remote == local_id => return Err(error::Ownership::Local(name)),
+        Some(remote) => remote,
+    };
+
+    match (parsed.inner, target) {
+        (Left(Rad::SignedRefs), Some(target)) => match SignedRefs::load_at(cx, *target, &remote, 0)
+        {
+            Ok(Some(_)) => Ok(()),
+            Ok(None) => Err(error::Ownership::Sigrefs { name, remote }),
+            Err(e) => {
+                debug!(err = %e, %remote, "invalid signed refs");
+                Err(error::Ownership::Sigrefs { name, remote })
+            },
+        },
+        // Identities are verified when preparing the update
+        (Left(_), _) => Ok(()),
+        (Right(owned), Some(target)) => {
+            let tip = signed
+                .get(&remote)
+                .and_then(|refs| refs.refs.get(owned.as_ref()));
+            match tip {
+                Some(tip) if tip.as_ref() == target.as_ref() => Ok(()),
+                _ => Err(error::Ownership::Unsigned { name, remote }),
+            }
+        },
+        (Right(_), None) => Err(error::Ownership::Unsigned { name, remote }),
+    }
+}
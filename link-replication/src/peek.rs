@@ -11,7 +11,7 @@ mod clone;
 pub use clone::ForClone;
 
 mod fetch;
-pub use fetch::{ForFetch, Spec as FetchSpec};
+pub use fetch::{batches, ForFetch, Spec as FetchSpec};
 
 pub(crate) fn ref_prefixes(id: &PeerId, remote_id: &PeerId) -> impl Iterator<Item = RefPrefix> {
     IntoIterator::into_iter([
@@ -3,7 +3,7 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, num::NonZeroUsize};
 
 use bstr::ByteSlice;
 use link_crypto::PeerId;
@@ -36,6 +36,30 @@ pub struct Spec {
     pub policy: track::DataPolicy,
 }
 
+/// Split `tracked` into batches of at most `size` peers, delegates first.
+///
+/// There is always at least one batch.
+pub fn batches(
+    tracked: BTreeMap<PeerId, Spec>,
+    size: Option<NonZeroUsize>,
+) -> Vec<BTreeMap<PeerId, Spec>> {
+    let size = match size {
+        None => return vec![tracked],
+        Some(size) => size.get(),
+    };
+    let (mut peers, others): (Vec<_>, Vec<_>) =
+        tracked.into_iter().partition(|(_, spec)| spec.is_delegate);
+    peers.extend(others);
+    let mut batches = peers
+        .chunks(size)
+        .map(|chunk| chunk.iter().copied().collect())
+        .collect::<Vec<_>>();
+    if batches.is_empty() {
+        batches.push(BTreeMap::new())
+    }
+    batches
+}
+
 #[derive(Debug)]
 pub struct ForFetch {
     /// The local peer, so we don't fetch our own data.
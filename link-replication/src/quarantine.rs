@@ -0,0 +1,123 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+//! Staging of fetched refs before they are promoted to the namespace.
+//!
+//! The tips computed during a replication run, including those of the
+//! identities, are held back until the signed trees of the fetched peers have
+//! been validated against them. To this end, the remote-tracking refs of those
+//! peers are copied into a [`refdb::Mem`], and the pending updates applied on
+//! top of it, honouring their fast-forward policies. Only if the resulting
+//! trees validate are the updates promoted to the actual [`crate::Refdb`].
+//! Otherwise, the updates of the offending peer are discarded, leaving its
+//! refs as they were. If the offending peer is a delegate, no updates are
+//! promoted at all.
+//!
+//! The updates are promoted by a single [`crate::Refdb::update`], so whether
+//! they are promoted atomically depends on the [`crate::Refdb`]. The `git`
+//! backend of this crate is not atomic: it locks all refs and checks their
+//! previous states before writing any, but then writes them one after the
+//! other, so an I/O error or crash while writing may leave only some of them
+//! applied.
+//!
+//! Objects are not staged: they are written to the object database as they
+//! are fetched. Objects which are only reachable from discarded updates stay
+//! unreferenced, and are removed by garbage collection.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+};
+
+use link_crypto::PeerId;
+use link_git::protocol::oid;
+
+use crate::{error, ids, refdb, sigrefs::Refs, validation, Odb, RefScan, Refdb as _, Update};
+
+/// The result of [`check`].
+#[derive(Debug)]
+pub struct Checked {
+    /// The updates which may be promoted.
+    pub updates: Vec<Update<'static>>,
+    /// The peers whose staged refs failed validation, and whose updates were
+    /// thus discarded.
+    pub discarded: Vec<error::Staged>,
+}
+
+/// Stage `updates`, validate the signed trees of the peers in `signed`
+/// against them, and discard the updates of the peers which fail.
+///
+/// Fails if the updates would be aborted by [`crate::Refdb::update`].
+pub fn check<U, S, O, Oid>(
+    scan: S,
+    odb: &O,
+    signed: &BTreeMap<PeerId, Refs<Oid>>,
+    mut updates: Vec<Update<'static>>,
+) -> Result<Checked, error::Error>
+where
+    U: ids::Urn + Clone + Debug,
+    S: RefScan + Copy,
+    O: Odb,
+    Oid: AsRef<oid> + Debug,
+{
+    let staged = stage(scan, odb, signed.keys(), &updates)?;
+    let mut discarded = Vec::new();
+    for (peer, refs) in signed {
+        let errors = validation::validate::<U, _, _, _>(&staged, peer, refs)?;
+        if !errors.is_empty() {
+            discarded.push(error::Staged {
+                remote: *peer,
+                errors,
+            });
+        }
+    }
+    updates.retain(|up| {
+        !discarded
+            .iter()
+            .any(|staged| is_owned_by(up, &staged.remote))
+    });
+
+    Ok(Checked { updates, discarded })
+}
+
+/// Stage `updates` on top of the refs of `peers` as currently found in
+/// `scan`, using `odb` to determine fast-forwards.
+fn stage<'a, 'b, S, O, I>(
+    scan: S,
+    odb: &'b O,
+    peers: I,
+    updates: &[Update<'static>],
+) -> Result<refdb::Mem<'b>, error::Error>
+where
+    S: RefScan + Copy,
+    O: Odb,
+    I: IntoIterator<Item = &'a PeerId>,
+{
+    let mut refs = HashMap::new();
+    for peer in peers {
+        for r in scan.scan(remote_prefix(peer))? {
+            let refdb::Ref { name, peeled, .. } = r?;
+            refs.insert(name, peeled.into());
+        }
+    }
+    let mut staged = refdb::Mem::from(refs)
+        .with_ancestry(move |new, old| Ok(odb.is_in_ancestry_path(new, old)?));
+    staged.update(updates.iter().cloned())?;
+
+    Ok(staged)
+}
+
+/// Whether `update` touches the remote-tracking refs of `peer`.
+fn is_owned_by(update: &Update<'_>, peer: &PeerId) -> bool {
+    update
+        .refname()
+        .as_str()
+        .strip_prefix(&remote_prefix(peer))
+        .map_or(false, |rest| rest.starts_with('/'))
+}
+
+fn remote_prefix(peer: &PeerId) -> String {
+    format!("refs/remotes/{}", peer)
+}
@@ -11,7 +11,7 @@ use link_git::protocol::{oid, ObjectId};
 
 use crate::refs;
 
-mod mem;
+pub mod mem;
 pub use mem::Mem;
 
 pub trait Refdb {
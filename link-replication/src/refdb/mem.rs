@@ -3,31 +3,104 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::collections::{hash_map, HashMap};
+use std::{
+    collections::{hash_map, HashMap},
+    fmt,
+};
 
-use super::{Applied, RefScan, Refdb, Update, Updated};
+use super::{Applied, Policy, RefScan, Refdb, Update, Updated};
 use crate::{refdb, refs::Qualified, ObjectId, Void};
 
+pub mod error {
+    use git_ref_format::RefString;
+    use link_git::protocol::ObjectId;
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum Tx {
+        #[error("non-fast-forward update of {name} (current: {cur}, new: {new})")]
+        NonFF {
+            name: RefString,
+            new: ObjectId,
+            cur: ObjectId,
+        },
+
+        #[error("error determining if {old} is an ancestor of {new} within {name}")]
+        Ancestry {
+            name: RefString,
+            new: ObjectId,
+            old: ObjectId,
+            #[source]
+            source: crate::error::Error,
+        },
+    }
+}
+
+/// Determines whether the first [`ObjectId`] is in the ancestry path of the
+/// second, see [`crate::Odb::is_in_ancestry_path`].
+type Ancestry<'a> = dyn Fn(ObjectId, ObjectId) -> Result<bool, crate::error::Error> + 'a;
+
 /// A very simple in-memory [`Refdb`].
 ///
-/// It treats symrefs as direct, and does not keep track of ancestry (hence
-/// updates can't fail).
-#[derive(Default, Debug)]
-pub struct Mem {
+/// It treats symrefs as direct. Unless created [`Mem::with_ancestry`], it does
+/// not keep track of ancestry, and so considers every update a fast-forward
+/// (hence updates can't fail).
+#[derive(Default)]
+pub struct Mem<'a> {
     refs: HashMap<Qualified<'static>, ObjectId>,
+    ancestry: Option<Box<Ancestry<'a>>>,
 }
 
-impl From<HashMap<Qualified<'static>, ObjectId>> for Mem {
+impl<'a> Mem<'a> {
+    /// Honour the `no_ff` [`Policy`] of [`Update::Direct`]s, using `ancestry`
+    /// to determine whether they are fast-forwards.
+    pub fn with_ancestry<F>(self, ancestry: F) -> Self
+    where
+        F: Fn(ObjectId, ObjectId) -> Result<bool, crate::error::Error> + 'a,
+    {
+        Self {
+            ancestry: Some(Box::new(ancestry)),
+            ..self
+        }
+    }
+
+    fn is_ff(&self, name: &Qualified<'_>, new: ObjectId, old: ObjectId) -> Result<bool, error::Tx> {
+        match &self.ancestry {
+            None => Ok(true),
+            Some(_) if new == old => Ok(true),
+            Some(ancestry) => ancestry(new, old).map_err(|source| error::Tx::Ancestry {
+                name: name.clone().into_refstring(),
+                new,
+                old,
+                source,
+            }),
+        }
+    }
+}
+
+impl fmt::Debug for Mem<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mem")
+            .field("refs", &self.refs)
+            .field("ancestry", &self.ancestry.is_some())
+            .finish()
+    }
+}
+
+impl From<HashMap<Qualified<'static>, ObjectId>> for Mem<'_> {
     fn from(refs: HashMap<Qualified<'static>, ObjectId>) -> Self {
-        Self { refs }
+        Self {
+            refs,
+            ancestry: None,
+        }
     }
 }
 
-impl Refdb for Mem {
+impl Refdb for Mem<'_> {
     type Oid = ObjectId;
 
     type FindError = Void;
-    type TxError = Void;
+    type TxError = error::Tx;
     type ReloadError = Void;
 
     fn refname_to_id<'a, Q>(&self, refname: Q) -> Result<Option<Self::Oid>, Self::FindError>
@@ -37,20 +110,50 @@ impl Refdb for Mem {
         Ok(self.refs.get(refname.as_ref()).map(Clone::clone))
     }
 
+    /// Apply `updates`, or none of them if a non-fast-forward [`Update`] with
+    /// [`Policy::Abort`] is encountered.
     fn update<'a, I>(&mut self, updates: I) -> Result<Applied<'a>, Self::TxError>
     where
         I: IntoIterator<Item = Update<'a>>,
     {
         let mut ap = Applied::default();
+        // Edits are collected first, so nothing is applied if we abort
+        let mut edits: HashMap<Qualified<'static>, Option<ObjectId>> = HashMap::new();
         for up in updates {
             match up {
                 Update::Direct {
                     name,
                     target,
-                    no_ff: _,
+                    no_ff,
                 } => {
                     let name = name.into_owned();
-                    self.refs.insert(name.clone(), target);
+                    let cur = match edits.get(&name) {
+                        Some(edit) => *edit,
+                        None => self.refs.get(&name).copied(),
+                    };
+                    if let Some(cur) = cur {
+                        if !self.is_ff(&name, target, cur)? {
+                            match no_ff {
+                                Policy::Abort => {
+                                    return Err(error::Tx::NonFF {
+                                        name: name.into_refstring(),
+                                        new: target,
+                                        cur,
+                                    })
+                                },
+                                Policy::Reject => {
+                                    ap.rejected.push(Update::Direct {
+                                        name,
+                                        target,
+                                        no_ff,
+                                    });
+                                    continue;
+                                },
+                                Policy::Allow => {},
+                            }
+                        }
+                    }
+                    edits.insert(name.clone(), Some(target));
                     ap.updated.push(Updated::Direct {
                         name: name.into_refstring(),
                         target,
@@ -62,7 +165,7 @@ impl Refdb for Mem {
                     type_change: _,
                 } => {
                     let name = name.into_owned();
-                    self.refs.insert(name.clone(), target.target);
+                    edits.insert(name.clone(), Some(target.target));
                     ap.updated.push(Updated::Symbolic {
                         name: name.into_refstring(),
                         target: target.name().to_owned(),
@@ -70,7 +173,12 @@ impl Refdb for Mem {
                 },
                 Update::Prune { name, prev: _ } => {
                     let name = name.into_owned();
-                    if self.refs.remove(&name).is_some() {
+                    let exists = match edits.get(&name) {
+                        Some(edit) => edit.is_some(),
+                        None => self.refs.contains_key(&name),
+                    };
+                    if exists {
+                        edits.insert(name.clone(), None);
                         ap.updated.push(Updated::Prune {
                             name: name.into_refstring(),
                         })
@@ -78,6 +186,12 @@ impl Refdb for Mem {
                 },
             }
         }
+        for (name, edit) in edits {
+            match edit {
+                Some(target) => self.refs.insert(name, target),
+                None => self.refs.remove(&name),
+            };
+        }
 
         Ok(ap)
     }
@@ -87,7 +201,7 @@ impl Refdb for Mem {
     }
 }
 
-impl<'a> RefScan for &'a Mem {
+impl<'a> RefScan for &'a Mem<'_> {
     type Oid = ObjectId;
     type Scan = Scan<'a, Self::Oid>;
     type Error = Void;
@@ -119,25 +233,15 @@ where
     fn next(&mut self) -> Option<Self::Item> {
         use either::Either::*;
 
-        let next = self.iter.next().and_then(|(k, v)| match &self.pref {
-            None => Some(refdb::Ref {
-                name: k.to_owned(),
-                target: Left(v.clone()),
-                peeled: v.clone(),
-            }),
-            Some(p) => {
-                if k.starts_with(p) {
-                    Some(refdb::Ref {
-                        name: k.to_owned(),
-                        target: Left(v.clone()),
-                        peeled: v.clone(),
-                    })
-                } else {
-                    None
-                }
-            },
-        });
-
-        next.map(Ok)
+        let pref = &self.pref;
+        self.iter
+            .find(|(k, _)| pref.as_ref().map_or(true, |p| k.starts_with(p)))
+            .map(|(k, v)| {
+                Ok(refdb::Ref {
+                    name: k.to_owned(),
+                    target: Left(v.clone()),
+                    peeled: v.clone(),
+                })
+            })
     }
 }
@@ -5,6 +5,7 @@
 
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
+    num::NonZeroUsize,
     ops::Deref,
 };
 
@@ -67,6 +68,55 @@ pub struct Flattened<Oid> {
     pub remotes: BTreeSet<PeerId>,
 }
 
+impl<T> Flattened<T> {
+    /// Split `self` into batches of at most `size` tracked peers each.
+    ///
+    /// The split is per peer: the [`Flattened::refs`] and the
+    /// [`Flattened::remotes`] entry of a peer end up in the same batch. There
+    /// is always at least one batch.
+    pub fn batches(self, size: Option<NonZeroUsize>) -> Vec<Self> {
+        let size = match size {
+            None => return vec![self],
+            Some(size) => size.get(),
+        };
+        let Self {
+            mut refs,
+            mut remotes,
+        } = self;
+        let peers = refs
+            .keys()
+            .chain(remotes.iter())
+            .copied()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let mut batches = peers
+            .chunks(size)
+            .map(|chunk| Self {
+                refs: chunk
+                    .iter()
+                    .filter_map(|peer| refs.remove_entry(peer))
+                    .collect(),
+                remotes: chunk.iter().filter_map(|peer| remotes.take(peer)).collect(),
+            })
+            .collect::<Vec<_>>();
+        if batches.is_empty() {
+            batches.push(Self::default())
+        }
+        batches
+    }
+
+    /// All peers of `self`, whether they have [`Flattened::refs`] or are
+    /// [`Flattened::remotes`].
+    pub fn peers(&self) -> BTreeSet<PeerId> {
+        self.refs
+            .keys()
+            .chain(self.remotes.iter())
+            .copied()
+            .collect()
+    }
+}
+
 impl<T> Default for Flattened<T> {
     fn default() -> Self {
         Self {
@@ -112,6 +162,15 @@ impl<Oid> Default for Combined<Oid> {
     }
 }
 
+impl<Oid> FromIterator<(PeerId, Sigrefs<Oid>)> for Combined<Oid> {
+    fn from_iter<T>(iter: T) -> Self
+    where
+        T: IntoIterator<Item = (PeerId, Sigrefs<Oid>)>,
+    {
+        Self(iter.into_iter().collect())
+    }
+}
+
 impl<Oid> From<Combined<Oid>> for Flattened<Oid> {
     fn from(a: Combined<Oid>) -> Self {
         a.flattened()
@@ -7,6 +7,7 @@ use std::collections::BTreeMap;
 
 use either::Either;
 use futures_lite::future::block_on;
+use futures_util::future::join_all;
 use git_ref_format::Qualified;
 use tracing::Instrument as _;
 
@@ -19,6 +20,7 @@ use crate::{
     refs,
     track,
     Applied,
+    FilteredRef,
     Identities,
     LocalPeer,
     Negotiation,
@@ -40,7 +42,7 @@ type DelegationTips<Urn> = BTreeMap<PeerId, BTreeMap<Urn, ObjectId>>;
 type SigrefTips = BTreeMap<PeerId, ObjectId>;
 
 pub(crate) struct FetchState<Urn> {
-    refs: refdb::Mem,
+    refs: refdb::Mem<'static>,
     idts: IdentityTips,
     dels: DelegationTips<Urn>,
     sigs: SigrefTips,
@@ -66,26 +68,54 @@ where
     U: ids::Urn + Ord,
 {
     pub fn step<C, S>(&mut self, cx: &mut C, step: &S) -> Result<(), error::Error>
+    where
+        C: Identities<Urn = U> + Net + Refdb + Odb,
+        for<'a> &'a C: RefScan,
+        S: Layout + Negotiation + UpdateTips + Send + Sync + 'static,
+    {
+        self.step_all(cx, std::slice::from_ref(step))?
+            .pop()
+            .expect("one result per step")
+    }
+
+    /// Like [`FetchState::step`], but for several independent `steps`, whose
+    /// `ls-refs` and `fetch` are run concurrently.
+    ///
+    /// The outer error is fatal, the inner ones pertain to the step at the
+    /// same index. The state is updated only with the results of the steps
+    /// whose transfer succeeded, in order. If updating the state fails
+    /// half-way, the caller should [`FetchState::discard`] the remotes of
+    /// the step.
+    pub fn step_all<C, S>(
+        &mut self,
+        cx: &mut C,
+        steps: &[S],
+    ) -> Result<Vec<Result<(), error::Error>>, error::Error>
     where
         C: Identities<Urn = U> + Net + Refdb + Odb,
         for<'a> &'a C: RefScan,
         S: Layout + Negotiation + UpdateTips + Send + Sync + 'static,
     {
         Refdb::reload(cx)?;
-        let refs = match step.ls_refs() {
-            None => Vec::default(),
-            Some(ls) => block_on(Net::run_ls_refs(cx, ls).in_current_span())?
-                .into_iter()
-                .filter_map(|r| step.ref_filter(r))
-                .collect::<Vec<_>>(),
-        };
-        Layout::pre_validate(step, &refs)?;
-        match step.wants_haves(cx, &refs)? {
-            Some((want, have)) => block_on(Net::run_fetch(cx, step.fetch_limit(), want, have))?,
-            None => info!("nothing to fetch"),
+        let transferred = {
+            let cx = &*cx;
+            block_on(join_all(steps.iter().map(|step| transfer(cx, step))).in_current_span())
         };
 
-        for r in &refs {
+        Ok(steps
+            .iter()
+            .zip(transferred)
+            .map(|(step, refs)| refs.and_then(|refs| self.apply(cx, step, &refs)))
+            .collect())
+    }
+
+    fn apply<C, S>(&mut self, cx: &C, step: &S, refs: &[FilteredRef<S>]) -> Result<(), error::Error>
+    where
+        C: Identities<Urn = U>,
+        for<'a> &'a C: RefScan,
+        S: UpdateTips,
+    {
+        for r in refs {
             if let Some(rad) = r.parsed.inner.as_ref().left() {
                 match rad {
                     refs::parsed::Rad::Id => {
@@ -110,7 +140,7 @@ where
             }
         }
 
-        let mut up = UpdateTips::prepare(step, self, cx, &refs)?;
+        let mut up = UpdateTips::prepare(step, self, cx, refs)?;
         self.trackings_mut().append(&mut up.track);
         self.update_all(up.tips.into_iter().map(|u| u.into_owned()));
 
@@ -118,6 +148,30 @@ where
     }
 }
 
+/// Run the `ls-refs` and `fetch` of `step`, returning the advertised refs it
+/// considers.
+async fn transfer<C, S>(cx: &C, step: &S) -> Result<Vec<FilteredRef<S>>, error::Error>
+where
+    C: Net + Refdb + Odb,
+    S: Layout + Negotiation,
+{
+    let refs = match step.ls_refs() {
+        None => Vec::default(),
+        Some(ls) => Net::run_ls_refs(cx, ls)
+            .await?
+            .into_iter()
+            .filter_map(|r| step.ref_filter(r))
+            .collect::<Vec<_>>(),
+    };
+    Layout::pre_validate(step, &refs)?;
+    match step.wants_haves(cx, &refs)? {
+        Some((want, have)) => Net::run_fetch(cx, step.fetch_limit(), want, have).await?,
+        None => info!("nothing to fetch"),
+    };
+
+    Ok(refs)
+}
+
 impl<Urn> FetchState<Urn>
 where
     Urn: Ord,
@@ -170,6 +224,19 @@ where
         &mut self.tips
     }
 
+    /// Forget the rad tips of `remotes`, eg. because they were fetched by a
+    /// step which failed.
+    pub fn discard<'a, I>(&mut self, remotes: I)
+    where
+        I: IntoIterator<Item = &'a PeerId>,
+    {
+        for remote in remotes {
+            self.idts.remove(remote);
+            self.dels.remove(remote);
+            self.sigs.remove(remote);
+        }
+    }
+
     pub fn clear_rad_refs(&mut self) {
         self.id_tips_mut().clear();
         self.delegation_tips_mut().clear();
@@ -194,11 +261,11 @@ where
     T: Refdb,
     U: Ord,
 {
-    type Oid = <refdb::Mem as Refdb>::Oid;
+    type Oid = <refdb::Mem<'static> as Refdb>::Oid;
 
     type FindError = <T as Refdb>::FindError;
-    type TxError = <refdb::Mem as Refdb>::TxError;
-    type ReloadError = <refdb::Mem as Refdb>::ReloadError;
+    type TxError = <refdb::Mem<'static> as Refdb>::TxError;
+    type ReloadError = <refdb::Mem<'static> as Refdb>::ReloadError;
 
     fn refname_to_id<'a, Q>(&self, refname: Q) -> Result<Option<Self::Oid>, Self::FindError>
     where
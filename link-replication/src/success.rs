@@ -7,7 +7,7 @@ use std::marker::PhantomData;
 
 use either::Either;
 
-use crate::{error, ids, Applied, PeerId, Update, Updated};
+use crate::{error, ids, ownership::Quarantined, Applied, PeerId, Update, Updated};
 
 #[derive(Debug)]
 pub struct Success<Urn> {
@@ -15,6 +15,8 @@ pub struct Success<Urn> {
     pub tracked: Vec<Either<PeerId, Urn>>,
     pub requires_confirmation: bool,
     pub validation: Vec<error::Validation>,
+    pub failed: Vec<error::Batch>,
+    pub quarantined: Vec<Quarantined>,
     pub(crate) _marker: PhantomData<Urn>,
 }
 
@@ -78,4 +80,20 @@ where
     pub fn validation_errors(&self) -> &[error::Validation] {
         &self.validation
     }
+
+    /// Batches of tracked peers which could not be fetched, or whose fetched
+    /// refs failed validation (see [`error::Staged`]).
+    ///
+    /// The refs of those peers were left as they were, and are not included
+    /// in [`Success::updated_refs`].
+    pub fn failed_batches(&self) -> &[error::Batch] {
+        &self.failed
+    }
+
+    /// Ref updates which have been withheld because the remote peer was not
+    /// entitled to make them, eg. refs claimed on behalf of another peer
+    /// without matching signed refs.
+    pub fn quarantined(&self) -> &[Quarantined] {
+        &self.quarantined
+    }
 }
@@ -3,4 +3,9 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
+mod batches;
+mod diff;
+mod fetchspecs;
+mod ownership;
+mod quarantine;
 mod refs;
@@ -0,0 +1,106 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    num::NonZeroUsize,
+};
+
+use link_crypto::{PeerId, SecretKey};
+use link_replication::{
+    peek::{self, FetchSpec},
+    sigrefs::{Flattened, Refs},
+    DataPolicy,
+    ObjectId,
+};
+
+fn peer(seed: u8) -> PeerId {
+    PeerId::from(SecretKey::from_seed([seed; 32]))
+}
+
+fn refs() -> Refs<ObjectId> {
+    Refs {
+        at: ObjectId::from_hex("00".repeat(20).as_bytes()).unwrap(),
+        refs: HashMap::new(),
+    }
+}
+
+fn size(n: usize) -> Option<NonZeroUsize> {
+    NonZeroUsize::new(n)
+}
+
+#[test]
+fn flattened_keeps_peers_together() {
+    let (a, b, c, d) = (peer(1), peer(2), peer(3), peer(4));
+    let flattened = Flattened {
+        refs: vec![(a, refs()), (b, refs()), (c, refs())]
+            .into_iter()
+            .collect(),
+        remotes: vec![b, d].into_iter().collect(),
+    };
+
+    let batches = flattened.batches(size(2));
+    assert_eq!(batches.len(), 2);
+    for batch in &batches {
+        assert!(batch.peers().len() <= 2);
+        for remote in &batch.remotes {
+            assert!(
+                batch.refs.contains_key(remote) || *remote == d,
+                "refs of {} are in another batch",
+                remote
+            );
+        }
+    }
+    let peers = batches
+        .iter()
+        .flat_map(|batch| batch.peers())
+        .collect::<Vec<_>>();
+    assert_eq!(peers.len(), 4, "every peer is in exactly one batch");
+    assert_eq!(
+        peers.into_iter().collect::<BTreeSet<_>>(),
+        vec![a, b, c, d].into_iter().collect()
+    );
+}
+
+#[test]
+fn flattened_unbatched() {
+    let flattened = Flattened {
+        refs: vec![(peer(1), refs())].into_iter().collect(),
+        remotes: vec![peer(2)].into_iter().collect(),
+    };
+    let batches = flattened.batches(None);
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].peers().len(), 2);
+
+    assert_eq!(Flattened::<ObjectId>::default().batches(size(2)).len(), 1);
+}
+
+#[test]
+fn tracked_delegates_first() {
+    let spec = |is_delegate| FetchSpec {
+        is_delegate,
+        policy: DataPolicy::Allow,
+    };
+    let tracked = (1..=5)
+        .map(|seed| (peer(seed), spec(seed % 2 == 0)))
+        .collect::<BTreeMap<_, _>>();
+    let delegates = tracked
+        .iter()
+        .filter(|(_, spec)| spec.is_delegate)
+        .map(|(id, _)| *id)
+        .collect::<BTreeSet<_>>();
+
+    let batches = peek::batches(tracked, size(2));
+    assert_eq!(batches.len(), 3);
+    assert_eq!(
+        batches[0].keys().copied().collect::<BTreeSet<_>>(),
+        delegates
+    );
+    assert!(batches[1..]
+        .iter()
+        .all(|batch| batch.values().all(|spec| !spec.is_delegate)));
+
+    assert_eq!(peek::batches(BTreeMap::new(), size(2)).len(), 1);
+}
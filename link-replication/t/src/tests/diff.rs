@@ -0,0 +1,348 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    convert::Infallible,
+    path::Path,
+};
+
+use git_ref_format::{refname, Component, Qualified, RefString};
+use link_crypto::{PeerId, SecretKey};
+use link_git::protocol::Ref;
+use link_replication::{
+    diff::{self, Change, Forward, RefChange},
+    odb,
+    oid,
+    refdb::{mem, Mem},
+    Applied,
+    LocalPeer,
+    ObjectId,
+    Odb,
+    RefScan,
+    Refdb,
+    Update,
+};
+use once_cell::sync::Lazy;
+
+static PEER: Lazy<PeerId> = Lazy::new(|| PeerId::from(SecretKey::from_seed([1; 32])));
+static OTHER: Lazy<PeerId> = Lazy::new(|| PeerId::from(SecretKey::from_seed([2; 32])));
+static LOCAL: Lazy<PeerId> = Lazy::new(|| PeerId::from(SecretKey::from_seed([3; 32])));
+
+fn tip(n: u8) -> ObjectId {
+    ObjectId::from_hex(format!("{:02x}", n).repeat(20).as_bytes()).unwrap()
+}
+
+fn tracking(peer: &PeerId, name: &str) -> Qualified<'static> {
+    refname!("refs/remotes")
+        .join(Component::from(peer))
+        .join(RefString::try_from(name).unwrap())
+        .into_qualified()
+        .unwrap()
+}
+
+fn advertised(name: &str, object: ObjectId) -> Ref {
+    Ref::Direct {
+        path: name.into(),
+        object,
+    }
+}
+
+/// Local state: the refs in a [`Mem`], the objects present, and which commits
+/// are ancestors of which.
+#[derive(Default)]
+struct Local {
+    refs: Mem<'static>,
+    objects: BTreeSet<ObjectId>,
+    ancestry: BTreeSet<(ObjectId, ObjectId)>,
+}
+
+impl Local {
+    fn with_refs<I>(refs: I) -> Self
+    where
+        I: IntoIterator<Item = (Qualified<'static>, ObjectId)>,
+    {
+        Self {
+            refs: Mem::from(refs.into_iter().collect::<HashMap<_, _>>()),
+            ..Default::default()
+        }
+    }
+
+    /// Mark `old` as an ancestor of `new`, and both as present.
+    fn ancestor(mut self, new: ObjectId, old: ObjectId) -> Self {
+        self.objects.insert(new);
+        self.objects.insert(old);
+        self.ancestry.insert((new, old));
+        self
+    }
+
+    fn object(mut self, oid: ObjectId) -> Self {
+        self.objects.insert(oid);
+        self
+    }
+}
+
+impl LocalPeer for Local {
+    fn id(&self) -> &PeerId {
+        &LOCAL
+    }
+}
+
+impl Refdb for Local {
+    type Oid = ObjectId;
+
+    type FindError = Infallible;
+    type TxError = mem::error::Tx;
+    type ReloadError = Infallible;
+
+    fn refname_to_id<'a, Q>(&self, refname: Q) -> Result<Option<Self::Oid>, Self::FindError>
+    where
+        Q: AsRef<Qualified<'a>>,
+    {
+        self.refs.refname_to_id(refname)
+    }
+
+    fn update<'a, I>(&mut self, updates: I) -> Result<Applied<'a>, Self::TxError>
+    where
+        I: IntoIterator<Item = Update<'a>>,
+    {
+        self.refs.update(updates)
+    }
+
+    fn reload(&mut self) -> Result<(), Self::ReloadError> {
+        Ok(())
+    }
+}
+
+impl<'a> RefScan for &'a Local {
+    type Oid = ObjectId;
+    type Scan = <&'a Mem as RefScan>::Scan;
+    type Error = Infallible;
+
+    fn scan<O, P>(self, prefix: O) -> Result<Self::Scan, Self::Error>
+    where
+        O: Into<Option<P>>,
+        P: AsRef<str>,
+    {
+        self.refs.scan(prefix)
+    }
+}
+
+impl Odb for Local {
+    type LookupError = Infallible;
+    type RevwalkError = Infallible;
+    type AddPackError = Infallible;
+
+    fn contains(&self, oid: impl AsRef<oid>) -> bool {
+        self.objects.contains(&oid.as_ref().to_owned())
+    }
+
+    fn lookup<'a>(
+        &self,
+        _: impl AsRef<oid>,
+        _: &'a mut Vec<u8>,
+    ) -> Result<Option<odb::Object<'a>>, Self::LookupError> {
+        Ok(None)
+    }
+
+    fn is_in_ancestry_path(
+        &self,
+        new: impl Into<ObjectId>,
+        old: impl Into<ObjectId>,
+    ) -> Result<bool, Self::RevwalkError> {
+        Ok(self.ancestry.contains(&(new.into(), old.into())))
+    }
+
+    fn add_pack(&self, _: impl AsRef<Path>) -> Result<(), Self::AddPackError> {
+        Ok(())
+    }
+}
+
+fn sigrefs(peer: &PeerId) -> (Qualified<'static>, ObjectId) {
+    (tracking(peer, "rad/signed_refs"), tip(0xff))
+}
+
+fn change<'a>(diff: &'a diff::Diff, name: &Qualified) -> Option<&'a RefChange> {
+    diff.changes.iter().find(|c| &c.name == name)
+}
+
+#[test]
+fn added() {
+    let local = Local::default();
+    let diff = diff::diff(
+        &local,
+        *PEER,
+        vec![
+            advertised("refs/heads/main", tip(1)),
+            advertised("refs/rad/signed_refs", tip(2)),
+        ],
+    )
+    .unwrap();
+
+    let main = change(&diff, &tracking(&PEER, "heads/main")).unwrap();
+    assert_eq!(main.remote_id, *PEER);
+    assert_eq!(main.change, Change::Created { target: tip(1) });
+    assert_eq!(diff.created().count(), 2);
+    assert!(diff.unchanged.is_empty());
+    assert_eq!(diff.new_peers, BTreeSet::from([*PEER]));
+}
+
+#[test]
+fn unchanged() {
+    let local = Local::with_refs(vec![
+        (tracking(&PEER, "heads/main"), tip(1)),
+        sigrefs(&PEER),
+    ]);
+    let diff = diff::diff(
+        &local,
+        *PEER,
+        vec![
+            advertised("refs/heads/main", tip(1)),
+            advertised("refs/rad/signed_refs", tip(0xff)),
+        ],
+    )
+    .unwrap();
+
+    assert!(diff.is_empty());
+    assert_eq!(diff.unchanged.len(), 2);
+    assert!(diff.new_peers.is_empty());
+}
+
+#[test]
+fn updated_fast_forward() {
+    let local = Local::with_refs(vec![
+        (tracking(&PEER, "heads/main"), tip(1)),
+        sigrefs(&PEER),
+    ])
+    .ancestor(tip(2), tip(1));
+    let diff = diff::diff(
+        &local,
+        *PEER,
+        vec![
+            advertised("refs/heads/main", tip(2)),
+            advertised("refs/rad/signed_refs", tip(0xff)),
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(
+        change(&diff, &tracking(&PEER, "heads/main"))
+            .unwrap()
+            .change,
+        Change::Updated {
+            prev: tip(1),
+            target: tip(2),
+            forward: Forward::FastForward
+        }
+    );
+    assert_eq!(diff.forced().count(), 0);
+}
+
+#[test]
+fn updated_non_fast_forward() {
+    let local = Local::with_refs(vec![
+        (tracking(&PEER, "heads/main"), tip(1)),
+        sigrefs(&PEER),
+    ])
+    .object(tip(1))
+    .object(tip(2));
+    let diff = diff::diff(
+        &local,
+        *PEER,
+        vec![
+            advertised("refs/heads/main", tip(2)),
+            advertised("refs/rad/signed_refs", tip(0xff)),
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(
+        change(&diff, &tracking(&PEER, "heads/main"))
+            .unwrap()
+            .change,
+        Change::Updated {
+            prev: tip(1),
+            target: tip(2),
+            forward: Forward::Forced
+        }
+    );
+    assert_eq!(diff.forced().count(), 1);
+}
+
+#[test]
+fn updated_unknown_target() {
+    let local = Local::with_refs(vec![
+        (tracking(&PEER, "heads/main"), tip(1)),
+        sigrefs(&PEER),
+    ]);
+    let diff = diff::diff(
+        &local,
+        *PEER,
+        vec![
+            advertised("refs/heads/main", tip(2)),
+            advertised("refs/rad/signed_refs", tip(0xff)),
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(
+        change(&diff, &tracking(&PEER, "heads/main"))
+            .unwrap()
+            .change,
+        Change::Updated {
+            prev: tip(1),
+            target: tip(2),
+            forward: Forward::Unknown
+        }
+    );
+    assert_eq!(diff.forced().count(), 0);
+}
+
+#[test]
+fn removed() {
+    let local = Local::with_refs(vec![
+        (tracking(&PEER, "heads/main"), tip(1)),
+        (tracking(&PEER, "heads/gone"), tip(3)),
+        (tracking(&PEER, "rad/id"), tip(4)),
+        sigrefs(&PEER),
+    ]);
+    let diff = diff::diff(
+        &local,
+        *PEER,
+        vec![
+            advertised("refs/heads/main", tip(1)),
+            advertised("refs/rad/signed_refs", tip(0xff)),
+        ],
+    )
+    .unwrap();
+
+    let removed = diff.removed().collect::<Vec<_>>();
+    assert_eq!(
+        removed.len(),
+        1,
+        "rad/ refs must not be reported as removed"
+    );
+    assert_eq!(removed[0].name, tracking(&PEER, "heads/gone"));
+    assert_eq!(removed[0].change, Change::Removed { prev: tip(3) });
+}
+
+#[test]
+fn scoped_and_local_refs() {
+    let local = Local::with_refs(vec![sigrefs(&OTHER)]);
+    let diff = diff::diff(
+        &local,
+        *PEER,
+        vec![
+            advertised(&format!("refs/remotes/{}/heads/main", *OTHER), tip(1)),
+            advertised(&format!("refs/remotes/{}/heads/main", *LOCAL), tip(2)),
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(diff.changes.len(), 1);
+    let main = change(&diff, &tracking(&OTHER, "heads/main")).unwrap();
+    assert_eq!(main.remote_id, *OTHER);
+    assert!(diff.new_peers.is_empty());
+}
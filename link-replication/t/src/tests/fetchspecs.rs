@@ -0,0 +1,207 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::Infallible,
+};
+
+use git_ref_format::RefString;
+use link_crypto::{PeerId, SecretKey};
+use link_replication::{
+    error,
+    fetch::Builder,
+    sigrefs::Combined,
+    ObjectId,
+    SignedRefs,
+    Sigrefs,
+    Urn,
+    VerifiedIdentity,
+};
+use radicle_data::NonEmpty;
+
+fn peer(seed: u8) -> PeerId {
+    PeerId::from(SecretKey::from_seed([seed; 32]))
+}
+
+fn oid(byte: u8) -> ObjectId {
+    ObjectId::from_hex(format!("{:02x}", byte).repeat(20).as_bytes()).unwrap()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Id(String);
+
+impl Urn for Id {
+    type Error = Infallible;
+
+    fn try_from_id(s: impl AsRef<str>) -> Result<Self, Self::Error> {
+        Ok(Self(s.as_ref().to_owned()))
+    }
+
+    fn encode_id(&self) -> String {
+        self.0.clone()
+    }
+}
+
+struct Project {
+    urn: Id,
+    delegates: BTreeSet<PeerId>,
+    delegate_urns: BTreeSet<Id>,
+}
+
+impl Project {
+    fn new(delegates: impl IntoIterator<Item = PeerId>) -> Self {
+        Self {
+            urn: Id("project".to_owned()),
+            delegates: delegates.into_iter().collect(),
+            delegate_urns: BTreeSet::new(),
+        }
+    }
+}
+
+impl VerifiedIdentity for Project {
+    type Rev = ObjectId;
+    type Oid = ObjectId;
+    type Urn = Id;
+
+    fn revision(&self) -> Self::Rev {
+        oid(0)
+    }
+
+    fn content_id(&self) -> Self::Oid {
+        oid(0)
+    }
+
+    fn urn(&self) -> Self::Urn {
+        self.urn.clone()
+    }
+
+    fn delegate_ids(&self) -> NonEmpty<BTreeSet<PeerId>> {
+        NonEmpty::from_maybe_empty(self.delegates.clone()).unwrap()
+    }
+
+    fn delegate_urns(&self) -> BTreeSet<Self::Urn> {
+        self.delegate_urns.clone()
+    }
+}
+
+fn combined(store: &Store, peers: impl IntoIterator<Item = PeerId>) -> Combined<ObjectId> {
+    peers
+        .into_iter()
+        .map(|id| (id, store.load(&id, 0).unwrap().unwrap()))
+        .collect()
+}
+
+/// Signed refs per peer, each signing the given ref names.
+struct Store(BTreeMap<PeerId, Vec<&'static str>>);
+
+impl SignedRefs for Store {
+    type Oid = ObjectId;
+    type Error = Infallible;
+
+    fn load(&self, of: &PeerId, _: usize) -> Result<Option<Sigrefs<Self::Oid>>, Self::Error> {
+        Ok(self.0.get(of).map(|names| Sigrefs {
+            at: oid(0),
+            refs: names
+                .iter()
+                .map(|name| (RefString::try_from(*name).unwrap(), oid(1)))
+                .collect(),
+            remotes: BTreeSet::new(),
+        }))
+    }
+
+    fn load_at(
+        &self,
+        _: impl Into<ObjectId>,
+        of: &PeerId,
+        cutoff: usize,
+    ) -> Result<Option<Sigrefs<Self::Oid>>, Self::Error> {
+        self.load(of, cutoff)
+    }
+
+    fn update(&self) -> Result<Option<Self::Oid>, Self::Error> {
+        Ok(None)
+    }
+}
+
+#[test]
+fn builds_batches() {
+    let (local, remote, delegate, tracked) = (peer(0), peer(1), peer(2), peer(3));
+    let store = Store(BTreeMap::from([
+        (delegate, vec!["refs/heads/main"]),
+        (tracked, vec!["refs/heads/main", "refs/heads/next"]),
+        (peer(4), vec!["refs/heads/main"]),
+    ]));
+
+    let fetches = Builder::new(local, remote, 1024)
+        .batch_size(Some(1.try_into().unwrap()))
+        .delegates(&Project::new([local, delegate]))
+        .unwrap()
+        .tracked([tracked, peer(5)])
+        .load(&store, 0)
+        .unwrap()
+        .build()
+        .unwrap();
+
+    let peers = fetches
+        .iter()
+        .flat_map(|fetch| fetch.signed_refs.refs.keys().copied())
+        .collect::<Vec<_>>();
+    assert_eq!(fetches.len(), 2);
+    assert_eq!(
+        peers.into_iter().collect::<BTreeSet<_>>(),
+        BTreeSet::from([delegate, tracked])
+    );
+    assert!(fetches.iter().all(|fetch| fetch.limit == 1024));
+}
+
+#[test]
+fn missing_delegate() {
+    let store = Store(BTreeMap::from([(peer(3), vec!["refs/heads/main"])]));
+    let res = Builder::new(peer(0), peer(1), 1024)
+        .delegates(&Project::new([peer(2)]))
+        .unwrap()
+        .tracked([peer(3)])
+        .load(&store, 0);
+    assert!(matches!(res, Err(error::Fetchspecs::MissingSigrefs(id)) if id == peer(2)));
+}
+
+#[test]
+fn self_delegation() {
+    let mut project = Project::new([peer(2)]);
+    project.delegate_urns.insert(project.urn.clone());
+    let res = Builder::<ObjectId>::new(peer(0), peer(1), 1024).delegates(&project);
+    assert!(matches!(res, Err(error::Fetchspecs::SelfDelegation(urn)) if urn == "project"));
+}
+
+#[test]
+fn untracked() {
+    let store = Store(BTreeMap::from([
+        (peer(2), vec!["refs/heads/main"]),
+        (peer(3), vec!["refs/heads/main"]),
+    ]));
+    let res = Builder::new(peer(0), peer(1), 1024)
+        .delegates(&Project::new([peer(2)]))
+        .unwrap()
+        .with_signed_refs(combined(&store, [peer(2), peer(3)]))
+        .build();
+    assert!(matches!(res, Err(error::Fetchspecs::Untracked(id)) if id == peer(3)));
+}
+
+#[test]
+fn malformed() {
+    let store = Store(BTreeMap::from([(peer(2), vec!["main"])]));
+    let res = Builder::new(peer(0), peer(1), 1024)
+        .delegates(&Project::new([peer(2)]))
+        .unwrap()
+        .load(&store, 0)
+        .unwrap()
+        .build();
+    assert!(matches!(
+        res,
+        Err(error::Fetchspecs::Malformed { remote, name })
+            if remote == peer(2) && name.as_str() == "main"
+    ));
+}
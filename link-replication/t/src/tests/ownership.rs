@@ -0,0 +1,195 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    convert::Infallible,
+};
+
+use git_ref_format::{Qualified, RefString};
+use link_crypto::{PeerId, SecretKey};
+use link_replication::{
+    error,
+    ownership,
+    refs::parsed::Identity,
+    sigrefs::Refs,
+    ObjectId,
+    Policy,
+    SignedRefs,
+    Sigrefs,
+    Update,
+};
+
+fn peer(seed: u8) -> PeerId {
+    PeerId::from(SecretKey::from_seed([seed; 32]))
+}
+
+fn oid(byte: u8) -> ObjectId {
+    ObjectId::from_hex(format!("{:02x}", byte).repeat(20).as_bytes()).unwrap()
+}
+
+fn refname(name: &str) -> RefString {
+    RefString::try_from(name).unwrap()
+}
+
+fn direct(name: &str, target: ObjectId) -> Update<'static> {
+    Update::Direct {
+        name: Qualified::from_refstr(refname(name)).unwrap(),
+        target,
+        no_ff: Policy::Abort,
+    }
+}
+
+fn signed(refs: &[(&str, ObjectId)]) -> Refs<ObjectId> {
+    Refs {
+        at: oid(0),
+        refs: refs
+            .iter()
+            .map(|(name, oid)| (refname(name), *oid))
+            .collect(),
+    }
+}
+
+/// Valid `rad/signed_refs` of a peer exist only at the given commits.
+struct Commits(BTreeMap<ObjectId, PeerId>);
+
+impl SignedRefs for Commits {
+    type Oid = ObjectId;
+    type Error = Infallible;
+
+    fn load(&self, _: &PeerId, _: usize) -> Result<Option<Sigrefs<Self::Oid>>, Self::Error> {
+        Ok(None)
+    }
+
+    fn load_at(
+        &self,
+        treeish: impl Into<ObjectId>,
+        of: &PeerId,
+        _: usize,
+    ) -> Result<Option<Sigrefs<Self::Oid>>, Self::Error> {
+        let at = treeish.into();
+        Ok((self.0.get(&at) == Some(of)).then(|| Sigrefs {
+            at,
+            refs: HashMap::new(),
+            remotes: BTreeSet::new(),
+        }))
+    }
+
+    fn update(&self) -> Result<Option<Self::Oid>, Self::Error> {
+        Ok(None)
+    }
+}
+
+fn verify(
+    cx: &Commits,
+    local: &PeerId,
+    signed: &BTreeMap<PeerId, Refs<ObjectId>>,
+    updates: Vec<Update<'static>>,
+) -> (Vec<String>, Vec<error::Ownership>) {
+    let (applicable, quarantined) = ownership::verify::<Identity, _, _>(cx, local, signed, updates);
+    (
+        applicable
+            .iter()
+            .map(|up| up.refname().as_str().to_owned())
+            .collect(),
+        quarantined.into_iter().map(|q| q.reason).collect(),
+    )
+}
+
+#[test]
+fn owned() {
+    let (local, remote) = (peer(1), peer(2));
+    let cx = Commits(vec![(oid(10), remote)].into_iter().collect());
+    let signed = vec![(remote, signed(&[("refs/heads/main", oid(1))]))]
+        .into_iter()
+        .collect();
+
+    let main = format!("refs/remotes/{}/heads/main", remote);
+    let sigrefs = format!("refs/remotes/{}/rad/signed_refs", remote);
+    let (applicable, quarantined) = verify(
+        &cx,
+        &local,
+        &signed,
+        vec![
+            direct(&main, oid(1)),
+            direct(&sigrefs, oid(10)),
+            direct("refs/rad/id", oid(3)),
+        ],
+    );
+    assert_eq!(applicable, vec![main, sigrefs, "refs/rad/id".to_owned()]);
+    assert!(quarantined.is_empty(), "{:?}", quarantined);
+}
+
+#[test]
+fn delegated() {
+    let (local, remote, delegate) = (peer(1), peer(2), peer(3));
+    let cx = Commits(vec![(oid(10), delegate)].into_iter().collect());
+    let signed = vec![
+        (remote, signed(&[])),
+        (delegate, signed(&[("refs/heads/main", oid(1))])),
+    ]
+    .into_iter()
+    .collect();
+
+    // Claimed by `remote` on behalf of `delegate`, signed by the latter
+    let main = format!("refs/remotes/{}/heads/main", delegate);
+    let sigrefs = format!("refs/remotes/{}/rad/signed_refs", delegate);
+    let (applicable, quarantined) = verify(
+        &cx,
+        &local,
+        &signed,
+        vec![direct(&main, oid(1)), direct(&sigrefs, oid(10))],
+    );
+    assert_eq!(applicable, vec![main.clone(), sigrefs.clone()]);
+    assert!(quarantined.is_empty(), "{:?}", quarantined);
+
+    // Diverging from what `delegate` signed
+    let (applicable, quarantined) = verify(
+        &cx,
+        &local,
+        &signed,
+        vec![direct(&main, oid(2)), direct(&sigrefs, oid(11))],
+    );
+    assert!(applicable.is_empty(), "{:?}", applicable);
+    assert!(matches!(
+        quarantined[..],
+        [
+            error::Ownership::Unsigned { remote: a, .. },
+            error::Ownership::Sigrefs { remote: b, .. },
+        ] if a == delegate && b == delegate
+    ));
+}
+
+#[test]
+fn foreign() {
+    let (local, remote, stranger) = (peer(1), peer(2), peer(4));
+    let cx = Commits(BTreeMap::new());
+    let signed = vec![(remote, signed(&[("refs/heads/main", oid(1))]))]
+        .into_iter()
+        .collect();
+
+    let (applicable, quarantined) = verify(
+        &cx,
+        &local,
+        &signed,
+        vec![
+            // Nobody signed for `stranger`
+            direct(&format!("refs/remotes/{}/heads/main", stranger), oid(1)),
+            // Refs of the local peer are never written to
+            direct(&format!("refs/remotes/{}/heads/main", local), oid(1)),
+            // Not in the `rad/` hierarchy
+            direct("refs/heads/main", oid(1)),
+        ],
+    );
+    assert!(applicable.is_empty(), "{:?}", applicable);
+    assert!(matches!(
+        quarantined[..],
+        [
+            error::Ownership::Unsigned { remote: a, .. },
+            error::Ownership::Local(_),
+            error::Ownership::Layout(_),
+        ] if a == stranger
+    ));
+}
@@ -0,0 +1,253 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    convert::Infallible,
+    path::Path,
+};
+
+use git_ref_format::{refname, Component, Qualified, RefString};
+use link_crypto::{PeerId, SecretKey};
+use link_replication::{
+    error,
+    odb,
+    oid,
+    quarantine::{self, Checked},
+    refdb::{mem, Mem},
+    refs::parsed::Identity,
+    sigrefs::Refs,
+    ObjectId,
+    Odb,
+    Policy,
+    RefScan,
+    Refdb as _,
+    Update,
+};
+
+fn peer(seed: u8) -> PeerId {
+    PeerId::from(SecretKey::from_seed([seed; 32]))
+}
+
+fn tip(n: u8) -> ObjectId {
+    ObjectId::from_hex(format!("{:02x}", n).repeat(20).as_bytes()).unwrap()
+}
+
+fn tracking(peer: &PeerId, name: &str) -> Qualified<'static> {
+    refname!("refs/remotes")
+        .join(Component::from(peer))
+        .join(RefString::try_from(name).unwrap())
+        .into_qualified()
+        .unwrap()
+}
+
+fn direct(name: Qualified<'static>, target: ObjectId, no_ff: Policy) -> Update<'static> {
+    Update::Direct {
+        name,
+        target,
+        no_ff,
+    }
+}
+
+/// The signed refs of a peer with a single branch `main` at `main`.
+fn signed(main: ObjectId) -> Refs<ObjectId> {
+    Refs {
+        at: tip(0xff),
+        refs: vec![(refname!("refs/heads/main"), main)]
+            .into_iter()
+            .collect(),
+    }
+}
+
+/// The updates of a peer whose tree matches [`signed`].
+fn tree(peer: &PeerId, main: ObjectId) -> Vec<Update<'static>> {
+    vec![
+        direct(tracking(peer, "rad/id"), tip(0xaa), Policy::Abort),
+        direct(tracking(peer, "rad/signed_refs"), tip(0xff), Policy::Abort),
+        direct(tracking(peer, "heads/main"), main, Policy::Abort),
+    ]
+}
+
+/// Local state: the refs in a [`Mem`], and which commits are ancestors of
+/// which.
+#[derive(Default)]
+struct Local {
+    refs: Mem<'static>,
+    ancestry: BTreeSet<(ObjectId, ObjectId)>,
+}
+
+impl Local {
+    fn with_refs<I>(refs: I) -> Self
+    where
+        I: IntoIterator<Item = (Qualified<'static>, ObjectId)>,
+    {
+        Self {
+            refs: Mem::from(refs.into_iter().collect::<HashMap<_, _>>()),
+            ..Default::default()
+        }
+    }
+
+    fn tip(&self, name: &Qualified) -> Option<ObjectId> {
+        self.refs.refname_to_id(name).unwrap()
+    }
+}
+
+impl<'a> RefScan for &'a Local {
+    type Oid = ObjectId;
+    type Scan = <&'a Mem<'static> as RefScan>::Scan;
+    type Error = Infallible;
+
+    fn scan<O, P>(self, prefix: O) -> Result<Self::Scan, Self::Error>
+    where
+        O: Into<Option<P>>,
+        P: AsRef<str>,
+    {
+        self.refs.scan(prefix)
+    }
+}
+
+impl Odb for Local {
+    type LookupError = Infallible;
+    type RevwalkError = Infallible;
+    type AddPackError = Infallible;
+
+    fn contains(&self, _: impl AsRef<oid>) -> bool {
+        true
+    }
+
+    fn lookup<'a>(
+        &self,
+        _: impl AsRef<oid>,
+        _: &'a mut Vec<u8>,
+    ) -> Result<Option<odb::Object<'a>>, Self::LookupError> {
+        Ok(None)
+    }
+
+    fn is_in_ancestry_path(
+        &self,
+        new: impl Into<ObjectId>,
+        old: impl Into<ObjectId>,
+    ) -> Result<bool, Self::RevwalkError> {
+        Ok(self.ancestry.contains(&(new.into(), old.into())))
+    }
+
+    fn add_pack(&self, _: impl AsRef<Path>) -> Result<(), Self::AddPackError> {
+        Ok(())
+    }
+}
+
+fn check(
+    local: &Local,
+    signed: &BTreeMap<PeerId, Refs<ObjectId>>,
+    updates: Vec<Update<'static>>,
+) -> Result<Checked, error::Error> {
+    quarantine::check::<Identity, _, _, _>(local, local, signed, updates)
+}
+
+#[test]
+fn discards_invalid_peer() {
+    let (good, bad) = (peer(1), peer(2));
+    let mut local = Local::with_refs(vec![
+        (tracking(&bad, "rad/id"), tip(0xaa)),
+        (tracking(&bad, "rad/signed_refs"), tip(0xfe)),
+        (tracking(&bad, "heads/main"), tip(2)),
+    ]);
+    local.ancestry.insert((tip(4), tip(2)));
+    local.ancestry.insert((tip(0xff), tip(0xfe)));
+    let signed = vec![(good, signed(tip(1))), (bad, signed(tip(3)))]
+        .into_iter()
+        .collect();
+
+    // `bad` signed `main` at 3, but sends 4
+    let mut updates = tree(&good, tip(1));
+    updates.extend(vec![
+        direct(tracking(&bad, "rad/signed_refs"), tip(0xff), Policy::Abort),
+        direct(tracking(&bad, "heads/main"), tip(4), Policy::Abort),
+    ]);
+    let Checked { updates, discarded } = check(&local, &signed, updates).unwrap();
+    assert!(matches!(
+        discarded[..],
+        [error::Staged { remote, ref errors }]
+            if remote == bad && matches!(errors[..], [error::Validation::MismatchedTips { .. }])
+    ));
+    assert_eq!(updates.len(), 3);
+
+    local.refs.update(updates).unwrap();
+    assert_eq!(local.tip(&tracking(&good, "heads/main")), Some(tip(1)));
+    assert_eq!(local.tip(&tracking(&good, "rad/id")), Some(tip(0xaa)));
+    assert_eq!(local.tip(&tracking(&bad, "heads/main")), Some(tip(2)));
+    assert_eq!(
+        local.tip(&tracking(&bad, "rad/signed_refs")),
+        Some(tip(0xfe))
+    );
+}
+
+#[test]
+fn missing_identity() {
+    let remote = peer(1);
+    let local = Local::default();
+    let signed = vec![(remote, signed(tip(1)))].into_iter().collect();
+
+    // Without `rad/id`, nothing of `remote` may be promoted
+    let updates = tree(&remote, tip(1)).into_iter().skip(1).collect();
+    let Checked { updates, discarded } = check(&local, &signed, updates).unwrap();
+    assert!(updates.is_empty(), "{:?}", updates);
+    assert!(matches!(
+        discarded[..],
+        [error::Staged { ref errors, .. }]
+            if matches!(errors[..], [error::Validation::MissingRadId(_)])
+    ));
+}
+
+#[test]
+fn non_fast_forward_aborts() {
+    let remote = peer(1);
+    let local = Local::with_refs(vec![(tracking(&remote, "heads/main"), tip(2))]);
+    let signed = vec![(remote, signed(tip(1)))].into_iter().collect();
+
+    // 1 is not a descendant of 2
+    assert!(check(&local, &signed, tree(&remote, tip(1))).is_err());
+    assert_eq!(local.tip(&tracking(&remote, "heads/main")), Some(tip(2)));
+}
+
+#[test]
+fn mem_honours_no_ff() {
+    let main = tracking(&peer(1), "heads/main");
+    let other = tracking(&peer(1), "heads/other");
+    let mem = || {
+        Mem::from(
+            vec![(main.clone(), tip(1))]
+                .into_iter()
+                .collect::<HashMap<_, _>>(),
+        )
+        .with_ancestry(|new, old| Ok(new == tip(2) && old == tip(1)))
+    };
+
+    let mut refs = mem();
+    let res = refs.update(vec![
+        direct(other.clone(), tip(5), Policy::Abort),
+        direct(main.clone(), tip(3), Policy::Abort),
+    ]);
+    assert!(matches!(res, Err(mem::error::Tx::NonFF { .. })));
+    assert_eq!(refs.refname_to_id(&other).unwrap(), None);
+    assert_eq!(refs.refname_to_id(&main).unwrap(), Some(tip(1)));
+
+    let mut refs = mem();
+    let applied = refs
+        .update(vec![direct(main.clone(), tip(3), Policy::Reject)])
+        .unwrap();
+    assert_eq!(applied.rejected.len(), 1);
+    assert_eq!(refs.refname_to_id(&main).unwrap(), Some(tip(1)));
+
+    let mut refs = mem();
+    refs.update(vec![direct(main.clone(), tip(3), Policy::Allow)])
+        .unwrap();
+    assert_eq!(refs.refname_to_id(&main).unwrap(), Some(tip(3)));
+
+    let mut refs = mem();
+    refs.update(vec![direct(main.clone(), tip(2), Policy::Abort)])
+        .unwrap();
+    assert_eq!(refs.refname_to_id(&main).unwrap(), Some(tip(2)));
+}
@@ -3,12 +3,13 @@
 // This file is part of radicle-link, distributed under the GPLv3 with Radicle
 // Linking Exception. For full terms see the included LICENSE file.
 
-use std::{convert::TryFrom, str::FromStr};
+use std::{collections::BTreeSet, convert::TryFrom, str::FromStr};
 
+use git_ref_format::refspec::PatternString;
 use thiserror::Error;
 
 use link_canonical::{
-    json::{ToCjson, Value},
+    json::{Number, ToCjson, Value},
     Canonical,
     Cstring,
 };
@@ -19,6 +20,8 @@ pub use cobs::{Cobs, Pattern, TypeName};
 
 const COBS: &str = "cobs";
 const DATA: &str = "data";
+const REFS: &str = "refs";
+const EXPIRES: &str = "expires";
 
 /// Configuration to act as a set of filters for non-`rad` references.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -30,15 +33,44 @@ pub struct Config<Typename, ObjectId: Ord> {
     /// Filter collaborative objects based on their type name, object
     /// identifier, and a filtering policy.
     pub cobs: Cobs<Typename, ObjectId>,
+    /// Further restrict the data-refs to those matching any of the given
+    /// patterns, eg. `refs/heads/main` or `refs/heads/release/*`. `None`
+    /// allows all data-refs.
+    pub refs: Option<BTreeSet<PatternString>>,
+    /// Seconds since the Unix epoch after which this entry is no longer in
+    /// effect, cf. [`Config::is_expired`]. `None` means it never expires.
+    pub expires: Option<u64>,
+}
+
+impl<Ty: Ord, Id: Ord> Config<Ty, Id> {
+    /// Whether this entry has expired as of `now`, given in seconds since the
+    /// Unix epoch.
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.map(|expires| expires <= now).unwrap_or(false)
+    }
 }
 
 impl<Ty: Into<Cstring> + Ord, Id: ToCjson + Ord> ToCjson for Config<Ty, Id> {
     fn into_cjson(self) -> Value {
+        // `refs` and `expires` are omitted if unset, so as to not change the
+        // canonical form of existing configs.
+        let refs = self.refs.map(|refs| {
+            (
+                REFS,
+                refs.into_iter()
+                    .map(|pat| pat.as_str().into_cjson())
+                    .collect::<Vec<_>>()
+                    .into_cjson(),
+            )
+        });
+        let expires = self.expires.map(|expires| (EXPIRES, expires.into_cjson()));
         vec![
-            ("data", self.data.into_cjson()),
-            ("cobs", self.cobs.into_cjson()),
+            (DATA, self.data.into_cjson()),
+            (COBS, self.cobs.into_cjson()),
         ]
         .into_iter()
+        .chain(refs)
+        .chain(expires)
         .collect()
     }
 }
@@ -58,6 +90,8 @@ impl<Ty: Ord, Id: Ord> Default for Config<Ty, Id> {
         Self {
             data: true,
             cobs: Cobs::default(),
+            refs: None,
+            expires: None,
         }
     }
 }
@@ -73,6 +107,8 @@ pub mod error {
         Missing(&'static str),
         #[error(transparent)]
         Cobs(#[from] cobs::cjson::error::Cobs),
+        #[error("invalid ref pattern '{0}'")]
+        RefPattern(String),
     }
 
     #[derive(Debug, Error)]
@@ -154,7 +190,23 @@ where
                     },
                 };
                 let cobs = Cobs::try_from(cobs)?;
-                Ok(Self { data, cobs })
+                let refs = map.remove(&REFS.into()).map(refs_from_cjson).transpose()?;
+                let expires = match map.remove(&EXPIRES.into()) {
+                    None => None,
+                    Some(Value::Number(Number::U64(expires))) => Some(expires),
+                    Some(val) => {
+                        return Err(Cjson::MismatchedTy {
+                            expected: "unsigned integer".to_string(),
+                            found: val.ty_name().to_string(),
+                        })
+                    },
+                };
+                Ok(Self {
+                    data,
+                    cobs,
+                    refs,
+                    expires,
+                })
             },
             val => Err(Cjson::MismatchedTy {
                 expected: "object, keys: [\"cobs\", \"data\"]".to_string(),
@@ -163,3 +215,25 @@ where
         }
     }
 }
+
+fn refs_from_cjson(val: Value) -> Result<BTreeSet<PatternString>, error::Cjson> {
+    use error::Cjson;
+
+    match val {
+        Value::Array(pats) => pats
+            .into_iter()
+            .map(|pat| match pat {
+                Value::String(pat) => PatternString::try_from(pat.as_str())
+                    .map_err(|_| Cjson::RefPattern(pat.as_str().to_owned())),
+                val => Err(Cjson::MismatchedTy {
+                    expected: "string".to_string(),
+                    found: val.ty_name().to_string(),
+                }),
+            })
+            .collect(),
+        val => Err(Cjson::MismatchedTy {
+            expected: "array of ref patterns".to_string(),
+            found: val.ty_name().to_string(),
+        }),
+    }
+}
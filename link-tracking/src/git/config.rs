@@ -5,7 +5,7 @@
 
 use std::convert::TryFrom;
 
-use git_ref_format::{self as refs, Qualified, RefStr, RefString};
+use git_ref_format::{self as refs, refspec::PatternStr, Qualified, RefStr, RefString};
 use link_canonical::{
     json::{ToCjson, Value},
     Canonical,
@@ -63,7 +63,14 @@ impl Config {
 
             (_refs, cat, _, _) => {
                 let cat: &RefStr = cat.as_ref();
-                if self.data && DATA_REFS.iter().any(|allowed| allowed.as_ref() == cat) {
+                let filtered = match &self.refs {
+                    None => true,
+                    Some(patterns) => patterns
+                        .iter()
+                        .any(|pat| matches(pat.as_pattern_str(), refname.as_str())),
+                };
+                if self.data && filtered && DATA_REFS.iter().any(|allowed| allowed.as_ref() == cat)
+                {
                     Policy::Allow
                 } else {
                     Policy::Deny
@@ -73,6 +80,19 @@ impl Config {
     }
 }
 
+/// Match `refname` against `pat`, where a `*` matches any (possibly empty)
+/// sequence of characters, including `/`, as in a git refspec.
+fn matches(pat: &PatternStr, refname: &str) -> bool {
+    match pat.as_str().split_once('*') {
+        None => pat.as_str() == refname,
+        Some((prefix, suffix)) => {
+            refname.len() >= prefix.len() + suffix.len()
+                && refname.starts_with(prefix)
+                && refname.ends_with(suffix)
+        },
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TypeName(pub cob::TypeName);
 
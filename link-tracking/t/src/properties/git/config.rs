@@ -111,6 +111,8 @@ pub mod filter {
                     Config {
                         data: true,
                         cobs: Cobs::allow_all(),
+                        refs: None,
+                        expires: None,
                     }.policy_for(&refname)
                 )
             }
@@ -5,6 +5,7 @@
 
 use std::convert::TryFrom as _;
 
+use git_ref_format::{refspec::PatternString, Qualified, RefStr};
 use link_canonical::Canonical as _;
 use link_tracking::{
     config::{
@@ -72,7 +73,9 @@ fn can_insert() {
                     }
                 ),
             ]
-            .into()
+            .into(),
+            refs: None,
+            expires: None,
         }
     )
 }
@@ -87,6 +90,8 @@ fn can_remove() {
         Config {
             data: true,
             cobs: Cobs::empty(),
+            refs: None,
+            expires: None,
         }
     )
 }
@@ -104,6 +109,8 @@ fn can_set_policy() {
         Config {
             data: true,
             cobs: Cobs::deny_all(),
+            refs: None,
+            expires: None,
         }
     )
 }
@@ -127,7 +134,9 @@ fn can_set_pattern() {
                     pattern: Pattern::Objects(Some(()).into_iter().collect())
                 }
             )]
-            .into()
+            .into(),
+            refs: None,
+            expires: None,
         }
     )
 }
@@ -144,6 +153,8 @@ fn can_insert_objects() {
             },
         )]
         .into(),
+        refs: None,
+        expires: None,
     };
     config
         .cobs
@@ -161,7 +172,9 @@ fn can_insert_objects() {
                     pattern: Pattern::Objects(vec![1, 2, 3, 4, 5, 6, 7, 8].into_iter().collect()),
                 }
             )]
-            .into()
+            .into(),
+            refs: None,
+            expires: None,
         }
     )
 }
@@ -178,6 +191,8 @@ fn can_remove_objects() {
             },
         )]
         .into(),
+        refs: None,
+        expires: None,
     };
     config
         .cobs
@@ -195,7 +210,62 @@ fn can_remove_objects() {
                     pattern: Pattern::Objects(Some(3).into_iter().collect()),
                 }
             )]
-            .into()
+            .into(),
+            refs: None,
+            expires: None,
         }
     )
 }
+
+#[test]
+fn refs_and_expires_roundtrip() {
+    let config = git::config::Config {
+        refs: Some(
+            vec!["refs/heads/main", "refs/heads/release/*"]
+                .into_iter()
+                .map(|pat| PatternString::try_from(pat).unwrap())
+                .collect(),
+        ),
+        expires: Some(1_700_000_000),
+        ..git::config::Config::default()
+    };
+    let canonical = config.canonical_form().unwrap();
+    assert_eq!(
+        git::config::Config::try_from(canonical.as_slice()).unwrap(),
+        config
+    );
+}
+
+#[test]
+fn refs_filter_data_refs() {
+    let config = git::config::Config {
+        refs: Some(
+            vec!["refs/heads/main", "refs/heads/release/*"]
+                .into_iter()
+                .map(|pat| PatternString::try_from(pat).unwrap())
+                .collect(),
+        ),
+        ..git::config::Config::default()
+    };
+
+    let policy = |name: &str| config.policy_for(&Qualified::from_refstr(refstr(name)).unwrap());
+    assert_eq!(policy("refs/heads/main"), Policy::Allow);
+    assert_eq!(policy("refs/heads/release/1.0"), Policy::Allow);
+    assert_eq!(policy("refs/heads/dev"), Policy::Deny);
+    assert_eq!(policy("refs/tags/v1.0"), Policy::Deny);
+}
+
+#[test]
+fn expiry() {
+    let config = git::config::Config {
+        expires: Some(10),
+        ..git::config::Config::default()
+    };
+    assert!(!config.is_expired(9));
+    assert!(config.is_expired(10));
+    assert!(!git::config::Config::default().is_expired(u64::MAX));
+}
+
+fn refstr(s: &str) -> &RefStr {
+    RefStr::try_from_str(s).unwrap()
+}
@@ -7,6 +7,7 @@
 
 pub mod ops;
 pub mod result;
+pub mod time;
 
 pub type Void = std::convert::Infallible;
 
@@ -0,0 +1,20 @@
+// Copyright © 2022 The Radicle Link Contributors
+//
+// This file is part of radicle-link, distributed under the GPLv3 with Radicle
+// Linking Exception. For full terms see the included LICENSE file.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The number of seconds elapsed between the unix epoch and `t`.
+///
+/// `0` if `t` is before the epoch.
+pub fn unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// The current time, in seconds since the unix epoch, cf. [`unix_secs`].
+pub fn unix_now() -> u64 {
+    unix_secs(SystemTime::now())
+}
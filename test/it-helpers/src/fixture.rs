@@ -0,0 +1,67 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Projects commonly set up at the start of a test.
+
+use git_ref_format::{lit, Qualified, RefString};
+use librad::{
+    git::{
+        identities::{self, local::LocalIdentity, project::import},
+        storage::Storage,
+        Urn,
+    },
+    identities::{
+        delegation,
+        payload::{self, ProjectPayload},
+    },
+};
+
+use crate::{fixed::TestProject, git::create_commit, tmp};
+
+/// Create a [`TestProject`] in `store`.
+///
+/// Returns the [`Urn`] of the project, and the [`LocalIdentity`] of its owner.
+pub fn project(store: &Storage) -> (Urn, LocalIdentity) {
+    let TestProject { project, owner } = TestProject::create(store).unwrap();
+    let whoami = identities::local::load(store, owner.urn())
+        .unwrap()
+        .unwrap();
+    (project.urn(), whoami)
+}
+
+/// Create a project called `name` in `store`, importing a working copy which
+/// has a commit on each of `branches`. The first branch is the default branch.
+///
+/// Returns the [`Urn`]s of the owner, and of the project.
+pub fn project_from_repo(store: &Storage, name: &str, branches: &[&str]) -> (Urn, Urn) {
+    let owner = TestProject::create(store).unwrap().owner;
+    let repo = tmp::repo().unwrap();
+    for branch in branches {
+        let branch = RefString::try_from(*branch).unwrap();
+        create_commit(&repo, Qualified::from(lit::refs_heads(branch))).unwrap();
+    }
+    let default_branch = branches.first().copied();
+    if let Some(branch) = default_branch {
+        repo.set_head(&format!("refs/heads/{}", branch)).unwrap();
+    }
+
+    let whoami = identities::local::load(store, owner.urn())
+        .unwrap()
+        .unwrap();
+    let project = identities::project::init_from_repo(
+        store,
+        whoami,
+        ProjectPayload::new(payload::Project {
+            name: name.into(),
+            description: None,
+            default_branch: default_branch.map(Into::into),
+        }),
+        delegation::Indirect::from(owner.clone()),
+        repo.path(),
+        import::Options::default(),
+    )
+    .unwrap()
+    .project;
+
+    (owner.urn(), project.urn())
+}
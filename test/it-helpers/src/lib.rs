@@ -2,8 +2,10 @@
 extern crate tracing;
 
 pub mod fixed;
+pub mod fixture;
 pub mod git;
 pub mod layout;
+pub mod simnet;
 pub mod ssh;
 pub mod testnet;
 pub mod tmp;
@@ -0,0 +1,778 @@
+// Copyright © 2022 The Radicle Link Contributors
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! A deterministic, in-memory simulator of the membership protocol.
+//!
+//! Unlike [`crate::testnet`], no sockets, timers or threads are involved: the
+//! membership state machines ([`Hpv`]) of all peers are driven by a single
+//! event queue, ordered by a virtual clock. All randomness (peer keys,
+//! latencies, packet loss, membership decisions) is derived from one seed, so
+//! a given [`Config`] always produces the same sequence of events. This makes
+//! it possible to reproduce failure scenarios such as partitions or crashing
+//! peers, and to assert on convergence of the membership views.
+//!
+//! Only [`Hpv`] is exercised. The [`Tick`]s it emits are interpreted by a
+//! simplified model of connection handling which is part of the simulator,
+//! not by `librad::net::protocol` -- the simulator thus can't tell whether the
+//! protocol acts on ticks correctly, nor does it cover gossip. Connections are
+//! modelled after QUIC: they are reliable (lost packets are retransmitted),
+//! and a connection is considered lost if the remote end can't be reached
+//! within the idle timeout.
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, BTreeSet, BinaryHeap, VecDeque},
+    iter,
+    time::Duration,
+};
+
+use rand::{Rng as _, SeedableRng as _};
+use rand_pcg::Pcg64Mcg;
+
+use librad::{
+    net::{
+        connection::{LocalAddr, LocalPeer, RemoteAddr, RemotePeer},
+        protocol::{
+            membership::{self, Hpv, Tick, TnT},
+            PartialPeerInfo,
+            PeerAdvertisement,
+            PeerInfo,
+        },
+    },
+    PeerId,
+    SecretKey,
+};
+
+/// The address of a simulated peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SimAddr(pub usize);
+
+/// Messages exchanged between simulated peers.
+pub type Message = membership::Message<SimAddr>;
+
+/// Properties of the simulated links.
+#[derive(Clone, Debug)]
+pub struct Faults {
+    /// Minimum one-way latency.
+    pub min_latency: Duration,
+    /// Maximum one-way latency.
+    pub max_latency: Duration,
+    /// Probability that a packet is lost, and needs to be retransmitted.
+    pub loss: f64,
+    /// Time after which an unresponsive connection is considered lost.
+    pub idle_timeout: Duration,
+}
+
+impl Default for Faults {
+    fn default() -> Self {
+        Self {
+            min_latency: Duration::from_millis(10),
+            max_latency: Duration::from_millis(100),
+            loss: 0.0,
+            idle_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+pub struct Config {
+    /// Seed for all randomness in the simulation.
+    pub seed: u64,
+    pub num_peers: usize,
+    pub membership: membership::Params,
+    pub faults: Faults,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            num_peers: 8,
+            membership: membership::Params::default(),
+            faults: Faults::default(),
+        }
+    }
+}
+
+/// Observable events, in the order in which they occurred.
+///
+/// Two simulations started from the same [`Config`] and driven by the same
+/// calls yield identical traces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Trace {
+    Connected {
+        at: Duration,
+        from: PeerId,
+        to: PeerId,
+    },
+    Delivered {
+        at: Duration,
+        from: PeerId,
+        to: PeerId,
+    },
+    Retransmit {
+        at: Duration,
+        from: PeerId,
+        to: PeerId,
+    },
+    ConnectionLost {
+        at: Duration,
+        peer: PeerId,
+        remote: PeerId,
+    },
+}
+
+/// A connection as seen from one of its ends.
+#[derive(Clone, Copy, Debug)]
+pub struct SimConnection {
+    local: PeerId,
+    remote: PeerId,
+    remote_addr: SimAddr,
+}
+
+impl LocalPeer for SimConnection {
+    fn local_peer_id(&self) -> PeerId {
+        self.local
+    }
+}
+
+impl RemotePeer for SimConnection {
+    fn remote_peer_id(&self) -> PeerId {
+        self.remote
+    }
+}
+
+impl RemoteAddr for SimConnection {
+    type Addr = SimAddr;
+
+    fn remote_addr(&self) -> Self::Addr {
+        self.remote_addr
+    }
+}
+
+/// Read access to the state of a simulated peer.
+pub struct SimPeer<'a> {
+    node: &'a Node,
+}
+
+impl<'a> SimPeer<'a> {
+    pub fn membership(&self) -> &'a Hpv<Pcg64Mcg, SimAddr> {
+        &self.node.hpv
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.node.up
+    }
+}
+
+impl LocalPeer for SimPeer<'_> {
+    fn local_peer_id(&self) -> PeerId {
+        self.node.id
+    }
+}
+
+impl LocalAddr for SimPeer<'_> {
+    type Addr = SimAddr;
+
+    fn listen_addrs(&self) -> Vec<Self::Addr> {
+        vec![self.node.addr]
+    }
+}
+
+struct Node {
+    id: PeerId,
+    addr: SimAddr,
+    hpv: Hpv<Pcg64Mcg, SimAddr>,
+    up: bool,
+}
+
+#[derive(Debug)]
+enum Event {
+    Deliver {
+        from: usize,
+        to: usize,
+        conn: u64,
+        sent_at: Duration,
+        message: Message,
+    },
+    /// Check whether a connection survived a partition or crash.
+    Probe {
+        conn: u64,
+    },
+    Shuffle {
+        node: usize,
+    },
+    Promote {
+        node: usize,
+    },
+}
+
+struct Scheduled {
+    at: Duration,
+    seq: u64,
+    event: Event,
+}
+
+impl PartialEq for Scheduled {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Scheduled {}
+
+impl PartialOrd for Scheduled {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scheduled {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.at, self.seq).cmp(&(other.at, other.seq))
+    }
+}
+
+pub struct Simnet {
+    now: Duration,
+    seq: u64,
+    rng: Pcg64Mcg,
+    params: membership::Params,
+    faults: Faults,
+    nodes: Vec<Node>,
+    index: BTreeMap<PeerId, usize>,
+    /// Live connections, keyed by the (ordered) pair of nodes.
+    conns: BTreeMap<(usize, usize), u64>,
+    next_conn: u64,
+    /// Partition group of each node, absent means the default group.
+    groups: BTreeMap<usize, usize>,
+    queue: BinaryHeap<Reverse<Scheduled>>,
+    trace: Vec<Trace>,
+}
+
+impl Simnet {
+    pub fn new(config: Config) -> Self {
+        let mut rng = Pcg64Mcg::seed_from_u64(config.seed);
+        let mut this = Self {
+            now: Duration::ZERO,
+            seq: 0,
+            rng: rng.clone(),
+            params: config.membership,
+            faults: config.faults,
+            nodes: Vec::with_capacity(config.num_peers),
+            index: BTreeMap::new(),
+            conns: BTreeMap::new(),
+            next_conn: 0,
+            groups: BTreeMap::new(),
+            queue: BinaryHeap::new(),
+            trace: Vec::new(),
+        };
+        for i in 0..config.num_peers {
+            let id = PeerId::from(SecretKey::from_seed(rng.gen()));
+            let hpv = Hpv::unscheduled(id, Pcg64Mcg::new(rng.gen()), this.params.clone());
+            this.nodes.push(Node {
+                id,
+                addr: SimAddr(i),
+                hpv,
+                up: true,
+            });
+            this.index.insert(id, i);
+        }
+        this.rng = rng;
+        for node in 0..this.nodes.len() {
+            this.schedule_periodic(node);
+        }
+
+        this
+    }
+
+    /// The current time of the virtual clock.
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    pub fn peers(&self) -> Vec<PeerId> {
+        self.nodes.iter().map(|node| node.id).collect()
+    }
+
+    pub fn peer(&self, id: &PeerId) -> Option<SimPeer> {
+        self.index.get(id).map(|&idx| SimPeer {
+            node: &self.nodes[idx],
+        })
+    }
+
+    /// The live connections of `peer`.
+    pub fn connections(&self, peer: &PeerId) -> Vec<SimConnection> {
+        let idx = match self.index.get(peer) {
+            None => return vec![],
+            Some(idx) => *idx,
+        };
+        self.conns
+            .keys()
+            .filter_map(|&(a, b)| {
+                let remote = if a == idx {
+                    b
+                } else if b == idx {
+                    a
+                } else {
+                    return None;
+                };
+                Some(SimConnection {
+                    local: *peer,
+                    remote: self.nodes[remote].id,
+                    remote_addr: self.nodes[remote].addr,
+                })
+            })
+            .collect()
+    }
+
+    pub fn trace(&self) -> &[Trace] {
+        &self.trace
+    }
+
+    pub fn faults_mut(&mut self) -> &mut Faults {
+        &mut self.faults
+    }
+
+    /// Let `peer` join the network via `seed`, like bootstrapping does.
+    pub fn join(&mut self, peer: &PeerId, seed: &PeerId) {
+        let (node, remote) = (self.idx(peer), self.idx(seed));
+        if !self.connect(node, remote) {
+            return;
+        }
+        let hello = self.nodes[node].hpv.hello(self.advertisement(node));
+        self.send(node, remote, hello);
+        let TnT { ticks, .. } = self.nodes[node]
+            .hpv
+            .connection_established(PartialPeerInfo {
+                peer_id: *seed,
+                advertised_info: None,
+                seen_addrs: iter::once(self.nodes[remote].addr).into(),
+            });
+        self.interpret(node, ticks)
+    }
+
+    /// Bootstrap every peer through the one started before it.
+    pub fn join_prev(&mut self) {
+        let peers = self.peers();
+        for pair in peers.windows(2) {
+            self.join(&pair[1], &pair[0]);
+        }
+    }
+
+    /// Split the network into `groups`, which can't reach each other.
+    ///
+    /// Peers not mentioned in any group form a group of their own.
+    pub fn partition<G, P>(&mut self, groups: G)
+    where
+        G: IntoIterator<Item = P>,
+        P: IntoIterator<Item = PeerId>,
+    {
+        self.groups.clear();
+        for (i, group) in groups.into_iter().enumerate() {
+            for peer in group {
+                let idx = self.idx(&peer);
+                self.groups.insert(idx, i + 1);
+            }
+        }
+        self.probe_all()
+    }
+
+    /// Remove all partitions.
+    pub fn heal(&mut self) {
+        self.groups.clear()
+    }
+
+    /// Stop `peer`, without notifying its connections.
+    pub fn crash(&mut self, peer: &PeerId) {
+        let node = self.idx(peer);
+        self.nodes[node].up = false;
+        self.probe_all()
+    }
+
+    /// Start a crashed `peer` with empty membership state.
+    ///
+    /// Use [`Simnet::join`] to reconnect it.
+    pub fn restart(&mut self, peer: &PeerId) {
+        let node = self.idx(peer);
+        let conns = self
+            .conns
+            .keys()
+            .filter(|(a, b)| *a == node || *b == node)
+            .copied()
+            .collect::<Vec<_>>();
+        for (a, b) in conns {
+            self.connection_lost(a, b)
+        }
+        let rng = Pcg64Mcg::new(self.rng.gen());
+        let n = &mut self.nodes[node];
+        n.hpv = Hpv::unscheduled(n.id, rng, self.params.clone());
+        n.up = true;
+    }
+
+    /// Process the next event, if any, advancing the clock to its time.
+    pub fn step(&mut self) -> bool {
+        match self.queue.pop() {
+            None => false,
+            Some(Reverse(Scheduled { at, event, .. })) => {
+                self.now = at;
+                self.handle(event);
+                true
+            },
+        }
+    }
+
+    /// Process all events scheduled within the next `duration`.
+    pub fn run_for(&mut self, duration: Duration) {
+        let deadline = self.now + duration;
+        while self.next_at().map(|at| at <= deadline).unwrap_or(false) {
+            self.step();
+        }
+        self.now = deadline;
+    }
+
+    /// Process events until `pred` holds, or `timeout` has elapsed.
+    ///
+    /// Returns whether `pred` holds.
+    pub fn run_until<F>(&mut self, timeout: Duration, pred: F) -> bool
+    where
+        F: Fn(&Self) -> bool,
+    {
+        let deadline = self.now + timeout;
+        loop {
+            if pred(self) {
+                return true;
+            }
+            match self.next_at() {
+                Some(at) if at <= deadline => {
+                    self.step();
+                },
+                _ => {
+                    self.now = deadline;
+                    return pred(self);
+                },
+            }
+        }
+    }
+
+    /// Whether the active views of all running peers form a connected graph.
+    pub fn is_connected(&self) -> bool {
+        let up = self
+            .nodes
+            .iter()
+            .filter(|node| node.up)
+            .map(|node| node.id)
+            .collect::<BTreeSet<_>>();
+        let start = match up.iter().next() {
+            None => return true,
+            Some(start) => *start,
+        };
+
+        let mut edges = BTreeMap::<PeerId, BTreeSet<PeerId>>::new();
+        for node in self.nodes.iter().filter(|node| node.up) {
+            for remote in node.hpv.active().into_iter().filter(|p| up.contains(p)) {
+                edges.entry(node.id).or_default().insert(remote);
+                edges.entry(remote).or_default().insert(node.id);
+            }
+        }
+
+        let mut seen = BTreeSet::from([start]);
+        let mut pending = VecDeque::from([start]);
+        while let Some(peer) = pending.pop_front() {
+            for next in edges.get(&peer).into_iter().flatten() {
+                if seen.insert(*next) {
+                    pending.push_back(*next)
+                }
+            }
+        }
+
+        seen == up
+    }
+
+    fn idx(&self, peer: &PeerId) -> usize {
+        *self
+            .index
+            .get(peer)
+            .unwrap_or_else(|| panic!("unknown peer {}", peer))
+    }
+
+    fn next_at(&self) -> Option<Duration> {
+        self.queue.peek().map(|Reverse(s)| s.at)
+    }
+
+    fn schedule(&mut self, after: Duration, event: Event) {
+        self.seq += 1;
+        self.queue.push(Reverse(Scheduled {
+            at: self.now + after,
+            seq: self.seq,
+            event,
+        }))
+    }
+
+    fn schedule_periodic(&mut self, node: usize) {
+        let jitter = self.rng.gen_range(Duration::ZERO..=Duration::from_secs(5));
+        self.schedule(
+            self.params.shuffle_interval + jitter,
+            Event::Shuffle { node },
+        );
+        let jitter = self.rng.gen_range(Duration::ZERO..=Duration::from_secs(5));
+        self.schedule(
+            self.params.promote_interval + jitter,
+            Event::Promote { node },
+        );
+    }
+
+    fn latency(&mut self) -> Duration {
+        self.rng
+            .gen_range(self.faults.min_latency..=self.faults.max_latency)
+    }
+
+    fn reachable(&self, a: usize, b: usize) -> bool {
+        self.nodes[a].up && self.nodes[b].up && self.groups.get(&a) == self.groups.get(&b)
+    }
+
+    fn conn_key(a: usize, b: usize) -> (usize, usize) {
+        (a.min(b), a.max(b))
+    }
+
+    fn conn(&self, a: usize, b: usize) -> Option<u64> {
+        self.conns.get(&Self::conn_key(a, b)).copied()
+    }
+
+    fn advertisement(&self, node: usize) -> PeerAdvertisement<SimAddr> {
+        PeerAdvertisement::new(self.nodes[node].addr)
+    }
+
+    fn connect(&mut self, from: usize, to: usize) -> bool {
+        if from == to || !self.reachable(from, to) {
+            return false;
+        }
+        if self.conn(from, to).is_none() {
+            self.next_conn += 1;
+            self.conns.insert(Self::conn_key(from, to), self.next_conn);
+            self.trace.push(Trace::Connected {
+                at: self.now,
+                from: self.nodes[from].id,
+                to: self.nodes[to].id,
+            });
+        }
+        true
+    }
+
+    /// Send `message` over an existing connection, returning `false` if there
+    /// is none.
+    fn send(&mut self, from: usize, to: usize, message: Message) -> bool {
+        match self.conn(from, to) {
+            None => false,
+            Some(conn) => {
+                let latency = self.latency();
+                self.schedule(
+                    latency,
+                    Event::Deliver {
+                        from,
+                        to,
+                        conn,
+                        sent_at: self.now,
+                        message,
+                    },
+                );
+                true
+            },
+        }
+    }
+
+    fn probe_all(&mut self) {
+        let conns = self.conns.values().copied().collect::<Vec<_>>();
+        for conn in conns {
+            self.schedule(self.faults.idle_timeout, Event::Probe { conn })
+        }
+    }
+
+    fn connection_lost(&mut self, a: usize, b: usize) {
+        if self.conns.remove(&Self::conn_key(a, b)).is_none() {
+            return;
+        }
+        for (local, remote) in [(a, b), (b, a)] {
+            if !self.nodes[local].up {
+                continue;
+            }
+            self.trace.push(Trace::ConnectionLost {
+                at: self.now,
+                peer: self.nodes[local].id,
+                remote: self.nodes[remote].id,
+            });
+            let TnT { ticks, .. } = self.nodes[local].hpv.connection_lost(self.nodes[remote].id);
+            self.interpret(local, ticks)
+        }
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Deliver {
+                from,
+                to,
+                conn,
+                sent_at,
+                message,
+            } => {
+                if self.conn(from, to) != Some(conn) {
+                    return;
+                }
+                if !self.reachable(from, to) || self.rng.gen_bool(self.faults.loss) {
+                    if self.now - sent_at >= self.faults.idle_timeout {
+                        return self.connection_lost(from, to);
+                    }
+                    self.trace.push(Trace::Retransmit {
+                        at: self.now,
+                        from: self.nodes[from].id,
+                        to: self.nodes[to].id,
+                    });
+                    let rto = self.latency() * 2;
+                    return self.schedule(
+                        rto,
+                        Event::Deliver {
+                            from,
+                            to,
+                            conn,
+                            sent_at,
+                            message,
+                        },
+                    );
+                }
+
+                self.trace.push(Trace::Delivered {
+                    at: self.now,
+                    from: self.nodes[from].id,
+                    to: self.nodes[to].id,
+                });
+                let remote_id = self.nodes[from].id;
+                match self.nodes[to]
+                    .hpv
+                    .apply(remote_id, self.nodes[from].addr, message)
+                {
+                    Err(e) => tracing::warn!(err = ?e, "membership error"),
+                    Ok(TnT { ticks, .. }) => self.interpret(to, ticks),
+                }
+            },
+
+            Event::Probe { conn } => {
+                let pair = self
+                    .conns
+                    .iter()
+                    .find(|(_, id)| **id == conn)
+                    .map(|(pair, _)| *pair);
+                if let Some((a, b)) = pair {
+                    if !self.reachable(a, b) {
+                        self.connection_lost(a, b)
+                    }
+                }
+            },
+
+            Event::Shuffle { node } => {
+                if self.nodes[node].up {
+                    if let Some(membership::Shuffle {
+                        recipient,
+                        sample,
+                        ttl,
+                    }) = self.nodes[node].hpv.shuffle()
+                    {
+                        let message = membership::Message::Shuffle {
+                            origin: PeerInfo {
+                                peer_id: self.nodes[node].id,
+                                advertised_info: self.advertisement(node),
+                                seen_addrs: iter::empty().into(),
+                            },
+                            peers: sample,
+                            ttl,
+                        };
+                        self.interpret_one(
+                            node,
+                            Tick::Reply {
+                                to: recipient,
+                                message,
+                            },
+                        )
+                    }
+                }
+                let jitter = self.rng.gen_range(Duration::ZERO..=Duration::from_secs(5));
+                self.schedule(
+                    self.params.shuffle_interval + jitter,
+                    Event::Shuffle { node },
+                )
+            },
+
+            Event::Promote { node } => {
+                if self.nodes[node].up {
+                    for candidate in self.nodes[node].hpv.choose_passive_to_promote() {
+                        let message = self.nodes[node].hpv.hello(self.advertisement(node));
+                        self.interpret_one(
+                            node,
+                            Tick::Try {
+                                recipient: candidate,
+                                message,
+                            },
+                        )
+                    }
+                }
+                let jitter = self.rng.gen_range(Duration::ZERO..=Duration::from_secs(5));
+                self.schedule(
+                    self.params.promote_interval + jitter,
+                    Event::Promote { node },
+                )
+            },
+        }
+    }
+
+    fn interpret(&mut self, node: usize, ticks: Vec<Tick<SimAddr>>) {
+        for tick in ticks {
+            self.interpret_one(node, tick)
+        }
+    }
+
+    /// Evaluate a membership [`Tick`] using the simulator's model of
+    /// connection handling.
+    fn interpret_one(&mut self, node: usize, tick: Tick<SimAddr>) {
+        match tick {
+            Tick::Forget { peer } => {
+                if let Some(&remote) = self.index.get(&peer) {
+                    self.connection_lost(node, remote)
+                }
+            },
+
+            Tick::Connect { to } => {
+                let remote = self.idx(&to.peer_id);
+                let hello = self.nodes[node].hpv.hello(self.advertisement(node));
+                if self.connect(node, remote) && self.send(node, remote, hello) {
+                    let TnT { ticks, .. } = self.nodes[node].hpv.connection_established(to.into());
+                    self.interpret(node, ticks)
+                }
+            },
+
+            Tick::Reply { to, message } => self.send_connected(node, to, message),
+
+            Tick::All {
+                recipients,
+                message,
+            } => {
+                for to in recipients {
+                    self.send_connected(node, to, message.clone())
+                }
+            },
+
+            Tick::Try { recipient, message } => {
+                let remote = self.idx(&recipient.peer_id);
+                if self.connect(node, remote) {
+                    self.send(node, remote, message);
+                }
+            },
+        }
+    }
+
+    /// Send to a connected peer, or evaluate the loss of the connection.
+    fn send_connected(&mut self, node: usize, to: PeerId, message: Message) {
+        let remote = self.idx(&to);
+        if !self.send(node, remote, message) {
+            let TnT { ticks, .. } = self.nodes[node].hpv.connection_lost(to);
+            self.interpret(node, ticks)
+        }
+    }
+}
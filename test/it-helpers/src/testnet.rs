@@ -57,6 +57,7 @@ impl Guard for AllowAll {
 
 pub struct TestClient {
     client: Client<SecretKey, protocol::SendOnly>,
+    signer: SecretKey,
     _tmp: TempDir,
 }
 
@@ -79,7 +80,7 @@ impl TestClient {
         let network = Network::Custom(b"localtestnet".as_ref().into());
         let endpoint = quic::SendOnly::new(key.clone(), network.clone()).await?;
         let config = client::Config {
-            signer: key,
+            signer: key.clone(),
             paths,
             replication: Default::default(),
             user_storage: Default::default(),
@@ -87,9 +88,14 @@ impl TestClient {
         };
         Ok(TestClient {
             client: Client::new(config, spawner, endpoint)?,
+            signer: key,
             _tmp: tmp,
         })
     }
+
+    pub fn signer(&self) -> &SecretKey {
+        &self.signer
+    }
 }
 
 pub struct BoundTestPeer {
@@ -122,6 +128,7 @@ impl LocalAddr for BoundTestPeer {
 pub struct RunningTestPeer {
     peer: Peer<SecretKey, AllowAll>,
     listen_addrs: Vec<SocketAddr>,
+    tcp_listen_addr: Option<SocketAddr>,
 }
 
 // No, this is not sound, but conveniently allows to write tests as if this was
@@ -139,6 +146,11 @@ impl RunningTestPeer {
         &self.listen_addrs
     }
 
+    /// The address of the [`librad::net::tcp`] fallback transport.
+    pub fn tcp_listen_addr(&self) -> Option<SocketAddr> {
+        self.tcp_listen_addr
+    }
+
     pub async fn track(&self, urn: Urn, peer: Option<PeerId>) -> anyhow::Result<()> {
         self.using_storage(move |s| {
             tracking::track(
@@ -190,12 +202,21 @@ where
         replication: Default::default(),
         rate_limits: Default::default(),
         request_pull: Default::default(),
+        request_pull_policy: Default::default(),
+        read_access: Default::default(),
+        announcements: Default::default(),
+        review: Default::default(),
+        tcp_listen_addr: Some(listen_addr),
+        health: Default::default(),
+        compression: Default::default(),
+        router: None,
     };
     let disco = seeds.into_iter().collect::<discovery::Static>();
     let peer = Peer::new(peer::Config {
         signer: key,
         protocol,
         storage: Default::default(),
+        runtime: Default::default(),
     })?;
     let bound = peer.bind().await?;
 
@@ -398,6 +419,7 @@ pub fn run(config: Config) -> anyhow::Result<Testnet> {
         peers.push(RunningTestPeer {
             peer,
             listen_addrs: bound.listen_addrs(),
+            tcp_listen_addr: bound.tcp_listen_addr(),
         });
         let (shutdown, run) = bound.accept(disco.discover());
         sig.push(Box::new(shutdown) as Box<dyn FnOnce()>);